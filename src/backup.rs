@@ -0,0 +1,264 @@
+//!
+//! # Backups
+//! Portable archives of a node's chain database and off-chain payloads.
+//!
+//! A backup is a tar archive holding a RocksDB checkpoint under `database/`, a copy of
+//! the payload directory under `offchain/`, and `backup-manifest.json` listing every
+//! file with its size and SHA-256. Restoring unpacks the archive next to the target,
+//! checks every file against the manifest (and that no file is missing or extra) and
+//! only then moves the database and payloads into place.
+
+use crate::core::snapshot::hex_array;
+use crate::core::{Blockchain, Hash};
+use crate::offchain_storage::{OffChainStorageError, OffChainStorageManager};
+use crate::storage::{backend, StorageError, StorageManager};
+use log::info;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Archive layout version written by this binary.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "backup-manifest.json";
+const DATABASE_DIR: &str = "database";
+const PAYLOADS_DIR: &str = "offchain";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Off-chain storage error: {0}")]
+    OffChain(#[from] OffChainStorageError),
+    #[error("Invalid backup manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+    #[error("Backup integrity check failed: {0}")]
+    Integrity(String),
+    #[error("{0}")]
+    InvalidTarget(String),
+}
+
+/// One file in the archive.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BackupFile {
+    /// Path inside the archive, `/`-separated.
+    pub path: String,
+    pub size: u64,
+    #[serde(with = "hex_array")]
+    pub sha256: Hash,
+}
+
+/// Contents of `backup-manifest.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    /// UNIX time the backup was taken.
+    pub created_at: u64,
+    pub chain_height: Option<u64>,
+    pub tip_hash: Option<String>,
+    /// Files sorted by path.
+    pub files: Vec<BackupFile>,
+}
+
+impl BackupManifest {
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+/// Scratch directory removed when dropped, so failed runs leave nothing behind.
+struct StagingDir(PathBuf);
+
+impl StagingDir {
+    fn beside(path: &Path, suffix: &str) -> Result<Self, BackupError> {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        let staging = PathBuf::from(name);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+        Ok(StagingDir(staging))
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<Hash> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// Size and SHA-256 of every file below `root`, keyed by `/`-separated relative path.
+fn collect_files(root: &Path) -> io::Result<BTreeMap<String, (u64, Hash)>> {
+    let mut files = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(root).expect("entry is below root")
+                .components().map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>().join("/");
+            files.insert(relative, (entry.metadata()?.len(), hash_file(&path)?));
+        }
+    }
+    Ok(files)
+}
+
+/// Writes a backup of the chain database and stored payloads to `archive`, which must not exist.
+/// The database part is a consistent checkpoint; payloads are copied as found at that time.
+/// `blockchain` is only locked while the checkpoint is taken, not while payloads are copied.
+pub fn create_backup(
+    blockchain: &Mutex<Blockchain>,
+    offchain_storage: &OffChainStorageManager,
+    archive: &Path,
+    now: u64,
+) -> Result<BackupManifest, BackupError> {
+    if archive.exists() {
+        return Err(BackupError::InvalidTarget(format!("Backup archive {:?} already exists", archive)));
+    }
+    let staging = StagingDir::beside(archive, ".staging")?;
+    let (chain_height, tip_hash) = {
+        let blockchain = blockchain.lock().expect("Blockchain lock poisoned");
+        blockchain.storage().create_snapshot(staging.0.join(DATABASE_DIR))?;
+        (blockchain.get_chain_height(), blockchain.get_last_block_hash().map(hex::encode))
+    };
+
+    let payloads = staging.0.join(PAYLOADS_DIR);
+    fs::create_dir_all(&payloads)?;
+    for (payload_hash, _) in offchain_storage.list_payloads()? {
+        let name = hex::encode(payload_hash);
+        match fs::copy(offchain_storage.storage_path().join(&name), payloads.join(&name)) {
+            Ok(_) => {}
+            // Garbage collected since it was listed
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let files = collect_files(&staging.0)?.into_iter()
+        .map(|(path, (size, sha256))| BackupFile { path, size, sha256 })
+        .collect();
+    let manifest = BackupManifest { format_version: BACKUP_FORMAT_VERSION, created_at: now, chain_height, tip_hash, files };
+    fs::write(staging.0.join(MANIFEST_ENTRY), serde_json::to_vec_pretty(&manifest)?)?;
+
+    // Written under a temporary name so a partial archive is never mistaken for a backup
+    let mut partial = archive.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let mut builder = tar::Builder::new(File::create(&partial)?);
+    builder.append_path_with_name(staging.0.join(MANIFEST_ENTRY), MANIFEST_ENTRY)?;
+    builder.append_dir_all(DATABASE_DIR, staging.0.join(DATABASE_DIR))?;
+    builder.append_dir_all(PAYLOADS_DIR, &payloads)?;
+    builder.into_inner()?.sync_all()?;
+    fs::rename(&partial, archive)?;
+    info!("Wrote backup {:?}: height {:?}, {} files, {} bytes", archive, manifest.chain_height, manifest.files.len(), manifest.total_size());
+    Ok(manifest)
+}
+
+/// Unpacks `archive` into `staging` and checks its contents against the manifest.
+fn unpack_and_verify(archive: &Path, staging: &Path) -> Result<BackupManifest, BackupError> {
+    tar::Archive::new(File::open(archive)?).unpack(staging)?;
+    let manifest: BackupManifest = serde_json::from_slice(&fs::read(staging.join(MANIFEST_ENTRY))?)?;
+    if manifest.format_version != BACKUP_FORMAT_VERSION {
+        return Err(BackupError::Integrity(format!(
+            "Unsupported backup format version {} (expected {})", manifest.format_version, BACKUP_FORMAT_VERSION
+        )));
+    }
+    let mut found = collect_files(staging)?;
+    found.remove(MANIFEST_ENTRY);
+    for file in &manifest.files {
+        match found.remove(&file.path) {
+            Some((size, sha256)) if size == file.size && sha256 == file.sha256 => {}
+            Some(_) => return Err(BackupError::Integrity(format!("{} does not match the manifest", file.path))),
+            None => return Err(BackupError::Integrity(format!("{} is missing from the archive", file.path))),
+        }
+    }
+    if let Some(extra) = found.keys().next() {
+        return Err(BackupError::Integrity(format!("{} is not listed in the manifest", extra)));
+    }
+    Ok(manifest)
+}
+
+/// Restores a backup made by [`create_backup`]: the database into `data_dir` and the payloads
+/// into the off-chain storage under `offchain_dir`. Both must be empty. Nothing is restored
+/// unless every file in the archive matches the manifest.
+pub fn restore_backup(archive: &Path, data_dir: &Path, offchain_dir: &Path) -> Result<BackupManifest, BackupError> {
+    if backend::is_database_dir(data_dir) {
+        return Err(BackupError::InvalidTarget(format!("{:?} already contains a database", data_dir)));
+    }
+    let offchain_storage = OffChainStorageManager::new(offchain_dir)?;
+    if !offchain_storage.list_payloads()?.is_empty() {
+        return Err(BackupError::InvalidTarget(format!("{:?} already contains payloads", offchain_storage.storage_path())));
+    }
+    let staging = StagingDir::beside(data_dir, ".restore-staging")?;
+    let manifest = unpack_and_verify(archive, &staging.0)?;
+
+    StorageManager::restore_from_snapshot(staging.0.join(DATABASE_DIR), data_dir)?;
+    for entry in fs::read_dir(staging.0.join(PAYLOADS_DIR))? {
+        let entry = entry?;
+        fs::copy(entry.path(), offchain_storage.storage_path().join(entry.file_name()))?;
+    }
+    info!("Restored backup {:?} taken at height {:?} into {:?}", archive, manifest.chain_height, data_dir);
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Transaction;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_backup_round_trip_and_tamper_detection() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(&dir.path().join("chain")).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let offchain = OffChainStorageManager::new(&dir.path().join("offchain")).unwrap();
+        let payload_hash = offchain.store_payload(b"backed up payload").unwrap();
+        blockchain.add_pending_transaction(Transaction::new_store_data(vec![1], payload_hash, 17)).unwrap();
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+
+        let blockchain = Mutex::new(blockchain);
+        let archive = dir.path().join("node.tar");
+        let manifest = create_backup(&blockchain, &offchain, &archive, 1_700_000_000).unwrap();
+        assert_eq!(manifest.chain_height, Some(1));
+        assert!(create_backup(&blockchain, &offchain, &archive, 1_700_000_000).is_err());
+
+        let restored = restore_backup(&archive, &dir.path().join("restored"), &dir.path().join("restored_offchain")).unwrap();
+        assert_eq!(restored, manifest);
+        assert_eq!(Blockchain::new(&dir.path().join("restored")).unwrap().get_chain_height(), Some(1));
+        let restored_offchain = OffChainStorageManager::new(&dir.path().join("restored_offchain")).unwrap();
+        assert_eq!(restored_offchain.retrieve_payload(&payload_hash).unwrap(), b"backed up payload");
+
+        // Repack the archive with one payload altered
+        let unpacked = dir.path().join("unpacked");
+        tar::Archive::new(File::open(&archive).unwrap()).unpack(&unpacked).unwrap();
+        fs::write(unpacked.join(PAYLOADS_DIR).join(hex::encode(payload_hash)), b"tampered").unwrap();
+        let tampered = dir.path().join("tampered.tar");
+        let mut builder = tar::Builder::new(File::create(&tampered).unwrap());
+        builder.append_dir_all(".", &unpacked).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+        let target = dir.path().join("never_restored");
+        assert!(matches!(restore_backup(&tampered, &target, &dir.path().join("never_offchain")), Err(BackupError::Integrity(_))));
+        assert!(!backend::is_database_dir(&target));
+    }
+}
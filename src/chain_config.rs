@@ -0,0 +1,175 @@
+//!
+//! # Chain Configuration
+//! Consensus-relevant parameters fixed when a chain is created (genesis time).
+//!
+//! The configuration is recorded in storage together with the genesis block,
+//! so a node reopening an existing database keeps validating with the rules the
+//! chain was created with, regardless of the defaults compiled into the binary.
+
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::consensus::{ConsensusMode, DifficultyAlgorithm, DifficultyBounds, PowAlgorithm};
+use crate::core::Address;
+
+/// Default number of confirmations after which a block is irreversible.
+pub const DEFAULT_FINALITY_DEPTH: u64 = 100;
+/// Default cap on the off-chain data one transaction may commit to, in bytes (256 MiB).
+pub const DEFAULT_MAX_TX_DATA_SIZE: u64 = 256 * 1024 * 1024;
+/// Default cap on the off-chain data the transactions of one block may commit to, in bytes (1 GiB).
+pub const DEFAULT_MAX_BLOCK_DATA_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Transaction kinds that can be shipped dormant and activated at a coordinated height.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TxFeature {
+    /// Token creation and token transfers.
+    Tokens,
+    /// Storage deals between clients and providers.
+    StorageDeals,
+    /// Stake, unstake and slashing transactions (Proof-of-Stake chains).
+    Staking,
+    /// Publishing named pointers to payloads.
+    Pointers,
+}
+
+/// Parameters chosen at genesis that every node on the chain must agree on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainConfig {
+    /// Consensus mechanism used to seal and validate blocks.
+    pub consensus_mode: ConsensusMode,
+    /// Hash function used to evaluate Proof-of-Work for block headers.
+    pub pow_algorithm: PowAlgorithm,
+    /// How the Proof-of-Work target is recalculated.
+    pub difficulty_algorithm: DifficultyAlgorithm,
+    /// Validator stakes written at genesis (Proof-of-Stake chains only).
+    pub initial_stakes: BTreeMap<Address, u64>,
+    /// Activation height for each accepted transaction version.
+    /// Versions missing from the map are unknown and always rejected.
+    pub transaction_version_activations: BTreeMap<u32, u64>,
+    /// Activation height for each gated transaction kind.
+    /// Features missing from the map are dormant and rejected at every height.
+    pub feature_activations: BTreeMap<TxFeature, u64>,
+    /// Blocks buried this deep below the tip are final: reorgs that would revert them are refused.
+    pub finality_depth: u64,
+    /// Largest `data_size` a single `StoreData` transaction may declare.
+    pub max_tx_data_size: u64,
+    /// Largest total of `data_size` over all transactions of a block, bounding how fast
+    /// the storage obligation of the network can grow.
+    pub max_block_data_size: u64,
+    /// Easiest and hardest Proof-of-Work targets; the genesis block uses the easiest.
+    pub difficulty_bounds: DifficultyBounds,
+    /// Timestamp of the genesis block. Chains that nodes create independently and must
+    /// agree on need a fixed one; without it genesis is stamped with the creation time.
+    pub genesis_timestamp: Option<u64>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        ChainConfig {
+            consensus_mode: ConsensusMode::default(),
+            pow_algorithm: PowAlgorithm::default(),
+            difficulty_algorithm: DifficultyAlgorithm::default(),
+            initial_stakes: BTreeMap::new(),
+            transaction_version_activations: BTreeMap::from([(1, 0)]),
+            feature_activations: BTreeMap::from([(TxFeature::Tokens, 0)]),
+            finality_depth: DEFAULT_FINALITY_DEPTH,
+            max_tx_data_size: DEFAULT_MAX_TX_DATA_SIZE,
+            max_block_data_size: DEFAULT_MAX_BLOCK_DATA_SIZE,
+            difficulty_bounds: DifficultyBounds::default(),
+            genesis_timestamp: None,
+        }
+    }
+}
+
+impl ChainConfig {
+    /// Returns a configuration using the given PoW algorithm and defaults elsewhere.
+    pub fn with_pow_algorithm(pow_algorithm: PowAlgorithm) -> Self {
+        ChainConfig { pow_algorithm, ..Default::default() }
+    }
+
+    /// Returns this configuration with the given difficulty adjustment algorithm.
+    pub fn with_difficulty_algorithm(mut self, difficulty_algorithm: DifficultyAlgorithm) -> Self {
+        self.difficulty_algorithm = difficulty_algorithm;
+        self
+    }
+
+    /// Returns this configuration with the given finality depth.
+    pub fn with_finality_depth(mut self, finality_depth: u64) -> Self {
+        self.finality_depth = finality_depth;
+        self
+    }
+
+    /// Returns this configuration with the given per-transaction and per-block data commitment caps.
+    pub fn with_data_limits(mut self, max_tx_data_size: u64, max_block_data_size: u64) -> Self {
+        self.max_tx_data_size = max_tx_data_size;
+        self.max_block_data_size = max_block_data_size;
+        self
+    }
+
+    /// Returns this configuration with the given Proof-of-Work target bounds.
+    pub fn with_difficulty_bounds(mut self, difficulty_bounds: DifficultyBounds) -> Self {
+        self.difficulty_bounds = difficulty_bounds;
+        self
+    }
+
+    /// Returns this configuration with a fixed genesis timestamp.
+    pub fn with_genesis_timestamp(mut self, genesis_timestamp: u64) -> Self {
+        self.genesis_timestamp = Some(genesis_timestamp);
+        self
+    }
+
+    /// Height of the highest final block once the tip is at `tip_height`, if any block is final yet.
+    pub fn finalized_height(&self, tip_height: u64) -> Option<u64> {
+        tip_height.checked_sub(self.finality_depth)
+    }
+
+    /// Returns a Proof-of-Stake configuration seeded with the given validator stakes.
+    /// Staking transactions are active from genesis.
+    pub fn proof_of_stake(initial_stakes: BTreeMap<Address, u64>) -> Self {
+        let mut config = ChainConfig {
+            consensus_mode: ConsensusMode::ProofOfStake,
+            initial_stakes,
+            ..Default::default()
+        };
+        config.feature_activations.insert(TxFeature::Staking, 0);
+        config
+    }
+
+    /// Checks whether transactions of `version` are valid in a block at `height`.
+    pub fn is_transaction_version_active(&self, version: u32, height: u64) -> bool {
+        self.transaction_version_activations
+            .get(&version)
+            .is_some_and(|&activation| height >= activation)
+    }
+
+    /// Checks whether `feature` is active in a block at `height`.
+    pub fn is_feature_active(&self, feature: TxFeature, height: u64) -> bool {
+        self.feature_activations
+            .get(&feature)
+            .is_some_and(|&activation| height >= activation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_activations() {
+        let config = ChainConfig::default();
+        assert!(config.is_transaction_version_active(1, 0));
+        assert!(!config.is_transaction_version_active(2, 1_000_000));
+        assert!(config.is_feature_active(TxFeature::Tokens, 0));
+        assert!(!config.is_feature_active(TxFeature::Pointers, u64::MAX));
+    }
+
+    #[test]
+    fn test_scheduled_activation() {
+        let mut config = ChainConfig::default();
+        config.transaction_version_activations.insert(2, 100);
+        config.feature_activations.insert(TxFeature::StorageDeals, 100);
+        assert!(!config.is_transaction_version_active(2, 99));
+        assert!(config.is_transaction_version_active(2, 100));
+        assert!(!config.is_feature_active(TxFeature::StorageDeals, 99));
+        assert!(config.is_feature_active(TxFeature::StorageDeals, 100));
+    }
+}
@@ -0,0 +1,149 @@
+//!
+//! # Chain Export
+//! Flat block files for moving a chain between nodes without the P2P network, e.g. to seed
+//! a new node from a disk or to archive the chain in a backend-independent form.
+//!
+//! A file starts with [`CHAIN_FILE_MAGIC`] and the format version (`u32`, little endian),
+//! followed by the main chain blocks in height order, each as its length (`u32`, little
+//! endian) and its canonical bytes (see [`Block::to_bytes`]). Importing feeds the blocks
+//! through [`Blockchain::import_blocks`], so they are validated like blocks from a peer;
+//! blocks the node already holds are skipped. Off-chain payloads are not part of the file
+//! (a full backup carries them, see [`crate::backup`]).
+
+use crate::core::{Block, Blockchain, BlockchainError};
+use log::info;
+use std::io::{self, Read, Write};
+
+/// First bytes of every chain file.
+pub const CHAIN_FILE_MAGIC: &[u8; 8] = b"BDSCHAIN";
+/// File layout version written by this binary.
+pub const CHAIN_FILE_VERSION: u32 = 2;
+/// Largest block record accepted, so a corrupt length can't make the importer allocate without bound.
+const MAX_BLOCK_RECORD: u32 = 64 * 1024 * 1024;
+/// Blocks handed to the chain per import call.
+const IMPORT_BATCH: usize = 500;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChainFileError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Blockchain error: {0}")]
+    Blockchain(#[from] BlockchainError),
+    #[error("Not a chain file: {0}")]
+    Format(String),
+}
+
+/// Outcome of [`import_chain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportSummary {
+    /// Blocks connected to the chain.
+    pub imported: usize,
+    /// Blocks the node already had.
+    pub skipped: usize,
+}
+
+/// Writes the main chain blocks from `from` to `to` (the tip if `None`) to `out`; returns how many were written.
+pub fn export_chain(blockchain: &Blockchain, out: impl Write, from: u64, to: Option<u64>) -> Result<u64, ChainFileError> {
+    let mut out = io::BufWriter::new(out);
+    out.write_all(CHAIN_FILE_MAGIC)?;
+    out.write_all(&CHAIN_FILE_VERSION.to_le_bytes())?;
+    let Some(tip) = blockchain.get_chain_height() else {
+        out.flush()?;
+        return Ok(0);
+    };
+    let to = to.map_or(tip, |to| to.min(tip));
+    let mut written = 0;
+    for block in blockchain.iter_blocks(from..=to) {
+        let bytes = block?.to_bytes();
+        out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        out.write_all(&bytes)?;
+        written += 1;
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+/// Reads a chain file from `input` and connects its blocks to `blockchain`.
+pub fn import_chain(blockchain: &mut Blockchain, input: impl Read) -> Result<ImportSummary, ChainFileError> {
+    let mut input = io::BufReader::new(input);
+    let mut header = [0u8; 12];
+    input.read_exact(&mut header).map_err(|_| ChainFileError::Format("file is shorter than its header".to_string()))?;
+    if &header[..8] != CHAIN_FILE_MAGIC {
+        return Err(ChainFileError::Format("missing the BDSCHAIN header".to_string()));
+    }
+    let version = u32::from_le_bytes(header[8..].try_into().expect("Four version bytes"));
+    if version != CHAIN_FILE_VERSION {
+        return Err(ChainFileError::Format(format!("unsupported version {}", version)));
+    }
+
+    let mut summary = ImportSummary::default();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH);
+    while let Some(block) = read_block(&mut input)? {
+        // Blocks we hold already (at least genesis, for any chain file) are skipped, not re-validated
+        let held = blockchain.get_block_by_height(block.header.height)?;
+        if held.is_some_and(|held| held.hash() == block.hash()) {
+            summary.skipped += 1;
+            continue;
+        }
+        batch.push(block);
+        if batch.len() >= IMPORT_BATCH {
+            summary.imported += blockchain.import_blocks(std::mem::take(&mut batch))?;
+            info!("Imported blocks up to height {:?}", blockchain.get_chain_height());
+        }
+    }
+    summary.imported += blockchain.import_blocks(batch)?;
+    Ok(summary)
+}
+
+// The next block record, or `None` at the end of the file
+fn read_block(input: &mut impl Read) -> Result<Option<Block>, ChainFileError> {
+    let mut length = [0u8; 4];
+    match input.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let length = u32::from_le_bytes(length);
+    if length > MAX_BLOCK_RECORD {
+        return Err(ChainFileError::Format(format!("block record of {} bytes", length)));
+    }
+    let mut bytes = vec![0u8; length as usize];
+    input.read_exact(&mut bytes).map_err(|_| ChainFileError::Format("file ends inside a block".to_string()))?;
+    Block::from_bytes(&bytes).map(Some).map_err(ChainFileError::Format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_config::ChainConfig;
+    use crate::core::Transaction;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_export_and_import_chain() {
+        let dir = tempdir().unwrap();
+        // Both chains need the same genesis block, which a clock tick between them would change
+        let config = ChainConfig::default().with_genesis_timestamp(1_700_000_000);
+        let mut source = Blockchain::new_with_config(&dir.path().join("source"), config.clone()).unwrap();
+        source.initialize_genesis_if_needed().unwrap();
+        for i in 0..3u8 {
+            source.add_pending_transaction(Transaction::new_store_data(vec![1], [i; 32], 17)).unwrap();
+            let block = source.mine_new_block().unwrap();
+            source.process_mined_block(block).unwrap();
+        }
+        let mut file = Vec::new();
+        assert_eq!(export_chain(&source, &mut file, 0, None).unwrap(), 4);
+
+        let mut target = Blockchain::new_with_config(&dir.path().join("target"), config).unwrap();
+        target.initialize_genesis_if_needed().unwrap();
+        assert_eq!(import_chain(&mut target, file.as_slice()).unwrap(), ImportSummary { imported: 3, skipped: 1 });
+        assert_eq!(target.get_last_block_hash(), source.get_last_block_hash());
+        // Importing again changes nothing
+        assert_eq!(import_chain(&mut target, file.as_slice()).unwrap(), ImportSummary { imported: 0, skipped: 4 });
+
+        let mut partial = Vec::new();
+        assert_eq!(export_chain(&source, &mut partial, 2, Some(9)).unwrap(), 2);
+        assert!(matches!(import_chain(&mut target, &b"NOTCHAIN\x01\0\0\0"[..]), Err(ChainFileError::Format(_))));
+        assert!(matches!(import_chain(&mut target, &file[..file.len() - 1]), Err(ChainFileError::Format(_))));
+    }
+}
@@ -0,0 +1,457 @@
+//!
+//! # Node Configuration File
+//! Settings loaded with `--config <FILE>`, so a deployment can keep its whole setup in one
+//! TOML file instead of a long command line.
+//!
+//! The file mirrors the command-line flags, grouped in sections; any flag given on the
+//! command line overrides the file. Unknown keys are rejected so a typo does not silently
+//! fall back to a default:
+//!
+//! ```toml
+//! network = "testnet"
+//! data_dir = "/var/lib/bds"
+//!
+//! [rpc]
+//! bind = "0.0.0.0:8000"
+//! auth_file = "/etc/bds/rpc_auth.json"
+//!
+//! [p2p]
+//! listen = ["/ip4/0.0.0.0/tcp/4001"]
+//! bootstrap_peers = ["/dns4/seed.example.com/tcp/4001/p2p/12D3KooW..."]
+//!
+//! [mempool]
+//! max_bytes = 100_000_000
+//! ```
+//!
+//! Only the part of TOML such a file needs is understood: tables, bare or quoted keys,
+//! strings, integers, booleans and arrays of those (which may span lines). Dotted keys,
+//! inline tables, floats and dates are reported as errors.
+
+use crate::presets::NetworkPreset;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigFileError {
+    #[error("Cannot read config file {path:?}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("Config file {path:?}, line {line}: {message}")]
+    Syntax { path: PathBuf, line: usize, message: String },
+    #[error("Config file {path:?}: {message}")]
+    Invalid { path: PathBuf, message: String },
+}
+
+/// Contents of a node configuration file; every setting is optional.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    pub network: Option<NetworkPreset>,
+    pub data_dir: Option<PathBuf>,
+    pub pid_file: Option<PathBuf>,
+    #[serde(default)]
+    pub rpc: RpcSection,
+    #[serde(default)]
+    pub p2p: P2pSection,
+    #[serde(default)]
+    pub mining: MiningSection,
+    #[serde(default)]
+    pub storage: StorageSection,
+    #[serde(default)]
+    pub mempool: MempoolSection,
+    #[serde(default)]
+    pub wallet: WalletSection,
+    #[serde(default)]
+    pub logging: LoggingSection,
+}
+
+/// `[rpc]`: the RPC server's address, credentials and limits.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpcSection {
+    pub bind: Option<String>,
+    pub auth_file: Option<PathBuf>,
+    pub tenants_file: Option<PathBuf>,
+    pub rate_limit: Option<u32>,
+    pub credential_rate_limit: Option<u32>,
+    pub cors_origins: Option<Vec<String>>,
+    pub cors_methods: Option<Vec<String>>,
+    pub cors_headers: Option<Vec<String>>,
+    pub dev_cors: Option<bool>,
+}
+
+/// `[p2p]`: listen addresses and the peers joined at startup, as multiaddrs.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct P2pSection {
+    pub listen: Option<Vec<String>>,
+    pub bootstrap_peers: Option<Vec<String>>,
+}
+
+/// `[mining]`: the node's own miner and the Stratum server for external miners.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MiningSection {
+    pub stratum_addr: Option<String>,
+    pub stratum_share_bits: Option<u32>,
+    pub mine: Option<bool>,
+    pub miner_address: Option<String>,
+}
+
+/// `[storage]`: database, block cache and payload retention settings.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StorageSection {
+    pub tx_index: Option<bool>,
+    pub indexer: Option<bool>,
+    pub block_cache_size: Option<usize>,
+    pub block_compression_level: Option<i32>,
+    pub train_block_dictionary: Option<bool>,
+    pub payload_retention_blocks: Option<u64>,
+    pub orphan_payload_grace_secs: Option<u64>,
+    pub retrieval_price: Option<u64>,
+    pub retrieval_payee: Option<String>,
+    pub audit_interval_secs: Option<u64>,
+    pub audit_sample_size: Option<usize>,
+    pub replication_interval_secs: Option<u64>,
+    pub namespace_quota_bytes: Option<u64>,
+    pub checkpoints_file: Option<PathBuf>,
+    pub backup_dir: Option<PathBuf>,
+}
+
+/// `[mempool]`: expiry and memory limits for pending transactions.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MempoolSection {
+    pub expiry_secs: Option<u64>,
+    pub max_bytes: Option<usize>,
+    pub max_per_sender: Option<usize>,
+    pub max_bytes_per_sender: Option<usize>,
+}
+
+/// `[wallet]`
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WalletSection {
+    pub disabled: Option<bool>,
+}
+
+/// `[logging]`: the rotating log file in the data directory.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingSection {
+    pub file: Option<bool>,
+    pub max_size: Option<u64>,
+    pub rotate_secs: Option<u64>,
+    pub max_files: Option<usize>,
+}
+
+impl NodeConfig {
+    pub fn from_file(path: &Path) -> Result<Self, ConfigFileError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|source| ConfigFileError::Io { path: path.to_path_buf(), source })?;
+        Self::parse(&contents).map_err(|e| match e {
+            ParseError::Syntax { line, message } => ConfigFileError::Syntax { path: path.to_path_buf(), line, message },
+            ParseError::Invalid(message) => ConfigFileError::Invalid { path: path.to_path_buf(), message },
+        })
+    }
+
+    fn parse(contents: &str) -> Result<Self, ParseError> {
+        let document = TomlParser::new(contents).parse_document()?;
+        serde_json::from_value(document).map_err(|e| ParseError::Invalid(e.to_string()))
+    }
+}
+
+#[derive(Debug)]
+enum ParseError {
+    Syntax { line: usize, message: String },
+    Invalid(String),
+}
+
+// Reads the supported TOML subset into JSON values
+struct TomlParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl TomlParser {
+    fn new(contents: &str) -> Self {
+        TomlParser { chars: contents.chars().collect(), pos: 0, line: 1 }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError::Syntax { line: self.line, message: message.into() })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    // Spaces, newlines and comments, as allowed between array elements
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => { self.bump(); }
+                Some('#') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.bump();
+        }
+    }
+
+    // Nothing but a comment may follow a key/value pair or table header on its line
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_spaces();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => Ok(()),
+            Some(c) => self.error(format!("unexpected {:?} after value", c)),
+        }
+    }
+
+    fn parse_document(mut self) -> Result<Value, ParseError> {
+        let mut root = Map::new();
+        let mut table: Option<String> = None;
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    self.bump();
+                    let name = self.parse_key()?;
+                    self.skip_spaces();
+                    if self.bump() != Some(']') {
+                        return self.error("expected ']' after table name");
+                    }
+                    self.end_of_line()?;
+                    if root.contains_key(&name) {
+                        return self.error(format!("table [{}] is defined twice", name));
+                    }
+                    root.insert(name.clone(), Value::Object(Map::new()));
+                    table = Some(name);
+                }
+                Some(_) => {
+                    let key = self.parse_key()?;
+                    self.skip_spaces();
+                    if self.bump() != Some('=') {
+                        return self.error(format!("expected '=' after key {:?}", key));
+                    }
+                    self.skip_spaces();
+                    let value = self.parse_value()?;
+                    self.end_of_line()?;
+                    let target = match &table {
+                        Some(name) => root.get_mut(name).and_then(Value::as_object_mut).expect("Table inserted when its header was read"),
+                        None => &mut root,
+                    };
+                    if target.insert(key.clone(), value).is_some() {
+                        return self.error(format!("key {:?} is defined twice", key));
+                    }
+                }
+            }
+        }
+        Ok(Value::Object(root))
+    }
+
+    fn parse_key(&mut self) -> Result<String, ParseError> {
+        self.skip_spaces();
+        let key = match self.peek() {
+            Some('"') => self.parse_basic_string()?,
+            Some('\'') => self.parse_literal_string()?,
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                    self.bump();
+                }
+                if self.pos == start {
+                    return self.error("expected a key");
+                }
+                self.chars[start..self.pos].iter().collect()
+            }
+        };
+        self.skip_spaces();
+        if self.peek() == Some('.') {
+            return self.error("dotted keys are not supported; use a [table]");
+        }
+        Ok(key)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string().map(Value::String),
+            Some('\'') => self.parse_literal_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.error("inline tables are not supported; use a [table]"),
+            Some(_) => self.parse_bare_value(),
+            None => self.error("expected a value"),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ParseError> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_blank();
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return self.error("expected ',' or ']' in array"),
+            }
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, ParseError> {
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.error("unterminated string"),
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(c @ ('u' | 'U')) => {
+                            let digits = if c == 'u' { 4 } else { 8 };
+                            let hex: String = (0..digits).filter_map(|_| self.bump()).collect();
+                            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                                Some(unicode) => unicode,
+                                None => return self.error(format!("invalid unicode escape \\{}{}", c, hex)),
+                            }
+                        }
+                        Some(c) => return self.error(format!("invalid escape \\{}", c)),
+                        None => return self.error("unterminated string"),
+                    };
+                    value.push(escaped);
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, ParseError> {
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.error("unterminated string"),
+                Some('\'') => return Ok(value),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    // Booleans and integers
+    fn parse_bare_value(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if !matches!(c, ' ' | '\t' | '\r' | '\n' | ',' | ']' | '#')) {
+            self.bump();
+        }
+        let token: String = self.chars[start..self.pos].iter().collect();
+        match token.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+        let digits = token.strip_prefix('+').unwrap_or(&token);
+        let valid_underscores = !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__");
+        match digits.replace('_', "").parse::<i64>() {
+            Ok(integer) if valid_underscores => Ok(Value::from(integer)),
+            _ => self.error(format!("unsupported value {:?}; expected a string, integer, boolean or array", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_file() {
+        let config = NodeConfig::parse(r#"
+            # Production node
+            network = "testnet"
+            data_dir = "/var/lib/bds"
+
+            [rpc]
+            bind = '0.0.0.0:8000'   # all interfaces
+            rate_limit = 600
+            cors_origins = [
+                "https://app.example.com",
+                "http://localhost:3000", # trailing comma allowed
+            ]
+            dev_cors = false
+
+            [p2p]
+            bootstrap_peers = ["/ip4/198.51.100.2/tcp/4001/p2p/12D3KooWA"]
+
+            [mempool]
+            max_bytes = 100_000_000
+
+            [logging]
+            file = true
+            rotate_secs = 86_400
+        "#).unwrap();
+        assert_eq!(config.network, Some(NetworkPreset::Testnet));
+        assert_eq!(config.data_dir, Some(PathBuf::from("/var/lib/bds")));
+        assert_eq!(config.rpc.bind.as_deref(), Some("0.0.0.0:8000"));
+        assert_eq!(config.rpc.rate_limit, Some(600));
+        assert_eq!(config.rpc.cors_origins, Some(vec!["https://app.example.com".to_string(), "http://localhost:3000".to_string()]));
+        assert_eq!(config.rpc.dev_cors, Some(false));
+        assert_eq!(config.p2p.bootstrap_peers, Some(vec!["/ip4/198.51.100.2/tcp/4001/p2p/12D3KooWA".to_string()]));
+        assert_eq!(config.mempool.max_bytes, Some(100_000_000));
+        assert_eq!(config.storage, StorageSection::default());
+        assert_eq!(config.logging, LoggingSection { file: Some(true), rotate_secs: Some(86_400), ..Default::default() });
+        assert_eq!(NodeConfig::parse("").unwrap(), NodeConfig::default());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let line_of = |contents: &str| match NodeConfig::parse(contents) {
+            Err(ParseError::Syntax { line, .. }) => Some(line),
+            _ => None,
+        };
+        assert_eq!(line_of("data_dir = \"/a\"\n\n[rpc]\nbind = 0.0.0.0:8000"), Some(4));
+        assert_eq!(line_of("data_dir = \"/a\" extra"), Some(1));
+        assert_eq!(line_of("[rpc]\n[rpc]"), Some(2));
+        assert_eq!(line_of("rpc.bind = \"x\""), Some(1));
+        assert_eq!(line_of("[rpc]\ncors_origins = [\"a\"\n\"b\"]"), Some(3));
+
+        // Typos and wrong types are caught instead of being ignored
+        assert!(matches!(NodeConfig::parse("[rpc]\nbnid = \"x\""), Err(ParseError::Invalid(_))));
+        assert!(matches!(NodeConfig::parse("[mempool]\nmax_bytes = -1"), Err(ParseError::Invalid(_))));
+        assert!(matches!(NodeConfig::parse("[storage]\ntx_index = \"yes\""), Err(ParseError::Invalid(_))));
+    }
+}
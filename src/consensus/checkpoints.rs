@@ -0,0 +1,150 @@
+//!
+//! # Checkpoints
+//! Known-good `(height, hash)` pairs the node trusts without re-validating history.
+//!
+//! A chain whose block at a checkpointed height has a different hash is rejected,
+//! so a node can never be led onto a fork below a checkpoint. A block whose hash is
+//! the checkpoint itself skips the Proof-of-Work check: the checkpoint pins it.
+
+use crate::core::Hash;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Checkpoints compiled into the binary, as `(height, hex block hash)`.
+///
+/// Empty until a network with a fixed genesis is published.
+pub const EMBEDDED_CHECKPOINTS: &[(u64, &str)] = &[];
+
+/// A checkpoint as written in a checkpoints file.
+#[derive(Deserialize, Debug, Clone)]
+struct CheckpointEntry {
+    height: u64,
+    /// Block hash, hex-encoded.
+    hash: String,
+}
+
+/// Set of checkpoints keyed by height.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Checkpoints {
+    points: BTreeMap<u64, Hash>,
+}
+
+fn parse_hash(hex_hash: &str) -> Result<Hash, String> {
+    let bytes = hex::decode(hex_hash).map_err(|e| format!("Invalid checkpoint hash '{}': {}", hex_hash, e))?;
+    bytes.as_slice().try_into()
+        .map_err(|_| format!("Invalid checkpoint hash '{}': expected 32 bytes, got {}", hex_hash, bytes.len()))
+}
+
+impl Checkpoints {
+    /// Builds a checkpoint set from `(height, hash)` pairs.
+    pub fn new(points: BTreeMap<u64, Hash>) -> Self {
+        Checkpoints { points }
+    }
+
+    /// The checkpoints compiled into the binary (`EMBEDDED_CHECKPOINTS`).
+    pub fn embedded() -> Self {
+        let points = EMBEDDED_CHECKPOINTS.iter()
+            .map(|(height, hash)| (*height, parse_hash(hash).expect("EMBEDDED_CHECKPOINTS holds valid hashes")))
+            .collect();
+        Checkpoints { points }
+    }
+
+    /// Loads checkpoints from a JSON file containing an array of `{"height": .., "hash": "<hex>"}`.
+    /// Listing two different hashes for one height is an error.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Cannot read {:?}: {}", path.as_ref(), e))?;
+        let entries: Vec<CheckpointEntry> = serde_json::from_str(&contents)
+            .map_err(|e| format!("Cannot parse {:?}: {}", path.as_ref(), e))?;
+        let mut checkpoints = Checkpoints::default();
+        for entry in entries {
+            checkpoints.insert(entry.height, parse_hash(&entry.hash)?)?;
+        }
+        Ok(checkpoints)
+    }
+
+    /// Adds a checkpoint, refusing to replace a different hash at the same height.
+    pub fn insert(&mut self, height: u64, hash: Hash) -> Result<(), String> {
+        if let Some(existing) = self.points.get(&height) {
+            if *existing != hash {
+                return Err(format!(
+                    "Conflicting checkpoints at height {}: {} and {}",
+                    height, hex::encode(existing), hex::encode(hash)
+                ));
+            }
+        }
+        self.points.insert(height, hash);
+        Ok(())
+    }
+
+    /// Adds every checkpoint of `other` (e.g. file checkpoints on top of the embedded ones).
+    pub fn extend(&mut self, other: &Checkpoints) -> Result<(), String> {
+        for (height, hash) in &other.points {
+            self.insert(*height, *hash)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the checkpointed hash at `height`, if any.
+    pub fn get(&self, height: u64) -> Option<&Hash> {
+        self.points.get(&height)
+    }
+
+    /// Height of the highest checkpoint.
+    pub fn last_height(&self) -> Option<u64> {
+        self.points.keys().next_back().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &Hash)> {
+        self.points.iter().map(|(height, hash)| (*height, hash))
+    }
+
+    /// Fails if a checkpoint exists at `height` with a hash other than `hash`.
+    pub fn check(&self, height: u64, hash: &Hash) -> Result<(), String> {
+        match self.points.get(&height) {
+            Some(expected) if expected != hash => Err(format!(
+                "Block {} at height {} conflicts with checkpoint {}",
+                hex::encode(hash), height, hex::encode(expected)
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a checkpoint exists at or above `height`.
+    pub fn covers(&self, height: u64) -> bool {
+        self.last_height().is_some_and(|last| height <= last)
+    }
+
+    /// Whether `hash` is the checkpointed block at `height`, so its Proof-of-Work need not be verified.
+    pub fn pins(&self, height: u64, hash: &Hash) -> bool {
+        self.points.get(&height) == Some(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_check_and_cover() {
+        let mut checkpoints = Checkpoints::new(BTreeMap::from([(10, [1u8; 32])]));
+        assert!(checkpoints.check(10, &[1u8; 32]).is_ok());
+        assert!(checkpoints.check(10, &[2u8; 32]).is_err());
+        assert!(checkpoints.check(11, &[2u8; 32]).is_ok());
+        assert!(checkpoints.covers(10));
+        assert!(!checkpoints.covers(11));
+        assert!(checkpoints.pins(10, &[1u8; 32]));
+        assert!(!checkpoints.pins(10, &[2u8; 32]));
+        assert!(!checkpoints.pins(9, &[1u8; 32]));
+
+        assert!(checkpoints.insert(10, [1u8; 32]).is_ok());
+        assert!(checkpoints.insert(10, [3u8; 32]).is_err());
+        assert!(!Checkpoints::default().covers(0));
+        assert!(parse_hash("abcd").is_err());
+    }
+}
@@ -0,0 +1,27 @@
+//!
+//! # Invalid-Block Evidence
+//! Records of blocks received from peers that failed validation.
+//!
+//! Each record keeps the offending header, the rejection reason and the peer that
+//! sent it, so operators can tell a network fork or buggy miner software apart
+//! from local misconfiguration. The store is bounded: once
+//! `MAX_INVALID_BLOCK_EVIDENCE` records exist, the oldest are dropped.
+
+use crate::core::{BlockHeader, Hash};
+use serde::{Serialize, Deserialize};
+
+/// Maximum number of invalid-block records kept in storage.
+pub const MAX_INVALID_BLOCK_EVIDENCE: usize = 256;
+
+/// A block that was rejected, with why and where it came from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InvalidBlockEvidence {
+    pub block_hash: Hash,
+    pub header: BlockHeader,
+    /// The validation error the block failed with.
+    pub reason: String,
+    /// Peer the block was received from, if it came over the network.
+    pub source: Option<String>,
+    /// UNIX time the block was rejected.
+    pub recorded_at: u64,
+}
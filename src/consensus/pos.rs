@@ -0,0 +1,193 @@
+//!
+//! # Proof-of-Stake
+//! Stake-weighted block producer selection, producer seals and double-sign evidence.
+//!
+//! For each height a single producer is drawn from the stake table, weighted by
+//! staked amount and seeded by the previous block hash. The producer signs the
+//! header hash with its ed25519 key; the signing key's public half is the
+//! producer's address. Signing two different headers for the same height is
+//! provable with a `DoubleSignEvidence` and punished by slashing the stake.
+
+use crate::core::{Address, Block, BlockHeader, Hash};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::BTreeMap;
+
+/// Staked amount per validator address.
+pub type StakeTable = BTreeMap<Address, u64>;
+
+/// Producer signature attached to a PoS block.
+///
+/// The seal signs the header hash and is not itself part of it, so the block hash
+/// is the same whether or not the block has been sealed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockSeal {
+    /// Producer address (ed25519 public key bytes).
+    pub producer: Address,
+    /// ed25519 signature over the header hash.
+    pub signature: Vec<u8>,
+}
+
+impl BlockSeal {
+    /// Seals `header` with the producer's signing key.
+    pub fn sign(header: &BlockHeader, key: &SigningKey) -> Self {
+        let signature = key.sign(&header.calculate_hash());
+        BlockSeal {
+            producer: key.verifying_key().to_bytes().to_vec(),
+            signature: signature.to_bytes().to_vec(),
+        }
+    }
+
+    /// Verifies that the seal is a valid signature of `header` by `producer`.
+    pub fn verify(&self, header: &BlockHeader) -> Result<(), String> {
+        let key_bytes: [u8; 32] = self.producer.as_slice().try_into()
+            .map_err(|_| format!("Invalid producer key length: {}", self.producer.len()))?;
+        let verifying_key = VerifyingKey::from_bytes(&key_bytes)
+            .map_err(|e| format!("Invalid producer key: {}", e))?;
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|e| format!("Malformed seal signature: {}", e))?;
+        verifying_key.verify(&header.calculate_hash(), &signature)
+            .map_err(|_| format!("Seal signature does not match header at height {}", header.height))
+    }
+}
+
+/// Proof that a producer sealed two different headers at the same height.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DoubleSignEvidence {
+    pub header_a: BlockHeader,
+    pub seal_a: BlockSeal,
+    pub header_b: BlockHeader,
+    pub seal_b: BlockSeal,
+}
+
+impl DoubleSignEvidence {
+    /// The producer accused by this evidence.
+    pub fn offender(&self) -> &Address {
+        &self.seal_a.producer
+    }
+
+    /// Checks that the evidence really shows two conflicting headers sealed by one producer.
+    pub fn verify(&self) -> Result<(), String> {
+        if self.header_a.height != self.header_b.height {
+            return Err(format!(
+                "Evidence headers are at different heights ({} and {})",
+                self.header_a.height, self.header_b.height
+            ));
+        }
+        if self.header_a.calculate_hash() == self.header_b.calculate_hash() {
+            return Err("Evidence headers are identical".to_string());
+        }
+        if self.seal_a.producer != self.seal_b.producer {
+            return Err("Evidence seals belong to different producers".to_string());
+        }
+        self.seal_a.verify(&self.header_a)?;
+        self.seal_b.verify(&self.header_b)?;
+        Ok(())
+    }
+}
+
+/// Derives the per-height randomness used for producer selection.
+pub fn selection_seed(previous_hash: &Hash, height: u64) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(previous_hash);
+    hasher.update(height.to_be_bytes());
+    hasher.finalize().into()
+}
+
+/// Selects the producer for a height, with probability proportional to stake.
+///
+/// Returns `None` if nothing is staked.
+pub fn select_producer(stakes: &StakeTable, seed: &Hash) -> Option<Address> {
+    let total: u128 = stakes.values().map(|&s| s as u128).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut draw_bytes = [0u8; 16];
+    draw_bytes.copy_from_slice(&seed[..16]);
+    let mut draw = u128::from_be_bytes(draw_bytes) % total;
+    for (address, &stake) in stakes {
+        let stake = stake as u128;
+        if draw < stake {
+            return Some(address.clone());
+        }
+        draw -= stake;
+    }
+    None // Unreachable: draw < total
+}
+
+/// Validates that `block` is sealed by the producer selected for its height.
+pub fn validate_seal(block: &Block, stakes: &StakeTable) -> Result<(), String> {
+    let seal = block.seal.as_ref()
+        .ok_or_else(|| format!("Block {} is missing a producer seal", block.header.height))?;
+    let seed = selection_seed(&block.header.previous_hash, block.header.height);
+    let expected = select_producer(stakes, &seed)
+        .ok_or_else(|| "No stake registered; no producer can be selected".to_string())?;
+    if seal.producer != expected {
+        return Err(format!(
+            "Block {} sealed by {} but the selected producer is {}",
+            block.header.height, hex::encode(&seal.producer), hex::encode(&expected)
+        ));
+    }
+    seal.verify(&block.header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_header(height: u64, nonce: u64) -> BlockHeader {
+        BlockHeader {
+            previous_hash: [7u8; 32],
+            merkle_root: [0u8; 32],
+            timestamp: 1_700_000_000,
+            nonce,
+            bits: 0,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_select_producer_weighted() {
+        let mut stakes = StakeTable::new();
+        stakes.insert(vec![1], 1);
+        stakes.insert(vec![2], 99);
+        let mut counts = BTreeMap::new();
+        for height in 0..1000u64 {
+            let seed = selection_seed(&[0u8; 32], height);
+            let producer = select_producer(&stakes, &seed).unwrap();
+            *counts.entry(producer).or_insert(0u32) += 1;
+        }
+        assert!(counts[&vec![2u8]] > 900);
+        assert!(select_producer(&StakeTable::new(), &[0u8; 32]).is_none());
+    }
+
+    #[test]
+    fn test_seal_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let header = test_header(5, 0);
+        let seal = BlockSeal::sign(&header, &key);
+        assert!(seal.verify(&header).is_ok());
+        assert!(seal.verify(&test_header(5, 1)).is_err());
+    }
+
+    #[test]
+    fn test_double_sign_evidence() {
+        let key = SigningKey::from_bytes(&[4u8; 32]);
+        let (header_a, header_b) = (test_header(9, 0), test_header(9, 1));
+        let evidence = DoubleSignEvidence {
+            seal_a: BlockSeal::sign(&header_a, &key),
+            seal_b: BlockSeal::sign(&header_b, &key),
+            header_a: header_a.clone(),
+            header_b,
+        };
+        assert!(evidence.verify().is_ok());
+
+        let not_conflicting = DoubleSignEvidence {
+            header_b: header_a.clone(),
+            seal_b: evidence.seal_a.clone(),
+            ..evidence.clone()
+        };
+        assert!(not_conflicting.verify().is_err());
+    }
+}
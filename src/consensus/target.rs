@@ -0,0 +1,201 @@
+//!
+//! # Compact Targets
+//! 256-bit Proof-of-Work targets and their compact ("bits") encoding.
+//!
+//! A block hash, read as a big-endian 256-bit integer, satisfies PoW when it is
+//! less than or equal to the target. Headers carry the target in the 32-bit compact
+//! form used by Bitcoin: the high byte is the length of the target in bytes and the
+//! low 23 bits are its most significant digits (bit 23 is a sign bit and must be zero).
+
+use crate::core::Hash;
+use uint::construct_uint;
+
+construct_uint! {
+    /// Unsigned 256-bit integer used for targets and chain work.
+    pub struct U256(4);
+}
+
+construct_uint! {
+    /// Wide intermediate so scaling a target never overflows.
+    struct U512(8);
+}
+
+/// Decodes a compact target.
+///
+/// Returns `None` if the encoding is negative, zero, or overflows 256 bits.
+pub fn compact_to_target(bits: u32) -> Option<U256> {
+    let size = (bits >> 24) as usize;
+    let mut word = bits & 0x007f_ffff;
+    let negative = word != 0 && (bits & 0x0080_0000) != 0;
+    let overflow = word != 0 && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+    if negative || overflow {
+        return None;
+    }
+    let target = if size <= 3 {
+        word >>= 8 * (3 - size);
+        U256::from(word)
+    } else {
+        U256::from(word) << (8 * (size - 3))
+    };
+    if target.is_zero() {
+        None
+    } else {
+        Some(target)
+    }
+}
+
+/// Encodes a target in compact form, rounding down to 23 bits of precision.
+pub fn target_to_compact(target: U256) -> u32 {
+    let mut size = target.bits().div_ceil(8);
+    let mut compact = if size <= 3 {
+        (target.low_u64() << (8 * (3 - size))) as u32
+    } else {
+        (target >> (8 * (size - 3))).low_u64() as u32
+    };
+    // The mantissa's top bit is a sign bit; move a byte into the exponent instead
+    if compact & 0x0080_0000 != 0 {
+        compact >>= 8;
+        size += 1;
+    }
+    compact | ((size as u32) << 24)
+}
+
+/// Compact target satisfied exactly by hashes with at least `zero_bits` leading zero bits
+/// (subject to compact rounding).
+pub fn bits_for_leading_zeros(zero_bits: u32) -> u32 {
+    let zero_bits = zero_bits.min(255) as usize;
+    target_to_compact((U256::one() << (256 - zero_bits)) - U256::one())
+}
+
+/// Computes `target * numerator / denominator`, saturating at `U256::MAX`.
+pub fn scale_target(target: U256, numerator: u64, denominator: u64) -> U256 {
+    let mut bytes = [0u8; 64];
+    target.to_big_endian(&mut bytes[32..]);
+    let scaled = U512::from_big_endian(&bytes) * U512::from(numerator) / U512::from(denominator.max(1));
+    scaled.to_big_endian(&mut bytes);
+    if bytes[..32].iter().any(|&b| b != 0) {
+        U256::max_value()
+    } else {
+        U256::from_big_endian(&bytes[32..])
+    }
+}
+
+/// Mean of `targets`, computed without intermediate overflow. Returns zero for an empty slice.
+pub fn average_targets(targets: &[U256]) -> U256 {
+    if targets.is_empty() {
+        return U256::zero();
+    }
+    let mut bytes = [0u8; 64];
+    let sum = targets.iter().fold(U512::zero(), |acc, target| {
+        target.to_big_endian(&mut bytes[32..]);
+        acc + U512::from_big_endian(&bytes)
+    });
+    let mean = sum / U512::from(targets.len() as u64);
+    mean.to_big_endian(&mut bytes);
+    U256::from_big_endian(&bytes[32..]) // mean <= max(targets), so it fits
+}
+
+/// Expected number of hashes needed to meet the target encoded by `bits`: `2^256 / (target + 1)`.
+/// Invalid encodings carry no work.
+pub fn block_work(bits: u32) -> U256 {
+    match compact_to_target(bits) {
+        // 2^256 doesn't fit; (2^256 - target - 1) / (target + 1) + 1 is the same value
+        Some(target) if target < U256::max_value() => (!target / (target + U256::one())) + U256::one(),
+        Some(_) => U256::one(),
+        None => U256::zero(),
+    }
+}
+
+/// Interprets a hash as a big-endian 256-bit integer.
+pub fn hash_to_u256(hash: &Hash) -> U256 {
+    U256::from_big_endian(hash)
+}
+
+/// Difficulty of `bits` relative to the easiest allowed target (`limit_bits`), for display.
+pub fn bits_to_difficulty(bits: u32, limit_bits: u32) -> f64 {
+    match (compact_to_target(bits), compact_to_target(limit_bits)) {
+        (Some(target), Some(limit)) => u256_to_f64(limit) / u256_to_f64(target),
+        _ => 0.0,
+    }
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, &limb| acc * 18_446_744_073_709_551_616.0 + limb as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_round_trip() {
+        // Bitcoin's genesis target
+        let target = compact_to_target(0x1d00ffff).unwrap();
+        assert_eq!(target, U256::from(0xffffu64) << (8 * (0x1d - 3)));
+        assert_eq!(target_to_compact(target), 0x1d00ffff);
+
+        for bits in [0x1b0404cbu32, 0x200fffff, 0x03123456, 0x01120000] {
+            let target = compact_to_target(bits).unwrap();
+            assert_eq!(target_to_compact(target), bits);
+        }
+    }
+
+    #[test]
+    fn test_compact_invalid_encodings() {
+        assert!(compact_to_target(0x04923456).is_none()); // negative
+        assert!(compact_to_target(0xff123456).is_none()); // overflow
+        assert!(compact_to_target(0x00000000).is_none()); // zero target
+    }
+
+    #[test]
+    fn test_sign_bit_moves_into_exponent() {
+        let target = U256::from(0x80u64);
+        let bits = target_to_compact(target);
+        assert_eq!(bits, 0x02008000);
+        assert_eq!(compact_to_target(bits).unwrap(), target);
+    }
+
+    #[test]
+    fn test_scale_target() {
+        let target = U256::one() << 250;
+        assert_eq!(scale_target(target, 3, 12), U256::one() << 248);
+        assert_eq!(scale_target(target, 48_000, 12_000), U256::one() << 252);
+        assert_eq!(scale_target(U256::max_value(), 2, 1), U256::max_value());
+    }
+
+    #[test]
+    fn test_average_targets() {
+        let big = U256::max_value();
+        assert_eq!(average_targets(&[big, big, big]), big);
+        assert_eq!(average_targets(&[U256::from(2u64), U256::from(5u64)]), U256::from(3u64));
+        assert!(average_targets(&[]).is_zero());
+    }
+
+    #[test]
+    fn test_bits_for_leading_zeros() {
+        let target = compact_to_target(bits_for_leading_zeros(8)).unwrap();
+        let mut hash = [0xffu8; 32];
+        hash[0] = 0x00;
+        hash[1] = 0xfe;
+        assert!(hash_to_u256(&hash) <= target);
+        hash[0] = 0x01;
+        assert!(hash_to_u256(&hash) > target);
+    }
+
+    #[test]
+    fn test_block_work() {
+        // A target admitting 1/16 of all hashes takes 16 hashes on average
+        assert_eq!(block_work(bits_for_leading_zeros(4)), U256::from(16u64));
+        assert_eq!(block_work(bits_for_leading_zeros(10)), U256::from(1024u64));
+        assert!(block_work(0).is_zero());
+    }
+
+    #[test]
+    fn test_bits_to_difficulty() {
+        let limit = bits_for_leading_zeros(4);
+        assert_eq!(bits_to_difficulty(limit, limit), 1.0);
+        let harder = target_to_compact(compact_to_target(limit).unwrap() / 2);
+        assert!((bits_to_difficulty(harder, limit) - 2.0).abs() < 1e-4); // compact rounding
+        assert_eq!(bits_to_difficulty(0, limit), 0.0);
+    }
+}
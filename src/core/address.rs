@@ -0,0 +1,240 @@
+//!
+//! # Addresses
+//! Versioned addresses that let several signature algorithms coexist.
+//!
+//! An address is one version byte naming the key algorithm followed by the first
+//! `ADDRESS_KEY_HASH_LEN` bytes of the SHA-256 hash of the public key. Verifying a
+//! signature for an address checks that the public key hashes to the address and
+//! then dispatches to the algorithm the version byte selects, so a new scheme (e.g.
+//! a post-quantum one) only needs a new `KeyAlgorithm` variant.
+//!
+//! Users see addresses as bech32m strings (see [`super::bech32`]) under a chain-specific
+//! human-readable part, e.g. `bds1q...`: a mistyped character fails the checksum instead
+//! of naming another address, and an address of another chain is refused.
+
+use super::bech32::{self, Bech32Error};
+use super::Address;
+use sha2::{Sha256, Digest};
+use std::fmt;
+
+/// Length of the public key hash carried in an address.
+pub const ADDRESS_KEY_HASH_LEN: usize = 20;
+/// Human-readable part of the node's encoded addresses unless a network sets another.
+pub const DEFAULT_ADDRESS_HRP: &str = "bds";
+
+/// Signature algorithm of the key behind an address, encoded as the address version byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyAlgorithm {
+    /// ed25519 (32-byte public keys, 64-byte signatures).
+    Ed25519,
+    /// ECDSA over secp256k1 (33-byte compressed SEC1 public keys, 64-byte signatures over SHA-256).
+    Secp256k1,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("Invalid address length: expected {expected}, got {actual}")]
+    InvalidLength { expected: usize, actual: usize },
+    #[error("Unknown address version: {0:#04x}")]
+    UnknownVersion(u8),
+    #[error("Invalid {0} public key")]
+    InvalidPublicKey(KeyAlgorithm),
+    #[error("Public key does not match the address")]
+    KeyMismatch,
+    #[error("Invalid {0} signature")]
+    InvalidSignature(KeyAlgorithm),
+    #[error("Unknown key algorithm {0:?}; expected ed25519 or secp256k1")]
+    UnknownAlgorithm(String),
+    #[error("Invalid address encoding: {0}")]
+    Encoding(#[from] Bech32Error),
+    #[error("Address is for another network (prefix {actual:?}, expected {expected:?})")]
+    WrongNetwork { expected: String, actual: String },
+}
+
+impl KeyAlgorithm {
+    /// Version byte that prefixes addresses of this algorithm.
+    pub fn version(self) -> u8 {
+        match self {
+            KeyAlgorithm::Ed25519 => 0x01,
+            KeyAlgorithm::Secp256k1 => 0x02,
+        }
+    }
+
+    /// The algorithm an address version byte stands for.
+    pub fn from_version(version: u8) -> Result<Self, AddressError> {
+        match version {
+            0x01 => Ok(KeyAlgorithm::Ed25519),
+            0x02 => Ok(KeyAlgorithm::Secp256k1),
+            other => Err(AddressError::UnknownVersion(other)),
+        }
+    }
+
+    /// Verifies `signature` over `message` with `public_key`.
+    pub fn verify(self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), AddressError> {
+        match self {
+            KeyAlgorithm::Ed25519 => {
+                use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+                let key_bytes: [u8; 32] = public_key.try_into().map_err(|_| AddressError::InvalidPublicKey(self))?;
+                let key = VerifyingKey::from_bytes(&key_bytes).map_err(|_| AddressError::InvalidPublicKey(self))?;
+                let signature = Signature::from_slice(signature).map_err(|_| AddressError::InvalidSignature(self))?;
+                key.verify(message, &signature).map_err(|_| AddressError::InvalidSignature(self))
+            }
+            KeyAlgorithm::Secp256k1 => {
+                use k256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+                let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|_| AddressError::InvalidPublicKey(self))?;
+                let signature = Signature::from_slice(signature).map_err(|_| AddressError::InvalidSignature(self))?;
+                key.verify(message, &signature).map_err(|_| AddressError::InvalidSignature(self))
+            }
+        }
+    }
+
+    fn check_public_key(self, public_key: &[u8]) -> Result<(), AddressError> {
+        let valid = match self {
+            KeyAlgorithm::Ed25519 => <[u8; 32]>::try_from(public_key).ok()
+                .is_some_and(|bytes| ed25519_dalek::VerifyingKey::from_bytes(&bytes).is_ok()),
+            // Compressed keys only, so each key has exactly one address
+            KeyAlgorithm::Secp256k1 => public_key.len() == 33 && k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key).is_ok(),
+        };
+        if valid { Ok(()) } else { Err(AddressError::InvalidPublicKey(self)) }
+    }
+}
+
+impl fmt::Display for KeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyAlgorithm::Ed25519 => f.write_str("ed25519"),
+            KeyAlgorithm::Secp256k1 => f.write_str("secp256k1"),
+        }
+    }
+}
+
+impl std::str::FromStr for KeyAlgorithm {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ed25519" => Ok(KeyAlgorithm::Ed25519),
+            "secp256k1" => Ok(KeyAlgorithm::Secp256k1),
+            other => Err(AddressError::UnknownAlgorithm(other.to_string())),
+        }
+    }
+}
+
+fn key_hash(public_key: &[u8]) -> [u8; ADDRESS_KEY_HASH_LEN] {
+    let digest = Sha256::digest(public_key);
+    let mut hash = [0u8; ADDRESS_KEY_HASH_LEN];
+    hash.copy_from_slice(&digest[..ADDRESS_KEY_HASH_LEN]);
+    hash
+}
+
+/// Derives the address of `public_key` under `algorithm`.
+pub fn address_from_public_key(algorithm: KeyAlgorithm, public_key: &[u8]) -> Result<Address, AddressError> {
+    algorithm.check_public_key(public_key)?;
+    let mut address = Vec::with_capacity(1 + ADDRESS_KEY_HASH_LEN);
+    address.push(algorithm.version());
+    address.extend_from_slice(&key_hash(public_key));
+    Ok(address)
+}
+
+/// Splits an address into its key algorithm and public key hash.
+pub fn parse_address(address: &[u8]) -> Result<(KeyAlgorithm, [u8; ADDRESS_KEY_HASH_LEN]), AddressError> {
+    if address.len() != 1 + ADDRESS_KEY_HASH_LEN {
+        return Err(AddressError::InvalidLength { expected: 1 + ADDRESS_KEY_HASH_LEN, actual: address.len() });
+    }
+    let algorithm = KeyAlgorithm::from_version(address[0])?;
+    let mut hash = [0u8; ADDRESS_KEY_HASH_LEN];
+    hash.copy_from_slice(&address[1..]);
+    Ok((algorithm, hash))
+}
+
+/// Encodes `address` as bech32m under `hrp`.
+pub fn encode_address(hrp: &str, address: &[u8]) -> String {
+    bech32::encode(hrp, address)
+}
+
+/// Decodes a bech32m address, checking its checksum, that it belongs to `hrp`, and its version and length.
+pub fn decode_address(hrp: &str, encoded: &str) -> Result<Address, AddressError> {
+    let (actual, address) = bech32::decode(encoded)?;
+    if actual != hrp {
+        return Err(AddressError::WrongNetwork { expected: hrp.to_string(), actual });
+    }
+    parse_address(&address)?;
+    Ok(address)
+}
+
+/// Reads an address typed by a user: bech32m under `hrp`, or the legacy hex form
+/// (which has no checksum, so only its version and length are checked).
+pub fn parse_address_str(hrp: &str, text: &str) -> Result<Address, AddressError> {
+    let text = text.trim();
+    match hex::decode(text) {
+        Ok(address) => {
+            parse_address(&address)?;
+            Ok(address)
+        }
+        Err(_) => decode_address(hrp, text),
+    }
+}
+
+/// Checks that `signature` over `message` was made by the key behind `address`.
+pub fn verify_signature(address: &[u8], public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), AddressError> {
+    let (algorithm, hash) = parse_address(address)?;
+    if key_hash(public_key) != hash {
+        return Err(AddressError::KeyMismatch);
+    }
+    algorithm.verify(public_key, message, signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_verification_dispatches_by_version() {
+        let message = b"store chunk 7";
+
+        let ed_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        let ed_public = ed_key.verifying_key().to_bytes();
+        let ed_address = address_from_public_key(KeyAlgorithm::Ed25519, &ed_public).unwrap();
+        let ed_signature = ed25519_dalek::Signer::sign(&ed_key, message).to_bytes();
+        assert_eq!(ed_address[0], KeyAlgorithm::Ed25519.version());
+        assert!(verify_signature(&ed_address, &ed_public, message, &ed_signature).is_ok());
+        assert_eq!(
+            verify_signature(&ed_address, &ed_public, b"other", &ed_signature),
+            Err(AddressError::InvalidSignature(KeyAlgorithm::Ed25519))
+        );
+
+        let k_key = k256::ecdsa::SigningKey::from_slice(&[9u8; 32]).unwrap();
+        let k_public = k_key.verifying_key().to_sec1_bytes();
+        let k_address = address_from_public_key(KeyAlgorithm::Secp256k1, &k_public).unwrap();
+        let k_signature: k256::ecdsa::Signature = k256::ecdsa::signature::Signer::sign(&k_key, message);
+        assert!(verify_signature(&k_address, &k_public, message, &k_signature.to_bytes()).is_ok());
+
+        // A key of one scheme cannot stand in for an address of another
+        assert_eq!(verify_signature(&ed_address, &k_public, message, &k_signature.to_bytes()), Err(AddressError::KeyMismatch));
+        let mut unknown = ed_address.clone();
+        unknown[0] = 0x7f;
+        assert_eq!(parse_address(&unknown), Err(AddressError::UnknownVersion(0x7f)));
+        assert!(matches!(parse_address(&ed_public), Err(AddressError::InvalidLength { .. })));
+    }
+
+    #[test]
+    fn test_encoded_addresses() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        let address = address_from_public_key(KeyAlgorithm::Ed25519, key.verifying_key().as_bytes()).unwrap();
+        let encoded = encode_address(DEFAULT_ADDRESS_HRP, &address);
+        assert!(encoded.starts_with("bds1"));
+        assert_eq!(decode_address(DEFAULT_ADDRESS_HRP, &encoded), Ok(address.clone()));
+        assert_eq!(parse_address_str(DEFAULT_ADDRESS_HRP, &encoded), Ok(address.clone()));
+        assert_eq!(parse_address_str(DEFAULT_ADDRESS_HRP, &hex::encode(&address)), Ok(address.clone()));
+
+        assert_eq!(
+            decode_address("tbds", &encoded),
+            Err(AddressError::WrongNetwork { expected: "tbds".to_string(), actual: "bds".to_string() })
+        );
+        let typo = encoded.replacen('q', "p", 1);
+        assert_eq!(parse_address_str(DEFAULT_ADDRESS_HRP, &typo), Err(AddressError::Encoding(Bech32Error::InvalidChecksum)));
+        // Well-formed encodings of something that is not an address are refused too
+        assert!(matches!(decode_address(DEFAULT_ADDRESS_HRP, &encode_address(DEFAULT_ADDRESS_HRP, &[1, 2, 3])), Err(AddressError::InvalidLength { .. })));
+        assert!(matches!(parse_address_str(DEFAULT_ADDRESS_HRP, "07"), Err(AddressError::InvalidLength { .. })));
+    }
+}
@@ -0,0 +1,141 @@
+//!
+//! # Bech32m
+//! The checksummed base32 text encoding of BIP-350, used for addresses.
+//!
+//! An encoded string is a human-readable part (HRP), the separator `1`, the data in a
+//! 32-character alphabet without look-alike characters, and a six-character BCH checksum
+//! over both. The checksum detects any error in up to four characters, and the HRP
+//! keeps strings meant for one chain from being accepted by another.
+
+/// The 32 data characters, indexed by value.
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+/// Checksum residue that marks bech32m (plain bech32 uses 1).
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+const CHECKSUM_LEN: usize = 6;
+/// Longest encoded string BIP-173 allows.
+const MAX_LEN: usize = 90;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Bech32Error {
+    #[error("Missing the '1' separator")]
+    MissingSeparator,
+    #[error("Invalid human-readable part")]
+    InvalidHrp,
+    #[error("Invalid character {0:?}")]
+    InvalidChar(char),
+    #[error("Mixed upper and lower case")]
+    MixedCase,
+    #[error("Too long: {0} characters")]
+    TooLong(usize),
+    #[error("Checksum mismatch (mistyped or truncated)")]
+    InvalidChecksum,
+    #[error("Invalid data padding")]
+    InvalidPadding,
+}
+
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+    values.fold(1u32, |checksum, value| {
+        let top = checksum >> 25;
+        let checksum = (checksum & 0x1ff_ffff) << 5 ^ value as u32;
+        GENERATOR.iter().enumerate()
+            .filter(|(i, _)| top >> i & 1 == 1)
+            .fold(checksum, |checksum, (_, generator)| checksum ^ generator)
+    })
+}
+
+// The HRP as the checksum sees it: high bits of each character, a zero, then the low bits
+fn expand_hrp(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes().map(|c| c >> 5).chain([0]).chain(hrp.bytes().map(|c| c & 31))
+}
+
+/// Encodes `data` under `hrp` (lowercase ASCII).
+pub fn encode(hrp: &str, data: &[u8]) -> String {
+    let values = convert_bits(data, 8, 5, true).expect("Padding 8-bit data always succeeds");
+    let residue = polymod(expand_hrp(hrp).chain(values.iter().copied()).chain([0; CHECKSUM_LEN])) ^ BECH32M_CONST;
+    let checksum = (0..CHECKSUM_LEN).map(|i| (residue >> (5 * (CHECKSUM_LEN - 1 - i)) & 31) as u8);
+    let mut encoded = String::with_capacity(hrp.len() + 1 + values.len() + CHECKSUM_LEN);
+    encoded.push_str(hrp);
+    encoded.push('1');
+    encoded.extend(values.into_iter().chain(checksum).map(|value| CHARSET[value as usize] as char));
+    encoded
+}
+
+/// Decodes a bech32m string into its (lowercase) HRP and data, verifying the checksum.
+pub fn decode(encoded: &str) -> Result<(String, Vec<u8>), Bech32Error> {
+    if encoded.len() > MAX_LEN {
+        return Err(Bech32Error::TooLong(encoded.len()));
+    }
+    if encoded.chars().any(|c| c.is_ascii_lowercase()) && encoded.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(Bech32Error::MixedCase);
+    }
+    let encoded = encoded.to_ascii_lowercase();
+    let separator = encoded.rfind('1').ok_or(Bech32Error::MissingSeparator)?;
+    let (hrp, data) = (&encoded[..separator], &encoded[separator + 1..]);
+    if hrp.is_empty() || hrp.bytes().any(|c| !(33..=126).contains(&c)) {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if data.len() < CHECKSUM_LEN {
+        return Err(Bech32Error::InvalidChecksum);
+    }
+    let values = data.chars()
+        .map(|c| CHARSET.iter().position(|&x| x as char == c).map(|value| value as u8).ok_or(Bech32Error::InvalidChar(c)))
+        .collect::<Result<Vec<u8>, _>>()?;
+    if polymod(expand_hrp(hrp).chain(values.iter().copied())) != BECH32M_CONST {
+        return Err(Bech32Error::InvalidChecksum);
+    }
+    let data = convert_bits(&values[..values.len() - CHECKSUM_LEN], 5, 8, false)?;
+    Ok((hrp.to_string(), data))
+}
+
+// Regroups `data` from `from`-bit to `to`-bit values
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Bech32Error> {
+    let mut accumulator = 0u32;
+    let mut bits = 0u32;
+    let mut output = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    let max = (1u32 << to) - 1;
+    for &value in data {
+        accumulator = accumulator << from | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            output.push((accumulator >> bits & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            output.push((accumulator << (to - bits) & max) as u8);
+        }
+    } else if bits >= from || accumulator << (to - bits) & max != 0 {
+        return Err(Bech32Error::InvalidPadding);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bech32m_vectors() {
+        // Valid checksums from BIP-350
+        for valid in ["A1LQFN3A", "a1lqfn3a", "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx", "?1v759aa"] {
+            assert!(decode(valid).is_ok(), "{}", valid);
+        }
+        // Plain bech32 (BIP-173) checksums are not bech32m
+        assert_eq!(decode("a12uel5l"), Err(Bech32Error::InvalidChecksum));
+
+        let data = [0x01, 0xde, 0xad, 0xbe, 0xef];
+        let encoded = encode("bds", &data);
+        assert_eq!(decode(&encoded), Ok(("bds".to_string(), data.to_vec())));
+        assert_eq!(decode(&encoded.to_uppercase()), Ok(("bds".to_string(), data.to_vec())));
+
+        // Any single mistyped character is caught
+        let mut typo = encoded.clone().into_bytes();
+        let last = typo.len() - 8;
+        typo[last] = if typo[last] == b'q' { b'p' } else { b'q' };
+        assert_eq!(decode(std::str::from_utf8(&typo).unwrap()), Err(Bech32Error::InvalidChecksum));
+        assert_eq!(decode("bds1qpzrB"), Err(Bech32Error::MixedCase));
+        assert_eq!(decode("bds1qpzbq9"), Err(Bech32Error::InvalidChar('b')));
+    }
+}
@@ -0,0 +1,71 @@
+//!
+//! # Storage Deals
+//! Agreements, made on-chain, by which a client pays a provider to keep a payload retrievable
+//! for a number of blocks.
+//!
+//! A `StorageDeal` transaction is sent by the client to the provider (its receiver) and names
+//! the payload hash, the duration in blocks and the price, which moves from the client to the
+//! provider when the deal is confirmed. The provider's obligation window runs from the height of
+//! the deal's block for `duration` blocks. A provider can't take a second deal on a payload
+//! while it is already bound to store it, so at most one window is open per payload and
+//! provider. Deals are recorded in the chain state, by payload, for storage proofs to be
+//! checked against (see [`StorageManager::get_storage_deals`](crate::storage::StorageManager::get_storage_deals)).
+
+use super::{Address, Hash, Transaction, TransactionType};
+use serde::{Serialize, Deserialize};
+
+/// Longest obligation a deal can set, in blocks (about two years of one-minute blocks).
+pub const MAX_DEAL_DURATION: u64 = 1_051_200;
+
+/// A confirmed storage deal; see the module documentation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StorageDeal {
+    /// Hash of the deal transaction.
+    pub deal_id: Hash,
+    pub data_hash: Hash,
+    pub client: Address,
+    pub provider: Address,
+    pub price: u64,
+    /// Height of the deal's block, where the obligation starts.
+    pub start_height: u64,
+    /// First height the provider is no longer bound at.
+    pub end_height: u64,
+}
+
+impl StorageDeal {
+    /// The deal `tx` makes when confirmed at `height`; `None` for other transaction kinds.
+    pub fn from_transaction(tx: &Transaction, height: u64) -> Option<Self> {
+        let TransactionType::StorageDeal { data_hash, duration, price } = tx.transaction_type() else { return None };
+        Some(StorageDeal {
+            deal_id: tx.calculate_hash(),
+            data_hash: *data_hash,
+            client: tx.sender().clone(),
+            provider: tx.receiver().cloned().unwrap_or_default(),
+            price: *price,
+            start_height: height,
+            end_height: height.saturating_add(*duration),
+        })
+    }
+
+    /// Whether the provider is bound to store the payload at `height`.
+    pub fn is_active_at(&self, height: u64) -> bool {
+        (self.start_height..self.end_height).contains(&height)
+    }
+}
+
+/// Checks the terms of a deal transaction that don't depend on the chain: a provider other than
+/// the client, a duration of 1 to `MAX_DEAL_DURATION` blocks and a price paid.
+pub fn check_deal_terms(tx: &Transaction) -> Result<(), String> {
+    let TransactionType::StorageDeal { duration, price, .. } = tx.transaction_type() else { return Ok(()) };
+    match tx.receiver() {
+        Some(provider) if !provider.is_empty() && provider != tx.sender() => {}
+        _ => return Err("A storage deal needs a provider other than its client".to_string()),
+    }
+    if !(1..=MAX_DEAL_DURATION).contains(duration) {
+        return Err(format!("Deal duration {} is outside 1..={} blocks", duration, MAX_DEAL_DURATION));
+    }
+    if *price == 0 {
+        return Err("A storage deal must pay its provider".to_string());
+    }
+    Ok(())
+}
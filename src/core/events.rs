@@ -0,0 +1,177 @@
+//!
+//! # Transaction Events
+//! Structured records of what a confirmed transaction did, for dApps and explorers that
+//! want to be notified of activity instead of decoding every transaction themselves.
+//!
+//! Each event has a topic naming what happened, the addresses it concerns and named data
+//! fields. Events are derived from the transaction when its block joins the main chain and
+//! stored next to the block, indexed by topic and by address (see
+//! [`StorageManager::get_events`](crate::storage::StorageManager::get_events)). Built-in
+//! transaction kinds emit the events below; contracts will add topics of their own.
+
+use super::{Address, Hash, Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Native currency moved from `from` to `to`.
+pub const TOPIC_TRANSFER: &str = "transfer";
+/// A token was created with its whole supply held by `creator`.
+pub const TOPIC_TOKEN_CREATED: &str = "token_created";
+/// Tokens of `token_id` moved from `from` to `to`.
+pub const TOPIC_TOKEN_TRANSFER: &str = "token_transfer";
+/// A payload hash was anchored on-chain.
+pub const TOPIC_DATA_STORED: &str = "data_stored";
+/// Native currency was locked as validator stake.
+pub const TOPIC_STAKED: &str = "staked";
+/// Validator stake was released.
+pub const TOPIC_UNSTAKED: &str = "unstaked";
+/// A producer was reported for sealing two headers at one height.
+pub const TOPIC_DOUBLE_SIGN_REPORTED: &str = "double_sign_reported";
+/// `client` paid `provider` to keep a payload for `duration` blocks.
+pub const TOPIC_STORAGE_DEAL: &str = "storage_deal";
+/// The pointer `name` was pointed at `payload_hash`.
+pub const TOPIC_POINTER_PUBLISHED: &str = "pointer_published";
+/// A block's subsidy and fees were paid to its miner.
+pub const TOPIC_BLOCK_REWARD: &str = "block_reward";
+
+/// Longest topic an event can have, so it fits the one-byte length of index keys.
+pub const MAX_TOPIC_LEN: usize = 64;
+
+/// A value carried by an event field.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum EventValue {
+    Amount(u64),
+    Address(Address),
+    Hash(Hash),
+    Text(String),
+}
+
+/// Something a transaction did; see the module documentation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub topic: String,
+    /// Addresses the event concerns; the event is found under each of them.
+    pub addresses: Vec<Address>,
+    pub data: BTreeMap<String, EventValue>,
+}
+
+impl Event {
+    fn new(topic: &str, addresses: Vec<Address>, data: impl IntoIterator<Item = (&'static str, EventValue)>) -> Self {
+        let mut unique = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            if !address.is_empty() && !unique.contains(&address) {
+                unique.push(address);
+            }
+        }
+        Event {
+            topic: topic.to_string(),
+            addresses: unique,
+            data: data.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        }
+    }
+}
+
+/// The events `tx` emits when it is confirmed, in order.
+pub fn transaction_events(tx: &Transaction) -> Vec<Event> {
+    let sender = tx.sender().clone();
+    let receiver = tx.receiver().cloned();
+    let event = match tx.transaction_type() {
+        TransactionType::TransferNative { amount } => {
+            let Some(receiver) = receiver else { return Vec::new() };
+            Event::new(TOPIC_TRANSFER, vec![sender.clone(), receiver.clone()], [
+                ("from", EventValue::Address(sender)),
+                ("to", EventValue::Address(receiver)),
+                ("amount", EventValue::Amount(*amount)),
+            ])
+        }
+        TransactionType::TransferToken { token_id, amount } => {
+            let Some(receiver) = receiver else { return Vec::new() };
+            Event::new(TOPIC_TOKEN_TRANSFER, vec![sender.clone(), receiver.clone()], [
+                ("token_id", EventValue::Hash(*token_id)),
+                ("from", EventValue::Address(sender)),
+                ("to", EventValue::Address(receiver)),
+                ("amount", EventValue::Amount(*amount)),
+            ])
+        }
+        TransactionType::CreateToken { metadata } => Event::new(TOPIC_TOKEN_CREATED, vec![metadata.creator.clone()], [
+            ("token_id", EventValue::Hash(metadata.metadata_hash)),
+            ("creator", EventValue::Address(metadata.creator.clone())),
+            ("name", EventValue::Text(metadata.name.clone())),
+            ("symbol", EventValue::Text(metadata.symbol.clone())),
+            ("total_supply", EventValue::Amount(metadata.total_supply)),
+        ]),
+        TransactionType::StoreData { data_hash, data_size, .. } => Event::new(TOPIC_DATA_STORED, vec![sender.clone()], [
+            ("data_hash", EventValue::Hash(*data_hash)),
+            ("data_size", EventValue::Amount(*data_size)),
+            ("sender", EventValue::Address(sender)),
+        ]),
+        TransactionType::Stake { amount } => Event::new(TOPIC_STAKED, vec![sender.clone()], [
+            ("validator", EventValue::Address(sender)),
+            ("amount", EventValue::Amount(*amount)),
+        ]),
+        TransactionType::Unstake { amount } => Event::new(TOPIC_UNSTAKED, vec![sender.clone()], [
+            ("validator", EventValue::Address(sender)),
+            ("amount", EventValue::Amount(*amount)),
+        ]),
+        TransactionType::SlashDoubleSign { evidence } => {
+            let offender = evidence.offender().clone();
+            Event::new(TOPIC_DOUBLE_SIGN_REPORTED, vec![offender.clone(), sender.clone()], [
+                ("offender", EventValue::Address(offender)),
+                ("reporter", EventValue::Address(sender)),
+                ("height", EventValue::Amount(evidence.header_a.height)),
+            ])
+        }
+        TransactionType::StorageDeal { data_hash, duration, price } => {
+            let Some(receiver) = receiver else { return Vec::new() };
+            Event::new(TOPIC_STORAGE_DEAL, vec![sender.clone(), receiver.clone()], [
+                ("data_hash", EventValue::Hash(*data_hash)),
+                ("client", EventValue::Address(sender)),
+                ("provider", EventValue::Address(receiver)),
+                ("duration", EventValue::Amount(*duration)),
+                ("price", EventValue::Amount(*price)),
+            ])
+        }
+        TransactionType::PublishPointer { record } => Event::new(TOPIC_POINTER_PUBLISHED, vec![sender.clone()], [
+            ("name", EventValue::Hash(record.name)),
+            ("payload_hash", EventValue::Hash(record.payload_hash)),
+            ("sequence", EventValue::Amount(record.sequence)),
+            ("publisher", EventValue::Address(sender)),
+        ]),
+        TransactionType::Coinbase { height, amount } => {
+            let Some(receiver) = receiver else { return Vec::new() };
+            Event::new(TOPIC_BLOCK_REWARD, vec![receiver.clone()], [
+                ("miner", EventValue::Address(receiver)),
+                ("height", EventValue::Amount(*height)),
+                ("amount", EventValue::Amount(*amount)),
+            ])
+        }
+    };
+    vec![event]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_events() {
+        let (alice, bob) = (vec![1u8; 21], vec![2u8; 21]);
+        let events = transaction_events(&Transaction::new_transfer_token(alice.clone(), bob.clone(), [7u8; 32], 30));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].topic, TOPIC_TOKEN_TRANSFER);
+        assert_eq!(events[0].addresses, vec![alice.clone(), bob.clone()]);
+        assert_eq!(events[0].data["amount"], EventValue::Amount(30));
+        assert_eq!(events[0].data["token_id"], EventValue::Hash([7u8; 32]));
+
+        // A transfer to oneself concerns one address
+        let events = transaction_events(&Transaction::new_transfer_native(alice.clone(), alice.clone(), 5));
+        assert_eq!(events[0].addresses, vec![alice.clone()]);
+
+        // A coinbase has no sender; only the miner is concerned
+        let events = transaction_events(&Transaction::new_coinbase(bob.clone(), 3, 50));
+        assert_eq!((events[0].topic.as_str(), events[0].addresses.clone()), (TOPIC_BLOCK_REWARD, vec![bob]));
+        for topic in [TOPIC_TRANSFER, TOPIC_TOKEN_CREATED, TOPIC_TOKEN_TRANSFER, TOPIC_DATA_STORED, TOPIC_STAKED, TOPIC_UNSTAKED, TOPIC_DOUBLE_SIGN_REPORTED, TOPIC_BLOCK_REWARD] {
+            assert!(topic.len() <= MAX_TOPIC_LEN);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//!
+//! # Compact Block Filters
+//! Small probabilistic summaries of what each block touches, in the style of BIP158, so a
+//! wallet can find the blocks relevant to it without downloading every block.
+//!
+//! A block's filter is a Golomb-coded set of the addresses its transactions involve (senders,
+//! receivers and every address their events concern) and of the payload hashes they anchor or
+//! make storage deals on.
+//! A wallet tests its addresses against each filter and fetches only the blocks that match.
+//! A filter never misses an item of its block; an item not in the block matches with a
+//! probability of about 1 in `FILTER_M`.
+//!
+//! Each item is hashed with SHA-256 keyed by the block hash, mapped onto `[0, N * FILTER_M)`
+//! for a set of `N` items, and the sorted values are stored as Golomb-Rice coded differences
+//! with parameter `FILTER_P`.
+
+use super::events::transaction_events;
+use super::{Block, Hash, TransactionType};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::BTreeSet;
+
+/// Golomb-Rice parameter: the low bits of each difference written as is.
+pub const FILTER_P: u8 = 19;
+/// Inverse false positive rate of one tested item.
+pub const FILTER_M: u64 = 784_931;
+
+/// A block's compact filter; see the module documentation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockFilter {
+    /// Number of items in the set.
+    pub item_count: u32,
+    /// Golomb-Rice coded differences of the sorted item values.
+    pub data: Vec<u8>,
+}
+
+/// The items the filter of `block` is built over, without duplicates.
+pub fn block_filter_items(block: &Block) -> Vec<Vec<u8>> {
+    let mut items = BTreeSet::new();
+    for tx in &block.transactions {
+        items.insert(tx.sender().clone());
+        if let Some(receiver) = tx.receiver() {
+            items.insert(receiver.clone());
+        }
+        for event in transaction_events(tx) {
+            items.extend(event.addresses);
+        }
+        if let TransactionType::StoreData { data_hash, .. } | TransactionType::StorageDeal { data_hash, .. } = tx.transaction_type() {
+            items.insert(data_hash.to_vec());
+        }
+    }
+    // Coinbases have no sender
+    items.remove(&Vec::new());
+    items.into_iter().collect()
+}
+
+// Maps `item` onto [0, range), keyed by the block so values differ from block to block
+fn item_value(block_hash: &Hash, item: &[u8], range: u64) -> u64 {
+    let digest = Sha256::new().chain_update(block_hash).chain_update(item).finalize();
+    let value = u64::from_be_bytes(digest[..8].try_into().expect("slice is 8 bytes"));
+    ((value as u128 * range as u128) >> 64) as u64
+}
+
+impl BlockFilter {
+    /// Builds the filter of the block `block_hash` over `items`.
+    pub fn new(block_hash: &Hash, items: &[Vec<u8>]) -> Self {
+        let range = items.len() as u64 * FILTER_M;
+        let mut values: Vec<u64> = items.iter().map(|item| item_value(block_hash, item, range)).collect();
+        values.sort_unstable();
+        let mut writer = BitWriter::default();
+        let mut previous = 0;
+        for value in values {
+            let delta = value - previous;
+            for _ in 0..delta >> FILTER_P {
+                writer.write_bit(true);
+            }
+            writer.write_bit(false);
+            writer.write_bits(delta, FILTER_P);
+            previous = value;
+        }
+        BlockFilter { item_count: items.len() as u32, data: writer.bytes }
+    }
+
+    /// Builds the filter of `block`.
+    pub fn for_block(block: &Block) -> Self {
+        Self::new(&block.hash(), &block_filter_items(block))
+    }
+
+    /// Whether the block `block_hash` this filter was built for may involve any of `items`.
+    /// A malformed filter matches nothing.
+    pub fn matches_any(&self, block_hash: &Hash, items: &[&[u8]]) -> bool {
+        if self.item_count == 0 || items.is_empty() {
+            return false;
+        }
+        let range = self.item_count as u64 * FILTER_M;
+        let mut targets: Vec<u64> = items.iter().map(|item| item_value(block_hash, item, range)).collect();
+        targets.sort_unstable();
+
+        let mut reader = BitReader::new(&self.data);
+        let mut targets = targets.into_iter().peekable();
+        let mut value = 0u64;
+        for _ in 0..self.item_count {
+            let mut quotient = 0u64;
+            loop {
+                match reader.read_bit() {
+                    Some(true) => quotient += 1,
+                    Some(false) => break,
+                    None => return false,
+                }
+            }
+            let Some(remainder) = reader.read_bits(FILTER_P) else { return false };
+            value = value.saturating_add((quotient << FILTER_P) | remainder);
+            while let Some(&target) = targets.peek() {
+                if target == value {
+                    return true;
+                }
+                if target > value {
+                    break;
+                }
+                targets.next();
+            }
+            if targets.peek().is_none() {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Whether the block `block_hash` this filter was built for may involve `item`.
+    pub fn matches(&self, block_hash: &Hash, item: &[u8]) -> bool {
+        self.matches_any(block_hash, &[item])
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bits == self.bytes.len() * 8 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().expect("a byte was pushed") |= 0x80 >> (self.bits % 8);
+        }
+        self.bits += 1;
+    }
+
+    // Writes the low `count` bits of `value`, most significant first
+    fn write_bits(&mut self, value: u64, count: u8) {
+        for shift in (0..count).rev() {
+            self.write_bit((value >> shift) & 1 == 1);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, position: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.position / 8)?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Transaction;
+
+    #[test]
+    fn test_block_filter() {
+        let (alice, bob, carol) = (vec![1u8; 21], vec![2u8; 21], vec![3u8; 21]);
+        let transactions = vec![
+            Transaction::new_coinbase(carol.clone(), 1, 50),
+            Transaction::new_transfer_native(alice.clone(), bob.clone(), 5),
+            Transaction::new_store_data(alice.clone(), [7u8; 32], 1024),
+        ];
+        let block = Block::new([0u8; 32], transactions, 0x207f_ffff, 1);
+        let items = block_filter_items(&block);
+        assert_eq!(items, vec![alice.clone(), bob.clone(), carol.clone(), vec![7u8; 32]]);
+
+        let filter = BlockFilter::for_block(&block);
+        let block_hash = block.hash();
+        assert_eq!(filter.item_count, 4);
+        for item in &items {
+            assert!(filter.matches(&block_hash, item));
+        }
+        assert!(filter.matches_any(&block_hash, &[&[9u8; 21], &bob]));
+        assert!(!filter.matches(&block_hash, &[9u8; 21]));
+        assert!(!filter.matches_any(&block_hash, &[]));
+
+        // Filters are keyed by their block, and survive a bincode round trip
+        assert!((0..100u8).filter(|i| filter.matches(&[*i; 32], &alice)).count() < 100);
+        let decoded: BlockFilter = bincode::deserialize(&bincode::serialize(&filter).unwrap()).unwrap();
+        assert!(decoded.matches(&block_hash, &carol));
+
+        // An empty block matches nothing; a truncated filter matches nothing it lost
+        let empty = BlockFilter::for_block(&Block::genesis(0, 0x207f_ffff));
+        assert_eq!((empty.item_count, empty.data.len()), (0, 0));
+        assert!(!empty.matches(&[0u8; 32], &alice));
+        let truncated = BlockFilter { item_count: filter.item_count, data: filter.data[..1].to_vec() };
+        assert!(!truncated.matches(&block_hash, &[9u8; 21]));
+    }
+}
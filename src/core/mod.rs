@@ -1,22 +1,50 @@
 use serde::{Serialize, Deserialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::SystemTime;
 use sha2::{Sha256, Digest};
 use hex;
 // Corrected: Import StorageError as well
-use crate::storage::{StorageManager, StorageError};
+use crate::storage::{AddressTxRef, EventFilter, EventRecord, StorageManager, StorageError, StorageStats};
 use crate::consensus; // Import consensus functions
-use crate::mempool::Mempool; // Import Mempool
+use crate::consensus::{Checkpoints, ConsensusEngine, ConsensusMode, EngineContext, InvalidBlockEvidence, MAX_INVALID_BLOCK_EVIDENCE, U256};
+use crate::consensus::pos::{self, BlockSeal, DoubleSignEvidence};
+use ed25519_dalek::SigningKey;
+use crate::mempool::{Mempool, MempoolEntry, MempoolEvent, MempoolInfo, PendingTransaction, SenderLimits}; // Import Mempool
+use crate::chain_config::{ChainConfig, TxFeature};
 use std::path::Path;
-use log::{info, error, debug};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::{info, error, debug, warn};
+
+pub mod address;
+pub mod bech32;
+pub mod deals;
+pub mod events;
+pub mod filters;
+pub mod namespaces;
+pub mod pointers;
+pub mod snapshot;
+pub mod time;
+
+pub use deals::StorageDeal;
+pub use events::{Event, EventValue};
+pub use filters::BlockFilter;
+pub use pointers::{PointerRecord, PublishedPointer};
+pub use snapshot::{MerkleProof, TokenHolder, TokenHolderSnapshot};
 
 // Constants
 const MAX_TRANSACTIONS_PER_BLOCK: usize = 100; // Example limit
 const MEMPOOL_MAX_SIZE: usize = 1000; // Example limit
+const IMPORT_BATCH_SIZE: usize = 500; // Blocks per WriteBatch during checkpointed sync
+/// Layout version of the file written by `Blockchain::save_mempool`.
+const SAVED_MEMPOOL_VERSION: u32 = 3;
+
+/// Transaction format version produced by this node's constructors.
+pub const CURRENT_TRANSACTION_VERSION: u32 = 1;
 
 // Define Hash as a fixed-size array for SHA-256
 pub type Hash = [u8; 32];
 
-// Placeholder for public key/address type
+// Address bytes; new addresses are versioned by key algorithm (see `address`)
 pub type Address = Vec<u8>;
 
 // Represents metadata for a custom token
@@ -44,15 +72,31 @@ impl TokenMetadata {
 // Enum to define different transaction types
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum TransactionType {
-    TransferNative, // Transfer the base currency
+    TransferNative { amount: u64 }, // Transfer the base currency
     TransferToken { token_id: Hash, amount: u64 }, // Transfer a specific token
     CreateToken { metadata: TokenMetadata }, // Create a new token
-    StoreData { data_hash: Hash, data_size: u64 }, // Store off-chain data reference
+    StoreData { data_hash: Hash, data_size: u64, namespace: Option<String> }, // Store off-chain data reference, optionally filed under a namespace
+    Stake { amount: u64 }, // Lock native currency as validator stake (PoS)
+    Unstake { amount: u64 }, // Release previously staked currency (PoS)
+    SlashDoubleSign { evidence: Box<DoubleSignEvidence> }, // Report a producer that sealed two headers at one height
+    Coinbase { height: u64, amount: u64 }, // Block subsidy plus fees paid to the miner; only as a block's first transaction
+    StorageDeal { data_hash: Hash, duration: u64, price: u64 }, // Pay the receiver to keep a payload for `duration` blocks
+    PublishPointer { record: PointerRecord }, // Point a signed name at a payload hash
+}
+
+/// The `StoreData` transaction that first put a payload hash on the main chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadAnchor {
+    pub height: u64,
+    pub tx_hash: Hash,
+    /// Payload size the transaction declared.
+    pub declared_size: u64,
 }
 
 // Represents a single transaction in the blockchain
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Transaction {
+    version: u32, // Format version, gated by activation height in ChainConfig
     sender: Address,
     receiver: Option<Address>, // Optional for some types like CreateToken
     // amount: u64, // Amount is now part of TransactionType for transfers
@@ -60,45 +104,80 @@ pub struct Transaction {
     // data_hash: Option<Vec<u8>>, // Replaced by TransactionType::StoreData
     // data_size: Option<u64>, // Replaced by TransactionType::StoreData
     transaction_type: TransactionType,
+    fee: u64, // Paid to the block producer
+    nonce: Option<u64>, // Sender-chosen sequence number; a pending transaction can be replaced by one with the same nonce
     // signature: Vec<u8>,
-    // nonce: u64,
+}
+
+/// A transaction as stored before fees and nonces, read when migrating older databases.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LegacyTransaction {
+    version: u32,
+    sender: Address,
+    receiver: Option<Address>,
+    timestamp: u64,
+    transaction_type: TransactionType,
+}
+
+impl From<LegacyTransaction> for Transaction {
+    fn from(legacy: LegacyTransaction) -> Self {
+        Transaction {
+            version: legacy.version,
+            sender: legacy.sender,
+            receiver: legacy.receiver,
+            timestamp: legacy.timestamp,
+            transaction_type: legacy.transaction_type,
+            fee: 0,
+            nonce: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl From<Transaction> for LegacyTransaction {
+    fn from(tx: Transaction) -> Self {
+        LegacyTransaction {
+            version: tx.version,
+            sender: tx.sender,
+            receiver: tx.receiver,
+            timestamp: tx.timestamp,
+            transaction_type: tx.transaction_type,
+        }
+    }
 }
 
 impl Transaction {
     // Constructor for native currency transfer
     pub fn new_transfer_native(sender: Address, receiver: Address, amount: u64) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+        let timestamp = time::unix_now();
         Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
             sender,
             receiver: Some(receiver),
             timestamp,
             transaction_type: TransactionType::TransferNative { amount }, // Specify amount here
+            fee: 0,
+            nonce: None,
         }
     }
 
     // Constructor for custom token transfer
     pub fn new_transfer_token(sender: Address, receiver: Address, token_id: Hash, amount: u64) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+        let timestamp = time::unix_now();
         Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
             sender,
             receiver: Some(receiver),
             timestamp,
             transaction_type: TransactionType::TransferToken { token_id, amount },
+            fee: 0,
+            nonce: None,
         }
     }
 
     // Constructor for creating a new token
     pub fn new_create_token(sender: Address, name: String, symbol: String, total_supply: u64) -> Self {
-        let creation_timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+        let creation_timestamp = time::unix_now();
         
         let mut metadata = TokenMetadata {
             name,
@@ -111,27 +190,211 @@ impl Transaction {
         metadata.metadata_hash = metadata.calculate_hash(); // Calculate the actual hash
 
         Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
             sender,
             receiver: None, // No specific receiver for token creation
             timestamp: creation_timestamp, // Use the same timestamp
             transaction_type: TransactionType::CreateToken { metadata },
+            fee: 0,
+            nonce: None,
         }
     }
 
     // Updated constructor for storing data hash
     pub fn new_store_data(sender: Address, data_hash: Hash, data_size: u64) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+        let timestamp = time::unix_now();
         Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
             sender,
             receiver: None, // No receiver for data storage
             timestamp,
-            transaction_type: TransactionType::StoreData { data_hash, data_size },
+            transaction_type: TransactionType::StoreData { data_hash, data_size, namespace: None },
+            fee: 0,
+            nonce: None,
+        }
+    }
+
+    // Constructor for storing data hash under a namespace
+    pub fn new_store_data_in(sender: Address, data_hash: Hash, data_size: u64, namespace: String) -> Self {
+        Transaction {
+            transaction_type: TransactionType::StoreData { data_hash, data_size, namespace: Some(namespace) },
+            ..Self::new_store_data(sender, data_hash, data_size)
+        }
+    }
+
+    // Constructor for a storage deal paying `provider` to keep a payload
+    pub fn new_storage_deal(client: Address, provider: Address, data_hash: Hash, duration: u64, price: u64) -> Self {
+        let timestamp = time::unix_now();
+        Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
+            sender: client,
+            receiver: Some(provider),
+            timestamp,
+            transaction_type: TransactionType::StorageDeal { data_hash, duration, price },
+            fee: 0,
+            nonce: None,
+        }
+    }
+
+    // Constructor for publishing a pointer record, sent and paid for by `sender`
+    pub fn new_publish_pointer(sender: Address, record: PointerRecord) -> Self {
+        let timestamp = time::unix_now();
+        Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
+            sender,
+            receiver: None,
+            timestamp,
+            transaction_type: TransactionType::PublishPointer { record },
+            fee: 0,
+            nonce: None,
+        }
+    }
+
+    // Constructor for staking native currency as a PoS validator
+    pub fn new_stake(sender: Address, amount: u64) -> Self {
+        Self::new_staking(sender, TransactionType::Stake { amount })
+    }
+
+    // Constructor for releasing staked currency
+    pub fn new_unstake(sender: Address, amount: u64) -> Self {
+        Self::new_staking(sender, TransactionType::Unstake { amount })
+    }
+
+    // Constructor for reporting double-signing evidence against a producer
+    pub fn new_slash_double_sign(sender: Address, evidence: DoubleSignEvidence) -> Self {
+        Self::new_staking(sender, TransactionType::SlashDoubleSign { evidence: Box::new(evidence) })
+    }
+
+    fn new_staking(sender: Address, transaction_type: TransactionType) -> Self {
+        let timestamp = time::unix_now();
+        Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
+            sender,
+            receiver: None,
+            timestamp,
+            transaction_type,
+            fee: 0,
+            nonce: None,
+        }
+    }
+
+    /// Pays `amount` (at most the block subsidy plus the block's fees) to `miner` in the block at `height`.
+    /// The coinbase has no sender; the height makes each one unique.
+    pub fn new_coinbase(miner: Address, height: u64, amount: u64) -> Self {
+        Transaction {
+            version: CURRENT_TRANSACTION_VERSION,
+            sender: Vec::new(),
+            receiver: Some(miner),
+            timestamp: time::unix_now(),
+            transaction_type: TransactionType::Coinbase { height, amount },
+            fee: 0,
+            nonce: None,
+        }
+    }
+
+    /// Overrides the format version (e.g. to build a transaction for an upcoming upgrade).
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Sets the fee offered to the block producer.
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Sets the sender's sequence number, making the transaction replaceable while pending.
+    pub fn with_nonce(mut self, nonce: u64) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    pub fn nonce(&self) -> Option<u64> {
+        self.nonce
+    }
+
+    /// Returns the transaction format version.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn transaction_type(&self) -> &TransactionType {
+        &self.transaction_type
+    }
+
+    pub fn sender(&self) -> &Address {
+        &self.sender
+    }
+
+    pub fn receiver(&self) -> Option<&Address> {
+        self.receiver.as_ref()
+    }
+
+    /// Fee paid to the block producer.
+    pub fn fee(&self) -> u64 {
+        self.fee
+    }
+
+    /// Whether this is a block's reward to its miner rather than a transaction from a sender.
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.transaction_type, TransactionType::Coinbase { .. })
+    }
+
+    /// Whether the transaction spends the sender's funds or stake, a fee included, or uses one of the
+    /// sender's nonces, so it only enters the mempool or a block signed by the sender (see
+    /// [`SignedTransaction`]).
+    pub fn requires_signature(&self) -> bool {
+        self.fee > 0 || self.nonce.is_some() || matches!(self.transaction_type,
+            TransactionType::TransferNative { .. } | TransactionType::TransferToken { .. } | TransactionType::StorageDeal { .. }
+            | TransactionType::Stake { .. } | TransactionType::Unstake { .. })
+    }
+
+    /// Bytes of off-chain data this transaction commits the network to storing.
+    pub fn data_commitment(&self) -> u64 {
+        match self.transaction_type {
+            TransactionType::StoreData { data_size, .. } => data_size,
+            _ => 0,
+        }
+    }
+
+    /// Serialized size in bytes, as stored in a block.
+    pub fn size(&self) -> usize {
+        bincode::serialized_size(self).expect("Failed to size transaction") as usize
+    }
+
+    /// Returns the gated feature this transaction's kind depends on, if any.
+    pub fn required_feature(&self) -> Option<TxFeature> {
+        match self.transaction_type {
+            TransactionType::CreateToken { .. } | TransactionType::TransferToken { .. } => Some(TxFeature::Tokens),
+            TransactionType::Stake { .. }
+            | TransactionType::Unstake { .. }
+            | TransactionType::SlashDoubleSign { .. } => Some(TxFeature::Staking),
+            TransactionType::StorageDeal { .. } => Some(TxFeature::StorageDeals),
+            TransactionType::PublishPointer { .. } => Some(TxFeature::Pointers),
+            TransactionType::TransferNative { .. } | TransactionType::StoreData { .. } | TransactionType::Coinbase { .. } => None,
         }
     }
 
+    /// Canonical byte encoding (bincode), as hashed and stored in blocks.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize transaction")
+    }
+
+    /// Parses the canonical encoding produced by `to_bytes`.
+    /// Trailing bytes, or any encoding that does not re-encode identically, are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let tx: Transaction = bincode::deserialize(bytes).map_err(|e| format!("Malformed transaction: {}", e))?;
+        if tx.to_bytes() != bytes {
+            return Err("Transaction bytes are not in canonical form".to_string());
+        }
+        Ok(tx)
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
     pub fn calculate_hash(&self) -> Hash {
         let mut hasher = Sha256::new();
         // Use bincode for consistent hashing
@@ -141,6 +404,70 @@ impl Transaction {
     }
 }
 
+/// A transaction with its sender's detached signature over the transaction hash, as
+/// produced by offline signers and accepted by `send_raw_transaction`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedTransaction {
+    pub transaction: Transaction,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedTransaction {
+    /// Canonical byte encoding (bincode).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize signed transaction")
+    }
+
+    /// Parses the canonical encoding produced by `to_bytes`, rejecting trailing or non-canonical bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let signed: SignedTransaction = bincode::deserialize(bytes).map_err(|e| format!("Malformed signed transaction: {}", e))?;
+        if signed.to_bytes() != bytes {
+            return Err("Signed transaction bytes are not in canonical form".to_string());
+        }
+        Ok(signed)
+    }
+
+    /// Signs `tx` with `key`, the ed25519 key behind its sender address.
+    pub fn sign_ed25519(tx: Transaction, key: &SigningKey) -> Self {
+        let signature = ed25519_dalek::Signer::sign(key, &tx.calculate_hash()).to_bytes().to_vec();
+        SignedTransaction { transaction: tx, public_key: key.verifying_key().to_bytes().to_vec(), signature }
+    }
+
+    /// Joins a transaction with the witness a block carries for it.
+    pub fn from_witness(transaction: Transaction, witness: TxWitness) -> Self {
+        SignedTransaction { transaction, public_key: witness.public_key, signature: witness.signature }
+    }
+
+    /// Splits off the signature part, as blocks and the mempool carry it next to the transaction.
+    pub fn into_parts(self) -> (Transaction, TxWitness) {
+        (self.transaction, TxWitness { public_key: self.public_key, signature: self.signature })
+    }
+
+    /// Checks that the public key belongs to the sender address and signed this transaction.
+    /// Stakes are held by producer keys (see [`pos`]), so staking transactions are signed by the sender itself.
+    pub fn verify(&self) -> Result<(), address::AddressError> {
+        let tx_hash = self.transaction.calculate_hash();
+        match self.transaction.transaction_type {
+            TransactionType::Stake { .. } | TransactionType::Unstake { .. } => {
+                if self.public_key != self.transaction.sender {
+                    return Err(address::AddressError::KeyMismatch);
+                }
+                address::KeyAlgorithm::Ed25519.verify(&self.public_key, &tx_hash, &self.signature)
+            }
+            _ => address::verify_signature(self.transaction.sender(), &self.public_key, &tx_hash, &self.signature),
+        }
+    }
+}
+
+/// The sender's public key and signature over a transaction hash, carried by a block next to
+/// the transaction it signs (see [`SignedTransaction`]).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxWitness {
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
 // Represents the header of a block
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct BlockHeader {
@@ -148,7 +475,7 @@ pub struct BlockHeader {
     pub merkle_root: Hash,
     pub timestamp: u64,
     pub nonce: u64,
-    pub difficulty: u32,
+    pub bits: u32, // Compact-encoded PoW target (see consensus::target)
     pub height: u64,
 }
 
@@ -166,14 +493,13 @@ impl BlockHeader {
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>,
+    pub witnesses: Vec<Option<TxWitness>>, // Sender signatures, one slot per transaction (committed by the Merkle root)
+    pub seal: Option<BlockSeal>, // Producer signature (PoS chains only; not part of the block hash)
 }
 
 impl Block {
-    pub fn new(previous_hash: Hash, transactions: Vec<Transaction>, difficulty: u32, height: u64) -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+    pub fn new(previous_hash: Hash, transactions: Vec<Transaction>, bits: u32, height: u64) -> Self {
+        let timestamp = time::unix_now();
 
         let merkle_root = Self::calculate_merkle_root(&transactions);
         let nonce = 0; // Placeholder, will be found by miner
@@ -183,22 +509,72 @@ impl Block {
             merkle_root,
             timestamp,
             nonce,
-            difficulty,
+            bits,
             height,
         };
 
-        Block { header, transactions }
+        let witnesses = vec![None; transactions.len()];
+        Block { header, transactions, witnesses, seal: None }
+    }
+
+    /// The empty first block of a chain created at `timestamp` with target `bits`.
+    pub fn genesis(timestamp: u64, bits: u32) -> Self {
+        let header = BlockHeader {
+            previous_hash: [0u8; 32],
+            merkle_root: Self::calculate_merkle_root(&[]),
+            timestamp,
+            nonce: 0,
+            bits,
+            height: 0,
+        };
+        Block { header, transactions: Vec::new(), witnesses: Vec::new(), seal: None }
+    }
+
+    /// Attaches the senders' signatures, one slot per transaction, and recommits the Merkle root to them.
+    pub fn set_witnesses(&mut self, witnesses: Vec<Option<TxWitness>>) {
+        self.witnesses = witnesses;
+        self.header.merkle_root = self.merkle_root();
+    }
+
+    /// Merkle root committing to the block's transactions and, when any is signed, to its witnesses.
+    pub fn merkle_root(&self) -> Hash {
+        Self::commit_witnesses(Self::calculate_merkle_root(&self.transactions), Self::witness_root(&self.witnesses))
+    }
+
+    /// Hash of a block's witnesses, or `None` when no transaction carries one.
+    pub fn witness_root(witnesses: &[Option<TxWitness>]) -> Option<Hash> {
+        if witnesses.iter().all(Option::is_none) {
+            return None;
+        }
+        let serialized = bincode::serialize(witnesses).expect("Failed to serialize witnesses for hashing");
+        Some(Sha256::digest(serialized).into())
+    }
+
+    /// Combines the root of the transaction hashes with the witness root. Blocks without
+    /// witnesses keep the bare transaction root, so blocks from before witnesses still match.
+    pub fn commit_witnesses(tx_root: Hash, witness_root: Option<Hash>) -> Hash {
+        let Some(witness_root) = witness_root else { return tx_root };
+        let mut hasher = Sha256::new();
+        hasher.update(tx_root);
+        hasher.update(witness_root);
+        hasher.finalize().into()
     }
 
     // Calculate the Merkle root from transaction hashes
     // TODO: Implement proper Merkle tree construction
     pub fn calculate_merkle_root(transactions: &[Transaction]) -> Hash {
-        if transactions.is_empty() {
+        let tx_hashes: Vec<Hash> = transactions.iter().map(Transaction::calculate_hash).collect();
+        Self::merkle_root_from_hashes(&tx_hashes)
+    }
+
+    /// Merkle root of a block whose transactions have the hashes `tx_hashes`, in order.
+    pub fn merkle_root_from_hashes(tx_hashes: &[Hash]) -> Hash {
+        if tx_hashes.is_empty() {
             return [0u8; 32];
         }
         let mut hasher = Sha256::new();
-        for tx in transactions {
-            hasher.update(tx.calculate_hash());
+        for tx_hash in tx_hashes {
+            hasher.update(tx_hash);
         }
         hasher.finalize().into()
     }
@@ -206,17 +582,127 @@ impl Block {
     pub fn hash(&self) -> Hash {
         self.header.calculate_hash()
     }
+
+    /// Canonical byte encoding (bincode) of the whole block, seal included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize block")
+    }
+
+    /// Parses the canonical encoding produced by `to_bytes`, rejecting non-canonical input
+    /// the same way as [`Transaction::from_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let block: Block = bincode::deserialize(bytes).map_err(|e| format!("Malformed block: {}", e))?;
+        if block.to_bytes() != bytes {
+            return Err("Block bytes are not in canonical form".to_string());
+        }
+        Ok(block)
+    }
+}
+
+/// A transaction included in the main chain, with where it was found.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfirmedTransaction {
+    pub transaction: Transaction,
+    pub block_hash: Hash,
+    pub height: u64,
+    /// Index of the transaction within its block.
+    pub position: u32,
+}
+
+/// Evidence that a transaction is included in a block, checkable against the block header
+/// alone. Until blocks commit to their transactions with a Merkle tree, the proof carries
+/// the hashes of all the block's transactions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransactionProof {
+    pub block_hash: Hash,
+    pub height: u64,
+    /// Index of the proven transaction within its block.
+    pub position: u32,
+    pub tx_hashes: Vec<Hash>,
+    /// Root of the block's transaction witnesses, if any transaction is signed (see [`Block::witness_root`]).
+    pub witness_root: Option<Hash>,
+}
+
+impl TransactionProof {
+    /// Checks that `header` is the proof's block and that it includes `tx_hash` at the proven position.
+    pub fn verify(&self, header: &BlockHeader, tx_hash: &Hash) -> Result<(), String> {
+        if header.calculate_hash() != self.block_hash || header.height != self.height {
+            return Err(format!("Proof is for block {} at height {}, not the given header", hex::encode(self.block_hash), self.height));
+        }
+        if self.tx_hashes.get(self.position as usize) != Some(tx_hash) {
+            return Err(format!("Transaction {} is not at position {} of the proof", hex::encode(tx_hash), self.position));
+        }
+        if Block::commit_witnesses(Block::merkle_root_from_hashes(&self.tx_hashes), self.witness_root) != header.merkle_root {
+            return Err("Proof does not match the block's Merkle root".to_string());
+        }
+        Ok(())
+    }
 }
 
 // --- Blockchain Structure (Persistent) ---
 
+/// Observes tip changes without holding the blockchain lock (e.g. to interrupt a miner).
+///
+/// Every new tip bumps a generation counter; clones share the same counter.
+#[derive(Debug, Clone, Default)]
+pub struct TipWatch {
+    generation: Arc<AtomicU64>,
+}
+
+impl TipWatch {
+    /// Current tip generation.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Whether the tip changed since `generation` was read.
+    pub fn changed_since(&self, generation: u64) -> bool {
+        self.generation() != generation
+    }
+
+    fn bump(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// First problem found by [`Blockchain::verify_chain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainCorruption {
+    pub height: u64,
+    pub reason: String,
+}
+
+/// Outcome of re-validating the stored main chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainVerification {
+    /// Height of the last block that passed every check, if any did.
+    pub verified_height: Option<u64>,
+    /// The first block that failed; `None` if the whole chain is intact.
+    pub corruption: Option<ChainCorruption>,
+}
+
 /// Manages the blockchain state, interacting with StorageManager and Mempool.
 #[derive(Debug)]
 pub struct Blockchain {
     storage: StorageManager,
     mempool: Mempool,
+    chain_config: ChainConfig,
+    engine: Box<dyn ConsensusEngine>,
+    checkpoints: Checkpoints,
     current_tip_hash: Option<Hash>,
     current_height: Option<u64>,
+    finalized_height: Option<u64>,
+    tip_watch: TipWatch,
+    /// Receives the coinbase of blocks built by `create_block_template`; without it there is no coinbase.
+    miner_address: Option<Address>,
+}
+
+/// Mempool contents as written by `Blockchain::save_mempool`.
+#[derive(Serialize, Deserialize)]
+struct SavedMempool {
+    version: u32,
+    /// Transactions with their witnesses and arrival times, in the order `Mempool::entries` lists them.
+    entries: Vec<(Transaction, Option<TxWitness>, u64)>,
 }
 
 // Custom error type for Blockchain operations
@@ -245,7 +731,17 @@ pub enum BlockchainError {
 
 impl Blockchain {
     /// Creates a new Blockchain instance, loading state from storage and initializing mempool.
+    /// A fresh chain is created with the default `ChainConfig`.
 pub fn new(storage_path: &Path) -> Result<Self, BlockchainError> {
+        Self::new_with_config(storage_path, ChainConfig::default())
+    }
+
+    /// Creates a new Blockchain instance using `config` if the chain has to be created.
+    ///
+    /// If the storage already holds a chain, the configuration recorded at its genesis
+    /// takes precedence over `config`; chains created before configurations were
+    /// recorded are treated as using `ChainConfig::default()`.
+    pub fn new_with_config(storage_path: &Path, config: ChainConfig) -> Result<Self, BlockchainError> {
         info!("Initializing blockchain from storage path: {:?}", storage_path);
         // Now StorageManager::new returns StorageError, which BlockchainError can handle via From
         let storage = StorageManager::new(storage_path)?;
@@ -268,11 +764,38 @@ pub fn new(storage_path: &Path) -> Result<Self, BlockchainError> {
             info!("No existing blockchain found in storage. Ready for genesis block.");
         }
 
+        let chain_config = match (storage.get_chain_config()?, current_height) {
+            (Some(recorded), _) => {
+                if recorded != config {
+                    warn!("Requested chain config {:?} differs from the one recorded at genesis {:?}. Using the recorded config.",
+                          config, recorded);
+                }
+                recorded
+            }
+            (None, Some(_)) => {
+                let legacy = ChainConfig::default();
+                if legacy != config {
+                    warn!("Existing chain has no recorded config; assuming defaults {:?} instead of {:?}.", legacy, config);
+                }
+                legacy
+            }
+            (None, None) => config,
+        };
+        info!("Chain PoW algorithm: {}", chain_config.pow_algorithm);
+        let finalized_height = storage.get_finalized_height()?;
+
+        let engine = consensus::engine_for(&chain_config);
         Ok(Blockchain {
             storage,
             mempool,
+            chain_config,
+            engine,
+            checkpoints: Checkpoints::embedded(),
             current_tip_hash,
             current_height,
+            finalized_height,
+            tip_watch: TipWatch::default(),
+            miner_address: None,
         })
     }
 
@@ -284,116 +807,868 @@ pub fn initialize_genesis_if_needed(&mut self) -> Result<(), BlockchainError> {
         }
 
         info!("Creating genesis block...");
-        let genesis_transactions = Vec::new();
-        let merkle_root = Block::calculate_merkle_root(&genesis_transactions);
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        let initial_difficulty = consensus::MIN_DIFFICULTY;
-
-        let genesis_header = BlockHeader {
-            previous_hash: [0u8; 32],
-            merkle_root,
-            timestamp,
-            nonce: 0,
-            difficulty: initial_difficulty,
-            height: 0,
-        };
-
-        let genesis_block = Block {
-            header: genesis_header,
-            transactions: genesis_transactions,
-        };
+        let timestamp = self.chain_config.genesis_timestamp.unwrap_or_else(time::unix_now);
+        let genesis_block = Block::genesis(timestamp, self.chain_config.difficulty_bounds.pow_limit_bits);
         let genesis_hash = genesis_block.hash();
 
+        // Record the chain config alongside genesis so validation rules survive restarts
+        self.storage.save_chain_config(&self.chain_config)?;
+        if self.engine.mode() == ConsensusMode::ProofOfStake {
+            self.storage.save_stakes(&self.chain_config.initial_stakes)?;
+        }
         // save_block now returns StorageError, handled by '?'
         self.storage.save_block(&genesis_block)?;
+        self.storage.save_chain_work(&genesis_hash, self.block_work(&genesis_block.header))?;
         self.current_tip_hash = Some(genesis_hash);
         self.current_height = Some(0);
         info!("Genesis block created and saved. Hash: {}", hex::encode(genesis_hash));
         Ok(())
     }
 
-    /// Adds a transaction to the mempool.
-pub fn add_pending_transaction(&mut self, tx: Transaction) -> Result<bool, BlockchainError> {
-        // TODO: Add validation against current blockchain state (e.g., sufficient funds)
-        self.mempool.add_transaction(tx).map_err(BlockchainError::Mempool)
+    /// Stores evidence that `block`, received from `source`, was rejected with `error`.
+    pub fn record_invalid_block(&self, block: &Block, error: &BlockchainError, source: Option<String>) -> Result<(), BlockchainError> {
+        let evidence = InvalidBlockEvidence {
+            block_hash: block.hash(),
+            header: block.header.clone(),
+            reason: error.to_string(),
+            source,
+            recorded_at: time::unix_now(),
+        };
+        self.storage.save_invalid_block_evidence(&evidence, MAX_INVALID_BLOCK_EVIDENCE)?;
+        Ok(())
     }
 
-    /// Retrieves a block by its hash from storage.
-pub fn get_block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, BlockchainError> {
-        // get_block_by_hash now returns StorageError, handled by '?'
-        Ok(self.storage.get_block_by_hash(hash)?)
+    /// Returns up to `limit` rejected-block records, newest first.
+    pub fn get_invalid_block_evidence(&self, limit: usize) -> Result<Vec<InvalidBlockEvidence>, BlockchainError> {
+        Ok(self.storage.get_invalid_block_evidence(limit)?)
     }
 
-    /// Retrieves a block by its height from storage.
-pub fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, BlockchainError> {
-        // get_block_by_height now returns StorageError, handled by '?'
-        Ok(self.storage.get_block_by_height(height)?)
+    /// Pages through the mempool by fee rate, returning the page and the total number of pending transactions.
+    pub fn get_mempool_contents(&self, offset: usize, limit: usize) -> (Vec<PendingTransaction>, usize) {
+        (self.mempool.list(offset, limit), self.mempool.size())
     }
 
-    /// Returns the hash of the latest block (tip) in the chain.
-pub fn get_last_block_hash(&self) -> Option<Hash> {
-        // This doesn't return Result, no change needed
-        self.current_tip_hash
+    /// Summarizes mempool occupancy, fee rates and evictions, for fee estimation and congestion monitoring.
+    pub fn get_mempool_info(&self) -> MempoolInfo {
+        self.mempool.info()
     }
 
-    /// Returns the height of the latest block (tip) in the chain.
-pub fn get_chain_height(&self) -> Option<u64> {
-        // This doesn't return Result, no change needed
-        self.current_height
+    /// Returns fee, size, age and in-pool dependency links of a pending transaction.
+    pub fn get_mempool_entry(&self, tx_hash: &Hash) -> Option<MempoolEntry> {
+        self.mempool.get_entry(tx_hash, time::unix_now())
     }
 
-    /// Validates and adds a new block to the blockchain.
-pub fn add_block(&mut self, block: Block) -> Result<(), BlockchainError> {
-        let block_hash = block.hash();
-        let header = &block.header;
-        info!("Attempting to add block {} (Hash: {})...", header.height, hex::encode(block_hash));
+    /// Sets how many seconds transactions may wait in the mempool; `None` keeps them until mined.
+    pub fn set_mempool_expiry(&mut self, expiry: Option<u64>) {
+        self.mempool.set_expiry(expiry);
+    }
 
-        let current_height = self.current_height.ok_or(BlockchainError::NotInitialized)?;
-        let current_tip_hash = self.current_tip_hash.ok_or(BlockchainError::NotInitialized)?;
+    /// Subscribes to transactions entering and leaving the mempool, without polling it.
+    pub fn subscribe_mempool(&self) -> tokio::sync::broadcast::Receiver<MempoolEvent> {
+        self.mempool.subscribe()
+    }
 
-        // --- Basic Validation ---
-        let expected_height = current_height + 1;
-        if header.height != expected_height {
-            return Err(BlockchainError::Validation(format!(
-                "Invalid block height. Expected: {}, Got: {}",
-                expected_height, header.height
-            )));
+    /// Sets the budget for the serialized bytes of all pending transactions.
+    pub fn set_mempool_max_bytes(&mut self, max_bytes: usize) {
+        self.mempool.set_max_bytes(max_bytes);
+    }
+
+    /// Caps how many pending transactions, and how many bytes of them, one sender may have.
+    pub fn set_mempool_sender_limits(&mut self, limits: SenderLimits) {
+        self.mempool.set_sender_limits(limits);
+    }
+
+    /// Sets the address paid the subsidy and fees of blocks built here; `None` leaves them out of blocks.
+    pub fn set_miner_address(&mut self, address: Option<Address>) {
+        self.miner_address = address;
+    }
+
+    pub fn miner_address(&self) -> Option<&Address> {
+        self.miner_address.as_ref()
+    }
+
+    /// Drops mempool transactions that have waited past the expiry, returning how many.
+    pub fn expire_mempool(&mut self, now: u64) -> usize {
+        self.mempool.expire(now).len()
+    }
+
+    /// Writes every mempool transaction, with its arrival time, to `path` so the next start can
+    /// restore them with [`load_mempool`](Self::load_mempool). Returns how many were saved.
+    pub fn save_mempool(&self, path: &Path) -> Result<usize, BlockchainError> {
+        let saved = SavedMempool { version: SAVED_MEMPOOL_VERSION, entries: self.mempool.entries() };
+        let bytes = bincode::serialize(&saved).map_err(StorageError::Serialization)?;
+        // Written aside and renamed, so a crash mid-write can't leave a truncated file
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, bytes).map_err(StorageError::Io)?;
+        std::fs::rename(&partial, path).map_err(StorageError::Io)?;
+        Ok(saved.entries.len())
+    }
+
+    /// Re-adds the transactions saved by [`save_mempool`](Self::save_mempool), keeping their arrival
+    /// times, and deletes the file so a later crash can't restore them twice. Returns how many
+    /// were restored; transactions no longer accepted (expired, or rejected at the current height)
+    /// are dropped. A missing file restores nothing.
+    pub fn load_mempool(&mut self, path: &Path) -> Result<usize, BlockchainError> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(StorageError::Io(e).into()),
+        };
+        std::fs::remove_file(path).map_err(StorageError::Io)?;
+        // The version leads the file, so older layouts are reported rather than misread
+        let version: u32 = bincode::deserialize(&bytes).map_err(StorageError::Deserialization)?;
+        if version != SAVED_MEMPOOL_VERSION {
+            return Err(BlockchainError::Mempool(format!("Saved mempool has unsupported version {}", version)));
+        }
+        let saved: SavedMempool = bincode::deserialize(&bytes).map_err(StorageError::Deserialization)?;
+        let held_before = self.mempool.size() + self.mempool.orphan_count();
+        for (tx, witness, added_at) in saved.entries {
+            let tx_hash = tx.calculate_hash();
+            let added = Self::check_witness(&tx, witness.as_ref())
+                .and_then(|()| self.check_pending_transaction(&tx))
+                .and_then(|()| self.mempool.add_transaction_at(tx, witness, added_at).map_err(BlockchainError::Mempool));
+            if let Err(e) = added {
+                debug!("Dropped saved mempool transaction {}: {}", hex::encode(tx_hash), e);
+            }
         }
+        self.mempool.expire(time::unix_now());
+        Ok((self.mempool.size() + self.mempool.orphan_count()).saturating_sub(held_before))
+    }
 
-        if header.previous_hash != current_tip_hash {
+    /// Adds a transaction to the mempool.
+    /// Transactions whose version or kind is not yet active for the next block are rejected, and so are
+    /// those spending the sender's funds: they go through [`Self::add_signed_transaction`].
+pub fn add_pending_transaction(&mut self, tx: Transaction) -> Result<bool, BlockchainError> {
+        self.check_pending_transaction(&tx)?;
+        Self::check_witness(&tx, None)?;
+        // TODO: Add validation against current blockchain state (e.g., sufficient funds)
+        self.mempool.add_transaction(tx).map_err(BlockchainError::Mempool)
+    }
+
+    /// Adds a transaction signed by its sender to the mempool, after checking the signature
+    /// against the sender address (see [`address::verify_signature`]).
+    pub fn add_signed_transaction(&mut self, signed: SignedTransaction) -> Result<bool, BlockchainError> {
+        signed.verify().map_err(|e| BlockchainError::Validation(format!(
+            "Invalid signature on transaction {}: {}", hex::encode(signed.transaction.calculate_hash()), e
+        )))?;
+        self.check_pending_transaction(&signed.transaction)?;
+        self.mempool.add_signed_transaction(signed).map_err(BlockchainError::Mempool)
+    }
+
+    /// Ensures `tx` carries a valid signature by its sender when it spends the sender's funds,
+    /// and that any witness it carries anyway is valid.
+    fn check_witness(tx: &Transaction, witness: Option<&TxWitness>) -> Result<(), BlockchainError> {
+        match witness {
+            Some(witness) => SignedTransaction::from_witness(tx.clone(), witness.clone()).verify().map_err(|e| BlockchainError::Validation(format!(
+                "Invalid signature on transaction {}: {}", hex::encode(tx.calculate_hash()), e
+            ))),
+            None if tx.requires_signature() => Err(BlockchainError::Validation(format!(
+                "Transaction {} spends the sender's funds and must be signed by the sender", hex::encode(tx.calculate_hash())
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the witnesses of a block: one slot per transaction, and a valid sender signature
+    /// for every transaction that needs one.
+    fn check_block_witnesses(block: &Block) -> Result<(), BlockchainError> {
+        if block.witnesses.len() != block.transactions.len() {
             return Err(BlockchainError::Validation(format!(
-                "Invalid previous block hash. Expected: {}, Got: {}",
-                hex::encode(current_tip_hash), hex::encode(header.previous_hash)
+                "Block {} has {} witnesses for {} transactions", block.header.height, block.witnesses.len(), block.transactions.len()
             )));
         }
+        block.transactions.iter().zip(&block.witnesses).try_for_each(|(tx, witness)| Self::check_witness(tx, witness.as_ref()))
+    }
 
-        let calculated_merkle_root = Block::calculate_merkle_root(&block.transactions);
+    /// Checks a transaction against the chain before it enters the mempool.
+    fn check_pending_transaction(&self, tx: &Transaction) -> Result<(), BlockchainError> {
+        if tx.is_coinbase() {
+            return Err(BlockchainError::Validation("A coinbase is only valid as part of a block".to_string()));
+        }
+        let next_height = self.current_height.map_or(0, |h| h + 1);
+        self.check_transaction_activation(tx, next_height)?;
+        namespaces::check_namespace(tx).map_err(BlockchainError::Validation)?;
+        self.check_storage_deal(tx, next_height, &mut std::collections::HashSet::new())?;
+        self.check_stake_funds(tx, &mut std::collections::HashMap::new())?;
+        self.check_pointer(tx, &mut std::collections::HashMap::new())?;
+        // Later nonces may still be waiting on pending transactions, used ones never confirm again
+        Self::check_nonce_present(tx)?;
+        if let Some(nonce) = tx.nonce() {
+            let next = self.storage.get_next_nonce(tx.sender())?;
+            if nonce < next {
+                return Err(BlockchainError::Validation(format!(
+                    "Transaction {} uses nonce {} but {} already confirmed nonces up to {}",
+                    hex::encode(tx.calculate_hash()), nonce, hex::encode(tx.sender()), next - 1
+                )));
+            }
+        }
+        // A transaction above the caps could never be mined
+        self.check_data_commitments(std::slice::from_ref(tx))
+    }
+
+    /// Ensures the transaction's version and gated kind are active at `height`.
+    fn check_transaction_activation(&self, tx: &Transaction, height: u64) -> Result<(), BlockchainError> {
+        if !self.chain_config.is_transaction_version_active(tx.version(), height) {
+            return Err(BlockchainError::Validation(format!(
+                "Transaction {} has version {} which is not active at height {}",
+                hex::encode(tx.calculate_hash()), tx.version(), height
+            )));
+        }
+        if let Some(feature) = tx.required_feature() {
+            if !self.chain_config.is_feature_active(feature, height) {
+                return Err(BlockchainError::Validation(format!(
+                    "Transaction {} uses feature {:?} which is not active at height {}",
+                    hex::encode(tx.calculate_hash()), feature, height
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensures no transaction exceeds the per-transaction data commitment cap and
+    /// that together they stay within the per-block cap.
+    fn check_data_commitments(&self, transactions: &[Transaction]) -> Result<(), BlockchainError> {
+        let mut total: u64 = 0;
+        for tx in transactions {
+            let size = tx.data_commitment();
+            if size > self.chain_config.max_tx_data_size {
+                return Err(BlockchainError::Validation(format!(
+                    "Transaction {} commits to {} bytes of data, above the per-transaction limit of {}",
+                    hex::encode(tx.calculate_hash()), size, self.chain_config.max_tx_data_size
+                )));
+            }
+            total = total.saturating_add(size);
+        }
+        if total > self.chain_config.max_block_data_size {
+            return Err(BlockchainError::Validation(format!(
+                "Block commits to {} bytes of off-chain data, above the limit of {}",
+                total, self.chain_config.max_block_data_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// Ensures a block has at most one coinbase, as its first transaction, for the block's height
+    /// and paying no more than the block subsidy plus the fees of the other transactions.
+    fn check_coinbase(transactions: &[Transaction], height: u64) -> Result<(), BlockchainError> {
+        if transactions.iter().skip(1).any(Transaction::is_coinbase) {
+            return Err(BlockchainError::Validation("Coinbase is not the first transaction of the block".to_string()));
+        }
+        let Some(coinbase) = transactions.first().filter(|tx| tx.is_coinbase()) else {
+            return Ok(());
+        };
+        let TransactionType::Coinbase { height: coinbase_height, amount } = coinbase.transaction_type else {
+            unreachable!()
+        };
+        if coinbase_height != height || !coinbase.sender.is_empty() || coinbase.receiver.is_none() || coinbase.fee != 0 {
+            return Err(BlockchainError::Validation(format!("Malformed coinbase {} in block {}", hex::encode(coinbase.calculate_hash()), height)));
+        }
+        let allowed = transactions[1..].iter().fold(consensus::BLOCK_SUBSIDY, |total, tx| total.saturating_add(tx.fee));
+        if amount > allowed {
+            return Err(BlockchainError::Validation(format!(
+                "Coinbase of block {} pays {}, above the subsidy plus fees of {}", height, amount, allowed
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks the storage deal `tx` would make in a block at `height`: its terms, and that its
+    /// provider is not already bound to store the payload by a deal on the chain or one in
+    /// `claimed`, the deals taken earlier in the block, to which it is added.
+    fn check_storage_deal(&self, tx: &Transaction, height: u64, claimed: &mut std::collections::HashSet<(Hash, Address)>) -> Result<(), BlockchainError> {
+        let Some(deal) = StorageDeal::from_transaction(tx, height) else { return Ok(()) };
+        deals::check_deal_terms(tx).map_err(|e| BlockchainError::Validation(format!("Invalid storage deal {}: {}", hex::encode(deal.deal_id), e)))?;
+        let bound = !claimed.insert((deal.data_hash, deal.provider.clone()))
+            || self.storage.get_storage_deals(&deal.data_hash)?.iter().any(|open| open.provider == deal.provider && open.is_active_at(height));
+        if bound {
+            return Err(BlockchainError::Validation(format!(
+                "Provider {} is already bound to store payload {} at height {}",
+                hex::encode(&deal.provider), hex::encode(deal.data_hash), height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks the pointer record `tx` publishes: its signature, and that its sequence number is
+    /// above the one its name holds on the chain or in `published`, the records published earlier
+    /// in the block, to which it is added.
+    fn check_pointer(&self, tx: &Transaction, published: &mut std::collections::HashMap<[u8; 32], u64>) -> Result<(), BlockchainError> {
+        let TransactionType::PublishPointer { record } = &tx.transaction_type else { return Ok(()) };
+        if !record.verify() {
+            return Err(BlockchainError::Validation(format!("Pointer record {} has a bad signature", hex::encode(tx.calculate_hash()))));
+        }
+        let current = match published.get(&record.name) {
+            Some(sequence) => Some(*sequence),
+            None => self.storage.resolve_pointer(&record.name)?.map(|pointer| pointer.record.sequence),
+        };
+        if current.is_some_and(|sequence| record.sequence <= sequence) {
+            return Err(BlockchainError::Validation(format!(
+                "Pointer {} is already at sequence {}; record {} does not advance it",
+                hex::encode(record.name), current.unwrap_or_default(), record.sequence
+            )));
+        }
+        published.insert(record.name, record.sequence);
+        Ok(())
+    }
+
+    /// Ensures a transaction signed by its sender carries a nonce, without which it could be
+    /// confirmed again.
+    fn check_nonce_present(tx: &Transaction) -> Result<(), BlockchainError> {
+        if tx.requires_signature() && tx.nonce().is_none() {
+            return Err(BlockchainError::Validation(format!(
+                "Transaction {} must be signed by its sender and carry a nonce", hex::encode(tx.calculate_hash())
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks the nonce of `tx`: present if the transaction must be signed, and the next one its
+    /// sender uses after the main chain and `used`, the nonces taken earlier in the block, to
+    /// which it is added. A nonce can't be reused, so a replaced or already confirmed transaction
+    /// never confirms (again), nor skipped.
+    fn check_nonce(&self, tx: &Transaction, used: &mut std::collections::HashMap<Address, u64>) -> Result<(), BlockchainError> {
+        Self::check_nonce_present(tx)?;
+        let Some(nonce) = tx.nonce() else { return Ok(()) };
+        let expected = match used.get(tx.sender()) {
+            Some(next) => *next,
+            None => self.storage.get_next_nonce(tx.sender())?,
+        };
+        if nonce != expected {
+            return Err(BlockchainError::Validation(format!(
+                "Transaction {} uses nonce {} but {} is at nonce {}",
+                hex::encode(tx.calculate_hash()), nonce, hex::encode(tx.sender()), expected
+            )));
+        }
+        used.insert(tx.sender().clone(), nonce.saturating_add(1));
+        Ok(())
+    }
+
+    /// The nonce the next transaction of `sender` should use: one past the highest it used on the
+    /// main chain or has pending in the mempool.
+    pub fn next_nonce(&self, sender: &Address) -> Result<u64, BlockchainError> {
+        let confirmed = self.storage.get_next_nonce(sender)?;
+        Ok(self.mempool.expected_nonce(sender).map_or(confirmed, |pending| pending.max(confirmed)))
+    }
+
+    /// Picks mempool transactions for the next block in fee priority order, skipping those that
+    /// would push the block over its transaction count or data commitment caps, and storage deals,
+    /// pointer records and nonces the block can't take.
+    fn select_block_transactions(&self) -> Vec<Transaction> {
+        let height = self.current_height.map_or(0, |h| h + 1);
+        let mut data_total: u64 = 0;
+        let mut claimed = std::collections::HashSet::new();
+        let mut published = std::collections::HashMap::new();
+        let mut staked = std::collections::HashMap::new();
+        let mut nonces = std::collections::HashMap::new();
+        let mut selected = Vec::new();
+        for tx in self.mempool.get_transactions(usize::MAX) {
+            if selected.len() >= MAX_TRANSACTIONS_PER_BLOCK {
+                break;
+            }
+            let size = tx.data_commitment();
+            if size > self.chain_config.max_tx_data_size || data_total.saturating_add(size) > self.chain_config.max_block_data_size {
+                continue;
+            }
+            let checked = self.check_storage_deal(&tx, height, &mut claimed)
+                .and_then(|_| self.check_pointer(&tx, &mut published))
+                .and_then(|_| self.check_stake_funds(&tx, &mut staked))
+                .and_then(|_| self.check_nonce(&tx, &mut nonces));
+            if let Err(e) = checked {
+                debug!("Leaving transaction {} out of the block: {}", hex::encode(tx.calculate_hash()), e);
+                continue;
+            }
+            data_total += size;
+            selected.push(tx);
+        }
+        selected
+    }
+
+    /// The signatures the mempool holds for `transactions`, one slot each, for the block including them.
+    fn pending_witnesses(&self, transactions: &[Transaction]) -> Vec<Option<TxWitness>> {
+        transactions.iter().map(|tx| self.mempool.witness(&tx.calculate_hash()).cloned()).collect()
+    }
+
+    /// Ensures the sender of a `Stake` holds the staked amount and its fee on the main chain, on top
+    /// of what `staked` records it spending on stakes earlier in the block, to which they are added.
+    fn check_stake_funds(&self, tx: &Transaction, staked: &mut std::collections::HashMap<Address, u64>) -> Result<(), BlockchainError> {
+        let TransactionType::Stake { amount } = tx.transaction_type else {
+            return Ok(());
+        };
+        let spent = staked.get(&tx.sender).copied().unwrap_or_default().saturating_add(amount).saturating_add(tx.fee);
+        let balance = self.get_native_balance(&tx.sender)?;
+        if spent > balance {
+            return Err(BlockchainError::Validation(format!(
+                "Stake {} of {} needs {} but {} holds {}", hex::encode(tx.calculate_hash()), amount, spent, hex::encode(&tx.sender), balance
+            )));
+        }
+        staked.insert(tx.sender.clone(), spent);
+        Ok(())
+    }
+
+    /// Validates the staking transactions of a block and returns the resulting stake entries
+    /// for every validator they touch (a zero amount means the validator is removed).
+    fn apply_staking_transactions(&self, transactions: &[Transaction]) -> Result<pos::StakeTable, BlockchainError> {
+        let mut updates = pos::StakeTable::new();
+        for tx in transactions {
+            let touched = match &tx.transaction_type {
+                TransactionType::Stake { .. } | TransactionType::Unstake { .. } => &tx.sender,
+                TransactionType::SlashDoubleSign { evidence } => evidence.offender(),
+                _ => continue,
+            };
+            let current = match updates.get(touched) {
+                Some(amount) => *amount,
+                None => self.storage.get_stake(touched)?,
+            };
+            let updated = match &tx.transaction_type {
+                TransactionType::Stake { amount } => current.saturating_add(*amount),
+                TransactionType::Unstake { amount } => current.checked_sub(*amount).ok_or_else(|| {
+                    BlockchainError::Validation(format!(
+                        "Unstake of {} exceeds stake {} of {}", amount, current, hex::encode(touched)
+                    ))
+                })?,
+                TransactionType::SlashDoubleSign { evidence } => {
+                    evidence.verify().map_err(|e| BlockchainError::Validation(format!("Invalid double-sign evidence: {}", e)))?;
+                    info!("Slashing stake {} of producer {} for double-signing at height {}",
+                          current, hex::encode(touched), evidence.header_a.height);
+                    0
+                }
+                _ => unreachable!(),
+            };
+            updates.insert(touched.clone(), updated);
+        }
+        Ok(updates)
+    }
+
+    /// Retrieves a block by its hash from storage.
+pub fn get_block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, BlockchainError> {
+        // get_block_by_hash now returns StorageError, handled by '?'
+        Ok(self.storage.get_block_by_hash(hash)?)
+    }
+
+    /// Persists every write to disk, e.g. before the node exits.
+    pub fn flush(&self) -> Result<(), BlockchainError> {
+        Ok(self.storage.flush()?)
+    }
+
+    /// Disk usage of the database and, given its directory, of the off-chain payloads.
+    pub fn storage_stats(&self, offchain_dir: Option<&Path>) -> Result<StorageStats, BlockchainError> {
+        Ok(self.storage.stats(offchain_dir)?)
+    }
+
+    /// Sets how many decoded blocks storage keeps cached in memory.
+    pub fn set_block_cache_capacity(&self, blocks: usize) {
+        self.storage.set_block_cache_capacity(blocks);
+    }
+
+    /// Compresses blocks stored from now on with zstd at `level`, or stores them raw with `None`.
+    pub fn set_block_compression(&self, level: Option<i32>) {
+        self.storage.set_block_compression(level);
+    }
+
+    /// Trains the block compression dictionary on recent blocks unless one exists already.
+    pub fn train_block_dictionary(&self, samples: u64, max_size: usize) -> Result<bool, BlockchainError> {
+        Ok(self.storage.train_block_dictionary(samples, max_size)?)
+    }
+
+    /// Enables or disables the transaction index. Enabling rebuilds it if blocks were saved without it.
+    pub fn set_tx_index(&mut self, enabled: bool) -> Result<(), BlockchainError> {
+        self.storage.set_tx_index(enabled);
+        if enabled && !self.storage.is_tx_index_complete()? {
+            info!("Building transaction index...");
+            self.storage.reindex_transactions()?;
+        }
+        Ok(())
+    }
+
+    /// Finds a main-chain transaction by hash.
+    /// Uses the transaction index when it is enabled; otherwise scans the chain from the tip.
+    pub fn get_transaction(&self, tx_hash: &Hash) -> Result<Option<ConfirmedTransaction>, BlockchainError> {
+        if self.storage.tx_index_enabled() {
+            let location = match self.storage.get_tx_location(tx_hash)? {
+                Some(location) => location,
+                None => return Ok(None),
+            };
+            let block = self.storage.get_block_by_hash(&location.block_hash)?
+                .ok_or_else(|| BlockchainError::BlockNotFoundByHash(hex::encode(location.block_hash)))?;
+            let transaction = block.transactions.get(location.position as usize).cloned()
+                .ok_or_else(|| StorageError::InvalidFormat(format!("Transaction index points past the end of block {}", block.header.height)))?;
+            return Ok(Some(ConfirmedTransaction { transaction, block_hash: location.block_hash, height: block.header.height, position: location.position }));
+        }
+
+        let tip_height = self.current_height.ok_or(BlockchainError::NotInitialized)?;
+        for height in (0..=tip_height).rev() {
+            let block = self.storage.get_block_by_height(height)?.ok_or(BlockchainError::BlockNotFoundByHeight(height))?;
+            if let Some(position) = block.transactions.iter().position(|tx| &tx.calculate_hash() == tx_hash) {
+                return Ok(Some(ConfirmedTransaction {
+                    transaction: block.transactions[position].clone(),
+                    block_hash: block.hash(),
+                    height,
+                    position: position as u32,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds a main-chain transaction with a proof of its inclusion in its block.
+    pub fn get_transaction_proof(&self, tx_hash: &Hash) -> Result<Option<(ConfirmedTransaction, TransactionProof)>, BlockchainError> {
+        let Some(confirmed) = self.get_transaction(tx_hash)? else { return Ok(None) };
+        let block = self.storage.get_block_by_hash(&confirmed.block_hash)?
+            .ok_or_else(|| BlockchainError::BlockNotFoundByHash(hex::encode(confirmed.block_hash)))?;
+        let proof = TransactionProof {
+            block_hash: confirmed.block_hash,
+            height: confirmed.height,
+            position: confirmed.position,
+            tx_hashes: block.transactions.iter().map(Transaction::calculate_hash).collect(),
+            witness_root: Block::witness_root(&block.witnesses),
+        };
+        Ok(Some((confirmed, proof)))
+    }
+
+    /// Lists the compact filters of up to `count` main-chain blocks from `start_height` on,
+    /// with their block hashes; fewer past the tip.
+    pub fn get_block_filters(&self, start_height: u64, count: u64) -> Result<Vec<(Hash, BlockFilter)>, BlockchainError> {
+        let mut filters = Vec::new();
+        for height in start_height..start_height.saturating_add(count) {
+            let Some(block_hash) = self.storage.get_hash_by_height(height)? else { break };
+            let filter = match self.storage.get_block_filter(&block_hash)? {
+                Some(filter) => filter,
+                None => {
+                    let block = self.storage.get_block_by_hash(&block_hash)?.ok_or(BlockchainError::BlockNotFoundByHeight(height))?;
+                    BlockFilter::for_block(&block)
+                }
+            };
+            filters.push((block_hash, filter));
+        }
+        Ok(filters)
+    }
+
+    /// Lists the storage deals confirmed on the main chain for the payload `data_hash`, oldest first.
+    pub fn get_storage_deals(&self, data_hash: &Hash) -> Result<Vec<StorageDeal>, BlockchainError> {
+        Ok(self.storage.get_storage_deals(data_hash)?)
+    }
+
+    /// The latest record published on the main chain for the pointer `name`, if any.
+    pub fn resolve_pointer(&self, name: &[u8; 32]) -> Result<Option<PublishedPointer>, BlockchainError> {
+        Ok(self.storage.resolve_pointer(name)?)
+    }
+
+    /// Iterates over the main-chain blocks at heights in `range`, in height order.
+    pub fn iter_blocks(&self, range: impl std::ops::RangeBounds<u64>) -> impl Iterator<Item = Result<Block, BlockchainError>> + '_ {
+        self.storage.iter_blocks(range).map(|block| Ok(block?))
+    }
+
+    /// Lists the main-chain transactions sending from or to `address`, oldest first.
+    pub fn get_address_transactions(&self, address: &Address) -> Result<Vec<AddressTxRef>, BlockchainError> {
+        Ok(self.storage.get_address_transactions(address)?)
+    }
+
+    /// Lists up to `limit` main-chain events matching `filter`, in chain order, continuing after
+    /// the event locator `after` if given.
+    pub fn get_events(&self, filter: &EventFilter, after: Option<(u64, u32, u32)>, limit: usize) -> Result<Vec<EventRecord>, BlockchainError> {
+        Ok(self.storage.get_events(filter, after, limit)?)
+    }
+
+    /// Lists up to `limit` main-chain transactions sending from or to `address`, newest first,
+    /// continuing below the (height, position) `before` if given.
+    pub fn get_address_history(&self, address: &Address, before: Option<(u64, u32)>, limit: usize) -> Result<Vec<AddressTxRef>, BlockchainError> {
+        Ok(self.storage.get_address_history(address, before, limit)?)
+    }
+
+    /// Retrieves a block by its height from storage.
+pub fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, BlockchainError> {
+        // get_block_by_height now returns StorageError, handled by '?'
+        Ok(self.storage.get_block_by_height(height)?)
+    }
+
+    /// Retrieves the main-chain block with the latest timestamp at or before `timestamp`.
+    pub fn get_block_by_time(&self, timestamp: u64) -> Result<Option<Block>, BlockchainError> {
+        Ok(self.storage.get_block_by_time(timestamp)?)
+    }
+
+    /// Returns the hash of the latest block (tip) in the chain.
+pub fn get_last_block_hash(&self) -> Option<Hash> {
+        // This doesn't return Result, no change needed
+        self.current_tip_hash
+    }
+
+    /// Returns the height of the latest block (tip) in the chain.
+pub fn get_chain_height(&self) -> Option<u64> {
+        // This doesn't return Result, no change needed
+        self.current_height
+    }
+
+    /// Returns the height of the highest irreversible block, if any block is final yet.
+    pub fn get_finalized_height(&self) -> Option<u64> {
+        self.finalized_height
+    }
+
+    /// Refuses a reorg whose fork point is `fork_height` (the last block kept from the current chain)
+    /// if it would revert a finalized block.
+    pub fn check_reorg_depth(&self, fork_height: u64) -> Result<(), BlockchainError> {
+        match self.finalized_height {
+            Some(finalized) if fork_height < finalized => Err(BlockchainError::Validation(format!(
+                "Reorg from height {} would revert finalized block {} (finality depth {})",
+                fork_height, finalized, self.chain_config.finality_depth
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns a handle that observes tip changes.
+    pub fn tip_watch(&self) -> TipWatch {
+        self.tip_watch.clone()
+    }
+
+    /// Returns the consensus configuration the chain was created with.
+    pub fn chain_config(&self) -> &ChainConfig {
+        &self.chain_config
+    }
+
+    /// Returns the checkpoints blocks are validated against.
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Replaces the checkpoint set (embedded checkpoints are used by default).
+    /// Fails, leaving the current set in place, if the stored chain conflicts with one of them.
+    pub fn set_checkpoints(&mut self, checkpoints: Checkpoints) -> Result<(), BlockchainError> {
+        let chain_height = self.current_height;
+        for (height, _) in checkpoints.iter().take_while(|(height, _)| Some(*height) <= chain_height) {
+            if let Some(stored_hash) = self.storage.get_hash_by_height(height)? {
+                checkpoints.check(height, &stored_hash).map_err(BlockchainError::Validation)?;
+            }
+        }
+        info!("Using {} checkpoint(s), last at height {:?}", checkpoints.iter().count(), checkpoints.last_height());
+        self.checkpoints = checkpoints;
+        Ok(())
+    }
+
+    /// The underlying storage, for components that keep their own records alongside the chain.
+    pub fn storage(&self) -> &StorageManager {
+        &self.storage
+    }
+
+    /// Validates and adds a new block to the blockchain.
+    ///
+    /// On Proof-of-Work chains a block extending another known block is kept as a side-chain
+    /// block; the chain with the most cumulative work becomes the main chain (see [`Self::get_chain_work`]).
+pub fn add_block(&mut self, block: Block) -> Result<(), BlockchainError> {
+        let current_height = self.current_height.ok_or(BlockchainError::NotInitialized)?;
+        let current_tip_hash = self.current_tip_hash.ok_or(BlockchainError::NotInitialized)?;
+
+        // A block that failed to connect, or a descendant of one, never joins the main chain
+        let block_hash = block.hash();
+        if self.storage.is_block_failed(&block_hash)? || self.storage.is_block_failed(&block.header.previous_hash)? {
+            self.storage.mark_blocks_failed(&[block_hash])?;
+            return Err(BlockchainError::Validation(format!("Block {} is on a branch that failed validation", hex::encode(block_hash))));
+        }
+        // A competing block at a height we already hold is a reorg attempt; refuse it below finality
+        if block.header.height <= current_height {
+            self.check_reorg_depth(block.header.height.saturating_sub(1))?;
+        }
+        if block.header.previous_hash != current_tip_hash && self.engine.mode() == ConsensusMode::ProofOfWork {
+            if let Some(parent) = self.storage.get_block_by_hash(&block.header.previous_hash)? {
+                return self.add_side_block(block, &parent);
+            }
+        }
+        self.connect_block(block)
+    }
+
+    /// Appends a run of blocks received during sync to the main chain; returns how many were connected.
+    ///
+    /// On Proof-of-Work chains, blocks below the last checkpoint are buffered after checking linkage,
+    /// target bits, Merkle root, signatures, nonces and activation rules. Once a buffered block matches the
+    /// checkpoint at its height, it and its buffered ancestors are pinned by that hash and written
+    /// in one batch without checking their PoW. If any check fails the buffered blocks are discarded; blocks left
+    /// buffered at the end of the run, and all other blocks, go through [`Self::add_block`].
+    pub fn import_blocks(&mut self, blocks: Vec<Block>) -> Result<usize, BlockchainError> {
+        let mut pending: Vec<Block> = Vec::new();
+        let mut connected = 0;
+        // Next nonce of the senders in the buffered blocks
+        let mut nonces = std::collections::HashMap::new();
+        for block in blocks {
+            let trusted = self.engine.mode() == ConsensusMode::ProofOfWork
+                && self.checkpoints.covers(block.header.height)
+                && !block.transactions.iter().any(|tx| matches!(tx.transaction_type,
+                    TransactionType::Stake { .. } | TransactionType::Unstake { .. } | TransactionType::SlashDoubleSign { .. }));
+            if !trusted {
+                nonces.clear();
+                connected += self.connect_unpinned_blocks(std::mem::take(&mut pending))?;
+                self.add_block(block)?;
+                connected += 1;
+                continue;
+            }
+            let (tip_hash, tip_height) = match pending.last() {
+                Some(last) => (last.hash(), last.header.height),
+                None => (
+                    self.current_tip_hash.ok_or(BlockchainError::NotInitialized)?,
+                    self.current_height.ok_or(BlockchainError::NotInitialized)?,
+                ),
+            };
+            let header = &block.header;
+            if header.height != tip_height + 1 || header.previous_hash != tip_hash {
+                return Err(BlockchainError::Validation(format!(
+                    "Imported block {} does not extend block {} ({})", header.height, tip_height, hex::encode(tip_hash)
+                )));
+            }
+            let block_hash = block.hash();
+            self.checkpoints.check(header.height, &block_hash).map_err(BlockchainError::Validation)?;
+            let expected_bits = self.imported_block_bits(header.height, &pending)?;
+            if header.bits != expected_bits {
+                return Err(BlockchainError::Consensus(format!(
+                    "Incorrect target for block {}. Expected bits: {:#010x}, Got: {:#010x}",
+                    header.height, expected_bits, header.bits
+                )));
+            }
+            let merkle_root = block.merkle_root();
+            if header.merkle_root != merkle_root {
+                return Err(BlockchainError::Validation(format!(
+                    "Invalid Merkle root. Expected: {}, Got: {}", hex::encode(merkle_root), hex::encode(header.merkle_root)
+                )));
+            }
+            Self::check_block_witnesses(&block)?;
+            block.transactions.iter().try_for_each(|tx| self.check_transaction_activation(tx, header.height))?;
+            block.transactions.iter().try_for_each(|tx| self.check_nonce(tx, &mut nonces))?;
+            Self::check_coinbase(&block.transactions, header.height)?;
+            self.check_data_commitments(&block.transactions)?;
+
+            let pinned = self.checkpoints.pins(header.height, &block_hash);
+            pending.push(block);
+            if pinned {
+                connected += self.flush_imported_blocks(&mut pending)?;
+            }
+        }
+        connected += self.connect_unpinned_blocks(pending)?;
+        Ok(connected)
+    }
+
+    /// Target bits required for the imported block at `height`, whose unstored ancestors are `pending`.
+    fn imported_block_bits(&self, height: u64, pending: &[Block]) -> Result<u32, BlockchainError> {
+        let first_pending = pending.first().map_or(height, |block| block.header.height);
+        consensus::calculate_next_difficulty_from_headers(
+            height - 1,
+            self.chain_config.difficulty_algorithm,
+            self.chain_config.difficulty_bounds,
+            |height| match height.checked_sub(first_pending) {
+                Some(offset) => Ok(pending[offset as usize].header.clone()),
+                None => self.storage.get_block_by_height(height)
+                    .map_err(|e| format!("DB error getting block {} for difficulty calc: {}", height, e))?
+                    .map(|block| block.header)
+                    .ok_or_else(|| format!("Block {} not found in storage for difficulty calc", height)),
+            },
+        ).map_err(BlockchainError::Consensus)
+    }
+
+    /// Connects buffered import blocks that no checkpoint pinned, with full validation.
+    fn connect_unpinned_blocks(&mut self, blocks: Vec<Block>) -> Result<usize, BlockchainError> {
+        let count = blocks.len();
+        for block in blocks {
+            self.add_block(block)?;
+        }
+        Ok(count)
+    }
+
+    /// Writes blocks validated by `import_blocks` in one batch and moves the tip to the last one.
+    fn flush_imported_blocks(&mut self, pending: &mut Vec<Block>) -> Result<usize, BlockchainError> {
+        let last = match pending.last() {
+            Some(last) => last,
+            None => return Ok(0),
+        };
+        let (last_hash, last_height) = (last.hash(), last.header.height);
+        let mut chain_work = self.get_tip_chain_work()?;
+        let mut works = Vec::with_capacity(pending.len());
+        for block in pending.iter() {
+            chain_work += self.block_work(&block.header);
+            works.push((block.hash(), chain_work));
+            for tx in &block.transactions {
+                if let TransactionType::CreateToken { metadata } = &tx.transaction_type {
+                    self.storage.save_token_metadata(metadata)?;
+                }
+            }
+        }
+        for (blocks, works) in pending.chunks(IMPORT_BATCH_SIZE).zip(works.chunks(IMPORT_BATCH_SIZE)) {
+            self.storage.save_blocks(blocks)?;
+            self.storage.save_chain_works(works)?;
+        }
+
+        let finalized_height = self.chain_config.finalized_height(last_height);
+        if finalized_height > self.finalized_height {
+            self.storage.save_finalized_height(finalized_height.unwrap_or_default())?;
+        }
+        let tx_hashes: Vec<Hash> = pending.iter().flat_map(|block| block.transactions.iter().map(|tx| tx.calculate_hash())).collect();
+        self.mempool.remove_transactions(&tx_hashes);
+
+        self.current_tip_hash = Some(last_hash);
+        self.current_height = Some(last_height);
+        self.finalized_height = self.finalized_height.max(finalized_height);
+        self.tip_watch.bump();
+        info!("Imported {} blocks. New height: {}, New tip: {}", pending.len(), last_height, hex::encode(last_hash));
+        let count = pending.len();
+        pending.clear();
+        Ok(count)
+    }
+
+    /// Validates `block` as the next block on top of the tip and appends it to the main chain.
+    fn connect_block(&mut self, block: Block) -> Result<(), BlockchainError> {
+        let block_hash = block.hash();
+        let header = &block.header;
+        info!("Attempting to add block {} (Hash: {})...", header.height, hex::encode(block_hash));
+
+        let current_height = self.current_height.ok_or(BlockchainError::NotInitialized)?;
+        let current_tip_hash = self.current_tip_hash.ok_or(BlockchainError::NotInitialized)?;
+
+        // --- Basic Validation ---
+        let expected_height = current_height + 1;
+        if header.height != expected_height {
+            return Err(BlockchainError::Validation(format!(
+                "Invalid block height. Expected: {}, Got: {}",
+                expected_height, header.height
+            )));
+        }
+
+        if header.previous_hash != current_tip_hash {
+            return Err(BlockchainError::Validation(format!(
+                "Invalid previous block hash. Expected: {}, Got: {}",
+                hex::encode(current_tip_hash), hex::encode(header.previous_hash)
+            )));
+        }
+
+        self.checkpoints.check(header.height, &block_hash).map_err(BlockchainError::Validation)?;
+
+        let calculated_merkle_root = block.merkle_root();
         if header.merkle_root != calculated_merkle_root {
             return Err(BlockchainError::Validation(format!(
                 "Invalid Merkle root. Expected: {}, Got: {}",
                 hex::encode(calculated_merkle_root), hex::encode(header.merkle_root)
             )));
         }
+        Self::check_block_witnesses(&block)?;
 
         // --- Consensus Validation ---
-        if !consensus::verify_pow(&block_hash, header.difficulty) {
-            return Err(BlockchainError::Consensus(format!(
-                "Invalid Proof-of-Work. Hash {} does not meet difficulty {}",
-                hex::encode(block_hash), header.difficulty
-            )));
-        }
-
-        // calculate_next_difficulty needs access to storage, error handled by '?'
-        let expected_difficulty = consensus::calculate_next_difficulty(current_height, &self.storage)
+        let ctx = EngineContext { storage: &self.storage, trusted: self.checkpoints.pins(header.height, &block_hash) };
+        self.engine.validate_header(&block, &ctx)
             .map_err(BlockchainError::Consensus)?;
-        if header.difficulty != expected_difficulty {
-            return Err(BlockchainError::Consensus(format!(
-                "Incorrect difficulty for block {}. Expected: {}, Got: {}",
-                header.height, expected_difficulty, header.difficulty
-            )));
+
+        for tx in &block.transactions {
+            self.check_transaction_activation(tx, header.height)?;
+            namespaces::check_namespace(tx).map_err(|e| BlockchainError::Validation(format!("Invalid transaction {}: {}", hex::encode(tx.calculate_hash()), e)))?;
+        }
+        Self::check_coinbase(&block.transactions, header.height)?;
+        self.check_data_commitments(&block.transactions)?;
+        let mut claimed = std::collections::HashSet::new();
+        let mut published = std::collections::HashMap::new();
+        let mut staked = std::collections::HashMap::new();
+        let mut nonces = std::collections::HashMap::new();
+        for tx in &block.transactions {
+            self.check_storage_deal(tx, header.height, &mut claimed)?;
+            self.check_pointer(tx, &mut published)?;
+            self.check_stake_funds(tx, &mut staked)?;
+            self.check_nonce(tx, &mut nonces)?;
         }
+        let stake_updates = self.apply_staking_transactions(&block.transactions)?;
 
-        // TODO: Add transaction validation logic here (e.g., check signatures, balances)
+        // TODO: Add transaction validation logic here (e.g., balances)
         // Iterate through transactions to perform state updates (like saving token metadata)
         for tx in &block.transactions {
             match &tx.transaction_type {
@@ -409,28 +1684,343 @@ pub fn add_block(&mut self, block: Block) -> Result<(), BlockchainError> {
                         }
                     }
                 }
-                // Handle other transaction types if needed for state updates here
-                _ => {}
+                // Handle other transaction types if needed for state updates here
+                _ => {}
+            }
+        }
+
+        // --- Save Block --- 
+        if !stake_updates.is_empty() {
+            self.storage.save_stakes(&stake_updates)?;
+        }
+        // save_block now returns StorageError, handled by '?'
+        let chain_work = self.get_chain_work(&current_tip_hash)? + self.block_work(header);
+        self.storage.save_block(&block)?;
+        self.storage.save_chain_work(&block_hash, chain_work)?;
+
+        let finalized_height = self.chain_config.finalized_height(header.height);
+        if finalized_height > self.finalized_height {
+            self.storage.save_finalized_height(finalized_height.unwrap_or_default())?;
+        }
+
+        // --- Update Cache --- 
+        self.current_tip_hash = Some(block_hash);
+        self.current_height = Some(header.height);
+        self.finalized_height = self.finalized_height.max(finalized_height);
+        self.tip_watch.bump();
+
+        info!("Block {} added successfully. New height: {}, New tip: {}",
+               header.height, self.current_height.unwrap(), hex::encode(self.current_tip_hash.unwrap()));
+
+        Ok(())
+    }
+
+    /// Work contributed by one block: the expected hashes for its target on PoW chains, 1 on PoS chains.
+    fn block_work(&self, header: &BlockHeader) -> U256 {
+        self.engine.block_work(header)
+    }
+
+    /// Context handed to the consensus engine for building a block.
+    fn engine_context(&self) -> EngineContext<'_> {
+        EngineContext { storage: &self.storage, trusted: false }
+    }
+
+    /// Walks the stored main chain from genesis and re-checks every block: stored hash,
+    /// height, previous-hash link, checkpoints, Merkle root, signatures and, on Proof-of-Work chains,
+    /// the PoW and the difficulty schedule. Stops at the first corrupt block.
+    pub fn verify_chain(&self) -> Result<ChainVerification, BlockchainError> {
+        let tip = self.current_height.ok_or(BlockchainError::NotInitialized)?;
+        let mut verification = ChainVerification { verified_height: None, corruption: None };
+        let mut previous: Option<Hash> = None;
+        let mut expected_height = 0;
+        for entry in self.storage.iter_main_chain_hashes(..=tip) {
+            let (height, hash) = entry?;
+            if let Err(reason) = self.verify_stored_block(expected_height, height, &hash, previous) {
+                verification.corruption = Some(ChainCorruption { height: expected_height.min(height), reason });
+                return Ok(verification);
+            }
+            verification.verified_height = Some(height);
+            previous = Some(hash);
+            expected_height = height + 1;
+        }
+        if expected_height <= tip {
+            let reason = format!("Height index ends at {:?}, chain tip is at {}", verification.verified_height, tip);
+            verification.corruption = Some(ChainCorruption { height: expected_height, reason });
+        }
+        Ok(verification)
+    }
+
+    fn verify_stored_block(&self, expected_height: u64, height: u64, hash: &Hash, previous: Option<Hash>) -> Result<(), String> {
+        if height != expected_height {
+            return Err(format!("Height index skips from {} to {}", expected_height, height));
+        }
+        let block = self.storage.get_block_by_hash(hash)
+            .map_err(|e| format!("Block {} cannot be read: {}", hex::encode(hash), e))?
+            .ok_or_else(|| format!("Block {} is missing", hex::encode(hash)))?;
+        if block.hash() != *hash {
+            return Err(format!("Stored block hashes to {}, indexed as {}", hex::encode(block.hash()), hex::encode(hash)));
+        }
+        if block.header.height != height {
+            return Err(format!("Block header claims height {}", block.header.height));
+        }
+        let expected_previous = previous.unwrap_or([0u8; 32]);
+        if block.header.previous_hash != expected_previous {
+            return Err(format!(
+                "Invalid previous block hash. Expected: {}, Got: {}",
+                hex::encode(expected_previous), hex::encode(block.header.previous_hash)
+            ));
+        }
+        self.checkpoints.check(height, hash)?;
+        if block.header.merkle_root != block.merkle_root() {
+            return Err("Invalid Merkle root".to_string());
+        }
+        Self::check_block_witnesses(&block).map_err(|e| e.to_string())?;
+        // Genesis is not mined, and stake checks depend on the stake table at the time the block was connected
+        if height > 0 && self.engine.mode() == ConsensusMode::ProofOfWork {
+            self.engine.validate_header(&block, &EngineContext { storage: &self.storage, trusted: false })?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the main-chain blocks from `height` up to the tip, making the block below `height`
+    /// the new tip. Used to cut a chain back to its last good block after [`Blockchain::verify_chain`].
+    pub fn truncate_chain(&mut self, height: u64) -> Result<(), BlockchainError> {
+        let tip = self.current_height.ok_or(BlockchainError::NotInitialized)?;
+        if height == 0 {
+            return Err(BlockchainError::Validation("Cannot truncate the genesis block".to_string()));
+        }
+        if height > tip {
+            return Ok(());
+        }
+        let new_tip = self.storage.get_hash_by_height(height - 1)?
+            .ok_or(BlockchainError::BlockNotFoundByHeight(height - 1))?;
+        let mut dropped_payloads = Vec::new();
+        // Unreadable blocks are the usual reason to truncate, so they are skipped
+        for block in self.storage.iter_blocks(height..=tip).flatten() {
+            dropped_payloads.extend(Self::anchored_payloads(&block));
+        }
+        self.storage.rollback_to_height(height - 1)?;
+        if matches!(self.finalized_height, Some(finalized) if finalized >= height) {
+            self.finalized_height = Some(height - 1);
+        }
+        self.current_tip_hash = Some(new_tip);
+        self.current_height = Some(height - 1);
+        self.tip_watch.bump();
+        self.revalidate_payload_acls(&dropped_payloads);
+        warn!("Truncated the chain to height {} ({} blocks dropped)", height - 1, tip - height + 1);
+        Ok(())
+    }
+
+    /// Payload hashes anchored by the `StoreData` transactions of `block`.
+    fn anchored_payloads(block: &Block) -> impl Iterator<Item = Hash> + '_ {
+        block.transactions.iter().filter_map(|tx| match tx.transaction_type {
+            TransactionType::StoreData { data_hash, .. } => Some(data_hash),
+            _ => None,
+        })
+    }
+
+    /// Rechecks the ACLs of payloads whose anchoring blocks left the main chain, see
+    /// [`crate::network::acl::revalidate_acls`].
+    fn revalidate_payload_acls(&self, payload_hashes: &[Hash]) {
+        if let Err(e) = crate::network::acl::revalidate_acls(self, payload_hashes) {
+            warn!("Failed to recheck payload ACLs after the main chain changed: {}", e);
+        }
+    }
+
+    /// Cumulative work of the chain ending at the block `hash`.
+    /// Falls back to summing ancestors for blocks stored before chain work was recorded.
+    pub fn get_chain_work(&self, hash: &Hash) -> Result<U256, BlockchainError> {
+        let mut missing = Vec::new();
+        let mut cursor = *hash;
+        let base = loop {
+            if let Some(work) = self.storage.get_chain_work(&cursor)? {
+                break work;
+            }
+            let block = self.storage.get_block_by_hash(&cursor)?
+                .ok_or_else(|| BlockchainError::BlockNotFoundByHash(hex::encode(cursor)))?;
+            missing.push(self.block_work(&block.header));
+            if block.header.height == 0 {
+                break U256::zero();
+            }
+            cursor = block.header.previous_hash;
+        };
+        Ok(missing.into_iter().fold(base, |work, block_work| work + block_work))
+    }
+
+    /// Returns the cumulative work of the current main chain.
+    pub fn get_tip_chain_work(&self) -> Result<U256, BlockchainError> {
+        let tip_hash = self.current_tip_hash.ok_or(BlockchainError::NotInitialized)?;
+        self.get_chain_work(&tip_hash)
+    }
+
+    /// Stores a valid block that does not extend the tip, and reorganizes onto its branch
+    /// if that branch now has more cumulative work than the main chain.
+    fn add_side_block(&mut self, block: Block, parent: &Block) -> Result<(), BlockchainError> {
+        let block_hash = block.hash();
+        let header = &block.header;
+        if header.height != parent.header.height + 1 {
+            return Err(BlockchainError::Validation(format!(
+                "Invalid block height. Expected: {}, Got: {}",
+                parent.header.height + 1, header.height
+            )));
+        }
+        self.checkpoints.check(header.height, &block_hash).map_err(BlockchainError::Validation)?;
+        let calculated_merkle_root = block.merkle_root();
+        if header.merkle_root != calculated_merkle_root {
+            return Err(BlockchainError::Validation(format!(
+                "Invalid Merkle root. Expected: {}, Got: {}",
+                hex::encode(calculated_merkle_root), hex::encode(header.merkle_root)
+            )));
+        }
+        // The target is checked against the branch's own history when the branch is connected
+        let pow_algorithm = self.chain_config.pow_algorithm;
+        if !self.checkpoints.pins(header.height, &block_hash) && !consensus::verify_header_pow(header, pow_algorithm) {
+            return Err(BlockchainError::Consensus(format!(
+                "Invalid Proof-of-Work. {} hash {} does not meet target bits {:#010x}",
+                pow_algorithm, hex::encode(pow_algorithm.hash_header(header)), header.bits
+            )));
+        }
+
+        let chain_work = self.get_chain_work(&header.previous_hash)? + self.block_work(header);
+        self.storage.save_side_block(&block)?;
+        self.storage.save_chain_work(&block_hash, chain_work)?;
+
+        if chain_work > self.get_tip_chain_work()? {
+            self.reorganize_to(&block)
+        } else {
+            info!("Stored side-chain block {} at height {} (not enough work to become the tip)",
+                  hex::encode(block_hash), header.height);
+            Ok(())
+        }
+    }
+
+    /// Switches the main chain to the branch ending at `new_tip`.
+    ///
+    /// The branch is connected block by block with full validation; if any block fails,
+    /// the previous main chain is restored and the error returned. A block failing validation
+    /// is marked failed together with its descendants up to `new_tip`, and branches through
+    /// a failed block are refused without being connected.
+    fn reorganize_to(&mut self, new_tip: &Block) -> Result<(), BlockchainError> {
+        let old_height = self.current_height.ok_or(BlockchainError::NotInitialized)?;
+
+        // Walk back until the branch meets the main chain
+        let mut branch = vec![new_tip.clone()];
+        loop {
+            let previous_hash = branch.last().expect("branch is never empty").header.previous_hash;
+            let parent = self.storage.get_block_by_hash(&previous_hash)?
+                .ok_or_else(|| BlockchainError::BlockNotFoundByHash(hex::encode(previous_hash)))?;
+            if self.storage.get_hash_by_height(parent.header.height)? == Some(previous_hash) {
+                break;
+            }
+            branch.push(parent);
+        }
+        branch.reverse();
+        for (position, block) in branch.iter().enumerate() {
+            if self.storage.is_block_failed(&block.hash())? {
+                self.storage.mark_blocks_failed(&branch[position..].iter().map(Block::hash).collect::<Vec<_>>())?;
+                return Err(BlockchainError::Validation(format!(
+                    "Branch to {} contains block {}, which failed validation",
+                    hex::encode(new_tip.hash()), hex::encode(block.hash())
+                )));
+            }
+        }
+        let fork_height = branch[0].header.height - 1;
+        let fork_hash = branch[0].header.previous_hash;
+        self.check_reorg_depth(fork_height)?;
+        info!("Reorganizing: fork at height {}, disconnecting {} block(s), connecting {}",
+              fork_height, old_height - fork_height, branch.len());
+
+        let mut disconnected = Vec::new();
+        for height in fork_height + 1..=old_height {
+            disconnected.push(self.storage.get_block_by_height(height)?.ok_or(BlockchainError::BlockNotFoundByHeight(height))?);
+        }
+
+        self.storage.rewind_main_chain(fork_height, &fork_hash, old_height)?;
+        self.current_tip_hash = Some(fork_hash);
+        self.current_height = Some(fork_height);
+        let connected = branch.iter().try_for_each(|block| self.connect_block(block.clone()));
+        if let Err(e) = connected {
+            warn!("Reorganization to {} failed, restoring previous chain: {}", hex::encode(new_tip.hash()), e);
+            let current_height = self.current_height.unwrap_or(fork_height);
+            if matches!(e, BlockchainError::Validation(_) | BlockchainError::Consensus(_)) {
+                let failed = (current_height - fork_height) as usize;
+                self.storage.mark_blocks_failed(&branch[failed..].iter().map(Block::hash).collect::<Vec<_>>())?;
+            }
+            self.storage.rewind_main_chain(fork_height, &fork_hash, current_height)?;
+            for block in &disconnected {
+                self.storage.save_block(block)?;
+            }
+            self.current_tip_hash = Some(disconnected.last().map_or(fork_hash, |block| block.hash()));
+            self.current_height = Some(old_height);
+            self.tip_watch.bump();
+            return Err(e);
+        }
+
+        let dropped_payloads: Vec<Hash> = disconnected.iter().flat_map(Self::anchored_payloads).collect();
+        self.revalidate_payload_acls(&dropped_payloads);
+
+        // Transactions only confirmed on the abandoned branch go back to the mempool
+        let connected_hashes: std::collections::HashSet<Hash> = branch.iter()
+            .flat_map(|block| block.transactions.iter().map(Transaction::calculate_hash))
+            .collect();
+        for (tx, witness) in disconnected.into_iter().flat_map(|block| block.transactions.into_iter().zip(block.witnesses)) {
+            if !tx.is_coinbase() && !connected_hashes.contains(&tx.calculate_hash()) {
+                if let Err(e) = self.mempool.add_transaction_at(tx, witness, time::unix_now()) {
+                    debug!("Dropped transaction from disconnected block: {}", e);
+                }
             }
         }
+        self.mempool.remove_transactions(&connected_hashes.into_iter().collect::<Vec<_>>());
+        Ok(())
+    }
 
-        // --- Save Block --- 
-        // save_block now returns StorageError, handled by '?'
-        self.storage.save_block(&block)?;
+    /// Creates a new block candidate, mines it, and returns the mined block.
+    /// Does NOT add the block to the chain automatically.
+    pub fn mine_new_block(&mut self) -> Result<Block, BlockchainError> {
+        let miner = self.miner_address.clone();
+        self.mine_new_block_for(miner.as_ref())
+    }
 
-        // --- Update Cache --- 
-        self.current_tip_hash = Some(block_hash);
-        self.current_height = Some(header.height);
+    /// Mines and connects `count` blocks paying their coinbase to `miner`, and returns them.
+    /// Meant for chains with a trivial target (regtest), where each block is found at once.
+    pub fn generate_blocks(&mut self, count: u64, miner: &Address) -> Result<Vec<Block>, BlockchainError> {
+        let mut blocks = Vec::new();
+        for _ in 0..count {
+            let block = self.mine_new_block_for(Some(miner))?;
+            self.process_mined_block(block.clone())?;
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
 
-        info!("Block {} added successfully. New height: {}, New tip: {}",
-               header.height, self.current_height.unwrap(), hex::encode(self.current_tip_hash.unwrap()));
+    fn mine_new_block_for(&mut self, miner: Option<&Address>) -> Result<Block, BlockchainError> {
+        let mut block = self.create_block_template_for(miner)?;
+        let next_height = block.header.height;
 
-        Ok(())
+        // Mine the block (find nonce)
+        let start_time = SystemTime::now();
+        self.engine.seal_block(&mut block, None, &self.engine_context())
+            .map_err(BlockchainError::Consensus)?;
+        let mining_duration = start_time.elapsed().unwrap_or_default();
+
+        info!("Successfully mined block {} in {:?}. Hash: {}, Nonce: {}",
+               next_height, mining_duration, hex::encode(block.hash()), block.header.nonce);
+
+        Ok(block)
     }
 
-    /// Creates a new block candidate, mines it, and returns the mined block.
-    /// Does NOT add the block to the chain automatically.
-    pub fn mine_new_block(&mut self /*, _miner_address: Address */) -> Result<Block, BlockchainError> {
+    /// Creates an unmined block candidate on top of the current tip, with mempool transactions
+    /// and the target required for the next height.
+    pub fn create_block_template(&mut self) -> Result<Block, BlockchainError> {
+        let miner = self.miner_address.clone();
+        self.create_block_template_for(miner.as_ref())
+    }
+
+    // A block template whose coinbase pays `miner`, if given
+    fn create_block_template_for(&mut self, miner: Option<&Address>) -> Result<Block, BlockchainError> {
+        if self.engine.mode() != ConsensusMode::ProofOfWork {
+            return Err(BlockchainError::Consensus("Mining requires a Proof-of-Work chain; use forge_block".to_string()));
+        }
         let current_height = self.current_height.ok_or(BlockchainError::NotInitialized)?;
         let previous_hash = self.current_tip_hash.ok_or(BlockchainError::NotInitialized)?;
         let next_height = current_height + 1;
@@ -438,28 +2028,46 @@ pub fn add_block(&mut self, block: Block) -> Result<(), BlockchainError> {
         info!("Attempting to mine block {}...", next_height);
 
         // 1. Get transactions from mempool
-        let transactions = self.mempool.get_transactions(MAX_TRANSACTIONS_PER_BLOCK);
+        let mut transactions = self.select_block_transactions();
         debug!("Selected {} transactions from mempool for block {}.", transactions.len(), next_height);
 
-        // TODO: Add Coinbase transaction rewarding the miner
+        // Subsidy and fees go to the configured miner; without one they are left unclaimed
+        if let Some(miner) = miner {
+            let amount = transactions.iter().fold(consensus::BLOCK_SUBSIDY, |total, tx| total.saturating_add(tx.fee));
+            transactions.insert(0, Transaction::new_coinbase(miner.clone(), next_height, amount));
+        }
 
-        // 2. Calculate difficulty for the new block
-        // calculate_next_difficulty needs access to storage, error handled by '?'
-        let difficulty = consensus::calculate_next_difficulty(current_height, &self.storage)
+        // 2. Create block template with the difficulty required for the new block
+        let mut block = self.engine.prepare_block(previous_hash, next_height, transactions, &self.engine_context())
             .map_err(BlockchainError::Consensus)?;
-        debug!("Calculated difficulty for block {}: {}", next_height, difficulty);
-
-        // 3. Create block template
-        let mut block = Block::new(previous_hash, transactions, difficulty, next_height);
-
-        // 4. Mine the block (find nonce)
-        let start_time = SystemTime::now();
-        let mined_hash = consensus::mine(&mut block.header, difficulty);
-        let mining_duration = start_time.elapsed().unwrap_or_default();
+        block.set_witnesses(self.pending_witnesses(&block.transactions));
+        debug!("Calculated difficulty for block {}: {}", next_height, block.header.bits);
+        Ok(block)
+    }
 
-        info!("Successfully mined block {} in {:?}. Hash: {}, Nonce: {}",
-               next_height, mining_duration, hex::encode(mined_hash), block.header.nonce);
+    /// Creates and seals a new block on a Proof-of-Stake chain.
+    /// Fails unless `signing_key` belongs to the producer selected for the next height.
+    /// Does NOT add the block to the chain automatically.
+    pub fn forge_block(&mut self, signing_key: &SigningKey) -> Result<Block, BlockchainError> {
+        if self.engine.mode() != ConsensusMode::ProofOfStake {
+            return Err(BlockchainError::Consensus("forge_block requires a Proof-of-Stake chain".to_string()));
+        }
+        let current_height = self.current_height.ok_or(BlockchainError::NotInitialized)?;
+        let previous_hash = self.current_tip_hash.ok_or(BlockchainError::NotInitialized)?;
+        let next_height = current_height + 1;
 
+        let mut transactions = self.select_block_transactions();
+        // As on Proof-of-Work chains, subsidy and fees go to the configured miner address
+        if let Some(miner) = &self.miner_address {
+            let amount = transactions.iter().fold(consensus::BLOCK_SUBSIDY, |total, tx| total.saturating_add(tx.fee));
+            transactions.insert(0, Transaction::new_coinbase(miner.clone(), next_height, amount));
+        }
+        let ctx = self.engine_context();
+        let mut block = self.engine.prepare_block(previous_hash, next_height, transactions, &ctx)
+            .map_err(BlockchainError::Consensus)?;
+        block.set_witnesses(self.pending_witnesses(&block.transactions));
+        self.engine.seal_block(&mut block, Some(signing_key), &ctx).map_err(BlockchainError::Consensus)?;
+        info!("Forged block {} as selected producer {}", next_height, hex::encode(signing_key.verifying_key().to_bytes()));
         Ok(block)
     }
 
@@ -481,6 +2089,38 @@ pub fn add_block(&mut self, block: Block) -> Result<(), BlockchainError> {
         Ok(())
     }
 
+    /// Calculates the native currency balance of an address on the main chain: coinbases, transfers and
+    /// deal payments received and stakes released, minus transfers, deal payments, stakes and fees sent.
+    /// Note: This iterates through the entire chain; the chain indexer keeps the same balances incrementally.
+    pub fn get_native_balance(&self, address: &Address) -> Result<u64, BlockchainError> {
+        let chain_height = self.get_chain_height().ok_or(BlockchainError::NotInitialized)?;
+        let mut balance: i128 = 0;
+        for block in self.storage.iter_blocks(0..=chain_height) {
+            for tx in &block?.transactions {
+                let sent = &tx.sender == address;
+                let received = tx.receiver.as_ref() == Some(address);
+                if sent {
+                    balance -= tx.fee as i128;
+                }
+                match &tx.transaction_type {
+                    TransactionType::TransferNative { amount } | TransactionType::StorageDeal { price: amount, .. } => {
+                        if sent {
+                            balance -= *amount as i128;
+                        }
+                        if received {
+                            balance += *amount as i128;
+                        }
+                    }
+                    TransactionType::Coinbase { amount, .. } if received => balance += *amount as i128,
+                    TransactionType::Stake { amount } if sent => balance -= *amount as i128,
+                    TransactionType::Unstake { amount } if sent => balance += *amount as i128,
+                    _ => {}
+                }
+            }
+        }
+        Ok(u64::try_from(balance.max(0)).unwrap_or(u64::MAX))
+    }
+
     /// Calculates the balance of a specific token for a given address.
     /// Note: This iterates through the entire chain. For performance, consider a dedicated balance state.
     pub fn get_token_balance(&self, address: &Address, token_id: &Hash) -> Result<u64, BlockchainError> {
@@ -529,6 +2169,89 @@ pub fn add_block(&mut self, block: Block) -> Result<(), BlockchainError> {
 
         Ok(balance)
     }
+
+    /// Earliest main-chain `StoreData` reference of each payload hash.
+    pub fn payload_anchors(&self) -> Result<std::collections::HashMap<Hash, PayloadAnchor>, BlockchainError> {
+        let mut anchors = std::collections::HashMap::new();
+        let Some(tip_height) = self.get_chain_height() else {
+            return Ok(anchors);
+        };
+        for block in self.storage.iter_blocks(0..=tip_height) {
+            let block = block?;
+            for tx in &block.transactions {
+                if let TransactionType::StoreData { data_hash, data_size, .. } = tx.transaction_type {
+                    anchors.entry(data_hash).or_insert(PayloadAnchor { height: block.header.height, tx_hash: tx.calculate_hash(), declared_size: data_size });
+                }
+            }
+        }
+        Ok(anchors)
+    }
+
+    /// Payload hashes referenced by `StoreData` transactions still waiting in the mempool.
+    pub fn pending_payloads(&self) -> std::collections::HashSet<Hash> {
+        self.mempool.get_transactions(usize::MAX).into_iter().filter_map(|tx| match tx.transaction_type {
+            TransactionType::StoreData { data_hash, .. } => Some(data_hash),
+            _ => None,
+        }).collect()
+    }
+
+    /// Payload bytes declared by `StoreData` transactions still waiting in the mempool that file
+    /// their payloads under `namespace`.
+    pub fn pending_namespace_bytes(&self, namespace: &str) -> u64 {
+        self.mempool.get_transactions(usize::MAX).into_iter().filter_map(|tx| match tx.transaction_type {
+            TransactionType::StoreData { data_size, namespace: Some(pending), .. } if pending == namespace => Some(data_size),
+            _ => None,
+        }).fold(0, u64::saturating_add)
+    }
+
+    /// Highest sequence number of the records for the pointer `name` still waiting in the mempool.
+    pub fn pending_pointer_sequence(&self, name: &[u8; 32]) -> Option<u64> {
+        self.mempool.get_transactions(usize::MAX).into_iter().filter_map(|tx| match tx.transaction_type {
+            TransactionType::PublishPointer { record } if &record.name == name => Some(record.sequence),
+            _ => None,
+        }).max()
+    }
+
+    /// Lists the balances of every holder of `token_id` as of the block at `height`,
+    /// committed to by a Merkle root (see [`TokenHolderSnapshot`]).
+    pub fn snapshot_token_holders(&self, token_id: &Hash, height: u64) -> Result<TokenHolderSnapshot, BlockchainError> {
+        let chain_height = self.get_chain_height().ok_or(BlockchainError::NotInitialized)?;
+        if height > chain_height {
+            return Err(BlockchainError::BlockNotFoundByHeight(height));
+        }
+        if self.storage.get_token_metadata(token_id)?.is_none() {
+            return Err(BlockchainError::Validation(format!("Token with ID {} not found", hex::encode(token_id))));
+        }
+
+        let mut balances: std::collections::BTreeMap<Address, u64> = std::collections::BTreeMap::new();
+        let mut block_hash = [0u8; 32];
+        for block in self.storage.iter_blocks(0..=height) {
+            let block = block?;
+            for tx in &block.transactions {
+                match &tx.transaction_type {
+                    TransactionType::CreateToken { metadata } if &metadata.metadata_hash == token_id => {
+                        let balance = balances.entry(metadata.creator.clone()).or_default();
+                        *balance = balance.saturating_add(metadata.total_supply);
+                    }
+                    TransactionType::TransferToken { token_id: tx_token_id, amount } if tx_token_id == token_id => {
+                        let sender_balance = balances.entry(tx.sender.clone()).or_default();
+                        *sender_balance = sender_balance.saturating_sub(*amount);
+                        if let Some(receiver) = &tx.receiver {
+                            let receiver_balance = balances.entry(receiver.clone()).or_default();
+                            *receiver_balance = receiver_balance.saturating_add(*amount);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            block_hash = block.hash();
+        }
+
+        let snapshot = TokenHolderSnapshot::new(*token_id, height, block_hash, balances);
+        info!("Snapshot of token {} at height {}: {} holders, Merkle root {}",
+              hex::encode(token_id), height, snapshot.holders.len(), hex::encode(snapshot.merkle_root));
+        Ok(snapshot)
+    }
 }
 
 #[cfg(test)]
@@ -539,9 +2262,16 @@ mod tests {
     use std::time::Duration;
     use crate::storage::StorageError; // Import StorageError for tests
 
+    // The ed25519 address of `key`, for transactions it signs
+    fn key_address(key: &SigningKey) -> Address {
+        address::address_from_public_key(address::KeyAlgorithm::Ed25519, &key.verifying_key().to_bytes()).unwrap()
+    }
+
     // Helper to create a basic block for testing add_block
     fn create_test_block(prev_hash: Hash, height: u64, difficulty: u32, transactions: Vec<Transaction>) -> Block {
         let mut block = Block::new(prev_hash, transactions, difficulty, height);
+        // Blocks built within one second still move past the median time past
+        block.header.timestamp += height;
         let _mined_hash = consensus::mine(&mut block.header, difficulty);
         block
     }
@@ -562,14 +2292,40 @@ mod tests {
     fn blockchain_add_pending_transaction() {
         let dir = tempdir().unwrap();
         let mut blockchain = Blockchain::new(dir.path()).unwrap();
-        let tx1 = Transaction::new_transfer(vec![1], vec![2], 100);
-        let tx2 = Transaction::new_transfer(vec![3], vec![4], 200);
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let tx1 = Transaction::new_transfer_native(key_address(&key), vec![2], 100).with_nonce(0);
+        let tx2 = Transaction::new_store_data(vec![3], [4u8; 32], 200);
+
+        // A transfer spends the sender's funds, so it needs the sender's signature
+        assert!(matches!(blockchain.add_pending_transaction(tx1.clone()), Err(BlockchainError::Validation(_))));
+        let forged = SignedTransaction::sign_ed25519(tx1.clone(), &SigningKey::from_bytes(&[2u8; 32]));
+        assert!(matches!(blockchain.add_signed_transaction(forged), Err(BlockchainError::Validation(_))));
+        assert!(blockchain.mempool.is_empty());
 
-        assert!(blockchain.add_pending_transaction(tx1.clone()).unwrap());
+        assert!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx1.clone(), &key)).unwrap());
         assert!(!blockchain.mempool.is_empty());
         assert!(blockchain.add_pending_transaction(tx2.clone()).unwrap());
         // Try adding duplicate
-        assert!(!blockchain.add_pending_transaction(tx1.clone()).unwrap());
+        assert!(!blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx1.clone(), &key)).unwrap());
+    }
+
+    #[test]
+    fn blockchain_save_and_load_mempool() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("mempool.dat");
+        let mut blockchain = Blockchain::new(&dir.path().join("a")).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        blockchain.add_pending_transaction(Transaction::new_store_data(vec![1], [1; 32], 17)).unwrap();
+        blockchain.add_pending_transaction(Transaction::new_store_data(vec![2], [2; 32], 17)).unwrap();
+        assert_eq!(blockchain.save_mempool(&path).unwrap(), 2);
+
+        let mut restarted = Blockchain::new(&dir.path().join("b")).unwrap();
+        restarted.initialize_genesis_if_needed().unwrap();
+        assert_eq!(restarted.load_mempool(&path).unwrap(), 2);
+        assert_eq!(restarted.mempool.entries(), blockchain.mempool.entries());
+        // The file is consumed, so a crash later can't restore the same transactions again
+        assert!(!path.exists());
+        assert_eq!(restarted.load_mempool(&path).unwrap(), 0);
     }
 
     #[test]
@@ -657,10 +2413,11 @@ mod tests {
         blockchain.initialize_genesis_if_needed().unwrap();
 
         // Add some transactions to mempool
-        let tx1 = Transaction::new_transfer(vec![1], vec![2], 50);
-        let tx2 = Transaction::new_transfer(vec![3], vec![4], 150);
-        blockchain.add_pending_transaction(tx1.clone()).unwrap();
-        blockchain.add_pending_transaction(tx2.clone()).unwrap();
+        let (alice, bob) = (SigningKey::from_bytes(&[1u8; 32]), SigningKey::from_bytes(&[3u8; 32]));
+        let tx1 = Transaction::new_transfer_native(key_address(&alice), vec![2], 50).with_nonce(0);
+        let tx2 = Transaction::new_transfer_native(key_address(&bob), vec![4], 150).with_nonce(0);
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx1.clone(), &alice)).unwrap();
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx2.clone(), &bob)).unwrap();
         assert!(!blockchain.mempool.is_empty());
 
         // Mine a new block
@@ -671,6 +2428,16 @@ mod tests {
         assert_eq!(mined_block.transactions.len(), 2);
         assert!(mined_block.transactions.contains(&tx1));
         assert!(mined_block.transactions.contains(&tx2));
+        assert!(mined_block.witnesses.iter().all(Option::is_some));
+
+        // The Merkle root commits to the signatures, and a block without them is refused
+        let mut stripped = mined_block.clone();
+        stripped.witnesses = vec![None; 2];
+        assert!(matches!(blockchain.add_block(stripped.clone()), Err(BlockchainError::Validation(_))));
+        stripped.header.merkle_root = stripped.merkle_root();
+        let bits = stripped.header.bits;
+        consensus::mine(&mut stripped.header, bits);
+        assert!(matches!(blockchain.add_block(stripped), Err(BlockchainError::Validation(_))));
 
         // Process the mined block
         let process_result = blockchain.process_mined_block(mined_block.clone());
@@ -686,6 +2453,667 @@ mod tests {
         // Verify block is in storage
         let stored_block = blockchain.get_block_by_height(1).unwrap().unwrap();
         assert_eq!(stored_block.hash(), mined_block.hash());
+        assert_eq!(stored_block.witnesses, mined_block.witnesses);
+        let (_, proof) = blockchain.get_transaction_proof(&tx1.calculate_hash()).unwrap().unwrap();
+        assert!(proof.witness_root.is_some());
+        assert_eq!(proof.verify(&mined_block.header, &tx1.calculate_hash()), Ok(()));
+        assert!(blockchain.verify_chain().unwrap().corruption.is_none());
+    }
+
+    #[test]
+    fn blockchain_rejects_inactive_transaction_version() {
+        let dir = tempdir().unwrap();
+        let mut config = ChainConfig::default();
+        config.transaction_version_activations.insert(2, 5);
+        let mut blockchain = Blockchain::new_with_config(dir.path(), config).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+
+        let future_tx = Transaction::new_transfer_native(vec![1], vec![2], 10).with_version(2);
+        match blockchain.add_pending_transaction(future_tx).unwrap_err() {
+            BlockchainError::Validation(msg) => assert!(msg.contains("version 2")),
+            e => panic!("Expected Validation error, got {:?}", e),
+        }
+
+        let unknown_tx = Transaction::new_transfer_native(vec![1], vec![2], 10).with_version(7);
+        assert!(blockchain.add_pending_transaction(unknown_tx).is_err());
+    }
+
+    #[test]
+    fn blockchain_rejects_dormant_feature_in_block() {
+        let dir = tempdir().unwrap();
+        let mut config = ChainConfig::default();
+        config.feature_activations.remove(&TxFeature::Tokens);
+        let mut blockchain = Blockchain::new_with_config(dir.path(), config).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+
+        let token_tx = Transaction::new_create_token(vec![1], "Test".to_string(), "TST".to_string(), 1000);
+        assert!(blockchain.add_pending_transaction(token_tx.clone()).is_err());
+
+        let prev_hash = blockchain.get_last_block_hash().unwrap();
+        let difficulty = consensus::calculate_next_difficulty(0, &blockchain.storage).unwrap();
+        let block = create_test_block(prev_hash, 1, difficulty, vec![token_tx]);
+        match blockchain.add_block(block).unwrap_err() {
+            BlockchainError::Validation(msg) => assert!(msg.contains("Tokens")),
+            e => panic!("Expected Validation error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn blockchain_verifies_and_truncates_stored_chain() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        for _ in 0..3 {
+            let block = blockchain.mine_new_block().unwrap();
+            blockchain.process_mined_block(block).unwrap();
+        }
+        let verification = blockchain.verify_chain().unwrap();
+        assert_eq!(verification, ChainVerification { verified_height: Some(3), corruption: None });
+
+        // Overwrite block 2 with one that does not link to block 1
+        let original = blockchain.get_block_by_height(2).unwrap().unwrap();
+        let broken = create_test_block([9u8; 32], 2, original.header.bits, Vec::new());
+        blockchain.storage.save_block(&broken).unwrap();
+        let verification = blockchain.verify_chain().unwrap();
+        assert_eq!(verification.verified_height, Some(1));
+        let corruption = verification.corruption.unwrap();
+        assert_eq!(corruption.height, 2);
+        assert!(corruption.reason.contains("previous block hash"));
+
+        blockchain.truncate_chain(corruption.height).unwrap();
+        assert_eq!(blockchain.get_chain_height(), Some(1));
+        assert!(blockchain.get_block_by_height(2).unwrap().is_none());
+        assert_eq!(blockchain.verify_chain().unwrap().corruption, None);
+        assert!(blockchain.truncate_chain(0).is_err());
+    }
+
+    #[test]
+    fn blockchain_caps_block_data_commitments() {
+        let dir = tempdir().unwrap();
+        let config = ChainConfig::default().with_data_limits(1000, 1500);
+        let mut blockchain = Blockchain::new_with_config(dir.path(), config).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+
+        assert!(blockchain.add_pending_transaction(Transaction::new_store_data(vec![1], [1u8; 32], 1001)).is_err());
+        let first = Transaction::new_store_data(vec![1], [2u8; 32], 1000);
+        let second = Transaction::new_store_data(vec![1], [3u8; 32], 600);
+        let third = Transaction::new_store_data(vec![1], [4u8; 32], 500);
+        for tx in [&first, &second, &third] {
+            assert!(blockchain.add_pending_transaction(tx.clone()).unwrap());
+        }
+        // The second one does not fit next to the first; the third does
+        let block = blockchain.mine_new_block().unwrap();
+        assert_eq!(block.transactions, vec![first, third]);
+        blockchain.process_mined_block(block).unwrap();
+        let block = blockchain.mine_new_block().unwrap();
+        assert_eq!(block.transactions, vec![second]);
+        blockchain.process_mined_block(block).unwrap();
+
+        let prev_hash = blockchain.get_last_block_hash().unwrap();
+        let height = blockchain.get_chain_height().unwrap() + 1;
+        let difficulty = consensus::calculate_next_difficulty(height - 1, &blockchain.storage).unwrap();
+        let over_cap = create_test_block(prev_hash, height, difficulty, vec![
+            Transaction::new_store_data(vec![2], [5u8; 32], 1000),
+            Transaction::new_store_data(vec![2], [6u8; 32], 600),
+        ]);
+        match blockchain.add_block(over_cap).unwrap_err() {
+            BlockchainError::Validation(msg) => assert!(msg.contains("above the limit")),
+            e => panic!("Expected Validation error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn blockchain_enforces_storage_deals() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), crate::presets::NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let client_key = SigningKey::from_bytes(&[1u8; 32]);
+        let sign = |tx: &Transaction| SignedTransaction::sign_ed25519(tx.clone(), &client_key);
+        let (client, provider, payload) = (key_address(&client_key), vec![2u8; 21], [5u8; 32]);
+
+        for bad in [
+            Transaction::new_storage_deal(client.clone(), client.clone(), payload, 3, 10),
+            Transaction::new_storage_deal(client.clone(), provider.clone(), payload, 0, 10),
+            Transaction::new_storage_deal(client.clone(), provider.clone(), payload, deals::MAX_DEAL_DURATION + 1, 10),
+            Transaction::new_storage_deal(client.clone(), provider.clone(), payload, 3, 0),
+        ] {
+            assert!(blockchain.add_signed_transaction(sign(&bad)).is_err());
+        }
+
+        // A second deal on the payload with the same provider waits until the first one ends
+        let first = Transaction::new_storage_deal(client.clone(), provider.clone(), payload, 3, 10).with_nonce(0);
+        let other_provider = Transaction::new_storage_deal(client.clone(), vec![3u8; 21], payload, 5, 20).with_nonce(1);
+        let second = Transaction::new_storage_deal(client.clone(), provider.clone(), payload, 5, 20).with_nonce(2);
+        for tx in [&first, &other_provider, &second] {
+            assert!(blockchain.add_signed_transaction(sign(tx)).unwrap());
+        }
+        let block = blockchain.mine_new_block().unwrap();
+        assert_eq!(block.transactions, vec![first.clone(), other_provider]);
+        blockchain.process_mined_block(block).unwrap();
+        let deals = blockchain.get_storage_deals(&payload).unwrap();
+        assert_eq!(deals.len(), 2);
+        assert_eq!(deals[0], StorageDeal {
+            deal_id: first.calculate_hash(), data_hash: payload, client: client.clone(), provider: provider.clone(),
+            price: 10, start_height: 1, end_height: 4,
+        });
+        assert!(matches!(blockchain.add_signed_transaction(sign(&Transaction::new_storage_deal(client.clone(), provider.clone(), payload, 1, 1))),
+                         Err(BlockchainError::Validation(msg)) if msg.contains("already bound")));
+        for _ in 2..4 {
+            let block = blockchain.mine_new_block().unwrap();
+            assert!(block.transactions.is_empty());
+            blockchain.process_mined_block(block).unwrap();
+        }
+        let block = blockchain.mine_new_block().unwrap();
+        assert_eq!(block.transactions, vec![second]);
+        blockchain.process_mined_block(block).unwrap();
+        assert_eq!(blockchain.get_storage_deals(&payload).unwrap().len(), 3);
+
+        // A block breaking the rule is refused, and deals leave the state with their block
+        let prev_hash = blockchain.get_last_block_hash().unwrap();
+        let bits = consensus::calculate_next_difficulty(4, &blockchain.storage).unwrap();
+        let rebound = create_test_block(prev_hash, 5, bits, vec![Transaction::new_storage_deal(client.clone(), provider.clone(), payload, 1, 1)]);
+        assert!(matches!(blockchain.add_block(rebound), Err(BlockchainError::Validation(_))));
+        blockchain.truncate_chain(4).unwrap();
+        assert_eq!(blockchain.get_storage_deals(&payload).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn blockchain_tracks_named_pointers() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), crate::presets::NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let (publisher, key) = (vec![1u8; 21], SigningKey::from_bytes(&[9u8; 32]));
+        let name = key.verifying_key().to_bytes();
+
+        let mut forged = PointerRecord::new([5u8; 32], 1, &key);
+        forged.payload_hash = [6u8; 32];
+        assert!(blockchain.add_pending_transaction(Transaction::new_publish_pointer(publisher.clone(), forged)).is_err());
+
+        // Records published in one block apply in order; the latest one resolves
+        let first = Transaction::new_publish_pointer(publisher.clone(), PointerRecord::new([5u8; 32], 1, &key));
+        let second = Transaction::new_publish_pointer(publisher.clone(), PointerRecord::new([6u8; 32], 2, &key));
+        for tx in [&first, &second] {
+            assert!(blockchain.add_pending_transaction(tx.clone()).unwrap());
+        }
+        assert_eq!(blockchain.pending_pointer_sequence(&name), Some(2));
+        let block = blockchain.mine_new_block().unwrap();
+        assert_eq!(block.transactions, vec![first.clone(), second.clone()]);
+        blockchain.process_mined_block(block).unwrap();
+        let pointer = blockchain.resolve_pointer(&name).unwrap().unwrap();
+        assert_eq!((pointer.record.payload_hash, pointer.record.sequence, pointer.height, pointer.tx_hash), ([6u8; 32], 2, 1, second.calculate_hash()));
+        assert_eq!(blockchain.pending_pointer_sequence(&name), None);
+        assert!(blockchain.resolve_pointer(&[0u8; 32]).unwrap().is_none());
+
+        // Old records can't roll the name back, in the mempool or in a block
+        assert!(matches!(blockchain.add_pending_transaction(first.clone()),
+                         Err(BlockchainError::Validation(msg)) if msg.contains("already at sequence 2")));
+        let prev_hash = blockchain.get_last_block_hash().unwrap();
+        let bits = consensus::calculate_next_difficulty(1, &blockchain.storage).unwrap();
+        let replayed = create_test_block(prev_hash, 2, bits, vec![first]);
+        assert!(matches!(blockchain.add_block(replayed), Err(BlockchainError::Validation(_))));
+
+        // Records leave the state with their block
+        let third = Transaction::new_publish_pointer(publisher, PointerRecord::new([7u8; 32], 3, &key));
+        assert!(blockchain.add_pending_transaction(third).unwrap());
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+        assert_eq!(blockchain.resolve_pointer(&name).unwrap().unwrap().record.sequence, 3);
+        blockchain.truncate_chain(2).unwrap();
+        assert_eq!(blockchain.resolve_pointer(&name).unwrap().unwrap().record.payload_hash, [6u8; 32]);
+    }
+
+    #[test]
+    fn blockchain_pays_miner_address() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let miner = vec![7u8; 21];
+        blockchain.set_miner_address(Some(miner.clone()));
+
+        // A fee is paid from the sender's funds, so it needs the sender's signature
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let tx = Transaction::new_store_data(key_address(&key), [1u8; 32], 10).with_fee(5).with_nonce(0);
+        assert!(matches!(blockchain.add_pending_transaction(tx.clone()), Err(BlockchainError::Validation(_))));
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx.clone(), &key)).unwrap();
+        let block = blockchain.mine_new_block().unwrap();
+        assert_eq!(block.transactions[0], Transaction { timestamp: block.transactions[0].timestamp, ..Transaction::new_coinbase(miner.clone(), 1, consensus::BLOCK_SUBSIDY + 5) });
+        assert_eq!(block.transactions[1], tx);
+        blockchain.process_mined_block(block).unwrap();
+
+        // Coinbases are never pending, and a block may not pay its miner more than subsidy plus fees
+        assert!(blockchain.add_pending_transaction(Transaction::new_coinbase(miner.clone(), 2, 1)).is_err());
+        let prev_hash = blockchain.get_last_block_hash().unwrap();
+        let difficulty = consensus::calculate_next_difficulty(1, &blockchain.storage).unwrap();
+        for transactions in [
+            vec![Transaction::new_coinbase(miner.clone(), 2, consensus::BLOCK_SUBSIDY + 1)],
+            vec![Transaction::new_coinbase(miner.clone(), 3, 1)],
+            vec![Transaction::new_store_data(vec![1], [2u8; 32], 10), Transaction::new_coinbase(miner.clone(), 2, 1)],
+        ] {
+            let block = create_test_block(prev_hash, 2, difficulty, transactions);
+            assert!(matches!(blockchain.add_block(block), Err(BlockchainError::Validation(_))));
+        }
+
+        // Without a miner address the block has no coinbase
+        blockchain.set_miner_address(None);
+        assert!(blockchain.mine_new_block().unwrap().transactions.is_empty());
+    }
+
+    #[test]
+    fn blockchain_enforces_sender_nonces() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let sender = key_address(&key);
+        let signed_block = |blockchain: &Blockchain, transactions: Vec<Transaction>| {
+            let tip = blockchain.get_chain_height().unwrap();
+            let bits = consensus::calculate_next_difficulty(tip, &blockchain.storage).unwrap();
+            let witnesses = transactions.iter().map(|tx| Some(SignedTransaction::sign_ed25519(tx.clone(), &key).into_parts().1)).collect();
+            let mut block = create_test_block(blockchain.get_last_block_hash().unwrap(), tip + 1, bits, transactions);
+            block.set_witnesses(witnesses);
+            consensus::mine(&mut block.header, bits);
+            block
+        };
+
+        // A signed transaction without a nonce could be confirmed again
+        let unnumbered = Transaction::new_transfer_native(sender.clone(), vec![2], 5);
+        assert!(matches!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(unnumbered.clone(), &key)), Err(BlockchainError::Validation(_))));
+        assert!(matches!(blockchain.add_block(signed_block(&blockchain, vec![unnumbered])), Err(BlockchainError::Validation(_))));
+
+        // Nonces can't be skipped
+        let first = Transaction::new_transfer_native(sender.clone(), vec![2], 5).with_nonce(0);
+        let second = Transaction::new_transfer_native(sender.clone(), vec![3], 5).with_nonce(1);
+        assert!(matches!(blockchain.add_block(signed_block(&blockchain, vec![second.clone()])), Err(BlockchainError::Validation(_))));
+        blockchain.add_block(signed_block(&blockchain, vec![first.clone()])).unwrap();
+        assert_eq!(blockchain.next_nonce(&sender).unwrap(), 1);
+
+        // Nor reused: a confirmed transaction is not replayed, and of a transaction and its replacement only one confirms
+        assert!(matches!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(first.clone(), &key)), Err(BlockchainError::Validation(_))));
+        assert!(matches!(blockchain.add_block(signed_block(&blockchain, vec![first.clone()])), Err(BlockchainError::Validation(_))));
+        let replacement = Transaction::new_transfer_native(sender.clone(), vec![4], 5).with_nonce(1);
+        assert!(matches!(blockchain.add_block(signed_block(&blockchain, vec![second.clone(), replacement.clone()])), Err(BlockchainError::Validation(_))));
+        assert!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(second.clone(), &key)).unwrap());
+        assert_eq!(blockchain.next_nonce(&sender).unwrap(), 2);
+        blockchain.add_block(signed_block(&blockchain, vec![replacement])).unwrap();
+        assert!(blockchain.mine_new_block().unwrap().transactions.is_empty());
+
+        // Nonces leave the state with their block
+        blockchain.truncate_chain(2).unwrap();
+        assert_eq!(blockchain.storage.get_next_nonce(&sender).unwrap(), 1);
+        blockchain.truncate_chain(1).unwrap();
+        assert_eq!(blockchain.storage.get_next_nonce(&sender).unwrap(), 0);
+    }
+
+    #[test]
+    fn blockchain_pos_forge_stake_and_slash() {
+        let dir = tempdir().unwrap();
+        let validator = SigningKey::from_bytes(&[5u8; 32]);
+        let validator_address = validator.verifying_key().to_bytes().to_vec();
+        let stakes = std::collections::BTreeMap::from([(validator_address.clone(), 1000)]);
+        let mut blockchain = Blockchain::new_with_config(dir.path(), ChainConfig::proof_of_stake(stakes)).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        assert!(blockchain.mine_new_block().is_err());
+
+        // The only validator is always selected, and its blocks pay it the subsidy
+        blockchain.set_miner_address(Some(validator_address.clone()));
+        let stake = Transaction::new_stake(validator_address.clone(), 50).with_nonce(0);
+        // Staking needs the staking key's signature and funds to stake
+        let intruder = SigningKey::from_bytes(&[6u8; 32]);
+        assert!(blockchain.add_pending_transaction(stake.clone()).is_err());
+        assert!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(stake.clone(), &validator)).is_err());
+        let block1 = blockchain.forge_block(&validator).unwrap();
+        blockchain.process_mined_block(block1.clone()).unwrap();
+        assert_eq!(blockchain.get_native_balance(&validator_address).unwrap(), consensus::BLOCK_SUBSIDY);
+        assert!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(stake.clone(), &intruder)).is_err());
+
+        // Topping up its own stake keeps it the only one; the stake leaves its balance
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(stake, &validator)).unwrap();
+        let block2 = blockchain.forge_block(&validator).unwrap();
+        blockchain.process_mined_block(block2.clone()).unwrap();
+        assert_eq!(blockchain.storage.get_stake(&validator_address).unwrap(), 1050);
+        assert_eq!(blockchain.get_native_balance(&validator_address).unwrap(), 2 * consensus::BLOCK_SUBSIDY - 50);
+
+        // A block sealed by someone else is rejected
+        let mut forged = Block::new(block2.hash(), vec![], 0, 3);
+        forged.seal = Some(BlockSeal::sign(&forged.header, &intruder));
+        assert!(matches!(blockchain.add_block(forged), Err(BlockchainError::Consensus(_))));
+
+        // Two conflicting headers at height 3 sealed by the validator get it slashed
+        let header_a = Block::new(block2.hash(), vec![], 0, 3).header;
+        let mut header_b = header_a.clone();
+        header_b.nonce = 1;
+        let evidence = DoubleSignEvidence {
+            seal_a: BlockSeal::sign(&header_a, &validator),
+            seal_b: BlockSeal::sign(&header_b, &validator),
+            header_a,
+            header_b,
+        };
+        blockchain.add_pending_transaction(Transaction::new_slash_double_sign(vec![9], evidence)).unwrap();
+        let block3 = blockchain.forge_block(&validator).unwrap();
+        blockchain.process_mined_block(block3).unwrap();
+        assert_eq!(blockchain.storage.get_stake(&validator_address).unwrap(), 0);
+    }
+
+    #[test]
+    fn blockchain_uses_recorded_pow_algorithm() {
+        let dir = tempdir().unwrap();
+        {
+            let config = ChainConfig::with_pow_algorithm(consensus::PowAlgorithm::Blake3);
+            let mut blockchain = Blockchain::new_with_config(dir.path(), config).unwrap();
+            blockchain.initialize_genesis_if_needed().unwrap();
+            let block = blockchain.mine_new_block().unwrap();
+            assert!(consensus::verify_header_pow(&block.header, consensus::PowAlgorithm::Blake3));
+            blockchain.process_mined_block(block).unwrap();
+        }
+
+        // Reopening with a different requested algorithm keeps the recorded one
+        let blockchain = Blockchain::new_with_config(dir.path(), ChainConfig::default()).unwrap();
+        assert_eq!(blockchain.chain_config().pow_algorithm, consensus::PowAlgorithm::Blake3);
+        assert_eq!(blockchain.get_chain_height(), Some(1));
+    }
+
+    #[test]
+    fn blockchain_enforces_checkpoints() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let genesis_hash = blockchain.get_last_block_hash().unwrap();
+        let bits = consensus::calculate_next_difficulty(0, &blockchain.storage).unwrap();
+
+        // The stored chain must agree with the checkpoints being installed
+        let wrong = Checkpoints::new(std::collections::BTreeMap::from([(0, [7u8; 32])]));
+        assert!(blockchain.set_checkpoints(wrong).is_err());
+
+        // An unmined block pinned by a checkpoint is accepted without checking its PoW
+        let unmined = Block::new(genesis_hash, vec![], bits, 1);
+        let pinned = std::collections::BTreeMap::from([(0, genesis_hash), (1, unmined.hash()), (3, [8u8; 32])]);
+        blockchain.set_checkpoints(Checkpoints::new(pinned)).unwrap();
+        let other = create_test_block(genesis_hash, 1, bits, vec![Transaction::new_transfer_native(vec![1], vec![2], 3)]);
+        match blockchain.add_block(other).unwrap_err() {
+            BlockchainError::Validation(msg) => assert!(msg.contains("checkpoint")),
+            e => panic!("Expected Validation error, got {:?}", e),
+        }
+        blockchain.add_block(unmined.clone()).unwrap();
+
+        // Below a checkpoint, a block that is not itself pinned still needs its PoW
+        let mut unmined2 = Block::new(unmined.hash(), vec![], bits, 2);
+        unmined2.header.timestamp += 2;
+        while consensus::verify_header_pow(&unmined2.header, consensus::PowAlgorithm::default()) {
+            unmined2.header.nonce += 1;
+        }
+        assert!(matches!(blockchain.add_block(unmined2), Err(BlockchainError::Consensus(_))));
+        let block2 = create_test_block(unmined.hash(), 2, bits, vec![]);
+        blockchain.add_block(block2.clone()).unwrap();
+
+        // A block conflicting with the checkpoint at height 3 is rejected even with valid PoW
+        let block3 = create_test_block(block2.hash(), 3, bits, vec![]);
+        assert!(matches!(blockchain.add_block(block3), Err(BlockchainError::Validation(_))));
+    }
+
+    #[test]
+    fn transaction_round_trips_canonical_bytes() {
+        let tx = Transaction::new_store_data(vec![4, 2], [9u8; 32], 1024);
+        let bytes = tx.to_bytes();
+        assert_eq!(Transaction::from_bytes(&bytes).unwrap(), tx);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Transaction::from_bytes(&trailing).is_err());
+        assert!(Transaction::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let block = Block::new([1u8; 32], vec![tx], 0x207fffff, 1);
+        let bytes = block.to_bytes();
+        assert_eq!(Block::from_bytes(&bytes).unwrap(), block);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Block::from_bytes(&trailing).is_err());
+    }
+
+    #[test]
+    fn signed_transaction_verifies_its_sender() {
+        let key = SigningKey::from_bytes(&[8u8; 32]);
+        let tx = Transaction::new_transfer_native(key_address(&key), vec![9; 21], 5).with_fee(1);
+        let signed = SignedTransaction::sign_ed25519(tx, &key);
+        let decoded = SignedTransaction::from_bytes(&signed.to_bytes()).unwrap();
+        assert_eq!(decoded, signed);
+        assert!(decoded.verify().is_ok());
+
+        // Changing any field invalidates the signature
+        let mut tampered = signed.clone();
+        tampered.transaction = tampered.transaction.with_fee(2);
+        assert!(matches!(tampered.verify(), Err(address::AddressError::InvalidSignature(_))));
+        // A key that does not hash to the sender address is refused
+        let mut impostor = signed.clone();
+        impostor.public_key = SigningKey::from_bytes(&[6u8; 32]).verifying_key().to_bytes().to_vec();
+        assert_eq!(impostor.verify(), Err(address::AddressError::KeyMismatch));
+    }
+
+    #[test]
+    fn blockchain_imports_checkpointed_blocks_in_batches() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let genesis_hash = blockchain.get_last_block_hash().unwrap();
+        let bits = consensus::calculate_next_difficulty(0, &blockchain.storage).unwrap();
+
+        // Blocks 1-3 with target `bits` and no valid PoW, and checkpoints pinning block 3
+        let unmined_chain = |bits: u32| {
+            let mut blocks: Vec<Block> = Vec::new();
+            let mut previous_hash = genesis_hash;
+            for height in 1..=3u64 {
+                let mut block = Block::new(previous_hash, vec![Transaction::new_store_data(vec![height as u8], [height as u8; 32], height)], bits, height);
+                while consensus::verify_header_pow(&block.header, consensus::PowAlgorithm::default()) {
+                    block.header.nonce += 1;
+                }
+                previous_hash = block.hash();
+                blocks.push(block);
+            }
+            let pinned = std::collections::BTreeMap::from([(0, genesis_hash), (3, previous_hash)]);
+            (blocks, Checkpoints::new(pinned))
+        };
+
+        // Blocks must carry the expected target even though their PoW is skipped
+        let (wrong_bits, checkpoints) = unmined_chain(0x1f0f_ffff);
+        blockchain.set_checkpoints(checkpoints).unwrap();
+        assert!(matches!(blockchain.import_blocks(wrong_bits), Err(BlockchainError::Consensus(_))));
+        assert_eq!(blockchain.get_chain_height(), Some(0));
+
+        let (mut blocks, checkpoints) = unmined_chain(bits);
+        let pinned_hash = blocks[2].hash();
+        blockchain.set_checkpoints(checkpoints).unwrap();
+        // A run that fails is discarded, including the blocks buffered before the failure
+        let mut broken = blocks.clone();
+        broken.swap(1, 2);
+        assert!(matches!(blockchain.import_blocks(broken), Err(BlockchainError::Validation(_))));
+        assert_eq!(blockchain.get_chain_height(), Some(0));
+        // Blocks that never reach the checkpoint are fully validated, PoW included
+        assert!(matches!(blockchain.import_blocks(blocks[..2].to_vec()), Err(BlockchainError::Consensus(_))));
+        assert_eq!(blockchain.get_chain_height(), Some(0));
+
+        blocks.push(create_test_block(pinned_hash, 4, bits, vec![]));
+        assert_eq!(blockchain.import_blocks(blocks).unwrap(), 4);
+        assert_eq!(blockchain.get_chain_height(), Some(4));
+        assert_eq!(blockchain.storage.get_hash_by_height(3).unwrap(), Some(pinned_hash));
+        assert_eq!(blockchain.get_tip_chain_work().unwrap(), consensus::block_work(consensus::POW_LIMIT_BITS) + consensus::block_work(bits) * U256::from(4));
+    }
+
+    #[test]
+    fn blockchain_tracks_finalized_height() {
+        let dir = tempdir().unwrap();
+        {
+            let config = ChainConfig::default().with_finality_depth(2);
+            let mut blockchain = Blockchain::new_with_config(dir.path(), config).unwrap();
+            blockchain.initialize_genesis_if_needed().unwrap();
+            for _ in 0..3 {
+                let block = blockchain.mine_new_block().unwrap();
+                blockchain.process_mined_block(block).unwrap();
+            }
+            assert_eq!(blockchain.get_finalized_height(), Some(1));
+
+            // A fork from genesis would revert finalized block 1; a fork from height 2 would not
+            assert!(blockchain.check_reorg_depth(0).is_err());
+            assert!(blockchain.check_reorg_depth(1).is_ok());
+            let genesis_hash = blockchain.get_block_by_height(0).unwrap().unwrap().hash();
+            let competing = create_test_block(genesis_hash, 1, consensus::POW_LIMIT_BITS, vec![]);
+            match blockchain.add_block(competing).unwrap_err() {
+                BlockchainError::Validation(msg) => assert!(msg.contains("finalized")),
+                e => panic!("Expected Validation error, got {:?}", e),
+            }
+        }
+
+        // The finalized height survives a restart
+        let blockchain = Blockchain::new(dir.path()).unwrap();
+        assert_eq!(blockchain.get_finalized_height(), Some(1));
+    }
+
+    #[test]
+    fn blockchain_reorganizes_onto_heavier_fork() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let genesis_hash = blockchain.get_last_block_hash().unwrap();
+        let genesis_work = blockchain.get_tip_chain_work().unwrap();
+
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let tx = Transaction::new_transfer_native(key_address(&key), vec![2], 5).with_nonce(0);
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx.clone(), &key)).unwrap();
+        let main1 = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(main1.clone()).unwrap();
+        assert!(blockchain.mempool.is_empty());
+
+        // Equal work: the first block seen stays the tip
+        let bits = main1.header.bits;
+        let fork1 = create_test_block(genesis_hash, 1, bits, vec![]);
+        blockchain.add_block(fork1.clone()).unwrap();
+        assert_eq!(blockchain.get_last_block_hash(), Some(main1.hash()));
+        assert_eq!(blockchain.get_chain_work(&fork1.hash()).unwrap(), blockchain.get_tip_chain_work().unwrap());
+
+        // The fork pulls ahead in work and becomes the main chain
+        let bits = consensus::calculate_next_difficulty(1, &blockchain.storage).unwrap();
+        let fork2 = create_test_block(fork1.hash(), 2, bits, vec![]);
+        blockchain.add_block(fork2.clone()).unwrap();
+        assert_eq!(blockchain.get_chain_height(), Some(2));
+        assert_eq!(blockchain.get_last_block_hash(), Some(fork2.hash()));
+        assert_eq!(blockchain.get_block_by_height(1).unwrap().unwrap().hash(), fork1.hash());
+        assert_eq!(
+            blockchain.get_tip_chain_work().unwrap(),
+            genesis_work + consensus::block_work(fork1.header.bits) + consensus::block_work(fork2.header.bits)
+        );
+        // The transaction confirmed only on the abandoned block is pending again
+        assert_eq!(blockchain.mempool.get_transactions(10), vec![tx]);
+    }
+
+    #[test]
+    fn blockchain_marks_failed_branches() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let genesis_hash = blockchain.get_last_block_hash().unwrap();
+        // The coinbase keeps the main block distinct from the empty fork block
+        blockchain.set_miner_address(Some(vec![1]));
+        let main1 = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(main1.clone()).unwrap();
+        let fork1 = create_test_block(genesis_hash, 1, main1.header.bits, vec![]);
+        blockchain.add_block(fork1.clone()).unwrap();
+
+        // The heavier branch overpays its coinbase, so the reorganization fails
+        let bits = consensus::calculate_next_difficulty(1, &blockchain.storage).unwrap();
+        let overpaid = Transaction::new_coinbase(vec![9], 2, consensus::BLOCK_SUBSIDY + 1);
+        let fork2 = create_test_block(fork1.hash(), 2, bits, vec![overpaid]);
+        assert!(matches!(blockchain.add_block(fork2.clone()), Err(BlockchainError::Validation(_))));
+        assert_eq!(blockchain.get_last_block_hash(), Some(main1.hash()));
+        assert!(blockchain.storage.is_block_failed(&fork2.hash()).unwrap());
+        assert!(!blockchain.storage.is_block_failed(&fork1.hash()).unwrap());
+
+        // Its descendants are refused without another reorganization
+        let fork3 = create_test_block(fork2.hash(), 3, bits, vec![]);
+        match blockchain.add_block(fork3.clone()) {
+            Err(BlockchainError::Validation(message)) => assert!(message.contains("failed validation"), "{}", message),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        assert!(blockchain.storage.is_block_failed(&fork3.hash()).unwrap());
+        assert_eq!(blockchain.get_chain_height(), Some(1));
+
+        // A valid sibling of the failed block still wins
+        let fork2b = create_test_block(fork1.hash(), 2, bits, vec![]);
+        blockchain.add_block(fork2b.clone()).unwrap();
+        assert_eq!(blockchain.get_last_block_hash(), Some(fork2b.hash()));
+    }
+
+    #[test]
+    fn blockchain_get_transaction_with_and_without_index() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let tx = Transaction::new_transfer_native(key_address(&key), vec![2], 8).with_nonce(0);
+        let tx_hash = tx.calculate_hash();
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx.clone(), &key)).unwrap();
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block.clone()).unwrap();
+
+        // Without the index the chain is scanned
+        let scanned = blockchain.get_transaction(&tx_hash).unwrap().unwrap();
+        assert_eq!(scanned, ConfirmedTransaction { transaction: tx, block_hash: block.hash(), height: 1, position: 0 });
+
+        // Enabling the index on an existing chain builds it
+        blockchain.set_tx_index(true).unwrap();
+        assert_eq!(blockchain.get_transaction(&tx_hash).unwrap(), Some(scanned));
+        assert!(blockchain.get_transaction(&[9u8; 32]).unwrap().is_none());
     }
-}
 
+    #[test]
+    fn blockchain_snapshot_token_holders() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+
+        let creator_key = SigningKey::from_bytes(&[1u8; 32]);
+        let creator = key_address(&creator_key);
+        let create = Transaction::new_create_token(creator.clone(), "Drop".to_string(), "DRP".to_string(), 1000);
+        let token_id = match &create.transaction_type {
+            TransactionType::CreateToken { metadata } => metadata.metadata_hash,
+            _ => unreachable!(),
+        };
+        blockchain.add_pending_transaction(create).unwrap();
+        let block1 = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block1).unwrap();
+        let transfer = Transaction::new_transfer_token(creator.clone(), vec![2], token_id, 300).with_nonce(0);
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(transfer, &creator_key)).unwrap();
+        let block2 = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block2).unwrap();
+
+        // The past state is reproduced, not the current one
+        let at_1 = blockchain.snapshot_token_holders(&token_id, 1).unwrap();
+        assert_eq!(at_1.holders, vec![TokenHolder { address: creator, balance: 1000 }]);
+        let at_2 = blockchain.snapshot_token_holders(&token_id, 2).unwrap();
+        assert_eq!(at_2.holders.len(), 2);
+        assert_eq!(at_2.total_balance(), 1000);
+        assert!(at_2.proof(1).unwrap().verify(&at_2.holders[1], &at_2.merkle_root));
+        assert!(blockchain.snapshot_token_holders(&token_id, 3).is_err());
+    }
+
+    #[test]
+    fn blockchain_lwma_retargets_every_block() {
+        let dir = tempdir().unwrap();
+        let algorithm = consensus::DifficultyAlgorithm::Lwma { window: 2 };
+        let config = ChainConfig::default().with_difficulty_algorithm(algorithm);
+        let mut blockchain = Blockchain::new_with_config(dir.path(), config).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        for _ in 0..3 {
+            let block = blockchain.mine_new_block().unwrap();
+            blockchain.process_mined_block(block).unwrap();
+        }
+
+        // Blocks within seconds of each other: LWMA demands a harder target than the interval rule
+        let height = blockchain.get_chain_height().unwrap();
+        let lwma_bits = consensus::calculate_next_difficulty_with_algorithm(height, &blockchain.storage, algorithm, consensus::DifficultyBounds::default()).unwrap();
+        let interval_bits = consensus::calculate_next_difficulty(height, &blockchain.storage).unwrap();
+        assert!(consensus::compact_to_target(lwma_bits).unwrap() < consensus::compact_to_target(interval_bits).unwrap());
+
+        let stale_target = create_test_block(blockchain.current_tip_hash.unwrap(), height + 1, interval_bits, vec![]);
+        assert!(matches!(blockchain.add_block(stale_target), Err(BlockchainError::Consensus(_))));
+        let block = blockchain.mine_new_block().unwrap();
+        assert_eq!(block.header.bits, lwma_bits);
+        blockchain.process_mined_block(block).unwrap();
+    }
+}
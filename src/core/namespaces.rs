@@ -0,0 +1,34 @@
+//!
+//! # Payload Namespaces
+//! Optional names a `StoreData` transaction files its payload under, so an application can
+//! group its payloads (for example one namespace per tenant or per bucket) and list them back.
+//!
+//! A namespace is 1 to `MAX_NAMESPACE_LEN` bytes of lowercase ASCII letters, digits, `-`, `_`,
+//! `.` and `/`. Namespaces are not owned: any sender can store under any of them. The chain
+//! indexer keeps the payloads of each namespace and their usage (see
+//! [`ChainIndexer::namespace_payloads`](crate::indexer::ChainIndexer::namespace_payloads)).
+
+use super::{Transaction, TransactionType};
+
+/// Longest namespace, in bytes.
+pub const MAX_NAMESPACE_LEN: usize = 64;
+
+/// Checks `namespace` follows the rules in the module documentation.
+pub fn validate_namespace(namespace: &str) -> Result<(), String> {
+    if namespace.is_empty() || namespace.len() > MAX_NAMESPACE_LEN {
+        return Err(format!("Namespace must be 1 to {} bytes long", MAX_NAMESPACE_LEN));
+    }
+    let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.' | '/');
+    if !namespace.chars().all(allowed) {
+        return Err(format!("Namespace {:?} may only hold lowercase letters, digits, '-', '_', '.' and '/'", namespace));
+    }
+    Ok(())
+}
+
+/// Checks the namespace of a `StoreData` transaction, if it names one.
+pub fn check_namespace(tx: &Transaction) -> Result<(), String> {
+    match tx.transaction_type() {
+        TransactionType::StoreData { namespace: Some(namespace), .. } => validate_namespace(namespace),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,78 @@
+//!
+//! # Named Pointers
+//! Mutable names for payloads. A payload hash names one version of a payload; a pointer names
+//! whichever payload its owner published last, so clients can follow the latest version.
+//!
+//! A pointer's name is an ed25519 public key. Its owner signs a [`PointerRecord`] binding the
+//! name to a payload hash under a sequence number and publishes it in a `PublishPointer`
+//! transaction. Any address can send and pay for the transaction: the record's signature alone
+//! authorizes the update. A record is only valid with a sequence number above the one the name
+//! holds on the main chain, so an old record can't be replayed to roll the name back. Published
+//! records are kept in the chain state, by name, and follow reorganizations (see
+//! [`StorageManager::resolve_pointer`](crate::storage::StorageManager::resolve_pointer)).
+//! Publishing a pointer does not anchor its payload, which still needs its own `StoreData`.
+
+use super::{Hash, Transaction, TransactionType};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+// Domain separator, so a pointer signature can't be replayed as another kind of signature
+const POINTER_SIGNING_TAG: &[u8] = b"named-pointer-v1";
+
+/// A name bound to a payload hash, signed by the name's key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PointerRecord {
+    /// Public key of the name.
+    pub name: [u8; 32],
+    pub payload_hash: Hash,
+    /// Must grow with each record published for the name.
+    pub sequence: u64,
+    pub signature: Vec<u8>,
+}
+
+impl PointerRecord {
+    /// Creates the record binding the name of `key` to `payload_hash`, signed by `key`.
+    pub fn new(payload_hash: Hash, sequence: u64, key: &SigningKey) -> Self {
+        let mut record = PointerRecord { name: key.verifying_key().to_bytes(), payload_hash, sequence, signature: Vec::new() };
+        record.signature = key.sign(&record.signing_digest()).to_bytes().to_vec();
+        record
+    }
+
+    /// Digest the name's key signs: SHA-256 of the tag `named-pointer-v1`, the name, the payload
+    /// hash and the big-endian sequence number.
+    pub fn signing_digest(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(POINTER_SIGNING_TAG);
+        hasher.update(self.name);
+        hasher.update(self.payload_hash);
+        hasher.update(self.sequence.to_be_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Checks the signature against the name.
+    pub fn verify(&self) -> bool {
+        match (VerifyingKey::from_bytes(&self.name), Signature::from_slice(&self.signature)) {
+            (Ok(key), Ok(signature)) => key.verify(&self.signing_digest(), &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// A pointer record confirmed on the main chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PublishedPointer {
+    pub record: PointerRecord,
+    /// Height of the block that published it.
+    pub height: u64,
+    /// Hash of the `PublishPointer` transaction.
+    pub tx_hash: Hash,
+}
+
+impl PublishedPointer {
+    /// The pointer `tx` publishes when confirmed at `height`; `None` for other transaction kinds.
+    pub fn from_transaction(tx: &Transaction, height: u64) -> Option<Self> {
+        let TransactionType::PublishPointer { record } = tx.transaction_type() else { return None };
+        Some(PublishedPointer { record: record.clone(), height, tx_hash: tx.calculate_hash() })
+    }
+}
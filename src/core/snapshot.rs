@@ -0,0 +1,196 @@
+//!
+//! # Token Holder Snapshots
+//! Balances of one token at a past height, for airdrops and governance votes.
+//!
+//! A snapshot lists every holder with a non-zero balance, sorted by address, and
+//! commits to the list with a Merkle root. Publishing the root on-chain (e.g. as the
+//! data hash of a `StoreData` transaction) lets each holder later prove their entry
+//! with a `MerkleProof` without trusting whoever distributed the list.
+
+use super::{Address, Hash};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+/// Domain separators so a leaf can never be mistaken for an inner node.
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+
+/// One holder's balance in a snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenHolder {
+    #[serde(with = "hex_bytes")]
+    pub address: Address,
+    pub balance: u64,
+}
+
+impl TokenHolder {
+    /// Merkle leaf committing to this entry.
+    pub fn leaf_hash(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update([LEAF_TAG]);
+        hasher.update((self.address.len() as u32).to_be_bytes());
+        hasher.update(&self.address);
+        hasher.update(self.balance.to_be_bytes());
+        hasher.finalize().into()
+    }
+}
+
+/// Token balances of all holders as of the block at `height`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenHolderSnapshot {
+    #[serde(with = "hex_array")]
+    pub token_id: Hash,
+    pub height: u64,
+    #[serde(with = "hex_array")]
+    pub block_hash: Hash,
+    /// Holders with a non-zero balance, sorted by address.
+    pub holders: Vec<TokenHolder>,
+    /// Merkle root over the holders' leaf hashes (all zeros when there are no holders).
+    #[serde(with = "hex_array")]
+    pub merkle_root: Hash,
+}
+
+/// Sibling hashes from a leaf up to the root. `true` means the sibling is on the left.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub siblings: Vec<(bool, Hash)>,
+}
+
+fn hash_nodes(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_TAG]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Hashes one tree level into the next. An odd last node is carried up unchanged.
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level.chunks(2)
+        .map(|pair| if pair.len() == 2 { hash_nodes(&pair[0], &pair[1]) } else { pair[0] })
+        .collect()
+}
+
+impl TokenHolderSnapshot {
+    /// Builds a snapshot from raw balances, dropping zero balances and computing the root.
+    pub fn new(token_id: Hash, height: u64, block_hash: Hash, balances: impl IntoIterator<Item = (Address, u64)>) -> Self {
+        let mut holders: Vec<TokenHolder> = balances.into_iter()
+            .filter(|(_, balance)| *balance > 0)
+            .map(|(address, balance)| TokenHolder { address, balance })
+            .collect();
+        holders.sort_by(|a, b| a.address.cmp(&b.address));
+        let mut snapshot = TokenHolderSnapshot { token_id, height, block_hash, holders, merkle_root: [0u8; 32] };
+        snapshot.merkle_root = snapshot.compute_merkle_root();
+        snapshot
+    }
+
+    fn compute_merkle_root(&self) -> Hash {
+        let mut level: Vec<Hash> = self.holders.iter().map(TokenHolder::leaf_hash).collect();
+        if level.is_empty() {
+            return [0u8; 32];
+        }
+        while level.len() > 1 {
+            level = next_level(&level);
+        }
+        level[0]
+    }
+
+    /// Sum of all balances in the snapshot.
+    pub fn total_balance(&self) -> u64 {
+        self.holders.iter().fold(0u64, |total, holder| total.saturating_add(holder.balance))
+    }
+
+    /// Merkle proof for the holder at `index` in `holders`.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.holders.len() {
+            return None;
+        }
+        let mut level: Vec<Hash> = self.holders.iter().map(TokenHolder::leaf_hash).collect();
+        let mut position = index;
+        let mut siblings = Vec::new();
+        while level.len() > 1 {
+            let sibling = position ^ 1;
+            if sibling < level.len() {
+                siblings.push((sibling < position, level[sibling]));
+            }
+            level = next_level(&level);
+            position /= 2;
+        }
+        Some(MerkleProof { siblings })
+    }
+
+    /// Renders the snapshot as CSV (`address,balance`, hex addresses) preceded by comment lines
+    /// recording the token, height, block hash and Merkle root.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!(
+            "# token_id={}\n# height={}\n# block_hash={}\n# merkle_root={}\naddress,balance\n",
+            hex::encode(self.token_id), self.height, hex::encode(self.block_hash), hex::encode(self.merkle_root)
+        );
+        for holder in &self.holders {
+            csv.push_str(&format!("{},{}\n", hex::encode(&holder.address), holder.balance));
+        }
+        csv
+    }
+}
+
+impl MerkleProof {
+    /// Checks that `holder` is committed to by `merkle_root`.
+    pub fn verify(&self, holder: &TokenHolder, merkle_root: &Hash) -> bool {
+        let computed = self.siblings.iter().fold(holder.leaf_hash(), |node, (sibling_is_left, sibling)| {
+            if *sibling_is_left { hash_nodes(sibling, &node) } else { hash_nodes(&node, sibling) }
+        });
+        &computed == merkle_root
+    }
+}
+
+pub(crate) mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex_str = String::deserialize(deserializer)?;
+        hex::decode(hex_str).map_err(serde::de::Error::custom)
+    }
+}
+
+pub(crate) mod hex_array {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        super::hex_bytes::serialize(bytes, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let bytes = super::hex_bytes::deserialize(deserializer)?;
+        bytes.as_slice().try_into().map_err(|_| serde::de::Error::custom("expected 32 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_merkle_proofs() {
+        let balances = (1..=5u8).map(|i| (vec![i], i as u64 * 10)).chain(std::iter::once((vec![9], 0)));
+        let snapshot = TokenHolderSnapshot::new([1u8; 32], 7, [2u8; 32], balances);
+        assert_eq!(snapshot.holders.len(), 5);
+        assert_eq!(snapshot.total_balance(), 150);
+
+        for (index, holder) in snapshot.holders.iter().enumerate() {
+            assert!(snapshot.proof(index).unwrap().verify(holder, &snapshot.merkle_root));
+        }
+        let forged = TokenHolder { address: vec![1], balance: 1_000 };
+        assert!(!snapshot.proof(0).unwrap().verify(&forged, &snapshot.merkle_root));
+        assert!(snapshot.proof(5).is_none());
+
+        let csv = snapshot.to_csv();
+        assert!(csv.contains(&format!("# merkle_root={}", hex::encode(snapshot.merkle_root))));
+        assert!(csv.ends_with("05,50\n"));
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<TokenHolderSnapshot>(&json).unwrap(), snapshot);
+    }
+}
@@ -0,0 +1,61 @@
+//!
+//! # Time Source
+//! Wall-clock UNIX time that never goes backwards within a running node.
+//!
+//! NTP corrections or a manually reset clock can move the system time backwards.
+//! Instead of panicking (as `duration_since(UNIX_EPOCH).expect(..)` does for times
+//! before the epoch) or producing timestamps older than ones already handed out,
+//! `unix_now` holds at the highest value returned so far until the clock catches up,
+//! logging a warning at most once a minute meanwhile.
+
+use log::warn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Highest timestamp returned by `unix_now` in this process.
+static LAST_TIMESTAMP: AtomicU64 = AtomicU64::new(0);
+/// System time of the last clock regression warning.
+static LAST_REGRESSION_WARNING: AtomicU64 = AtomicU64::new(0);
+/// A clock stuck behind the timestamps already handed out warns at most this often, in seconds.
+const REGRESSION_WARNING_INTERVAL_SECS: u64 = 60;
+
+/// Current UNIX time in seconds, as reported by the system clock (0 if it is before the epoch).
+pub fn system_unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Current UNIX time in seconds, clamped so it never decreases between calls.
+pub fn unix_now() -> u64 {
+    clamp_monotonic(&LAST_TIMESTAMP, system_unix_time())
+}
+
+/// Records `wall` in `last` and returns it, or the timestamp in `last` if `wall` is older.
+fn clamp_monotonic(last: &AtomicU64, wall: u64) -> u64 {
+    let last = last.fetch_max(wall, Ordering::SeqCst);
+    if wall < last {
+        let warned = LAST_REGRESSION_WARNING.load(Ordering::Relaxed);
+        let due = wall.abs_diff(warned) >= REGRESSION_WARNING_INTERVAL_SECS;
+        if due && LAST_REGRESSION_WARNING.compare_exchange(warned, wall, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            warn!("System clock went backwards by {}s; holding timestamps at {}", last - wall, last);
+        }
+        last
+    } else {
+        wall
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_regression_is_clamped() {
+        let last = AtomicU64::new(0);
+        assert_eq!(clamp_monotonic(&last, 1000), 1000);
+        // The clock jumps back: keep returning the highest timestamp seen
+        assert_eq!(clamp_monotonic(&last, 900), 1000);
+        assert_eq!(clamp_monotonic(&last, 0), 1000);
+        assert_eq!(clamp_monotonic(&last, 1001), 1001);
+        assert_eq!(last.load(Ordering::SeqCst), 1001);
+    }
+}
@@ -0,0 +1,640 @@
+//!
+//! # Chain Indexer
+//! Derived tables for explorer queries, kept in a database of their own (`<data-dir>/index`)
+//! so they never touch consensus storage and can be thrown away and rebuilt at any time.
+//!
+//! The indexer follows the main chain block by block and maintains:
+//! - native balances (transfers, fees, stakes and coinbases), ranked from the richest
+//!   address down, and the number of addresses holding a balance,
+//! - the transactions sending from or to each address,
+//! - the holders of each token and their balances,
+//! - the payload registry: the first `StoreData` transaction anchoring each payload hash,
+//! - the payloads filed under each namespace (see [`crate::core::namespaces`]), with the
+//!   number and declared bytes of the payloads in it.
+//!
+//! Every indexed block gets an undo record holding the previous value of each entry it
+//! changed. When the chain reorganizes, blocks no longer on the main chain are rolled back
+//! from their undo records before the new branch is applied; undo records of finalized
+//! blocks are dropped, since those can't be reverted anymore. If the index is lost or
+//! inconsistent, the `reindex` command rebuilds it from the stored chain.
+
+use crate::core::{Address, Block, Blockchain, BlockchainError, Hash, TransactionType};
+use crate::service::Shutdown;
+use crate::storage::backend::{self, ChainStore, Direction, WriteBatch};
+use crate::storage::{AddressTxRef, StorageError};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Subdirectory of the data directory holding the index database.
+pub const INDEX_DIR: &str = "index";
+/// Layout version of the index; an index of another version has to be rebuilt.
+pub const INDEX_VERSION: u32 = 3;
+/// Blocks indexed per hold of the blockchain lock, so block production isn't stalled during catch-up.
+pub const INDEX_BATCH_BLOCKS: usize = 100;
+/// Most addresses one rich list query returns.
+pub const MAX_TOP_HOLDERS: usize = 1000;
+/// How often the indexer checks for a new tip.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const CF_BALANCES: &str = "balances"; // Key: address => Value: native balance (u64 BE)
+const CF_RICH_LIST: &str = "rich_list"; // Key: !balance (u64 BE, richest first) + address => Value: empty
+const CF_ADDRESS_TXS: &str = "address_txs"; // Key: address length (u8) + address + height (u64 BE) + position (u32 BE) => Value: tx_hash
+const CF_TOKEN_HOLDERS: &str = "token_holders"; // Key: token_id + address => Value: token balance (u64 BE)
+const CF_PAYLOADS: &str = "payloads"; // Key: data_hash => Value: serialized PayloadRecord
+const CF_NAMESPACE_PAYLOADS: &str = "namespace_payloads"; // Key: namespace length (u8) + namespace + data_hash => Value: serialized PayloadRecord
+const CF_NAMESPACE_USAGE: &str = "namespace_usage"; // Key: namespace => Value: serialized NamespaceUsage
+const CF_BLOCK_HASHES: &str = "block_hashes"; // Key: height (u64 BE) => Value: hash of the indexed block
+const CF_UNDO: &str = "undo"; // Key: height (u64 BE) => Value: serialized Vec<UndoEntry>
+const CF_METADATA: &str = "metadata"; // KEY_* below
+
+/// Tables an undo entry can refer to, by position.
+const TABLES: [&str; 8] = [CF_BALANCES, CF_ADDRESS_TXS, CF_TOKEN_HOLDERS, CF_PAYLOADS, CF_RICH_LIST, CF_METADATA, CF_NAMESPACE_PAYLOADS, CF_NAMESPACE_USAGE];
+const COLUMN_FAMILIES: [&str; 10] = [
+    CF_BALANCES, CF_RICH_LIST, CF_ADDRESS_TXS, CF_TOKEN_HOLDERS, CF_PAYLOADS, CF_NAMESPACE_PAYLOADS, CF_NAMESPACE_USAGE, CF_BLOCK_HASHES, CF_UNDO, CF_METADATA,
+];
+
+const KEY_INDEX_VERSION: &[u8] = b"iv"; // Key: KEY_INDEX_VERSION => Value: INDEX_VERSION (u32 BE)
+const KEY_ADDRESS_COUNT: &[u8] = b"ac"; // Key: KEY_ADDRESS_COUNT => Value: addresses with a native balance (u64 BE)
+
+#[derive(Debug, thiserror::Error)]
+pub enum IndexerError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Blockchain error: {0}")]
+    Blockchain(#[from] BlockchainError),
+    #[error("Index is unusable ({0}); rebuild it with the reindex command")]
+    Rebuild(String),
+}
+
+/// The first main-chain transaction storing a payload.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadRecord {
+    pub height: u64,
+    pub tx_hash: Hash,
+    pub sender: Address,
+    /// Payload size the transaction declared.
+    pub data_size: u64,
+}
+
+/// What the payloads filed under one namespace add up to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NamespaceUsage {
+    /// Distinct payloads in the namespace.
+    pub payloads: u64,
+    /// Sum of their declared sizes.
+    pub payload_bytes: u64,
+}
+
+/// What one [`ChainIndexer::sync`] call did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IndexerProgress {
+    /// Blocks rolled back because they left the main chain.
+    pub rolled_back: u64,
+    /// Main-chain blocks indexed.
+    pub indexed: u64,
+    /// Whether the index reached the chain tip.
+    pub caught_up: bool,
+}
+
+// Previous value of an entry changed by a block; `None` if the block created it
+#[derive(Serialize, Deserialize, Debug)]
+struct UndoEntry {
+    table: u8,
+    key: Vec<u8>,
+    previous: Option<Vec<u8>>,
+}
+
+/// The index database; see the module documentation.
+#[derive(Debug)]
+pub struct ChainIndexer {
+    store: Box<dyn ChainStore>,
+}
+
+impl ChainIndexer {
+    /// Opens (or creates) the index at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IndexerError> {
+        let store = backend::open(path.as_ref(), &COLUMN_FAMILIES)?;
+        let indexer = ChainIndexer { store };
+        match indexer.store.get(CF_METADATA, KEY_INDEX_VERSION)? {
+            Some(bytes) => {
+                let version = u32::from_be_bytes(bytes.as_slice().try_into().map_err(|_| StorageError::InvalidFormat("Invalid index version".to_string()))?);
+                if version != INDEX_VERSION {
+                    return Err(IndexerError::Rebuild(format!("layout version {}, this binary uses {}", version, INDEX_VERSION)));
+                }
+            }
+            None => indexer.store.put(CF_METADATA, KEY_INDEX_VERSION, &INDEX_VERSION.to_be_bytes())?,
+        }
+        Ok(indexer)
+    }
+
+    /// Height and hash of the last indexed block.
+    pub fn tip(&self) -> Result<Option<(u64, Hash)>, IndexerError> {
+        let Some(item) = self.store.iter_from(CF_BLOCK_HASHES, &u64::MAX.to_be_bytes(), Direction::Reverse).next() else {
+            return Ok(None);
+        };
+        let (key, value) = item?;
+        Ok(Some((decode_u64(&key)?, decode_hash(&value)?)))
+    }
+
+    /// Brings the index up to the main chain of `blockchain`: rolls back indexed blocks that left
+    /// it, then indexes at most `max_blocks` new ones.
+    pub fn sync(&self, blockchain: &Blockchain, max_blocks: usize) -> Result<IndexerProgress, IndexerError> {
+        let mut progress = IndexerProgress::default();
+        let Some(chain_height) = blockchain.get_chain_height() else {
+            progress.caught_up = true;
+            return Ok(progress);
+        };
+
+        while let Some((height, hash)) = self.tip()? {
+            let main_hash = if height <= chain_height { blockchain.storage().get_hash_by_height(height)? } else { None };
+            if main_hash == Some(hash) {
+                break;
+            }
+            self.roll_back(height)?;
+            progress.rolled_back += 1;
+        }
+
+        let next_height = self.tip()?.map_or(0, |(height, _)| height + 1);
+        for height in (next_height..=chain_height).take(max_blocks) {
+            let block = blockchain.get_block_by_height(height)?.ok_or(BlockchainError::BlockNotFoundByHeight(height))?;
+            self.apply_block(&block)?;
+            progress.indexed += 1;
+        }
+        progress.caught_up = self.tip()?.map(|(height, _)| height) == Some(chain_height);
+
+        if let Some(finalized_height) = blockchain.get_finalized_height() {
+            self.prune_undo(finalized_height)?;
+        }
+        Ok(progress)
+    }
+
+    /// Native balance of `address`.
+    pub fn balance(&self, address: &Address) -> Result<u64, IndexerError> {
+        self.store.get(CF_BALANCES, address)?.map_or(Ok(0), |bytes| decode_u64(&bytes))
+    }
+
+    /// The `n` addresses with the largest native balances, richest first; ties are ordered by address.
+    pub fn top_holders(&self, n: usize) -> Result<Vec<(Address, u64)>, IndexerError> {
+        let mut holders = Vec::new();
+        for item in self.store.iter_from(CF_RICH_LIST, &[], Direction::Forward).take(n) {
+            let (key, _) = item?;
+            if key.len() < 8 {
+                return Err(StorageError::InvalidFormat("Invalid rich list entry".to_string()).into());
+            }
+            holders.push((key[8..].to_vec(), !decode_u64(&key[..8])?));
+        }
+        Ok(holders)
+    }
+
+    /// How many addresses hold a native balance.
+    pub fn address_count(&self) -> Result<u64, IndexerError> {
+        self.store.get(CF_METADATA, KEY_ADDRESS_COUNT)?.map_or(Ok(0), |bytes| decode_u64(&bytes))
+    }
+
+    /// Main-chain transactions sending from or to `address`, oldest first.
+    pub fn address_transactions(&self, address: &Address) -> Result<Vec<AddressTxRef>, IndexerError> {
+        let prefix = address_key_prefix(address);
+        let mut refs = Vec::new();
+        for item in self.store.iter_from(CF_ADDRESS_TXS, &prefix, Direction::Forward) {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let suffix = &key[prefix.len()..];
+            if suffix.len() != 12 {
+                return Err(StorageError::InvalidFormat(format!("Invalid address entry for {}", hex::encode(address))).into());
+            }
+            refs.push(AddressTxRef {
+                tx_hash: decode_hash(&value)?,
+                height: decode_u64(&suffix[..8])?,
+                position: u32::from_be_bytes(suffix[8..].try_into().expect("slice is 4 bytes")),
+            });
+        }
+        Ok(refs)
+    }
+
+    /// Balance of `token_id` held by `address`.
+    pub fn token_balance(&self, token_id: &Hash, address: &Address) -> Result<u64, IndexerError> {
+        self.store.get(CF_TOKEN_HOLDERS, &token_holder_key(token_id, address))?.map_or(Ok(0), |bytes| decode_u64(&bytes))
+    }
+
+    /// Every address holding some of `token_id`, with its balance, ordered by address.
+    pub fn token_holders(&self, token_id: &Hash) -> Result<Vec<(Address, u64)>, IndexerError> {
+        let mut holders = Vec::new();
+        for item in self.store.iter_from(CF_TOKEN_HOLDERS, token_id, Direction::Forward) {
+            let (key, value) = item?;
+            if !key.starts_with(token_id) {
+                break;
+            }
+            holders.push((key[token_id.len()..].to_vec(), decode_u64(&value)?));
+        }
+        Ok(holders)
+    }
+
+    /// Where the payload `data_hash` was first stored on the main chain.
+    pub fn payload(&self, data_hash: &Hash) -> Result<Option<PayloadRecord>, IndexerError> {
+        match self.store.get(CF_PAYLOADS, data_hash)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes).map_err(StorageError::Deserialization)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Payloads filed under `namespace`, by hash, with the first transaction filing each there.
+    pub fn namespace_payloads(&self, namespace: &str) -> Result<Vec<(Hash, PayloadRecord)>, IndexerError> {
+        let prefix = namespace_key_prefix(namespace);
+        let mut payloads = Vec::new();
+        for item in self.store.iter_from(CF_NAMESPACE_PAYLOADS, &prefix, Direction::Forward) {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            payloads.push((decode_hash(&key[prefix.len()..])?, bincode::deserialize(&value).map_err(StorageError::Deserialization)?));
+        }
+        Ok(payloads)
+    }
+
+    /// Usage of `namespace`; zero if nothing was filed under it.
+    pub fn namespace_usage(&self, namespace: &str) -> Result<NamespaceUsage, IndexerError> {
+        match self.store.get(CF_NAMESPACE_USAGE, namespace.as_bytes())? {
+            Some(bytes) => Ok(bincode::deserialize(&bytes).map_err(StorageError::Deserialization)?),
+            None => Ok(NamespaceUsage::default()),
+        }
+    }
+
+    /// Persists everything written so far to disk.
+    pub fn flush(&self) -> Result<(), IndexerError> {
+        Ok(self.store.flush()?)
+    }
+
+    fn apply_block(&self, block: &Block) -> Result<(), IndexerError> {
+        let height = block.header.height;
+        let mut changes = BlockChanges::new(self.store.as_ref());
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let tx_hash = tx.calculate_hash();
+            let sender = tx.sender();
+            // A coinbase has no sender
+            let has_sender = !sender.is_empty();
+
+            let mut addresses = Vec::new();
+            if has_sender {
+                addresses.push(sender);
+            }
+            if let Some(receiver) = tx.receiver().filter(|receiver| *receiver != sender) {
+                addresses.push(receiver);
+            }
+            for address in addresses {
+                let mut key = address_key_prefix(address);
+                key.extend_from_slice(&height.to_be_bytes());
+                key.extend_from_slice(&(position as u32).to_be_bytes());
+                changes.set(CF_ADDRESS_TXS, key, Some(tx_hash.to_vec()))?;
+            }
+
+            if has_sender {
+                changes.add_to_native_balance(sender.clone(), -(tx.fee() as i128))?;
+            }
+            match tx.transaction_type() {
+                TransactionType::TransferNative { amount } => {
+                    changes.add_to_native_balance(sender.clone(), -(*amount as i128))?;
+                    if let Some(receiver) = tx.receiver() {
+                        changes.add_to_native_balance(receiver.clone(), *amount as i128)?;
+                    }
+                }
+                TransactionType::Stake { amount } => changes.add_to_native_balance(sender.clone(), -(*amount as i128))?,
+                TransactionType::Unstake { amount } => changes.add_to_native_balance(sender.clone(), *amount as i128)?,
+                TransactionType::Coinbase { amount, .. } => {
+                    if let Some(receiver) = tx.receiver() {
+                        changes.add_to_native_balance(receiver.clone(), *amount as i128)?;
+                    }
+                }
+                TransactionType::CreateToken { metadata } => {
+                    let key = token_holder_key(&metadata.metadata_hash, &metadata.creator);
+                    changes.add_to_balance(CF_TOKEN_HOLDERS, key, metadata.total_supply as i128)?;
+                }
+                TransactionType::TransferToken { token_id, amount } => {
+                    changes.add_to_balance(CF_TOKEN_HOLDERS, token_holder_key(token_id, sender), -(*amount as i128))?;
+                    if let Some(receiver) = tx.receiver() {
+                        changes.add_to_balance(CF_TOKEN_HOLDERS, token_holder_key(token_id, receiver), *amount as i128)?;
+                    }
+                }
+                TransactionType::StoreData { data_hash, data_size, namespace } => {
+                    let record = PayloadRecord { height, tx_hash, sender: sender.clone(), data_size: *data_size };
+                    let serialized_record = bincode::serialize(&record).map_err(StorageError::Serialization)?;
+                    if changes.get(CF_PAYLOADS, data_hash)?.is_none() {
+                        changes.set(CF_PAYLOADS, data_hash.to_vec(), Some(serialized_record.clone()))?;
+                    }
+                    if let Some(namespace) = namespace {
+                        let mut key = namespace_key_prefix(namespace);
+                        key.extend_from_slice(data_hash);
+                        if changes.get(CF_NAMESPACE_PAYLOADS, &key)?.is_none() {
+                            changes.set(CF_NAMESPACE_PAYLOADS, key, Some(serialized_record))?;
+                            let mut usage: NamespaceUsage = match changes.get(CF_NAMESPACE_USAGE, namespace.as_bytes())? {
+                                Some(bytes) => bincode::deserialize(&bytes).map_err(StorageError::Deserialization)?,
+                                None => NamespaceUsage::default(),
+                            };
+                            usage.payloads += 1;
+                            usage.payload_bytes = usage.payload_bytes.saturating_add(*data_size);
+                            changes.set(CF_NAMESPACE_USAGE, namespace.as_bytes().to_vec(), Some(bincode::serialize(&usage).map_err(StorageError::Serialization)?))?;
+                        }
+                    }
+                }
+                TransactionType::StorageDeal { price, .. } => {
+                    changes.add_to_native_balance(sender.clone(), -(*price as i128))?;
+                    if let Some(receiver) = tx.receiver() {
+                        changes.add_to_native_balance(receiver.clone(), *price as i128)?;
+                    }
+                }
+                TransactionType::SlashDoubleSign { .. } | TransactionType::PublishPointer { .. } => {}
+            }
+        }
+
+        let (mut batch, undo) = changes.finish();
+        batch.put_cf(CF_BLOCK_HASHES, height.to_be_bytes(), block.hash());
+        batch.put_cf(CF_UNDO, height.to_be_bytes(), bincode::serialize(&undo).map_err(StorageError::Serialization)?);
+        self.store.write(batch)?;
+        debug!("Indexed block {} ({} transactions)", height, block.transactions.len());
+        Ok(())
+    }
+
+    fn roll_back(&self, height: u64) -> Result<(), IndexerError> {
+        let bytes = self.store.get(CF_UNDO, &height.to_be_bytes())?
+            .ok_or_else(|| IndexerError::Rebuild(format!("block {} left the main chain but its undo data was pruned", height)))?;
+        let undo: Vec<UndoEntry> = bincode::deserialize(&bytes).map_err(StorageError::Deserialization)?;
+        let mut batch = WriteBatch::default();
+        for entry in undo.into_iter().rev() {
+            let table = *TABLES.get(entry.table as usize)
+                .ok_or_else(|| StorageError::InvalidFormat(format!("Undo entry of block {} names table {}", height, entry.table)))?;
+            match entry.previous {
+                Some(previous) => batch.put_cf(table, entry.key, previous),
+                None => batch.delete_cf(table, entry.key),
+            }
+        }
+        batch.delete_cf(CF_BLOCK_HASHES, height.to_be_bytes());
+        batch.delete_cf(CF_UNDO, height.to_be_bytes());
+        self.store.write(batch)?;
+        info!("Rolled back indexed block {}", height);
+        Ok(())
+    }
+
+    // Undo records of finalized blocks are never needed again
+    fn prune_undo(&self, finalized_height: u64) -> Result<(), IndexerError> {
+        let mut batch = WriteBatch::default();
+        for item in self.store.iter_from(CF_UNDO, &[], Direction::Forward) {
+            let (key, _) = item?;
+            if decode_u64(&key)? >= finalized_height {
+                break;
+            }
+            batch.delete_cf(CF_UNDO, key);
+        }
+        if !batch.is_empty() {
+            self.store.write(batch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Entries changed by one block, with their values from before it.
+struct BlockChanges<'a> {
+    store: &'a dyn ChainStore,
+    current: HashMap<(&'static str, Vec<u8>), Option<Vec<u8>>>,
+    batch: WriteBatch,
+    undo: Vec<UndoEntry>,
+}
+
+impl<'a> BlockChanges<'a> {
+    fn new(store: &'a dyn ChainStore) -> Self {
+        BlockChanges { store, current: HashMap::new(), batch: WriteBatch::default(), undo: Vec::new() }
+    }
+
+    fn get(&self, table: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        match self.current.get(&(table, key.to_vec())) {
+            Some(value) => Ok(value.clone()),
+            None => self.store.get(table, key),
+        }
+    }
+
+    fn set(&mut self, table: &'static str, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<(), StorageError> {
+        if !self.current.contains_key(&(table, key.clone())) {
+            let previous = self.store.get(table, &key)?;
+            let table_id = TABLES.iter().position(|name| *name == table).expect("Undo covers every table") as u8;
+            self.undo.push(UndoEntry { table: table_id, key: key.clone(), previous });
+        }
+        match &value {
+            Some(value) => self.batch.put_cf(table, &key, value),
+            None => self.batch.delete_cf(table, &key),
+        }
+        self.current.insert((table, key), value);
+        Ok(())
+    }
+
+    // Balances saturate at zero like the chain's own token accounting; empty ones are removed
+    // Returns the balance before and after
+    fn add_to_balance(&mut self, table: &'static str, key: Vec<u8>, delta: i128) -> Result<(u64, u64), StorageError> {
+        let balance = match self.get(table, &key)? {
+            Some(bytes) => decode_u64(&bytes).map_err(|_| StorageError::InvalidFormat(format!("Invalid balance entry in {}", table)))?,
+            None => 0,
+        };
+        let updated = (balance as i128 + delta).clamp(0, u64::MAX as i128) as u64;
+        if updated != balance {
+            self.set(table, key, (updated > 0).then(|| updated.to_be_bytes().to_vec()))?;
+        }
+        Ok((balance, updated))
+    }
+
+    // Keeps the rich list and the address count in step with the balance
+    fn add_to_native_balance(&mut self, address: Address, delta: i128) -> Result<(), StorageError> {
+        let (before, after) = self.add_to_balance(CF_BALANCES, address.clone(), delta)?;
+        if before == after {
+            return Ok(());
+        }
+        if before > 0 {
+            self.set(CF_RICH_LIST, rich_list_key(before, &address), None)?;
+        }
+        if after > 0 {
+            self.set(CF_RICH_LIST, rich_list_key(after, &address), Some(Vec::new()))?;
+        }
+        if (before == 0) != (after == 0) {
+            let count = match self.get(CF_METADATA, KEY_ADDRESS_COUNT)? {
+                Some(bytes) => decode_u64(&bytes).map_err(|_| StorageError::InvalidFormat("Invalid address count".to_string()))?,
+                None => 0,
+            };
+            let count = if after > 0 { count + 1 } else { count.saturating_sub(1) };
+            self.set(CF_METADATA, KEY_ADDRESS_COUNT.to_vec(), Some(count.to_be_bytes().to_vec()))?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> (WriteBatch, Vec<UndoEntry>) {
+        (self.batch, self.undo)
+    }
+}
+
+fn address_key_prefix(address: &Address) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(1 + address.len());
+    prefix.push(address.len() as u8);
+    prefix.extend_from_slice(address);
+    prefix
+}
+
+fn namespace_key_prefix(namespace: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(1 + namespace.len());
+    prefix.push(namespace.len() as u8);
+    prefix.extend_from_slice(namespace.as_bytes());
+    prefix
+}
+
+fn rich_list_key(balance: u64, address: &Address) -> Vec<u8> {
+    let mut key = (!balance).to_be_bytes().to_vec();
+    key.extend_from_slice(address);
+    key
+}
+
+fn token_holder_key(token_id: &Hash, address: &Address) -> Vec<u8> {
+    let mut key = token_id.to_vec();
+    key.extend_from_slice(address);
+    key
+}
+
+fn decode_u64(bytes: &[u8]) -> Result<u64, IndexerError> {
+    let bytes = bytes.try_into().map_err(|_| StorageError::InvalidFormat(format!("Expected 8 bytes, got {}", bytes.len())))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn decode_hash(bytes: &[u8]) -> Result<Hash, IndexerError> {
+    Ok(bytes.try_into().map_err(|_| StorageError::InvalidFormat(format!("Expected a 32-byte hash, got {} bytes", bytes.len())))?)
+}
+
+/// Deletes the index at `path` and builds it again from the main chain of `blockchain`.
+/// Returns how many blocks were indexed.
+pub fn reindex(path: &Path, blockchain: &Blockchain) -> Result<u64, IndexerError> {
+    if path.exists() {
+        std::fs::remove_dir_all(path).map_err(StorageError::Io)?;
+    }
+    let indexer = ChainIndexer::open(path)?;
+    let mut indexed = 0;
+    loop {
+        let progress = indexer.sync(blockchain, INDEX_BATCH_BLOCKS)?;
+        indexed += progress.indexed;
+        if progress.caught_up {
+            break;
+        }
+        info!("Indexed blocks up to height {:?}", indexer.tip()?.map(|(height, _)| height));
+    }
+    indexer.flush()?;
+    Ok(indexed)
+}
+
+/// Keeps `indexer` following the main chain of `blockchain` until `shutdown` is triggered.
+pub async fn run_indexer(indexer: Arc<ChainIndexer>, blockchain: Arc<Mutex<Blockchain>>, shutdown: Shutdown) -> Result<(), String> {
+    let tip_watch = blockchain.lock().expect("Blockchain lock poisoned").tip_watch();
+    let mut synced_generation = None;
+    loop {
+        let generation = tip_watch.generation();
+        if synced_generation != Some(generation) {
+            // The lock is released between batches, so catching up doesn't stall block production
+            let progress = {
+                let bc_guard = blockchain.lock().expect("Blockchain lock poisoned");
+                indexer.sync(&bc_guard, INDEX_BATCH_BLOCKS).map_err(|e| e.to_string())?
+            };
+            if progress.rolled_back > 0 {
+                info!("Indexer rolled back {} blocks that left the main chain", progress.rolled_back);
+            }
+            if progress.indexed > 1 {
+                info!("Indexed {} blocks, up to height {:?}", progress.indexed, indexer.tip().map_err(|e| e.to_string())?.map(|(height, _)| height));
+            }
+            if progress.caught_up {
+                synced_generation = Some(generation);
+            } else {
+                tokio::task::yield_now().await;
+                continue;
+            }
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = shutdown.wait() => break,
+        }
+    }
+    indexer.flush().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_config::ChainConfig;
+    use crate::core::address::{address_from_public_key, KeyAlgorithm};
+    use crate::core::{SignedTransaction, Transaction};
+    use ed25519_dalek::SigningKey;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_indexer_follows_chain_and_reorgs() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(&dir.path().join("chain"), ChainConfig::default().with_finality_depth(2)).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let indexer = ChainIndexer::open(dir.path().join(INDEX_DIR)).unwrap();
+        let (alice_key, miner_key) = (SigningKey::from_bytes(&[1u8; 32]), SigningKey::from_bytes(&[3u8; 32]));
+        let [alice, miner] = [&alice_key, &miner_key].map(|key| address_from_public_key(KeyAlgorithm::Ed25519, &key.verifying_key().to_bytes()).unwrap());
+        let bob = vec![2u8; 21];
+
+        blockchain.set_miner_address(Some(miner.clone()));
+        let create_token = Transaction::new_create_token(alice.clone(), "Token".to_string(), "TKN".to_string(), 1000);
+        let TransactionType::CreateToken { metadata } = create_token.transaction_type() else { unreachable!() };
+        let token_id = metadata.metadata_hash;
+        for tx in [create_token, Transaction::new_store_data_in(alice.clone(), [8u8; 32], 10, "photos".to_string())] {
+            blockchain.add_pending_transaction(tx).unwrap();
+        }
+        let paid = Transaction::new_store_data(alice.clone(), [9u8; 32], 64).with_fee(2).with_nonce(0);
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(paid, &alice_key)).unwrap();
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+        assert_eq!(indexer.sync(&blockchain, 1).unwrap(), IndexerProgress { rolled_back: 0, indexed: 1, caught_up: false });
+        assert_eq!(indexer.sync(&blockchain, 10).unwrap(), IndexerProgress { rolled_back: 0, indexed: 1, caught_up: true });
+        let after_block_1 = (indexer.balance(&miner).unwrap(), indexer.token_holders(&token_id).unwrap());
+        assert_eq!(after_block_1.0, crate::consensus::BLOCK_SUBSIDY + 2);
+        assert_eq!(after_block_1.1, vec![(alice.clone(), 1000)]);
+        assert_eq!(indexer.payload(&[9u8; 32]).unwrap().map(|record| record.height), Some(1));
+        assert_eq!(indexer.namespace_payloads("photos").unwrap().iter().map(|(hash, _)| *hash).collect::<Vec<_>>(), vec![[8u8; 32]]);
+        assert!(indexer.namespace_payloads("photo").unwrap().is_empty());
+        assert_eq!(indexer.top_holders(10).unwrap(), vec![(miner.clone(), after_block_1.0)]);
+        assert_eq!(indexer.address_count().unwrap(), 1);
+
+        let token_transfer = Transaction::new_transfer_token(alice.clone(), bob.clone(), token_id, 300).with_nonce(1);
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(token_transfer, &alice_key)).unwrap();
+        let transfer = Transaction::new_transfer_native(miner.clone(), bob.clone(), 10).with_fee(1).with_nonce(0);
+        blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(transfer, &miner_key)).unwrap();
+        blockchain.add_pending_transaction(Transaction::new_store_data_in(bob.clone(), [7u8; 32], 5, "photos".to_string())).unwrap();
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+        indexer.sync(&blockchain, 10).unwrap();
+        assert_eq!(indexer.token_holders(&token_id).unwrap(), vec![(alice.clone(), 700), (bob.clone(), 300)]);
+        assert_eq!(indexer.address_transactions(&bob).unwrap().len(), 3);
+        assert_eq!(indexer.namespace_usage("photos").unwrap(), NamespaceUsage { payloads: 2, payload_bytes: 15 });
+        assert_eq!(indexer.address_transactions(&alice).unwrap().iter().map(|tx| tx.height).collect::<Vec<_>>(), vec![1, 1, 1, 2]);
+        let miner_balance = after_block_1.0 + crate::consensus::BLOCK_SUBSIDY + 1 - 11;
+        assert_eq!(indexer.top_holders(10).unwrap(), vec![(miner.clone(), miner_balance), (bob.clone(), 10)]);
+        assert_eq!(indexer.top_holders(1).unwrap().len(), 1);
+        assert_eq!(indexer.address_count().unwrap(), 2);
+
+        // Block 2 is cut off the chain: the index forgets it
+        blockchain.truncate_chain(2).unwrap();
+        assert_eq!(indexer.sync(&blockchain, 10).unwrap(), IndexerProgress { rolled_back: 1, indexed: 0, caught_up: true });
+        assert_eq!((indexer.balance(&miner).unwrap(), indexer.token_holders(&token_id).unwrap()), after_block_1);
+        assert!(indexer.address_transactions(&bob).unwrap().is_empty());
+        assert_eq!(indexer.top_holders(10).unwrap(), vec![(miner.clone(), after_block_1.0)]);
+        assert_eq!(indexer.address_count().unwrap(), 1);
+        assert_eq!(indexer.tip().unwrap().map(|(height, _)| height), Some(1));
+        assert_eq!(indexer.namespace_usage("photos").unwrap(), NamespaceUsage { payloads: 1, payload_bytes: 10 });
+        assert_eq!(indexer.namespace_payloads("photos").unwrap().len(), 1);
+
+        // A rebuilt index matches the one kept up to date
+        let rebuilt_path = dir.path().join("rebuilt");
+        assert_eq!(reindex(&rebuilt_path, &blockchain).unwrap(), 2);
+        let rebuilt = ChainIndexer::open(&rebuilt_path).unwrap();
+        assert_eq!(rebuilt.tip().unwrap(), indexer.tip().unwrap());
+        assert_eq!(rebuilt.balance(&miner).unwrap(), after_block_1.0);
+        assert_eq!(rebuilt.address_count().unwrap(), 1);
+    }
+}
@@ -1,8 +1,29 @@
 pub mod core;
+pub mod chain_config;
+pub mod presets;
+pub mod config;
 pub mod consensus;
 pub mod network;
 pub mod storage;
+pub mod indexer;
 pub mod mempool;
+pub mod miner;
 pub mod rpc; // Declare the rpc module
+pub mod service;
+pub mod logging;
+pub mod supervisor;
+pub mod preflight;
+pub mod stratum;
+pub mod tenants;
+pub mod rpc_auth;
+pub mod rpc_rate_limit;
+pub mod rpc_cors;
+pub mod openrpc;
 pub mod offchain_storage; // Declare the offchain_storage module
+pub mod manifest;
+pub mod backup;
+pub mod chain_export;
+pub mod wallet;
+pub mod light;
+pub mod light_client;
 
@@ -0,0 +1,151 @@
+//!
+//! # Light Verification
+//! Pure functions checking chain data received from an untrusted RPC server against the
+//! consensus rules, without storage or network access, for applications that embed a light
+//! client of their own. [`LightClient`](crate::light_client::LightClient) is built on them.
+//!
+//! - [`verify_header_chain`] checks a segment of consecutive headers: linkage, heights,
+//!   Proof-of-Work, checkpoints, the genesis header and every difficulty transition.
+//! - [`verify_difficulty`] checks that one header carries the target the network's difficulty
+//!   algorithm expects after its ancestors.
+//! - [`verify_inclusion`] checks a transaction's inclusion proof against a validated header.
+//!
+//! Ancestors needed by the difficulty algorithm are supplied by the caller through a
+//! `get_header(height)` function, so headers can come from wherever the embedder keeps them.
+
+use crate::chain_config::ChainConfig;
+use crate::consensus::{self, Checkpoints, U256};
+use crate::core::{Block, BlockHeader, Transaction, TransactionProof};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum VerifyError {
+    #[error("Invalid header at height {height}: {reason}")]
+    InvalidHeader { height: u64, reason: String },
+    #[error("Invalid transaction proof: {0}")]
+    InvalidProof(String),
+}
+
+fn invalid(height: u64, reason: impl Into<String>) -> VerifyError {
+    VerifyError::InvalidHeader { height, reason: reason.into() }
+}
+
+/// Checks that `header` is the genesis of the network of `chain_config`. Networks without a
+/// fixed genesis time can only have the rest of the header checked; its time is taken as is.
+pub fn verify_genesis(chain_config: &ChainConfig, header: &BlockHeader) -> Result<(), VerifyError> {
+    let timestamp = chain_config.genesis_timestamp.unwrap_or(header.timestamp);
+    if *header != Block::genesis(timestamp, chain_config.difficulty_bounds.pow_limit_bits).header {
+        return Err(invalid(0, "not the network's genesis"));
+    }
+    Ok(())
+}
+
+/// Checks that `header` carries the target bits expected after the header at
+/// `header.height - 1`, looking ancestors up with `get_header`.
+pub fn verify_difficulty<F>(chain_config: &ChainConfig, header: &BlockHeader, get_header: F) -> Result<(), VerifyError>
+where
+    F: Fn(u64) -> Result<BlockHeader, String>,
+{
+    let height = header.height;
+    let parent_height = height.checked_sub(1).ok_or_else(|| invalid(height, "genesis has no difficulty transition"))?;
+    let expected_bits = consensus::calculate_next_difficulty_from_headers(
+        parent_height, chain_config.difficulty_algorithm, chain_config.difficulty_bounds, get_header,
+    )
+    .map_err(|e| invalid(height, e))?;
+    if header.bits != expected_bits {
+        return Err(invalid(height, format!("expected target bits {:#010x}, got {:#010x}", expected_bits, header.bits)));
+    }
+    Ok(())
+}
+
+/// Checks `headers`, consecutive headers extending `parent` (or starting at genesis if `None`),
+/// and returns the work they add. `get_ancestor` supplies headers at or below `parent`'s height
+/// for the difficulty transitions; the segment itself is used above it.
+pub fn verify_header_chain<F>(
+    chain_config: &ChainConfig,
+    checkpoints: &Checkpoints,
+    parent: Option<&BlockHeader>,
+    headers: &[BlockHeader],
+    get_ancestor: F,
+) -> Result<U256, VerifyError>
+where
+    F: Fn(u64) -> Result<BlockHeader, String>,
+{
+    let start_height = parent.map_or(0, |parent| parent.height + 1);
+    let get_header = |height: u64| match height.checked_sub(start_height) {
+        Some(index) => headers.get(index as usize).cloned().ok_or_else(|| format!("Header {} is past the segment", height)),
+        None => get_ancestor(height),
+    };
+    let mut work = U256::zero();
+    for (offset, header) in headers.iter().enumerate() {
+        let height = start_height + offset as u64;
+        if header.height != height {
+            return Err(invalid(height, format!("header claims height {}", header.height)));
+        }
+        checkpoints.check(height, &header.calculate_hash()).map_err(|e| invalid(height, e))?;
+        let previous = if offset == 0 { parent } else { Some(&headers[offset - 1]) };
+        match previous {
+            None => verify_genesis(chain_config, header)?,
+            Some(previous) => {
+                if header.previous_hash != previous.calculate_hash() {
+                    return Err(invalid(height, "does not extend the previous header"));
+                }
+                if !consensus::verify_header_pow(header, chain_config.pow_algorithm) {
+                    return Err(invalid(height, format!("hash does not meet target bits {:#010x}", header.bits)));
+                }
+                verify_difficulty(chain_config, header, get_header)?;
+            }
+        }
+        work += consensus::block_work(header.bits);
+    }
+    Ok(work)
+}
+
+/// Checks that `proof` places `transaction` in the block of `header`.
+pub fn verify_inclusion(header: &BlockHeader, transaction: &Transaction, proof: &TransactionProof) -> Result<(), VerifyError> {
+    proof.verify(header, &transaction.calculate_hash()).map_err(VerifyError::InvalidProof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Blockchain;
+    use crate::presets::NetworkPreset;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_verify_header_chain() {
+        let dir = tempdir().unwrap();
+        let config = NetworkPreset::Regtest.chain_config();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), config.clone()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        blockchain.generate_blocks(4, &vec![1u8; 21]).unwrap();
+        let headers: Vec<BlockHeader> = blockchain.iter_blocks(..).map(|block| block.unwrap().header).collect();
+        let get_header = |height: u64| Ok(headers[height as usize].clone());
+        let checkpoints = Checkpoints::embedded();
+        let block_work = consensus::block_work(config.difficulty_bounds.pow_limit_bits);
+
+        // The whole chain from genesis, or a segment on top of a known parent
+        assert_eq!(verify_header_chain(&config, &checkpoints, None, &headers, get_header), Ok(block_work * 5));
+        assert_eq!(verify_header_chain(&config, &checkpoints, Some(&headers[2]), &headers[3..], get_header), Ok(block_work * 2));
+        assert_eq!(verify_difficulty(&config, &headers[4], get_header), Ok(()));
+
+        let mut tampered = headers.clone();
+        tampered[3].previous_hash = [9u8; 32];
+        assert!(matches!(verify_header_chain(&config, &checkpoints, None, &tampered, get_header), Err(VerifyError::InvalidHeader { height: 3, .. })));
+        let mut tampered = headers.clone();
+        tampered[2].bits = consensus::bits_for_leading_zeros(2);
+        assert!(verify_difficulty(&config, &tampered[2], get_header).is_err());
+        assert!(verify_header_chain(&config, &checkpoints, Some(&headers[1]), &tampered[2..], get_header).is_err());
+        assert!(matches!(verify_header_chain(&config, &checkpoints, Some(&headers[0]), &headers[2..], get_header), Err(VerifyError::InvalidHeader { height: 1, .. })));
+        let testnet = NetworkPreset::Testnet.chain_config();
+        assert!(matches!(verify_header_chain(&testnet, &checkpoints, None, &headers[..1], get_header), Err(VerifyError::InvalidHeader { height: 0, .. })));
+
+        // Inclusion proofs are checked against the header of their block
+        let coinbase = blockchain.get_block_by_height(3).unwrap().unwrap().transactions[0].clone();
+        let (_, proof) = blockchain.get_transaction_proof(&coinbase.calculate_hash()).unwrap().unwrap();
+        assert_eq!(verify_inclusion(&headers[3], &coinbase, &proof), Ok(()));
+        assert!(matches!(verify_inclusion(&headers[2], &coinbase, &proof), Err(VerifyError::InvalidProof(_))));
+        let other = blockchain.get_block_by_height(2).unwrap().unwrap().transactions[0].clone();
+        assert!(matches!(verify_inclusion(&headers[3], &other, &proof), Err(VerifyError::InvalidProof(_))));
+    }
+}
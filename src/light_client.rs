@@ -0,0 +1,340 @@
+//!
+//! # Light Client
+//! A header-only node mode for devices that can't hold the chain: it keeps the block headers
+//! of the main chain (in `<data-dir>/light`) and checks single transactions against them.
+//!
+//! Headers are fetched from a full node's RPC server (`get_blocks` with `headers_only`) and
+//! none is trusted: each is checked with [`light::verify_header_chain`] (linkage,
+//! Proof-of-Work, difficulty and the embedded checkpoints). When the node's chain forks from
+//! the stored one, its branch replaces the stored headers only if it has more cumulative work.
+//!
+//! A transaction is verified with `get_transaction_proof`: the node returns the transaction
+//! with the hashes of all transactions of its block, which must hash to the Merkle root of
+//! the locally validated header ([`light::verify_inclusion`]). Only Proof-of-Work chains can be followed this way; the
+//! seal of Proof-of-Stake blocks needs the stake table, which headers don't carry.
+
+use crate::chain_config::ChainConfig;
+use crate::consensus::{self, Checkpoints, ConsensusMode, U256};
+use crate::core::{BlockHeader, Hash, Transaction, TransactionProof};
+use crate::light::{self, VerifyError};
+use crate::storage::backend::{self, ChainStore, Direction, WriteBatch};
+use crate::storage::StorageError;
+use crate::wallet::{RpcClient, WalletError};
+use log::{info, warn};
+use std::path::Path;
+use std::time::Duration;
+
+/// Subdirectory of the data directory holding the light client's headers.
+pub const LIGHT_DIR: &str = "light";
+/// Headers requested per `get_blocks` call (the most a node returns with `headers_only`).
+pub const HEADERS_PER_REQUEST: u64 = 2000;
+/// How often `light sync --follow` asks the node for new headers.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+const CF_HEADERS: &str = "headers"; // Key: height (u64 BE) => Value: serialized BlockHeader
+const CF_METADATA: &str = "metadata"; // KEY_* below
+const COLUMN_FAMILIES: [&str; 2] = [CF_HEADERS, CF_METADATA];
+
+const KEY_CHAIN_WORK: &[u8] = b"cw"; // Key: KEY_CHAIN_WORK => Value: cumulative work of the stored headers (U256 BE)
+
+#[derive(Debug, thiserror::Error)]
+pub enum LightClientError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Node error: {0}")]
+    Node(#[from] WalletError),
+    #[error("Light clients can only follow Proof-of-Work chains")]
+    Unsupported,
+    #[error("Invalid header at height {height}: {reason}")]
+    InvalidHeader { height: u64, reason: String },
+    #[error("Headers from height {0} on do not have more work than the stored chain")]
+    InsufficientWork(u64),
+    #[error("Transaction {0} is not confirmed on the node's main chain")]
+    NotFound(String),
+    #[error("Invalid transaction proof: {0}")]
+    InvalidProof(String),
+}
+
+impl From<VerifyError> for LightClientError {
+    fn from(error: VerifyError) -> Self {
+        match error {
+            VerifyError::InvalidHeader { height, reason } => LightClientError::InvalidHeader { height, reason },
+            VerifyError::InvalidProof(reason) => LightClientError::InvalidProof(reason),
+        }
+    }
+}
+
+/// What one [`LightClient::connect_headers`] or [`LightClient::sync`] call did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LightProgress {
+    /// Headers added to the stored chain.
+    pub connected: u64,
+    /// Stored headers replaced by a branch with more work.
+    pub replaced: u64,
+}
+
+/// A transaction proven to be in a block of the stored header chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedTransaction {
+    pub transaction: Transaction,
+    pub block_hash: Hash,
+    pub height: u64,
+    /// Stored headers from its block up to the tip, its block included.
+    pub confirmations: u64,
+}
+
+/// The header store; see the module documentation.
+#[derive(Debug)]
+pub struct LightClient {
+    store: Box<dyn ChainStore>,
+    chain_config: ChainConfig,
+    checkpoints: Checkpoints,
+}
+
+impl LightClient {
+    /// Opens (or creates) the header store at `path` for a chain created with `chain_config`.
+    pub fn open<P: AsRef<Path>>(path: P, chain_config: ChainConfig) -> Result<Self, LightClientError> {
+        if chain_config.consensus_mode != ConsensusMode::ProofOfWork {
+            return Err(LightClientError::Unsupported);
+        }
+        let store = backend::open(path.as_ref(), &COLUMN_FAMILIES)?;
+        Ok(LightClient { store, chain_config, checkpoints: Checkpoints::embedded() })
+    }
+
+    /// Stored header at `height`.
+    pub fn header(&self, height: u64) -> Result<Option<BlockHeader>, LightClientError> {
+        match self.store.get(CF_HEADERS, &height.to_be_bytes())? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes).map_err(StorageError::Serialization)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Height and header of the stored tip.
+    pub fn tip(&self) -> Result<Option<(u64, BlockHeader)>, LightClientError> {
+        let Some(item) = self.store.iter_from(CF_HEADERS, &u64::MAX.to_be_bytes(), Direction::Reverse).next() else {
+            return Ok(None);
+        };
+        let (_, value) = item?;
+        let header: BlockHeader = bincode::deserialize(&value).map_err(StorageError::Serialization)?;
+        Ok(Some((header.height, header)))
+    }
+
+    /// Cumulative work of the stored headers.
+    pub fn chain_work(&self) -> Result<U256, LightClientError> {
+        match self.store.get(CF_METADATA, KEY_CHAIN_WORK)? {
+            Some(bytes) if bytes.len() == 32 => Ok(U256::from_big_endian(&bytes)),
+            Some(bytes) => Err(StorageError::InvalidFormat(format!("Invalid chain work length ({})", bytes.len())).into()),
+            None => Ok(U256::zero()),
+        }
+    }
+
+    /// Validates `headers`, consecutive headers of one chain, and stores them if they extend the
+    /// stored chain or fork from it with more work. Headers already stored are skipped.
+    pub fn connect_headers(&self, headers: &[BlockHeader]) -> Result<LightProgress, LightClientError> {
+        let mut known = 0;
+        while known < headers.len() && self.header(headers[known].height)?.as_ref() == Some(&headers[known]) {
+            known += 1;
+        }
+        let headers = &headers[known..];
+        let Some(first) = headers.first() else { return Ok(LightProgress::default()) };
+        let fork_height = first.height;
+        let parent = match fork_height {
+            0 => None,
+            _ => Some(self.header(fork_height - 1)?.ok_or_else(|| LightClientError::InvalidHeader {
+                height: fork_height,
+                reason: "does not connect to the stored headers".to_string(),
+            })?),
+        };
+        if parent.is_none() && self.chain_config.genesis_timestamp.is_none() {
+            warn!("Trusting genesis {} from the node: the network has no fixed genesis time", hex::encode(first.calculate_hash()));
+        }
+        let get_ancestor = |height: u64| self.header(height).map_err(|e| e.to_string())?.ok_or_else(|| format!("Header {} not stored", height));
+        let work = light::verify_header_chain(&self.chain_config, &self.checkpoints, parent.as_ref(), headers, get_ancestor)?;
+
+        // Fork choice: a branch replacing stored headers needs more work than they have
+        let mut batch = WriteBatch::default();
+        let mut replaced = Vec::new();
+        for item in self.store.iter_from(CF_HEADERS, &fork_height.to_be_bytes(), Direction::Forward) {
+            let (key, value) = item?;
+            let header: BlockHeader = bincode::deserialize(&value).map_err(StorageError::Serialization)?;
+            replaced.push(header);
+            batch.delete_cf(CF_HEADERS, key);
+        }
+        let replaced_work = replaced.iter().fold(U256::zero(), |acc, header| acc + consensus::block_work(header.bits));
+        if !replaced.is_empty() && work <= replaced_work {
+            return Err(LightClientError::InsufficientWork(fork_height));
+        }
+        for header in headers {
+            let bytes = bincode::serialize(header).map_err(StorageError::Serialization)?;
+            batch.put_cf(CF_HEADERS, header.height.to_be_bytes(), bytes);
+        }
+        let mut work_bytes = [0u8; 32];
+        (self.chain_work()? - replaced_work + work).to_big_endian(&mut work_bytes);
+        batch.put_cf(CF_METADATA, KEY_CHAIN_WORK, work_bytes);
+        self.store.write(batch)?;
+        if !replaced.is_empty() {
+            info!("Replaced {} headers from height {} with a branch of {} headers", replaced.len(), fork_height, headers.len());
+        }
+        Ok(LightProgress { connected: headers.len() as u64, replaced: replaced.len() as u64 })
+    }
+
+    /// Fetches and connects the headers of the node behind `client` up to its tip, following it
+    /// onto a branch with more work if it forked from the stored chain.
+    pub fn sync(&self, client: &RpcClient) -> Result<LightProgress, LightClientError> {
+        let fetch = |start_height: u64, count: u64| -> Result<(u64, Vec<BlockHeader>), LightClientError> {
+            let result = client.call("get_blocks", serde_json::json!({ "start_height": start_height, "count": count, "headers_only": true }))?;
+            let tip_height = result["tip_height"].as_u64()
+                .ok_or_else(|| WalletError::InvalidResponse("get_blocks result has no tip_height".to_string()))?;
+            let headers = result["blocks"].as_array().into_iter().flatten()
+                .map(|entry| serde_json::from_value(entry["header"].clone()))
+                .collect::<Result<Vec<BlockHeader>, _>>()
+                .map_err(|e| WalletError::InvalidResponse(format!("invalid header: {}", e)))?;
+            Ok((tip_height, headers))
+        };
+
+        // Highest stored header the node also has, stepping back further on each miss
+        let mut start_height = 0;
+        let stored_tip = self.tip()?.map(|(height, _)| height);
+        if let Some(tip_height) = stored_tip {
+            let (mut height, mut step) = (tip_height, 1);
+            loop {
+                let (_, headers) = fetch(height, 1)?;
+                if headers.first().is_some_and(|header| self.header(height).ok().flatten().as_ref() == Some(header)) {
+                    start_height = height + 1;
+                    break;
+                }
+                if height == 0 {
+                    return Err(LightClientError::InvalidHeader { height: 0, reason: "the node follows another chain".to_string() });
+                }
+                height = height.saturating_sub(step);
+                step *= 2;
+            }
+        }
+
+        // Headers extending the stored tip are connected page by page; a competing branch is
+        // gathered whole, since it only wins once its total work is known
+        let extends_tip = stored_tip.is_none_or(|tip_height| start_height == tip_height + 1);
+        let mut progress = LightProgress::default();
+        let mut branch = Vec::new();
+        let mut next_height = start_height;
+        loop {
+            let (node_tip, headers) = fetch(next_height, HEADERS_PER_REQUEST)?;
+            if headers.is_empty() {
+                break;
+            }
+            next_height += headers.len() as u64;
+            branch.extend(headers);
+            if extends_tip {
+                progress.connected += self.connect_headers(&branch)?.connected;
+                branch.clear();
+            }
+            if next_height > node_tip {
+                break;
+            }
+        }
+        if !branch.is_empty() {
+            let connected = self.connect_headers(&branch)?;
+            progress.connected += connected.connected;
+            progress.replaced += connected.replaced;
+        }
+        Ok(progress)
+    }
+
+    /// Fetches `tx_hash` with its inclusion proof from the node behind `client` and checks it
+    /// against the stored headers.
+    pub fn verify_transaction(&self, client: &RpcClient, tx_hash: &Hash) -> Result<VerifiedTransaction, LightClientError> {
+        let result = client.call("get_transaction_proof", serde_json::json!({ "tx_hash": hex::encode(tx_hash) }))?;
+        if result.is_null() {
+            return Err(LightClientError::NotFound(hex::encode(tx_hash)));
+        }
+        let decode_hash = |value: &serde_json::Value| -> Result<Hash, WalletError> {
+            value.as_str().and_then(|hex_str| hex::decode(hex_str).ok()).and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| WalletError::InvalidResponse(format!("invalid hash {}", value)))
+        };
+        let transaction: Transaction = serde_json::from_value(result["transaction"].clone())
+            .map_err(|e| WalletError::InvalidResponse(format!("invalid transaction: {}", e)))?;
+        let proof = TransactionProof {
+            block_hash: decode_hash(&result["block_hash"])?,
+            height: result["height"].as_u64().ok_or_else(|| WalletError::InvalidResponse("proof has no height".to_string()))?,
+            position: result["position"].as_u64().and_then(|position| u32::try_from(position).ok())
+                .ok_or_else(|| WalletError::InvalidResponse("proof has no position".to_string()))?,
+            tx_hashes: result["tx_hashes"].as_array().into_iter().flatten().map(decode_hash).collect::<Result<_, _>>()?,
+            witness_root: Some(&result["witness_root"]).filter(|value| !value.is_null()).map(decode_hash).transpose()?,
+        };
+        if transaction.calculate_hash() != *tx_hash {
+            return Err(LightClientError::InvalidProof("the node returned another transaction".to_string()));
+        }
+        self.verify_proof(transaction, &proof)
+    }
+
+    /// Checks that `proof` places `transaction` in a block of the stored header chain.
+    pub fn verify_proof(&self, transaction: Transaction, proof: &TransactionProof) -> Result<VerifiedTransaction, LightClientError> {
+        let (tip_height, _) = self.tip()?.ok_or_else(|| LightClientError::InvalidProof("no headers stored yet; run light sync first".to_string()))?;
+        let header = self.header(proof.height)?
+            .ok_or_else(|| LightClientError::InvalidProof(format!("no header stored at height {}; run light sync first", proof.height)))?;
+        light::verify_inclusion(&header, &transaction, proof)?;
+        Ok(VerifiedTransaction { transaction, block_hash: proof.block_hash, height: proof.height, confirmations: tip_height - proof.height + 1 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Blockchain;
+    use crate::presets::NetworkPreset;
+    use tempfile::tempdir;
+
+    fn headers(blockchain: &Blockchain, from: u64) -> Vec<BlockHeader> {
+        blockchain.iter_blocks(from..).map(|block| block.unwrap().header).collect()
+    }
+
+    #[test]
+    fn test_light_client_follows_most_work() {
+        let dir = tempdir().unwrap();
+        let config = NetworkPreset::Regtest.chain_config();
+        let mut node_a = Blockchain::new_with_config(&dir.path().join("a"), config.clone()).unwrap();
+        let mut node_b = Blockchain::new_with_config(&dir.path().join("b"), config.clone()).unwrap();
+        node_a.initialize_genesis_if_needed().unwrap();
+        node_b.initialize_genesis_if_needed().unwrap();
+        node_a.generate_blocks(3, &vec![1u8; 21]).unwrap();
+        node_b.generate_blocks(5, &vec![2u8; 21]).unwrap();
+
+        let light = LightClient::open(dir.path().join(LIGHT_DIR), config.clone()).unwrap();
+        assert_eq!(light.connect_headers(&headers(&node_a, 0)).unwrap(), LightProgress { connected: 4, replaced: 0 });
+        assert_eq!(light.connect_headers(&headers(&node_a, 0)).unwrap(), LightProgress::default());
+        assert_eq!(light.tip().unwrap().map(|(height, _)| height), Some(3));
+
+        // A coinbase of node A's chain is proven against the stored headers
+        let coinbase = node_a.get_block_by_height(2).unwrap().unwrap().transactions[0].clone();
+        let (_, proof) = node_a.get_transaction_proof(&coinbase.calculate_hash()).unwrap().unwrap();
+        let verified = light.verify_proof(coinbase.clone(), &proof).unwrap();
+        assert_eq!((verified.height, verified.confirmations), (2, 2));
+        let other = node_b.get_block_by_height(2).unwrap().unwrap().transactions[0].clone();
+        assert!(matches!(light.verify_proof(other, &proof), Err(LightClientError::InvalidProof(_))));
+
+        // Node B's longer branch replaces it; node A's can't come back
+        assert_eq!(light.connect_headers(&headers(&node_b, 0)).unwrap(), LightProgress { connected: 5, replaced: 3 });
+        assert_eq!(light.tip().unwrap().map(|(_, header)| header.calculate_hash()), node_b.get_last_block_hash());
+        assert!(matches!(light.connect_headers(&headers(&node_a, 1)), Err(LightClientError::InsufficientWork(1))));
+        assert!(matches!(light.verify_proof(coinbase, &proof), Err(LightClientError::InvalidProof(_))));
+        let chain_work = (0..=5).fold(U256::zero(), |acc, _| acc + consensus::block_work(config.difficulty_bounds.pow_limit_bits));
+        assert_eq!(light.chain_work().unwrap(), chain_work);
+
+        // Headers that don't link up or carry the wrong target are refused
+        let mut tampered = headers(&node_b, 4);
+        tampered[0].previous_hash = [9u8; 32];
+        assert!(matches!(light.connect_headers(&tampered), Err(LightClientError::InvalidHeader { height: 4, .. })));
+        let mut tampered = headers(&node_b, 0);
+        tampered[0].timestamp += 1;
+        assert!(matches!(light.connect_headers(&tampered), Err(LightClientError::InvalidHeader { height: 0, .. })));
+        node_b.generate_blocks(1, &vec![2u8; 21]).unwrap();
+        let mut tampered = headers(&node_b, 6);
+        tampered[0].bits = consensus::bits_for_leading_zeros(2);
+        assert!(light.connect_headers(&tampered).is_err());
+        assert_eq!(light.connect_headers(&headers(&node_b, 6)).unwrap().connected, 1);
+
+        // Proof-of-Stake chains can't be followed from headers
+        let pos = ChainConfig { consensus_mode: ConsensusMode::ProofOfStake, ..config };
+        assert!(matches!(LightClient::open(dir.path().join("pos"), pos), Err(LightClientError::Unsupported)));
+    }
+}
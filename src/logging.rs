@@ -0,0 +1,206 @@
+//!
+//! # Logging
+//! The node logs to stderr, filtered by `RUST_LOG` (`info` by default), and can also keep
+//! its log history in files under the data directory.
+//!
+//! The log file holds one JSON object per line (`ts` in UNIX milliseconds, `level`,
+//! `target`, `msg`), so it can be searched with standard tools such as `jq`. It is rotated
+//! once it would grow beyond a size limit or, optionally, once it gets older than an age
+//! limit: `node.log` becomes `node.log.1`, older files shift up by one, and files beyond
+//! the retention count are deleted.
+
+use log::{Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Log files live in this subdirectory of the data directory.
+pub const LOG_DIR: &str = "logs";
+/// Name of the file being written; rotated files get a `.1`, `.2`, ... suffix.
+pub const LOG_FILE: &str = "node.log";
+/// Size a log file may reach before it is rotated (64 MiB).
+pub const DEFAULT_LOG_MAX_SIZE: u64 = 64 * 1024 * 1024;
+/// Rotated log files kept by default.
+pub const DEFAULT_LOG_MAX_FILES: usize = 10;
+
+/// Where the log file is kept and when it is rotated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLogSettings {
+    pub dir: PathBuf,
+    /// Rotate before the file would exceed this many bytes.
+    pub max_size: u64,
+    /// Rotate once the file is this old, if set.
+    pub max_age: Option<Duration>,
+    /// Rotated files kept; older ones are deleted.
+    pub max_files: usize,
+}
+
+/// A log file that rotates itself according to [`FileLogSettings`].
+pub struct RotatingFile {
+    settings: FileLogSettings,
+    file: File,
+    size: u64,
+    started: SystemTime,
+}
+
+impl RotatingFile {
+    /// Opens (or creates) the log file in `settings.dir`, appending to what it holds.
+    pub fn open(settings: FileLogSettings) -> io::Result<Self> {
+        fs::create_dir_all(&settings.dir)?;
+        let (file, size, started) = Self::open_current(&settings.dir)?;
+        Ok(RotatingFile { settings, file, size, started })
+    }
+
+    fn open_current(dir: &Path) -> io::Result<(File, u64, SystemTime)> {
+        let file = OpenOptions::new().create(true).append(true).open(dir.join(LOG_FILE))?;
+        let metadata = file.metadata()?;
+        // A file left by an earlier run keeps aging from when it was created
+        let started = if metadata.len() > 0 {
+            metadata.created().or_else(|_| metadata.modified()).unwrap_or_else(|_| SystemTime::now())
+        } else {
+            SystemTime::now()
+        };
+        Ok((file, metadata.len(), started))
+    }
+
+    /// Appends `line`, rotating first if it would exceed the size limit or the file is too old.
+    pub fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.size > 0 && (self.size + line.len() as u64 > self.settings.max_size || self.expired()) {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn expired(&self) -> bool {
+        self.settings.max_age.is_some_and(|max_age| self.started.elapsed().unwrap_or_default() >= max_age)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let rotated = |index: usize| self.settings.dir.join(format!("{}.{}", LOG_FILE, index));
+        // Files past the retention count, including any left by a larger earlier setting
+        let mut index = self.settings.max_files.max(1);
+        while rotated(index).exists() {
+            fs::remove_file(rotated(index))?;
+            index += 1;
+        }
+        for index in (1..self.settings.max_files).rev() {
+            if rotated(index).exists() {
+                fs::rename(rotated(index), rotated(index + 1))?;
+            }
+        }
+        let current = self.settings.dir.join(LOG_FILE);
+        if self.settings.max_files > 0 {
+            fs::rename(&current, rotated(1))?;
+        } else {
+            fs::remove_file(&current)?;
+        }
+        (self.file, self.size, self.started) = Self::open_current(&self.settings.dir)?;
+        Ok(())
+    }
+}
+
+/// The log file, once [`log_to_file`] has opened it.
+static FILE_SINK: OnceLock<Mutex<RotatingFile>> = OnceLock::new();
+
+struct NodeLogger {
+    stderr: env_logger::Logger,
+}
+
+impl Log for NodeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        self.stderr.log(record);
+        if let Some(sink) = FILE_SINK.get() {
+            let mut line = json_line(record).into_bytes();
+            line.push(b'\n');
+            let mut file = sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Err(e) = file.write_line(&line) {
+                // Logging through `log` here would recurse
+                eprintln!("Cannot write to the log file: {}", e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(sink) = FILE_SINK.get() {
+            let _ = sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).file.flush();
+        }
+    }
+}
+
+fn json_line(record: &Record) -> String {
+    let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    serde_json::json!({
+        "ts": ts,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "msg": record.args().to_string(),
+    })
+    .to_string()
+}
+
+/// Installs the node's logger, writing to stderr as filtered by `RUST_LOG` (`info` if unset).
+pub fn init() {
+    let stderr = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build();
+    let max_level = stderr.filter();
+    if log::set_boxed_logger(Box::new(NodeLogger { stderr })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Also writes every logged record to the rotating log file described by `settings`.
+/// Only the first call takes effect.
+pub fn log_to_file(settings: FileLogSettings) -> io::Result<()> {
+    let file = RotatingFile::open(settings)?;
+    let _ = FILE_SINK.set(Mutex::new(file));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = tempdir().unwrap();
+        let settings = FileLogSettings { dir: dir.path().to_path_buf(), max_size: 10, max_age: None, max_files: 2 };
+        let mut file = RotatingFile::open(settings.clone()).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_line(line.as_bytes()).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read(LOG_FILE), "fourth\n");
+        assert_eq!(read("node.log.1"), "third\n");
+        assert_eq!(read("node.log.2"), "second\n");
+        assert!(!dir.path().join("node.log.3").exists());
+
+        // Reopened, the file is appended to; an expired file rotates on the next write
+        let mut file = RotatingFile::open(FileLogSettings { max_size: 1024, max_age: Some(Duration::ZERO), ..settings }).unwrap();
+        file.write_line(b"fifth\n").unwrap();
+        assert_eq!(read(LOG_FILE), "fifth\n");
+        assert_eq!(read("node.log.1"), "fourth\n");
+    }
+
+    #[test]
+    fn test_json_line() {
+        let record = Record::builder().level(log::Level::Warn).target("node").args(format_args!("peer {} \"gone\"", 7)).build();
+        let value: serde_json::Value = serde_json::from_str(&json_line(&record)).unwrap();
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["target"], "node");
+        assert_eq!(value["msg"], "peer 7 \"gone\"");
+        assert!(value["ts"].as_u64().unwrap() > 0);
+    }
+}
@@ -1,21 +1,59 @@
 // src/main.rs
 
-use blockchain_data_storage::core::Blockchain;
-use blockchain_data_storage::network;
+use blockchain_data_storage::consensus::{Checkpoints, ConsensusMode};
+use blockchain_data_storage::core::{Blockchain, Transaction};
+use blockchain_data_storage::core::address::parse_address_str;
+use blockchain_data_storage::config::NodeConfig;
+use blockchain_data_storage::presets::NetworkPreset;
+use blockchain_data_storage::network::{self, audit, redundancy, NetworkConfig, NetworkMessage, PeerObservations, ProofAuditor, RetrievalTerms};
 use blockchain_data_storage::rpc; // Importar o módulo RPC
-use blockchain_data_storage::offchain_storage::OffChainStorageManager; // Importar o gerenciador de armazenamento off-chain
+use blockchain_data_storage::manifest;
+use blockchain_data_storage::miner::{self, MinerControl};
+use blockchain_data_storage::indexer::{self, ChainIndexer};
+use blockchain_data_storage::light_client::{self, LightClient};
+use blockchain_data_storage::backup;
+use blockchain_data_storage::chain_export;
+use blockchain_data_storage::wallet::{self, keystore, Keystore, Mnemonic, StoreFileStatus};
+use blockchain_data_storage::service::{self, DataDirLock, NodeError, PidFile};
+use blockchain_data_storage::logging::{self, FileLogSettings, DEFAULT_LOG_MAX_FILES, DEFAULT_LOG_MAX_SIZE};
+use blockchain_data_storage::supervisor::{RestartPolicy, Supervisor};
+use blockchain_data_storage::preflight::ConfigReport;
+use blockchain_data_storage::storage::{backend, StorageManager};
+use blockchain_data_storage::storage::cache::DEFAULT_BLOCK_CACHE_CAPACITY;
+use blockchain_data_storage::storage::compression::DEFAULT_DICTIONARY_SIZE;
+use blockchain_data_storage::mempool::{SenderLimits, DEFAULT_MAX_PENDING_BYTES_PER_SENDER, DEFAULT_MAX_PENDING_PER_SENDER, DEFAULT_MEMPOOL_EXPIRY, DEFAULT_MEMPOOL_MAX_BYTES};
+use blockchain_data_storage::stratum::{self, StratumServer};
+use blockchain_data_storage::tenants::TenantManager;
+use blockchain_data_storage::rpc_auth::RpcAuth;
+use blockchain_data_storage::rpc_rate_limit::{RateLimiter, RateLimits};
+use blockchain_data_storage::rpc_cors::CorsPolicy;
+use blockchain_data_storage::offchain_storage::{OffChainStorageManager, RetentionPolicy}; // Importar o gerenciador de armazenamento off-chain
+use blockchain_data_storage::core::time::unix_now;
 
-use clap::Parser;
-use log::{info, error};
-use std::path::PathBuf;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use log::{info, error, warn};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::{Arc, Mutex};
 use tokio::select;
-use tokio::signal::ctrl_c;
+
+/// Recent blocks sampled by `--train-block-dictionary`.
+const BLOCK_DICTIONARY_SAMPLES: u64 = 2000;
 
 /// Command-line arguments for the blockchain node.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// TOML file with the node's settings; flags given on the command line override it
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Network to run on: mainnet, testnet, devnet or regtest. Sets the genesis parameters of a new chain,
+    /// the address prefix, the default RPC and P2P addresses and the subdirectory of --data-dir used
+    #[arg(long, value_name = "NETWORK", default_value_t = NetworkPreset::Mainnet)]
+    network: NetworkPreset,
+
     /// Directory to store blockchain data.
     #[arg(short, long, value_name = "DIR", default_value = ".blockchain_data")]
     data_dir: PathBuf,
@@ -24,89 +62,1269 @@ struct Cli {
     #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8000")]
     rpc_addr: String,
 
-    // TODO: Add arguments for listen address, bootstrap peers, etc.
-    // #[arg(short, long, value_name = "MULTIADDR")]
-    // listen_address: Option<String>,
+    /// JSON file declaring API tenants (keys and quotas); enables multi-tenant mode
+    #[arg(long, value_name = "FILE")]
+    tenants_file: Option<PathBuf>,
+
+    /// JSON file with RPC API keys and/or a JWT secret; other clients may only call the public read-only methods
+    #[arg(long, value_name = "FILE")]
+    rpc_auth_file: Option<PathBuf>,
+
+    /// Requests per minute each IP may send to the RPC server without valid credentials (unlimited if unset)
+    #[arg(long, value_name = "REQUESTS")]
+    rpc_rate_limit: Option<u32>,
+
+    /// Requests per minute each RPC API key, JWT or tenant key may send (unlimited if unset)
+    #[arg(long, value_name = "REQUESTS")]
+    rpc_credential_rate_limit: Option<u32>,
+
+    /// Origin allowed to call the RPC server from a browser, e.g. https://app.example.com (repeatable; none by default)
+    #[arg(long = "rpc-cors-origin", value_name = "ORIGIN")]
+    rpc_cors_origins: Vec<String>,
+
+    /// Method allowed in cross-origin requests (repeatable; GET and POST by default)
+    #[arg(long = "rpc-cors-method", value_name = "METHOD")]
+    rpc_cors_methods: Vec<String>,
+
+    /// Request header allowed in cross-origin requests (repeatable; Content-Type, Authorization, X-API-Key and Range by default)
+    #[arg(long = "rpc-cors-header", value_name = "HEADER")]
+    rpc_cors_headers: Vec<String>,
+
+    /// Let any web page call the RPC server from a browser; for local development only
+    #[arg(long, conflicts_with_all = ["rpc_cors_origins", "rpc_cors_methods", "rpc_cors_headers"])]
+    dev_cors: bool,
+
+    /// Don't open the node's wallet (the wallet_* RPCs are refused)
+    #[arg(long)]
+    disable_wallet: bool,
+
+    /// JSON file with extra checkpoints (`[{"height": .., "hash": ".."}]`) on top of the embedded ones
+    #[arg(long, value_name = "FILE")]
+    checkpoints_file: Option<PathBuf>,
+
+    /// Address for the Stratum mining server (disabled unless set)
+    #[arg(long, value_name = "ADDR")]
+    stratum_addr: Option<String>,
+
+    /// Mine blocks in the node itself (Proof-of-Work chains); pause and resume with the pause_mining/resume_mining RPCs
+    #[arg(long)]
+    mine: bool,
+
+    /// Address paid the subsidy and fees of blocks mined by this node or its Stratum workers (no coinbase unless set)
+    #[arg(long, value_name = "ADDRESS")]
+    miner_address: Option<String>,
+
+    /// Compact share target handed to Stratum workers
+    #[arg(long, value_name = "BITS", default_value_t = stratum::DEFAULT_SHARE_BITS)]
+    stratum_share_bits: u32,
+
+    /// Maintain a transaction index (tx hash -> block and position) for fast get_transaction
+    #[arg(long)]
+    tx_index: bool,
+
+    /// Maintain the chain index in <data-dir>/index (balances, transactions by address, token holders, payloads) for explorer queries
+    #[arg(long)]
+    indexer: bool,
+
+    /// Number of decoded blocks kept in the in-memory cache (0 disables it)
+    #[arg(long, value_name = "BLOCKS", default_value_t = DEFAULT_BLOCK_CACHE_CAPACITY)]
+    block_cache_size: usize,
+
+    /// Compress blocks written from now on with zstd at this level (1-22); existing blocks stay readable either way
+    #[arg(long, value_name = "LEVEL")]
+    block_compression_level: Option<i32>,
+
+    /// Train a zstd dictionary on recent blocks at startup and compress new blocks with it (once per database)
+    #[arg(long, requires = "block_compression_level")]
+    train_block_dictionary: bool,
+
+    /// Drop mempool transactions that have not been mined after this many seconds (0 keeps them until mined)
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_MEMPOOL_EXPIRY)]
+    mempool_expiry: u64,
+
+    /// Memory budget for pending transactions, in serialized bytes; the oldest are evicted beyond it
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MEMPOOL_MAX_BYTES)]
+    mempool_max_bytes: usize,
+
+    /// Most pending transactions the mempool accepts from one sender
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_MAX_PENDING_PER_SENDER)]
+    mempool_max_per_sender: usize,
+
+    /// Most bytes of pending transactions the mempool accepts from one sender
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_PENDING_BYTES_PER_SENDER)]
+    mempool_max_bytes_per_sender: usize,
+
+    /// Delete anchored payloads this many blocks after the block that anchored them (kept forever if unset)
+    #[arg(long, value_name = "BLOCKS")]
+    payload_retention_blocks: Option<u64>,
+
+    /// Delete payloads no transaction references once they are this old
+    #[arg(long, value_name = "SECS", default_value_t = RetentionPolicy::default().orphan_grace_secs)]
+    orphan_payload_grace_secs: u64,
+
+    /// Charge peers this much per payload served, paid by a transfer to --retrieval-payee (served free if unset)
+    #[arg(long, value_name = "AMOUNT", requires = "retrieval_payee")]
+    retrieval_price: Option<u64>,
+
+    /// Address peers pay for the payloads this node serves
+    #[arg(long, value_name = "ADDRESS", requires = "retrieval_price")]
+    retrieval_payee: Option<String>,
 
-    // #[arg(short, long, value_name = "MULTIADDR")]
-    // bootstrap_peer: Option<String>,
+    /// Seconds between audits of the payloads this node keeps: local copies are hashed and their other providers challenged (0 disables)
+    #[arg(long, value_name = "SECS", default_value_t = audit::DEFAULT_AUDIT_INTERVAL_SECS)]
+    audit_interval: u64,
+
+    /// Payloads sampled in each audit round
+    #[arg(long, value_name = "COUNT", default_value_t = audit::DEFAULT_AUDIT_SAMPLE_SIZE)]
+    audit_sample_size: usize,
+
+    /// Seconds between reconciliations of payload placement against the redundancy policies stored payloads asked for (0 disables)
+    #[arg(long, value_name = "SECS", default_value_t = redundancy::DEFAULT_REPLICATION_INTERVAL_SECS)]
+    replication_interval: u64,
+
+    /// Refuse payloads that would take their namespace past this many bytes, counting confirmed and pending ones (needs --indexer)
+    #[arg(long, value_name = "BYTES")]
+    namespace_quota_bytes: Option<u64>,
+
+    /// Initialize the data directory from a database snapshot (see the create_snapshot RPC) before starting
+    #[arg(long, value_name = "DIR")]
+    restore_snapshot: Option<PathBuf>,
+
+    /// Directory the create_snapshot and create_backup RPCs write into [default: backups in the data directory]
+    #[arg(long, value_name = "DIR")]
+    backup_dir: Option<PathBuf>,
+
+    /// Write the process id to this file while the node runs
+    #[arg(long, value_name = "FILE")]
+    pid_file: Option<PathBuf>,
+
+    /// Also write the log as JSON lines to logs/node.log in the data directory, rotated by size (and age)
+    #[arg(long)]
+    log_file: bool,
+
+    /// Rotate the log file before it grows beyond this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_LOG_MAX_SIZE)]
+    log_max_size: u64,
+
+    /// Also rotate the log file once it is this many seconds old
+    #[arg(long, value_name = "SECONDS")]
+    log_rotate_secs: Option<u64>,
+
+    /// Rotated log files to keep; older ones are deleted
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_LOG_MAX_FILES)]
+    log_max_files: usize,
+
+    /// P2P listen address (repeatable; all interfaces on an OS-chosen port by default)
+    #[arg(long = "p2p-listen", value_name = "MULTIADDR")]
+    p2p_listen: Vec<String>,
+
+    /// Peer to join the network through, ending in /p2p/<peer id> (repeatable)
+    #[arg(long = "bootstrap-peer", value_name = "MULTIADDR")]
+    bootstrap_peers: Vec<String>,
+
+    /// Command to run; without one the node runs, as with `run`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// The node itself, and maintenance commands on the data directory of a stopped node.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create the data directory with the genesis block and node key, without starting the node
+    Init,
+    /// Run the node (the default when no command is given)
+    Run,
+    /// Write main chain blocks to a flat file, for import-chain on another node
+    ExportChain {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// First height to export
+        #[arg(long, value_name = "HEIGHT", default_value_t = 0)]
+        from: u64,
+        /// Last height to export (the tip by default)
+        #[arg(long, value_name = "HEIGHT")]
+        to: Option<u64>,
+    },
+    /// Validate and append the blocks of a file written by export-chain
+    ImportChain {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Write a backup archive (database checkpoint and off-chain payloads); use the create_backup RPC while the node runs
+    Backup {
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+    },
+    /// Verify a backup archive and restore it into an empty data directory
+    Restore {
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+    },
+    /// Rebuild the chain index (<data-dir>/index) from the stored chain
+    Reindex,
+    /// Re-validate the stored chain from genesis and report the first corrupt block
+    VerifyChain {
+        /// Drop the corrupt block and everything above it
+        #[arg(long)]
+        truncate: bool,
+    },
+    /// Print the seed phrase of the node's wallet; writing it down is enough to restore every address
+    WalletMnemonic {
+        /// File holding the password of an encrypted wallet
+        #[arg(long, value_name = "FILE")]
+        password_file: Option<PathBuf>,
+    },
+    /// Sign a raw transaction (hex, from create_raw_transaction) read from standard input with the
+    /// wallet in the data directory, without a running node, and print it for send_raw_transaction
+    SignRawTransaction {
+        /// File holding the password of an encrypted wallet
+        #[arg(long, value_name = "FILE")]
+        password_file: Option<PathBuf>,
+    },
+    /// Recreate the node's wallet from a seed phrase read from standard input
+    RestoreWallet {
+        /// File holding the BIP39 passphrase the phrase was used with, if any
+        #[arg(long, value_name = "FILE")]
+        passphrase_file: Option<PathBuf>,
+        /// Encrypt the restored wallet with the password in this file
+        #[arg(long, value_name = "FILE")]
+        password_file: Option<PathBuf>,
+    },
+    /// Client commands against a running node's RPC server
+    Wallet {
+        /// RPC server of the node (the network's default RPC address if unset)
+        #[arg(long, value_name = "ADDR")]
+        node: Option<String>,
+        /// API key for nodes running in multi-tenant mode
+        #[arg(long, value_name = "KEY")]
+        api_key: Option<String>,
+        #[command(subcommand)]
+        command: WalletCommand,
+    },
+    /// Header-only light client, validating headers and transactions fetched from a full node
+    Light {
+        /// RPC server of the full node (the network's default RPC address if unset)
+        #[arg(long, value_name = "ADDR")]
+        node: Option<String>,
+        /// API key for nodes running in multi-tenant mode
+        #[arg(long, value_name = "KEY")]
+        api_key: Option<String>,
+        #[command(subcommand)]
+        command: LightCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum LightCommand {
+    /// Download and validate the node's headers up to its tip
+    Sync {
+        /// Keep following the node's tip instead of returning once synced
+        #[arg(long)]
+        follow: bool,
+    },
+    /// Check that a transaction is included in a block of the validated headers
+    VerifyTx {
+        /// Transaction hash (hex)
+        #[arg(value_name = "TX_HASH")]
+        tx_hash: String,
+    },
+    /// Show the validated header chain
+    Status,
 }
 
+#[derive(Subcommand, Debug)]
+enum WalletCommand {
+    /// Create a new address in the node's wallet
+    Create {
+        /// Key algorithm: ed25519 or secp256k1
+        #[arg(long, value_name = "ALGORITHM", default_value = "ed25519")]
+        algorithm: String,
+        #[arg(long, value_name = "LABEL")]
+        label: Option<String>,
+    },
+    /// Show the native balance of an address
+    Balance {
+        /// Address (bech32m, or legacy hex)
+        #[arg(value_name = "ADDRESS")]
+        address: String,
+    },
+    /// Send native coins from an address of the node's wallet
+    Send {
+        /// Recipient address
+        #[arg(long, value_name = "ADDRESS")]
+        to: String,
+        #[arg(long, value_name = "AMOUNT")]
+        amount: u64,
+        /// Sender address (the wallet's first address by default)
+        #[arg(long, value_name = "ADDRESS")]
+        from: Option<String>,
+        /// Fee offered to the block producer
+        #[arg(long, value_name = "FEE", default_value_t = 0)]
+        fee: u64,
+    },
+    /// Upload a file, pay for its storage and wait until the storage transactions are final
+    StoreFile {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        /// Address paying for the storage
+        #[arg(long, value_name = "ADDRESS")]
+        sender: String,
+        /// Fee offered per byte of each storage transaction; higher rates confirm first
+        #[arg(long, value_name = "FEE", default_value_t = 0)]
+        fee_rate: u64,
+        /// Return once the transactions are submitted instead of waiting for finality
+        #[arg(long)]
+        no_wait: bool,
+        /// Give up waiting for finality after this many seconds
+        #[arg(long, value_name = "SECONDS", default_value_t = 3600)]
+        timeout: u64,
+    },
+}
+
+/// Off-chain storage lives in this subdirectory of the data directory.
+const OFFCHAIN_DIR: &str = "offchain_data";
+/// Default directory, inside the data directory, for snapshots and backups requested over RPC.
+const BACKUP_DIR: &str = "backups";
+
+/// The node's identity key, in the data directory.
+const NODE_KEY_FILE: &str = "node_key";
+
+/// How long shutdown waits for stopped services to release the blockchain before closing it.
+const CLOSE_WAIT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Mempool saved on shutdown and restored on the next start, in the data directory.
+const MEMPOOL_FILE: &str = "mempool.dat";
+
+/// How often expired mempool transactions are swept.
+const MEMPOOL_EXPIRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How often expired and orphaned payloads are garbage collected.
+const PAYLOAD_GC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logger
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+async fn main() -> ExitCode {
+    // Initialize logger; the log file, if enabled, is opened once the data directory is known
+    logging::init();
+
+    // Parse command-line arguments, then fill the ones not given from the config file
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let result = match (load_settings(&mut cli, &matches), &cli.command) {
+        (Err(e), _) => Err(e),
+        (Ok(()), None | Some(Command::Run)) => run(cli).await,
+        (Ok(()), Some(command)) => run_command(&cli.data_dir, cli.network, command),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-    // Parse command-line arguments
-    let cli = Cli::parse();
+/// Settles the flags not given on the command line: from the config file if it has them,
+/// else from the network preset. The preset's data lives in its subdirectory of --data-dir.
+fn load_settings(cli: &mut Cli, matches: &ArgMatches) -> Result<(), NodeError> {
+    let config = match &cli.config {
+        Some(path) => Some(NodeConfig::from_file(path).map_err(|e| NodeError::Config(e.to_string()))?),
+        None => None,
+    };
+    if let Some(network) = config.as_ref().and_then(|config| config.network) {
+        if matches.value_source("network") != Some(ValueSource::CommandLine) {
+            cli.network = network;
+        }
+    }
+    if matches.value_source("rpc_addr") != Some(ValueSource::CommandLine) {
+        cli.rpc_addr = cli.network.rpc_addr().to_string();
+    }
+    if matches.value_source("p2p_listen") != Some(ValueSource::CommandLine) {
+        cli.p2p_listen = vec![cli.network.p2p_listen_addr().to_string()];
+    }
+    if let Some(config) = config {
+        apply_config_file(cli, matches, config);
+    }
+    cli.data_dir = cli.network.data_dir(&cli.data_dir);
+    Ok(())
+}
+
+/// Fills every flag not given on the command line from the config file's value, if it has one.
+fn apply_config_file(cli: &mut Cli, matches: &ArgMatches, config: NodeConfig) {
+    macro_rules! fill {
+        ($field:ident, $value:expr) => {
+            if let Some(value) = $value {
+                if matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine) {
+                    cli.$field = value;
+                }
+            }
+        };
+    }
+    fill!(data_dir, config.data_dir);
+    fill!(pid_file, config.pid_file.map(Some));
+    fill!(rpc_addr, config.rpc.bind);
+    fill!(rpc_auth_file, config.rpc.auth_file.map(Some));
+    fill!(tenants_file, config.rpc.tenants_file.map(Some));
+    fill!(rpc_rate_limit, config.rpc.rate_limit.map(Some));
+    fill!(rpc_credential_rate_limit, config.rpc.credential_rate_limit.map(Some));
+    fill!(rpc_cors_origins, config.rpc.cors_origins);
+    fill!(rpc_cors_methods, config.rpc.cors_methods);
+    fill!(rpc_cors_headers, config.rpc.cors_headers);
+    fill!(dev_cors, config.rpc.dev_cors);
+    fill!(p2p_listen, config.p2p.listen);
+    fill!(bootstrap_peers, config.p2p.bootstrap_peers);
+    fill!(stratum_addr, config.mining.stratum_addr.map(Some));
+    fill!(stratum_share_bits, config.mining.stratum_share_bits);
+    fill!(mine, config.mining.mine);
+    fill!(miner_address, config.mining.miner_address.map(Some));
+    fill!(tx_index, config.storage.tx_index);
+    fill!(indexer, config.storage.indexer);
+    fill!(block_cache_size, config.storage.block_cache_size);
+    fill!(block_compression_level, config.storage.block_compression_level.map(Some));
+    fill!(train_block_dictionary, config.storage.train_block_dictionary);
+    fill!(payload_retention_blocks, config.storage.payload_retention_blocks.map(Some));
+    fill!(orphan_payload_grace_secs, config.storage.orphan_payload_grace_secs);
+    fill!(checkpoints_file, config.storage.checkpoints_file.map(Some));
+    fill!(backup_dir, config.storage.backup_dir.map(Some));
+    fill!(retrieval_price, config.storage.retrieval_price.map(Some));
+    fill!(retrieval_payee, config.storage.retrieval_payee.map(Some));
+    fill!(audit_interval, config.storage.audit_interval_secs);
+    fill!(audit_sample_size, config.storage.audit_sample_size);
+    fill!(replication_interval, config.storage.replication_interval_secs);
+    fill!(namespace_quota_bytes, config.storage.namespace_quota_bytes.map(Some));
+    fill!(mempool_expiry, config.mempool.expiry_secs);
+    fill!(mempool_max_bytes, config.mempool.max_bytes);
+    fill!(mempool_max_per_sender, config.mempool.max_per_sender);
+    fill!(mempool_max_bytes_per_sender, config.mempool.max_bytes_per_sender);
+    fill!(disable_wallet, config.wallet.disabled);
+    fill!(log_file, config.logging.file);
+    fill!(log_max_size, config.logging.max_size);
+    fill!(log_rotate_secs, config.logging.rotate_secs.map(Some));
+    fill!(log_max_files, config.logging.max_files);
+}
+
+/// Runs a maintenance command against `data_dir`.
+fn run_command(data_dir: &Path, network: NetworkPreset, command: &Command) -> Result<(), NodeError> {
+    let offchain_dir = data_dir.join(OFFCHAIN_DIR);
+    // Commands that open the database or payloads must not run beside a node using them
+    let _data_dir_lock = match command {
+        Command::Init | Command::ExportChain { .. } | Command::ImportChain { .. } | Command::Backup { .. }
+        | Command::Restore { .. } | Command::Reindex | Command::VerifyChain { .. } | Command::RestoreWallet { .. } => Some(lock_data_dir(data_dir)?),
+        // The light client's headers live apart from the node's database, so both can run
+        Command::Light { .. } => Some(lock_data_dir(&data_dir.join(light_client::LIGHT_DIR))?),
+        _ => None,
+    };
+    match command {
+        Command::Init => {
+            let mut blockchain = Blockchain::new_with_config(data_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the database (stop the node first): {}", e)))?;
+            if blockchain.get_chain_height().is_some() {
+                info!("{:?} already holds a chain at height {:?}", data_dir, blockchain.get_chain_height());
+            }
+            blockchain.initialize_genesis_if_needed()
+                .map_err(|e| NodeError::Database(format!("Failed during genesis block check/initialization: {}", e)))?;
+            std::fs::create_dir_all(&offchain_dir)
+                .map_err(|e| NodeError::Database(format!("Cannot create {:?}: {}", offchain_dir, e)))?;
+            let node_key_path = data_dir.join(NODE_KEY_FILE);
+            let node_key = manifest::load_or_create_node_key(&node_key_path)
+                .map_err(|e| NodeError::Database(format!("Failed to load node key {:?}: {}", node_key_path, e)))?;
+            info!("Initialized {:?}; node key {}", data_dir, hex::encode(node_key.verifying_key().to_bytes()));
+        }
+        Command::Run => unreachable!("run is handled by main"),
+        Command::ExportChain { file, from, to } => {
+            let blockchain = Blockchain::new_with_config(data_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the database (stop the node first): {}", e)))?;
+            let out = std::fs::File::create(file)
+                .map_err(|e| NodeError::Config(format!("Cannot create {:?}: {}", file, e)))?;
+            let written = chain_export::export_chain(&blockchain, out, *from, *to)
+                .map_err(|e| NodeError::Runtime(format!("Export failed: {}", e)))?;
+            info!("Exported {} blocks to {:?}", written, file);
+        }
+        Command::ImportChain { file } => {
+            let mut blockchain = Blockchain::new_with_config(data_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the database (stop the node first): {}", e)))?;
+            blockchain.initialize_genesis_if_needed()
+                .map_err(|e| NodeError::Database(format!("Failed during genesis block check/initialization: {}", e)))?;
+            let input = std::fs::File::open(file)
+                .map_err(|e| NodeError::Config(format!("Cannot open {:?}: {}", file, e)))?;
+            let summary = chain_export::import_chain(&mut blockchain, input)
+                .map_err(|e| NodeError::Database(format!("Import of {:?} stopped at height {:?}: {}", file, blockchain.get_chain_height(), e)))?;
+            info!(
+                "Imported {} blocks from {:?} ({} already held); chain height is now {:?}",
+                summary.imported, file, summary.skipped, blockchain.get_chain_height()
+            );
+        }
+        Command::Backup { archive } => {
+            let blockchain = Blockchain::new_with_config(data_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the database (if the node is running, use the create_backup RPC): {}", e)))?;
+            let offchain_storage = OffChainStorageManager::new(&offchain_dir)
+                .map_err(|e| NodeError::Database(format!("Failed to open off-chain storage: {}", e)))?;
+            let manifest = backup::create_backup(&Mutex::new(blockchain), &offchain_storage, archive, unix_now())
+                .map_err(|e| NodeError::Runtime(format!("Backup failed: {}", e)))?;
+            info!("Backup written to {:?} ({} files, {} bytes)", archive, manifest.files.len(), manifest.total_size());
+        }
+        Command::Restore { archive } => {
+            let manifest = backup::restore_backup(archive, data_dir, &offchain_dir)
+                .map_err(|e| NodeError::Config(format!("Cannot restore backup {:?}: {}", archive, e)))?;
+            info!("Restored backup of height {:?} into {:?}", manifest.chain_height, data_dir);
+        }
+        Command::Reindex => {
+            let blockchain = Blockchain::new_with_config(data_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the database (stop the node first): {}", e)))?;
+            let index_dir = data_dir.join(indexer::INDEX_DIR);
+            let indexed = indexer::reindex(&index_dir, &blockchain)
+                .map_err(|e| NodeError::Database(format!("Reindexing failed: {}", e)))?;
+            info!("Rebuilt the chain index in {:?} from {} blocks", index_dir, indexed);
+        }
+        Command::VerifyChain { truncate } => {
+            let mut blockchain = Blockchain::new_with_config(data_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the database (stop the node first): {}", e)))?;
+            let verification = blockchain.verify_chain()
+                .map_err(|e| NodeError::Database(format!("Chain verification failed: {}", e)))?;
+            let Some(corruption) = verification.corruption else {
+                info!("Chain verified up to height {:?}", verification.verified_height);
+                return Ok(());
+            };
+            error!("First corrupt block at height {}: {}", corruption.height, corruption.reason);
+            if !*truncate {
+                return Err(NodeError::Database(format!(
+                    "Chain is corrupt from height {}; rerun with --truncate to cut it back to height {:?}",
+                    corruption.height, verification.verified_height
+                )));
+            }
+            blockchain.truncate_chain(corruption.height)
+                .map_err(|e| NodeError::Database(format!("Cannot truncate the chain: {}", e)))?;
+            info!("Chain truncated to height {:?}; the node will resync the rest from peers", verification.verified_height);
+        }
+        Command::WalletMnemonic { password_file } => {
+            let keystore = open_unlocked_wallet(data_dir, password_file.as_deref())?;
+            let mnemonic = keystore.mnemonic().map_err(|e| NodeError::Config(e.to_string()))?;
+            println!("{}", mnemonic.phrase());
+        }
+        Command::SignRawTransaction { password_file } => {
+            let mut raw = String::new();
+            std::io::stdin().read_line(&mut raw)
+                .map_err(|e| NodeError::Config(format!("Cannot read the transaction from standard input: {}", e)))?;
+            let tx = hex::decode(raw.trim()).map_err(|e| e.to_string()).and_then(|bytes| Transaction::from_bytes(&bytes))
+                .map_err(|e| NodeError::Config(format!("Invalid raw transaction: {}", e)))?;
+            let keystore = open_unlocked_wallet(data_dir, password_file.as_deref())?;
+            let signed = keystore.sign_raw_transaction(tx)
+                .map_err(|e| NodeError::Config(format!("Cannot sign the transaction: {}", e)))?;
+            info!("Signed transaction {}", hex::encode(signed.transaction.calculate_hash()));
+            println!("{}", hex::encode(signed.to_bytes()));
+        }
+        Command::RestoreWallet { passphrase_file, password_file } => {
+            let mut phrase = String::new();
+            std::io::stdin().read_line(&mut phrase)
+                .map_err(|e| NodeError::Config(format!("Cannot read the seed phrase from standard input: {}", e)))?;
+            let mnemonic = Mnemonic::parse(&phrase).map_err(|e| NodeError::Config(e.to_string()))?;
+            let passphrase = match passphrase_file {
+                Some(path) => read_secret_file("--passphrase-file", path)?,
+                None => String::new(),
+            };
+            let password = password_file.as_ref().map(|path| read_secret_file("--password-file", path)).transpose()?;
+            // Addresses with transactions on the local chain are the ones worth restoring
+            let blockchain = Blockchain::new_with_config(data_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the database (stop the node first): {}", e)))?;
+            let wallet_dir = data_dir.join(keystore::WALLET_DIR);
+            let keystore = Keystore::restore(&wallet_dir, &mnemonic, &passphrase, password.as_deref(), |address| {
+                blockchain.get_address_transactions(address).is_ok_and(|txs| !txs.is_empty())
+            })
+            .map_err(|e| NodeError::Config(format!("Cannot restore the wallet: {}", e)))?;
+            info!("Restored {} used addresses into {:?}", keystore.addresses().len(), wallet_dir);
+        }
+        Command::Wallet { node, api_key, command } => {
+            let mut client = wallet::RpcClient::new(node.clone().unwrap_or_else(|| network.rpc_addr().to_string()));
+            if let Some(api_key) = api_key {
+                client = client.with_api_key(api_key.clone());
+            }
+            run_wallet_command(&client, command)?;
+        }
+        Command::Light { node, api_key, command } => {
+            let mut client = wallet::RpcClient::new(node.clone().unwrap_or_else(|| network.rpc_addr().to_string()));
+            if let Some(api_key) = api_key {
+                client = client.with_api_key(api_key.clone());
+            }
+            let light_dir = data_dir.join(light_client::LIGHT_DIR);
+            let light = LightClient::open(&light_dir, network.chain_config())
+                .map_err(|e| NodeError::Database(format!("Cannot open the light client headers {:?}: {}", light_dir, e)))?;
+            run_light_command(&light, &client, command)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs a light client command, fetching what it needs from the node behind `client`.
+fn run_light_command(light: &LightClient, client: &wallet::RpcClient, command: &LightCommand) -> Result<(), NodeError> {
+    let tip = || light.tip().map_err(|e| NodeError::Database(e.to_string()));
+    match command {
+        LightCommand::Sync { follow } => loop {
+            let progress = light.sync(client).map_err(|e| NodeError::Runtime(format!("Header sync failed: {}", e)))?;
+            if progress.connected > 0 || !follow {
+                let tip_height = tip()?.map(|(height, _)| height);
+                info!("Synced {} headers ({} replaced by a fork with more work); tip at height {:?}", progress.connected, progress.replaced, tip_height);
+            }
+            if !follow {
+                break;
+            }
+            std::thread::sleep(light_client::POLL_INTERVAL);
+        },
+        LightCommand::VerifyTx { tx_hash } => {
+            let hash = hex::decode(tx_hash).ok().and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| NodeError::Config(format!("Invalid transaction hash {:?}", tx_hash)))?;
+            let verified = light.verify_transaction(client, &hash)
+                .map_err(|e| NodeError::Runtime(format!("Verifying transaction {} failed: {}", tx_hash, e)))?;
+            info!(
+                "Transaction {} is in block {} at height {} ({} confirmations)",
+                tx_hash, hex::encode(verified.block_hash), verified.height, verified.confirmations
+            );
+        }
+        LightCommand::Status => match tip()? {
+            Some((height, header)) => {
+                let chain_work = light.chain_work().map_err(|e| NodeError::Database(e.to_string()))?;
+                info!("Validated headers up to height {} (block {}), chain work {}", height, hex::encode(header.calculate_hash()), chain_work);
+            }
+            None => info!("No headers yet; run light sync"),
+        },
+    }
+    Ok(())
+}
+
+/// Takes the lock on `data_dir`, failing fast if another node or command is using it.
+fn lock_data_dir(data_dir: &Path) -> Result<DataDirLock, NodeError> {
+    DataDirLock::acquire(data_dir).map_err(|e| match e.kind() {
+        std::io::ErrorKind::WouldBlock => NodeError::Config(format!("Data directory {}; stop that node first or use another --data-dir", e)),
+        _ => NodeError::Database(format!("Cannot lock the data directory {:?}: {}", data_dir, e)),
+    })
+}
+
+/// Opens the wallet in `data_dir`, unlocking it with the password in `password_file` if it is encrypted.
+fn open_unlocked_wallet(data_dir: &Path, password_file: Option<&Path>) -> Result<Keystore, NodeError> {
+    let wallet_dir = data_dir.join(keystore::WALLET_DIR);
+    let mut keystore = Keystore::open(&wallet_dir)
+        .map_err(|e| NodeError::Database(format!("Failed to open wallet {:?}: {}", wallet_dir, e)))?;
+    if keystore.is_encrypted() {
+        let path = password_file
+            .ok_or_else(|| NodeError::Config("The wallet is encrypted; pass its password with --password-file".to_string()))?;
+        keystore.unlock(&read_secret_file("--password-file", path)?, None)
+            .map_err(|e| NodeError::Config(format!("Cannot unlock the wallet: {}", e)))?;
+    }
+    Ok(keystore)
+}
+
+/// Reads a password or passphrase kept alone in a file, ignoring the trailing newline.
+fn read_secret_file(flag: &str, path: &Path) -> Result<String, NodeError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| NodeError::Config(format!("Cannot read {} {:?}: {}", flag, path, e)))?;
+    Ok(contents.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Runs a wallet command against the node behind `client`.
+fn run_wallet_command(client: &wallet::RpcClient, command: &WalletCommand) -> Result<(), NodeError> {
+    // Checked against the node's network, so an address of another chain is refused before sending
+    let parse_address = |flag: &str, address: &str| {
+        let hrp = wallet::address_hrp(client).map_err(|e| NodeError::Runtime(format!("Fetching node info failed: {}", e)))?;
+        parse_address_str(&hrp, address).map_err(|e| NodeError::Config(format!("Invalid {} address: {}", flag, e)))
+    };
+    match command {
+        WalletCommand::Create { algorithm, label } => {
+            let address = wallet::create_address(client, Some(algorithm), label.as_deref())
+                .map_err(|e| NodeError::Runtime(format!("Creating an address failed: {}", e)))?;
+            info!("New {} address {} ({})", address.algorithm, address.address, address.derivation_path.as_deref().unwrap_or("random key"));
+        }
+        WalletCommand::Balance { address } => {
+            let balance = wallet::balance(client, &parse_address("ADDRESS", address)?)
+                .map_err(|e| NodeError::Runtime(format!("Fetching the balance failed: {}", e)))?;
+            info!("Balance of {}: {}", address, balance);
+        }
+        WalletCommand::Send { to, amount, from, fee } => {
+            let recipient = parse_address("--to", to)?;
+            let sender = from.as_deref().map(|from| parse_address("--from", from)).transpose()?;
+            let tx_hash = wallet::send(client, sender.as_ref(), &recipient, *amount, *fee)
+                .map_err(|e| NodeError::Runtime(format!("Sending failed: {}", e)))?;
+            info!("Sent {} to {} in transaction {}", amount, to, hex::encode(tx_hash));
+        }
+        WalletCommand::StoreFile { path, sender, fee_rate, no_wait, timeout } => {
+            let sender = parse_address("--sender", sender)?;
+            let options = wallet::StoreFileOptions {
+                fee_rate: *fee_rate,
+                wait: !no_wait,
+                timeout: std::time::Duration::from_secs(*timeout),
+                ..wallet::StoreFileOptions::default()
+            };
+            let stored = wallet::store_file(client, &sender, path, &options, |status| match status {
+                StoreFileStatus::Submitted { payload_hash, tx_hash, size } => {
+                    info!("Uploaded {} bytes as payload {} in transaction {}", size, hex::encode(payload_hash), hex::encode(tx_hash))
+                }
+                StoreFileStatus::Confirming { confirmed, total, finalized_height } => {
+                    info!("{}/{} transactions in blocks (finalized height {:?})", confirmed, total, finalized_height)
+                }
+                StoreFileStatus::Final { height } => info!("All transactions final at height {}", height),
+            })
+            .map_err(|e| NodeError::Runtime(format!("Storing {:?} failed: {}", path, e)))?;
+            info!(
+                "Stored {:?} ({} bytes, {} chunks, fee {}) as {}",
+                path, stored.size, stored.chunk_hashes.len(), stored.fee, hex::encode(stored.root_hash)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks the flags for mistakes before anything is opened, reporting all of them at once.
+fn validate_config(cli: &Cli) -> Result<(), NodeError> {
+    let mut report = ConfigReport::new();
+    report.check_writable_dir("--data-dir", &cli.data_dir);
+    if let Some(pid_file) = &cli.pid_file {
+        let parent = pid_file.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        report.check_writable_dir("--pid-file directory", parent);
+    }
+    if let Some(path) = &cli.rpc_auth_file {
+        report.check_readable_file("--rpc-auth-file", path);
+    }
+    if let Some(path) = &cli.tenants_file {
+        report.check_readable_file("--tenants-file", path);
+    }
+    if let Some(path) = &cli.checkpoints_file {
+        report.check_readable_file("--checkpoints-file", path);
+    }
+
+    let rpc_addr = report.check_listen_addr("--rpc-addr", &cli.rpc_addr);
+    if let Some(stratum_addr) = &cli.stratum_addr {
+        let stratum_addr = report.check_listen_addr("--stratum-addr", stratum_addr);
+        if stratum_addr.is_some() && stratum_addr == rpc_addr {
+            report.push("--stratum-addr and --rpc-addr are the same address", "give the Stratum server its own port");
+        }
+    }
+
+    if let Some(snapshot) = &cli.restore_snapshot {
+        if !backend::is_database_dir(snapshot) {
+            report.push(
+                format!("--restore-snapshot {:?} is not a database snapshot", snapshot),
+                "pass a directory created by the create_snapshot RPC",
+            );
+        }
+        if backend::is_database_dir(&cli.data_dir) {
+            report.push(
+                format!("--restore-snapshot needs an empty --data-dir, but {:?} already holds a database", cli.data_dir),
+                "point --data-dir to a new directory, or drop --restore-snapshot to keep the existing chain",
+            );
+        }
+    }
+    if let Some(level) = cli.block_compression_level {
+        if !(1..=22).contains(&level) {
+            report.push(format!("--block-compression-level {} is outside zstd's range", level), "use a level from 1 to 22, 3 is a good default");
+        }
+    }
+    if cli.rpc_rate_limit == Some(0) || cli.rpc_credential_rate_limit == Some(0) {
+        report.push("--rpc-rate-limit and --rpc-credential-rate-limit must be above 0", "raise the limit, or omit the flag to leave those clients unlimited");
+    }
+    if let Err(e) = CorsPolicy::restricted(&cli.rpc_cors_origins, &cli.rpc_cors_methods, &cli.rpc_cors_headers) {
+        report.push(e.to_string(), "check the --rpc-cors-origin, --rpc-cors-method and --rpc-cors-header values");
+    }
+    // Clap enforces these between flags, but a config file can still combine them
+    if cli.dev_cors && !(cli.rpc_cors_origins.is_empty() && cli.rpc_cors_methods.is_empty() && cli.rpc_cors_headers.is_empty()) {
+        report.push("--dev-cors allows every origin, so the CORS lists would be ignored", "drop dev_cors or the cors_* lists");
+    }
+    if cli.namespace_quota_bytes.is_some() && !cli.indexer {
+        report.push("--namespace-quota-bytes needs --indexer to count what each namespace holds", "enable the indexer, or drop the quota");
+    }
+    if cli.train_block_dictionary && cli.block_compression_level.is_none() {
+        report.push("--train-block-dictionary needs --block-compression-level", "set a compression level, e.g. 3");
+    }
+    if let Err(e) = NetworkConfig::parse(&cli.p2p_listen, &cli.bootstrap_peers) {
+        report.push(e, "use multiaddrs like /ip4/0.0.0.0/tcp/4001; bootstrap peers end in /p2p/<peer id>");
+    }
+    if cli.mempool_max_bytes == 0 {
+        report.push("--mempool-max-bytes must be above 0", "raise the budget, or omit the flag to use the default");
+    } else if cli.mempool_max_bytes_per_sender > cli.mempool_max_bytes {
+        report.push(
+            format!("--mempool-max-bytes-per-sender {} is above the whole --mempool-max-bytes {}", cli.mempool_max_bytes_per_sender, cli.mempool_max_bytes),
+            "lower the per-sender limit or raise the mempool budget",
+        );
+    }
+    if cli.mempool_max_per_sender == 0 || cli.mempool_max_bytes_per_sender == 0 {
+        report.push("--mempool-max-per-sender and --mempool-max-bytes-per-sender must be above 0", "raise the limit, or omit the flag to use the default");
+    }
+    if let Some(address) = &cli.miner_address {
+        if let Err(e) = parse_address_str(cli.network.address_hrp(), address) {
+            report.push(format!("--miner-address {} is not a {} address: {}", address, cli.network, e), "use an address of the node's network, e.g. from `wallet create`");
+        }
+    }
+    match (cli.retrieval_price, &cli.retrieval_payee) {
+        (Some(_), Some(payee)) => {
+            if let Err(e) = parse_address_str(cli.network.address_hrp(), payee) {
+                report.push(format!("--retrieval-payee {} is not a {} address: {}", payee, cli.network, e), "use an address of the node's network, e.g. from `wallet create`");
+            }
+        }
+        (None, None) => {}
+        // Clap enforces this between flags, but a config file can still set one alone
+        _ => report.push("--retrieval-price and --retrieval-payee must be set together", "set both retrieval_price and retrieval_payee, or neither"),
+    }
+    if cli.log_file {
+        report.check_writable_dir("--log-file directory", &cli.data_dir.join(logging::LOG_DIR));
+    }
+    if cli.log_max_size == 0 || cli.log_rotate_secs == Some(0) {
+        report.push("--log-max-size and --log-rotate-secs must be above 0", "raise the limit, or omit the flag to use the default");
+    }
+    if cli.payload_retention_blocks == Some(0) {
+        report.push(
+            "--payload-retention-blocks 0 would delete payloads as soon as they are anchored",
+            "use at least 1, or omit the flag to keep anchored payloads forever",
+        );
+    }
+    report.into_result().map_err(NodeError::Config)
+}
+
+async fn run(cli: Cli) -> Result<(), NodeError> {
+    validate_config(&cli)?;
+    if cli.log_file {
+        let dir = cli.data_dir.join(logging::LOG_DIR);
+        logging::log_to_file(FileLogSettings {
+            dir: dir.clone(),
+            max_size: cli.log_max_size,
+            max_age: cli.log_rotate_secs.map(std::time::Duration::from_secs),
+            max_files: cli.log_max_files,
+        })
+        .map_err(|e| NodeError::Config(format!("Cannot open the log file in {:?}: {}", dir, e)))?;
+    }
     info!("Starting blockchain node...");
+    info!("Network: {}", cli.network);
     info!("Data directory: {:?}", cli.data_dir);
     info!("RPC server address: {}", cli.rpc_addr);
 
+    // Held until the node exits; the file is removed on drop
+    let _pid_file = match &cli.pid_file {
+        Some(path) => Some(PidFile::create(path).map_err(|e| NodeError::Config(format!("Cannot write PID file {:?}: {}", path, e)))?),
+        None => None,
+    };
+    // Likewise held until exit, so no other process opens the same database and payloads
+    let _data_dir_lock = lock_data_dir(&cli.data_dir)?;
+
     // Storage Manager is initialized within Blockchain::new
 
+    if let Some(snapshot) = &cli.restore_snapshot {
+        StorageManager::restore_from_snapshot(snapshot, &cli.data_dir)
+            .map_err(|e| NodeError::Config(format!("Cannot restore snapshot {:?}: {}", snapshot, e)))?;
+        info!("Restored snapshot {:?} into {:?}", snapshot, cli.data_dir);
+    }
+
     // Initialize Blockchain - Pass the data directory path directly
-    let mut blockchain = match Blockchain::new(&cli.data_dir) { // Pass path
+    let mut blockchain = match Blockchain::new_with_config(&cli.data_dir, cli.network.chain_config()) {
         Ok(bc) => {
             info!("Blockchain core initialized successfully.");
             bc
         }
         Err(e) => {
-            error!("Failed to initialize blockchain core: {}", e);
-            return Err(e.into());
+            return Err(NodeError::Database(format!("Failed to initialize blockchain core: {}", e)));
         }
     };
 
+    blockchain.set_block_cache_capacity(cli.block_cache_size);
+
     // Initialize Genesis Block if needed - Adjust match arms for Ok(())
     match blockchain.initialize_genesis_if_needed() {
         Ok(()) => info!("Genesis block checked/initialized successfully."), // Handle Ok(())
         Err(e) => {
-            error!("Failed during genesis block check/initialization: {}", e);
-            return Err(e.into());
+            return Err(NodeError::Database(format!("Failed during genesis block check/initialization: {}", e)));
+        }
+    }
+
+    blockchain.set_mempool_expiry(Some(cli.mempool_expiry).filter(|&expiry| expiry > 0));
+    blockchain.set_mempool_max_bytes(cli.mempool_max_bytes);
+    blockchain.set_mempool_sender_limits(SenderLimits {
+        max_transactions: cli.mempool_max_per_sender,
+        max_bytes: cli.mempool_max_bytes_per_sender,
+    });
+    blockchain.set_block_compression(cli.block_compression_level);
+    if let Some(address) = &cli.miner_address {
+        let miner_address = parse_address_str(cli.network.address_hrp(), address)
+            .map_err(|e| NodeError::Config(format!("Invalid --miner-address: {}", e)))?;
+        blockchain.set_miner_address(Some(miner_address));
+        info!("Block rewards go to {}", address);
+    } else if cli.mine || cli.stratum_addr.is_some() {
+        warn!("No --miner-address set: mined blocks carry no coinbase, so their subsidy and fees are not claimed");
+    }
+    if cli.train_block_dictionary {
+        match blockchain.train_block_dictionary(BLOCK_DICTIONARY_SAMPLES, DEFAULT_DICTIONARY_SIZE) {
+            Ok(true) => info!("Block compression dictionary trained."),
+            Ok(false) => info!("Block compression dictionary already trained, keeping it."),
+            Err(e) => return Err(NodeError::Database(format!("Failed to train the block compression dictionary: {}", e))),
+        }
+    }
+
+    if let Err(e) = blockchain.set_tx_index(cli.tx_index) {
+        return Err(NodeError::Database(format!("Failed to build the transaction index: {}", e)));
+    }
+    let chain_indexer = if cli.indexer {
+        let index_dir = cli.data_dir.join(indexer::INDEX_DIR);
+        let chain_indexer = ChainIndexer::open(&index_dir)
+            .map_err(|e| NodeError::Database(format!("Cannot open the chain index {:?}: {}", index_dir, e)))?;
+        info!("Chain index at {:?}, indexed up to height {:?}", index_dir, chain_indexer.tip().ok().flatten().map(|(height, _)| height));
+        Some(Arc::new(chain_indexer))
+    } else {
+        None
+    };
+
+    // Transactions still pending at the last clean shutdown
+    let mempool_path = cli.data_dir.join(MEMPOOL_FILE);
+    match blockchain.load_mempool(&mempool_path) {
+        Ok(0) => {}
+        Ok(restored) => info!("Restored {} mempool transactions from {:?}", restored, mempool_path),
+        Err(e) => warn!("Discarding the saved mempool {:?}: {}", mempool_path, e),
+    }
+
+    let mut checkpoints = Checkpoints::embedded();
+    if let Some(path) = &cli.checkpoints_file {
+        Checkpoints::from_file(path)
+            .and_then(|file_checkpoints| checkpoints.extend(&file_checkpoints))
+            .map_err(NodeError::Config)?;
+        info!("Loaded checkpoints from {:?}", path);
+    }
+    if !checkpoints.is_empty() {
+        if let Err(e) = blockchain.set_checkpoints(checkpoints) {
+            return Err(NodeError::Config(format!("Stored chain conflicts with the configured checkpoints: {}", e)));
         }
     }
 
     // Inicializar o gerenciador de armazenamento off-chain
-    let offchain_storage_path = cli.data_dir.join("offchain_data");
-    std::fs::create_dir_all(&offchain_storage_path)?;
-    let offchain_storage = Arc::new(OffChainStorageManager::new(&offchain_storage_path)?);
+    let offchain_storage_path = cli.data_dir.join(OFFCHAIN_DIR);
+    std::fs::create_dir_all(&offchain_storage_path)
+        .map_err(|e| NodeError::Database(format!("Cannot create {:?}: {}", offchain_storage_path, e)))?;
+    let offchain_storage = Arc::new(OffChainStorageManager::new(&offchain_storage_path)
+        .map_err(|e| NodeError::Database(format!("Failed to open off-chain storage: {}", e)))?);
     info!("Off-chain storage initialized at {:?}", offchain_storage_path);
 
+    // Node identity key, used to sign payload manifests and storage proofs
+    let node_key_path = cli.data_dir.join(NODE_KEY_FILE);
+    let node_key = Arc::new(manifest::load_or_create_node_key(&node_key_path)
+        .map_err(|e| NodeError::Database(format!("Failed to load node key {:?}: {}", node_key_path, e)))?);
+    info!("Node key: {}", hex::encode(node_key.verifying_key().to_bytes()));
+
+    if cli.mine && blockchain.chain_config().consensus_mode != ConsensusMode::ProofOfWork {
+        return Err(NodeError::Config("--mine only works on Proof-of-Work chains".to_string()));
+    }
+
     // Wrap Blockchain in Arc<Mutex> for safe sharing
     let blockchain_arc = Arc::new(Mutex::new(blockchain));
     info!("Blockchain state prepared for concurrent access.");
 
-    // Iniciar o servidor RPC em uma thread separada (não em uma task do Tokio)
+    // Handshake aggregates written by the network node and read by the RPC server
+    let peer_observations = Arc::new(Mutex::new(PeerObservations::new()));
+    // Blocks and transactions produced locally (e.g. submitted over RPC) go out through the network node
+    let (outbound_tx, outbound_rx) = network::outbound_channel();
+    let stratum_outbound_tx = outbound_tx.clone();
+    let miner_outbound_tx = outbound_tx.clone();
+    let audit_outbound_tx = outbound_tx.clone();
+    let replication_outbound_tx = outbound_tx.clone();
+
     let rpc_blockchain = blockchain_arc.clone();
     let rpc_offchain_storage = offchain_storage.clone();
     let rpc_addr = cli.rpc_addr.clone();
+    let rpc_peer_observations = peer_observations.clone();
+    let retention = RetentionPolicy {
+        retention_blocks: cli.payload_retention_blocks,
+        orphan_grace_secs: cli.orphan_payload_grace_secs,
+    };
+    // Storage proofs audited by the network node and scheduled audits, reported by get_payload_availability and audit_report
+    let proof_auditor = Arc::new(Mutex::new(ProofAuditor::new()));
+    let rpc_proof_auditor = proof_auditor.clone();
+    let network_node_key = node_key.clone();
+    let audit_node_key = node_key.clone();
+    let replication_node_key = node_key.clone();
+
+    let tenants = match &cli.tenants_file {
+        Some(path) => {
+            let manager = TenantManager::from_file(path).map_err(|e| NodeError::Config(e.to_string()))?;
+            info!("Multi-tenant mode enabled with tenants from {:?}", path);
+            Some(Arc::new(manager))
+        }
+        None => None,
+    };
+    let rpc_auth = match &cli.rpc_auth_file {
+        Some(path) => {
+            let auth = RpcAuth::from_file(path).map_err(|e| NodeError::Config(e.to_string()))?;
+            info!("RPC authentication enabled with credentials from {:?}", path);
+            Some(Arc::new(auth))
+        }
+        None => None,
+    };
+    let rate_limiter = match (cli.rpc_rate_limit, cli.rpc_credential_rate_limit) {
+        (None, None) => None,
+        (per_ip, per_credential) => {
+            info!("RPC rate limits: {:?} requests per minute per IP, {:?} per credential", per_ip, per_credential);
+            Some(Arc::new(RateLimiter::new(RateLimits { per_ip, per_credential })))
+        }
+    };
+    let rpc_wallet = if cli.disable_wallet {
+        None
+    } else {
+        let wallet_dir = cli.data_dir.join(keystore::WALLET_DIR);
+        let keystore = Keystore::open(&wallet_dir).map_err(|e| NodeError::Database(format!("Failed to open wallet {:?}: {}", wallet_dir, e)))?;
+        info!("Wallet opened at {:?} with {} addresses", wallet_dir, keystore.addresses().len());
+        if keystore.is_encrypted() {
+            info!("Wallet is encrypted and locked; unlock it with the wallet_unlock RPC before signing");
+        }
+        Some(Arc::new(Mutex::new(keystore)))
+    };
+    let cors = if cli.dev_cors {
+        info!("RPC CORS: any origin allowed (--dev-cors)");
+        CorsPolicy::Permissive
+    } else {
+        CorsPolicy::restricted(&cli.rpc_cors_origins, &cli.rpc_cors_methods, &cli.rpc_cors_headers)
+            .map_err(|e| NodeError::Config(e.to_string()))?
+    };
     
-    // Usando uma thread std para o servidor RPC
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async {
-            info!("Starting RPC server on {}", rpc_addr);
-            if let Err(e) = rpc::start_rpc_server(rpc_addr, rpc_blockchain, rpc_offchain_storage).await {
-                error!("RPC server error: {}", e);
-            }
-        });
+    // Every subsystem runs under the supervisor, which restarts it if it panics or fails
+    // and stops them in spawn order on shutdown: new RPC requests first (in-flight ones
+    // finish), then mining and background jobs, then the network
+    let mut supervisor = Supervisor::new();
+
+    let network = cli.network;
+    let miner = cli.mine.then(|| Arc::new(MinerControl::default()));
+    let mut rpc_state = rpc::AppState::new(rpc_blockchain, rpc_offchain_storage, rpc_peer_observations, tenants, outbound_tx, node_key)
+        .with_proof_auditor(rpc_proof_auditor)
+        .with_retention(retention)
+        .with_network(network);
+    if let Some(wallet) = rpc_wallet {
+        rpc_state = rpc_state.with_wallet(wallet);
+    }
+    if let Some(miner) = miner.clone() {
+        rpc_state = rpc_state.with_miner(miner);
+    }
+    if let Some(indexer) = chain_indexer.clone() {
+        rpc_state = rpc_state.with_indexer(indexer);
+    }
+    if let Some(quota_bytes) = cli.namespace_quota_bytes {
+        rpc_state = rpc_state.with_namespace_quota(quota_bytes);
+    }
+    let backup_dir = cli.backup_dir.clone().unwrap_or_else(|| cli.data_dir.join(BACKUP_DIR));
+    info!("Snapshots and backups requested over RPC are written under {:?}", backup_dir);
+    rpc_state = rpc_state.with_backup_dir(backup_dir);
+    let (rpc_bound_tx, rpc_bound_rx) = tokio::sync::oneshot::channel();
+    let mut rpc_bound_tx = Some(rpc_bound_tx);
+    supervisor.spawn("rpc", RestartPolicy::Restart, move |shutdown| {
+        let server = rpc::start_rpc_server(
+            rpc_addr.clone(), rpc_state.clone(), rpc_auth.clone(), rate_limiter.clone(), cors.clone(), rpc_bound_tx.take(), shutdown,
+        );
+        async move { server.await.map_err(|e| e.to_string()) }
     });
+    if rpc_bound_rx.await.is_err() {
+        supervisor.shutdown().await;
+        return Err(NodeError::Config(format!("RPC server could not bind {}", cli.rpc_addr)));
+    }
 
-    info!("Node initialization complete. Starting network loop...");
+    if let Some(stratum_addr) = cli.stratum_addr.clone() {
+        let server = StratumServer::new(blockchain_arc.clone(), Some(stratum_outbound_tx), cli.stratum_share_bits);
+        supervisor.spawn("stratum", RestartPolicy::Restart, move |shutdown| {
+            let (server, stratum_addr) = (server.clone(), stratum_addr.clone());
+            async move { server.run(stratum_addr, shutdown).await.map_err(|e| e.to_string()) }
+        });
+    }
+
+    if let Some(control) = miner {
+        let miner_blockchain = blockchain_arc.clone();
+        supervisor.spawn("miner", RestartPolicy::Restart, move |shutdown| {
+            miner::run_miner(miner_blockchain.clone(), control.clone(), miner_outbound_tx.clone(), shutdown)
+        });
+    }
+
+    if let Some(chain_indexer) = chain_indexer {
+        let indexer_blockchain = blockchain_arc.clone();
+        supervisor.spawn("indexer", RestartPolicy::Restart, move |shutdown| {
+            indexer::run_indexer(chain_indexer.clone(), indexer_blockchain.clone(), shutdown)
+        });
+    }
 
-    // Executar o nó de rede com tratamento de sinal para encerramento
-    select! {
-        result = network::start_network_node(blockchain_arc) => {
-            if let Err(e) = result {
-                error!("Network node encountered a fatal error: {}", e);
-                return Err(e);
+    let gc_blockchain = blockchain_arc.clone();
+    let gc_offchain_storage = offchain_storage.clone();
+    supervisor.spawn("payload-gc", RestartPolicy::Restart, move |shutdown| {
+        let (gc_blockchain, gc_offchain_storage) = (gc_blockchain.clone(), gc_offchain_storage.clone());
+        async move {
+            let mut interval = tokio::time::interval(PAYLOAD_GC_INTERVAL);
+            loop {
+                select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown.wait() => return Ok(()),
+                }
+                let bc_guard = gc_blockchain.lock().expect("Blockchain lock poisoned");
+                if let Err(e) = gc_offchain_storage.collect_garbage(&bc_guard, &retention, unix_now()) {
+                    error!("Payload garbage collection failed: {}", e);
+                }
             }
-            info!("Network node loop exited gracefully.");
         }
-        _ = ctrl_c() => {
+    });
+
+    if cli.audit_interval > 0 && cli.audit_sample_size > 0 {
+        let audit_blockchain = blockchain_arc.clone();
+        let audit_offchain_storage = offchain_storage.clone();
+        let audit_proof_auditor = proof_auditor.clone();
+        let (audit_interval, audit_sample_size) = (std::time::Duration::from_secs(cli.audit_interval), cli.audit_sample_size);
+        supervisor.spawn("storage-audit", RestartPolicy::Restart, move |shutdown| {
+            let (audit_blockchain, audit_offchain_storage) = (audit_blockchain.clone(), audit_offchain_storage.clone());
+            let (audit_proof_auditor, audit_node_key, audit_outbound_tx) = (audit_proof_auditor.clone(), audit_node_key.clone(), audit_outbound_tx.clone());
+            async move {
+                let mut interval = tokio::time::interval(audit_interval);
+                loop {
+                    select! {
+                        _ = interval.tick() => {}
+                        _ = shutdown.wait() => return Ok(()),
+                    }
+                    let committed = audit::committed_payloads(&audit_blockchain.lock().expect("Blockchain lock poisoned"), &audit_offchain_storage);
+                    let committed = match committed {
+                        Ok(committed) => committed,
+                        Err(e) => {
+                            error!("Storage audit failed: {}", e);
+                            continue;
+                        }
+                    };
+                    let now = unix_now();
+                    let audits = audit::audit_payloads(&committed, &audit_offchain_storage, &audit_node_key, audit_sample_size, now);
+                    let challenges: Vec<_> = audits.iter().map(|(_, challenge)| challenge.clone()).collect();
+                    let corrupted = audits.iter().filter(|(audit, _)| audit.local != audit::LocalCheck::Intact).count();
+                    if corrupted > 0 {
+                        warn!("Storage audit found {} of {} sampled payloads damaged or unreadable", corrupted, audits.len());
+                    }
+                    audit_proof_auditor.lock().expect("Proof auditor lock poisoned").record_audits(audits, now);
+                    for challenge in challenges {
+                        if audit_outbound_tx.send(NetworkMessage::StorageChallenge(challenge)).is_err() {
+                            warn!("Network node is not running; storage challenges were not sent");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    if cli.replication_interval > 0 {
+        let replication_blockchain = blockchain_arc.clone();
+        let replication_offchain_storage = offchain_storage.clone();
+        let replication_proof_auditor = proof_auditor.clone();
+        let replication_interval = std::time::Duration::from_secs(cli.replication_interval);
+        supervisor.spawn("payload-replication", RestartPolicy::Restart, move |shutdown| {
+            let (replication_blockchain, replication_offchain_storage) = (replication_blockchain.clone(), replication_offchain_storage.clone());
+            let (replication_proof_auditor, replication_node_key, replication_outbound_tx) =
+                (replication_proof_auditor.clone(), replication_node_key.clone(), replication_outbound_tx.clone());
+            async move {
+                let mut interval = tokio::time::interval(replication_interval);
+                loop {
+                    select! {
+                        _ = interval.tick() => {}
+                        _ = shutdown.wait() => return Ok(()),
+                    }
+                    let round = {
+                        let bc_guard = replication_blockchain.lock().expect("Blockchain lock poisoned");
+                        let auditor = replication_proof_auditor.lock().expect("Proof auditor lock poisoned");
+                        redundancy::reconcile(&bc_guard, &replication_offchain_storage, &auditor, &replication_node_key, unix_now())
+                    };
+                    let round = match round {
+                        Ok(round) => round,
+                        Err(e) => {
+                            error!("Payload replication round failed: {}", e);
+                            continue;
+                        }
+                    };
+                    let over = round.placements.iter().filter(|(_, placement)| placement.status == network::PlacementStatus::OverReplicated).count();
+                    if let Some(request) = &round.request {
+                        warn!("{} of {} payloads with a redundancy policy have fewer copies than asked for", request.payload_hashes.len(), round.placements.len());
+                    }
+                    if over > 0 {
+                        info!("{} payloads have more copies than their redundancy policies ask for", over);
+                    }
+                    let messages = round.challenges.into_iter().map(NetworkMessage::StorageChallenge)
+                        .chain(round.repairs.into_iter().map(|payload_hash| NetworkMessage::FetchPayload { payload_hash, payment: None }))
+                        .chain(round.request.map(NetworkMessage::ReplicationRequest));
+                    for message in messages {
+                        if replication_outbound_tx.send(message).is_err() {
+                            warn!("Network node is not running; replication messages were not sent");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    if cli.mempool_expiry > 0 {
+        let expiry_blockchain = blockchain_arc.clone();
+        supervisor.spawn("mempool-expiry", RestartPolicy::Restart, move |shutdown| {
+            let expiry_blockchain = expiry_blockchain.clone();
+            async move {
+                let mut interval = tokio::time::interval(MEMPOOL_EXPIRY_INTERVAL);
+                loop {
+                    select! {
+                        _ = interval.tick() => {}
+                        _ = shutdown.wait() => return Ok(()),
+                    }
+                    let expired = expiry_blockchain.lock().expect("Blockchain lock poisoned").expire_mempool(unix_now());
+                    if expired > 0 {
+                        info!("Dropped {} expired transactions from the mempool", expired);
+                    }
+                }
+            }
+        });
+    }
+
+    let mut network_config = NetworkConfig::parse(&cli.p2p_listen, &cli.bootstrap_peers).map_err(NodeError::Config)?;
+    if let (Some(price), Some(payee)) = (cli.retrieval_price, &cli.retrieval_payee) {
+        let payee = parse_address_str(cli.network.address_hrp(), payee)
+            .map_err(|e| NodeError::Config(format!("Invalid --retrieval-payee: {}", e)))?;
+        info!("Charging {} per payload served to peers", price);
+        network_config.retrieval = Some(RetrievalTerms { price, payee });
+    }
+    info!("Node initialization complete. Starting network loop...");
+    // The outbound queue can't be handed to a second instance, so a network failure stops the node
+    let network_blockchain = blockchain_arc.clone();
+    let mut outbound_rx = Some(outbound_rx);
+    supervisor.spawn("network", RestartPolicy::Fatal, move |shutdown| {
+        let outbound_rx = outbound_rx.take().expect("A fatal service is started once");
+        let network = network::start_network_node(
+            network_blockchain.clone(), offchain_storage.clone(), peer_observations.clone(), outbound_rx,
+            network_node_key.clone(), proof_auditor.clone(), network_config.clone(), shutdown,
+        );
+        async move { network.await.map_err(|e| e.to_string()) }
+    });
+    // Genesis is checked and RPC is listening: the node is ready for supervisors
+    service::notify_ready(&format!("RPC listening on {}", cli.rpc_addr));
+
+    let failure = select! {
+        failure = supervisor.failure() => Some(failure),
+        _ = service::shutdown_signal() => {
             info!("Received shutdown signal. Stopping blockchain node...");
+            None
         }
+    };
+    service::notify_stopping();
+    supervisor.shutdown().await;
+    // The state is flushed once nothing else can write to it
+    let closed = close_blockchain(blockchain_arc, &mempool_path).await;
+
+    if let Some(failure) = failure {
+        return Err(NodeError::Runtime(failure.to_string()));
     }
+    closed?;
+    info!("Blockchain node stopped.");
+    Ok(())
+}
 
+/// Saves the mempool and flushes the database, then closes it if no other handle is left.
+async fn close_blockchain(blockchain: Arc<Mutex<Blockchain>>, mempool_path: &Path) -> Result<(), NodeError> {
+    // Stopped services can take a moment to drop their handles (RPC workers end on their own threads)
+    let deadline = std::time::Instant::now() + CLOSE_WAIT;
+    while Arc::strong_count(&blockchain) > 1 && std::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    {
+        let bc_guard = blockchain.lock().expect("Blockchain lock poisoned");
+        match bc_guard.save_mempool(mempool_path) {
+            Ok(saved) => info!("Saved {} mempool transactions to {:?}", saved, mempool_path),
+            Err(e) => warn!("Failed to save the mempool to {:?}: {}", mempool_path, e),
+        }
+        bc_guard.flush().map_err(|e| NodeError::Database(format!("Failed to flush the database: {}", e)))?;
+    }
+    match Arc::try_unwrap(blockchain) {
+        Ok(_) => info!("Database closed."),
+        Err(_) => warn!("Database still referenced by a background task; it is closed when the process exits"),
+    }
     Ok(())
 }
@@ -0,0 +1,193 @@
+//!
+//! # Payload Manifests
+//! Signed inventories of the off-chain payloads a node stores, for external audits.
+//!
+//! A manifest lists every stored payload hash with its size, whether a `StoreData`
+//! transaction on the main chain anchors it and the redundancy policy its client asked
+//! for, if any (see [`redundancy`](crate::network::redundancy)), together with the chain
+//! tip it was taken at. The node signs it with its ed25519 node key, so an auditor
+//! can check the list against on-chain records (and spot-check payloads through
+//! `get_offchain_data`) without shell access to the provider.
+
+use crate::core::snapshot::{hex_array, hex_bytes};
+use crate::core::{Blockchain, BlockchainError, Hash};
+use crate::network::RedundancyPolicy;
+use crate::offchain_storage::{OffChainStorageError, OffChainStorageManager};
+use crate::storage::StorageError;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Domain separator for manifest signatures.
+const MANIFEST_SIGNING_TAG: &[u8] = b"payload-manifest-v1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("Blockchain error: {0}")]
+    Blockchain(#[from] BlockchainError),
+    #[error("Off-chain storage error: {0}")]
+    OffChain(#[from] OffChainStorageError),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// How a stored payload is accounted for on-chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PayloadStatus {
+    /// Referenced by a `StoreData` transaction on the main chain (the earliest one is reported).
+    Anchored {
+        height: u64,
+        #[serde(with = "hex_array")]
+        tx_hash: Hash,
+        /// Size the transaction declared; an auditor should expect it to equal the stored size.
+        declared_size: u64,
+    },
+    /// Stored locally with no on-chain reference.
+    Unanchored,
+}
+
+/// One stored payload.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    #[serde(with = "hex_array")]
+    pub payload_hash: Hash,
+    pub size: u64,
+    #[serde(flatten)]
+    pub status: PayloadStatus,
+    /// Copies the payload's client asked the network to keep.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redundancy: Option<RedundancyPolicy>,
+}
+
+/// A node's payload inventory as of a chain tip, signed by the node key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadManifest {
+    pub chain_height: Option<u64>,
+    pub tip_hash: Option<String>,
+    /// UNIX time the manifest was generated.
+    pub generated_at: u64,
+    /// Entries sorted by payload hash.
+    pub entries: Vec<ManifestEntry>,
+    #[serde(with = "hex_array")]
+    pub signer: [u8; 32],
+    #[serde(with = "hex_bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl PayloadManifest {
+    /// Digest the signature covers: every field except the signature itself.
+    fn signing_digest(&self) -> Result<Hash, ManifestError> {
+        // JSON rather than bincode: bincode cannot encode the flattened entry status
+        let body = serde_json::to_vec(&(&self.chain_height, &self.tip_hash, self.generated_at, &self.entries, &self.signer))?;
+        let mut hasher = Sha256::new();
+        hasher.update(MANIFEST_SIGNING_TAG);
+        hasher.update(body);
+        Ok(hasher.finalize().into())
+    }
+
+    /// Checks the signature against the embedded signer key.
+    pub fn verify(&self) -> bool {
+        let (Ok(key), Ok(signature), Ok(digest)) = (
+            VerifyingKey::from_bytes(&self.signer),
+            Signature::from_slice(&self.signature),
+            self.signing_digest(),
+        ) else {
+            return false;
+        };
+        key.verify(&digest, &signature).is_ok()
+    }
+}
+
+/// Builds and signs the manifest of every payload in `offchain_storage`.
+pub fn export_manifest(
+    blockchain: &Blockchain,
+    offchain_storage: &OffChainStorageManager,
+    node_key: &SigningKey,
+    now: u64,
+) -> Result<PayloadManifest, ManifestError> {
+    let anchors = blockchain.payload_anchors()?;
+    let policies: std::collections::HashMap<Hash, RedundancyPolicy> = blockchain.storage().get_redundancy_policies()?.into_iter().collect();
+    let entries = offchain_storage.list_payloads()?.into_iter().map(|(payload_hash, size)| ManifestEntry {
+        payload_hash,
+        size,
+        status: match anchors.get(&payload_hash) {
+            Some(anchor) => PayloadStatus::Anchored { height: anchor.height, tx_hash: anchor.tx_hash, declared_size: anchor.declared_size },
+            None => PayloadStatus::Unanchored,
+        },
+        redundancy: policies.get(&payload_hash).copied(),
+    }).collect();
+    let mut manifest = PayloadManifest {
+        chain_height: blockchain.get_chain_height(),
+        tip_hash: blockchain.get_last_block_hash().map(hex::encode),
+        generated_at: now,
+        entries,
+        signer: node_key.verifying_key().to_bytes(),
+        signature: Vec::new(),
+    };
+    manifest.signature = node_key.sign(&manifest.signing_digest()?).to_bytes().to_vec();
+    Ok(manifest)
+}
+
+/// Loads the node's ed25519 key from `path` (32 raw secret bytes), generating it on first use.
+pub fn load_or_create_node_key(path: &Path) -> io::Result<SigningKey> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            let secret: [u8; 32] = bytes.as_slice().try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Node key {:?} must be 32 bytes", path)))?;
+            Ok(SigningKey::from_bytes(&secret))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key = SigningKey::generate(&mut rand::rngs::OsRng);
+            fs::write(path, key.to_bytes())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Transaction;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_manifest_reports_anchors_and_verifies() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(&dir.path().join("chain")).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let offchain = OffChainStorageManager::new(dir.path()).unwrap();
+        let anchored = offchain.store_payload(b"anchored payload").unwrap();
+        let loose = offchain.store_payload(b"loose").unwrap();
+        blockchain.storage().put_redundancy_policy(&anchored, &RedundancyPolicy::Replicas(3)).unwrap();
+        blockchain.add_pending_transaction(Transaction::new_store_data(vec![1], anchored, 16)).unwrap();
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+
+        let node_key = load_or_create_node_key(&dir.path().join("node_key")).unwrap();
+        assert_eq!(load_or_create_node_key(&dir.path().join("node_key")).unwrap().to_bytes(), node_key.to_bytes());
+        let manifest = export_manifest(&blockchain, &offchain, &node_key, 1_700_000_000).unwrap();
+        assert!(manifest.verify());
+        assert_eq!(manifest.chain_height, Some(1));
+        let status_of = |hash: Hash| manifest.entries.iter().find(|e| e.payload_hash == hash).unwrap().status.clone();
+        assert!(matches!(status_of(anchored), PayloadStatus::Anchored { height: 1, declared_size: 16, .. }));
+        assert_eq!(status_of(loose), PayloadStatus::Unanchored);
+        let redundancy_of = |hash: Hash| manifest.entries.iter().find(|e| e.payload_hash == hash).unwrap().redundancy;
+        assert_eq!((redundancy_of(anchored), redundancy_of(loose)), (Some(RedundancyPolicy::Replicas(3)), None));
+
+        let mut tampered = manifest.clone();
+        tampered.entries.retain(|e| e.payload_hash != loose);
+        assert!(!tampered.verify());
+    }
+}
@@ -1,16 +1,173 @@
-use crate::core::{Transaction, Hash};
-use std::collections::{HashMap, VecDeque};
-use log::debug;
+use crate::core::{Address, Hash, SignedTransaction, Transaction, TxWitness};
+use crate::core::time::unix_now;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use log::{debug, info};
+use tokio::sync::broadcast;
+
+/// Default time a transaction may wait in the mempool before it is dropped (two weeks).
+pub const DEFAULT_MEMPOOL_EXPIRY: u64 = 14 * 24 * 60 * 60;
+
+/// A replacement must pay at least this much more than the transaction it replaces, in percent
+/// (and at least 1), so a sender can't make every node re-relay it for free.
+pub const MIN_REPLACEMENT_FEE_BUMP_PERCENT: u64 = 10;
+
+/// Default budget for the serialized bytes of all pending transactions.
+pub const DEFAULT_MEMPOOL_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+/// Events buffered per subscriber before the slowest one starts missing them.
+pub const MEMPOOL_EVENT_CAPACITY: usize = 1024;
+
+/// Most future-nonce transactions held in the orphan area across all senders.
+pub const MAX_ORPHAN_TRANSACTIONS: usize = 1000;
+/// How far ahead of its sender's expected nonce a transaction may be and still be held as an orphan.
+pub const MAX_ORPHAN_NONCE_GAP: u64 = 64;
+
+/// Default cap on pending transactions from one sender.
+pub const DEFAULT_MAX_PENDING_PER_SENDER: usize = 100;
+/// Default cap on the serialized bytes of one sender's pending transactions.
+pub const DEFAULT_MAX_PENDING_BYTES_PER_SENDER: usize = 256 * 1024;
+
+/// How much of the mempool a single sender may occupy, so one spammer can't fill it
+/// and push everyone else's transactions out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SenderLimits {
+    pub max_transactions: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for SenderLimits {
+    fn default() -> Self {
+        SenderLimits { max_transactions: DEFAULT_MAX_PENDING_PER_SENDER, max_bytes: DEFAULT_MAX_PENDING_BYTES_PER_SENDER }
+    }
+}
+
+/// Why a transaction left the mempool without being evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// Included in a block.
+    Included,
+    /// Waited longer than the mempool expiry.
+    Expired,
+    /// Replaced by a transaction with the same sender and nonce paying a higher fee.
+    Replaced { by: Hash },
+}
+
+/// A change to the set of pending transactions, published through [`Mempool::subscribe`].
+/// Orphans are not pending yet, so holding one emits nothing; promoting it emits `Added`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MempoolEvent {
+    Added { tx_hash: Hash },
+    Removed { tx_hash: Hash, reason: RemovalReason },
+    /// Pushed out of the full mempool by `by`, which pays a higher fee rate.
+    Evicted { tx_hash: Hash, by: Hash },
+}
+
+/// Details of one pending transaction, as reported by `get_mempool_entry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolEntry {
+    pub transaction: Transaction,
+    pub fee: u64,
+    /// Serialized size in bytes.
+    pub size: usize,
+    /// UNIX time the transaction entered the mempool.
+    pub added_at: u64,
+    /// Seconds the transaction has waited in the mempool.
+    pub time_in_pool: u64,
+    /// Earlier pending transactions this one builds on: ones from the same sender,
+    /// or ones paying the sender. They must confirm first.
+    pub depends: Vec<Hash>,
+    /// Later pending transactions that build on this one.
+    pub spent_by: Vec<Hash>,
+}
+
+/// One pending transaction as listed by [`Mempool::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTransaction {
+    pub tx_hash: Hash,
+    pub transaction: Transaction,
+    pub fee: u64,
+    /// Serialized size in bytes.
+    pub size: usize,
+    /// UNIX time the transaction entered the mempool.
+    pub added_at: u64,
+}
+
+/// Occupancy, fee rates and eviction counters of the mempool, as reported by `get_mempool_info`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MempoolInfo {
+    /// Pending transactions and their serialized bytes, not counting orphans.
+    pub size: usize,
+    pub bytes: usize,
+    pub max_size: usize,
+    pub max_bytes: usize,
+    pub orphans: usize,
+    /// Lowest and median fee per byte of pending transactions; `None` when the mempool is empty.
+    pub min_fee_rate: Option<f64>,
+    pub median_fee_rate: Option<f64>,
+    /// Transactions pushed out by higher fee rates, and dropped for waiting too long, since startup.
+    pub evicted: u64,
+    pub expired: u64,
+}
+
+/// Block inclusion priority of a pending transaction: higher fee per byte first,
+/// then earlier arrival.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Priority {
+    fee: u64,
+    size: usize,
+    sequence: u64,
+}
+
+impl Ord for Priority {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compares fee / size across both sides without rounding
+        let ours = self.fee as u128 * other.size as u128;
+        let theirs = other.fee as u128 * self.size as u128;
+        theirs.cmp(&ours).then(self.sequence.cmp(&other.sequence))
+    }
+}
+
+impl PartialOrd for Priority {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
 /// Manages pending transactions that have not yet been included in a block.
 #[derive(Debug)]
 pub struct Mempool {
     /// Stores transactions keyed by their hash for quick lookup and deduplication.
     transactions: HashMap<Hash, Transaction>,
-    /// Maintains the order in which transactions arrived (FIFO for now).
-    order: VecDeque<Hash>,
+    /// Sender signatures of the pending transactions that came signed, for the blocks that include them.
+    witnesses: HashMap<Hash, TxWitness>,
+    /// Hashes by arrival sequence number, oldest first.
+    order: BTreeMap<u64, Hash>,
+    /// Priority of each transaction, including its arrival sequence number.
+    priorities: HashMap<Hash, Priority>,
+    /// UNIX time each transaction entered the mempool.
+    added_at: HashMap<Hash, u64>,
+    /// Pending transaction of each sender and nonce, for replace-by-fee and finding the expected nonce.
+    nonces: BTreeMap<(Address, u64), Hash>,
+    /// Transactions whose nonce is ahead of their sender's expected one, with their witness and the
+    /// time they arrived. They wait here until the gap is filled.
+    orphans: BTreeMap<(Address, u64), (Transaction, Option<TxWitness>, u64)>,
+    /// Pending transaction count and bytes per sender.
+    sender_usage: HashMap<Address, (usize, usize)>,
+    sender_limits: SenderLimits,
+    next_sequence: u64,
     /// Maximum number of transactions allowed in the mempool.
     max_size: usize,
+    /// Serialized bytes of all pending transactions, not counting orphans.
+    total_bytes: usize,
+    /// Maximum serialized bytes of pending transactions, so a few large ones can't exhaust memory.
+    max_bytes: usize,
+    /// Seconds after which a pending transaction is dropped; `None` keeps it until mined.
+    expiry: Option<u64>,
+    /// Transactions evicted and expired since startup.
+    evicted_count: u64,
+    expired_count: u64,
+    events: broadcast::Sender<MempoolEvent>,
 }
 
 impl Mempool {
@@ -18,9 +175,71 @@ impl Mempool {
     pub fn new(max_size: usize) -> Self {
         Mempool {
             transactions: HashMap::new(),
-            order: VecDeque::new(),
+            witnesses: HashMap::new(),
+            order: BTreeMap::new(),
+            priorities: HashMap::new(),
+            added_at: HashMap::new(),
+            nonces: BTreeMap::new(),
+            orphans: BTreeMap::new(),
+            sender_usage: HashMap::new(),
+            sender_limits: SenderLimits::default(),
+            next_sequence: 0,
             max_size,
+            total_bytes: 0,
+            max_bytes: DEFAULT_MEMPOOL_MAX_BYTES,
+            expiry: Some(DEFAULT_MEMPOOL_EXPIRY),
+            evicted_count: 0,
+            expired_count: 0,
+            events: broadcast::channel(MEMPOOL_EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Subscribes to added, removed and evicted transactions from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<MempoolEvent> {
+        self.events.subscribe()
+    }
+
+    // Publishing fails only when nobody is subscribed
+    fn publish(&self, event: MempoolEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Sets the budget for the serialized bytes of all pending transactions.
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+    }
+
+    pub fn set_sender_limits(&mut self, limits: SenderLimits) {
+        self.sender_limits = limits;
+    }
+
+    /// Sets how many seconds transactions may wait before [`Mempool::expire`] drops them.
+    pub fn set_expiry(&mut self, expiry: Option<u64>) {
+        self.expiry = expiry;
+    }
+
+    /// Drops the transactions that have waited longer than the expiry at `now`, returning their hashes.
+    pub fn expire(&mut self, now: u64) -> Vec<Hash> {
+        let Some(expiry) = self.expiry else {
+            return Vec::new();
+        };
+        let expired: Vec<Hash> = self.order.values()
+            .filter(|hash| self.added_at.get(*hash).is_some_and(|added_at| now.saturating_sub(*added_at) >= expiry))
+            .copied()
+            .collect();
+        if !expired.is_empty() {
+            debug!("Expiring {} transactions from mempool.", expired.len());
+            for tx_hash in &expired {
+                self.remove_with_reason(tx_hash, RemovalReason::Expired);
+            }
+            self.expired_count += expired.len() as u64;
+        }
+        let orphan_count = self.orphans.len();
+        self.orphans.retain(|_, (_, _, added_at)| now.saturating_sub(*added_at) < expiry);
+        if self.orphans.len() < orphan_count {
+            debug!("Expiring {} orphan transactions from mempool.", orphan_count - self.orphans.len());
         }
+        expired
     }
 
     /// Adds a transaction to the mempool if valid and space permits.
@@ -32,8 +251,35 @@ impl Mempool {
     /// # Returns
     ///
     /// * `Ok(bool)` - Returns `Ok(true)` if the transaction was added, `Ok(false)` if it already existed.
-    /// * `Err(String)` - If the transaction is invalid or the mempool is full.
+    /// * `Err(String)` - If the transaction is invalid, larger than the whole mempool byte budget,
+    ///   or the mempool is full of transactions paying at least its fee rate.
+    ///
+    /// When the mempool is at its transaction count or byte budget, expired transactions are
+    /// dropped first and then the lowest fee rate ones until the new transaction fits. A
+    /// transaction paying the same fee rate as the new one or more is never evicted for it.
+    ///
+    /// A transaction with the same sender and nonce as a pending one replaces it if its fee is
+    /// at least [`MIN_REPLACEMENT_FEE_BUMP_PERCENT`] higher, taking its place in the sender's order;
+    /// otherwise it is rejected. Transactions that would take their sender over its
+    /// [`SenderLimits`] are rejected too.
+    ///
+    /// A transaction whose nonce is ahead of the one its sender is expected to use next is held
+    /// as an orphan (and `Ok(true)` returned) until the transactions filling the gap arrive.
 pub fn add_transaction(&mut self, tx: Transaction) -> Result<bool, String> {
+        self.add_transaction_at(tx, None, unix_now())
+    }
+
+    /// Like [`add_transaction`](Self::add_transaction) for a transaction signed by its sender,
+    /// keeping the signature for the block that includes it. The caller checks the signature.
+    pub fn add_signed_transaction(&mut self, signed: SignedTransaction) -> Result<bool, String> {
+        let (tx, witness) = signed.into_parts();
+        self.add_transaction_at(tx, Some(witness), unix_now())
+    }
+
+    /// Like [`add_transaction`](Self::add_transaction), with the sender's `witness` if it came
+    /// signed and recording `added_at` as the UNIX time the transaction arrived; used to restore
+    /// a saved mempool without resetting expiry clocks.
+    pub fn add_transaction_at(&mut self, tx: Transaction, witness: Option<TxWitness>, added_at: u64) -> Result<bool, String> {
         // TODO: Add more sophisticated validation (e.g., signature verification, balance checks)
         let tx_hash = tx.calculate_hash();
 
@@ -41,31 +287,239 @@ pub fn add_transaction(&mut self, tx: Transaction) -> Result<bool, String> {
             debug!("Transaction {} already exists in mempool.", hex::encode(tx_hash));
             return Ok(false); // Indicate transaction already present
         }
+        // Expired transactions make room before anything still live is evicted
+        if self.is_full_for(tx.size()) {
+            self.expire(unix_now());
+        }
 
-        if self.transactions.len() >= self.max_size {
-            // Option 1: Reject new transaction (simple approach)
-            // return Err("Mempool is full".to_string());
+        let replaced = tx.nonce().and_then(|nonce| self.nonces.get(&(tx.sender().clone(), nonce))).copied();
+        if let (None, Some(nonce), Some(expected)) = (replaced, tx.nonce(), self.expected_nonce(tx.sender())) {
+            if nonce > expected {
+                return self.add_orphan(tx, witness, tx_hash, expected, added_at);
+            }
+        }
+        // The sender's orphans hold slots too, so it can't outgrow its limits through them
+        let (mut pending_count, mut pending_bytes) = self.sender_usage.get(tx.sender()).copied().unwrap_or_default();
+        let (orphan_count, orphan_bytes) = self.orphan_usage(tx.sender(), None);
+        pending_count += orphan_count;
+        pending_bytes += orphan_bytes;
+        if let Some(replaced_hash) = replaced {
+            pending_count -= 1;
+            pending_bytes -= self.priorities[&replaced_hash].size;
+        }
+        if pending_count + 1 > self.sender_limits.max_transactions {
+            return Err(format!(
+                "Sender {} already has {} pending transactions (limit {})",
+                hex::encode(tx.sender()), pending_count, self.sender_limits.max_transactions
+            ));
+        }
+        if tx.size() > self.max_bytes {
+            return Err(format!("Transaction of {} bytes exceeds the mempool budget of {} bytes", tx.size(), self.max_bytes));
+        }
+        if pending_bytes + tx.size() > self.sender_limits.max_bytes {
+            return Err(format!(
+                "Sender {} would have {} bytes of pending transactions (limit {})",
+                hex::encode(tx.sender()), pending_bytes + tx.size(), self.sender_limits.max_bytes
+            ));
+        }
 
-            // Option 2: Evict the oldest transaction
-            if let Some(oldest_hash) = self.order.pop_front() {
-                self.transactions.remove(&oldest_hash);
-                debug!("Mempool full. Evicted oldest transaction: {}", hex::encode(oldest_hash));
-            } else {
-                 // Should not happen if len >= max_size and max_size > 0
-                 return Err("Mempool full, but failed to evict oldest transaction".to_string());
+        if let Some(replaced_hash) = replaced {
+            let replaced_fee = self.transactions[&replaced_hash].fee();
+            let required_fee = replacement_fee(replaced_fee);
+            if tx.fee() < required_fee {
+                return Err(format!(
+                    "Transaction conflicts with pending {} (same sender and nonce); a replacement must pay a fee of at least {}, got {}",
+                    hex::encode(replaced_hash), required_fee, tx.fee()
+                ));
             }
         }
+        // Nothing leaves the mempool until the transaction is known to fit
+        let evicted = self.plan_evictions(&tx, replaced)?;
+
+        let mut sequence = None;
+        if let Some(replaced_hash) = replaced {
+            info!("Replacing transaction {} (fee {}) with {} (fee {}).", hex::encode(replaced_hash), self.transactions[&replaced_hash].fee(), hex::encode(tx_hash), tx.fee());
+            sequence = self.remove_with_reason(&replaced_hash, RemovalReason::Replaced { by: tx_hash }).map(|priority| priority.sequence);
+        }
+        for lowest_hash in evicted {
+            self.remove_entry(&lowest_hash);
+            self.evicted_count += 1;
+            self.publish(MempoolEvent::Evicted { tx_hash: lowest_hash, by: tx_hash });
+            debug!("Mempool full. Evicted lowest fee rate transaction: {}", hex::encode(lowest_hash));
+        }
 
         debug!("Adding transaction {} to mempool.", hex::encode(tx_hash));
+        let sequence = sequence.unwrap_or_else(|| {
+            self.next_sequence += 1;
+            self.next_sequence - 1
+        });
+        if let Some(nonce) = tx.nonce() {
+            self.nonces.insert((tx.sender().clone(), nonce), tx_hash);
+        }
+        let usage = self.sender_usage.entry(tx.sender().clone()).or_default();
+        usage.0 += 1;
+        usage.1 += tx.size();
+        self.total_bytes += tx.size();
+        self.priorities.insert(tx_hash, Priority { fee: tx.fee(), size: tx.size(), sequence });
+        let (sender, nonce) = (tx.sender().clone(), tx.nonce());
         self.transactions.insert(tx_hash, tx);
-        self.order.push_back(tx_hash);
+        if let Some(witness) = witness {
+            self.witnesses.insert(tx_hash, witness);
+        }
+        self.order.insert(sequence, tx_hash);
+        self.added_at.insert(tx_hash, added_at);
+        self.publish(MempoolEvent::Added { tx_hash });
 
+        if replaced.is_none() {
+            self.promote_orphan(&sender, nonce);
+        }
         Ok(true) // Indicate transaction was added
     }
 
+    // Whether a transaction of `size` bytes needs room made for it first
+    fn is_full_for(&self, size: usize) -> bool {
+        self.transactions.len() >= self.max_size || self.total_bytes + size > self.max_bytes
+    }
+
+    // The transactions to evict, lowest fee rate first and newest first among equals, for `tx` to
+    // fit once `replaced` leaves; an error if that would evict one paying at least its fee rate
+    fn plan_evictions(&self, tx: &Transaction, replaced: Option<Hash>) -> Result<Vec<Hash>, String> {
+        let freed = replaced.map(|hash| self.priorities[&hash].size);
+        let mut count = self.transactions.len() - usize::from(freed.is_some());
+        let mut bytes = self.total_bytes - freed.unwrap_or_default();
+        let mut evicted = Vec::new();
+        if count < self.max_size && bytes + tx.size() <= self.max_bytes {
+            return Ok(evicted);
+        }
+        let mut candidates: Vec<(Hash, Priority)> = self.priorities.iter()
+            .filter(|(hash, _)| Some(**hash) != replaced)
+            .map(|(hash, priority)| (*hash, *priority))
+            .collect();
+        candidates.sort_by_key(|(_, priority)| std::cmp::Reverse(*priority));
+        let mut candidates = candidates.into_iter();
+        while count >= self.max_size || bytes + tx.size() > self.max_bytes {
+            let Some((lowest_hash, lowest)) = candidates.next() else {
+                // Should not happen if len >= max_size and max_size > 0
+                return Err("Mempool full, but failed to evict a transaction".to_string());
+            };
+            // Compares fee / size across both sides without rounding
+            if lowest.fee as u128 * tx.size() as u128 >= tx.fee() as u128 * lowest.size as u128 {
+                return Err(format!(
+                    "Mempool is full; a fee above {} per {} bytes is needed to evict the lowest paying transaction",
+                    lowest.fee, lowest.size
+                ));
+            }
+            count -= 1;
+            bytes -= lowest.size;
+            evicted.push(lowest_hash);
+        }
+        Ok(evicted)
+    }
+
+    /// The nonce `sender` should use next: one past its highest pending nonce. `None` if it has no
+    /// pending nonce, since the mempool doesn't know how far its confirmed transactions got.
+    pub fn expected_nonce(&self, sender: &Address) -> Option<u64> {
+        self.nonces.range((sender.clone(), 0)..=(sender.clone(), u64::MAX))
+            .next_back()
+            .map(|((_, nonce), _)| nonce.saturating_add(1))
+    }
+
+    // Holds a future-nonce transaction until its sender's gap is filled
+    fn add_orphan(&mut self, tx: Transaction, witness: Option<TxWitness>, tx_hash: Hash, expected: u64, added_at: u64) -> Result<bool, String> {
+        let nonce = tx.nonce().expect("orphans have a nonce");
+        if nonce - expected > MAX_ORPHAN_NONCE_GAP {
+            return Err(format!(
+                "Transaction nonce {} is too far ahead of the expected nonce {} (at most {} ahead)",
+                nonce, expected, MAX_ORPHAN_NONCE_GAP
+            ));
+        }
+        let key = (tx.sender().clone(), nonce);
+        match self.orphans.get(&key) {
+            Some((existing, _, _)) if *existing == tx => return Ok(false),
+            Some((existing, _, _)) => {
+                let required_fee = replacement_fee(existing.fee());
+                if tx.fee() < required_fee {
+                    return Err(format!(
+                        "Transaction conflicts with orphan {} (same sender and nonce); a replacement must pay a fee of at least {}, got {}",
+                        hex::encode(existing.calculate_hash()), required_fee, tx.fee()
+                    ));
+                }
+            }
+            None if self.orphans.len() >= MAX_ORPHAN_TRANSACTIONS => {
+                return Err(format!("Orphan area is full ({} transactions)", MAX_ORPHAN_TRANSACTIONS));
+            }
+            None => {}
+        }
+        // Every nonce up to the sender's highest orphan needs a slot, or the gap could never be filled
+        let (pending_count, pending_bytes) = self.sender_usage.get(tx.sender()).copied().unwrap_or_default();
+        let (_, orphan_bytes) = self.orphan_usage(tx.sender(), Some(nonce));
+        let highest = self.orphans.range((tx.sender().clone(), 0)..=(tx.sender().clone(), u64::MAX))
+            .next_back()
+            .map_or(nonce, |((_, highest), _)| nonce.max(*highest));
+        let slots = pending_count + (highest - expected + 1) as usize;
+        if slots > self.sender_limits.max_transactions {
+            return Err(format!(
+                "Sender {} would need {} pending transactions to reach nonce {} (limit {})",
+                hex::encode(tx.sender()), slots, highest, self.sender_limits.max_transactions
+            ));
+        }
+        if pending_bytes + orphan_bytes + tx.size() > self.sender_limits.max_bytes {
+            return Err(format!(
+                "Sender {} would have {} bytes of pending transactions (limit {})",
+                hex::encode(tx.sender()), pending_bytes + orphan_bytes + tx.size(), self.sender_limits.max_bytes
+            ));
+        }
+        debug!("Holding transaction {} with nonce {} as an orphan until nonce {} arrives.", hex::encode(tx_hash), nonce, expected);
+        self.orphans.insert(key, (tx, witness, added_at));
+        Ok(true)
+    }
+
+    // Count and bytes of the orphans held for `sender`, leaving out the one at nonce `except`
+    fn orphan_usage(&self, sender: &Address, except: Option<u64>) -> (usize, usize) {
+        self.orphans.range((sender.clone(), 0)..=(sender.clone(), u64::MAX))
+            .filter(|((_, nonce), _)| Some(*nonce) != except)
+            .fold((0, 0), |(count, bytes), (_, (tx, _, _))| (count + 1, bytes + tx.size()))
+    }
+
+    // Moves the orphan that follows `nonce` into the pool; adding it promotes the next one in turn
+    fn promote_orphan(&mut self, sender: &Address, nonce: Option<u64>) {
+        let Some(next) = nonce.and_then(|nonce| nonce.checked_add(1)) else { return };
+        let Some((orphan, witness, _)) = self.orphans.remove(&(sender.clone(), next)) else { return };
+        let orphan_hash = orphan.calculate_hash();
+        match self.add_transaction_at(orphan, witness, unix_now()) {
+            Ok(_) => debug!("Promoted orphan transaction {} into the mempool.", hex::encode(orphan_hash)),
+            Err(e) => debug!("Dropped orphan transaction {}: {}", hex::encode(orphan_hash), e),
+        }
+    }
+
+    /// Every transaction held, pending ones in arrival order and then orphans, each with its witness
+    /// and the UNIX time it arrived. Re-adding them in this order with
+    /// [`add_transaction_at`](Self::add_transaction_at) rebuilds the same pool.
+    pub fn entries(&self) -> Vec<(Transaction, Option<TxWitness>, u64)> {
+        let pending = self.order.values().map(|hash| (
+            self.transactions[hash].clone(),
+            self.witnesses.get(hash).cloned(),
+            self.added_at.get(hash).copied().unwrap_or_default(),
+        ));
+        let orphans = self.orphans.values().cloned();
+        pending.chain(orphans).collect()
+    }
+
+    /// The sender's signature of the pending transaction `tx_hash`, if it came signed.
+    pub fn witness(&self, tx_hash: &Hash) -> Option<&TxWitness> {
+        self.witnesses.get(tx_hash)
+    }
+
+    /// Returns the number of future-nonce transactions waiting for their gap to be filled.
+    pub fn orphan_count(&self) -> usize {
+        self.orphans.len()
+    }
+
     /// Retrieves a batch of transactions from the mempool.
     ///
-    /// Returns up to `max_count` transactions, prioritizing older ones (FIFO).
+    /// Returns up to `max_count` transactions, highest fee rate (fee per byte) first and,
+    /// at equal rates, oldest first. Transactions of one sender keep their arrival order,
+    /// so a high fee can pull the sender's earlier transactions forward but never skip them.
     ///
     /// # Arguments
     ///
@@ -75,11 +529,27 @@ pub fn add_transaction(&mut self, tx: Transaction) -> Result<bool, String> {
     ///
     /// * A vector containing the retrieved transactions.
 pub fn get_transactions(&self, max_count: usize) -> Vec<Transaction> {
-        self.order
-            .iter()
-            .take(max_count)
-            .filter_map(|hash| self.transactions.get(hash).cloned()) // Clone transactions
-            .collect()
+        // Queue each sender's transactions by arrival; only the head of a queue can be picked
+        let mut queues: HashMap<&Address, VecDeque<(Priority, &Transaction)>> = HashMap::new();
+        for hash in self.order.values() {
+            let tx = &self.transactions[hash];
+            queues.entry(tx.sender()).or_default().push_back((self.priorities[hash], tx));
+        }
+        let mut candidates: BTreeSet<(Priority, &Address)> = queues.iter()
+            .filter_map(|(sender, queue)| queue.front().map(|(priority, _)| (*priority, *sender)))
+            .collect();
+
+        let mut selected = Vec::new();
+        while selected.len() < max_count {
+            let Some((_, sender)) = candidates.pop_first() else { break };
+            let queue = queues.get_mut(sender).expect("candidates come from queues");
+            let (_, tx) = queue.pop_front().expect("candidate is the head of its queue");
+            selected.push(tx.clone());
+            if let Some((priority, _)) = queue.front() {
+                candidates.insert((*priority, sender));
+            }
+        }
+        selected
     }
 
     /// Removes a list of transactions from the mempool, typically after they've been included in a block.
@@ -90,9 +560,7 @@ pub fn get_transactions(&self, max_count: usize) -> Vec<Transaction> {
 pub fn remove_transactions(&mut self, tx_hashes: &[Hash]) {
         let mut removed_count = 0;
         for tx_hash in tx_hashes {
-            if self.transactions.remove(tx_hash).is_some() {
-                // Also remove from the order queue (less efficient, but necessary)
-                self.order.retain(|h| h != tx_hash);
+            if self.remove_with_reason(tx_hash, RemovalReason::Included).is_some() {
                 removed_count += 1;
             }
         }
@@ -101,17 +569,127 @@ pub fn remove_transactions(&mut self, tx_hashes: &[Hash]) {
         }
     }
 
-    /// Returns the current number of transactions in the mempool.
+    // Drops one transaction and tells subscribers why
+    fn remove_with_reason(&mut self, tx_hash: &Hash, reason: RemovalReason) -> Option<Priority> {
+        let priority = self.remove_entry(tx_hash)?;
+        self.publish(MempoolEvent::Removed { tx_hash: *tx_hash, reason });
+        Some(priority)
+    }
+
+    // Drops one transaction from every index, returning its priority if it was pending
+    fn remove_entry(&mut self, tx_hash: &Hash) -> Option<Priority> {
+        let tx = self.transactions.remove(tx_hash)?;
+        self.witnesses.remove(tx_hash);
+        self.added_at.remove(tx_hash);
+        if let Some(nonce) = tx.nonce() {
+            self.nonces.remove(&(tx.sender().clone(), nonce));
+        }
+        let priority = self.priorities.remove(tx_hash)?;
+        self.order.remove(&priority.sequence);
+        self.total_bytes -= priority.size;
+        if let Some(usage) = self.sender_usage.get_mut(tx.sender()) {
+            usage.0 -= 1;
+            usage.1 -= priority.size;
+            if usage.0 == 0 {
+                self.sender_usage.remove(tx.sender());
+            }
+        }
+        Some(priority)
+    }
+
+    /// Lists up to `limit` pending transactions after skipping `offset`, highest fee rate first
+    /// and oldest first at equal rates.
+    pub fn list(&self, offset: usize, limit: usize) -> Vec<PendingTransaction> {
+        let mut by_priority: Vec<(&Priority, &Hash)> = self.priorities.iter().map(|(hash, priority)| (priority, hash)).collect();
+        by_priority.sort_unstable();
+        by_priority.into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(priority, hash)| PendingTransaction {
+                tx_hash: *hash,
+                transaction: self.transactions[hash].clone(),
+                fee: priority.fee,
+                size: priority.size,
+                added_at: self.added_at.get(hash).copied().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Returns the pool details of the transaction `tx_hash`, with its age measured at `now`.
+    pub fn get_entry(&self, tx_hash: &Hash, now: u64) -> Option<MempoolEntry> {
+        let tx = self.transactions.get(tx_hash)?;
+        let sequence = self.priorities.get(tx_hash)?.sequence;
+        let builds_on = |earlier: &Transaction, later: &Transaction| {
+            earlier.sender() == later.sender() || earlier.receiver() == Some(later.sender())
+        };
+        let depends = self.order.range(..sequence)
+            .map(|(_, h)| h)
+            .filter(|h| self.transactions.get(*h).is_some_and(|earlier| builds_on(earlier, tx)))
+            .copied()
+            .collect();
+        let spent_by = self.order.range(sequence + 1..)
+            .map(|(_, h)| h)
+            .filter(|h| self.transactions.get(*h).is_some_and(|later| builds_on(tx, later)))
+            .copied()
+            .collect();
+        let added_at = self.added_at.get(tx_hash).copied().unwrap_or(now);
+        Some(MempoolEntry {
+            transaction: tx.clone(),
+            fee: tx.fee(),
+            size: tx.size(),
+            added_at,
+            time_in_pool: now.saturating_sub(added_at),
+            depends,
+            spent_by,
+        })
+    }
+
+    /// Summarizes occupancy, the fee rates pending transactions pay, and how many were evicted or expired.
+    pub fn info(&self) -> MempoolInfo {
+        let mut fee_rates: Vec<f64> = self.priorities.values()
+            .map(|priority| priority.fee as f64 / priority.size as f64)
+            .collect();
+        fee_rates.sort_unstable_by(f64::total_cmp);
+        let middle = fee_rates.len() / 2;
+        let median_fee_rate = match fee_rates.len() {
+            0 => None,
+            len if len % 2 == 0 => Some((fee_rates[middle - 1] + fee_rates[middle]) / 2.0),
+            _ => Some(fee_rates[middle]),
+        };
+        MempoolInfo {
+            size: self.transactions.len(),
+            bytes: self.total_bytes,
+            max_size: self.max_size,
+            max_bytes: self.max_bytes,
+            orphans: self.orphans.len(),
+            min_fee_rate: fee_rates.first().copied(),
+            median_fee_rate,
+            evicted: self.evicted_count,
+            expired: self.expired_count,
+        }
+    }
+
+    /// Returns the current number of transactions in the mempool, not counting orphans.
     pub fn size(&self) -> usize {
         self.transactions.len()
     }
 
+    /// Returns the serialized bytes of all pending transactions, not counting orphans.
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
     /// Checks if the mempool is empty.
     pub fn is_empty(&self) -> bool {
         self.transactions.is_empty()
     }
 }
 
+// Lowest fee a transaction must pay to replace a pending one paying `fee`
+fn replacement_fee(fee: u64) -> u64 {
+    fee.saturating_add((fee.saturating_mul(MIN_REPLACEMENT_FEE_BUMP_PERCENT) / 100).max(1))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,7 +741,7 @@ mod tests {
         assert!(mempool.transactions.get(&tx1_hash).is_none());
         assert!(mempool.transactions.get(&tx2_hash).is_some());
         assert_eq!(mempool.order.len(), 1);
-        assert_eq!(mempool.order[0], tx2_hash);
+        assert_eq!(mempool.order.values().next(), Some(&tx2_hash));
 
         // Remove tx2
         mempool.remove_transactions(&[tx2_hash]);
@@ -176,29 +754,298 @@ mod tests {
         assert_eq!(mempool.size(), 0);
     }
 
+    #[test]
+    fn mempool_orders_by_fee_rate() {
+        let mut mempool = Mempool::new(10);
+        let cheap = Transaction::new_transfer_native(vec![1], vec![2], 100).with_fee(1);
+        let first_free = Transaction::new_transfer_native(vec![3], vec![4], 100);
+        let second_free = Transaction::new_transfer_native(vec![5], vec![6], 100);
+        let rich = Transaction::new_transfer_native(vec![7], vec![8], 100).with_fee(1000);
+        // Pays well, but must wait for the cheap transaction from the same sender
+        let chained = Transaction::new_transfer_native(vec![1], vec![9], 100).with_fee(500);
+        for tx in [&cheap, &first_free, &second_free, &rich, &chained] {
+            mempool.add_transaction(tx.clone()).unwrap();
+        }
+
+        assert_eq!(mempool.get_transactions(10), vec![rich.clone(), cheap.clone(), chained.clone(), first_free, second_free]);
+        assert_eq!(mempool.get_transactions(1), vec![rich.clone()]);
+
+        // Listing ranks purely by fee rate, without the per-sender ordering
+        let listed: Vec<Hash> = mempool.list(1, 2).into_iter().map(|pending| pending.tx_hash).collect();
+        assert_eq!(listed, vec![chained.calculate_hash(), cheap.calculate_hash()]);
+        assert_eq!(mempool.list(0, 1)[0].fee, rich.fee());
+        assert!(mempool.list(5, 10).is_empty());
+    }
+
+    #[test]
+    fn mempool_expires_old_transactions() {
+        let mut mempool = Mempool::new(10);
+        mempool.set_expiry(Some(60));
+        let old = Transaction::new_transfer_native(vec![1], vec![2], 100);
+        let fresh = Transaction::new_transfer_native(vec![3], vec![4], 100);
+        mempool.add_transaction(old.clone()).unwrap();
+        mempool.add_transaction(fresh.clone()).unwrap();
+        let now = unix_now();
+        mempool.added_at.insert(old.calculate_hash(), now - 61);
+
+        assert_eq!(mempool.expire(now), vec![old.calculate_hash()]);
+        assert_eq!(mempool.get_transactions(10), vec![fresh.clone()]);
+        assert!(mempool.get_entry(&old.calculate_hash(), now).is_none());
+
+        mempool.set_expiry(None);
+        assert!(mempool.expire(now + 1_000_000).is_empty());
+        assert_eq!(mempool.size(), 1);
+    }
+
+    #[test]
+    fn mempool_replace_by_fee() {
+        let mut mempool = Mempool::new(10);
+        let original = Transaction::new_transfer_native(vec![1], vec![2], 100).with_nonce(0).with_fee(100);
+        let next = Transaction::new_transfer_native(vec![1], vec![3], 100).with_nonce(1).with_fee(100);
+        mempool.add_transaction(original.clone()).unwrap();
+        mempool.add_transaction(next.clone()).unwrap();
+
+        // Same sender and nonce needs a 10% higher fee
+        let too_cheap = Transaction::new_transfer_native(vec![1], vec![4], 100).with_nonce(0).with_fee(109);
+        assert!(mempool.add_transaction(too_cheap).unwrap_err().contains("at least 110"));
+        let replacement = Transaction::new_transfer_native(vec![1], vec![4], 100).with_nonce(0).with_fee(110);
+        assert!(mempool.add_transaction(replacement.clone()).unwrap());
+
+        assert_eq!(mempool.size(), 2);
+        assert!(mempool.get_entry(&original.calculate_hash(), unix_now()).is_none());
+        // The replacement keeps the original's place ahead of the sender's next transaction
+        assert_eq!(mempool.get_transactions(10), vec![replacement, next]);
+        // Transactions without a nonce never conflict
+        assert!(mempool.add_transaction(Transaction::new_transfer_native(vec![1], vec![5], 100)).unwrap());
+
+        // In a full mempool the replaced transaction's bytes count as freed, and a replacement
+        // that still doesn't fit leaves the original in place
+        let mut mempool = Mempool::new(10);
+        let original = Transaction::new_transfer_native(vec![1], vec![2], 100).with_nonce(0).with_fee(100);
+        let rich = Transaction::new_transfer_native(vec![3], vec![4], 100).with_fee(1000);
+        mempool.set_max_bytes(original.size() + rich.size());
+        mempool.add_transaction(original.clone()).unwrap();
+        mempool.add_transaction(rich.clone()).unwrap();
+        let mut events = mempool.subscribe();
+        let bulky = Transaction::new_store_data(vec![1], [7u8; 32], 1024).with_nonce(0).with_fee(110);
+        assert!(mempool.add_transaction(bulky).unwrap_err().contains("Mempool is full"));
+        assert_eq!(mempool.get_transactions(10), vec![rich.clone(), original.clone()]);
+        assert!(events.try_recv().is_err());
+        let replacement = Transaction::new_transfer_native(vec![1], vec![4], 100).with_nonce(0).with_fee(110);
+        assert!(mempool.add_transaction(replacement.clone()).unwrap());
+        assert_eq!(mempool.get_transactions(10), vec![rich, replacement]);
+    }
+
+    #[test]
+    fn mempool_holds_future_nonces_as_orphans() {
+        let mut mempool = Mempool::new(10);
+        let first = Transaction::new_transfer_native(vec![1], vec![2], 100).with_nonce(0);
+        let third = Transaction::new_transfer_native(vec![1], vec![2], 300).with_nonce(2);
+        let fourth = Transaction::new_transfer_native(vec![1], vec![2], 400).with_nonce(3);
+        mempool.add_transaction(first.clone()).unwrap();
+        assert!(mempool.add_transaction(fourth.clone()).unwrap());
+        assert!(mempool.add_transaction(third.clone()).unwrap());
+        assert!(!mempool.add_transaction(third.clone()).unwrap());
+        assert_eq!(mempool.size(), 1);
+        assert_eq!(mempool.orphan_count(), 2);
+        assert_eq!(mempool.get_transactions(10), vec![first.clone()]);
+
+        // Filling the gap promotes the whole run, in nonce order
+        let second = Transaction::new_transfer_native(vec![1], vec![2], 200).with_nonce(1);
+        assert!(mempool.add_transaction(second.clone()).unwrap());
+        assert_eq!(mempool.orphan_count(), 0);
+        assert_eq!(mempool.get_transactions(10), vec![first, second, third, fourth]);
+
+        let far = Transaction::new_transfer_native(vec![1], vec![2], 1).with_nonce(4 + MAX_ORPHAN_NONCE_GAP + 1);
+        assert!(mempool.add_transaction(far).unwrap_err().contains("too far ahead"));
+        // A sender with nothing pending is taken at its word
+        assert!(mempool.add_transaction(Transaction::new_transfer_native(vec![7], vec![2], 1).with_nonce(42)).unwrap());
+        assert_eq!(mempool.size(), 5);
+    }
+
+    #[test]
+    fn mempool_expires_orphans() {
+        let mut mempool = Mempool::new(10);
+        mempool.set_expiry(Some(60));
+        mempool.add_transaction(Transaction::new_transfer_native(vec![1], vec![2], 1).with_nonce(0)).unwrap();
+        mempool.add_transaction(Transaction::new_transfer_native(vec![1], vec![2], 1).with_nonce(5)).unwrap();
+        assert_eq!(mempool.orphan_count(), 1);
+        mempool.expire(unix_now() + 30);
+        assert_eq!(mempool.orphan_count(), 1);
+        mempool.expire(unix_now() + 61);
+        assert_eq!(mempool.orphan_count(), 0);
+    }
+
+    #[test]
+    fn mempool_limits_each_sender() {
+        let mut mempool = Mempool::new(10);
+        let size = Transaction::new_transfer_native(vec![1], vec![2], 0).size();
+        mempool.set_sender_limits(SenderLimits { max_transactions: 2, max_bytes: size * 10 });
+        let first = Transaction::new_transfer_native(vec![1], vec![2], 1).with_nonce(0);
+        mempool.add_transaction(first.clone()).unwrap();
+        let second = Transaction::new_transfer_native(vec![1], vec![2], 2);
+        mempool.add_transaction(second.clone()).unwrap();
+        let third = Transaction::new_transfer_native(vec![1], vec![2], 3);
+        assert!(mempool.add_transaction(third.clone()).unwrap_err().contains("limit 2"));
+        // Other senders are unaffected, and a replacement doesn't count twice
+        assert!(mempool.add_transaction(Transaction::new_transfer_native(vec![7], vec![2], 1)).unwrap());
+        assert!(mempool.add_transaction(first.with_fee(10)).unwrap());
+
+        // Confirming transactions frees the sender's slots
+        mempool.remove_transactions(&[second.calculate_hash()]);
+        assert!(mempool.add_transaction(third).unwrap());
+
+        mempool.set_sender_limits(SenderLimits { max_transactions: 10, max_bytes: size });
+        assert!(mempool.add_transaction(Transaction::new_transfer_native(vec![8], vec![2], 1)).is_ok());
+        assert!(mempool.add_transaction(Transaction::new_transfer_native(vec![8], vec![2], 2)).unwrap_err().contains("bytes"));
+    }
+
+    #[test]
+    fn mempool_counts_orphans_toward_sender_limits() {
+        let mut mempool = Mempool::new(10);
+        let size = Transaction::new_transfer_native(vec![1], vec![2], 0).size();
+        mempool.set_sender_limits(SenderLimits { max_transactions: 3, max_bytes: size * 10 });
+        mempool.add_transaction(Transaction::new_transfer_native(vec![1], vec![2], 1).with_nonce(0)).unwrap();
+        let third = Transaction::new_transfer_native(vec![1], vec![2], 3).with_nonce(2);
+        assert!(mempool.add_transaction(third.clone()).unwrap());
+        // Nonce 3 would leave no slot for the missing nonce 1
+        let fourth = Transaction::new_transfer_native(vec![1], vec![2], 4).with_nonce(3);
+        assert!(mempool.add_transaction(fourth).unwrap_err().contains("limit 3"));
+        // Replacing an orphan doesn't count it twice
+        assert!(mempool.add_transaction(third.with_fee(10)).unwrap());
+        assert_eq!(mempool.orphan_count(), 1);
+        // Orphans also count against transactions that carry no nonce
+        assert!(mempool.add_transaction(Transaction::new_transfer_native(vec![1], vec![2], 5)).unwrap());
+        assert!(mempool.add_transaction(Transaction::new_transfer_native(vec![1], vec![2], 6)).unwrap_err().contains("limit 3"));
+    }
+
+    #[test]
+    fn mempool_entry_dependencies() {
+        let mut mempool = Mempool::new(10);
+        let funding = Transaction::new_transfer_native(vec![1], vec![2], 100);
+        let chained = Transaction::new_transfer_native(vec![2], vec![3], 50);
+        let unrelated = Transaction::new_transfer_native(vec![7], vec![8], 10);
+        for tx in [&funding, &chained, &unrelated] {
+            mempool.add_transaction(tx.clone()).unwrap();
+        }
+
+        let now = unix_now();
+        let entry = mempool.get_entry(&chained.calculate_hash(), now + 30).unwrap();
+        assert_eq!(entry.depends, vec![funding.calculate_hash()]);
+        assert!(entry.spent_by.is_empty());
+        assert_eq!(entry.size, chained.size());
+        assert!(entry.time_in_pool >= 30);
+
+        let funding_entry = mempool.get_entry(&funding.calculate_hash(), now).unwrap();
+        assert_eq!(funding_entry.spent_by, vec![chained.calculate_hash()]);
+        assert!(mempool.get_entry(&unrelated.calculate_hash(), now).unwrap().depends.is_empty());
+        assert!(mempool.get_entry(&[0u8; 32], now).is_none());
+    }
+
     #[test]
     fn mempool_max_size_eviction() {
         let mut mempool = Mempool::new(2);
-        let tx1 = Transaction::new_transfer(vec![1], vec![2], 100);
+        let tx1 = Transaction::new_transfer(vec![1], vec![2], 100).with_fee(50);
         let tx1_hash = tx1.calculate_hash();
-        let tx2 = Transaction::new_transfer(vec![3], vec![4], 200);
+        let tx2 = Transaction::new_transfer(vec![3], vec![4], 200).with_fee(10);
         let tx2_hash = tx2.calculate_hash();
-        let tx3 = Transaction::new_transfer(vec![5], vec![6], 300);
+        let tx3 = Transaction::new_transfer(vec![5], vec![6], 300).with_fee(20);
         let tx3_hash = tx3.calculate_hash();
 
         mempool.add_transaction(tx1.clone()).unwrap();
         mempool.add_transaction(tx2.clone()).unwrap();
         assert_eq!(mempool.size(), 2);
 
-        // Add tx3, should evict tx1 (oldest)
+        // Add tx3, should evict tx2 (lowest fee rate) even though tx1 is older
         assert!(mempool.add_transaction(tx3.clone()).unwrap());
         assert_eq!(mempool.size(), 2);
-        assert!(mempool.transactions.get(&tx1_hash).is_none()); // tx1 evicted
-        assert!(mempool.transactions.get(&tx2_hash).is_some());
+        assert!(mempool.transactions.get(&tx1_hash).is_some());
+        assert!(mempool.transactions.get(&tx2_hash).is_none()); // tx2 evicted
         assert!(mempool.transactions.get(&tx3_hash).is_some());
         assert_eq!(mempool.order.len(), 2);
-        assert_eq!(mempool.order[0], tx2_hash); // tx2 is now oldest
-        assert_eq!(mempool.order[1], tx3_hash);
+        assert_eq!(mempool.order.values().collect::<Vec<_>>(), vec![&tx1_hash, &tx3_hash]);
+
+        // Nothing pays less than a fee of 20, so a transaction paying the same is turned away
+        let tx4 = Transaction::new_transfer(vec![7], vec![8], 400).with_fee(20);
+        assert!(mempool.add_transaction(tx4).unwrap_err().contains("Mempool is full"));
+        assert_eq!(mempool.order.values().collect::<Vec<_>>(), vec![&tx1_hash, &tx3_hash]);
     }
-}
 
+    #[test]
+    fn mempool_publishes_events() {
+        let mut mempool = Mempool::new(2);
+        let mut events = mempool.subscribe();
+        let original = Transaction::new_transfer_native(vec![1], vec![2], 100).with_nonce(0).with_fee(10);
+        let replacement = Transaction::new_transfer_native(vec![1], vec![2], 100).with_nonce(0).with_fee(20);
+        let cheap = Transaction::new_transfer_native(vec![3], vec![4], 100).with_fee(1);
+        let rich = Transaction::new_transfer_native(vec![5], vec![6], 100).with_fee(50);
+        let orphan = Transaction::new_transfer_native(vec![1], vec![2], 100).with_nonce(2).with_fee(20);
+        for tx in [&original, &replacement, &cheap, &rich, &orphan] {
+            mempool.add_transaction(tx.clone()).unwrap();
+        }
+        mempool.remove_transactions(&[rich.calculate_hash()]);
+        mempool.set_expiry(Some(0));
+        mempool.expire(unix_now());
+
+        let (original, replacement, cheap, rich) =
+            (original.calculate_hash(), replacement.calculate_hash(), cheap.calculate_hash(), rich.calculate_hash());
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(received, vec![
+            MempoolEvent::Added { tx_hash: original },
+            MempoolEvent::Removed { tx_hash: original, reason: RemovalReason::Replaced { by: replacement } },
+            MempoolEvent::Added { tx_hash: replacement },
+            MempoolEvent::Added { tx_hash: cheap },
+            MempoolEvent::Evicted { tx_hash: cheap, by: rich },
+            MempoolEvent::Added { tx_hash: rich },
+            MempoolEvent::Removed { tx_hash: rich, reason: RemovalReason::Included },
+            MempoolEvent::Removed { tx_hash: replacement, reason: RemovalReason::Expired },
+        ]);
+    }
+
+    #[test]
+    fn mempool_byte_budget_eviction() {
+        let mut mempool = Mempool::new(10);
+        let small = Transaction::new_transfer_native(vec![1], vec![2], 100);
+        let other = Transaction::new_transfer_native(vec![3], vec![4], 100);
+        let large = Transaction::new_store_data(vec![5; 64], [7u8; 32], 1024).with_fee(10);
+        mempool.set_max_bytes(small.size() + other.size() + large.size() - 1);
+        mempool.add_transaction(small.clone()).unwrap();
+        mempool.add_transaction(other.clone()).unwrap();
+        assert_eq!(mempool.total_bytes(), small.size() + other.size());
+
+        // The large transaction only fits once one of the others is gone
+        assert!(mempool.add_transaction(large.clone()).unwrap());
+        assert_eq!(mempool.get_transactions(10), vec![large.clone(), small.clone()]);
+        assert_eq!(mempool.total_bytes(), small.size() + large.size());
+
+        mempool.remove_transactions(&[small.calculate_hash(), large.calculate_hash()]);
+        assert_eq!(mempool.total_bytes(), 0);
+        mempool.set_max_bytes(large.size() - 1);
+        assert!(mempool.add_transaction(large).unwrap_err().contains("exceeds the mempool budget"));
+    }
+
+    #[test]
+    fn mempool_info() {
+        let mut mempool = Mempool::new(2);
+        let info = mempool.info();
+        assert_eq!((info.size, info.bytes, info.max_size, info.min_fee_rate, info.median_fee_rate), (0, 0, 2, None, None));
+
+        let cheap = Transaction::new_transfer_native(vec![1], vec![2], 100).with_fee(10);
+        let rich = Transaction::new_transfer_native(vec![3], vec![4], 100).with_fee(30);
+        let richer = Transaction::new_transfer_native(vec![5], vec![6], 100).with_fee(50);
+        for tx in [&cheap, &rich, &richer] {
+            mempool.add_transaction(tx.clone()).unwrap();
+        }
+        let info = mempool.info();
+        assert_eq!((info.size, info.bytes, info.evicted), (2, rich.size() + richer.size(), 1));
+        assert_eq!(info.min_fee_rate, Some(30.0 / rich.size() as f64));
+        assert_eq!(info.median_fee_rate, Some((30.0 / rich.size() as f64 + 50.0 / richer.size() as f64) / 2.0));
+
+        mempool.set_expiry(Some(0));
+        mempool.expire(unix_now());
+        assert_eq!(mempool.info().expired, 2);
+    }
+}
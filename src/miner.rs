@@ -0,0 +1,179 @@
+//!
+//! # Miner
+//! Proof-of-Work mining against a shared `Blockchain`.
+//!
+//! The blockchain lock is held only while building a template and while importing
+//! the solved block, so blocks from peers can be imported during the nonce search.
+//! Each import bumps the chain's `TipWatch`; the miner polls it and restarts on the
+//! new tip instead of grinding a stale template.
+//!
+//! With `--mine` the node runs [`run_miner`], which mines block after block on a blocking
+//! thread and broadcasts each one; [`MinerControl`] lets the RPC server pause and resume it.
+
+use crate::core::{Block, Blockchain, BlockchainError};
+use crate::consensus;
+use crate::network::{NetworkMessage, OutboundSender};
+use crate::service::Shutdown;
+use log::{info, warn};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often a paused miner checks whether it was resumed.
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Pause switch and statistics of the node's miner, shared with the RPC server.
+#[derive(Debug, Default)]
+pub struct MinerControl {
+    paused: AtomicBool,
+    blocks_mined: AtomicU64,
+}
+
+impl MinerControl {
+    /// Stops mining after the current nonce batch, until [`MinerControl::resume`].
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Blocks mined and added to the chain since the node started.
+    pub fn blocks_mined(&self) -> u64 {
+        self.blocks_mined.load(Ordering::SeqCst)
+    }
+}
+
+/// Mines and imports the next block, restarting whenever the tip changes mid-search.
+///
+/// Returns the block once it has been added to the chain.
+pub fn mine_next_block(blockchain: &Arc<Mutex<Blockchain>>) -> Result<Block, BlockchainError> {
+    Ok(mine_next_block_until(blockchain, || false)?.expect("Mining without a stop condition ends with a block"))
+}
+
+/// Like [`mine_next_block`], but gives up once `stop` returns true (it is polled between
+/// nonce batches) and returns `None`.
+pub fn mine_next_block_until(blockchain: &Arc<Mutex<Blockchain>>, mut stop: impl FnMut() -> bool) -> Result<Option<Block>, BlockchainError> {
+    loop {
+        if stop() {
+            return Ok(None);
+        }
+        let (mut block, pow_algorithm, tip_watch, generation) = {
+            let mut bc_guard = blockchain.lock().expect("Blockchain lock poisoned");
+            let tip_watch = bc_guard.tip_watch();
+            let generation = tip_watch.generation();
+            (bc_guard.create_block_template()?, bc_guard.chain_config().pow_algorithm, tip_watch, generation)
+        };
+
+        let bits = block.header.bits;
+        if consensus::mine_until(&mut block.header, bits, pow_algorithm, || tip_watch.changed_since(generation) || stop()).is_none() {
+            if !tip_watch.changed_since(generation) {
+                return Ok(None);
+            }
+            info!("New tip arrived while mining block {}; restarting on the new tip", block.header.height);
+            continue;
+        }
+
+        let mut bc_guard = blockchain.lock().expect("Blockchain lock poisoned");
+        if tip_watch.changed_since(generation) {
+            // Solved just as another block arrived; our template no longer extends the tip
+            warn!("Discarding solved block {}: the tip changed before it could be imported", block.header.height);
+            continue;
+        }
+        bc_guard.process_mined_block(block.clone())?;
+        return Ok(Some(block));
+    }
+}
+
+/// Mines block after block until `shutdown` is triggered, broadcasting each through `outbound`.
+/// The nonce search runs on a blocking thread; while `control` is paused no block is mined.
+pub async fn run_miner(
+    blockchain: Arc<Mutex<Blockchain>>,
+    control: Arc<MinerControl>,
+    outbound: OutboundSender,
+    shutdown: Shutdown,
+) -> Result<(), String> {
+    info!("Miner started");
+    while !shutdown.is_triggered() {
+        if control.is_paused() {
+            tokio::select! {
+                _ = tokio::time::sleep(PAUSED_POLL_INTERVAL) => {}
+                _ = shutdown.wait() => {}
+            }
+            continue;
+        }
+        let (blockchain, search_control, search_shutdown) = (blockchain.clone(), control.clone(), shutdown.clone());
+        let mined = tokio::task::spawn_blocking(move || {
+            mine_next_block_until(&blockchain, || search_shutdown.is_triggered() || search_control.is_paused())
+        })
+        .await
+        .map_err(|e| format!("Mining thread failed: {}", e))?
+        .map_err(|e| format!("Mining failed: {}", e))?;
+        if let Some(block) = mined {
+            control.blocks_mined.fetch_add(1, Ordering::SeqCst);
+            info!("Mined block {} ({})", block.header.height, hex::encode(block.hash()));
+            if outbound.send(NetworkMessage::NewBlock(block)).is_err() {
+                warn!("Network node is not running; mined block not broadcast");
+            }
+        }
+    }
+    info!("Miner stopped.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_mine_next_block_restarts_on_new_tip() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let tip_watch = blockchain.tip_watch();
+        // A template taken before a peer's block is imported goes stale
+        let stale_generation = tip_watch.generation();
+        let peer_block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(peer_block.clone()).unwrap();
+        assert!(tip_watch.changed_since(stale_generation));
+
+        let blockchain = Arc::new(Mutex::new(blockchain));
+        let block = mine_next_block(&blockchain).unwrap();
+        assert_eq!(block.header.previous_hash, peer_block.hash());
+        assert_eq!(blockchain.lock().unwrap().get_chain_height(), Some(2));
+    }
+
+    #[test]
+    fn test_run_miner_pauses_and_stops() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let blockchain = Arc::new(Mutex::new(blockchain));
+        let control = Arc::new(MinerControl::default());
+        let (outbound, mut broadcast) = crate::network::outbound_channel();
+        let (trigger, shutdown) = crate::service::shutdown_channel();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let miner = tokio::spawn(run_miner(blockchain.clone(), control.clone(), outbound, shutdown));
+            let Some(NetworkMessage::NewBlock(block)) = broadcast.recv().await else { panic!("expected a mined block") };
+            assert_eq!(block.header.height, 1);
+
+            control.pause();
+            tokio::time::sleep(PAUSED_POLL_INTERVAL).await;
+            let paused_height = blockchain.lock().unwrap().get_chain_height();
+            tokio::time::sleep(PAUSED_POLL_INTERVAL).await;
+            assert_eq!(blockchain.lock().unwrap().get_chain_height(), paused_height);
+
+            trigger.trigger();
+            assert_eq!(miner.await.unwrap(), Ok(()));
+        });
+        assert_eq!(Some(control.blocks_mined()), blockchain.lock().unwrap().get_chain_height());
+    }
+}
@@ -0,0 +1,289 @@
+//!
+//! # Payload Access Control
+//! Lists of the keys allowed to retrieve a payload, set by its owner.
+//!
+//! The owner of a payload is the sender of the `StoreData` transaction that first anchored it
+//! on the main chain. The owner signs a [`PayloadAcl`] naming the ed25519 public keys that may
+//! retrieve the payload with the key behind that address. Nodes keep the latest ACL of each
+//! payload, by issue time, in their state, gossip it on the storage-proofs topic and hand it
+//! over with the payload when serving it, so every provider enforces it. An ACL listing no keys
+//! lifts the restriction.
+//!
+//! A request for a restricted payload must carry a [`ReadAuthorization`]: the payload hash and
+//! a timestamp signed by one of the listed keys, within `READ_AUTHORIZATION_MAX_AGE_SECS` of the
+//! serving node's clock. Over RPC the client signs it; nodes fetching from each other sign with
+//! their node key, so providers meant to replicate a restricted payload must be listed too.
+
+use crate::core::address::{self, AddressError};
+use crate::core::{Address, Blockchain, BlockchainError, Hash, PayloadAnchor};
+use crate::storage::StorageError;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use log::warn;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+/// Read authorizations are accepted this far from the serving node's clock, either way.
+pub const READ_AUTHORIZATION_MAX_AGE_SECS: u64 = 300;
+/// Most keys one ACL can list.
+pub const MAX_ACL_READERS: usize = 256;
+/// How far past the receiving node's clock an ACL may be issued. A later issue time would let
+/// an ACL outrank every ACL its owner issues until then.
+pub const ACL_MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+// Domain separators, so signatures of one kind can't be replayed as another
+const ACL_SIGNING_TAG: &[u8] = b"payload-acl-v1";
+const READ_SIGNING_TAG: &[u8] = b"payload-read-v1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AclError {
+    #[error("Payload {0} is not anchored on the main chain")]
+    NotAnchored(String),
+    #[error("ACL is not signed by the payload owner: {0}")]
+    NotOwner(#[from] AddressError),
+    #[error("An ACL lists at most {} keys", MAX_ACL_READERS)]
+    TooManyReaders,
+    #[error("An ACL issued at {0} or later is already in force")]
+    Stale(u64),
+    #[error("ACL is issued at {0}, ahead of this node's clock")]
+    IssuedInFuture(u64),
+    #[error("Payload {0} needs a request signed by a key its ACL lists")]
+    AccessDenied(String),
+    #[error("Chain error: {0}")]
+    Chain(#[from] BlockchainError),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+/// The keys allowed to retrieve a payload, signed by its owner.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadAcl {
+    pub payload_hash: Hash,
+    /// ed25519 public keys of the readers; none lifts the restriction.
+    pub readers: Vec<[u8; 32]>,
+    /// UNIX time the ACL was issued; a later one replaces it.
+    pub issued_at: u64,
+    /// Public key behind the owner address.
+    pub owner_public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl PayloadAcl {
+    /// Creates an ACL signed by `sign`, which signs the digest with the key of `owner_public_key`.
+    pub fn signed(
+        payload_hash: Hash,
+        readers: Vec<[u8; 32]>,
+        issued_at: u64,
+        owner_public_key: Vec<u8>,
+        sign: impl FnOnce(&Hash) -> Vec<u8>,
+    ) -> Self {
+        let mut acl = PayloadAcl { payload_hash, readers, issued_at, owner_public_key, signature: Vec::new() };
+        acl.signature = sign(&acl.signing_digest());
+        acl
+    }
+
+    /// Digest the owner signs: SHA-256 of the tag `payload-acl-v1`, the payload hash, the
+    /// big-endian issue time and reader count, and the reader keys in order.
+    pub fn signing_digest(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(ACL_SIGNING_TAG);
+        hasher.update(self.payload_hash);
+        hasher.update(self.issued_at.to_be_bytes());
+        hasher.update((self.readers.len() as u32).to_be_bytes());
+        for reader in &self.readers {
+            hasher.update(reader);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Checks the ACL is signed by the key behind `owner`.
+    pub fn verify(&self, owner: &Address) -> Result<(), AclError> {
+        if self.readers.len() > MAX_ACL_READERS {
+            return Err(AclError::TooManyReaders);
+        }
+        address::verify_signature(owner, &self.owner_public_key, &self.signing_digest(), &self.signature)?;
+        Ok(())
+    }
+
+    /// Whether `authorization` may retrieve the payload at `now`.
+    pub fn authorizes(&self, authorization: Option<&ReadAuthorization>, now: u64) -> bool {
+        if self.readers.is_empty() {
+            return true;
+        }
+        authorization.is_some_and(|authorization| {
+            self.readers.contains(&authorization.reader) && authorization.verify(&self.payload_hash, now)
+        })
+    }
+}
+
+/// Proof that a reader's key asked for a payload, sent with the request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReadAuthorization {
+    /// ed25519 public key of the reader.
+    pub reader: [u8; 32],
+    /// UNIX time the request was signed.
+    pub issued_at: u64,
+    pub signature: Vec<u8>,
+}
+
+impl ReadAuthorization {
+    /// Authorizes reading `payload_hash` at `issued_at` with `key`.
+    pub fn new(payload_hash: &Hash, issued_at: u64, key: &SigningKey) -> Self {
+        let signature = key.sign(&Self::signing_digest(payload_hash, issued_at)).to_bytes().to_vec();
+        ReadAuthorization { reader: key.verifying_key().to_bytes(), issued_at, signature }
+    }
+
+    /// Digest the reader signs: SHA-256 of the tag `payload-read-v1`, the payload hash and the
+    /// big-endian issue time.
+    pub fn signing_digest(payload_hash: &Hash, issued_at: u64) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(READ_SIGNING_TAG);
+        hasher.update(payload_hash);
+        hasher.update(issued_at.to_be_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Checks the signature over `payload_hash` and that it was made close enough to `now`.
+    pub fn verify(&self, payload_hash: &Hash, now: u64) -> bool {
+        if self.issued_at.abs_diff(now) > READ_AUTHORIZATION_MAX_AGE_SECS {
+            return false;
+        }
+        match (VerifyingKey::from_bytes(&self.reader), Signature::from_slice(&self.signature)) {
+            (Ok(key), Ok(signature)) => key.verify(&Self::signing_digest(payload_hash, self.issued_at), &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// The sender of the `StoreData` transaction that first anchored `payload_hash` on the main chain.
+pub fn payload_owner(blockchain: &Blockchain, payload_hash: &Hash) -> Result<Option<Address>, BlockchainError> {
+    match blockchain.payload_anchors()?.remove(payload_hash) {
+        Some(anchor) => anchor_sender(blockchain, &anchor),
+        None => Ok(None),
+    }
+}
+
+fn anchor_sender(blockchain: &Blockchain, anchor: &PayloadAnchor) -> Result<Option<Address>, BlockchainError> {
+    let Some(block) = blockchain.get_block_by_height(anchor.height)? else { return Ok(None) };
+    Ok(block.transactions.into_iter().find(|tx| tx.calculate_hash() == anchor.tx_hash).map(|tx| tx.sender().clone()))
+}
+
+/// Checks `acl` is signed by the owner of its payload and stores it, unless an ACL issued at
+/// the same time or later is already in force or it is issued too far past `now`.
+pub fn accept_acl(blockchain: &Blockchain, acl: &PayloadAcl, now: u64) -> Result<(), AclError> {
+    if acl.issued_at > now.saturating_add(ACL_MAX_CLOCK_SKEW_SECS) {
+        return Err(AclError::IssuedInFuture(acl.issued_at));
+    }
+    if let Some(current) = blockchain.storage().get_payload_acl(&acl.payload_hash)? {
+        if current.issued_at >= acl.issued_at {
+            return Err(AclError::Stale(current.issued_at));
+        }
+    }
+    let owner = payload_owner(blockchain, &acl.payload_hash)?
+        .ok_or_else(|| AclError::NotAnchored(hex::encode(acl.payload_hash)))?;
+    acl.verify(&owner)?;
+    blockchain.storage().put_payload_acl(acl)?;
+    Ok(())
+}
+
+/// Drops the ACLs of `payload_hashes` not signed by the payload's owner on the current main
+/// chain, after the blocks anchoring them left it. An ACL whose payload is no longer anchored is
+/// dropped too, as [`accept_acl`] would refuse it; the owner sets it again once the payload is
+/// anchored anew.
+pub fn revalidate_acls(blockchain: &Blockchain, payload_hashes: &[Hash]) -> Result<(), AclError> {
+    let mut anchors = None;
+    for payload_hash in payload_hashes {
+        let Some(acl) = blockchain.storage().get_payload_acl(payload_hash)? else { continue };
+        if anchors.is_none() {
+            anchors = Some(blockchain.payload_anchors()?);
+        }
+        let owner = match anchors.as_ref().and_then(|anchors| anchors.get(payload_hash)) {
+            Some(anchor) => anchor_sender(blockchain, anchor)?,
+            None => None,
+        };
+        if owner.is_none_or(|owner| acl.verify(&owner).is_err()) {
+            warn!("Dropping ACL of payload {}: its owner changed with the main chain", hex::encode(payload_hash));
+            blockchain.storage().delete_payload_acl(payload_hash)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks `authorization` against the ACL in force for `payload_hash` at `now`, returning the
+/// ACL, if any.
+pub fn check_access(
+    blockchain: &Blockchain,
+    payload_hash: &Hash,
+    authorization: Option<&ReadAuthorization>,
+    now: u64,
+) -> Result<Option<PayloadAcl>, AclError> {
+    let acl = blockchain.storage().get_payload_acl(payload_hash)?;
+    match acl {
+        Some(acl) if !acl.authorizes(authorization, now) => Err(AclError::AccessDenied(hex::encode(payload_hash))),
+        acl => Ok(acl),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::address::{address_from_public_key, KeyAlgorithm};
+    use crate::core::Transaction;
+    use crate::presets::NetworkPreset;
+    use tempfile::tempdir;
+
+    fn sign_as(key: &SigningKey) -> impl Fn(&Hash) -> Vec<u8> + '_ {
+        move |digest| key.sign(digest).to_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_only_listed_keys_read_restricted_payloads() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let owner_key = SigningKey::from_bytes(&[3u8; 32]);
+        let owner_public_key = owner_key.verifying_key().to_bytes().to_vec();
+        let owner = address_from_public_key(KeyAlgorithm::Ed25519, &owner_public_key).unwrap();
+        let payload_hash = [5u8; 32];
+        assert!(blockchain.add_pending_transaction(Transaction::new_store_data(owner, payload_hash, 10)).unwrap());
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+
+        let (reader, stranger) = (SigningKey::from_bytes(&[4u8; 32]), SigningKey::from_bytes(&[6u8; 32]));
+        let acl = PayloadAcl::signed(payload_hash, vec![reader.verifying_key().to_bytes()], 100, owner_public_key.clone(), sign_as(&owner_key));
+
+        // Only the owner sets the ACL of an anchored payload, and only a newer one replaces it
+        let forged = PayloadAcl::signed(payload_hash, vec![], 100, stranger.verifying_key().to_bytes().to_vec(), sign_as(&stranger));
+        assert!(matches!(accept_acl(&blockchain, &forged, 1_000), Err(AclError::NotOwner(_))));
+        let unanchored = PayloadAcl::signed([9u8; 32], vec![], 100, owner_public_key.clone(), sign_as(&owner_key));
+        assert!(matches!(accept_acl(&blockchain, &unanchored, 1_000), Err(AclError::NotAnchored(_))));
+        accept_acl(&blockchain, &acl, 1_000).unwrap();
+        assert!(matches!(accept_acl(&blockchain, &acl, 1_000), Err(AclError::Stale(100))));
+        assert_eq!(blockchain.storage().get_payload_acl(&payload_hash).unwrap(), Some(acl.clone()));
+
+        // Requests need a fresh signature by a listed key
+        let allowed = ReadAuthorization::new(&payload_hash, 1_000, &reader);
+        assert_eq!(check_access(&blockchain, &payload_hash, Some(&allowed), 1_100).unwrap(), Some(acl));
+        for denied in [None, Some(ReadAuthorization::new(&payload_hash, 1_000, &stranger)), Some(ReadAuthorization::new(&[7u8; 32], 1_000, &reader))] {
+            assert!(matches!(check_access(&blockchain, &payload_hash, denied.as_ref(), 1_000), Err(AclError::AccessDenied(_))));
+        }
+        let late = 1_000 + READ_AUTHORIZATION_MAX_AGE_SECS + 1;
+        assert!(matches!(check_access(&blockchain, &payload_hash, Some(&allowed), late), Err(AclError::AccessDenied(_))));
+        assert_eq!(check_access(&blockchain, &[9u8; 32], None, 1_000).unwrap(), None);
+
+        // An ACL listing nobody opens the payload again
+        let lifted = PayloadAcl::signed(payload_hash, vec![], 200, owner_public_key.clone(), sign_as(&owner_key));
+        accept_acl(&blockchain, &lifted, 1_000).unwrap();
+        assert!(check_access(&blockchain, &payload_hash, None, 1_000).is_ok());
+
+        // Issue times past the clock skew are refused, so an ACL can't pin itself in force
+        let ahead = 1_000 + ACL_MAX_CLOCK_SKEW_SECS + 1;
+        let future = PayloadAcl::signed(payload_hash, vec![], ahead, owner_public_key.clone(), sign_as(&owner_key));
+        assert!(matches!(accept_acl(&blockchain, &future, 1_000), Err(AclError::IssuedInFuture(t)) if t == ahead));
+
+        // Dropping the anchoring block from the main chain drops the ACL with it
+        let restricted = PayloadAcl::signed(payload_hash, vec![reader.verifying_key().to_bytes()], 300, owner_public_key, sign_as(&owner_key));
+        accept_acl(&blockchain, &restricted, 1_000).unwrap();
+        blockchain.truncate_chain(1).unwrap();
+        assert_eq!(blockchain.storage().get_payload_acl(&payload_hash).unwrap(), None);
+    }
+}
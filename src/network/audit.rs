@@ -0,0 +1,221 @@
+//!
+//! # Storage Audits
+//! Periodic checks that the payloads this node is committed to keep are intact here and still
+//! held by the other providers storing them.
+//!
+//! The committed payloads are those stored locally that are anchored on the main chain or
+//! under a storage deal still in force. Each round samples up to a configured number of them,
+//! hashes the local copy to catch corruption and issues a signed [`StorageChallenge`] for it,
+//! gossiped like any other. Proofs answering the challenge are audited against the local copy
+//! by the [`ProofAuditor`], which adds each verdict to the audit's record in its [`AuditLog`].
+//! The latest `MAX_AUDIT_RECORDS` audits are kept in memory.
+
+use super::proofs::StorageChallenge;
+use crate::core::{Blockchain, BlockchainError, Hash};
+use crate::offchain_storage::{OffChainStorageError, OffChainStorageManager};
+use ed25519_dalek::SigningKey;
+use rand::RngCore;
+use rand::seq::SliceRandom;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::{HashSet, VecDeque};
+
+/// Seconds between audit rounds by default.
+pub const DEFAULT_AUDIT_INTERVAL_SECS: u64 = 10 * 60;
+/// Payloads audited per round by default.
+pub const DEFAULT_AUDIT_SAMPLE_SIZE: usize = 16;
+/// Audits kept in the log; older ones are dropped.
+pub const MAX_AUDIT_RECORDS: usize = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("Chain error: {0}")]
+    Chain(#[from] BlockchainError),
+    #[error("Off-chain storage error: {0}")]
+    Storage(#[from] OffChainStorageError),
+}
+
+/// State of the local copy of an audited payload.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalCheck {
+    /// The stored bytes hash to the payload hash.
+    Intact,
+    /// The stored bytes hash to something else.
+    Corrupted,
+    /// The payload could not be read.
+    Unreadable,
+}
+
+/// A remote provider's answer to an audit's challenge.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicaCheck {
+    /// Node key of the provider.
+    pub provider: [u8; 32],
+    /// Whether its proof matched the local copy.
+    pub valid: bool,
+    pub responded_at: u64,
+}
+
+/// One payload audit: the local check and the remote proofs answering its challenge so far.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadAudit {
+    pub payload_hash: Hash,
+    pub audited_at: u64,
+    pub local: LocalCheck,
+    pub challenge_id: Hash,
+    pub replicas: Vec<ReplicaCheck>,
+}
+
+/// The latest audits, oldest first.
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    records: VecDeque<PayloadAudit>,
+    rounds: u64,
+    last_round_at: Option<u64>,
+}
+
+impl AuditLog {
+    /// Records the audits of a round finished at `now`.
+    pub fn record_round(&mut self, audits: Vec<PayloadAudit>, now: u64) {
+        for audit in audits {
+            if self.records.len() >= MAX_AUDIT_RECORDS {
+                self.records.pop_front();
+            }
+            self.records.push_back(audit);
+        }
+        self.rounds += 1;
+        self.last_round_at = Some(now);
+    }
+
+    /// Adds `check` to the audit whose challenge it answers. Returns `false` if no audit issued it.
+    pub fn record_replica(&mut self, challenge_id: &Hash, check: ReplicaCheck) -> bool {
+        match self.records.iter_mut().rev().find(|audit| &audit.challenge_id == challenge_id) {
+            Some(audit) => {
+                audit.replicas.retain(|replica| replica.provider != check.provider);
+                audit.replicas.push(check);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Recorded audits, newest first.
+    pub fn records(&self) -> impl Iterator<Item = &PayloadAudit> {
+        self.records.iter().rev()
+    }
+
+    /// Number of rounds run since the node started.
+    pub fn rounds(&self) -> u64 {
+        self.rounds
+    }
+
+    pub fn last_round_at(&self) -> Option<u64> {
+        self.last_round_at
+    }
+}
+
+/// Payloads stored locally that the node is committed to keep (see the module documentation), sorted by hash.
+pub fn committed_payloads(blockchain: &Blockchain, storage: &OffChainStorageManager) -> Result<Vec<Hash>, AuditError> {
+    let anchors = blockchain.payload_anchors()?;
+    let tip_height = blockchain.get_chain_height();
+    let mut committed = Vec::new();
+    for (payload_hash, _) in storage.list_payloads()? {
+        let under_deal = || -> Result<bool, AuditError> {
+            let deals = blockchain.get_storage_deals(&payload_hash)?;
+            Ok(tip_height.is_some_and(|height| deals.iter().any(|deal| deal.is_active_at(height))))
+        };
+        if anchors.contains_key(&payload_hash) || under_deal()? {
+            committed.push(payload_hash);
+        }
+    }
+    Ok(committed)
+}
+
+/// Hashes the local copy of `payload_hash`.
+pub fn check_local_copy(storage: &OffChainStorageManager, payload_hash: &Hash) -> LocalCheck {
+    match storage.retrieve_payload(payload_hash) {
+        Ok(payload) if Sha256::digest(&payload).as_slice() == payload_hash => LocalCheck::Intact,
+        Ok(_) => LocalCheck::Corrupted,
+        Err(_) => LocalCheck::Unreadable,
+    }
+}
+
+/// Audits up to `sample_size` payloads picked at random from `committed`: checks each local copy
+/// and creates a challenge signed by `key` for the remote ones. The challenges are to be recorded
+/// with [`ProofAuditor::record_audits`](super::ProofAuditor::record_audits) and gossiped.
+pub fn audit_payloads(
+    committed: &[Hash],
+    storage: &OffChainStorageManager,
+    key: &SigningKey,
+    sample_size: usize,
+    now: u64,
+) -> Vec<(PayloadAudit, StorageChallenge)> {
+    let sample: HashSet<&Hash> = committed.choose_multiple(&mut rand::thread_rng(), sample_size).collect();
+    committed.iter().filter(|payload_hash| sample.contains(payload_hash)).map(|payload_hash| {
+        let mut nonce = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let challenge = StorageChallenge::new(*payload_hash, nonce, now, key);
+        let audit = PayloadAudit {
+            payload_hash: *payload_hash,
+            audited_at: now,
+            local: check_local_copy(storage, payload_hash),
+            challenge_id: challenge.id(),
+            replicas: Vec::new(),
+        };
+        (audit, challenge)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::address::{address_from_public_key, KeyAlgorithm};
+    use crate::core::{SignedTransaction, Transaction};
+    use crate::network::proofs::{respond_to_challenge, ProofAuditor, ProofVerdict};
+    use crate::presets::NetworkPreset;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_audit_round_checks_local_and_remote_copies() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let storage = OffChainStorageManager::new(&dir.path().join("local")).unwrap();
+        let (anchored, dealt, loose) = (b"anchored".to_vec(), b"under a deal".to_vec(), b"nobody asked".to_vec());
+        let [anchored_hash, dealt_hash, _] = [&anchored, &dealt, &loose].map(|payload| storage.store_payload(payload).unwrap());
+        let client_key = SigningKey::from_bytes(&[3u8; 32]);
+        let client = address_from_public_key(KeyAlgorithm::Ed25519, &client_key.verifying_key().to_bytes()).unwrap();
+        assert!(blockchain.add_pending_transaction(Transaction::new_store_data(client.clone(), anchored_hash, anchored.len() as u64)).unwrap());
+        let deal = Transaction::new_storage_deal(client.clone(), vec![2u8; 21], dealt_hash, 10, 5).with_nonce(0);
+        assert!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(deal, &client_key)).unwrap());
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+
+        let mut committed = vec![anchored_hash, dealt_hash];
+        committed.sort();
+        assert_eq!(committed_payloads(&blockchain, &storage).unwrap(), committed);
+        assert_eq!(audit_payloads(&committed, &storage, &SigningKey::from_bytes(&[1u8; 32]), 1, 1_000).len(), 1);
+
+        // Corrupt the anchored copy on disk
+        std::fs::write(storage.storage_path().join(hex::encode(anchored_hash)), b"bit rot").unwrap();
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let audits = audit_payloads(&committed, &storage, &key, 10, 1_000);
+        let local: Vec<_> = audits.iter().map(|(audit, _)| (audit.payload_hash, audit.local)).collect();
+        assert!(local.contains(&(anchored_hash, LocalCheck::Corrupted)) && local.contains(&(dealt_hash, LocalCheck::Intact)));
+
+        // Remote proofs answering the round's challenges land in its records
+        let mut auditor = ProofAuditor::new();
+        auditor.record_audits(audits.clone(), 1_000);
+        let (_, challenge) = audits.iter().find(|(audit, _)| audit.payload_hash == dealt_hash).unwrap();
+        let replica_storage = OffChainStorageManager::new(&dir.path().join("replica")).unwrap();
+        replica_storage.store_payload(&dealt).unwrap();
+        let replica_key = SigningKey::from_bytes(&[2u8; 32]);
+        let proof = respond_to_challenge(challenge, &replica_storage, &replica_key, 1_010).unwrap();
+        assert_eq!(auditor.observe_proof(&proof, &storage, 1_020), ProofVerdict::Valid);
+        let log = auditor.audit_log();
+        assert_eq!((log.rounds(), log.last_round_at(), log.records().count()), (1, Some(1_000), 2));
+        let record = log.records().find(|audit| audit.payload_hash == dealt_hash).unwrap();
+        assert_eq!(record.replicas, vec![ReplicaCheck { provider: replica_key.verifying_key().to_bytes(), valid: true, responded_at: 1_010 }]);
+    }
+}
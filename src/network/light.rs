@@ -0,0 +1,199 @@
+//!
+//! # Light Client Service
+//! Request-response messages serving light clients: ranges of main-chain headers and of
+//! compact block filters (see [`crate::core::filters`]), and inclusion proofs of confirmed
+//! transactions, the data [`crate::light`] verifies.
+//!
+//! Serving is budgeted per peer in fixed windows of `LIGHT_QUOTA_WINDOW_SECS`, with separate
+//! budgets for headers, filters and proofs, so a light client syncing from scratch or polling
+//! for proofs can't take the node's time away from full peers. A range request larger than
+//! what is left of the budget gets the items that fit; once nothing is left, requests are
+//! answered with `QuotaExceeded` until the window ends.
+
+use crate::core::{Blockchain, BlockFilter, BlockHeader, Hash, Transaction, TransactionProof};
+use libp2p::PeerId;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+/// Request-response protocol for light clients.
+pub const LIGHT_PROTOCOL: &str = "/blockchain-data-storage/light/1.0.0";
+/// Most headers one response holds.
+pub const MAX_HEADERS_PER_RESPONSE: u64 = 2000;
+/// Most block filters one response holds.
+pub const MAX_FILTERS_PER_RESPONSE: u64 = 1000;
+/// Length of a quota window, in seconds.
+pub const LIGHT_QUOTA_WINDOW_SECS: u64 = 60;
+/// Headers served to one peer per window by default.
+pub const DEFAULT_HEADERS_PER_WINDOW: u64 = 20_000;
+/// Block filters served to one peer per window by default.
+pub const DEFAULT_FILTERS_PER_WINDOW: u64 = 20_000;
+/// Inclusion proofs served to one peer per window by default.
+pub const DEFAULT_PROOFS_PER_WINDOW: u64 = 600;
+
+/// Peers tracked before windows from earlier periods are dropped.
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// What a light client asks for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum LightRequest {
+    /// Main-chain headers from `start_height` on, at most `count` (and `MAX_HEADERS_PER_RESPONSE`).
+    GetHeaders { start_height: u64, count: u64 },
+    /// Filters of main-chain blocks from `start_height` on, at most `count` (and `MAX_FILTERS_PER_RESPONSE`).
+    GetFilters { start_height: u64, count: u64 },
+    /// A confirmed transaction with the proof of its inclusion in its block.
+    GetProof { tx_hash: Hash },
+}
+
+/// A transaction with the proof that its block includes it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProvenTransaction {
+    pub transaction: Transaction,
+    pub proof: TransactionProof,
+}
+
+/// The serving node's answer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum LightResponse {
+    /// Consecutive main-chain headers; empty past the tip.
+    Headers { tip_height: Option<u64>, headers: Vec<BlockHeader> },
+    /// Filters of consecutive main-chain blocks with their hashes; empty past the tip.
+    Filters { start_height: u64, filters: Vec<(Hash, BlockFilter)> },
+    /// `None` if the transaction is not on the main chain.
+    Proof(Option<Box<ProvenTransaction>>),
+    /// The peer spent its budget; retry in `retry_after` seconds.
+    QuotaExceeded { retry_after: u64 },
+    Error(String),
+}
+
+/// Budgets of each peer per `LIGHT_QUOTA_WINDOW_SECS` window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LightQuotaLimits {
+    pub headers_per_window: u64,
+    pub filters_per_window: u64,
+    pub proofs_per_window: u64,
+}
+
+impl Default for LightQuotaLimits {
+    fn default() -> Self {
+        LightQuotaLimits {
+            headers_per_window: DEFAULT_HEADERS_PER_WINDOW,
+            filters_per_window: DEFAULT_FILTERS_PER_WINDOW,
+            proofs_per_window: DEFAULT_PROOFS_PER_WINDOW,
+        }
+    }
+}
+
+// What a peer was served in its current window
+#[derive(Debug, Clone, Copy)]
+struct LightWindow {
+    start: u64,
+    headers: u64,
+    filters: u64,
+    proofs: u64,
+}
+
+/// Counts what each peer is served in fixed windows.
+#[derive(Debug)]
+pub struct LightQuotas {
+    limits: LightQuotaLimits,
+    windows: HashMap<PeerId, LightWindow>,
+}
+
+impl LightQuotas {
+    pub fn new(limits: LightQuotaLimits) -> Self {
+        LightQuotas { limits, windows: HashMap::new() }
+    }
+
+    /// Charges `request` from `peer` at `now` and returns how much of it may be served: the
+    /// headers or filters that fit in the budget, or 1 for a proof. Fails with the seconds until the window
+    /// ends if the budget is spent.
+    pub fn charge(&mut self, peer: &PeerId, request: &LightRequest, now: u64) -> Result<u64, u64> {
+        let window_start = now - now % LIGHT_QUOTA_WINDOW_SECS;
+        if self.windows.len() >= MAX_TRACKED_PEERS && !self.windows.contains_key(peer) {
+            self.windows.retain(|_, window| window.start == window_start);
+        }
+        let fresh = LightWindow { start: window_start, headers: 0, filters: 0, proofs: 0 };
+        let window = self.windows.entry(*peer).or_insert(fresh);
+        if window.start != window_start {
+            *window = fresh;
+        }
+        let (used, limit, wanted) = match request {
+            LightRequest::GetHeaders { count, .. } => (&mut window.headers, self.limits.headers_per_window, (*count).min(MAX_HEADERS_PER_RESPONSE)),
+            LightRequest::GetFilters { count, .. } => (&mut window.filters, self.limits.filters_per_window, (*count).min(MAX_FILTERS_PER_RESPONSE)),
+            LightRequest::GetProof { .. } => (&mut window.proofs, self.limits.proofs_per_window, 1),
+        };
+        let allowed = wanted.min(limit.saturating_sub(*used));
+        if allowed == 0 && wanted > 0 {
+            return Err(window_start + LIGHT_QUOTA_WINDOW_SECS - now);
+        }
+        *used += allowed;
+        Ok(allowed)
+    }
+}
+
+/// Answers a light client's request from the main chain of `blockchain`, serving at most
+/// `allowed` headers or filters (see [`LightQuotas::charge`]).
+pub fn serve_light_request(blockchain: &Blockchain, request: &LightRequest, allowed: u64) -> LightResponse {
+    match request {
+        LightRequest::GetHeaders { start_height, .. } => {
+            let end_height = start_height.saturating_add(allowed);
+            match blockchain.storage().iter_headers(*start_height..end_height).collect::<Result<Vec<_>, _>>() {
+                Ok(headers) => LightResponse::Headers { tip_height: blockchain.get_chain_height(), headers },
+                Err(e) => LightResponse::Error(format!("Storage error: {}", e)),
+            }
+        }
+        LightRequest::GetFilters { start_height, .. } => match blockchain.get_block_filters(*start_height, allowed) {
+            Ok(filters) => LightResponse::Filters { start_height: *start_height, filters },
+            Err(e) => LightResponse::Error(e.to_string()),
+        },
+        LightRequest::GetProof { tx_hash } => match blockchain.get_transaction_proof(tx_hash) {
+            Ok(proven) => LightResponse::Proof(proven.map(|(confirmed, proof)| Box::new(ProvenTransaction { transaction: confirmed.transaction, proof }))),
+            Err(e) => LightResponse::Error(e.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light;
+    use crate::presets::NetworkPreset;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_light_requests_are_served_within_quota() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        blockchain.generate_blocks(4, &vec![1u8; 21]).unwrap();
+
+        let (peer, other) = (PeerId::random(), PeerId::random());
+        let mut quotas = LightQuotas::new(LightQuotaLimits { headers_per_window: 3, filters_per_window: 2, proofs_per_window: 1 });
+        let request = LightRequest::GetHeaders { start_height: 1, count: 10 };
+        let allowed = quotas.charge(&peer, &request, 120).unwrap();
+        assert_eq!(allowed, 3);
+        let LightResponse::Headers { tip_height, headers } = serve_light_request(&blockchain, &request, allowed) else { panic!("expected headers") };
+        assert_eq!((tip_height, headers.iter().map(|header| header.height).collect::<Vec<_>>()), (Some(4), vec![1, 2, 3]));
+        assert_eq!(quotas.charge(&peer, &request, 150), Err(30));
+        assert_eq!(quotas.charge(&other, &request, 150), Ok(3));
+        assert_eq!(quotas.charge(&peer, &request, 180), Ok(3));
+
+        // A served proof verifies against the served header of its block
+        let tx_hash = blockchain.get_block_by_height(2).unwrap().unwrap().transactions[0].calculate_hash();
+        let request = LightRequest::GetProof { tx_hash };
+        assert_eq!(quotas.charge(&peer, &request, 180), Ok(1));
+        let LightResponse::Proof(Some(proven)) = serve_light_request(&blockchain, &request, 1) else { panic!("expected a proof") };
+        assert_eq!(light::verify_inclusion(&headers[1], &proven.transaction, &proven.proof), Ok(()));
+        assert!(quotas.charge(&peer, &request, 190).is_err());
+        assert_eq!(serve_light_request(&blockchain, &LightRequest::GetProof { tx_hash: [0u8; 32] }, 1), LightResponse::Proof(None));
+
+        // Filters have a budget of their own, and match the addresses of their blocks
+        let request = LightRequest::GetFilters { start_height: 3, count: 5 };
+        let allowed = quotas.charge(&peer, &request, 180).unwrap();
+        assert_eq!(allowed, 2);
+        let LightResponse::Filters { start_height: 3, filters } = serve_light_request(&blockchain, &request, allowed) else { panic!("expected filters") };
+        assert_eq!(filters.iter().map(|(block_hash, _)| *block_hash).collect::<Vec<_>>(), vec![headers[2].calculate_hash(), blockchain.get_block_by_height(4).unwrap().unwrap().hash()]);
+        assert!(filters.iter().all(|(block_hash, filter)| filter.matches(block_hash, &[1u8; 21])));
+        assert!(quotas.charge(&peer, &request, 190).is_err());
+    }
+}
@@ -2,6 +2,32 @@
 //! # Network Module
 //! This module handles the peer-to-peer (P2P) networking aspects of the blockchain using libp2p.
 //! It is responsible for node identity, peer discovery, connection management, and message propagation.
+//! On every new connection both sides exchange a small handshake reporting the address each sees the
+//! other at and their clocks; the results are aggregated in [`PeerObservations`].
+//! Off-chain payloads anchored by blocks received from a peer are fetched from that peer
+//! (see [`payloads`]), paying peers that charge for retrieval (see [`retrieval`]). Storage challenges and proofs are gossiped so any node holding a
+//! copy of a payload can audit its providers (see [`proofs`]), and the node audits the payloads
+//! it keeps on a schedule (see [`audit`]). Payload owners can restrict retrieval to the keys an
+//! ACL lists, gossiped with the proofs and enforced by every provider (see [`acl`]). Light clients
+//! can fetch headers and transaction inclusion proofs, within per-peer quotas (see [`light`]).
+
+pub mod acl;
+pub mod audit;
+pub mod light;
+pub mod observations;
+pub mod payloads;
+pub mod proofs;
+pub mod redundancy;
+pub mod retrieval;
+
+pub use acl::{AclError, PayloadAcl, ReadAuthorization};
+pub use audit::{AuditLog, LocalCheck, PayloadAudit};
+pub use light::{LightQuotaLimits, LightQuotas, LightRequest, LightResponse};
+pub use observations::{HandshakeRequest, HandshakeResponse, ObservationsSummary, PeerDirection, PeerInfo, PeerObservations};
+pub use payloads::{PayloadRequest, PayloadResponse};
+pub use proofs::{PayloadHolder, ProofAuditor, ProofVerdict, StorageChallenge, StorageProof};
+pub use redundancy::{Placement, PlacementStatus, RedundancyPolicy, ReplicationRequest};
+pub use retrieval::{RetrievalEarnings, RetrievalTerms};
 
 use libp2p::{
     core::upgrade,
@@ -10,12 +36,14 @@ use libp2p::{
     identity,
     kad::{self, store::MemoryStore},
     noise, // Feature 'noise' added in Cargo.toml
-    // request_response, // Keep for future use
+    request_response::{self, ProtocolSupport},
     swarm::{NetworkBehaviour, SwarmEvent}, // SwarmBuilder moved
     tcp,
     yamux,
-    // Multiaddr, // Not used currently
+    multiaddr::Protocol,
+    Multiaddr,
     PeerId,
+    StreamProtocol,
     Swarm,
     SwarmBuilder, // Imported directly as suggested
     Transport,
@@ -23,19 +51,23 @@ use libp2p::{
 use std::collections::hash_map::DefaultHasher;
 use std::error::Error;
 use std::hash::{Hash, Hasher};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::{
     select,
-    // sync::mpsc, // Keep for potential future command channel
+    sync::mpsc,
     // time::sleep,
 };
 use log::{error, info, warn, debug};
 use serde::{Serialize, Deserialize};
 
 // Import core types needed for network messages
-use crate::core::{Block, Transaction, BlockchainError};
+use crate::core::{Block, SignedTransaction, Transaction, BlockchainError};
 use crate::core::Blockchain; // Import Blockchain to interact with it
+use crate::core::time::unix_now;
+use crate::offchain_storage::OffChainStorageManager;
+use crate::service::Shutdown;
 use std::sync::{Arc, Mutex}; // To share Blockchain state safely
+use ed25519_dalek::SigningKey;
 
 // --- Network Message Definition ---
 
@@ -46,9 +78,81 @@ pub enum NetworkMessage {
     NewBlock(Block),
     /// Announce a new transaction to the network.
     NewTransaction(Transaction),
+    /// Announce a new transaction with its sender's signature, for transactions spending the sender's funds.
+    NewSignedTransaction(SignedTransaction),
+    /// Challenge the storers of a payload to prove they still hold it.
+    StorageChallenge(StorageChallenge),
+    /// A provider's answer to a storage challenge.
+    StorageProof(StorageProof),
+    /// Ask connected peers for a payload, naming the payment for peers that charge. Local only:
+    /// sent to the network node by other components and never gossiped.
+    FetchPayload { payload_hash: [u8; 32], payment: Option<[u8; 32]> },
+    /// Ask peers to store payloads that have fewer copies than their redundancy policies ask for.
+    ReplicationRequest(ReplicationRequest),
+    /// A payload owner's latest ACL for the payload.
+    PayloadAcl(PayloadAcl),
     // TODO: Add messages for request/response (e.g., GetBlocks, BlocksResponse)
 }
 
+/// Sending half of the channel other components (RPC, miner) use to publish through the network node.
+pub type OutboundSender = mpsc::UnboundedSender<NetworkMessage>;
+/// Receiving half, consumed by [`start_network_node`].
+pub type OutboundReceiver = mpsc::UnboundedReceiver<NetworkMessage>;
+
+/// Listen address used when none is configured: all interfaces, a port picked by the OS.
+pub const DEFAULT_LISTEN_ADDR: &str = "/ip4/0.0.0.0/tcp/0";
+
+/// How long a shutting-down node waits for its peer connections to close.
+const SWARM_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the network node listens and which peers it joins through at startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkConfig {
+    pub listen_addrs: Vec<Multiaddr>,
+    /// Dialed at startup and seeded into Kademlia; each ends in `/p2p/<peer id>`.
+    pub bootstrap_peers: Vec<Multiaddr>,
+    /// What payloads are served for; free if `None`.
+    pub retrieval: Option<RetrievalTerms>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            listen_addrs: vec![DEFAULT_LISTEN_ADDR.parse().expect("Default listen address parses")],
+            bootstrap_peers: Vec::new(),
+            retrieval: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Parses multiaddrs given as text; no listen address means [`DEFAULT_LISTEN_ADDR`].
+    pub fn parse(listen_addrs: &[String], bootstrap_peers: &[String]) -> Result<Self, String> {
+        let parse = |addr: &String| addr.parse::<Multiaddr>().map_err(|e| format!("Invalid multiaddr {:?}: {}", addr, e));
+        let mut config = NetworkConfig::default();
+        if !listen_addrs.is_empty() {
+            config.listen_addrs = listen_addrs.iter().map(parse).collect::<Result<_, _>>()?;
+        }
+        config.bootstrap_peers = bootstrap_peers.iter().map(parse).collect::<Result<_, _>>()?;
+        if let Some(addr) = config.bootstrap_peers.iter().find(|addr| bootstrap_peer_id(addr).is_none()) {
+            return Err(format!("Bootstrap peer {} does not end in /p2p/<peer id>", addr));
+        }
+        Ok(config)
+    }
+}
+
+fn bootstrap_peer_id(addr: &Multiaddr) -> Option<PeerId> {
+    match addr.iter().last() {
+        Some(Protocol::P2p(peer_id)) => Some(peer_id),
+        _ => None,
+    }
+}
+
+/// Creates the channel for messages to be published by the network node.
+pub fn outbound_channel() -> (OutboundSender, OutboundReceiver) {
+    mpsc::unbounded_channel()
+}
+
 // --- Topics for Gossipsub ---
 
 /// Topic for broadcasting newly mined blocks.
@@ -56,6 +160,9 @@ pub const BLOCKS_TOPIC: &str = "blocks";
 /// Topic for broadcasting new transactions.
 pub const TRANSACTIONS_TOPIC: &str = "transactions";
 
+/// Request-response protocol for the connection handshake (observed address and clock).
+pub const HANDSHAKE_PROTOCOL: &str = "/blockchain-data-storage/handshake/1.0.0";
+
 // --- Network Behaviour Definition ---
 
 /// Defines the combined network behaviour for the blockchain node.
@@ -64,7 +171,9 @@ pub const TRANSACTIONS_TOPIC: &str = "transactions";
 pub struct BlockchainBehaviour {
     gossipsub: gossipsub::Behaviour,
     kademlia: kad::Behaviour<MemoryStore>,
-    // TODO: Add request_response behaviour
+    handshake: request_response::json::Behaviour<HandshakeRequest, HandshakeResponse>,
+    payloads: request_response::json::Behaviour<PayloadRequest, PayloadResponse>,
+    light: request_response::json::Behaviour<LightRequest, LightResponse>,
 }
 
 /// Events generated by the `BlockchainBehaviour`.
@@ -72,7 +181,9 @@ pub struct BlockchainBehaviour {
 pub enum BlockchainBehaviourEvent {
     Gossipsub(gossipsub::Event),
     Kademlia(kad::Event),
-    // TODO: Add RequestResponse event variant
+    Handshake(request_response::Event<HandshakeRequest, HandshakeResponse>),
+    Payloads(request_response::Event<PayloadRequest, PayloadResponse>),
+    Light(request_response::Event<LightRequest, LightResponse>),
 }
 
 impl From<gossipsub::Event> for BlockchainBehaviourEvent {
@@ -87,6 +198,24 @@ impl From<kad::Event> for BlockchainBehaviourEvent {
     }
 }
 
+impl From<request_response::Event<HandshakeRequest, HandshakeResponse>> for BlockchainBehaviourEvent {
+    fn from(event: request_response::Event<HandshakeRequest, HandshakeResponse>) -> Self {
+        BlockchainBehaviourEvent::Handshake(event)
+    }
+}
+
+impl From<request_response::Event<PayloadRequest, PayloadResponse>> for BlockchainBehaviourEvent {
+    fn from(event: request_response::Event<PayloadRequest, PayloadResponse>) -> Self {
+        BlockchainBehaviourEvent::Payloads(event)
+    }
+}
+
+impl From<request_response::Event<LightRequest, LightResponse>> for BlockchainBehaviourEvent {
+    fn from(event: request_response::Event<LightRequest, LightResponse>) -> Self {
+        BlockchainBehaviourEvent::Light(event)
+    }
+}
+
 // --- Network Service Helper ---
 
 /// Helper struct to encapsulate network actions like publishing messages.
@@ -124,8 +253,16 @@ impl<
 
     /// Publishes a transaction to the network via Gossipsub.
     pub fn publish_transaction(&mut self, tx: &Transaction) -> Result<(), PublishError> {
+        self.publish_transaction_message(tx, NetworkMessage::NewTransaction(tx.clone()))
+    }
+
+    /// Publishes a transaction and its sender's signature to the network via Gossipsub.
+    pub fn publish_signed_transaction(&mut self, signed: &SignedTransaction) -> Result<(), PublishError> {
+        self.publish_transaction_message(&signed.transaction, NetworkMessage::NewSignedTransaction(signed.clone()))
+    }
+
+    fn publish_transaction_message(&mut self, tx: &Transaction, message: NetworkMessage) -> Result<(), PublishError> {
         let topic = Topic::new(TRANSACTIONS_TOPIC);
-        let message = NetworkMessage::NewTransaction(tx.clone());
         match bincode::serialize(&message) {
             Ok(serialized) => {
                 let tx_hash = tx.calculate_hash();
@@ -138,6 +275,21 @@ impl<
             }
         }
     }
+
+    /// Publishes a storage challenge, proof, replication request or payload ACL via Gossipsub.
+    pub fn publish_storage_proof_message(&mut self, message: &NetworkMessage) -> Result<(), PublishError> {
+        let topic = Topic::new(proofs::PROOFS_TOPIC);
+        match bincode::serialize(message) {
+            Ok(serialized) => {
+                debug!("Publishing {:?} to topic {}", message, proofs::PROOFS_TOPIC);
+                self.swarm.behaviour_mut().gossipsub.publish(topic, serialized).map(|_id: MessageId| ())
+            }
+            Err(e) => {
+                error!("Failed to serialize storage proof message for publishing: {}", e);
+                Err(PublishError::InsufficientPeers) // Placeholder error
+            }
+        }
+    }
 }
 
 // --- Swarm Building Logic ---
@@ -175,13 +327,33 @@ fn build_swarm(local_key: identity::Keypair) -> Result<Swarm<BlockchainBehaviour
 
     let blocks_topic = Topic::new(BLOCKS_TOPIC);
     let transactions_topic = Topic::new(TRANSACTIONS_TOPIC);
+    let proofs_topic = Topic::new(proofs::PROOFS_TOPIC);
     gossipsub.subscribe(&blocks_topic)?;
     gossipsub.subscribe(&transactions_topic)?;
-    info!("Subscribed to Gossipsub topics: {}, {}", BLOCKS_TOPIC, TRANSACTIONS_TOPIC);
+    gossipsub.subscribe(&proofs_topic)?;
+    info!("Subscribed to Gossipsub topics: {}, {}, {}", BLOCKS_TOPIC, TRANSACTIONS_TOPIC, proofs::PROOFS_TOPIC);
+
+    let handshake = request_response::json::Behaviour::new(
+        [(StreamProtocol::new(HANDSHAKE_PROTOCOL), ProtocolSupport::Full)],
+        request_response::Config::default(),
+    );
+
+    let payloads = request_response::json::Behaviour::new(
+        [(StreamProtocol::new(payloads::PAYLOAD_PROTOCOL), ProtocolSupport::Full)],
+        request_response::Config::default(),
+    );
+
+    let light = request_response::json::Behaviour::new(
+        [(StreamProtocol::new(light::LIGHT_PROTOCOL), ProtocolSupport::Full)],
+        request_response::Config::default(),
+    );
 
     let behaviour = BlockchainBehaviour {
         gossipsub,
         kademlia,
+        handshake,
+        payloads,
+        light,
     };
 
     // Corrected SwarmBuilder usage for libp2p 0.53
@@ -202,7 +374,27 @@ fn build_swarm(local_key: identity::Keypair) -> Result<Swarm<BlockchainBehaviour
 // --- Node Start and Event Loop ---
 
 /// Starts the network node and runs the main event loop.
-pub async fn start_network_node(blockchain: Arc<Mutex<Blockchain>>) -> Result<(), Box<dyn Error>> {
+///
+/// Handshake results from peers are recorded in `observations`, which is shared with the RPC server.
+/// Messages received on `outbound` (e.g. blocks submitted over RPC) are published to the network.
+/// Payloads are served to and fetched from peers through `offchain_storage`.
+/// Storage challenges for payloads held locally are answered with proofs signed by `node_key`;
+/// proofs from other providers are audited into `auditor`, which is shared with the RPC server.
+/// Light clients are served headers and inclusion proofs within the default per-peer quotas.
+/// The node listens on, and joins the network through, the addresses in `config`, and charges
+/// for the payloads it serves under its retrieval terms, if any.
+/// Once `shutdown` is triggered it closes its peer connections and returns `Ok(())`.
+#[allow(clippy::too_many_arguments)]
+pub async fn start_network_node(
+    blockchain: Arc<Mutex<Blockchain>>,
+    offchain_storage: Arc<OffChainStorageManager>,
+    observations: Arc<Mutex<PeerObservations>>,
+    mut outbound: OutboundReceiver,
+    node_key: Arc<SigningKey>,
+    auditor: Arc<Mutex<ProofAuditor>>,
+    config: NetworkConfig,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn Error>> {
     let _ = env_logger::try_init(); // Use try_init to avoid panic
 
     let local_key = identity::Keypair::generate_ed25519();
@@ -211,24 +403,95 @@ pub async fn start_network_node(blockchain: Arc<Mutex<Blockchain>>) -> Result<()
 
     let mut swarm = build_swarm(local_key)?;
 
-    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+    for addr in config.listen_addrs {
+        swarm.listen_on(addr)?;
+    }
+    for addr in config.bootstrap_peers {
+        if let Some(peer_id) = bootstrap_peer_id(&addr) {
+            swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
+        }
+        if let Err(e) = swarm.dial(addr.clone()) {
+            warn!("Failed to dial bootstrap peer {}: {}", addr, e);
+        }
+    }
+    swarm.behaviour_mut().kademlia.bootstrap().ok();
+    let mut light_quotas = LightQuotas::new(LightQuotaLimits::default());
+    let retrieval = config.retrieval;
 
     // Main event loop
     loop {
         select! {
             event = swarm.select_next_some() => {
-                handle_swarm_event(&mut swarm, event, blockchain.clone()).await;
+                let context = ProofContext { node_key: &node_key, auditor: &auditor };
+                handle_swarm_event(&mut swarm, event, blockchain.clone(), &offchain_storage, &observations, context, &mut light_quotas, retrieval.as_ref()).await;
             }
+            Some(message) = outbound.recv() => {
+                // Gossipsub does not deliver our own messages back, so audit local challenges here
+                if let NetworkMessage::StorageChallenge(challenge) = &message {
+                    auditor.lock().expect("Proof auditor lock poisoned").observe_challenge(challenge.clone(), unix_now());
+                }
+                let mut network_service = NetworkService::new(&mut swarm);
+                let published = match &message {
+                    NetworkMessage::NewBlock(block) => network_service.publish_block(block),
+                    NetworkMessage::NewTransaction(tx) => network_service.publish_transaction(tx),
+                    NetworkMessage::NewSignedTransaction(signed) => network_service.publish_signed_transaction(signed),
+                    NetworkMessage::StorageChallenge(_) | NetworkMessage::StorageProof(_) | NetworkMessage::ReplicationRequest(_) | NetworkMessage::PayloadAcl(_) => {
+                        network_service.publish_storage_proof_message(&message)
+                    }
+                    NetworkMessage::FetchPayload { payload_hash, payment } => {
+                        let peers: Vec<PeerId> = network_service.swarm.connected_peers().copied().collect();
+                        info!("Requesting payload {} from {} peers", hex::encode(payload_hash), peers.len());
+                        for peer in peers {
+                            let request = PayloadRequest::signed(*payload_hash, *payment, &node_key, unix_now());
+                            network_service.swarm.behaviour_mut().payloads.send_request(&peer, request);
+                        }
+                        Ok(())
+                    }
+                };
+                if let Err(e) = published {
+                    warn!("Failed to publish locally produced message: {}", e);
+                }
+            }
+            _ = shutdown.wait() => break,
             // TODO: Replace with actual node logic (e.g., mining trigger, API calls)
         }
     }
+
+    // Close connections so peers see a clean goodbye rather than a reset, without waiting on slow ones forever
+    let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+    info!("Closing connections to {} peers...", peers.len());
+    for peer in peers {
+        let _ = swarm.disconnect_peer_id(peer);
+    }
+    let closed = tokio::time::timeout(SWARM_CLOSE_TIMEOUT, async {
+        while swarm.connected_peers().next().is_some() {
+            swarm.select_next_some().await;
+        }
+    }).await;
+    if closed.is_err() {
+        warn!("Some peer connections did not close within {:?}; dropping them", SWARM_CLOSE_TIMEOUT);
+    }
+    info!("Network node stopped.");
+    Ok(())
+}
+
+/// Node key and audit state used to answer and audit storage proofs.
+struct ProofContext<'a> {
+    node_key: &'a SigningKey,
+    auditor: &'a Mutex<ProofAuditor>,
 }
 
 /// Handles events generated by the libp2p Swarm, including processing network messages.
+#[allow(clippy::too_many_arguments)]
 async fn handle_swarm_event(
     swarm: &mut Swarm<BlockchainBehaviour>,
     event: SwarmEvent<BlockchainBehaviourEvent>,
-    blockchain: Arc<Mutex<Blockchain>> // Pass blockchain state
+    blockchain: Arc<Mutex<Blockchain>>, // Pass blockchain state
+    offchain_storage: &Arc<OffChainStorageManager>,
+    observations: &Arc<Mutex<PeerObservations>>,
+    proof_context: ProofContext<'_>,
+    light_quotas: &mut LightQuotas,
+    retrieval: Option<&RetrievalTerms>,
 ) {
     match event {
         SwarmEvent::NewListenAddr { address, .. } => {
@@ -259,6 +522,33 @@ async fn handle_swarm_event(
                     debug!("Gossipsub: Received message ID {} from Peer {} on Topic {}", message_id, peer_id, topic);
 
                     match bincode::deserialize::<NetworkMessage>(&message.data) {
+                        Ok(NetworkMessage::StorageChallenge(challenge)) if topic == proofs::PROOFS_TOPIC => {
+                            handle_storage_challenge(swarm, challenge, offchain_storage, proof_context);
+                        }
+                        Ok(NetworkMessage::ReplicationRequest(request)) if topic == proofs::PROOFS_TOPIC => {
+                            // The signed origin, not the relaying peer, is the node holding the payloads
+                            let requester = message.source.unwrap_or(peer_id);
+                            handle_replication_request(swarm, request, requester, &blockchain, offchain_storage, proof_context.node_key);
+                        }
+                        Ok(NetworkMessage::PayloadAcl(acl)) if topic == proofs::PROOFS_TOPIC => {
+                            let payload_hash = hex::encode(acl.payload_hash);
+                            match acl::accept_acl(&blockchain.lock().expect("Blockchain lock poisoned"), &acl, unix_now()) {
+                                Ok(()) => info!("Updated ACL of payload {} to {} readers (via {})", payload_hash, acl.readers.len(), peer_id),
+                                Err(AclError::Stale(_)) => debug!("Ignored stale ACL of payload {} from {}", payload_hash, peer_id),
+                                Err(e) => warn!("Rejected ACL of payload {} from {}: {}", payload_hash, peer_id, e),
+                            }
+                        }
+                        Ok(NetworkMessage::StorageProof(proof)) if topic == proofs::PROOFS_TOPIC => {
+                            let provider = hex::encode(proof.provider);
+                            let verdict = proof_context.auditor.lock().expect("Proof auditor lock poisoned")
+                                .observe_proof(&proof, offchain_storage, unix_now());
+                            match verdict {
+                                ProofVerdict::Valid => debug!("Valid storage proof from provider {} (via {})", provider, peer_id),
+                                ProofVerdict::Invalid => warn!("Invalid storage proof for payload {} from provider {} (via {})", hex::encode(proof.payload_hash), provider, peer_id),
+                                ProofVerdict::Unverifiable => debug!("Cannot audit storage proof from provider {}: challenge or payload unknown here", provider),
+                                ProofVerdict::Rejected => debug!("Ignored storage proof from provider {} (via {})", provider, peer_id),
+                            }
+                        }
                         Ok(network_message) => {
                             let mut bc_guard = blockchain.lock().expect("Blockchain lock poisoned");
                             let mut network_service = NetworkService::new(swarm);
@@ -270,6 +560,12 @@ async fn handle_swarm_event(
                                         Ok(_) => {
                                             info!("Successfully processed block received from network.");
                                             drop(bc_guard);
+                                            // Fetch the payloads the block anchors from the peer that sent it
+                                            for payload_hash in payloads::missing_payloads(&block, offchain_storage) {
+                                                debug!("Requesting payload {} from {}", hex::encode(payload_hash), peer_id);
+                                                let request = PayloadRequest::signed(payload_hash, None, proof_context.node_key, unix_now());
+                                                network_service.swarm.behaviour_mut().payloads.send_request(&peer_id, request);
+                                            }
                                             // Optional: Propagate valid block
                                             // if let Err(e) = network_service.publish_block(&block) {
                                             //     error!("Failed to re-publish block {}: {}", block.header.height, e);
@@ -277,7 +573,16 @@ async fn handle_swarm_event(
                                         }
                                         Err(e) => {
                                             match e {
-                                                BlockchainError::Validation(_) | BlockchainError::Consensus(_) => {
+                                                BlockchainError::Consensus(_) => {
+                                                    warn!("Invalid block received from {}: {}", peer_id, e);
+                                                    observations.lock().expect("Observations lock poisoned")
+                                                        .penalize(&peer_id, observations::INVALID_BLOCK_PENALTY);
+                                                    // Kept as evidence; orphans and stale blocks (Validation) are too common to record
+                                                    if let Err(record_err) = bc_guard.record_invalid_block(&block, &e, Some(peer_id.to_string())) {
+                                                        error!("Failed to record invalid block evidence: {}", record_err);
+                                                    }
+                                                }
+                                                BlockchainError::Validation(_) => {
                                                     warn!("Invalid block received from {}: {}", peer_id, e);
                                                 }
                                                 _ => {
@@ -306,6 +611,24 @@ async fn handle_swarm_event(
                                         Err(e) => warn!("Failed to add transaction {} from {}: {}", hex::encode(tx_hash), peer_id, e),
                                     }
                                 }
+                                (TRANSACTIONS_TOPIC, NetworkMessage::NewSignedTransaction(signed)) => {
+                                    let tx_hash = signed.transaction.calculate_hash();
+                                    info!("Received NewSignedTransaction message (Hash: {}) from {}", hex::encode(tx_hash), peer_id);
+                                    match bc_guard.add_signed_transaction(signed.clone()) {
+                                        Ok(added) => {
+                                            if added {
+                                                info!("Added new transaction {} from network to mempool.", hex::encode(tx_hash));
+                                                drop(bc_guard);
+                                                if let Err(e) = network_service.publish_signed_transaction(&signed) {
+                                                    error!("Failed to re-publish transaction {}: {}", hex::encode(tx_hash), e);
+                                                }
+                                            } else {
+                                                debug!("Transaction {} from network already in mempool.", hex::encode(tx_hash));
+                                            }
+                                        }
+                                        Err(e) => warn!("Failed to add transaction {} from {}: {}", hex::encode(tx_hash), peer_id, e),
+                                    }
+                                }
                                 (other_topic, msg_type) => {
                                     warn!("Received unexpected message type {:?} on topic {}", msg_type, other_topic);
                                 }
@@ -313,6 +636,8 @@ async fn handle_swarm_event(
                         }
                         Err(e) => {
                             warn!("Failed to deserialize Gossipsub message from {}: {}", peer_id, e);
+                            observations.lock().expect("Observations lock poisoned")
+                                .penalize(&peer_id, observations::MALFORMED_MESSAGE_PENALTY);
                         }
                     }
                 }
@@ -325,12 +650,37 @@ async fn handle_swarm_event(
                 _ => {}
             }
         }
-        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+        SwarmEvent::Behaviour(BlockchainBehaviourEvent::Handshake(handshake_event)) => {
+            handle_handshake_event(swarm, handshake_event, &blockchain, observations);
+        }
+        SwarmEvent::Behaviour(BlockchainBehaviourEvent::Payloads(payload_event)) => {
+            handle_payload_event(swarm, payload_event, &blockchain, offchain_storage, observations, retrieval);
+        }
+        SwarmEvent::Behaviour(BlockchainBehaviourEvent::Light(light_event)) => {
+            handle_light_event(swarm, light_event, &blockchain, light_quotas);
+        }
+        SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
             info!("Connection established with peer: {peer_id} at {endpoint:?}");
-            swarm.behaviour_mut().kademlia.add_address(&peer_id, endpoint.get_remote_address().clone());
+            let remote_addr = endpoint.get_remote_address().clone();
+            swarm.behaviour_mut().kademlia.add_address(&peer_id, remote_addr.clone());
+            let direction = if endpoint.is_dialer() { PeerDirection::Outbound } else { PeerDirection::Inbound };
+            observations.lock().expect("Observations lock poisoned").peer_connected(peer_id, remote_addr.to_string(), direction);
+            // Handshake once per peer, on its first connection
+            if num_established.get() == 1 {
+                let request = HandshakeRequest {
+                    timestamp: unix_now(),
+                    protocol_version: observations::PROTOCOL_VERSION,
+                    height: blockchain.lock().expect("Blockchain lock poisoned").get_chain_height(),
+                };
+                swarm.behaviour_mut().handshake.send_request(&peer_id, request);
+                observations.lock().expect("Observations lock poisoned").handshake_sent(&peer_id, Instant::now());
+            }
         }
-        SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+        SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
             warn!("Connection closed with peer: {peer_id}, Cause: {cause:?}");
+            if num_established == 0 {
+                observations.lock().expect("Observations lock poisoned").peer_disconnected(&peer_id);
+            }
         }
         SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
             debug!("Incoming connection from {send_back_addr} to {local_addr}");
@@ -348,3 +698,198 @@ async fn handle_swarm_event(
     }
 }
 
+
+/// Answers a gossiped storage challenge with a proof if the payload is held locally.
+fn handle_storage_challenge(
+    swarm: &mut Swarm<BlockchainBehaviour>,
+    challenge: StorageChallenge,
+    offchain_storage: &OffChainStorageManager,
+    proof_context: ProofContext<'_>,
+) {
+    let now = unix_now();
+    if !proof_context.auditor.lock().expect("Proof auditor lock poisoned").observe_challenge(challenge.clone(), now) {
+        debug!("Ignored invalid or expired storage challenge for payload {}", hex::encode(challenge.payload_hash));
+        return;
+    }
+    if challenge.challenger == proof_context.node_key.verifying_key().to_bytes() {
+        return;
+    }
+    if let Some(proof) = proofs::respond_to_challenge(&challenge, offchain_storage, proof_context.node_key, now) {
+        info!("Answering storage challenge for payload {}", hex::encode(challenge.payload_hash));
+        if let Err(e) = NetworkService::new(swarm).publish_storage_proof_message(&NetworkMessage::StorageProof(proof)) {
+            warn!("Failed to publish storage proof: {}", e);
+        }
+    }
+}
+
+/// Fetches the payloads of a peer's replication request this node should keep from the peer.
+fn handle_replication_request(
+    swarm: &mut Swarm<BlockchainBehaviour>,
+    request: ReplicationRequest,
+    requester: PeerId,
+    blockchain: &Mutex<Blockchain>,
+    offchain_storage: &OffChainStorageManager,
+    node_key: &SigningKey,
+) {
+    let wanted = {
+        let blockchain = blockchain.lock().expect("Blockchain lock poisoned");
+        redundancy::payloads_to_replicate(&blockchain, offchain_storage, &request)
+    };
+    match wanted {
+        Ok(wanted) => {
+            for payload_hash in wanted {
+                info!("Replicating payload {} from {}", hex::encode(payload_hash), requester);
+                let payload_request = PayloadRequest::signed(payload_hash, None, node_key, unix_now());
+                swarm.behaviour_mut().payloads.send_request(&requester, payload_request);
+            }
+        }
+        Err(e) => warn!("Failed to check replication request from {}: {}", requester, e),
+    }
+}
+
+/// Answers handshake requests and records handshake responses.
+fn handle_handshake_event(
+    swarm: &mut Swarm<BlockchainBehaviour>,
+    event: request_response::Event<HandshakeRequest, HandshakeResponse>,
+    blockchain: &Arc<Mutex<Blockchain>>,
+    observations: &Arc<Mutex<PeerObservations>>,
+) {
+    match event {
+        request_response::Event::Message { peer, message } => match message {
+            request_response::Message::Request { request, channel, .. } => {
+                let now = unix_now();
+                let mut obs = observations.lock().expect("Observations lock poisoned");
+                // The request carries the peer's clock too; latency is not corrected on this side
+                obs.record_clock_offset(peer, request.timestamp as i64 - now as i64);
+                obs.record_handshake_request(&peer, &request);
+                let observed_addr = obs.remote_address(&peer).cloned().unwrap_or_default();
+                drop(obs);
+                let response = HandshakeResponse {
+                    observed_addr,
+                    timestamp: now,
+                    request_timestamp: request.timestamp,
+                    protocol_version: observations::PROTOCOL_VERSION,
+                    height: blockchain.lock().expect("Blockchain lock poisoned").get_chain_height(),
+                };
+                if swarm.behaviour_mut().handshake.send_response(channel, response).is_err() {
+                    debug!("Handshake response to {peer} dropped: connection closed");
+                }
+            }
+            request_response::Message::Response { response, .. } => {
+                debug!("Handshake from {peer}: observed us at {}, peer time {}", response.observed_addr, response.timestamp);
+                observations.lock().expect("Observations lock poisoned")
+                    .record_handshake_response(peer, &response, unix_now());
+            }
+        },
+        request_response::Event::OutboundFailure { peer, error, .. } => {
+            debug!("Handshake with {peer} failed: {error}");
+        }
+        request_response::Event::InboundFailure { peer, error, .. } => {
+            debug!("Inbound handshake from {peer} failed: {error}");
+        }
+        request_response::Event::ResponseSent { .. } => {}
+    }
+}
+
+/// Serves payload requests from local storage to the keys their ACLs list, charging under
+/// `retrieval` if set, and stores verified payloads peers send back with their ACLs. Terms of
+/// peers that charge are recorded in `observations`.
+fn handle_payload_event(
+    swarm: &mut Swarm<BlockchainBehaviour>,
+    event: request_response::Event<PayloadRequest, PayloadResponse>,
+    blockchain: &Mutex<Blockchain>,
+    offchain_storage: &OffChainStorageManager,
+    observations: &Mutex<PeerObservations>,
+    retrieval: Option<&RetrievalTerms>,
+) {
+    match event {
+        request_response::Event::Message { peer, message } => match message {
+            request_response::Message::Request { request, channel, .. } => {
+                let served = {
+                    let blockchain = blockchain.lock().expect("Blockchain lock poisoned");
+                    payloads::serve_authorized_request(&blockchain, offchain_storage, retrieval, &request, unix_now())
+                };
+                let response = served.unwrap_or_else(|e| {
+                    error!("Failed to check the ACL of payload {}: {}", hex::encode(request.payload_hash), e);
+                    PayloadResponse::empty(request.payload_hash)
+                });
+                if response.access_denied {
+                    info!("Refused payload {} to {}: its ACL does not list the requester", hex::encode(request.payload_hash), peer);
+                }
+                if retrieval.is_some() && response.payload.is_some() {
+                    info!("Served paid retrieval of payload {} to {}", hex::encode(request.payload_hash), peer);
+                }
+                if swarm.behaviour_mut().payloads.send_response(channel, response).is_err() {
+                    debug!("Payload response to {peer} dropped: connection closed");
+                }
+            }
+            request_response::Message::Response { response, .. } => {
+                let payload_hash = hex::encode(response.payload_hash);
+                if let Some(terms) = &response.payment_required {
+                    info!("Peer {} charges {} for payload {}", peer, terms.price, payload_hash);
+                    observations.lock().expect("Observations lock poisoned").record_retrieval_terms(&peer, terms.clone());
+                    return;
+                }
+                if response.access_denied {
+                    info!("Peer {} refused payload {}: its ACL does not list this node", peer, payload_hash);
+                    return;
+                }
+                // Kept before the payload, so it is enforced as soon as the payload is served from here
+                if let Some(acl) = &response.acl {
+                    match acl::accept_acl(&blockchain.lock().expect("Blockchain lock poisoned"), acl, unix_now()) {
+                        Ok(()) | Err(AclError::Stale(_)) => {}
+                        Err(e) => warn!("Rejected ACL of payload {} from {}: {}", payload_hash, peer, e),
+                    }
+                }
+                match payloads::accept_payload_response(offchain_storage, response) {
+                    Ok(true) => info!("Stored payload {} fetched from {}", payload_hash, peer),
+                    Ok(false) => debug!("Peer {} does not store payload {}", peer, payload_hash),
+                    Err(e) => warn!("Rejected payload {} from {}: {}", payload_hash, peer, e),
+                }
+            }
+        },
+        request_response::Event::OutboundFailure { peer, error, .. } => {
+            debug!("Payload request to {peer} failed: {error}");
+        }
+        request_response::Event::InboundFailure { peer, error, .. } => {
+            debug!("Inbound payload request from {peer} failed: {error}");
+        }
+        request_response::Event::ResponseSent { .. } => {}
+    }
+}
+
+/// Serves light clients' header and proof requests within their quotas.
+fn handle_light_event(
+    swarm: &mut Swarm<BlockchainBehaviour>,
+    event: request_response::Event<LightRequest, LightResponse>,
+    blockchain: &Arc<Mutex<Blockchain>>,
+    light_quotas: &mut LightQuotas,
+) {
+    match event {
+        request_response::Event::Message { peer, message } => match message {
+            request_response::Message::Request { request, channel, .. } => {
+                let response = match light_quotas.charge(&peer, &request, unix_now()) {
+                    Ok(allowed) => light::serve_light_request(&blockchain.lock().expect("Blockchain lock poisoned"), &request, allowed),
+                    Err(retry_after) => {
+                        debug!("Light client {peer} is over its quota; {:?} refused", request);
+                        LightResponse::QuotaExceeded { retry_after }
+                    }
+                };
+                if swarm.behaviour_mut().light.send_response(channel, response).is_err() {
+                    debug!("Light client response to {peer} dropped: connection closed");
+                }
+            }
+            // This node only serves light clients
+            request_response::Message::Response { .. } => {
+                debug!("Ignored unsolicited light client response from {peer}");
+            }
+        },
+        request_response::Event::OutboundFailure { peer, error, .. } => {
+            debug!("Light client request to {peer} failed: {error}");
+        }
+        request_response::Event::InboundFailure { peer, error, .. } => {
+            debug!("Inbound light client request from {peer} failed: {error}");
+        }
+        request_response::Event::ResponseSent { .. } => {}
+    }
+}
@@ -0,0 +1,416 @@
+//!
+//! # Peer Observations
+//! Aggregates what connected peers report about us during the handshake.
+//!
+//! Each peer tells us the address it sees our connection coming from and its
+//! current clock. Observed addresses reported by several distinct peers are
+//! candidates for our external (post-NAT) address. Clock samples give a
+//! network-adjusted time: our local clock plus the median offset to our peers,
+//! ignored until enough peers have reported and capped so a set of peers can't
+//! drag our time arbitrarily far.
+//!
+//! Per connected peer we also keep the connection direction, the protocol
+//! version and chain height it announced, the handshake round-trip latency
+//! and a misbehavior score, so connectivity can be debugged over RPC, as well as the retrieval
+//! terms it quoted if it charges for payloads.
+
+use super::retrieval::RetrievalTerms;
+use crate::core::time::unix_now;
+use libp2p::PeerId;
+use log::warn;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Version of the node protocol announced in handshakes.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Score deducted from a peer that relays a block failing consensus checks.
+pub const INVALID_BLOCK_PENALTY: i64 = 20;
+/// Score deducted from a peer that relays a message we can't decode.
+pub const MALFORMED_MESSAGE_PENALTY: i64 = 5;
+
+/// Maximum number of peers whose clock samples are retained.
+pub const MAX_TIME_SAMPLES: usize = 200;
+/// Minimum number of clock samples before the network offset is applied.
+pub const MIN_TIME_SAMPLES: usize = 5;
+/// Largest absolute offset (seconds) that will be applied to the local clock.
+pub const MAX_TIME_ADJUSTMENT_SECS: i64 = 70 * 60;
+
+/// Handshake request sent to a newly connected peer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeRequest {
+    /// Sender's clock (UNIX seconds) when the request was sent.
+    pub timestamp: u64,
+    /// Sender's protocol version; zero from peers predating the field.
+    #[serde(default)]
+    pub protocol_version: u32,
+    /// Sender's chain height, if it has a chain.
+    #[serde(default)]
+    pub height: Option<u64>,
+}
+
+/// Handshake response describing how the responder sees the requester.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeResponse {
+    /// Address the responder sees the requester's connection coming from.
+    pub observed_addr: String,
+    /// Responder's clock (UNIX seconds) when the response was sent.
+    pub timestamp: u64,
+    /// The request timestamp, echoed back so the requester can correct for latency.
+    pub request_timestamp: u64,
+    /// Responder's protocol version; zero from peers predating the field.
+    #[serde(default)]
+    pub protocol_version: u32,
+    /// Responder's chain height, if it has a chain.
+    #[serde(default)]
+    pub height: Option<u64>,
+}
+
+/// Aggregates of observed addresses and clock samples, as exposed over RPC.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ObservationsSummary {
+    /// Number of currently connected peers.
+    pub connected_peers: usize,
+    /// Observed addresses with the number of distinct peers reporting each, most reported first.
+    pub observed_addresses: Vec<ObservedAddress>,
+    /// Number of peers contributing a clock sample.
+    pub time_samples: usize,
+    /// Median peer clock offset in seconds; zero until `MIN_TIME_SAMPLES` peers have reported.
+    pub time_offset_secs: i64,
+    /// Local clock adjusted by `time_offset_secs` (UNIX seconds).
+    pub network_adjusted_time: u64,
+}
+
+/// An observed external address and how many peers reported it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ObservedAddress {
+    pub address: String,
+    pub peers: usize,
+}
+
+/// Which side opened the connection to a peer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerDirection {
+    /// The peer dialed us.
+    Inbound,
+    /// We dialed the peer.
+    Outbound,
+}
+
+/// A connected peer, as exposed over RPC.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    /// Remote multiaddr of the connection, as seen by us.
+    pub address: String,
+    pub direction: PeerDirection,
+    /// When the connection was established (UNIX seconds).
+    pub connected_since: u64,
+    /// Protocol version announced in the handshake; `None` until it completes.
+    pub protocol_version: Option<u32>,
+    /// Chain height announced in the handshake.
+    pub height: Option<u64>,
+    /// Round trip of our handshake request, in milliseconds.
+    pub latency_ms: Option<u64>,
+    /// Zero for a well-behaved peer; decreases with each penalty.
+    pub score: i64,
+}
+
+/// State kept for a connected peer.
+#[derive(Debug, Clone)]
+struct ConnectedPeer {
+    address: String,
+    direction: PeerDirection,
+    connected_since: u64,
+    handshake_sent: Option<Instant>,
+    protocol_version: Option<u32>,
+    height: Option<u64>,
+    latency: Option<Duration>,
+    score: i64,
+    retrieval_terms: Option<RetrievalTerms>,
+}
+
+/// Handshake results collected from peers.
+#[derive(Debug, Default)]
+pub struct PeerObservations {
+    /// Each connected peer, with the remote address we see it at.
+    connected: HashMap<PeerId, ConnectedPeer>,
+    /// Latest address each peer reported for us.
+    observed_addrs: HashMap<PeerId, String>,
+    /// Latest clock offset (peer clock minus ours) reported by each peer.
+    clock_offsets: HashMap<PeerId, i64>,
+}
+
+impl PeerObservations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a new connection and the address we see the peer at.
+    /// Further connections to an already connected peer keep its handshake results and score.
+    pub fn peer_connected(&mut self, peer: PeerId, remote_addr: String, direction: PeerDirection) {
+        self.connected.entry(peer).or_insert_with(|| ConnectedPeer {
+            address: remote_addr,
+            direction,
+            connected_since: unix_now(),
+            handshake_sent: None,
+            protocol_version: None,
+            height: None,
+            latency: None,
+            score: 0,
+            retrieval_terms: None,
+        });
+    }
+
+    /// Forgets the peer's connection. Its samples are kept, as in Bitcoin's time data.
+    pub fn peer_disconnected(&mut self, peer: &PeerId) {
+        self.connected.remove(peer);
+    }
+
+    /// The address we see a connected peer at, to report back in its handshake.
+    pub fn remote_address(&self, peer: &PeerId) -> Option<&String> {
+        self.connected.get(peer).map(|connected| &connected.address)
+    }
+
+    /// Records that our handshake request to `peer` left at `sent_at`, to time its response.
+    pub fn handshake_sent(&mut self, peer: &PeerId, sent_at: Instant) {
+        if let Some(connected) = self.connected.get_mut(peer) {
+            connected.handshake_sent = Some(sent_at);
+        }
+    }
+
+    /// Records the protocol version and chain height a peer announced in its handshake request.
+    pub fn record_handshake_request(&mut self, peer: &PeerId, request: &HandshakeRequest) {
+        if let Some(connected) = self.connected.get_mut(peer) {
+            connected.protocol_version = Some(request.protocol_version);
+            connected.height = request.height;
+        }
+    }
+
+    /// Deducts `penalty` from a connected peer's score.
+    pub fn penalize(&mut self, peer: &PeerId, penalty: i64) {
+        if let Some(connected) = self.connected.get_mut(peer) {
+            connected.score = connected.score.saturating_sub(penalty);
+        }
+    }
+
+    /// Records the terms a connected peer quoted for serving payloads.
+    pub fn record_retrieval_terms(&mut self, peer: &PeerId, terms: RetrievalTerms) {
+        if let Some(connected) = self.connected.get_mut(peer) {
+            connected.retrieval_terms = Some(terms);
+        }
+    }
+
+    /// Connected peers that quoted retrieval terms, ordered by peer id.
+    pub fn retrieval_quotes(&self) -> Vec<(PeerId, RetrievalTerms)> {
+        let mut quotes: Vec<(PeerId, RetrievalTerms)> = self.connected.iter()
+            .filter_map(|(peer, connected)| Some((*peer, connected.retrieval_terms.clone()?)))
+            .collect();
+        quotes.sort_by_key(|(peer, _)| peer.to_string());
+        quotes
+    }
+
+    /// Records the address `peer` observed for us.
+    pub fn record_observed_address(&mut self, peer: PeerId, address: String) {
+        self.observed_addrs.insert(peer, address);
+    }
+
+    /// Records a clock sample: `offset_secs` is the peer's clock minus ours.
+    /// Only one sample per peer is kept, and new peers are ignored once `MAX_TIME_SAMPLES` is reached.
+    pub fn record_clock_offset(&mut self, peer: PeerId, offset_secs: i64) {
+        if self.clock_offsets.len() >= MAX_TIME_SAMPLES && !self.clock_offsets.contains_key(&peer) {
+            return;
+        }
+        self.clock_offsets.insert(peer, offset_secs);
+    }
+
+    /// Records a handshake response received at local time `received_at`.
+    pub fn record_handshake_response(&mut self, peer: PeerId, response: &HandshakeResponse, received_at: u64) {
+        self.record_observed_address(peer, response.observed_addr.clone());
+        // Compare the peer clock against the midpoint of our request/response round trip
+        let midpoint = (response.request_timestamp as i128 + received_at as i128) / 2;
+        let offset = (response.timestamp as i128 - midpoint).clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        self.record_clock_offset(peer, offset);
+        if let Some(connected) = self.connected.get_mut(&peer) {
+            connected.protocol_version = Some(response.protocol_version);
+            connected.height = response.height;
+            if let Some(sent_at) = connected.handshake_sent.take() {
+                connected.latency = Some(sent_at.elapsed());
+            }
+        }
+    }
+
+    /// Highest chain height announced by a connected peer, if any has announced one.
+    pub fn best_peer_height(&self) -> Option<u64> {
+        self.connected.values().filter_map(|connected| connected.height).max()
+    }
+
+    /// Connected peers, ordered by peer id.
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.connected.iter().map(|(peer, connected)| PeerInfo {
+            peer_id: peer.to_string(),
+            address: connected.address.clone(),
+            direction: connected.direction,
+            connected_since: connected.connected_since,
+            protocol_version: connected.protocol_version,
+            height: connected.height,
+            latency_ms: connected.latency.map(|latency| latency.as_millis() as u64),
+            score: connected.score,
+        }).collect();
+        peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        peers
+    }
+
+    /// Observed addresses ranked by the number of distinct peers reporting them.
+    pub fn external_address_candidates(&self) -> Vec<ObservedAddress> {
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        for address in self.observed_addrs.values() {
+            *counts.entry(address).or_insert(0) += 1;
+        }
+        let mut candidates: Vec<ObservedAddress> = counts
+            .into_iter()
+            .map(|(address, peers)| ObservedAddress { address: address.clone(), peers })
+            .collect();
+        candidates.sort_by(|a, b| b.peers.cmp(&a.peers).then_with(|| a.address.cmp(&b.address)));
+        candidates
+    }
+
+    /// Median clock offset to our peers, or zero if too few samples or the median exceeds
+    /// `MAX_TIME_ADJUSTMENT_SECS`.
+    pub fn time_offset_secs(&self) -> i64 {
+        if self.clock_offsets.len() < MIN_TIME_SAMPLES {
+            return 0;
+        }
+        let mut offsets: Vec<i64> = self.clock_offsets.values().copied().collect();
+        offsets.sort_unstable();
+        let mid = offsets.len() / 2;
+        let median = if offsets.len().is_multiple_of(2) {
+            ((offsets[mid - 1] as i128 + offsets[mid] as i128) / 2) as i64
+        } else {
+            offsets[mid]
+        };
+        if median.abs() > MAX_TIME_ADJUSTMENT_SECS {
+            warn!(
+                "Median peer clock offset {}s exceeds {}s; check the local clock. Not adjusting.",
+                median, MAX_TIME_ADJUSTMENT_SECS
+            );
+            return 0;
+        }
+        median
+    }
+
+    /// Local time corrected by the median peer clock offset (UNIX seconds).
+    pub fn network_adjusted_time(&self) -> u64 {
+        unix_now().saturating_add_signed(self.time_offset_secs())
+    }
+
+    /// Snapshot of the aggregates for reporting.
+    pub fn summary(&self) -> ObservationsSummary {
+        let time_offset_secs = self.time_offset_secs();
+        ObservationsSummary {
+            connected_peers: self.connected.len(),
+            observed_addresses: self.external_address_candidates(),
+            time_samples: self.clock_offsets.len(),
+            time_offset_secs,
+            network_adjusted_time: unix_now().saturating_add_signed(time_offset_secs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observed_address_ranking() {
+        let mut obs = PeerObservations::new();
+        let addr_a = "/ip4/203.0.113.7/tcp/4001".to_string();
+        let addr_b = "/ip4/198.51.100.2/tcp/4001".to_string();
+        for _ in 0..3 {
+            obs.record_observed_address(PeerId::random(), addr_a.clone());
+        }
+        obs.record_observed_address(PeerId::random(), addr_b.clone());
+
+        // A peer re-reporting only counts once
+        let repeat = PeerId::random();
+        obs.record_observed_address(repeat, addr_b.clone());
+        obs.record_observed_address(repeat, addr_b.clone());
+
+        let candidates = obs.external_address_candidates();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0], ObservedAddress { address: addr_a, peers: 3 });
+        assert_eq!(candidates[1], ObservedAddress { address: addr_b, peers: 2 });
+    }
+
+    #[test]
+    fn test_time_offset_median_and_limits() {
+        let mut obs = PeerObservations::new();
+        for offset in [10, 12, 11, 500] {
+            obs.record_clock_offset(PeerId::random(), offset);
+        }
+        // Too few samples: no adjustment
+        assert_eq!(obs.time_offset_secs(), 0);
+
+        obs.record_clock_offset(PeerId::random(), 13);
+        assert_eq!(obs.time_offset_secs(), 12);
+
+        // A median beyond the cap is ignored entirely
+        let mut far = PeerObservations::new();
+        for _ in 0..MIN_TIME_SAMPLES {
+            far.record_clock_offset(PeerId::random(), MAX_TIME_ADJUSTMENT_SECS + 1);
+        }
+        assert_eq!(far.time_offset_secs(), 0);
+    }
+
+    #[test]
+    fn test_handshake_response_uses_round_trip_midpoint() {
+        let mut obs = PeerObservations::new();
+        let peer = PeerId::random();
+        let response = HandshakeResponse {
+            observed_addr: "/ip4/203.0.113.7/tcp/50000".to_string(),
+            timestamp: 1_000_030,
+            request_timestamp: 1_000_000,
+            protocol_version: PROTOCOL_VERSION,
+            height: Some(3),
+        };
+        obs.record_handshake_response(peer, &response, 1_000_020);
+        assert_eq!(obs.clock_offsets[&peer], 20);
+        assert_eq!(obs.observed_addrs[&peer], response.observed_addr);
+    }
+
+    #[test]
+    fn test_connected_peer_info() {
+        let mut obs = PeerObservations::new();
+        let peer = PeerId::random();
+        obs.peer_connected(peer, "/ip4/198.51.100.2/tcp/4001".to_string(), PeerDirection::Outbound);
+        obs.handshake_sent(&peer, Instant::now() - Duration::from_millis(40));
+        let response = HandshakeResponse {
+            observed_addr: "/ip4/203.0.113.7/tcp/50000".to_string(),
+            timestamp: unix_now(),
+            request_timestamp: unix_now(),
+            protocol_version: PROTOCOL_VERSION,
+            height: Some(12),
+        };
+        obs.record_handshake_response(peer, &response, unix_now());
+        obs.penalize(&peer, INVALID_BLOCK_PENALTY);
+
+        let peers = obs.peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].address, "/ip4/198.51.100.2/tcp/4001");
+        assert_eq!(peers[0].direction, PeerDirection::Outbound);
+        assert_eq!((peers[0].protocol_version, peers[0].height), (Some(PROTOCOL_VERSION), Some(12)));
+        assert!(peers[0].latency_ms.is_some_and(|latency| latency >= 40));
+        assert_eq!(peers[0].score, -INVALID_BLOCK_PENALTY);
+        assert_eq!(obs.best_peer_height(), Some(12));
+
+        let terms = RetrievalTerms { price: 5, payee: vec![2u8; 21] };
+        obs.record_retrieval_terms(&PeerId::random(), terms.clone());
+        obs.record_retrieval_terms(&peer, terms.clone());
+        assert_eq!(obs.retrieval_quotes(), vec![(peer, terms)]);
+
+        obs.peer_disconnected(&peer);
+        assert!(obs.peers().is_empty());
+        assert!(obs.retrieval_quotes().is_empty());
+    }
+}
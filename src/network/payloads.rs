@@ -0,0 +1,147 @@
+//!
+//! # Payload Exchange
+//! Request-response messages for fetching off-chain payloads from peers.
+//!
+//! When a block from the network anchors payloads (`StoreData` transactions) that
+//! are not stored locally, the node asks the peer that sent the block for them.
+//! Responses are only stored if the data hashes to the requested payload hash, so
+//! a peer cannot substitute content. Nodes may charge for what they serve (see
+//! [`retrieval`](super::retrieval)) and only serve payloads under an ACL to the keys it lists
+//! (see [`acl`](super::acl)); requests are signed with the node key for that.
+
+use super::acl::{self, AclError, PayloadAcl, ReadAuthorization};
+use super::retrieval::{self, RetrievalTerms};
+use crate::core::{Block, Blockchain, Hash, TransactionType};
+use crate::offchain_storage::{OffChainStorageError, OffChainStorageManager};
+use ed25519_dalek::SigningKey;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+/// Request-response protocol for payload retrieval.
+pub const PAYLOAD_PROTOCOL: &str = "/blockchain-data-storage/payload/1.0.0";
+
+/// Asks a peer for the payload with `payload_hash`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadRequest {
+    pub payload_hash: Hash,
+    /// Hash of the transfer paying for the retrieval, for peers that charge.
+    #[serde(default)]
+    pub payment: Option<Hash>,
+    /// The requester's signature, for payloads under an ACL.
+    #[serde(default)]
+    pub authorization: Option<ReadAuthorization>,
+}
+
+impl PayloadRequest {
+    /// Asks for `payload_hash`, authorized at `now` by the node key `key`.
+    pub fn signed(payload_hash: Hash, payment: Option<Hash>, key: &SigningKey, now: u64) -> Self {
+        PayloadRequest { payload_hash, payment, authorization: Some(ReadAuthorization::new(&payload_hash, now, key)) }
+    }
+}
+
+/// A peer's answer: the payload, or `None` if it does not store it or wants to be paid.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadResponse {
+    pub payload_hash: Hash,
+    pub payload: Option<Vec<u8>>,
+    /// The peer's terms, if it holds the payload but the request did not pay for it.
+    #[serde(default)]
+    pub payment_required: Option<RetrievalTerms>,
+    /// The payload's ACL, sent with the payload so the requester enforces it too.
+    #[serde(default)]
+    pub acl: Option<PayloadAcl>,
+    /// Set when the payload's ACL does not list the requester.
+    #[serde(default)]
+    pub access_denied: bool,
+}
+
+impl PayloadResponse {
+    /// An answer for `payload_hash` without the payload.
+    pub fn empty(payload_hash: Hash) -> Self {
+        PayloadResponse { payload_hash, payload: None, payment_required: None, acl: None, access_denied: false }
+    }
+
+    /// Refuses `payload_hash` to a requester its ACL does not list.
+    pub fn denied(payload_hash: Hash) -> Self {
+        PayloadResponse { access_denied: true, ..Self::empty(payload_hash) }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PayloadFetchError {
+    #[error("Peer sent data hashing to {actual} for payload {expected}")]
+    HashMismatch { expected: String, actual: String },
+    #[error("Off-chain storage error: {0}")]
+    Storage(#[from] OffChainStorageError),
+}
+
+/// Answers a peer's payload request from local storage.
+pub fn serve_payload_request(storage: &OffChainStorageManager, request: &PayloadRequest) -> PayloadResponse {
+    PayloadResponse {
+        payload: storage.retrieve_payload(&request.payload_hash).ok(),
+        ..PayloadResponse::empty(request.payload_hash)
+    }
+}
+
+/// Answers a peer's payload request at `now` if the payload's ACL, if any, lists the requester,
+/// charging under `retrieval` if set. Served payloads carry their ACL.
+pub fn serve_authorized_request(
+    blockchain: &Blockchain,
+    storage: &OffChainStorageManager,
+    retrieval: Option<&RetrievalTerms>,
+    request: &PayloadRequest,
+    now: u64,
+) -> Result<PayloadResponse, AclError> {
+    // Checked before charging, so a refused requester's payment is not spent
+    let acl = match acl::check_access(blockchain, &request.payload_hash, request.authorization.as_ref(), now) {
+        Ok(acl) => acl,
+        Err(AclError::AccessDenied(_)) => return Ok(PayloadResponse::denied(request.payload_hash)),
+        Err(e) => return Err(e),
+    };
+    let response = match retrieval {
+        Some(terms) => retrieval::serve_paid_request(blockchain, storage, terms, request, now),
+        None => serve_payload_request(storage, request),
+    };
+    Ok(PayloadResponse { acl: acl.filter(|_| response.payload.is_some()), ..response })
+}
+
+/// Stores the payload in `response` if it matches the hash it answers for.
+/// Returns `Ok(false)` when the peer did not have the payload.
+pub fn accept_payload_response(storage: &OffChainStorageManager, response: PayloadResponse) -> Result<bool, PayloadFetchError> {
+    let Some(payload) = response.payload else {
+        return Ok(false);
+    };
+    let actual_hash: Hash = Sha256::digest(&payload).into();
+    if actual_hash != response.payload_hash {
+        return Err(PayloadFetchError::HashMismatch {
+            expected: hex::encode(response.payload_hash),
+            actual: hex::encode(actual_hash),
+        });
+    }
+    storage.store_payload(&payload)?;
+    Ok(true)
+}
+
+/// Payload hashes anchored by `block` that are not stored locally.
+pub fn missing_payloads(block: &Block, storage: &OffChainStorageManager) -> Vec<Hash> {
+    block.transactions.iter().filter_map(|tx| match tx.transaction_type() {
+        TransactionType::StoreData { data_hash, .. } if !storage.has_payload(data_hash) => Some(*data_hash),
+        _ => None,
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_payload_response_must_match_requested_hash() {
+        let dir = tempdir().unwrap();
+        let storage = OffChainStorageManager::new(dir.path()).unwrap();
+        let forged = PayloadResponse { payload: Some(b"not it".to_vec()), ..PayloadResponse::empty([1u8; 32]) };
+        assert!(matches!(accept_payload_response(&storage, forged), Err(PayloadFetchError::HashMismatch { .. })));
+        assert!(storage.list_payloads().unwrap().is_empty());
+        assert!(!accept_payload_response(&storage, PayloadResponse::empty([1u8; 32])).unwrap());
+    }
+}
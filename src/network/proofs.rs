@@ -0,0 +1,309 @@
+//!
+//! # Storage Proofs
+//! Gossiped retrievability challenges and the providers' responses.
+//!
+//! A challenger asks whoever stores a payload to prove it still holds the bytes by
+//! hashing them together with a fresh nonce. Challenges and proofs are broadcast on
+//! [`PROOFS_TOPIC`] and signed with the sender's node key, so any node that also
+//! stores the payload can recompute the expected digest, audit the answer and keep a
+//! reputation score per provider ([`ProofAuditor`]). Nodes without a copy can check
+//! the signatures but not the digest. The node challenges the providers of the payloads
+//! it keeps on a schedule (see [`audit`](super::audit)).
+
+use super::audit::{AuditLog, PayloadAudit, ReplicaCheck};
+use crate::core::Hash;
+use crate::offchain_storage::OffChainStorageManager;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::{HashMap, HashSet};
+
+/// Gossipsub topic for storage challenges and proofs.
+pub const PROOFS_TOPIC: &str = "storage-proofs";
+
+/// Challenges older than this are no longer answered or audited.
+pub const CHALLENGE_TTL_SECS: u64 = 600;
+
+// Domain separators, so signatures and digests of one kind can't be replayed as another
+const CHALLENGE_SIGNING_TAG: &[u8] = b"storage-challenge-v1";
+const PROOF_SIGNING_TAG: &[u8] = b"storage-proof-v1";
+const PROOF_DIGEST_TAG: &[u8] = b"storage-proof-digest-v1";
+
+fn tagged_digest(tag: &[u8], parts: &[&[u8]]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(tag);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn signature_matches(signer: &[u8; 32], digest: &Hash, signature: &[u8]) -> bool {
+    match (VerifyingKey::from_bytes(signer), Signature::from_slice(signature)) {
+        (Ok(key), Ok(signature)) => key.verify(digest, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+/// Digest a provider must return to prove it holds `payload` under `nonce`.
+pub fn proof_digest(nonce: &[u8; 32], payload: &[u8]) -> Hash {
+    tagged_digest(PROOF_DIGEST_TAG, &[nonce, payload])
+}
+
+/// Asks the storers of `payload_hash` to prove they still hold it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StorageChallenge {
+    pub payload_hash: Hash,
+    pub nonce: [u8; 32],
+    /// UNIX time the challenge was issued.
+    pub issued_at: u64,
+    /// Node key of the challenger.
+    pub challenger: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+impl StorageChallenge {
+    /// Creates a challenge signed by `key`.
+    pub fn new(payload_hash: Hash, nonce: [u8; 32], issued_at: u64, key: &SigningKey) -> Self {
+        let mut challenge = StorageChallenge {
+            payload_hash,
+            nonce,
+            issued_at,
+            challenger: key.verifying_key().to_bytes(),
+            signature: Vec::new(),
+        };
+        challenge.signature = key.sign(&challenge.id()).to_bytes().to_vec();
+        challenge
+    }
+
+    /// Identifier proofs refer to; also the digest the challenger signs.
+    pub fn id(&self) -> Hash {
+        tagged_digest(CHALLENGE_SIGNING_TAG, &[&self.payload_hash, &self.nonce, &self.issued_at.to_be_bytes(), &self.challenger])
+    }
+
+    /// Checks the challenger's signature.
+    pub fn verify(&self) -> bool {
+        signature_matches(&self.challenger, &self.id(), &self.signature)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now > self.issued_at.saturating_add(CHALLENGE_TTL_SECS)
+    }
+}
+
+/// A provider's answer to a [`StorageChallenge`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StorageProof {
+    pub challenge_id: Hash,
+    pub payload_hash: Hash,
+    /// `proof_digest(challenge.nonce, payload)`.
+    pub digest: Hash,
+    pub responded_at: u64,
+    /// Node key of the provider.
+    pub provider: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+impl StorageProof {
+    fn signing_digest(&self) -> Hash {
+        tagged_digest(PROOF_SIGNING_TAG, &[&self.challenge_id, &self.payload_hash, &self.digest, &self.responded_at.to_be_bytes(), &self.provider])
+    }
+
+    /// Checks the provider's signature.
+    pub fn verify(&self) -> bool {
+        signature_matches(&self.provider, &self.signing_digest(), &self.signature)
+    }
+}
+
+/// Answers `challenge` if the payload is stored locally.
+pub fn respond_to_challenge(
+    challenge: &StorageChallenge,
+    storage: &OffChainStorageManager,
+    key: &SigningKey,
+    now: u64,
+) -> Option<StorageProof> {
+    let payload = storage.retrieve_payload(&challenge.payload_hash).ok()?;
+    let mut proof = StorageProof {
+        challenge_id: challenge.id(),
+        payload_hash: challenge.payload_hash,
+        digest: proof_digest(&challenge.nonce, &payload),
+        responded_at: now,
+        provider: key.verifying_key().to_bytes(),
+        signature: Vec::new(),
+    };
+    proof.signature = key.sign(&proof.signing_digest()).to_bytes().to_vec();
+    Some(proof)
+}
+
+/// Outcome of auditing a proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofVerdict {
+    /// The digest matches the locally stored payload.
+    Valid,
+    /// The digest does not match: the provider could not produce the payload.
+    Invalid,
+    /// The challenge is unknown or the payload is not stored here, so the digest can't be checked.
+    Unverifiable,
+    /// Bad signature, mismatched or expired challenge, or a proof already audited. Not scored.
+    Rejected,
+}
+
+/// Audit results for one provider.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProviderScore {
+    pub valid: u64,
+    pub invalid: u64,
+}
+
+impl ProviderScore {
+    /// Share of valid proofs, smoothed so a provider with no history starts at 0.5.
+    pub fn reputation(&self) -> f64 {
+        (self.valid as f64 + 1.0) / ((self.valid + self.invalid) as f64 + 2.0)
+    }
+}
+
+/// A provider seen answering a challenge for a payload.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadHolder {
+    pub provider: [u8; 32],
+    /// UNIX time of its latest proof.
+    pub last_proof_at: u64,
+    /// Whether that proof was checked against a local copy; otherwise only its signature was.
+    pub verified: bool,
+}
+
+/// Tracks live challenges seen on the network and scores providers by the proofs answering them.
+/// Verdicts on proofs answering the node's own scheduled audits are added to its [`AuditLog`].
+#[derive(Debug, Default)]
+pub struct ProofAuditor {
+    challenges: HashMap<Hash, StorageChallenge>,
+    audited: HashSet<(Hash, [u8; 32])>,
+    scores: HashMap<[u8; 32], ProviderScore>,
+    holders: HashMap<Hash, HashMap<[u8; 32], PayloadHolder>>,
+    audit_log: AuditLog,
+}
+
+impl ProofAuditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a challenge so proofs answering it can be audited.
+    /// Returns `false` for challenges with a bad signature or already expired.
+    pub fn observe_challenge(&mut self, challenge: StorageChallenge, now: u64) -> bool {
+        self.challenges.retain(|_, known| !known.is_expired(now));
+        let challenges = &self.challenges;
+        self.audited.retain(|(challenge_id, _)| challenges.contains_key(challenge_id));
+        if !challenge.verify() || challenge.is_expired(now) {
+            return false;
+        }
+        self.challenges.insert(challenge.id(), challenge);
+        true
+    }
+
+    /// Audits `proof` against its challenge and the local copy of the payload,
+    /// updating the provider's score for `Valid` and `Invalid` verdicts.
+    pub fn observe_proof(&mut self, proof: &StorageProof, storage: &OffChainStorageManager, now: u64) -> ProofVerdict {
+        let Some(challenge) = self.challenges.get(&proof.challenge_id) else {
+            return ProofVerdict::Unverifiable;
+        };
+        if !proof.verify() || challenge.payload_hash != proof.payload_hash || challenge.is_expired(now) {
+            return ProofVerdict::Rejected;
+        }
+        let Ok(payload) = storage.retrieve_payload(&proof.payload_hash) else {
+            // The provider still claims the payload under a signed answer to a live challenge
+            self.record_holder(proof, false);
+            return ProofVerdict::Unverifiable;
+        };
+        if !self.audited.insert((proof.challenge_id, proof.provider)) {
+            return ProofVerdict::Rejected;
+        }
+        let score = self.scores.entry(proof.provider).or_default();
+        let valid = proof_digest(&challenge.nonce, &payload) == proof.digest;
+        self.audit_log.record_replica(&proof.challenge_id, ReplicaCheck { provider: proof.provider, valid, responded_at: proof.responded_at });
+        if valid {
+            score.valid += 1;
+            self.record_holder(proof, true);
+            ProofVerdict::Valid
+        } else {
+            score.invalid += 1;
+            if let Some(holders) = self.holders.get_mut(&proof.payload_hash) {
+                holders.remove(&proof.provider);
+            }
+            ProofVerdict::Invalid
+        }
+    }
+
+    /// Records a round of the node's own audits run at `now`, tracking their challenges.
+    pub fn record_audits(&mut self, audits: Vec<(PayloadAudit, StorageChallenge)>, now: u64) {
+        let mut records = Vec::with_capacity(audits.len());
+        for (audit, challenge) in audits {
+            self.observe_challenge(challenge, now);
+            records.push(audit);
+        }
+        self.audit_log.record_round(records, now);
+    }
+
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit_log
+    }
+
+    fn record_holder(&mut self, proof: &StorageProof, verified: bool) {
+        let holder = PayloadHolder { provider: proof.provider, last_proof_at: proof.responded_at, verified };
+        self.holders.entry(proof.payload_hash).or_default().insert(proof.provider, holder);
+    }
+
+    /// Providers whose latest proof for `payload_hash` was not found invalid, sorted by provider key.
+    pub fn holders(&self, payload_hash: &Hash) -> Vec<PayloadHolder> {
+        let mut holders: Vec<_> = self.holders.get(payload_hash).map_or_else(Vec::new, |holders| holders.values().copied().collect());
+        holders.sort_by_key(|holder| holder.provider);
+        holders
+    }
+
+    pub fn score(&self, provider: &[u8; 32]) -> Option<ProviderScore> {
+        self.scores.get(provider).copied()
+    }
+
+    pub fn scores(&self) -> &HashMap<[u8; 32], ProviderScore> {
+        &self.scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_third_party_audits_storage_proofs() {
+        let dir = tempdir().unwrap();
+        let provider_storage = OffChainStorageManager::new(&dir.path().join("provider")).unwrap();
+        let verifier_storage = OffChainStorageManager::new(&dir.path().join("verifier")).unwrap();
+        let payload_hash = provider_storage.store_payload(b"audited payload").unwrap();
+        verifier_storage.store_payload(b"audited payload").unwrap();
+        let challenger_key = SigningKey::from_bytes(&[1u8; 32]);
+        let provider_key = SigningKey::from_bytes(&[2u8; 32]);
+
+        let challenge = StorageChallenge::new(payload_hash, [7u8; 32], 1_000, &challenger_key);
+        let proof = respond_to_challenge(&challenge, &provider_storage, &provider_key, 1_010).unwrap();
+        let mut auditor = ProofAuditor::new();
+        assert_eq!(auditor.observe_proof(&proof, &verifier_storage, 1_020), ProofVerdict::Unverifiable);
+        assert!(auditor.observe_challenge(challenge.clone(), 1_005));
+        assert_eq!(auditor.observe_proof(&proof, &verifier_storage, 1_020), ProofVerdict::Valid);
+        assert_eq!(auditor.observe_proof(&proof, &verifier_storage, 1_020), ProofVerdict::Rejected);
+
+        // A provider that lost the data can only guess the digest
+        let liar_key = SigningKey::from_bytes(&[3u8; 32]);
+        let mut forged = StorageProof { provider: liar_key.verifying_key().to_bytes(), digest: [0u8; 32], ..proof.clone() };
+        assert_eq!(auditor.observe_proof(&forged, &verifier_storage, 1_020), ProofVerdict::Rejected);
+        forged.signature = liar_key.sign(&forged.signing_digest()).to_bytes().to_vec();
+        assert_eq!(auditor.observe_proof(&forged, &verifier_storage, 1_020), ProofVerdict::Invalid);
+
+        assert_eq!(auditor.score(&proof.provider), Some(ProviderScore { valid: 1, invalid: 0 }));
+        assert!(auditor.score(&forged.provider).unwrap().reputation() < 0.5);
+        let holders = auditor.holders(&payload_hash);
+        assert_eq!(holders.len(), 1);
+        assert!(holders[0].provider == proof.provider && holders[0].verified);
+        assert!(!auditor.observe_challenge(challenge, 1_000 + CHALLENGE_TTL_SECS + 1));
+    }
+}
@@ -0,0 +1,235 @@
+//!
+//! # Redundancy Policies
+//! How many copies of a payload its client wants the network to keep, and the reconciliation
+//! that keeps actual placement in line with it.
+//!
+//! A policy is given when the payload is stored (see `send_transaction`) and kept in the
+//! node's state, by payload, and in its payload manifest. It asks either for a number of full
+//! replicas or for an erasure-coding profile of data and parity shards. Payloads are
+//! replicated whole, so a profile is met by the copies giving the loss tolerance it would
+//! give: one more than its parity shards.
+//!
+//! The copies counted are the local one, if intact, and those of remote providers whose
+//! latest proof was checked against it within `HOLDER_FRESHNESS_SECS`. Each reconciliation
+//! round challenges the providers of every payload with a policy, so their proofs keep the
+//! count current, restores missing local copies from peers and gossips a
+//! [`ReplicationRequest`] for the under-replicated ones. Peers that lack one of the requested
+//! payloads and see it anchored fetch it from the requester, as they would have on receiving
+//! its block. Payloads with more copies than asked for are only reported.
+
+use super::audit::{self, LocalCheck};
+use super::proofs::{PayloadHolder, ProofAuditor, StorageChallenge};
+use crate::core::{Blockchain, BlockchainError, Hash};
+use crate::offchain_storage::OffChainStorageManager;
+use crate::storage::StorageError;
+use ed25519_dalek::SigningKey;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+
+/// Seconds between reconciliation rounds by default.
+pub const DEFAULT_REPLICATION_INTERVAL_SECS: u64 = 5 * 60;
+/// Remote copies are counted while their latest verified proof is at most this old.
+pub const HOLDER_FRESHNESS_SECS: u64 = 30 * 60;
+/// Most copies or shards a policy can ask for.
+pub const MAX_POLICY_COPIES: u32 = 64;
+/// Most payloads one replication request names; receivers ignore the rest.
+pub const MAX_REPLICATION_REQUEST_PAYLOADS: usize = 256;
+
+/// Copies of a payload to keep; see the module documentation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedundancyPolicy {
+    /// Full copies, this node's included.
+    Replicas(u32),
+    /// Erasure-coding profile of `data_shards` shards plus `parity_shards` recovery shards.
+    ErasureCoded { data_shards: u32, parity_shards: u32 },
+}
+
+impl RedundancyPolicy {
+    /// Checks the policy asks for 1 to `MAX_POLICY_COPIES` replicas, or at least one data shard
+    /// and at most `MAX_POLICY_COPIES` shards in all.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            RedundancyPolicy::Replicas(count) if !(1..=MAX_POLICY_COPIES).contains(&count) => {
+                Err(format!("Replica count {} is outside 1..={}", count, MAX_POLICY_COPIES))
+            }
+            RedundancyPolicy::ErasureCoded { data_shards, parity_shards }
+                if data_shards == 0 || data_shards.saturating_add(parity_shards) > MAX_POLICY_COPIES =>
+            {
+                Err(format!("Erasure-coding profile needs 1 or more data shards and at most {} shards", MAX_POLICY_COPIES))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Full copies that meet the policy.
+    pub fn required_copies(&self) -> u32 {
+        match *self {
+            RedundancyPolicy::Replicas(count) => count,
+            RedundancyPolicy::ErasureCoded { parity_shards, .. } => parity_shards + 1,
+        }
+    }
+}
+
+/// How actual placement compares with a policy.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlacementStatus {
+    Satisfied,
+    UnderReplicated,
+    OverReplicated,
+}
+
+/// The copies of a payload known to exist against what its policy asks for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub copies: u32,
+    pub required: u32,
+    pub status: PlacementStatus,
+}
+
+/// Counts the copies of a payload from the state of the local copy and its remote `holders`
+/// (see the module documentation) and compares them with `policy`.
+pub fn assess_placement(policy: &RedundancyPolicy, local: LocalCheck, holders: &[PayloadHolder], now: u64) -> Placement {
+    let fresh = holders.iter()
+        .filter(|holder| holder.verified && holder.last_proof_at.saturating_add(HOLDER_FRESHNESS_SECS) >= now)
+        .count() as u32;
+    let copies = u32::from(local == LocalCheck::Intact) + fresh;
+    let required = policy.required_copies();
+    let status = match copies.cmp(&required) {
+        std::cmp::Ordering::Less => PlacementStatus::UnderReplicated,
+        std::cmp::Ordering::Equal => PlacementStatus::Satisfied,
+        std::cmp::Ordering::Greater => PlacementStatus::OverReplicated,
+    };
+    Placement { copies, required, status }
+}
+
+/// Asks peers to store payloads that have fewer copies than their policies ask for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationRequest {
+    pub payload_hashes: Vec<Hash>,
+}
+
+/// What one reconciliation round found and what it asks of the network.
+#[derive(Debug, Default)]
+pub struct Reconciliation {
+    /// Placement of every payload with a policy, by payload hash.
+    pub placements: Vec<(Hash, Placement)>,
+    /// Challenges refreshing the count of remote copies, to be gossiped.
+    pub challenges: Vec<StorageChallenge>,
+    /// Payloads whose local copy is missing, to be fetched from peers.
+    pub repairs: Vec<Hash>,
+    /// Request for the under-replicated payloads, if any.
+    pub request: Option<ReplicationRequest>,
+}
+
+/// Runs a reconciliation round at `now` over every payload with a policy in the state of
+/// `blockchain`, counting remote copies from the providers `auditor` has seen.
+pub fn reconcile(
+    blockchain: &Blockchain,
+    storage: &OffChainStorageManager,
+    auditor: &ProofAuditor,
+    key: &SigningKey,
+    now: u64,
+) -> Result<Reconciliation, StorageError> {
+    let mut round = Reconciliation::default();
+    let mut under_replicated = Vec::new();
+    for (payload_hash, policy) in blockchain.storage().get_redundancy_policies()? {
+        let local = audit::check_local_copy(storage, &payload_hash);
+        let placement = assess_placement(&policy, local, &auditor.holders(&payload_hash), now);
+        if !storage.has_payload(&payload_hash) {
+            round.repairs.push(payload_hash);
+        }
+        if placement.status == PlacementStatus::UnderReplicated {
+            under_replicated.push(payload_hash);
+        }
+        let mut nonce = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        round.challenges.push(StorageChallenge::new(payload_hash, nonce, now, key));
+        round.placements.push((payload_hash, placement));
+    }
+    if !under_replicated.is_empty() {
+        round.request = Some(ReplicationRequest { payload_hashes: under_replicated });
+    }
+    Ok(round)
+}
+
+/// The payloads of a peer's `request` this node should fetch from it: those it lacks that are
+/// anchored on the main chain or waiting in the mempool.
+pub fn payloads_to_replicate(
+    blockchain: &Blockchain,
+    storage: &OffChainStorageManager,
+    request: &ReplicationRequest,
+) -> Result<Vec<Hash>, BlockchainError> {
+    let missing: Vec<Hash> = request.payload_hashes.iter().take(MAX_REPLICATION_REQUEST_PAYLOADS)
+        .filter(|payload_hash| !storage.has_payload(payload_hash))
+        .copied()
+        .collect();
+    if missing.is_empty() {
+        return Ok(missing);
+    }
+    let anchors = blockchain.payload_anchors()?;
+    let pending = blockchain.pending_payloads();
+    Ok(missing.into_iter().filter(|payload_hash| anchors.contains_key(payload_hash) || pending.contains(payload_hash)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Transaction;
+    use crate::network::proofs::{respond_to_challenge, ProofVerdict};
+    use crate::presets::NetworkPreset;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_placement_is_reconciled_against_policies() {
+        assert_eq!(RedundancyPolicy::ErasureCoded { data_shards: 4, parity_shards: 2 }.required_copies(), 3);
+        assert!(RedundancyPolicy::Replicas(0).validate().is_err());
+        assert!(RedundancyPolicy::ErasureCoded { data_shards: 0, parity_shards: 2 }.validate().is_err());
+        assert!(RedundancyPolicy::ErasureCoded { data_shards: 60, parity_shards: 5 }.validate().is_err());
+        let json = serde_json::to_value(RedundancyPolicy::Replicas(3)).unwrap();
+        assert_eq!(json, serde_json::json!({ "replicas": 3 }));
+
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let storage = OffChainStorageManager::new(&dir.path().join("local")).unwrap();
+        let payload = b"kept twice".to_vec();
+        let payload_hash = storage.store_payload(&payload).unwrap();
+        let lost_hash = [7u8; 32];
+        blockchain.storage().put_redundancy_policy(&payload_hash, &RedundancyPolicy::Replicas(2)).unwrap();
+        blockchain.storage().put_redundancy_policy(&lost_hash, &RedundancyPolicy::Replicas(1)).unwrap();
+
+        // Only the local copy exists: both payloads are short of copies, the lost one is repaired
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let mut auditor = ProofAuditor::new();
+        let round = reconcile(&blockchain, &storage, &auditor, &key, 1_000).unwrap();
+        assert_eq!(round.challenges.len(), 2);
+        assert_eq!(round.repairs, vec![lost_hash]);
+        let mut under_replicated = vec![payload_hash, lost_hash];
+        under_replicated.sort();
+        assert_eq!(round.request, Some(ReplicationRequest { payload_hashes: under_replicated }));
+
+        // A replica proving its copy meets the policy until its proof goes stale
+        let challenge = round.challenges.iter().find(|challenge| challenge.payload_hash == payload_hash).unwrap();
+        auditor.observe_challenge(challenge.clone(), 1_000);
+        let replica_storage = OffChainStorageManager::new(&dir.path().join("replica")).unwrap();
+        replica_storage.store_payload(&payload).unwrap();
+        let proof = respond_to_challenge(challenge, &replica_storage, &SigningKey::from_bytes(&[2u8; 32]), 1_010).unwrap();
+        assert_eq!(auditor.observe_proof(&proof, &storage, 1_010), ProofVerdict::Valid);
+        let round = reconcile(&blockchain, &storage, &auditor, &key, 1_020).unwrap();
+        let placement = round.placements.iter().find(|(hash, _)| *hash == payload_hash).unwrap().1;
+        assert_eq!(placement, Placement { copies: 2, required: 2, status: PlacementStatus::Satisfied });
+        assert_eq!(round.request, Some(ReplicationRequest { payload_hashes: vec![lost_hash] }));
+        let holders = auditor.holders(&payload_hash);
+        assert_eq!(assess_placement(&RedundancyPolicy::Replicas(1), LocalCheck::Intact, &holders, 1_020).status, PlacementStatus::OverReplicated);
+        assert_eq!(assess_placement(&RedundancyPolicy::Replicas(2), LocalCheck::Intact, &holders, 1_010 + HOLDER_FRESHNESS_SECS + 1).copies, 1);
+
+        // Peers only replicate what they lack and the chain anchors
+        assert!(payloads_to_replicate(&blockchain, &replica_storage, &ReplicationRequest { payload_hashes: vec![payload_hash, lost_hash] }).unwrap().is_empty());
+        let empty_storage = OffChainStorageManager::new(&dir.path().join("empty")).unwrap();
+        assert!(blockchain.add_pending_transaction(Transaction::new_store_data(vec![1u8; 21], payload_hash, payload.len() as u64)).unwrap());
+        let request = ReplicationRequest { payload_hashes: vec![payload_hash, lost_hash] };
+        assert_eq!(payloads_to_replicate(&blockchain, &empty_storage, &request).unwrap(), vec![payload_hash]);
+    }
+}
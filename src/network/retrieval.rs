@@ -0,0 +1,160 @@
+//!
+//! # Retrieval Payments
+//! Lets a node charge peers for the payloads it serves.
+//!
+//! A node with retrieval terms (a price per retrieval and the address to pay) answers a
+//! payload request that names no payment with its terms instead of the payload. The client
+//! pays with an ordinary native transfer to the payee and repeats the request naming that
+//! transfer. The payload is served once the transfer is found paying at least the price, in
+//! the mempool or on the main chain: through the transaction index when it is enabled,
+//! otherwise among the last `PAYMENT_LOOKBACK_BLOCKS` blocks. Each transfer pays for one
+//! retrieval. A transfer accepted from the mempool may still fail to confirm; prices are meant
+//! to be small enough for a provider to take that risk.
+//!
+//! Paid retrievals are accounted per payload in the node's state (see
+//! [`StorageManager::record_retrieval_payment`](crate::storage::StorageManager::record_retrieval_payment)),
+//! so a provider can see what each payload earns.
+
+use super::payloads::{PayloadRequest, PayloadResponse};
+use crate::core::{Address, Blockchain, BlockchainError, Hash, Transaction, TransactionType};
+use crate::offchain_storage::OffChainStorageManager;
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+
+/// Blocks below the tip searched for a payment when the transaction index is disabled.
+pub const PAYMENT_LOOKBACK_BLOCKS: u64 = 100;
+
+/// What a node charges for serving one payload.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RetrievalTerms {
+    pub price: u64,
+    /// Address the payment transfer must go to.
+    pub payee: Address,
+}
+
+/// What one payload has earned this node.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetrievalEarnings {
+    /// Paid retrievals served.
+    pub retrievals: u64,
+    /// Sum of the payments, in base units.
+    pub earned: u64,
+    /// When the last payment was accepted (UNIX seconds).
+    pub last_paid_at: u64,
+}
+
+/// The amount `tx` pays under `terms`: a native transfer to the payee of at least the price.
+pub fn payment_amount(tx: &Transaction, terms: &RetrievalTerms) -> Option<u64> {
+    match tx.transaction_type() {
+        TransactionType::TransferNative { amount } if tx.receiver() == Some(&terms.payee) && *amount >= terms.price => Some(*amount),
+        _ => None,
+    }
+}
+
+/// Finds the payment `tx_hash` in the mempool or on the main chain (see the module documentation).
+pub fn find_payment(blockchain: &Blockchain, tx_hash: &Hash) -> Result<Option<Transaction>, BlockchainError> {
+    if let Some(entry) = blockchain.get_mempool_entry(tx_hash) {
+        return Ok(Some(entry.transaction));
+    }
+    if blockchain.storage().tx_index_enabled() {
+        return Ok(blockchain.get_transaction(tx_hash)?.map(|confirmed| confirmed.transaction));
+    }
+    let Some(tip_height) = blockchain.get_chain_height() else { return Ok(None) };
+    for height in (tip_height.saturating_sub(PAYMENT_LOOKBACK_BLOCKS - 1)..=tip_height).rev() {
+        let Some(block) = blockchain.get_block_by_height(height)? else { continue };
+        if let Some(tx) = block.transactions.into_iter().find(|tx| &tx.calculate_hash() == tx_hash) {
+            return Ok(Some(tx));
+        }
+    }
+    Ok(None)
+}
+
+/// Answers a payload request under `terms`: the payload if the request names an unused payment
+/// meeting them, recording it, otherwise the terms. Payloads not stored locally are answered
+/// with `None` either way.
+pub fn serve_paid_request(
+    blockchain: &Blockchain,
+    storage: &OffChainStorageManager,
+    terms: &RetrievalTerms,
+    request: &PayloadRequest,
+    now: u64,
+) -> PayloadResponse {
+    let unpaid = PayloadResponse { payment_required: Some(terms.clone()), ..PayloadResponse::empty(request.payload_hash) };
+    if !storage.has_payload(&request.payload_hash) {
+        return PayloadResponse { payment_required: None, ..unpaid };
+    }
+    let Some(payment) = request.payment else { return unpaid };
+    let amount = match find_payment(blockchain, &payment) {
+        Ok(Some(tx)) => payment_amount(&tx, terms),
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Failed to look up retrieval payment {}: {}", hex::encode(payment), e);
+            None
+        }
+    };
+    let Some(amount) = amount else {
+        debug!("Retrieval payment {} not found or below the price", hex::encode(payment));
+        return unpaid;
+    };
+    let Ok(payload) = storage.retrieve_payload(&request.payload_hash) else {
+        return PayloadResponse { payment_required: None, ..unpaid };
+    };
+    match blockchain.storage().record_retrieval_payment(&request.payload_hash, &payment, amount, now) {
+        Ok(true) => PayloadResponse { payload: Some(payload), payment_required: None, ..unpaid },
+        Ok(false) => {
+            debug!("Retrieval payment {} was already used", hex::encode(payment));
+            unpaid
+        }
+        Err(e) => {
+            warn!("Failed to record retrieval payment {}: {}", hex::encode(payment), e);
+            unpaid
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::address::{address_from_public_key, KeyAlgorithm};
+    use crate::core::SignedTransaction;
+    use crate::presets::NetworkPreset;
+    use ed25519_dalek::SigningKey;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_payloads_are_served_once_per_payment() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new_with_config(dir.path(), NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let storage = OffChainStorageManager::new(dir.path()).unwrap();
+        let payload_hash = storage.store_payload(b"paid content").unwrap();
+        let client_key = SigningKey::from_bytes(&[1u8; 32]);
+        let client = address_from_public_key(KeyAlgorithm::Ed25519, &client_key.verifying_key().to_bytes()).unwrap();
+        let payee = vec![2u8; 21];
+        let terms = RetrievalTerms { price: 10, payee: payee.clone() };
+
+        // Without a payment the terms are returned; payloads not held are never charged for
+        let request = PayloadRequest { payload_hash, payment: None, authorization: None };
+        assert_eq!(serve_paid_request(&blockchain, &storage, &terms, &request, 100).payment_required, Some(terms.clone()));
+        let missing = serve_paid_request(&blockchain, &storage, &terms, &PayloadRequest { payload_hash: [9u8; 32], payment: None, authorization: None }, 100);
+        assert_eq!((missing.payload, missing.payment_required), (None, None));
+
+        // A confirmed transfer meeting the price pays for one retrieval
+        let underpaid = Transaction::new_transfer_native(client.clone(), payee.clone(), 9).with_nonce(0);
+        let paid = Transaction::new_transfer_native(client.clone(), payee.clone(), 12).with_nonce(1);
+        for tx in [&underpaid, &paid] {
+            assert!(blockchain.add_signed_transaction(SignedTransaction::sign_ed25519(tx.clone(), &client_key)).unwrap());
+        }
+        let block = blockchain.mine_new_block().unwrap();
+        blockchain.process_mined_block(block).unwrap();
+        let request = PayloadRequest { payload_hash, payment: Some(underpaid.calculate_hash()), authorization: None };
+        assert!(serve_paid_request(&blockchain, &storage, &terms, &request, 100).payload.is_none());
+        let request = PayloadRequest { payload_hash, payment: Some(paid.calculate_hash()), authorization: None };
+        let response = serve_paid_request(&blockchain, &storage, &terms, &request, 100);
+        assert_eq!((response.payload.as_deref(), response.payment_required), (Some(&b"paid content"[..]), None));
+        assert!(serve_paid_request(&blockchain, &storage, &terms, &request, 200).payload.is_none());
+
+        let earnings = blockchain.storage().get_retrieval_earnings().unwrap();
+        assert_eq!(earnings, vec![(payload_hash, RetrievalEarnings { retrievals: 1, earned: 12, last_paid_at: 100 })]);
+    }
+}
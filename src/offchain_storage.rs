@@ -1,8 +1,12 @@
 // src/offchain_storage.rs
 
+use std::collections::HashMap;
+use crate::core::Blockchain;
 use std::fs::{self, File};
 use std::io::{Write, Read};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use sha2::{Sha256, Digest};
 use hex;
 use log::{info, error, debug, warn};
@@ -18,12 +22,50 @@ pub enum OffChainStorageError {
     NotFound(String),
     #[error("Failed to create storage directory: {0}")]
     DirectoryCreationFailed(String),
+    #[error("Failed to read chain state: {0}")]
+    Chain(String),
+}
+
+/// Prefix of in-progress payload files; they are renamed to the payload hash once complete.
+const TEMP_FILE_PREFIX: &str = ".tmp-";
+
+/// When stored payloads may be garbage collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Anchored payloads expire this many blocks after the block that anchored them; `None` keeps them forever.
+    pub retention_blocks: Option<u64>,
+    /// Payloads no confirmed or pending transaction references are deleted once their file is this old (seconds).
+    pub orphan_grace_secs: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy { retention_blocks: None, orphan_grace_secs: 24 * 60 * 60 }
+    }
+}
+
+/// Payloads removed by one garbage collection pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Anchored payloads past their retention period.
+    pub expired: Vec<[u8; 32]>,
+    /// Unreferenced payloads past the grace period.
+    pub orphaned: Vec<[u8; 32]>,
+    pub bytes_freed: u64,
 }
 
 /// Manages the storage and retrieval of large data payloads off-chain.
+///
+/// Safe to share between threads: writers of the same payload are serialized by a
+/// per-hash lock, and payloads are written to a temporary file and atomically renamed
+/// into place, so readers never observe a partially written payload.
 #[derive(Debug)]
 pub struct OffChainStorageManager {
     storage_path: PathBuf,
+    /// Write lock per payload hash currently being stored.
+    write_locks: Mutex<HashMap<[u8; 32], Arc<Mutex<()>>>>,
+    /// Distinguishes temporary files of concurrent writes.
+    temp_counter: AtomicU64,
 }
 
 impl OffChainStorageManager {
@@ -47,7 +89,40 @@ impl OffChainStorageManager {
             })?;
         }
 
-        Ok(OffChainStorageManager { storage_path })
+        // Leftovers from writes interrupted by a crash are never valid payloads
+        for entry in fs::read_dir(&storage_path)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(TEMP_FILE_PREFIX) {
+                warn!("Removing incomplete off-chain payload file {:?}", entry.path());
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(OffChainStorageManager {
+            storage_path,
+            write_locks: Mutex::new(HashMap::new()),
+            temp_counter: AtomicU64::new(0),
+        })
+    }
+
+    /// Directory holding the payload files, one per payload named by its hex hash.
+    pub fn storage_path(&self) -> &Path {
+        &self.storage_path
+    }
+
+    /// Returns the write lock for `hash`, creating it if no writer holds one.
+    fn write_lock(&self, hash: &[u8; 32]) -> Arc<Mutex<()>> {
+        let mut locks = self.write_locks.lock().expect("Write lock table poisoned");
+        locks.entry(*hash).or_default().clone()
+    }
+
+    /// Drops the lock entry for `hash` once no other writer is waiting on it.
+    fn release_write_lock(&self, hash: &[u8; 32], lock: Arc<Mutex<()>>) {
+        let mut locks = self.write_locks.lock().expect("Write lock table poisoned");
+        drop(lock);
+        if locks.get(hash).is_some_and(|entry| Arc::strong_count(entry) == 1) {
+            locks.remove(hash);
+        }
     }
 
     /// Stores a data payload off-chain.
@@ -55,7 +130,8 @@ impl OffChainStorageManager {
     /// Calculates the SHA-256 hash of the payload, uses the hex representation
     /// of the hash as the filename, and saves the payload to the storage directory.
     /// If a file with the same hash already exists, it's assumed the content is identical
-    /// and the operation succeeds without rewriting.
+    /// and the operation succeeds without rewriting. Concurrent stores of the same payload
+    /// write it once; the file appears atomically when complete.
     ///
     /// # Arguments
     ///
@@ -77,18 +153,37 @@ impl OffChainStorageManager {
         let file_path = self.storage_path.join(&hash_hex);
         debug!("Storing payload with hash {} at {:?}", hash_hex, file_path);
 
+        let lock = self.write_lock(&hash_array);
+        let result = {
+            let _guard = lock.lock().expect("Payload write lock poisoned");
+            self.write_payload_file(payload, &hash_hex, &file_path)
+        };
+        self.release_write_lock(&hash_array, lock);
+        result.map(|_| hash_array)
+    }
+
+    /// Writes `payload` to `file_path` through a temporary file. Callers hold the hash's write lock.
+    fn write_payload_file(&self, payload: &[u8], hash_hex: &str, file_path: &Path) -> Result<(), OffChainStorageError> {
         // 3. Check if file already exists (optimisation)
         if file_path.exists() {
             info!("Payload with hash {} already exists. Skipping write.", hash_hex);
-            return Ok(hash_array);
+            return Ok(());
         }
 
-        // 4. Write payload to file
-        let mut file = File::create(&file_path)?;
-        file.write_all(payload)?;
+        // 4. Write payload to a temporary file, then move it into place
+        let temp_name = format!("{}{}-{}", TEMP_FILE_PREFIX, hash_hex, self.temp_counter.fetch_add(1, Ordering::Relaxed));
+        let temp_path = self.storage_path.join(temp_name);
+        let written = File::create(&temp_path).and_then(|mut file| {
+            file.write_all(payload)?;
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| fs::rename(&temp_path, file_path)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
         debug!("Successfully wrote {} bytes to {:?}", payload.len(), file_path);
 
-        Ok(hash_array)
+        Ok(())
     }
 
     /// Retrieves a data payload from off-chain storage using its hash.
@@ -129,6 +224,109 @@ impl OffChainStorageManager {
 
         Ok(buffer)
     }
+
+    /// Opens a stored payload for reading in pieces, returning the file and its length in bytes.
+    /// The file stays readable even if the payload is removed while it is open.
+    pub fn open_payload(&self, payload_hash: &[u8; 32]) -> Result<(File, u64), OffChainStorageError> {
+        let hash_hex = hex::encode(payload_hash);
+        let file = match File::open(self.storage_path.join(&hash_hex)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(OffChainStorageError::NotFound(hash_hex)),
+            Err(e) => return Err(e.into()),
+        };
+        let length = file.metadata()?.len();
+        Ok((file, length))
+    }
+
+    /// Whether a payload with `payload_hash` is stored.
+    pub fn has_payload(&self, payload_hash: &[u8; 32]) -> bool {
+        self.storage_path.join(hex::encode(payload_hash)).exists()
+    }
+
+    /// Deletes a stored payload. Returns the number of bytes freed (0 if it was not stored).
+    pub fn remove_payload(&self, payload_hash: &[u8; 32]) -> Result<u64, OffChainStorageError> {
+        let file_path = self.storage_path.join(hex::encode(payload_hash));
+        let lock = self.write_lock(payload_hash);
+        let result = {
+            let _guard = lock.lock().expect("Payload write lock poisoned");
+            match fs::metadata(&file_path) {
+                Ok(metadata) => fs::remove_file(&file_path).map(|_| metadata.len()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+                Err(e) => Err(e),
+            }
+        };
+        self.release_write_lock(payload_hash, lock);
+        Ok(result?)
+    }
+
+    /// Removes payloads that have expired or that nothing references, according to `policy`.
+    /// Payloads referenced by a pending transaction are always kept.
+    pub fn collect_garbage(&self, blockchain: &Blockchain, policy: &RetentionPolicy, now: u64) -> Result<GcReport, OffChainStorageError> {
+        let anchors = blockchain.payload_anchors()
+            .map_err(|e| OffChainStorageError::Chain(e.to_string()))?;
+        let pending = blockchain.pending_payloads();
+        let tip_height = blockchain.get_chain_height().unwrap_or(0);
+        let mut report = GcReport::default();
+        for (payload_hash, _) in self.list_payloads()? {
+            if pending.contains(&payload_hash) {
+                continue;
+            }
+            match anchors.get(&payload_hash) {
+                Some(anchor) => {
+                    let expired = policy.retention_blocks
+                        .is_some_and(|retention| tip_height >= anchor.height.saturating_add(retention));
+                    if expired {
+                        report.bytes_freed += self.remove_payload(&payload_hash)?;
+                        report.expired.push(payload_hash);
+                    }
+                }
+                None => {
+                    let modified = fs::metadata(self.storage_path.join(hex::encode(payload_hash)))?
+                        .modified()?
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |age| age.as_secs());
+                    if now.saturating_sub(modified) >= policy.orphan_grace_secs {
+                        report.bytes_freed += self.remove_payload(&payload_hash)?;
+                        report.orphaned.push(payload_hash);
+                    }
+                }
+            }
+        }
+        if !report.expired.is_empty() || !report.orphaned.is_empty() {
+            info!("Payload GC removed {} expired and {} orphaned payloads ({} bytes)",
+                  report.expired.len(), report.orphaned.len(), report.bytes_freed);
+        }
+        Ok(report)
+    }
+
+    /// Lists every stored payload as (hash, size in bytes), sorted by hash.
+    pub fn list_payloads(&self) -> Result<Vec<([u8; 32], u64)>, OffChainStorageError> {
+        let mut payloads = Vec::new();
+        for entry in fs::read_dir(&self.storage_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(hash) = hex::decode(&name).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) {
+                payloads.push((hash, entry.metadata()?.len()));
+            }
+        }
+        payloads.sort();
+        Ok(payloads)
+    }
+
+    /// Lists up to `limit` stored payload hashes whose hex form starts with `hex_prefix`
+    /// (already lowercased), in ascending order.
+    pub fn find_payloads_by_prefix(&self, hex_prefix: &str, limit: usize) -> Result<Vec<[u8; 32]>, OffChainStorageError> {
+        let mut names: Vec<String> = fs::read_dir(&self.storage_path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with(hex_prefix) && !name.starts_with(TEMP_FILE_PREFIX))
+            .collect();
+        names.sort();
+        Ok(names.iter()
+            .filter_map(|name| hex::decode(name).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()))
+            .take(limit)
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -186,6 +384,11 @@ mod tests {
         let retrieved1_again_result = manager.retrieve_payload(&hash1);
         assert!(retrieved1_again_result.is_ok());
         assert_eq!(retrieved1_again_result.unwrap(), payload1);
+
+        // Open for streaming
+        let (_, length) = manager.open_payload(&hash1).unwrap();
+        assert_eq!(length, payload1.len() as u64);
+        assert!(matches!(manager.open_payload(&[0u8; 32]), Err(OffChainStorageError::NotFound(_))));
     }
 
     #[test]
@@ -216,6 +419,58 @@ mod tests {
         assert_eq!(manager.retrieve_payload(&hash1).unwrap(), payload);
     }
 
+    #[test]
+    fn test_offchain_concurrent_store() {
+        let base_dir = tempdir().unwrap();
+        let manager = Arc::new(OffChainStorageManager::new(base_dir.path()).unwrap());
+        let shared_payload = vec![7u8; 256 * 1024];
+
+        // Many writers race on the same new payload while others store distinct ones
+        let handles: Vec<_> = (0..16u8).map(|i| {
+            let manager = manager.clone();
+            let shared_payload = shared_payload.clone();
+            std::thread::spawn(move || {
+                let shared_hash = manager.store_payload(&shared_payload).unwrap();
+                let own_payload = vec![i; 1024];
+                let own_hash = manager.store_payload(&own_payload).unwrap();
+                assert_eq!(manager.retrieve_payload(&own_hash).unwrap(), own_payload);
+                shared_hash
+            })
+        }).collect();
+        let hashes: Vec<[u8; 32]> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert!(hashes.iter().all(|hash| *hash == hashes[0]));
+        assert_eq!(manager.retrieve_payload(&hashes[0]).unwrap(), shared_payload);
+        // One file per distinct payload, no temporary leftovers, no lingering locks
+        let files: Vec<String> = fs::read_dir(base_dir.path().join("offchain_storage")).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(files.len(), 17);
+        assert!(files.iter().all(|name| !name.starts_with(TEMP_FILE_PREFIX)));
+        assert!(manager.write_locks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_offchain_removes_incomplete_writes_on_open() {
+        let base_dir = tempdir().unwrap();
+        let storage_dir = base_dir.path().join("offchain_storage");
+        fs::create_dir_all(&storage_dir).unwrap();
+        fs::write(storage_dir.join(format!("{}abc-0", TEMP_FILE_PREFIX)), b"partial").unwrap();
+        let _manager = OffChainStorageManager::new(base_dir.path()).unwrap();
+        assert_eq!(fs::read_dir(&storage_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_offchain_find_payloads_by_prefix() {
+        let base_dir = tempdir().unwrap();
+        let manager = OffChainStorageManager::new(base_dir.path()).unwrap();
+        let hash = manager.store_payload(b"prefix search").unwrap();
+        manager.store_payload(b"another payload").unwrap();
+        let hash_hex = hex::encode(hash);
+        assert_eq!(manager.find_payloads_by_prefix(&hash_hex[..6], 10).unwrap(), vec![hash]);
+        assert_eq!(manager.find_payloads_by_prefix("", 1).unwrap().len(), 1);
+    }
+
     #[test]
     fn test_offchain_retrieve_not_found() {
         let base_dir = tempdir().unwrap();
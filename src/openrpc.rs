@@ -0,0 +1,116 @@
+//!
+//! # OpenRPC
+//! Machine-readable description of the JSON-RPC API, served by `rpc.discover`.
+//!
+//! Every method the RPC server dispatches is registered as a [`MethodSpec`] mirroring its
+//! params struct, and requests for unregistered methods are refused, so the document client
+//! SDKs are generated from can't drift from what the node actually serves.
+
+use serde_json::{json, Value};
+
+/// Version of the OpenRPC specification the document follows.
+pub const OPENRPC_VERSION: &str = "1.2.6";
+
+/// JSON Schema type of a param or result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaType {
+    Integer,
+    String,
+    Boolean,
+    Object,
+    /// A bech32m address string, or the array of unsigned bytes older clients send.
+    Address,
+    Array,
+    /// `null` or the given type.
+    Nullable(&'static SchemaType),
+}
+
+impl SchemaType {
+    pub fn to_schema(self) -> Value {
+        match self {
+            SchemaType::Integer => json!({ "type": "integer", "minimum": 0 }),
+            SchemaType::String => json!({ "type": "string" }),
+            SchemaType::Boolean => json!({ "type": "boolean" }),
+            SchemaType::Object => json!({ "type": "object" }),
+            SchemaType::Address => json!({ "oneOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "integer", "minimum": 0, "maximum": 255 } },
+            ] }),
+            SchemaType::Array => json!({ "type": "array" }),
+            SchemaType::Nullable(inner) => json!({ "oneOf": [inner.to_schema(), { "type": "null" }] }),
+        }
+    }
+}
+
+/// One field of a method's params object.
+#[derive(Debug, Clone, Copy)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub schema: SchemaType,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+/// A method the RPC server dispatches.
+#[derive(Debug, Clone, Copy)]
+pub struct MethodSpec {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub params: &'static [ParamSpec],
+    pub result: SchemaType,
+}
+
+/// A param the method can't do without.
+pub const fn required(name: &'static str, schema: SchemaType, description: &'static str) -> ParamSpec {
+    ParamSpec { name, schema, required: true, description }
+}
+
+/// A param that may be left out or `null`.
+pub const fn optional(name: &'static str, schema: SchemaType, description: &'static str) -> ParamSpec {
+    ParamSpec { name, schema, required: false, description }
+}
+
+/// Builds the OpenRPC document for `methods`. Params are passed by name, as one object.
+pub fn document(title: &str, version: &str, methods: &[MethodSpec]) -> Value {
+    let methods: Vec<Value> = methods.iter().map(|method| {
+        let params: Vec<Value> = method.params.iter().map(|param| json!({
+            "name": param.name,
+            "description": param.description,
+            "required": param.required,
+            "schema": param.schema.to_schema(),
+        })).collect();
+        json!({
+            "name": method.name,
+            "summary": method.summary,
+            "paramStructure": "by-name",
+            "params": params,
+            "result": { "name": format!("{}_result", method.name), "schema": method.result.to_schema() },
+        })
+    }).collect();
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": { "title": title, "version": version },
+        "methods": methods,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document() {
+        const METHODS: &[MethodSpec] = &[MethodSpec {
+            name: "get_block_by_height",
+            summary: "Block at a height",
+            params: &[required("height", SchemaType::Integer, "Block height")],
+            result: SchemaType::Nullable(&SchemaType::Object),
+        }];
+        let document = document("node", "1.0.0", METHODS);
+        assert_eq!(document["openrpc"], json!(OPENRPC_VERSION));
+        assert_eq!(document["methods"][0]["name"], json!("get_block_by_height"));
+        assert_eq!(document["methods"][0]["params"][0]["required"], json!(true));
+        assert_eq!(document["methods"][0]["params"][0]["schema"]["type"], json!("integer"));
+        assert_eq!(document["methods"][0]["result"]["schema"]["oneOf"][1]["type"], json!("null"));
+    }
+}
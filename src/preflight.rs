@@ -0,0 +1,128 @@
+//!
+//! # Startup Checks
+//! Validates the node configuration before anything is opened or bound.
+//!
+//! Every check records its problem together with a suggested fix in a
+//! [`ConfigReport`] instead of returning early, so an operator sees all mistakes in
+//! one run rather than fixing them one restart at a time.
+
+use std::fmt;
+use std::fs;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::Path;
+
+/// One configuration mistake and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub problem: String,
+    pub fix: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (fix: {})", self.problem, self.fix)
+    }
+}
+
+/// Problems collected by a validation pass.
+#[derive(Debug, Default)]
+pub struct ConfigReport {
+    problems: Vec<ConfigProblem>,
+}
+
+impl ConfigReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, problem: impl Into<String>, fix: impl Into<String>) {
+        self.problems.push(ConfigProblem { problem: problem.into(), fix: fix.into() });
+    }
+
+    pub fn problems(&self) -> &[ConfigProblem] {
+        &self.problems
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Checks that `dir` is (or can be created as) a directory the node can write to.
+    pub fn check_writable_dir(&mut self, flag: &str, dir: &Path) {
+        if dir.exists() && !dir.is_dir() {
+            self.push(format!("{} {:?} is not a directory", flag, dir), format!("point {} to a directory", flag));
+            return;
+        }
+        let probe = dir.join(format!(".write-check-{}", std::process::id()));
+        let writable = fs::create_dir_all(dir).and_then(|_| fs::write(&probe, b"")).and_then(|_| fs::remove_file(&probe));
+        if let Err(e) = writable {
+            self.push(
+                format!("{} {:?} is not writable: {}", flag, dir, e),
+                format!("fix the permissions of {:?} or choose another {}", dir, flag),
+            );
+        }
+    }
+
+    /// Checks that `file` exists and can be read.
+    pub fn check_readable_file(&mut self, flag: &str, file: &Path) {
+        if let Err(e) = fs::File::open(file) {
+            self.push(format!("{} {:?} cannot be read: {}", flag, file, e), format!("check the path given to {}", flag));
+        }
+    }
+
+    /// Checks that `addr` resolves to a socket address that can be bound right now.
+    pub fn check_listen_addr(&mut self, flag: &str, addr: &str) -> Option<SocketAddr> {
+        let parsed = match addr.to_socket_addrs().ok().and_then(|mut addrs| addrs.next()) {
+            Some(parsed) => parsed,
+            None => {
+                self.push(format!("{} {:?} is not a socket address", flag, addr), format!("use HOST:PORT, e.g. {} 127.0.0.1:8000", flag));
+                return None;
+            }
+        };
+        if let Err(e) = TcpListener::bind(parsed) {
+            self.push(
+                format!("{} {} cannot be bound: {}", flag, parsed, e),
+                format!("stop the process using port {} or pass another {}", parsed.port(), flag),
+            );
+        }
+        Some(parsed)
+    }
+
+    /// `Ok` if no problem was found, otherwise one message listing them all.
+    pub fn into_result(self) -> Result<(), String> {
+        if self.problems.is_empty() {
+            return Ok(());
+        }
+        let mut message = format!("{} configuration problem(s):", self.problems.len());
+        for problem in &self.problems {
+            message.push_str("\n  - ");
+            message.push_str(&problem.to_string());
+        }
+        Err(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_report_collects_every_problem() {
+        let dir = tempdir().unwrap();
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let busy_addr = busy.local_addr().unwrap().to_string();
+
+        let mut report = ConfigReport::new();
+        report.check_writable_dir("--data-dir", &dir.path().join("data"));
+        report.check_readable_file("--tenants-file", &dir.path().join("missing.json"));
+        assert!(report.check_listen_addr("--rpc-addr", "localhost").is_none());
+        assert!(report.check_listen_addr("--stratum-addr", &busy_addr).is_some());
+        assert_eq!(report.problems().len(), 3);
+
+        let message = report.into_result().unwrap_err();
+        assert!(message.starts_with("3 configuration problem(s):"));
+        assert!(message.contains("--tenants-file") && message.contains("--rpc-addr") && message.contains("--stratum-addr"));
+        assert!(ConfigReport::new().into_result().is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+//!
+//! # Network Presets
+//! Built-in settings for the networks a node can join with `--network`, so joining the
+//! testnet or running a local devnet needs no hand-written genesis parameters.
+//!
+//! A preset fixes the [`ChainConfig`] a new chain is created with (genesis time, difficulty
+//! algorithm and bounds, active features), the prefix of encoded addresses, and the default
+//! RPC and P2P addresses. Test networks keep their data in a subdirectory of the data
+//! directory, so one `--data-dir` can hold a chain of each network side by side.
+//!
+//! | Network   | Addresses | RPC             | P2P                     | Data                 |
+//! |-----------|-----------|-----------------|-------------------------|----------------------|
+//! | `mainnet` | `bds1…`   | 127.0.0.1:8000  | all interfaces, OS port | `<data-dir>`         |
+//! | `testnet` | `tbds1…`  | 127.0.0.1:18000 | all interfaces, 14001   | `<data-dir>/testnet` |
+//! | `devnet`  | `dbds1…`  | 127.0.0.1:28000 | loopback, OS port       | `<data-dir>/devnet`  |
+//! | `regtest` | `rbds1…`  | 127.0.0.1:38000 | loopback, OS port       | `<data-dir>/regtest` |
+//!
+//! On regtest, blocks are only made on request, with the `generate_blocks` RPC.
+
+use crate::chain_config::{ChainConfig, TxFeature};
+use crate::consensus::{DifficultyAlgorithm, DifficultyBounds, POW_LIMIT_BITS};
+use crate::core::address::DEFAULT_ADDRESS_HRP;
+use crate::network::DEFAULT_LISTEN_ADDR;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Genesis time of the mainnet (2026-02-01T00:00:00Z).
+pub const MAINNET_GENESIS_TIMESTAMP: u64 = 1_769_904_000;
+/// Height from which the mainnet accepts storage deals and pointer records, about a week of blocks
+/// after genesis.
+pub const MAINNET_FEATURES_ACTIVATION_HEIGHT: u64 = 1_008;
+/// Genesis time of the testnet (2026-01-01T00:00:00Z).
+pub const TESTNET_GENESIS_TIMESTAMP: u64 = 1_767_225_600;
+/// Hardest testnet target, in compact form (hashes with 40 leading zero bits).
+pub const TESTNET_MIN_TARGET_BITS: u32 = 0x1c00_ffff;
+/// Genesis time of devnets (2026-01-01T00:00:00Z).
+pub const DEVNET_GENESIS_TIMESTAMP: u64 = 1_767_225_600;
+/// The fixed devnet target, in compact form (hashes with 1 leading zero bit): blocks mine instantly.
+pub const DEVNET_TARGET_BITS: u32 = 0x207f_ffff;
+/// Devnet blocks are final this many blocks deep, so finality can be tried out quickly.
+pub const DEVNET_FINALITY_DEPTH: u64 = 6;
+
+/// Genesis time of regtest chains (2026-01-01T00:00:00Z).
+pub const REGTEST_GENESIS_TIMESTAMP: u64 = 1_767_225_600;
+/// The fixed regtest target, in compact form (hashes with 1 leading zero bit).
+pub const REGTEST_TARGET_BITS: u32 = 0x207f_ffff;
+
+/// A network a node can run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkPreset {
+    /// The production network, with the compiled-in consensus defaults, a fixed genesis time and
+    /// storage deals and pointers scheduled to activate.
+    #[default]
+    Mainnet,
+    /// The public test network: LWMA retargeting, a capped difficulty and every feature active.
+    Testnet,
+    /// A local development network: a fixed, trivial difficulty and quick finality.
+    Devnet,
+    /// A private chain for integration tests: a fixed, trivial difficulty and blocks generated on demand.
+    Regtest,
+}
+
+impl NetworkPreset {
+    /// Configuration chains of this network are created with.
+    pub fn chain_config(self) -> ChainConfig {
+        match self {
+            NetworkPreset::Mainnet => {
+                let mut config = ChainConfig::default().with_genesis_timestamp(MAINNET_GENESIS_TIMESTAMP);
+                for feature in [TxFeature::StorageDeals, TxFeature::Pointers] {
+                    config.feature_activations.insert(feature, MAINNET_FEATURES_ACTIVATION_HEIGHT);
+                }
+                config
+            }
+            NetworkPreset::Testnet => with_all_features(ChainConfig::default())
+                .with_difficulty_algorithm(DifficultyAlgorithm::lwma())
+                .with_difficulty_bounds(DifficultyBounds { pow_limit_bits: POW_LIMIT_BITS, min_target_bits: TESTNET_MIN_TARGET_BITS })
+                .with_genesis_timestamp(TESTNET_GENESIS_TIMESTAMP),
+            NetworkPreset::Devnet => with_all_features(ChainConfig::default())
+                .with_difficulty_bounds(DifficultyBounds { pow_limit_bits: DEVNET_TARGET_BITS, min_target_bits: DEVNET_TARGET_BITS })
+                .with_finality_depth(DEVNET_FINALITY_DEPTH)
+                .with_genesis_timestamp(DEVNET_GENESIS_TIMESTAMP),
+            NetworkPreset::Regtest => with_all_features(ChainConfig::default())
+                .with_difficulty_bounds(DifficultyBounds { pow_limit_bits: REGTEST_TARGET_BITS, min_target_bits: REGTEST_TARGET_BITS })
+                .with_genesis_timestamp(REGTEST_GENESIS_TIMESTAMP),
+        }
+    }
+
+    /// Human-readable part of the network's encoded addresses.
+    pub fn address_hrp(self) -> &'static str {
+        match self {
+            NetworkPreset::Mainnet => DEFAULT_ADDRESS_HRP,
+            NetworkPreset::Testnet => "tbds",
+            NetworkPreset::Devnet => "dbds",
+            NetworkPreset::Regtest => "rbds",
+        }
+    }
+
+    /// Default address of the RPC server.
+    pub fn rpc_addr(self) -> &'static str {
+        match self {
+            NetworkPreset::Mainnet => "127.0.0.1:8000",
+            NetworkPreset::Testnet => "127.0.0.1:18000",
+            NetworkPreset::Devnet => "127.0.0.1:28000",
+            NetworkPreset::Regtest => "127.0.0.1:38000",
+        }
+    }
+
+    /// Default P2P listen address.
+    pub fn p2p_listen_addr(self) -> &'static str {
+        match self {
+            NetworkPreset::Mainnet => DEFAULT_LISTEN_ADDR,
+            NetworkPreset::Testnet => "/ip4/0.0.0.0/tcp/14001",
+            NetworkPreset::Devnet | NetworkPreset::Regtest => "/ip4/127.0.0.1/tcp/0",
+        }
+    }
+
+    /// Where the network's data lives under the data directory `base`.
+    pub fn data_dir(self, base: &Path) -> PathBuf {
+        match self {
+            NetworkPreset::Mainnet => base.to_path_buf(),
+            NetworkPreset::Testnet | NetworkPreset::Devnet | NetworkPreset::Regtest => base.join(self.to_string()),
+        }
+    }
+}
+
+// Gated transaction kinds are live from genesis on test networks, so they can be tried out
+fn with_all_features(mut config: ChainConfig) -> ChainConfig {
+    for feature in [TxFeature::Tokens, TxFeature::StorageDeals, TxFeature::Pointers] {
+        config.feature_activations.insert(feature, 0);
+    }
+    config
+}
+
+impl fmt::Display for NetworkPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkPreset::Mainnet => f.write_str("mainnet"),
+            NetworkPreset::Testnet => f.write_str("testnet"),
+            NetworkPreset::Devnet => f.write_str("devnet"),
+            NetworkPreset::Regtest => f.write_str("regtest"),
+        }
+    }
+}
+
+impl FromStr for NetworkPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mainnet" => Ok(NetworkPreset::Mainnet),
+            "testnet" => Ok(NetworkPreset::Testnet),
+            "devnet" => Ok(NetworkPreset::Devnet),
+            "regtest" => Ok(NetworkPreset::Regtest),
+            other => Err(format!("Unknown network '{}'. Expected 'mainnet', 'testnet', 'devnet' or 'regtest'", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::bits_for_leading_zeros;
+    use crate::core::{Block, Blockchain};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(TESTNET_MIN_TARGET_BITS, bits_for_leading_zeros(40));
+        assert_eq!(DEVNET_TARGET_BITS, bits_for_leading_zeros(1));
+        assert_eq!(REGTEST_TARGET_BITS, bits_for_leading_zeros(1));
+        for network in [NetworkPreset::Mainnet, NetworkPreset::Testnet, NetworkPreset::Devnet, NetworkPreset::Regtest] {
+            assert_eq!(network.to_string().parse::<NetworkPreset>(), Ok(network));
+            assert!(network.chain_config().difficulty_bounds.validate().is_ok());
+            assert!(network.p2p_listen_addr().parse::<libp2p::Multiaddr>().is_ok());
+        }
+        let mainnet = NetworkPreset::Mainnet.chain_config();
+        assert_eq!(mainnet.genesis_timestamp, Some(MAINNET_GENESIS_TIMESTAMP));
+        for feature in [TxFeature::Tokens, TxFeature::StorageDeals, TxFeature::Pointers] {
+            assert!(mainnet.is_feature_active(feature, MAINNET_FEATURES_ACTIVATION_HEIGHT));
+        }
+        assert!(!mainnet.is_feature_active(TxFeature::StorageDeals, MAINNET_FEATURES_ACTIVATION_HEIGHT - 1));
+        assert_eq!(NetworkPreset::Testnet.data_dir(Path::new("data")), Path::new("data/testnet"));
+        assert!("simnet".parse::<NetworkPreset>().is_err());
+
+        // Devnet nodes create the same genesis independently and mine at the fixed target
+        let dir = tempdir().unwrap();
+        let mut genesis_hashes = Vec::new();
+        for node in ["a", "b"] {
+            let mut blockchain = Blockchain::new_with_config(&dir.path().join(node), NetworkPreset::Devnet.chain_config()).unwrap();
+            blockchain.initialize_genesis_if_needed().unwrap();
+            genesis_hashes.push(blockchain.get_last_block_hash());
+            let block = blockchain.mine_new_block().unwrap();
+            assert_eq!(block.header.bits, DEVNET_TARGET_BITS);
+            blockchain.process_mined_block(block).unwrap();
+        }
+        assert_eq!(genesis_hashes[0], genesis_hashes[1]);
+        assert_ne!(genesis_hashes[0], None);
+
+        // So do mainnet nodes, on a genesis of their own
+        let mut mainnet_hashes = Vec::new();
+        for node in ["mainnet-a", "mainnet-b"] {
+            let mut blockchain = Blockchain::new_with_config(&dir.path().join(node), NetworkPreset::Mainnet.chain_config()).unwrap();
+            blockchain.initialize_genesis_if_needed().unwrap();
+            mainnet_hashes.push(blockchain.get_last_block_hash());
+        }
+        assert_eq!(mainnet_hashes[0], mainnet_hashes[1]);
+        assert_ne!(mainnet_hashes[0], genesis_hashes[0]);
+
+        // Regtest blocks are generated on demand, paying the given address
+        let mut blockchain = Blockchain::new_with_config(&dir.path().join("regtest"), NetworkPreset::Regtest.chain_config()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let miner = vec![7u8; 21];
+        let blocks = blockchain.generate_blocks(5, &miner).unwrap();
+        assert_eq!(blockchain.get_chain_height(), Some(5));
+        assert_eq!(blockchain.get_last_block_hash(), blocks.last().map(Block::hash));
+        assert!(blocks.iter().all(|block| block.transactions[0].receiver() == Some(&miner)));
+        assert_eq!(blockchain.miner_address(), None);
+    }
+}
@@ -0,0 +1,243 @@
+//!
+//! # RPC Authentication
+//! Optional access control for the JSON-RPC API.
+//!
+//! Clients present a static API key or an HS256-signed JWT in the `Authorization: Bearer`
+//! header (an API key may also go in `X-API-Key`). Requests without valid credentials are
+//! only served when every method they call is on the public allowlist, which by default
+//! holds the read-only methods. When no auth file is configured the API stays open.
+
+use hmac::{Hmac, Mac};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Read-only methods served without credentials unless the auth file lists its own.
+pub const DEFAULT_PUBLIC_METHODS: &[&str] = &[
+    "get_chain_height",
+    "get_sync_status",
+    "get_transaction",
+    "get_transaction_proof",
+    "get_raw_transaction",
+    "decode_raw_transaction",
+    "get_mempool_entry",
+    "get_mempool_contents",
+    "get_node_info",
+    "get_mempool_info",
+    "get_block_by_height",
+    "get_block_by_hash",
+    "get_block_by_time",
+    "get_blocks",
+    "get_raw_block",
+    "get_offchain_data",
+    "get_balance",
+    "list_tokens",
+    "get_token_balance",
+    "get_address_history",
+    "get_events",
+    "get_block_filters",
+    "get_storage_deals",
+    "get_top_holders",
+    "get_address_count",
+    "list_payloads",
+    "resolve_pointer",
+    "find_by_prefix",
+    "get_payload_availability",
+    "rpc.discover",
+];
+
+/// Methods that need admin credentials: they are never public, and in multi-tenant mode only
+/// admin tenants may call them.
+pub const ADMIN_METHODS: &[&str] = &[
+    "pause_mining",
+    "resume_mining",
+    "generate_blocks",
+    "create_snapshot",
+    "create_backup",
+    "wallet_new_address",
+    "wallet_list_addresses",
+    "wallet_encrypt",
+    "wallet_unlock",
+    "wallet_lock",
+    "sign_raw_transaction",
+];
+
+/// Credentials and public methods, as declared in the RPC auth file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpcAuthConfig {
+    /// Static API keys, each granting access to every method.
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// Shared secret that HS256 JWTs must be signed with; JWTs are refused when unset.
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+    /// Methods callable without credentials; `None` uses [`DEFAULT_PUBLIC_METHODS`].
+    #[serde(default)]
+    pub public_methods: Option<Vec<String>>,
+}
+
+/// Errors raised while loading the auth configuration or checking credentials.
+#[derive(Debug, thiserror::Error)]
+pub enum RpcAuthError {
+    #[error("Missing credentials")]
+    MissingCredentials,
+    #[error("Unknown API key")]
+    UnknownApiKey,
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+    #[error("Token expired at {0}")]
+    TokenExpired(u64),
+    #[error("Invalid RPC auth configuration: {0}")]
+    InvalidConfig(String),
+}
+
+/// Claims of a JWT this node checks; any others are ignored.
+#[derive(Deserialize, Debug)]
+struct JwtClaims {
+    exp: Option<u64>,
+    nbf: Option<u64>,
+}
+
+/// Checks RPC credentials against the configured keys and JWT secret.
+#[derive(Debug)]
+pub struct RpcAuth {
+    /// SHA-256 of each API key, so lookups don't branch on the secret itself.
+    key_hashes: HashSet<[u8; 32]>,
+    jwt_secret: Option<Vec<u8>>,
+    public_methods: HashSet<String>,
+}
+
+fn hash_api_key(api_key: &str) -> [u8; 32] {
+    Sha256::digest(api_key.as_bytes()).into()
+}
+
+impl RpcAuth {
+    /// Builds the checker, requiring at least one API key or a JWT secret and no empty secrets.
+    pub fn new(config: RpcAuthConfig) -> Result<Self, RpcAuthError> {
+        if config.api_keys.iter().any(|key| key.is_empty()) {
+            return Err(RpcAuthError::InvalidConfig("API keys must not be empty".to_string()));
+        }
+        if config.jwt_secret.as_deref() == Some("") {
+            return Err(RpcAuthError::InvalidConfig("The JWT secret must not be empty".to_string()));
+        }
+        if config.api_keys.is_empty() && config.jwt_secret.is_none() {
+            return Err(RpcAuthError::InvalidConfig("Declare at least one API key or a JWT secret".to_string()));
+        }
+        let public_methods = match config.public_methods {
+            Some(methods) => methods.into_iter().collect(),
+            None => DEFAULT_PUBLIC_METHODS.iter().map(|method| method.to_string()).collect(),
+        };
+        Ok(RpcAuth {
+            key_hashes: config.api_keys.iter().map(|key| hash_api_key(key)).collect(),
+            jwt_secret: config.jwt_secret.map(String::into_bytes),
+            public_methods,
+        })
+    }
+
+    /// Loads the configuration from a JSON file containing an `RpcAuthConfig` object.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, RpcAuthError> {
+        let contents = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| RpcAuthError::InvalidConfig(format!("Cannot read {:?}: {}", path.as_ref(), e)))?;
+        let config: RpcAuthConfig = serde_json::from_str(&contents)
+            .map_err(|e| RpcAuthError::InvalidConfig(format!("Cannot parse {:?}: {}", path.as_ref(), e)))?;
+        Self::new(config)
+    }
+
+    /// Whether `method` may be called without credentials. [`ADMIN_METHODS`] never are.
+    pub fn is_public(&self, method: &str) -> bool {
+        !ADMIN_METHODS.contains(&method) && self.public_methods.contains(method)
+    }
+
+    /// Accepts `credential` if it is a configured API key or a JWT signed with the secret
+    /// that is valid at `now`.
+    pub fn authenticate(&self, credential: Option<&str>, now: u64) -> Result<(), RpcAuthError> {
+        let credential = credential.ok_or(RpcAuthError::MissingCredentials)?;
+        if self.key_hashes.contains(&hash_api_key(credential)) {
+            return Ok(());
+        }
+        match &self.jwt_secret {
+            Some(secret) if credential.split('.').count() == 3 => verify_jwt(credential, secret, now),
+            _ => Err(RpcAuthError::UnknownApiKey),
+        }
+    }
+}
+
+// Checks an HS256 JWT's signature and its `exp` / `nbf` claims
+fn verify_jwt(token: &str, secret: &[u8], now: u64) -> Result<(), RpcAuthError> {
+    let (signing_input, signature) = token.rsplit_once('.').expect("caller checked the token has three parts");
+    let (header, claims) = signing_input.split_once('.').expect("caller checked the token has three parts");
+    let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|e| RpcAuthError::InvalidToken(format!("Bad encoding: {}", e)));
+
+    let header: serde_json::Value = serde_json::from_slice(&decode(header)?)
+        .map_err(|e| RpcAuthError::InvalidToken(format!("Bad header: {}", e)))?;
+    if header["alg"] != "HS256" {
+        return Err(RpcAuthError::InvalidToken(format!("Unsupported algorithm {}", header["alg"])));
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(signing_input.as_bytes());
+    mac.verify_slice(&decode(signature)?).map_err(|_| RpcAuthError::InvalidToken("Bad signature".to_string()))?;
+
+    let claims: JwtClaims = serde_json::from_slice(&decode(claims)?)
+        .map_err(|e| RpcAuthError::InvalidToken(format!("Bad claims: {}", e)))?;
+    if let Some(exp) = claims.exp.filter(|exp| now >= *exp) {
+        return Err(RpcAuthError::TokenExpired(exp));
+    }
+    if claims.nbf.is_some_and(|nbf| now < nbf) {
+        return Err(RpcAuthError::InvalidToken("Token is not valid yet".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign_jwt(header: &str, claims: &str, secret: &[u8]) -> String {
+        let signing_input = format!("{}.{}", URL_SAFE_NO_PAD.encode(header), URL_SAFE_NO_PAD.encode(claims));
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(signing_input.as_bytes());
+        format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_api_keys_and_public_methods() {
+        let auth = RpcAuth::new(RpcAuthConfig { api_keys: vec!["k1".to_string()], ..Default::default() }).unwrap();
+        assert!(auth.authenticate(Some("k1"), 0).is_ok());
+        assert!(matches!(auth.authenticate(Some("k2"), 0), Err(RpcAuthError::UnknownApiKey)));
+        assert!(matches!(auth.authenticate(None, 0), Err(RpcAuthError::MissingCredentials)));
+        assert!(auth.is_public("get_block_by_height"));
+        assert!(!auth.is_public("send_transaction"));
+
+        let custom = RpcAuthConfig { api_keys: vec!["k1".to_string()], public_methods: Some(vec![]), ..Default::default() };
+        assert!(!RpcAuth::new(custom).unwrap().is_public("get_block_by_height"));
+        // Admin methods stay behind credentials even when listed as public
+        let listed = RpcAuthConfig {
+            api_keys: vec!["k1".to_string()],
+            public_methods: Some(vec!["create_snapshot".to_string(), "wallet_unlock".to_string()]),
+            ..Default::default()
+        };
+        let listed = RpcAuth::new(listed).unwrap();
+        assert!(!listed.is_public("create_snapshot"));
+        assert!(!listed.is_public("wallet_unlock"));
+        assert!(RpcAuth::new(RpcAuthConfig::default()).is_err());
+        assert!(RpcAuth::new(RpcAuthConfig { api_keys: vec![String::new()], ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn test_jwt_verification() {
+        let auth = RpcAuth::new(RpcAuthConfig { jwt_secret: Some("s3cret".to_string()), ..Default::default() }).unwrap();
+        let header = r#"{"alg":"HS256","typ":"JWT"}"#;
+        let token = sign_jwt(header, r#"{"sub":"explorer","exp":2000,"nbf":1000}"#, b"s3cret");
+        assert!(auth.authenticate(Some(&token), 1500).is_ok());
+        assert!(matches!(auth.authenticate(Some(&token), 2000), Err(RpcAuthError::TokenExpired(2000))));
+        assert!(matches!(auth.authenticate(Some(&token), 999), Err(RpcAuthError::InvalidToken(_))));
+
+        let forged = sign_jwt(header, r#"{"exp":2000}"#, b"guess");
+        assert!(matches!(auth.authenticate(Some(&forged), 1500), Err(RpcAuthError::InvalidToken(_))));
+        let unsigned = sign_jwt(r#"{"alg":"none"}"#, r#"{}"#, b"s3cret");
+        assert!(matches!(auth.authenticate(Some(&unsigned), 1500), Err(RpcAuthError::InvalidToken(_))));
+        assert!(matches!(auth.authenticate(Some("not-a-token"), 1500), Err(RpcAuthError::UnknownApiKey)));
+    }
+}
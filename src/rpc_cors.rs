@@ -0,0 +1,141 @@
+//!
+//! # RPC CORS Policy
+//! Which web pages may call the RPC server from a browser.
+//!
+//! By default no cross-origin request is allowed: browsers only let pages served from the
+//! RPC address itself read the responses. Operators list the origins of their frontends, and
+//! may narrow the methods and request headers those pages can use. The permissive policy
+//! accepts any origin, method and header, for local development only.
+
+use actix_cors::Cors;
+use actix_web::http::header::HeaderName;
+use actix_web::http::{Method, Uri};
+
+/// Methods allowed for listed origins unless others are configured: those the RPC and REST routes use.
+pub const DEFAULT_CORS_METHODS: &[&str] = &["GET", "POST"];
+/// Request headers allowed for listed origins unless others are configured.
+pub const DEFAULT_CORS_HEADERS: &[&str] = &["content-type", "authorization", "x-api-key", "range"];
+/// How long browsers may cache a preflight response, in seconds.
+const CORS_MAX_AGE_SECS: usize = 3600;
+
+/// Errors in the configured CORS settings.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CorsConfigError {
+    #[error("Invalid CORS origin {0:?}: expected scheme://host[:port], e.g. https://app.example.com")]
+    InvalidOrigin(String),
+    #[error("Invalid CORS method {0:?}")]
+    InvalidMethod(String),
+    #[error("Invalid CORS header {0:?}")]
+    InvalidHeader(String),
+}
+
+/// Cross-origin requests the RPC server accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorsPolicy {
+    /// Any origin, method and header.
+    Permissive,
+    /// Only the listed origins, using only the listed methods and request headers.
+    Restricted {
+        origins: Vec<String>,
+        methods: Vec<Method>,
+        headers: Vec<HeaderName>,
+    },
+}
+
+impl Default for CorsPolicy {
+    /// No cross-origin requests.
+    fn default() -> Self {
+        CorsPolicy::Restricted {
+            origins: Vec::new(),
+            methods: default_methods(),
+            headers: default_headers(),
+        }
+    }
+}
+
+fn default_methods() -> Vec<Method> {
+    DEFAULT_CORS_METHODS.iter().map(|method| Method::from_bytes(method.as_bytes()).expect("Valid default method")).collect()
+}
+
+fn default_headers() -> Vec<HeaderName> {
+    DEFAULT_CORS_HEADERS.iter().map(|header| HeaderName::from_static(header)).collect()
+}
+
+impl CorsPolicy {
+    /// Allows `origins`, with `methods` and `headers` or the defaults when they are empty.
+    pub fn restricted(origins: &[String], methods: &[String], headers: &[String]) -> Result<Self, CorsConfigError> {
+        let origins = origins.iter().map(|origin| validate_origin(origin)).collect::<Result<_, _>>()?;
+        let methods = if methods.is_empty() {
+            default_methods()
+        } else {
+            methods.iter()
+                .map(|method| Method::from_bytes(method.to_ascii_uppercase().as_bytes()).map_err(|_| CorsConfigError::InvalidMethod(method.clone())))
+                .collect::<Result<_, _>>()?
+        };
+        let headers = if headers.is_empty() {
+            default_headers()
+        } else {
+            headers.iter()
+                .map(|header| HeaderName::from_bytes(header.as_bytes()).map_err(|_| CorsConfigError::InvalidHeader(header.clone())))
+                .collect::<Result<_, _>>()?
+        };
+        Ok(CorsPolicy::Restricted { origins, methods, headers })
+    }
+
+    /// Builds the middleware enforcing the policy.
+    pub fn to_cors(&self) -> Cors {
+        match self {
+            CorsPolicy::Permissive => Cors::permissive().max_age(CORS_MAX_AGE_SECS),
+            CorsPolicy::Restricted { origins, methods, headers } => {
+                let cors = origins.iter().fold(Cors::default(), |cors, origin| cors.allowed_origin(origin));
+                cors.allowed_methods(methods.clone())
+                    .allowed_headers(headers.clone())
+                    .max_age(CORS_MAX_AGE_SECS)
+            }
+        }
+    }
+}
+
+/// Checks that `origin` is a bare `scheme://host[:port]`, as browsers send it in `Origin`.
+fn validate_origin(origin: &str) -> Result<String, CorsConfigError> {
+    let invalid = || CorsConfigError::InvalidOrigin(origin.to_string());
+    let uri: Uri = origin.parse().map_err(|_| invalid())?;
+    let bare = matches!(uri.scheme_str(), Some("http" | "https"))
+        && uri.authority().is_some_and(|authority| !authority.as_str().contains('@'))
+        && uri.path() == "/"
+        && !origin.ends_with('/')
+        && uri.query().is_none();
+    if bare { Ok(origin.to_string()) } else { Err(invalid()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN};
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn test_restricted_policy() {
+        for origin in ["*", "app.example.com", "https://app.example.com/path", "ftp://app.example.com", "https://user@app.example.com"] {
+            assert_eq!(
+                CorsPolicy::restricted(&[origin.to_string()], &[], &[]),
+                Err(CorsConfigError::InvalidOrigin(origin.to_string()))
+            );
+        }
+        let policy = CorsPolicy::restricted(&["https://app.example.com:8443".to_string()], &[], &[]).unwrap();
+
+        let app = test::init_service(
+            App::new().wrap(policy.to_cors()).route("/", web::post().to(HttpResponse::Ok)),
+        ).await;
+        for (origin, allowed) in [("https://app.example.com:8443", true), ("https://evil.example.com", false)] {
+            let request = test::TestRequest::default()
+                .method(Method::OPTIONS)
+                .uri("/")
+                .insert_header((ORIGIN, origin))
+                .insert_header((ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_some(), allowed, "{}", origin);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//!
+//! # RPC Rate Limiting
+//! Per-client request budgets for the JSON-RPC API, so scrapers can't flatten a public node.
+//!
+//! Requests carrying a valid API key, JWT or tenant key are counted against that credential;
+//! all others against the client's IP address, so rotating made-up keys gains nothing. Budgets
+//! are fixed windows of `RATE_WINDOW_SECS`, like the tenants' own request budgets. Limits that
+//! are unset leave that kind of client unlimited. Each request of a batch counts separately.
+
+use crate::tenants::RATE_WINDOW_SECS;
+use sha2::{Sha256, Digest};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Clients tracked before windows from earlier periods are dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Requests allowed per `RATE_WINDOW_SECS` window for each kind of client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Budget of each IP address sending requests without valid credentials.
+    pub per_ip: Option<u32>,
+    /// Budget of each valid API key, JWT or tenant key, wherever its requests come from.
+    pub per_credential: Option<u32>,
+}
+
+/// Who a request is counted against.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateLimitClient {
+    Ip(IpAddr),
+    /// SHA-256 of the credential, so the secret itself isn't kept around.
+    Credential([u8; 32]),
+}
+
+impl RateLimitClient {
+    pub fn credential(credential: &str) -> Self {
+        RateLimitClient::Credential(Sha256::digest(credential.as_bytes()).into())
+    }
+}
+
+/// Errors raised when a client has spent its budget.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum RateLimitError {
+    #[error("Rate limit of {limit} requests per minute exceeded; retry in {retry_after} seconds")]
+    RateLimited { limit: u32, retry_after: u64 },
+}
+
+/// Counts requests per client in fixed windows.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    /// Current window per client: (window start, requests in window).
+    windows: Mutex<HashMap<RateLimitClient, (u64, u32)>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter { limits, windows: Mutex::new(HashMap::new()) }
+    }
+
+    /// Budget of `client` per window; `None` if it is unlimited.
+    pub fn limit_for(&self, client: &RateLimitClient) -> Option<u32> {
+        match client {
+            RateLimitClient::Ip(_) => self.limits.per_ip,
+            RateLimitClient::Credential(_) => self.limits.per_credential,
+        }
+    }
+
+    /// Counts `requests` from `client` at `now`, failing without counting them if they don't
+    /// fit in what is left of its window's budget.
+    pub fn check(&self, client: &RateLimitClient, requests: u32, now: u64) -> Result<(), RateLimitError> {
+        let Some(limit) = self.limit_for(client) else {
+            return Ok(());
+        };
+        let window_start = now - now % RATE_WINDOW_SECS;
+        let mut windows = self.windows.lock().expect("Rate limit windows lock poisoned");
+        if windows.len() >= MAX_TRACKED_CLIENTS && !windows.contains_key(client) {
+            windows.retain(|_, window| window.0 == window_start);
+        }
+        let window = windows.entry(client.clone()).or_insert((window_start, 0));
+        if window.0 != window_start {
+            *window = (window_start, 0);
+        }
+        if window.1.saturating_add(requests) <= limit {
+            window.1 += requests;
+            Ok(())
+        } else {
+            Err(RateLimitError::RateLimited { limit, retry_after: window_start + RATE_WINDOW_SECS - now })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_per_client() {
+        let limiter = RateLimiter::new(RateLimits { per_ip: Some(2), per_credential: None });
+        let ip = RateLimitClient::Ip("10.0.0.1".parse().unwrap());
+        let other_ip = RateLimitClient::Ip("10.0.0.2".parse().unwrap());
+        assert!(limiter.check(&ip, 1, 600).is_ok());
+        assert_eq!(limiter.check(&ip, 2, 605), Err(RateLimitError::RateLimited { limit: 2, retry_after: 55 }));
+        assert!(limiter.check(&ip, 1, 610).is_ok());
+        assert_eq!(limiter.check(&ip, 1, 620), Err(RateLimitError::RateLimited { limit: 2, retry_after: 40 }));
+        assert!(limiter.check(&other_ip, 1, 620).is_ok());
+        // A new window restores the budget
+        assert!(limiter.check(&ip, 2, 660).is_ok());
+        // Credentials are unlimited without a per-credential limit
+        assert_eq!(limiter.limit_for(&RateLimitClient::credential("k1")), None);
+        assert!(limiter.check(&RateLimitClient::credential("k1"), 100, 660).is_ok());
+    }
+}
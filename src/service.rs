@@ -0,0 +1,266 @@
+//!
+//! # Service Integration
+//! Helpers for running the node under an init system such as systemd.
+//!
+//! - `PidFile` writes the process id on startup and removes it on exit.
+//! - `DataDirLock` keeps a second node process (or an offline command such as
+//!   `export-chain`) from opening a data directory that is already in use.
+//! - `sd_notify` sends readiness/status messages to `$NOTIFY_SOCKET` (a no-op when the
+//!   variable is unset), so a `Type=notify` unit only counts the node as started once
+//!   genesis is checked and the RPC server is listening.
+//! - `NodeError` classifies fatal errors into distinct exit codes (sysexits-style) so a
+//!   supervisor can tell a bad configuration, which restarting will not fix, from a
+//!   damaged database or a runtime failure.
+//! - `Shutdown` is handed to long-running tasks (network loop, Stratum server, background
+//!   jobs) so that on Ctrl-C or SIGTERM they wind down in order instead of being killed
+//!   mid-write, and `shutdown_signal` waits for either signal.
+
+use log::{debug, warn};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+
+/// Exit code for invalid configuration or arguments (`EX_CONFIG`).
+pub const EXIT_CONFIG: u8 = 78;
+/// Exit code for a corrupt or inconsistent database (`EX_DATAERR`).
+pub const EXIT_DATABASE: u8 = 65;
+/// Exit code for failures while running (`EX_SOFTWARE`).
+pub const EXIT_RUNTIME: u8 = 70;
+
+/// Fatal node error, classified by how a supervisor should react.
+#[derive(Debug, thiserror::Error)]
+pub enum NodeError {
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("Runtime error: {0}")]
+    Runtime(String),
+}
+
+impl NodeError {
+    /// Process exit code for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            NodeError::Config(_) => EXIT_CONFIG,
+            NodeError::Database(_) => EXIT_DATABASE,
+            NodeError::Runtime(_) => EXIT_RUNTIME,
+        }
+    }
+}
+
+/// A PID file that is removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the current process id to `path`.
+    /// Fails if the file names another running process; a stale file is replaced.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(contents) = fs::read_to_string(&path) {
+            if let Ok(pid) = contents.trim().parse::<u32>() {
+                if pid != std::process::id() && Path::new(&format!("/proc/{}", pid)).exists() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("PID file {:?} belongs to running process {}", path, pid),
+                    ));
+                }
+            }
+            warn!("Replacing stale PID file {:?}", path);
+        }
+        fs::write(&path, format!("{}\n", std::process::id()))?;
+        Ok(PidFile { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove PID file {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Lock file a process holds in the data directory while it uses it.
+pub const DATA_DIR_LOCK_FILE: &str = "node.lock";
+
+/// An exclusive lock on a data directory (database and off-chain files), released when dropped.
+/// The operating system also releases it when the process dies, so a crash leaves no stale lock.
+#[derive(Debug)]
+pub struct DataDirLock {
+    _file: File,
+}
+
+impl DataDirLock {
+    /// Locks `data_dir`, creating it if needed, and records the current process id in the lock file.
+    /// Fails with `ErrorKind::WouldBlock` if another process holds the lock.
+    pub fn acquire<P: AsRef<Path>>(data_dir: P) -> io::Result<Self> {
+        let data_dir = data_dir.as_ref();
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(DATA_DIR_LOCK_FILE);
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = fs::read_to_string(&path).ok()
+                    .and_then(|contents| contents.trim().parse::<u32>().ok())
+                    .map_or_else(|| "another process".to_string(), |pid| format!("process {}", pid));
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, format!("{:?} is in use by {}", data_dir, holder)));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(DataDirLock { _file: file })
+    }
+}
+
+/// Sends `state` (e.g. `"READY=1"`) to the service manager.
+/// Returns `Ok(false)` when not running under a notifying service manager.
+pub fn sd_notify(state: &str) -> io::Result<bool> {
+    let socket_path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(false),
+    };
+    debug!("sd_notify: {}", state);
+    send_notification(&socket_path, state)?;
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn send_notification(socket_path: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    let path_bytes = socket_path.as_bytes();
+    // A leading '@' names a Linux abstract socket
+    if let Some(name) = path_bytes.strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "abstract notify sockets require Linux"));
+        }
+    }
+    socket.send_to(state.as_bytes(), Path::new(socket_path))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notification(_socket_path: &std::ffi::OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "sd_notify requires a Unix platform"))
+}
+
+/// Tells the service manager the node is ready, logging instead of failing if that is impossible.
+pub fn notify_ready(status: &str) {
+    if let Err(e) = sd_notify(&format!("READY=1\nSTATUS={}", status)) {
+        warn!("Failed to send readiness notification: {}", e);
+    }
+}
+
+/// Tells the service manager the node is shutting down.
+pub fn notify_stopping() {
+    if let Err(e) = sd_notify("STOPPING=1") {
+        warn!("Failed to send stopping notification: {}", e);
+    }
+}
+
+/// Resolves on Ctrl-C or, on Unix, SIGTERM (what a service manager sends to stop the node).
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Cannot listen for SIGTERM, only Ctrl-C stops the node: {}", e),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Starts a shutdown for every [`Shutdown`] handle of its channel.
+#[derive(Debug)]
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+/// Lets a long-running task notice that the node is shutting down.
+#[derive(Debug, Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+/// A trigger and the handle (cloned for each task) that observes it.
+pub fn shutdown_channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(sender), Shutdown(receiver))
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once shutdown is triggered, or the trigger is dropped.
+    pub async fn wait(&self) {
+        let mut receiver = self.0.clone();
+        let _ = receiver.wait_for(|&triggered| triggered).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pid_file_lifecycle() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("node.pid");
+        // A stale file from a process that no longer exists is replaced
+        fs::write(&path, "4294967295\n").unwrap();
+        {
+            let pid_file = PidFile::create(&path).unwrap();
+            assert_eq!(fs::read_to_string(pid_file.path()).unwrap(), format!("{}\n", std::process::id()));
+        }
+        assert!(!path.exists());
+        assert_eq!(NodeError::Config("bad".to_string()).exit_code(), EXIT_CONFIG);
+    }
+
+    #[test]
+    fn test_data_dir_lock() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        {
+            let _lock = DataDirLock::acquire(&data_dir).unwrap();
+            let held = DataDirLock::acquire(&data_dir).unwrap_err();
+            assert_eq!(held.kind(), io::ErrorKind::WouldBlock);
+            assert!(held.to_string().contains(&format!("process {}", std::process::id())));
+        }
+        // Released on drop
+        DataDirLock::acquire(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+//!
+//! # Storage Backends
+//! The key-value engine underneath [`StorageManager`](super::StorageManager).
+//!
+//! `StorageManager` keeps all chain logic (key layout, indexes, migrations, caching)
+//! and talks to the engine only through [`ChainStore`]: point reads, ordered scans
+//! within a column family and atomic [`WriteBatch`]es across column families.
+//! RocksDB is the default engine (cargo feature `rocksdb`); the `sled` feature adds a
+//! pure-Rust engine for embedders who can't build or ship RocksDB.
+
+use super::StorageError;
+use std::fmt;
+use std::path::Path;
+
+/// Column family holding the flat prefixed keyspace of databases from before column families.
+pub const CF_DEFAULT: &str = "default";
+
+/// Scan order of [`ChainStore::iter_from`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Keys `>= start`, ascending.
+    Forward,
+    /// Keys `<= start`, descending.
+    Reverse,
+}
+
+/// Key-value pairs yielded by a scan.
+pub type KvIter<'a> = Box<dyn Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), StorageError>> + 'a>;
+
+/// Space taken by one column family, as estimated by the engine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColumnFamilyUsage {
+    pub size_bytes: u64,
+    pub estimated_keys: u64,
+}
+
+/// One write in a [`WriteBatch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    Put { cf: &'static str, key: Vec<u8>, value: Vec<u8> },
+    Delete { cf: &'static str, key: Vec<u8> },
+}
+
+/// Writes applied together by [`ChainStore::write`]: all of them or none.
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn put_cf(&mut self, cf: &'static str, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        self.ops.push(BatchOp::Put { cf, key: key.as_ref().to_vec(), value: value.as_ref().to_vec() });
+    }
+
+    pub fn delete_cf(&mut self, cf: &'static str, key: impl AsRef<[u8]>) {
+        self.ops.push(BatchOp::Delete { cf, key: key.as_ref().to_vec() });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+}
+
+/// An ordered key-value store with named column families, as needed by `StorageManager`.
+///
+/// Implementations must create every column family they are asked for on open and keep
+/// keys within a column family sorted bytewise.
+pub trait ChainStore: fmt::Debug + Send + Sync {
+    /// Name of the engine, for logs.
+    fn backend_name(&self) -> &'static str;
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Applies every operation of `batch` atomically.
+    fn write(&self, batch: WriteBatch) -> Result<(), StorageError>;
+
+    /// Scans `cf` from `start` in `direction`.
+    fn iter_from<'a>(&'a self, cf: &str, start: &[u8], direction: Direction) -> KvIter<'a>;
+
+    /// Writes a copy of the whole store to the directory `path`, which does not exist yet.
+    fn checkpoint(&self, path: &Path) -> Result<(), StorageError>;
+
+    /// Estimated size and key count of `cf`.
+    fn usage(&self, cf: &str) -> Result<ColumnFamilyUsage, StorageError>;
+
+    /// Persists everything written so far to disk, e.g. before the process exits.
+    fn flush(&self) -> Result<(), StorageError>;
+
+    fn put(&self, cf: &'static str, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        batch.put_cf(cf, key, value);
+        self.write(batch)
+    }
+}
+
+// Files each engine keeps at the top of its database directory
+const ROCKSDB_MARKER: &str = "CURRENT";
+const SLED_MARKER: &str = "conf";
+
+/// Whether `path` holds a database written by one of the supported engines.
+pub fn is_database_dir(path: &Path) -> bool {
+    path.join(ROCKSDB_MARKER).is_file() || path.join(SLED_MARKER).is_file()
+}
+
+/// Opens the store at `path` with the engine that wrote it, or creates one with the default
+/// engine: RocksDB if the `rocksdb` feature is on, sled otherwise.
+pub fn open(path: &Path, column_families: &[&'static str]) -> Result<Box<dyn ChainStore>, StorageError> {
+    let use_sled = path.join(SLED_MARKER).is_file() || (cfg!(not(feature = "rocksdb")) && !path.join(ROCKSDB_MARKER).is_file());
+    if use_sled {
+        #[cfg(feature = "sled")]
+        return Ok(Box::new(super::sled_store::SledStore::open(path, column_families)?));
+        #[cfg(not(feature = "sled"))]
+        return Err(StorageError::InvalidFormat(format!("{:?} is a sled database; rebuild with the `sled` feature", path)));
+    }
+    #[cfg(feature = "rocksdb")]
+    {
+        Ok(Box::new(super::rocksdb_store::RocksDbStore::open(path, column_families)?))
+    }
+    #[cfg(not(feature = "rocksdb"))]
+    {
+        Err(StorageError::InvalidFormat(format!("{:?} is a RocksDB database; rebuild with the `rocksdb` feature", path)))
+    }
+}
@@ -0,0 +1,121 @@
+//!
+//! # Block Cache
+//! Bounded least-recently-used cache for decoded blocks and headers.
+//!
+//! Blocks and headers are keyed by hash, and a hash always names the same content,
+//! so entries never go stale: reorgs only change which hash a height maps to, and
+//! that mapping is not cached. Only blocks deleted from storage have to be evicted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// Default number of blocks kept decoded in memory.
+pub const DEFAULT_BLOCK_CACHE_CAPACITY: usize = 1024;
+/// Headers are small, so many more of them are kept.
+pub const HEADER_CACHE_CAPACITY_FACTOR: usize = 8;
+
+/// A map holding at most `capacity` entries, evicting the least recently used one.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// Value and last-use tick per key.
+    entries: HashMap<K, (V, u64)>,
+    /// Keys by last-use tick, oldest first.
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    /// Creates an empty cache; a capacity of 0 disables caching.
+    pub fn new(capacity: usize) -> Self {
+        LruCache { capacity, entries: HashMap::new(), recency: BTreeMap::new(), tick: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Returns a copy of the value for `key`, marking it as most recently used.
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let tick = self.next_tick();
+        let (value, last_used) = self.entries.get_mut(key)?;
+        self.recency.remove(last_used);
+        *last_used = tick;
+        self.recency.insert(tick, key.clone());
+        Some(value.clone())
+    }
+
+    /// Inserts or replaces the value for `key`, evicting the least recently used entry if full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((_, last_used)) = self.entries.insert(key.clone(), (value, tick)) {
+            self.recency.remove(&last_used);
+        } else if self.entries.len() > self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.recency.insert(tick, key);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, last_used) = self.entries.remove(key)?;
+        self.recency.remove(&last_used);
+        Some(value)
+    }
+
+    /// Changes the capacity, evicting the least recently used entries that no longer fit.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            match self.recency.pop_first() {
+                Some((_, oldest)) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.get(&1), Some("one"));
+        cache.insert(3, "three");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("one"));
+        assert_eq!(cache.get(&3), Some("three"));
+        cache.insert(3, "THREE");
+        assert_eq!(cache.len(), 2);
+        cache.set_capacity(1);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&3), Some("THREE"));
+
+        let mut disabled = LruCache::new(0);
+        disabled.insert(1, "one");
+        assert!(disabled.is_empty());
+    }
+}
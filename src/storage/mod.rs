@@ -1,44 +1,436 @@
 //!
 //! # Storage Module
 //! Handles the persistence of blockchain data (blocks, etc.) to a local database.
-//! Currently uses RocksDB as the underlying key-value store.
+//! The key-value engine is pluggable (see [`backend`]): RocksDB by default, or sled
+//! with the `sled` cargo feature.
+//!
+//! Data is split into column families so each kind can be compacted, iterated
+//! and (later) pruned on its own: full blocks, headers, the height index, chain
+//! state, the transaction and address indexes, transaction events, compact block filters and node metadata. Databases written before
+//! column families existed keep everything in the default column family under
+//! one-byte key prefixes; they are migrated the first time they are opened.
+//! Decoded blocks and headers are kept in an LRU cache (see [`cache`]) so hot paths
+//! such as difficulty calculation and RPC reads don't hit the database every time.
+//! Blocks can be stored zstd-compressed (see [`compression`]).
+
+pub mod backend;
+pub mod cache;
+pub mod compression;
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb_store;
+#[cfg(feature = "sled")]
+pub mod sled_store;
+
+#[cfg(not(any(feature = "rocksdb", feature = "sled")))]
+compile_error!("enable a storage backend: the `rocksdb` (default) or `sled` feature");
 
-use crate::core::{Block, Hash, TokenMetadata}; // Import TokenMetadata
-use rocksdb::{Options, DB, WriteBatch, Error as RocksDbError, IteratorMode};
+use crate::core::{Address, Block, BlockFilter, BlockHeader, Event, Hash, LegacyTransaction, PublishedPointer, StorageDeal, TokenMetadata, Transaction, TransactionType}; // Import TokenMetadata
+use crate::core::events::transaction_events;
+use crate::chain_config::{ChainConfig, TxFeature};
+use crate::consensus::pos::{BlockSeal, StakeTable};
+use crate::consensus::{ConsensusMode, DifficultyAlgorithm, DifficultyBounds, InvalidBlockEvidence, PowAlgorithm, U256};
+use crate::network::acl::PayloadAcl;
+use crate::network::redundancy::RedundancyPolicy;
+use crate::network::retrieval::RetrievalEarnings;
+use crate::tenants::TenantUsage;
+use backend::{ChainStore, ColumnFamilyUsage, Direction, WriteBatch, CF_DEFAULT};
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
 use std::path::Path;
-use std::sync::Arc;
-use log::{error, info};
+use std::sync::{Arc, Mutex, RwLock};
+use compression::{BlockCodec, ENCODING_RAW};
+use cache::{LruCache, DEFAULT_BLOCK_CACHE_CAPACITY, HEADER_CACHE_CAPACITY_FACTOR};
+use log::{error, info, warn};
 
-// Define key prefixes for different data types in RocksDB
-const PREFIX_BLOCK: u8 = b'b'; // Key: PREFIX_BLOCK + block_hash => Value: serialized_block
-const PREFIX_HEIGHT_TO_HASH: u8 = b'h'; // Key: PREFIX_HEIGHT_TO_HASH + height (u64 BE) => Value: block_hash
+// Column families
+const CF_BLOCKS: &str = "blocks"; // Key: block_hash => Value: encoding byte + serialized_block (see compression)
+const CF_HEADERS: &str = "headers"; // Key: block_hash => Value: serialized BlockHeader
+const CF_HEIGHT_INDEX: &str = "height_index"; // Key: height (u64 BE) => Value: block_hash (main chain only)
+const CF_STATE: &str = "state"; // Chain state derived from blocks, keyed by the PREFIX_* below
+const CF_TX_INDEX: &str = "tx_index"; // Key: tx_hash => Value: location of the transaction in the main chain
+const CF_ADDRESS_INDEX: &str = "address_index"; // Key: address length (u8) + address + height (u64 BE) + position (u32 BE) => Value: tx_hash
+const CF_METADATA: &str = "metadata"; // Tip, height, chain config and other node bookkeeping (KEY_* below)
+const CF_TIME_INDEX: &str = "time_index"; // Key: timestamp (u64 BE) + height (u64 BE) => Value: empty (main chain only)
+const CF_EVENTS: &str = "events"; // Key: event key (height (u64 BE) + position (u32 BE) + index (u32 BE)) => Value: serialized StoredEvent (main chain only)
+const CF_EVENT_INDEX: &str = "event_index"; // Key: EVENT_BY_* + length (u8) + topic or address + event key => Value: empty
+const CF_BLOCK_FILTERS: &str = "block_filters"; // Key: block_hash => Value: serialized BlockFilter
+const COLUMN_FAMILIES: [&str; 11] = [CF_BLOCKS, CF_HEADERS, CF_HEIGHT_INDEX, CF_STATE, CF_TX_INDEX, CF_ADDRESS_INDEX, CF_METADATA, CF_TIME_INDEX, CF_EVENTS, CF_EVENT_INDEX, CF_BLOCK_FILTERS];
+
+// Define key prefixes for different data types in the state column family
 const PREFIX_TOKEN_METADATA: u8 = b't'; // Key: PREFIX_TOKEN_METADATA + token_hash => Value: serialized_token_metadata
+const PREFIX_STAKE: u8 = b's'; // Key: PREFIX_STAKE + address => Value: staked amount (u64 BE)
+const PREFIX_CHAIN_WORK: u8 = b'w'; // Key: PREFIX_CHAIN_WORK + block_hash => Value: cumulative chain work up to the block (U256 BE)
+const PREFIX_FAILED_BLOCK: u8 = b'f'; // Key: PREFIX_FAILED_BLOCK + block_hash => Value: empty (blocks that failed to connect, and their descendants)
+const PREFIX_INVALID_BLOCK: u8 = b'x'; // Key: PREFIX_INVALID_BLOCK + recorded_at (u64 BE) + block_hash => Value: serialized InvalidBlockEvidence
+const PREFIX_STORAGE_DEAL: u8 = b'd'; // Key: PREFIX_STORAGE_DEAL + data_hash + height (u64 BE) + position (u32 BE) => Value: serialized StorageDeal (main chain only)
+const PREFIX_POINTER: u8 = b'n'; // Key: PREFIX_POINTER + name + height (u64 BE) + position (u32 BE) => Value: serialized PublishedPointer (main chain only)
+const PREFIX_TENANT_USAGE: u8 = b'u'; // Key: PREFIX_TENANT_USAGE + tenant_id + 0x00 + month (YYYY-MM) => Value: serialized TenantUsage
+const PREFIX_RETRIEVAL_EARNINGS: u8 = b'r'; // Key: PREFIX_RETRIEVAL_EARNINGS + payload_hash => Value: serialized RetrievalEarnings
+const PREFIX_RETRIEVAL_PAYMENT: u8 = b'p'; // Key: PREFIX_RETRIEVAL_PAYMENT + payment tx_hash => Value: payload_hash it paid for
+const PREFIX_REDUNDANCY_POLICY: u8 = b'y'; // Key: PREFIX_REDUNDANCY_POLICY + payload_hash => Value: serialized RedundancyPolicy
+const PREFIX_PAYLOAD_ACL: u8 = b'a'; // Key: PREFIX_PAYLOAD_ACL + payload_hash => Value: serialized PayloadAcl
+const PREFIX_NONCE: u8 = b'o'; // Key: PREFIX_NONCE + length (u8) + sender + height (u64 BE) + position (u32 BE) => Value: nonce (u64 BE) (main chain only)
+// Key prefixes in the event index column family
+const EVENT_BY_TOPIC: u8 = b't';
+const EVENT_BY_ADDRESS: u8 = b'a';
+// Keys in the metadata column family
 const KEY_LAST_HASH: &[u8] = b"lh"; // Key: KEY_LAST_HASH => Value: last_block_hash
 const KEY_CHAIN_HEIGHT: &[u8] = b"ch"; // Key: KEY_CHAIN_HEIGHT => Value: current_height (u64 BE)
-/// Manages the interaction with the RocksDB database for blockchain storage.
+const KEY_CHAIN_CONFIG: &[u8] = b"cc"; // Key: KEY_CHAIN_CONFIG => Value: serialized ChainConfig (written at genesis)
+const KEY_FINALIZED_HEIGHT: &[u8] = b"fh"; // Key: KEY_FINALIZED_HEIGHT => Value: finalized_height (u64 BE)
+const KEY_TX_INDEX_COMPLETE: &[u8] = b"ti"; // Key: KEY_TX_INDEX_COMPLETE => Value: [1] while the tx index covers the whole main chain
+const KEY_SCHEMA_VERSION: &[u8] = b"sv"; // Key: KEY_SCHEMA_VERSION => Value: on-disk layout version (u32 BE)
+const KEY_BLOCK_DICTIONARY: &[u8] = b"bd"; // Key: KEY_BLOCK_DICTIONARY => Value: trained zstd dictionary for blocks
+// Prefixes used in the default column family before column families were introduced
+/// On-disk layout version written by this binary. Databases at an older version are
+/// upgraded on open by running [`MIGRATIONS`] in order; newer ones are refused.
+pub const SCHEMA_VERSION: u32 = 11;
+/// First schema version whose stored blocks start with an encoding byte.
+const TAGGED_BLOCKS_VERSION: u32 = 4;
+/// First schema version whose stored transactions carry a fee and a nonce.
+const TRANSACTION_FEES_VERSION: u32 = 8;
+/// First schema version whose stored blocks carry transaction witnesses.
+const BLOCK_WITNESSES_VERSION: u32 = 9;
+
+/// A step upgrading the layout from the version at its index to the next one.
+type Migration = (&'static str, fn(&StorageManager) -> Result<(), StorageError>);
+
+/// `MIGRATIONS[v]` upgrades a version `v` database to version `v + 1`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    ("move the flat prefixed keyspace into column families", StorageManager::migrate_legacy_keyspace),
+    ("build the address index", StorageManager::backfill_address_index),
+    ("build the block time index", StorageManager::backfill_time_index),
+    ("tag stored blocks with their encoding", StorageManager::tag_block_encodings),
+    ("add difficulty bounds and genesis time to the chain config", StorageManager::extend_chain_config),
+    ("record transaction events", StorageManager::backfill_events),
+    ("build compact block filters", StorageManager::backfill_block_filters),
+    ("add fees and nonces to stored transactions", StorageManager::add_transaction_fees),
+    ("add transaction witnesses to stored blocks", StorageManager::add_block_witnesses),
+    ("index sender nonces", StorageManager::backfill_nonces),
+    ("drop HTLCs from the chain config features", StorageManager::drop_htlc_feature),
+];
+
+const LEGACY_PREFIX_BLOCK: u8 = b'b';
+const LEGACY_PREFIX_HEIGHT_TO_HASH: u8 = b'h';
+
+/// Chain config as recorded before schema version 5.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LegacyChainConfig {
+    consensus_mode: ConsensusMode,
+    pow_algorithm: PowAlgorithm,
+    difficulty_algorithm: DifficultyAlgorithm,
+    initial_stakes: BTreeMap<Address, u64>,
+    transaction_version_activations: BTreeMap<u32, u64>,
+    feature_activations: BTreeMap<LegacyTxFeature, u64>,
+    finality_depth: u64,
+    max_tx_data_size: u64,
+    max_block_data_size: u64,
+}
+
+/// Chain config as recorded from schema version 5 to 10, with HTLCs among the features.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HtlcChainConfig {
+    consensus_mode: ConsensusMode,
+    pow_algorithm: PowAlgorithm,
+    difficulty_algorithm: DifficultyAlgorithm,
+    initial_stakes: BTreeMap<Address, u64>,
+    transaction_version_activations: BTreeMap<u32, u64>,
+    feature_activations: BTreeMap<LegacyTxFeature, u64>,
+    finality_depth: u64,
+    max_tx_data_size: u64,
+    max_block_data_size: u64,
+    difficulty_bounds: DifficultyBounds,
+    genesis_timestamp: Option<u64>,
+}
+
+/// Gated transaction kinds as recorded before schema version 11, which listed HTLCs though no
+/// transaction kind ever implemented them.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LegacyTxFeature {
+    Tokens,
+    StorageDeals,
+    Htlc,
+    Staking,
+    Pointers,
+}
+
+impl LegacyTxFeature {
+    fn upgrade(self) -> Option<TxFeature> {
+        match self {
+            LegacyTxFeature::Tokens => Some(TxFeature::Tokens),
+            LegacyTxFeature::StorageDeals => Some(TxFeature::StorageDeals),
+            LegacyTxFeature::Htlc => None,
+            LegacyTxFeature::Staking => Some(TxFeature::Staking),
+            LegacyTxFeature::Pointers => Some(TxFeature::Pointers),
+        }
+    }
+}
+
+/// Block as stored before schema version 8, with transactions lacking a fee and a nonce.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LegacyBlock {
+    header: BlockHeader,
+    transactions: Vec<LegacyTransaction>,
+    seal: Option<BlockSeal>,
+}
+
+impl From<LegacyBlock> for Block {
+    fn from(legacy: LegacyBlock) -> Self {
+        let transactions: Vec<Transaction> = legacy.transactions.into_iter().map(Into::into).collect();
+        Block { header: legacy.header, witnesses: vec![None; transactions.len()], transactions, seal: legacy.seal }
+    }
+}
+
+/// Block as stored at schema version 8, before blocks carried transaction witnesses.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct UnwitnessedBlock {
+    header: BlockHeader,
+    transactions: Vec<Transaction>,
+    seal: Option<BlockSeal>,
+}
+
+impl From<UnwitnessedBlock> for Block {
+    fn from(unwitnessed: UnwitnessedBlock) -> Self {
+        let witnesses = vec![None; unwitnessed.transactions.len()];
+        Block { header: unwitnessed.header, transactions: unwitnessed.transactions, witnesses, seal: unwitnessed.seal }
+    }
+}
+
+/// Disk usage reported by [`StorageManager::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageStats {
+    pub backend: &'static str,
+    pub column_families: Vec<(&'static str, ColumnFamilyUsage)>,
+    /// Files and bytes in the off-chain payload directory, if one was given.
+    pub offchain: Option<DirectoryUsage>,
+}
+
+impl StorageStats {
+    /// Total size of the column families.
+    pub fn database_size_bytes(&self) -> u64 {
+        self.column_families.iter().map(|(_, usage)| usage.size_bytes).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirectoryUsage {
+    pub files: u64,
+    pub size_bytes: u64,
+}
+
+/// Stores the blockchain in a [`ChainStore`].
 #[derive(Debug, Clone)] // Clone is cheap due to Arc
 pub struct StorageManager {
-    db: Arc<DB>,
+    store: Arc<dyn ChainStore>,
+    /// Maintain the transaction index (`CF_TX_INDEX`) when saving blocks.
+    tx_index: bool,
+    /// Recently read blocks and headers by hash, shared between clones.
+    block_cache: Arc<Mutex<LruCache<Hash, Block>>>,
+    header_cache: Arc<Mutex<LruCache<Hash, BlockHeader>>>,
+    /// Encoding of stored blocks, shared between clones.
+    codec: Arc<RwLock<BlockCodec>>,
+    /// Schema version whose layout the stored blocks are in; behind [`SCHEMA_VERSION`] only while
+    /// migrations run, so they can read blocks written by older versions.
+    block_format: u32,
 }
 
-// Custom error type to wrap RocksDB and other potential storage errors
+/// Where a transaction sits in the main chain, as recorded by the transaction index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxLocation {
+    pub block_hash: Hash,
+    /// Index of the transaction within the block.
+    pub position: u32,
+}
+
+impl TxLocation {
+    fn to_bytes(self) -> [u8; 36] {
+        let mut bytes = [0u8; 36];
+        bytes[..32].copy_from_slice(&self.block_hash);
+        bytes[32..].copy_from_slice(&self.position.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        if bytes.len() != 36 {
+            return Err(StorageError::InvalidFormat(format!("Invalid tx index entry length ({})", bytes.len())));
+        }
+        let mut block_hash = [0u8; 32];
+        block_hash.copy_from_slice(&bytes[..32]);
+        let position = u32::from_be_bytes(bytes[32..].try_into().expect("slice is 4 bytes"));
+        Ok(TxLocation { block_hash, position })
+    }
+}
+
+/// A main-chain transaction touching an address, as recorded by the address index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressTxRef {
+    pub tx_hash: Hash,
+    pub height: u64,
+    /// Index of the transaction within its block.
+    pub position: u32,
+}
+
+// Decodes an address index entry from the key suffix after the address (height + position) and the value
+fn decode_address_entry(address: &Address, suffix: &[u8], value: &[u8]) -> Result<AddressTxRef, StorageError> {
+    if suffix.len() != 12 || value.len() != 32 {
+        return Err(StorageError::InvalidFormat(format!("Invalid address index entry for {}", hex::encode(address))));
+    }
+    let mut tx_hash = [0u8; 32];
+    tx_hash.copy_from_slice(value);
+    Ok(AddressTxRef {
+        tx_hash,
+        height: u64::from_be_bytes(suffix[..8].try_into().expect("slice is 8 bytes")),
+        position: u32::from_be_bytes(suffix[8..].try_into().expect("slice is 4 bytes")),
+    })
+}
+
+/// A main-chain transaction event, with where it was emitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventRecord {
+    pub height: u64,
+    /// Index of the emitting transaction within its block.
+    pub position: u32,
+    /// Index of the event among those of its transaction.
+    pub index: u32,
+    pub tx_hash: Hash,
+    pub event: Event,
+}
+
+impl EventRecord {
+    /// The (height, position, index) that orders events along the chain.
+    pub fn locator(&self) -> (u64, u32, u32) {
+        (self.height, self.position, self.index)
+    }
+}
+
+/// Which events [`StorageManager::get_events`] returns; unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    pub topic: Option<String>,
+    pub address: Option<Address>,
+    pub from_height: u64,
+    pub to_height: Option<u64>,
+}
+
+// What CF_EVENTS holds for an event
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredEvent {
+    tx_hash: Hash,
+    event: Event,
+}
+
+fn event_key((height, position, index): (u64, u32, u32)) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&height.to_be_bytes());
+    key[8..12].copy_from_slice(&position.to_be_bytes());
+    key[12..].copy_from_slice(&index.to_be_bytes());
+    key
+}
+
+fn event_index_prefix(kind: u8, value: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(2 + value.len());
+    prefix.push(kind);
+    prefix.push(value.len() as u8);
+    prefix.extend_from_slice(value);
+    prefix
+}
+
+/// Lowercases and validates a hex hash prefix (1 to 64 hex digits).
+pub fn normalize_hash_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.to_ascii_lowercase();
+    if prefix.is_empty() || prefix.len() > 64 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(prefix)
+}
+
+/// Smallest key whose hex encoding starts with `hex_prefix` (already normalized).
+fn hash_prefix_lower_bound(hex_prefix: &str) -> Vec<u8> {
+    let mut padded = hex_prefix.to_string();
+    if padded.len() % 2 == 1 {
+        padded.push('0');
+    }
+    hex::decode(padded).expect("prefix is validated hex")
+}
+
+/// Converts a height range into inclusive bounds; an empty range yields `start > end`.
+fn inclusive_height_bounds(range: impl RangeBounds<u64>) -> (u64, u64) {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => Some(start),
+        Bound::Excluded(&start) => start.checked_add(1),
+        Bound::Unbounded => Some(0),
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => Some(end),
+        Bound::Excluded(&end) => end.checked_sub(1),
+        Bound::Unbounded => Some(u64::MAX),
+    };
+    match (start, end) {
+        (Some(start), Some(end)) => (start, end),
+        _ => (1, 0),
+    }
+}
+
+/// Recursively copies the directory `from` to `to` (sled databases have subdirectories).
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+        } else {
+            std::fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+// Counts the files under `dir`, recursively, and their total size
+fn directory_usage(dir: &Path) -> Result<DirectoryUsage, StorageError> {
+    let mut usage = DirectoryUsage::default();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let nested = directory_usage(&entry.path())?;
+            usage.files += nested.files;
+            usage.size_bytes += nested.size_bytes;
+        } else {
+            usage.files += 1;
+            usage.size_bytes += entry.metadata()?.len();
+        }
+    }
+    Ok(usage)
+}
+
+fn time_index_key(header: &BlockHeader) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&header.timestamp.to_be_bytes());
+    key[8..].copy_from_slice(&header.height.to_be_bytes());
+    key
+}
+
+fn address_key_prefix(address: &Address) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(1 + address.len());
+    prefix.push(address.len() as u8);
+    prefix.extend_from_slice(address);
+    prefix
+}
+
+// Custom error type to wrap database engine and other potential storage errors
 #[derive(Debug, thiserror::Error)]
 pub enum StorageError {
-    #[error("RocksDB error: {0}")]
-    Database(RocksDbError), // Removed #[from]
+    #[error("Database error: {0}")]
+    Database(String), // Engine errors, converted in each backend module
     #[error("Serialization error: {0}")]
     Serialization(bincode::Error), // Removed #[from]
     #[error("Deserialization error: {0}")]
     Deserialization(bincode::Error), // Removed #[from]
     #[error("Invalid data format: {0}")]
     InvalidFormat(String),
-}
-
-// Add explicit From<RocksDbError> impl
-impl From<RocksDbError> for StorageError {
-    fn from(err: RocksDbError) -> Self {
-        StorageError::Database(err)
-    }
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database schema version {found} is newer than this binary supports ({supported})")]
+    UnsupportedSchema { found: u32, supported: u32 },
 }
 
 // Add explicit From<bincode::Error> impl
@@ -53,69 +445,1023 @@ impl From<bincode::Error> for StorageError {
 }
 
 impl StorageManager {
-    /// Opens or creates a RocksDB database at the specified path.
+    /// Opens the database at the specified path, or creates one with the default backend.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        // Use path.as_ref() to pass a reference
-        // Use '?' now that From<RocksDbError> is implemented manually
-        let db = DB::open(&opts, path.as_ref())?;
-        info!("RocksDB database opened successfully at {:?}", path.as_ref());
-        Ok(StorageManager { db: Arc::new(db) })
+        Self::with_store(backend::open(path.as_ref(), &COLUMN_FAMILIES)?)
+    }
+
+    /// Uses an already opened store, which must provide the column families `StorageManager` needs.
+    pub fn with_store(store: Box<dyn ChainStore>) -> Result<Self, StorageError> {
+        let mut storage = StorageManager {
+            store: Arc::from(store),
+            tx_index: false,
+            block_cache: Arc::new(Mutex::new(LruCache::new(DEFAULT_BLOCK_CACHE_CAPACITY))),
+            header_cache: Arc::new(Mutex::new(LruCache::new(DEFAULT_BLOCK_CACHE_CAPACITY * HEADER_CACHE_CAPACITY_FACTOR))),
+            codec: Arc::new(RwLock::new(BlockCodec::default())),
+            block_format: SCHEMA_VERSION,
+        };
+        // Migrations decode stored blocks, which may be compressed with the dictionary
+        let dictionary = storage.store.get(CF_METADATA, KEY_BLOCK_DICTIONARY)?;
+        storage.codec.write().expect("Block codec lock poisoned").dictionary = dictionary;
+        storage.block_format = storage.schema_version()?;
+        storage.migrate_schema()?;
+        storage.block_format = SCHEMA_VERSION;
+        Ok(storage)
+    }
+
+    /// On-disk layout version of the open database; 0 for databases written before versioning.
+    pub fn schema_version(&self) -> Result<u32, StorageError> {
+        match self.store.get(CF_METADATA, KEY_SCHEMA_VERSION)? {
+            Some(bytes) => {
+                let bytes: [u8; 4] = bytes.as_slice().try_into()
+                    .map_err(|_| StorageError::InvalidFormat("Invalid schema version length".to_string()))?;
+                Ok(u32::from_be_bytes(bytes))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Brings the database up to [`SCHEMA_VERSION`], recording the version after each step
+    /// so an interrupted upgrade resumes where it stopped.
+    fn migrate_schema(&mut self) -> Result<(), StorageError> {
+        let found = self.schema_version()?;
+        if found > SCHEMA_VERSION {
+            return Err(StorageError::UnsupportedSchema { found, supported: SCHEMA_VERSION });
+        }
+        if found == 0 && self.is_empty()? {
+            // A new database is created at the current layout
+            self.store.put(CF_METADATA, KEY_SCHEMA_VERSION, &SCHEMA_VERSION.to_be_bytes())?;
+            return Ok(());
+        }
+        for (version, (description, migrate)) in MIGRATIONS.iter().enumerate().skip(found as usize) {
+            let target = version as u32 + 1;
+            info!("Migrating database schema to version {}: {}", target, description);
+            migrate(self)?;
+            self.store.put(CF_METADATA, KEY_SCHEMA_VERSION, &target.to_be_bytes())?;
+            self.block_format = target;
+        }
+        Ok(())
+    }
+
+    /// True if neither the legacy keyspace nor any column family holds data.
+    fn is_empty(&self) -> Result<bool, StorageError> {
+        if self.store.iter_from(CF_DEFAULT, &[], Direction::Forward).next().transpose()?.is_some() {
+            return Ok(false);
+        }
+        for name in COLUMN_FAMILIES {
+            if self.store.iter_from(name, &[], Direction::Forward).next().transpose()?.is_some() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Persists all writes to disk; see [`ChainStore::flush`].
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.store.flush()
+    }
+
+    /// Reports the size of every column family and, given the off-chain payload directory, its usage too.
+    pub fn stats(&self, offchain_dir: Option<&Path>) -> Result<StorageStats, StorageError> {
+        let column_families = COLUMN_FAMILIES.iter()
+            .map(|cf| Ok((*cf, self.store.usage(cf)?)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let offchain = offchain_dir.map(directory_usage).transpose()?;
+        Ok(StorageStats { backend: self.store.backend_name(), column_families, offchain })
+    }
+
+    /// Writes a consistent point-in-time copy of the database to `path`, which must not exist yet.
+    /// Safe while the node is running; on the same filesystem the files are hard links, so it is cheap.
+    pub fn create_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), StorageError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(StorageError::InvalidFormat(format!("Snapshot path {:?} already exists", path)));
+        }
+        self.store.checkpoint(path)?;
+        info!("Created {} database snapshot at {:?}", self.store.backend_name(), path);
+        Ok(())
+    }
+
+    /// Initializes the database directory `target` from a snapshot made by `create_snapshot`.
+    /// Refuses to overwrite an existing database. The restored database is opened once to check it.
+    pub fn restore_from_snapshot<P: AsRef<Path>, Q: AsRef<Path>>(snapshot: P, target: Q) -> Result<(), StorageError> {
+        let (snapshot, target) = (snapshot.as_ref(), target.as_ref());
+        if !backend::is_database_dir(snapshot) {
+            return Err(StorageError::InvalidFormat(format!("{:?} is not a database snapshot", snapshot)));
+        }
+        if backend::is_database_dir(target) {
+            return Err(StorageError::InvalidFormat(format!("{:?} already contains a database", target)));
+        }
+        copy_dir(snapshot, target)?;
+        let restored = StorageManager::new(target)?;
+        info!("Restored database snapshot {:?} into {:?} (height {:?})", snapshot, target, restored.get_chain_height()?);
+        Ok(())
+    }
+
+    /// Moves keys from the single prefixed keyspace of older databases into the column families (schema version 1).
+    fn migrate_legacy_keyspace(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        let mut migrated = 0usize;
+        for item in self.store.iter_from(CF_DEFAULT, &[], Direction::Forward) {
+            let (key, value) = item?;
+            match key.first() {
+                Some(&LEGACY_PREFIX_BLOCK) => {
+                    let block: LegacyBlock = bincode::deserialize(&value)?;
+                    batch.put_cf(CF_BLOCKS, &key[1..], &value);
+                    batch.put_cf(CF_HEADERS, &key[1..], bincode::serialize(&block.header).map_err(StorageError::Serialization)?);
+                }
+                Some(&LEGACY_PREFIX_HEIGHT_TO_HASH) => batch.put_cf(CF_HEIGHT_INDEX, &key[1..], &value),
+                _ if [KEY_LAST_HASH, KEY_CHAIN_HEIGHT, KEY_CHAIN_CONFIG, KEY_FINALIZED_HEIGHT].contains(&key.as_ref()) => {
+                    batch.put_cf(CF_METADATA, &key, &value)
+                }
+                _ => batch.put_cf(CF_STATE, &key, &value),
+            }
+            batch.delete_cf(CF_DEFAULT, &key);
+            migrated += 1;
+        }
+        if migrated > 0 {
+            self.store.write(batch)?;
+            info!("Migrated {} keys from the legacy keyspace into column families", migrated);
+        }
+        Ok(())
+    }
+
+    /// Stores blocks written from now on zstd-compressed at `level`, or raw with `None`.
+    /// Blocks already stored keep their encoding and stay readable either way.
+    pub fn set_block_compression(&self, level: Option<i32>) {
+        self.codec.write().expect("Block codec lock poisoned").level = level;
+    }
+
+    pub fn block_dictionary_trained(&self) -> bool {
+        self.codec.read().expect("Block codec lock poisoned").dictionary.is_some()
+    }
+
+    /// Trains a compression dictionary of at most `max_size` bytes from up to `samples` of the
+    /// latest main-chain blocks and compresses later blocks with it. Returns `false` without
+    /// training if the database already has a dictionary.
+    pub fn train_block_dictionary(&self, samples: u64, max_size: usize) -> Result<bool, StorageError> {
+        if self.block_dictionary_trained() {
+            return Ok(false);
+        }
+        let tip = self.get_chain_height()?.ok_or_else(|| StorageError::InvalidFormat("No blocks to train a dictionary on".to_string()))?;
+        let sample_blocks = self.iter_blocks(tip.saturating_sub(samples.saturating_sub(1))..=tip)
+            .map(|block| Ok(bincode::serialize(&block?)?))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let dictionary = compression::train_dictionary(&sample_blocks, max_size)?;
+        self.store.put(CF_METADATA, KEY_BLOCK_DICTIONARY, &dictionary)?;
+        info!("Trained a {} byte block compression dictionary on {} blocks", dictionary.len(), sample_blocks.len());
+        self.codec.write().expect("Block codec lock poisoned").dictionary = Some(dictionary);
+        Ok(true)
+    }
+
+    /// Re-records the chain config with the default difficulty bounds and no fixed genesis time,
+    /// which is what chains created before schema version 5 ran with.
+    fn extend_chain_config(&self) -> Result<(), StorageError> {
+        let Some(serialized_config) = self.store.get(CF_METADATA, KEY_CHAIN_CONFIG)? else {
+            return Ok(());
+        };
+        let legacy: LegacyChainConfig = bincode::deserialize(&serialized_config)?;
+        // Written in the version 5 layout, which a later migration reads
+        let extended = HtlcChainConfig {
+            consensus_mode: legacy.consensus_mode,
+            pow_algorithm: legacy.pow_algorithm,
+            difficulty_algorithm: legacy.difficulty_algorithm,
+            initial_stakes: legacy.initial_stakes,
+            transaction_version_activations: legacy.transaction_version_activations,
+            feature_activations: legacy.feature_activations,
+            finality_depth: legacy.finality_depth,
+            max_tx_data_size: legacy.max_tx_data_size,
+            max_block_data_size: legacy.max_block_data_size,
+            difficulty_bounds: DifficultyBounds::default(),
+            genesis_timestamp: None,
+        };
+        self.store.put(CF_METADATA, KEY_CHAIN_CONFIG, &bincode::serialize(&extended).map_err(StorageError::Serialization)?)
+    }
+
+    /// Removes HTLCs, which no transaction kind implements, from the features of the chain config
+    /// (schema version 11).
+    fn drop_htlc_feature(&self) -> Result<(), StorageError> {
+        let Some(serialized_config) = self.store.get(CF_METADATA, KEY_CHAIN_CONFIG)? else {
+            return Ok(());
+        };
+        let legacy: HtlcChainConfig = bincode::deserialize(&serialized_config)?;
+        self.save_chain_config(&ChainConfig {
+            consensus_mode: legacy.consensus_mode,
+            pow_algorithm: legacy.pow_algorithm,
+            difficulty_algorithm: legacy.difficulty_algorithm,
+            initial_stakes: legacy.initial_stakes,
+            transaction_version_activations: legacy.transaction_version_activations,
+            feature_activations: legacy.feature_activations.into_iter()
+                .filter_map(|(feature, height)| Some((feature.upgrade()?, height)))
+                .collect(),
+            finality_depth: legacy.finality_depth,
+            max_tx_data_size: legacy.max_tx_data_size,
+            max_block_data_size: legacy.max_block_data_size,
+            difficulty_bounds: legacy.difficulty_bounds,
+            genesis_timestamp: legacy.genesis_timestamp,
+        })
+    }
+
+    /// Prefixes every stored block with the raw encoding byte (schema version 4).
+    fn tag_block_encodings(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for item in self.store.iter_from(CF_BLOCKS, &[], Direction::Forward) {
+            let (key, value) = item?;
+            batch.put_cf(CF_BLOCKS, key, [&[ENCODING_RAW], value.as_ref()].concat());
+        }
+        self.store.write(batch)
+    }
+
+    /// Sets how many decoded blocks are cached (headers get [`HEADER_CACHE_CAPACITY_FACTOR`] times as many).
+    /// 0 disables the caches.
+    pub fn set_block_cache_capacity(&self, blocks: usize) {
+        self.block_cache.lock().expect("Block cache lock poisoned").set_capacity(blocks);
+        self.header_cache.lock().expect("Header cache lock poisoned").set_capacity(blocks.saturating_mul(HEADER_CACHE_CAPACITY_FACTOR));
+    }
+
+    /// Turns maintenance of the transaction index on or off for blocks saved from now on.
+    pub fn set_tx_index(&mut self, enabled: bool) {
+        self.tx_index = enabled;
+    }
+
+    pub fn tx_index_enabled(&self) -> bool {
+        self.tx_index
+    }
+
+    /// Whether the transaction index covers every main-chain block.
+    /// Saving a block with the index disabled leaves it incomplete until `reindex_transactions`.
+    pub fn is_tx_index_complete(&self) -> Result<bool, StorageError> {
+        Ok(self.store.get(CF_METADATA, KEY_TX_INDEX_COMPLETE)?.is_some())
+    }
+
+    /// Rebuilds the transaction index from the main chain and marks it complete.
+    pub fn reindex_transactions(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for item in self.store.iter_from(CF_TX_INDEX, &[], Direction::Forward) {
+            let (key, _) = item?;
+            batch.delete_cf(CF_TX_INDEX, key);
+        }
+        let mut height = 0;
+        for block in self.iter_blocks(..) {
+            self.index_transactions(&mut batch, &block?);
+            height += 1;
+        }
+        batch.put_cf(CF_METADATA, KEY_TX_INDEX_COMPLETE, [1u8]);
+        self.store.write(batch)?;
+        info!("Rebuilt transaction index over {} blocks", height);
+        Ok(())
+    }
+
+    fn index_transactions(&self, batch: &mut WriteBatch, block: &Block) {
+        let block_hash = block.hash();
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let location = TxLocation { block_hash, position: position as u32 };
+            batch.put_cf(CF_TX_INDEX, tx.calculate_hash(), location.to_bytes());
+        }
+    }
+
+    /// Builds the address index over the existing main chain (schema version 2).
+    fn backfill_address_index(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        let mut height = 0;
+        for block in self.iter_blocks(..) {
+            self.index_addresses(&mut batch, &block?);
+            height += 1;
+        }
+        self.store.write(batch)?;
+        if height > 0 {
+            info!("Built address index over {} blocks", height);
+        }
+        Ok(())
+    }
+
+    /// Records the events of the existing main chain (schema version 6).
+    fn backfill_events(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for block in self.iter_blocks(..) {
+            self.index_events(&mut batch, &block?)?;
+        }
+        self.store.write(batch)
+    }
+
+    /// Builds the filters of the existing main chain (schema version 7). Side blocks stored
+    /// before then have none.
+    fn backfill_block_filters(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for block in self.iter_blocks(..) {
+            self.put_block_filter(&mut batch, &block?)?;
+        }
+        self.store.write(batch)
+    }
+
+    /// Re-encodes every stored block with a zero fee and no nonce on its transactions (schema version 8).
+    /// That changes the transaction hashes, so the main-chain entries keyed or valued by them are rewritten too.
+    fn add_transaction_fees(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        let mut blocks = 0usize;
+        for item in self.store.iter_from(CF_BLOCKS, &[], Direction::Forward) {
+            let (key, _) = item?;
+            let hash: Hash = key.as_ref().try_into()
+                .map_err(|_| StorageError::InvalidFormat("Invalid block key length".to_string()))?;
+            let block = self.get_block_by_hash(&hash)?
+                .ok_or_else(|| StorageError::InvalidFormat(format!("Block {} disappeared during migration", hex::encode(hash))))?;
+            // Written in the version 8 layout, which the next migration reads
+            let unwitnessed = UnwitnessedBlock { header: block.header, transactions: block.transactions, seal: block.seal };
+            batch.put_cf(CF_BLOCKS, hash, self.encode_block_bytes(&bincode::serialize(&unwitnessed).map_err(StorageError::Serialization)?)?);
+            blocks += 1;
+        }
+        let tx_index_complete = self.is_tx_index_complete()?;
+        for item in self.store.iter_from(CF_TX_INDEX, &[], Direction::Forward) {
+            let (key, _) = item?;
+            batch.delete_cf(CF_TX_INDEX, key);
+        }
+        for block in self.iter_blocks(..) {
+            let block = block?;
+            self.index_addresses(&mut batch, &block);
+            self.index_events(&mut batch, &block)?;
+            self.index_storage_deals(&mut batch, &block)?;
+            self.index_pointers(&mut batch, &block)?;
+            if tx_index_complete {
+                self.index_transactions(&mut batch, &block);
+            }
+        }
+        self.store.write(batch)?;
+        if blocks > 0 {
+            info!("Re-encoded {} blocks with transaction fees and nonces", blocks);
+        }
+        Ok(())
+    }
+
+    /// Re-encodes every stored block with an empty witness slot per transaction (schema version 9).
+    /// Witnesses are not part of transaction or block hashes, so no index changes.
+    fn add_block_witnesses(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for item in self.store.iter_from(CF_BLOCKS, &[], Direction::Forward) {
+            let (key, _) = item?;
+            let hash: Hash = key.as_ref().try_into()
+                .map_err(|_| StorageError::InvalidFormat("Invalid block key length".to_string()))?;
+            let block = self.get_block_by_hash(&hash)?
+                .ok_or_else(|| StorageError::InvalidFormat(format!("Block {} disappeared during migration", hex::encode(hash))))?;
+            batch.put_cf(CF_BLOCKS, hash, self.encode_block(&block)?);
+        }
+        self.store.write(batch)
+    }
+
+    /// Builds the timestamp index over the existing main chain (schema version 3).
+    fn backfill_time_index(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for header in self.iter_headers(..) {
+            batch.put_cf(CF_TIME_INDEX, time_index_key(&header?), b"");
+        }
+        self.store.write(batch)
+    }
+
+    fn address_index_keys(block: &Block) -> Vec<(Vec<u8>, Hash)> {
+        let height = block.header.height;
+        let mut keys = Vec::new();
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let tx_hash = tx.calculate_hash();
+            let mut addresses = vec![tx.sender()];
+            if let Some(receiver) = tx.receiver() {
+                // A transfer to oneself is recorded once
+                if receiver != tx.sender() {
+                    addresses.push(receiver);
+                }
+            }
+            for address in addresses {
+                let mut key = address_key_prefix(address);
+                key.extend_from_slice(&height.to_be_bytes());
+                key.extend_from_slice(&(position as u32).to_be_bytes());
+                keys.push((key, tx_hash));
+            }
+        }
+        keys
+    }
+
+    fn index_addresses(&self, batch: &mut WriteBatch, block: &Block) {
+        for (key, tx_hash) in Self::address_index_keys(block) {
+            batch.put_cf(CF_ADDRESS_INDEX, key, tx_hash);
+        }
+    }
+
+    // Events of `block` with their keys in CF_EVENTS and in the event index
+    fn block_events(block: &Block) -> Vec<([u8; 16], Vec<Vec<u8>>, StoredEvent)> {
+        let mut events = Vec::new();
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let tx_hash = tx.calculate_hash();
+            for (index, event) in transaction_events(tx).into_iter().enumerate() {
+                let key = event_key((block.header.height, position as u32, index as u32));
+                let mut index_keys = vec![[event_index_prefix(EVENT_BY_TOPIC, event.topic.as_bytes()), key.to_vec()].concat()];
+                for address in &event.addresses {
+                    index_keys.push([event_index_prefix(EVENT_BY_ADDRESS, address), key.to_vec()].concat());
+                }
+                events.push((key, index_keys, StoredEvent { tx_hash, event }));
+            }
+        }
+        events
+    }
+
+    fn index_events(&self, batch: &mut WriteBatch, block: &Block) -> Result<(), StorageError> {
+        for (key, index_keys, stored) in Self::block_events(block) {
+            batch.put_cf(CF_EVENTS, key, bincode::serialize(&stored).map_err(StorageError::Serialization)?);
+            for index_key in index_keys {
+                batch.put_cf(CF_EVENT_INDEX, index_key, b"");
+            }
+        }
+        Ok(())
+    }
+
+    fn unindex_events(batch: &mut WriteBatch, block: &Block) {
+        for (key, index_keys, _) in Self::block_events(block) {
+            batch.delete_cf(CF_EVENTS, key);
+            for index_key in index_keys {
+                batch.delete_cf(CF_EVENT_INDEX, index_key);
+            }
+        }
+    }
+
+    fn put_block_filter(&self, batch: &mut WriteBatch, block: &Block) -> Result<(), StorageError> {
+        let filter = BlockFilter::for_block(block);
+        batch.put_cf(CF_BLOCK_FILTERS, block.hash(), bincode::serialize(&filter).map_err(StorageError::Serialization)?);
+        Ok(())
+    }
+
+    // Deals made by the transactions of `block`, with their keys in the state column family
+    fn block_storage_deals(block: &Block) -> Vec<(Vec<u8>, StorageDeal)> {
+        let height = block.header.height;
+        block.transactions.iter().enumerate().filter_map(|(position, tx)| {
+            let deal = StorageDeal::from_transaction(tx, height)?;
+            let key = [&[PREFIX_STORAGE_DEAL], deal.data_hash.as_slice(), &height.to_be_bytes(), &(position as u32).to_be_bytes()].concat();
+            Some((key, deal))
+        }).collect()
+    }
+
+    fn index_storage_deals(&self, batch: &mut WriteBatch, block: &Block) -> Result<(), StorageError> {
+        for (key, deal) in Self::block_storage_deals(block) {
+            batch.put_cf(CF_STATE, key, bincode::serialize(&deal).map_err(StorageError::Serialization)?);
+        }
+        Ok(())
+    }
+
+    fn unindex_storage_deals(batch: &mut WriteBatch, block: &Block) {
+        for (key, _) in Self::block_storage_deals(block) {
+            batch.delete_cf(CF_STATE, key);
+        }
+    }
+
+    // Pointer records published by the transactions of `block`, with their keys in the state column family
+    fn block_pointers(block: &Block) -> Vec<(Vec<u8>, PublishedPointer)> {
+        let height = block.header.height;
+        block.transactions.iter().enumerate().filter_map(|(position, tx)| {
+            let pointer = PublishedPointer::from_transaction(tx, height)?;
+            let key = [&[PREFIX_POINTER], pointer.record.name.as_slice(), &height.to_be_bytes(), &(position as u32).to_be_bytes()].concat();
+            Some((key, pointer))
+        }).collect()
+    }
+
+    fn index_pointers(&self, batch: &mut WriteBatch, block: &Block) -> Result<(), StorageError> {
+        for (key, pointer) in Self::block_pointers(block) {
+            batch.put_cf(CF_STATE, key, bincode::serialize(&pointer).map_err(StorageError::Serialization)?);
+        }
+        Ok(())
+    }
+
+    fn unindex_pointers(batch: &mut WriteBatch, block: &Block) {
+        for (key, _) in Self::block_pointers(block) {
+            batch.delete_cf(CF_STATE, key);
+        }
+    }
+
+    // Nonces used by the transactions of `block`, with their keys in the state column family
+    fn block_nonces(block: &Block) -> Vec<(Vec<u8>, u64)> {
+        let height = block.header.height;
+        block.transactions.iter().enumerate().filter_map(|(position, tx)| {
+            let nonce = tx.nonce()?;
+            let key = [&[PREFIX_NONCE], address_key_prefix(tx.sender()).as_slice(), &height.to_be_bytes(), &(position as u32).to_be_bytes()].concat();
+            Some((key, nonce))
+        }).collect()
+    }
+
+    fn index_nonces(&self, batch: &mut WriteBatch, block: &Block) {
+        for (key, nonce) in Self::block_nonces(block) {
+            batch.put_cf(CF_STATE, key, nonce.to_be_bytes());
+        }
+    }
+
+    fn unindex_nonces(batch: &mut WriteBatch, block: &Block) {
+        for (key, _) in Self::block_nonces(block) {
+            batch.delete_cf(CF_STATE, key);
+        }
+    }
+
+    /// Indexes the nonces of the main-chain transactions (schema version 10).
+    fn backfill_nonces(&self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for block in self.iter_blocks(..) {
+            self.index_nonces(&mut batch, &block?);
+        }
+        self.store.write(batch)
+    }
+
+    /// The nonce the next transaction of `sender` must use: one past the latest it used on the
+    /// main chain, or zero if it never used one. Blocks only accept consecutive nonces, so the
+    /// latest is also the highest.
+    pub fn get_next_nonce(&self, sender: &Address) -> Result<u64, StorageError> {
+        let prefix = [&[PREFIX_NONCE], address_key_prefix(sender).as_slice()].concat();
+        let end = [prefix.as_slice(), &[0xff; 12]].concat();
+        match self.store.iter_from(CF_STATE, &end, Direction::Reverse).next() {
+            Some(item) => {
+                let (key, value) = item?;
+                if !key.starts_with(&prefix) {
+                    return Ok(0);
+                }
+                let nonce = u64::from_be_bytes(value.as_ref().try_into()
+                    .map_err(|_| StorageError::InvalidFormat("Invalid nonce length".to_string()))?);
+                Ok(nonce.saturating_add(1))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// The latest record published on the main chain for the pointer `name`, if any. Sequence
+    /// numbers only grow, so it is also the one with the highest sequence number.
+    pub fn resolve_pointer(&self, name: &[u8; 32]) -> Result<Option<PublishedPointer>, StorageError> {
+        let prefix = [&[PREFIX_POINTER], name.as_slice()].concat();
+        let end = [prefix.as_slice(), &[0xff; 12]].concat();
+        match self.store.iter_from(CF_STATE, &end, Direction::Reverse).next() {
+            Some(item) => {
+                let (key, value) = item?;
+                if !key.starts_with(&prefix) {
+                    return Ok(None);
+                }
+                Ok(Some(bincode::deserialize(&value)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Lists the main-chain storage deals on the payload `data_hash`, oldest first.
+    pub fn get_storage_deals(&self, data_hash: &Hash) -> Result<Vec<StorageDeal>, StorageError> {
+        let prefix = [&[PREFIX_STORAGE_DEAL], data_hash.as_slice()].concat();
+        let mut deals = Vec::new();
+        for item in self.store.iter_from(CF_STATE, &prefix, Direction::Forward) {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            deals.push(bincode::deserialize(&value)?);
+        }
+        Ok(deals)
+    }
+
+    /// Retrieves the compact filter of the block `block_hash`, main chain or not.
+    pub fn get_block_filter(&self, block_hash: &Hash) -> Result<Option<BlockFilter>, StorageError> {
+        match self.store.get(CF_BLOCK_FILTERS, block_hash)? {
+            Some(serialized) => Ok(Some(bincode::deserialize(&serialized)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Lists up to `limit` main-chain events matching `filter`, in chain order. With `after`, the
+    /// listing continues right after that event locator (see [`EventRecord::locator`]).
+    /// An address filter is served from the address index, a topic-only filter from the topic index.
+    pub fn get_events(&self, filter: &EventFilter, after: Option<(u64, u32, u32)>, limit: usize) -> Result<Vec<EventRecord>, StorageError> {
+        let prefix = match (&filter.address, &filter.topic) {
+            (Some(address), _) => event_index_prefix(EVENT_BY_ADDRESS, address),
+            (None, Some(topic)) => event_index_prefix(EVENT_BY_TOPIC, topic.as_bytes()),
+            (None, None) => Vec::new(),
+        };
+        let cf = if prefix.is_empty() { CF_EVENTS } else { CF_EVENT_INDEX };
+        let after = after.map(event_key);
+        let from = event_key((filter.from_height, 0, 0));
+        let start = [prefix.as_slice(), after.map_or(from, |after| after.max(from)).as_slice()].concat();
+
+        let mut records = Vec::new();
+        for item in self.store.iter_from(cf, &start, Direction::Forward) {
+            if records.len() >= limit {
+                break;
+            }
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let locator: [u8; 16] = key[prefix.len()..].try_into()
+                .map_err(|_| StorageError::InvalidFormat("Invalid event index entry".to_string()))?;
+            // The scan includes its start key, which the previous page already returned
+            if after == Some(locator) {
+                continue;
+            }
+            let height = u64::from_be_bytes(locator[..8].try_into().expect("slice is 8 bytes"));
+            if filter.to_height.is_some_and(|to_height| height > to_height) {
+                break;
+            }
+            let value = if cf == CF_EVENTS {
+                value.into_vec()
+            } else {
+                self.store.get(CF_EVENTS, &locator)?
+                    .ok_or_else(|| StorageError::InvalidFormat(format!("Event {} is indexed but missing", hex::encode(locator))))?
+            };
+            let stored: StoredEvent = bincode::deserialize(&value).map_err(StorageError::Deserialization)?;
+            if filter.topic.as_ref().is_some_and(|topic| *topic != stored.event.topic) {
+                continue;
+            }
+            records.push(EventRecord {
+                height,
+                position: u32::from_be_bytes(locator[8..12].try_into().expect("slice is 4 bytes")),
+                index: u32::from_be_bytes(locator[12..].try_into().expect("slice is 4 bytes")),
+                tx_hash: stored.tx_hash,
+                event: stored.event,
+            });
+        }
+        Ok(records)
+    }
+
+    /// Lists the main-chain transactions sending from or to `address`, oldest first.
+    pub fn get_address_transactions(&self, address: &Address) -> Result<Vec<AddressTxRef>, StorageError> {
+        let prefix = address_key_prefix(address);
+        let mut refs = Vec::new();
+        for item in self.store.iter_from(CF_ADDRESS_INDEX, &prefix, Direction::Forward) {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            refs.push(decode_address_entry(address, &key[prefix.len()..], &value)?);
+        }
+        Ok(refs)
+    }
+
+    /// Lists up to `limit` main-chain transactions sending from or to `address`, newest first.
+    /// With `before`, the listing starts right below that (height, position).
+    pub fn get_address_history(&self, address: &Address, before: Option<(u64, u32)>, limit: usize) -> Result<Vec<AddressTxRef>, StorageError> {
+        let prefix = address_key_prefix(address);
+        let mut start = prefix.clone();
+        match before {
+            Some((height, position)) => {
+                start.extend_from_slice(&height.to_be_bytes());
+                start.extend_from_slice(&position.to_be_bytes());
+            }
+            None => start.extend_from_slice(&[0xff; 12]),
+        }
+        let mut refs = Vec::new();
+        for item in self.store.iter_from(CF_ADDRESS_INDEX, &start, Direction::Reverse) {
+            if refs.len() >= limit {
+                break;
+            }
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            // The scan includes its start key, which the previous page already returned
+            if before.is_some() && *key == *start {
+                continue;
+            }
+            refs.push(decode_address_entry(address, &key[prefix.len()..], &value)?);
+        }
+        Ok(refs)
+    }
+
+    /// Returns up to `limit` hashes in `cf_name` whose hex encoding starts with `hex_prefix`.
+    /// Keys are ordered, so matches are contiguous and the scan stops at the first non-match.
+    fn find_hashes_by_prefix(&self, cf_name: &str, hex_prefix: &str, limit: usize) -> Result<Vec<Hash>, StorageError> {
+        let hex_prefix = normalize_hash_prefix(hex_prefix)
+            .ok_or_else(|| StorageError::InvalidFormat(format!("Invalid hash prefix: {:?}", hex_prefix)))?;
+        let start = hash_prefix_lower_bound(&hex_prefix);
+        let mut hashes = Vec::new();
+        for item in self.store.iter_from(cf_name, &start, Direction::Forward) {
+            if hashes.len() >= limit {
+                break;
+            }
+            let (key, _) = item?;
+            if !hex::encode(&key).starts_with(&hex_prefix) {
+                break;
+            }
+            if let Ok(hash) = <Hash>::try_from(&key[..]) {
+                hashes.push(hash);
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Stored blocks (main chain and side chains) whose hash starts with `hex_prefix`, at most `limit`.
+    pub fn find_blocks_by_prefix(&self, hex_prefix: &str, limit: usize) -> Result<Vec<Hash>, StorageError> {
+        self.find_hashes_by_prefix(CF_BLOCKS, hex_prefix, limit)
+    }
+
+    /// Indexed main-chain transactions whose hash starts with `hex_prefix`, at most `limit`.
+    /// Only transactions covered by the transaction index are found.
+    pub fn find_transactions_by_prefix(&self, hex_prefix: &str, limit: usize) -> Result<Vec<Hash>, StorageError> {
+        self.find_hashes_by_prefix(CF_TX_INDEX, hex_prefix, limit)
+    }
+
+    /// Looks up where a main-chain transaction is stored.
+    pub fn get_tx_location(&self, tx_hash: &Hash) -> Result<Option<TxLocation>, StorageError> {
+        match self.store.get(CF_TX_INDEX, tx_hash)? {
+            Some(location_bytes) => Ok(Some(TxLocation::from_bytes(&location_bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn encode_block(&self, block: &Block) -> Result<Vec<u8>, StorageError> {
+        let serialized_block = bincode::serialize(block).map_err(StorageError::Serialization)?;
+        self.encode_block_bytes(&serialized_block)
+    }
+
+    // Compresses an already serialized block the way it is stored
+    fn encode_block_bytes(&self, serialized_block: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.codec.read().expect("Block codec lock poisoned").encode(serialized_block)
     }
 
     /// Saves a block to the database.
     /// Uses a WriteBatch for atomicity.
     pub fn save_block(&self, block: &Block) -> Result<(), StorageError> {
+        self.save_blocks(std::slice::from_ref(block))
+    }
+
+    /// Saves consecutive main-chain blocks in a single WriteBatch; the last one becomes the tip.
+    /// Used by sync, where one write per block makes the import disk-bound.
+    pub fn save_blocks(&self, blocks: &[Block]) -> Result<(), StorageError> {
+        let last = match blocks.last() {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+        let mut batch = WriteBatch::default();
+        for block in blocks {
+            let block_hash = block.hash();
+            // Use '?' now that From<bincode::Error> is implemented manually
+            let serialized_block = self.encode_block(block)?;
+
+            // Store block and header by hash
+            batch.put_cf(CF_BLOCKS, block_hash, &serialized_block);
+            batch.put_cf(CF_HEADERS, block_hash, bincode::serialize(&block.header)?);
+
+            // Store height to hash mapping
+            batch.put_cf(CF_HEIGHT_INDEX, block.header.height.to_be_bytes(), block_hash);
+            batch.put_cf(CF_TIME_INDEX, time_index_key(&block.header), b"");
+
+            self.index_addresses(&mut batch, block);
+            self.index_events(&mut batch, block)?;
+            self.index_storage_deals(&mut batch, block)?;
+            self.index_pointers(&mut batch, block)?;
+            self.index_nonces(&mut batch, block);
+            self.put_block_filter(&mut batch, block)?;
+            if self.tx_index {
+                self.index_transactions(&mut batch, block);
+            }
+        }
+
+        // Update last hash and chain height
+        batch.put_cf(CF_METADATA, KEY_LAST_HASH, last.hash());
+        batch.put_cf(CF_METADATA, KEY_CHAIN_HEIGHT, last.header.height.to_be_bytes());
+
+        // The blocks' transactions were indexed in the same batch; otherwise mark the index as stale
+        if !self.tx_index {
+            batch.delete_cf(CF_METADATA, KEY_TX_INDEX_COMPLETE);
+        }
+
+        // Use '?' now that From<RocksDbError> is implemented manually
+        self.store.write(batch)?;
+        Ok(())
+    }
+
+    /// Saves a block that is not (yet) part of the main chain: it is retrievable by hash,
+    /// but the height index and tip are left untouched.
+    pub fn save_side_block(&self, block: &Block) -> Result<(), StorageError> {
         let block_hash = block.hash();
-        let block_height = block.header.height;
-        // Use '?' now that From<bincode::Error> is implemented manually
-        let serialized_block = bincode::serialize(block)?;
+        let serialized_block = self.encode_block(block)?;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(CF_BLOCKS, block_hash, &serialized_block);
+        batch.put_cf(CF_HEADERS, block_hash, bincode::serialize(&block.header).map_err(StorageError::Serialization)?);
+        self.put_block_filter(&mut batch, block)?;
+        self.store.write(batch)?;
+        Ok(())
+    }
 
+    /// Rewinds the main chain to the block `fork_hash` at `fork_height`, unindexing heights
+    /// `fork_height + 1..=old_height`. The unindexed blocks stay retrievable by hash.
+    pub fn rewind_main_chain(&self, fork_height: u64, fork_hash: &Hash, old_height: u64) -> Result<(), StorageError> {
         let mut batch = WriteBatch::default();
+        for height in fork_height + 1..=old_height {
+            // An unreadable block can't have its index entries located; drop it from the chain anyway
+            let block = self.get_block_by_height(height).unwrap_or_else(|e| {
+                warn!("Rewinding past unreadable block at height {}: {}", height, e);
+                None
+            });
+            if let Some(block) = block {
+                batch.delete_cf(CF_TIME_INDEX, time_index_key(&block.header));
+                for (key, _) in Self::address_index_keys(&block) {
+                    batch.delete_cf(CF_ADDRESS_INDEX, key);
+                }
+                Self::unindex_events(&mut batch, &block);
+                Self::unindex_storage_deals(&mut batch, &block);
+                Self::unindex_pointers(&mut batch, &block);
+                Self::unindex_nonces(&mut batch, &block);
+                if self.tx_index {
+                    for tx in &block.transactions {
+                        batch.delete_cf(CF_TX_INDEX, tx.calculate_hash());
+                    }
+                }
+            }
+            batch.delete_cf(CF_HEIGHT_INDEX, height.to_be_bytes());
+        }
+        batch.put_cf(CF_METADATA, KEY_LAST_HASH, fork_hash);
+        batch.put_cf(CF_METADATA, KEY_CHAIN_HEIGHT, fork_height.to_be_bytes());
+        self.store.write(batch)?;
+        Ok(())
+    }
 
-        // Store block by hash: b<hash> -> block_data
-        let block_key = [&[PREFIX_BLOCK], block_hash.as_slice()].concat();
-        batch.put(&block_key, &serialized_block);
+    /// Deletes every main-chain block above `height` together with its height mapping, index
+    /// entries, chain work and created tokens, in one batch, and makes the block at `height`
+    /// the tip. Unlike [`StorageManager::rewind_main_chain`] the blocks are gone afterwards.
+    /// Stakes are rebuilt from the kept blocks if a deleted block changed them, and the finalized
+    /// height is lowered to `height` if it was above. Returns the number of blocks deleted.
+    pub fn rollback_to_height(&self, height: u64) -> Result<u64, StorageError> {
+        let tip = match self.get_chain_height()? {
+            Some(tip) if tip > height => tip,
+            _ => return Ok(0),
+        };
+        let new_tip_hash = self.get_hash_by_height(height)?
+            .ok_or_else(|| StorageError::InvalidFormat(format!("No main-chain block at height {}", height)))?;
 
-        // Store height to hash mapping: h<height_be> -> hash
-        let mut height_key = Vec::with_capacity(1 + 8);
-        height_key.push(PREFIX_HEIGHT_TO_HASH);
-        height_key.extend_from_slice(&block_height.to_be_bytes());
-        batch.put(&height_key, &block_hash);
+        let mut batch = WriteBatch::default();
+        let mut deleted_hashes = Vec::new();
+        let mut stakes_changed = false;
+        for item in self.iter_main_chain_hashes(height + 1..=tip) {
+            let (block_height, hash) = item?;
+            // An unreadable block can't have its index entries located; delete what is keyed by its hash
+            let block = self.get_block_by_hash(&hash).unwrap_or_else(|e| {
+                warn!("Rolling back unreadable block at height {}: {}", block_height, e);
+                None
+            });
+            if let Some(block) = block {
+                batch.delete_cf(CF_TIME_INDEX, time_index_key(&block.header));
+                for (key, _) in Self::address_index_keys(&block) {
+                    batch.delete_cf(CF_ADDRESS_INDEX, key);
+                }
+                Self::unindex_events(&mut batch, &block);
+                Self::unindex_storage_deals(&mut batch, &block);
+                Self::unindex_pointers(&mut batch, &block);
+                Self::unindex_nonces(&mut batch, &block);
+                for tx in &block.transactions {
+                    batch.delete_cf(CF_TX_INDEX, tx.calculate_hash());
+                    match tx.transaction_type() {
+                        TransactionType::CreateToken { metadata } => {
+                            batch.delete_cf(CF_STATE, [&[PREFIX_TOKEN_METADATA], metadata.metadata_hash.as_slice()].concat());
+                        }
+                        TransactionType::Stake { .. } | TransactionType::Unstake { .. } | TransactionType::SlashDoubleSign { .. } => {
+                            stakes_changed = true;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            batch.delete_cf(CF_BLOCKS, hash);
+            batch.delete_cf(CF_HEADERS, hash);
+            batch.delete_cf(CF_BLOCK_FILTERS, hash);
+            batch.delete_cf(CF_STATE, [&[PREFIX_CHAIN_WORK], hash.as_slice()].concat());
+            batch.delete_cf(CF_HEIGHT_INDEX, block_height.to_be_bytes());
+            deleted_hashes.push(hash);
+        }
+        if stakes_changed {
+            for address in self.get_all_stakes()?.keys() {
+                batch.delete_cf(CF_STATE, [&[PREFIX_STAKE], address.as_slice()].concat());
+            }
+            for (address, amount) in self.replay_stakes(height)? {
+                if amount > 0 {
+                    batch.put_cf(CF_STATE, [&[PREFIX_STAKE], address.as_slice()].concat(), amount.to_be_bytes());
+                }
+            }
+        }
+        if matches!(self.get_finalized_height()?, Some(finalized) if finalized > height) {
+            batch.put_cf(CF_METADATA, KEY_FINALIZED_HEIGHT, height.to_be_bytes());
+        }
+        batch.put_cf(CF_METADATA, KEY_LAST_HASH, new_tip_hash);
+        batch.put_cf(CF_METADATA, KEY_CHAIN_HEIGHT, height.to_be_bytes());
+        self.store.write(batch)?;
+
+        let mut block_cache = self.block_cache.lock().expect("Block cache lock poisoned");
+        let mut header_cache = self.header_cache.lock().expect("Header cache lock poisoned");
+        for hash in &deleted_hashes {
+            block_cache.remove(hash);
+            header_cache.remove(hash);
+        }
+        info!("Rolled back {} blocks to height {}", deleted_hashes.len(), height);
+        Ok(deleted_hashes.len() as u64)
+    }
+
+    // Stake table after the main-chain blocks up to `height`, starting from the genesis stakes
+    fn replay_stakes(&self, height: u64) -> Result<StakeTable, StorageError> {
+        let mut stakes = self.get_chain_config()?.map(|config| config.initial_stakes).unwrap_or_default();
+        for block in self.iter_blocks(..=height) {
+            for tx in &block?.transactions {
+                match tx.transaction_type() {
+                    TransactionType::Stake { amount } => {
+                        *stakes.entry(tx.sender().clone()).or_default() += amount;
+                    }
+                    TransactionType::Unstake { amount } => {
+                        let stake = stakes.entry(tx.sender().clone()).or_default();
+                        *stake = stake.saturating_sub(*amount);
+                    }
+                    TransactionType::SlashDoubleSign { evidence } => {
+                        stakes.insert(evidence.offender().clone(), 0);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(stakes)
+    }
+
+    /// Records the cumulative work of the chain ending at `hash`.
+    pub fn save_chain_work(&self, hash: &Hash, work: U256) -> Result<(), StorageError> {
+        let work_key = [&[PREFIX_CHAIN_WORK], hash.as_slice()].concat();
+        let mut work_bytes = [0u8; 32];
+        work.to_big_endian(&mut work_bytes);
+        self.store.put(CF_STATE, &work_key, &work_bytes)?;
+        Ok(())
+    }
 
-        // Update last hash: lh -> hash
-        batch.put(KEY_LAST_HASH, &block_hash);
+    /// Records the cumulative work of many blocks in one write.
+    pub fn save_chain_works(&self, works: &[(Hash, U256)]) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for (hash, work) in works {
+            let mut work_bytes = [0u8; 32];
+            work.to_big_endian(&mut work_bytes);
+            batch.put_cf(CF_STATE, [&[PREFIX_CHAIN_WORK], hash.as_slice()].concat(), work_bytes);
+        }
+        self.store.write(batch)?;
+        Ok(())
+    }
+
+    /// Records that the blocks `hashes` can never join the main chain.
+    pub fn mark_blocks_failed(&self, hashes: &[Hash]) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for hash in hashes {
+            batch.put_cf(CF_STATE, [&[PREFIX_FAILED_BLOCK], hash.as_slice()].concat(), b"");
+        }
+        self.store.write(batch)
+    }
 
-        // Update chain height: ch -> height_be
-        batch.put(KEY_CHAIN_HEIGHT, &block_height.to_be_bytes());
+    pub fn is_block_failed(&self, hash: &Hash) -> Result<bool, StorageError> {
+        Ok(self.store.get(CF_STATE, &[&[PREFIX_FAILED_BLOCK], hash.as_slice()].concat())?.is_some())
+    }
 
-        // Use '?' now that From<RocksDbError> is implemented manually
-        self.db.write(batch)?;
-        Ok(())
+    /// Retrieves the cumulative work of the chain ending at `hash`, if recorded.
+    pub fn get_chain_work(&self, hash: &Hash) -> Result<Option<U256>, StorageError> {
+        let work_key = [&[PREFIX_CHAIN_WORK], hash.as_slice()].concat();
+        match self.store.get(CF_STATE, &work_key)? {
+            Some(work_bytes) if work_bytes.len() == 32 => Ok(Some(U256::from_big_endian(&work_bytes))),
+            Some(work_bytes) => Err(StorageError::InvalidFormat(format!("Invalid chain work length ({})", work_bytes.len()))),
+            None => Ok(None),
+        }
     }
 
     /// Retrieves a block from the database by its hash.
     pub fn get_block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, StorageError> {
-        let block_key = [&[PREFIX_BLOCK], hash.as_slice()].concat();
+        if let Some(block) = self.block_cache.lock().expect("Block cache lock poisoned").get(hash) {
+            return Ok(Some(block));
+        }
         // Use '?' for RocksDB error
-        match self.db.get(&block_key)? {
-            Some(serialized_block) => {
+        match self.store.get(CF_BLOCKS, hash)? {
+            Some(stored_block) => {
+                let serialized_block = if self.block_format < TAGGED_BLOCKS_VERSION {
+                    stored_block
+                } else {
+                    self.codec.read().expect("Block codec lock poisoned").decode(&stored_block)?
+                };
                 // Use '?' now that From<bincode::Error> is implemented manually
-                let block: Block = bincode::deserialize(&serialized_block)?;
+                let block: Block = if self.block_format < TRANSACTION_FEES_VERSION {
+                    bincode::deserialize::<LegacyBlock>(&serialized_block)?.into()
+                } else if self.block_format < BLOCK_WITNESSES_VERSION {
+                    bincode::deserialize::<UnwitnessedBlock>(&serialized_block)?.into()
+                } else {
+                    bincode::deserialize(&serialized_block)?
+                };
+                self.block_cache.lock().expect("Block cache lock poisoned").insert(*hash, block.clone());
                 Ok(Some(block))
             }
             None => Ok(None),
         }
     }
 
+    /// Retrieves only the header of a block by its hash, without loading its transactions.
+    pub fn get_header_by_hash(&self, hash: &Hash) -> Result<Option<BlockHeader>, StorageError> {
+        if let Some(header) = self.header_cache.lock().expect("Header cache lock poisoned").get(hash) {
+            return Ok(Some(header));
+        }
+        match self.store.get(CF_HEADERS, hash)? {
+            Some(serialized_header) => {
+                let header: BlockHeader = bincode::deserialize(&serialized_header)?;
+                self.header_cache.lock().expect("Header cache lock poisoned").insert(*hash, header.clone());
+                Ok(Some(header))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Retrieves a block hash from the database by its height.
     pub fn get_hash_by_height(&self, height: u64) -> Result<Option<Hash>, StorageError> {
-        let mut height_key = Vec::with_capacity(1 + 8);
-        height_key.push(PREFIX_HEIGHT_TO_HASH);
-        height_key.extend_from_slice(&height.to_be_bytes());
         // Use '?' for RocksDB error
-        match self.db.get(&height_key)? {
+        match self.store.get(CF_HEIGHT_INDEX, &height.to_be_bytes())? {
             Some(hash_vec) => {
                 if hash_vec.len() == 32 {
                     let mut hash = [0u8; 32];
@@ -140,10 +1486,68 @@ impl StorageManager {
         }
     }
 
+    /// Iterates over the main-chain hashes at heights in `range`, in height order,
+    /// with a single forward scan of the height index.
+    pub fn iter_main_chain_hashes(&self, range: impl RangeBounds<u64>) -> impl Iterator<Item = Result<(u64, Hash), StorageError>> + '_ {
+        let (start, end) = inclusive_height_bounds(range);
+        self.store.iter_from(CF_HEIGHT_INDEX, &start.to_be_bytes(), Direction::Forward)
+            .map(|item| {
+                let (key, value) = item?;
+                let height_bytes: [u8; 8] = key.as_ref().try_into()
+                    .map_err(|_| StorageError::InvalidFormat(format!("Invalid height index key length ({})", key.len())))?;
+                let height = u64::from_be_bytes(height_bytes);
+                let hash = <Hash>::try_from(value.as_ref())
+                    .map_err(|_| StorageError::InvalidFormat(format!("Invalid hash length ({}) for height {}", value.len(), height)))?;
+                Ok((height, hash))
+            })
+            .take_while(move |entry| !matches!(entry, Ok((height, _)) if *height > end))
+    }
+
+    /// Iterates over the main-chain blocks at heights in `range`, in height order.
+    /// Walks the height index once instead of looking up each height separately.
+    pub fn iter_blocks(&self, range: impl RangeBounds<u64>) -> impl Iterator<Item = Result<Block, StorageError>> + '_ {
+        self.iter_main_chain_hashes(range).map(move |entry| {
+            let (height, hash) = entry?;
+            self.get_block_by_hash(&hash)?
+                .ok_or_else(|| StorageError::InvalidFormat(format!("Height index points to a missing block at height {}", height)))
+        })
+    }
+
+    /// Like [`StorageManager::iter_blocks`], but reads only the headers.
+    pub fn iter_headers(&self, range: impl RangeBounds<u64>) -> impl Iterator<Item = Result<BlockHeader, StorageError>> + '_ {
+        self.iter_main_chain_hashes(range).map(move |entry| {
+            let (height, hash) = entry?;
+            self.get_header_by_hash(&hash)?
+                .ok_or_else(|| StorageError::InvalidFormat(format!("Height index points to a missing header at height {}", height)))
+        })
+    }
+
+    /// Returns the main-chain block with the latest timestamp at or before `timestamp` (the highest
+    /// one if several share it). Timestamps need not increase with height, so a later block may
+    /// carry an earlier timestamp.
+    pub fn get_block_by_time(&self, timestamp: u64) -> Result<Option<Block>, StorageError> {
+        let mut start = [0u8; 16];
+        start[..8].copy_from_slice(&timestamp.to_be_bytes());
+        start[8..].copy_from_slice(&u64::MAX.to_be_bytes());
+        for item in self.store.iter_from(CF_TIME_INDEX, &start, Direction::Reverse) {
+            let (key, _) = item?;
+            let key: [u8; 16] = key.as_ref().try_into()
+                .map_err(|_| StorageError::InvalidFormat(format!("Invalid time index key length ({})", key.len())))?;
+            let entry_timestamp = u64::from_be_bytes(key[..8].try_into().expect("slice is 8 bytes"));
+            let height = u64::from_be_bytes(key[8..].try_into().expect("slice is 8 bytes"));
+            // A block saved over another at the same height leaves the old entry behind; skip it
+            match self.get_block_by_height(height)? {
+                Some(block) if block.header.timestamp == entry_timestamp => return Ok(Some(block)),
+                _ => continue,
+            }
+        }
+        Ok(None)
+    }
+
     /// Retrieves the hash of the latest block in the main chain.
     pub fn get_last_block_hash(&self) -> Result<Option<Hash>, StorageError> {
         // Use '?' for RocksDB error
-        match self.db.get(KEY_LAST_HASH)? {
+        match self.store.get(CF_METADATA, KEY_LAST_HASH)? {
             Some(hash_vec) => {
                 if hash_vec.len() == 32 {
                     let mut hash = [0u8; 32];
@@ -161,7 +1565,7 @@ impl StorageManager {
     /// Retrieves the current height of the main chain.
     pub fn get_chain_height(&self) -> Result<Option<u64>, StorageError> {
         // Use '?' for RocksDB error
-        match self.db.get(KEY_CHAIN_HEIGHT)? {
+        match self.store.get(CF_METADATA, KEY_CHAIN_HEIGHT)? {
             Some(height_bytes) => {
                 if height_bytes.len() == 8 {
                     Ok(Some(u64::from_be_bytes(height_bytes.try_into().unwrap())))
@@ -174,18 +1578,249 @@ impl StorageManager {
         }
     }
 
+    /// Records the height of the highest irreversible block.
+    pub fn save_finalized_height(&self, height: u64) -> Result<(), StorageError> {
+        self.store.put(CF_METADATA, KEY_FINALIZED_HEIGHT, &height.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Retrieves the height of the highest irreversible block, if any block is final yet.
+    pub fn get_finalized_height(&self) -> Result<Option<u64>, StorageError> {
+        match self.store.get(CF_METADATA, KEY_FINALIZED_HEIGHT)? {
+            Some(height_bytes) => {
+                let height_bytes: [u8; 8] = height_bytes.as_slice().try_into()
+                    .map_err(|_| StorageError::InvalidFormat("Invalid finalized_height length".to_string()))?;
+                Ok(Some(u64::from_be_bytes(height_bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Records the chain configuration the chain was created with.
+    pub fn save_chain_config(&self, config: &ChainConfig) -> Result<(), StorageError> {
+        let serialized_config = bincode::serialize(config).map_err(StorageError::Serialization)?;
+        self.store.put(CF_METADATA, KEY_CHAIN_CONFIG, &serialized_config)?;
+        Ok(())
+    }
+
+    /// Retrieves the chain configuration recorded at genesis, if any.
+    pub fn get_chain_config(&self) -> Result<Option<ChainConfig>, StorageError> {
+        match self.store.get(CF_METADATA, KEY_CHAIN_CONFIG)? {
+            Some(serialized_config) => {
+                let config: ChainConfig = bincode::deserialize(&serialized_config)?;
+                Ok(Some(config))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Writes the given stake entries; a zero amount removes the validator.
+    pub fn save_stakes(&self, stakes: &StakeTable) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for (address, amount) in stakes {
+            let stake_key = [&[PREFIX_STAKE], address.as_slice()].concat();
+            if *amount == 0 {
+                batch.delete_cf(CF_STATE, &stake_key);
+            } else {
+                batch.put_cf(CF_STATE, &stake_key, amount.to_be_bytes());
+            }
+        }
+        self.store.write(batch)?;
+        Ok(())
+    }
+
+    /// Retrieves the amount staked by a validator (0 if none).
+    pub fn get_stake(&self, address: &Address) -> Result<u64, StorageError> {
+        let stake_key = [&[PREFIX_STAKE], address.as_slice()].concat();
+        match self.store.get(CF_STATE, &stake_key)? {
+            Some(amount_bytes) => {
+                let amount_bytes: [u8; 8] = amount_bytes.as_slice().try_into()
+                    .map_err(|_| StorageError::InvalidFormat("Invalid stake amount length".to_string()))?;
+                Ok(u64::from_be_bytes(amount_bytes))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Loads the full validator stake table.
+    pub fn get_all_stakes(&self) -> Result<StakeTable, StorageError> {
+        let mut stakes = StakeTable::new();
+        let prefix = [PREFIX_STAKE];
+        let iter = self.store.iter_from(CF_STATE, &prefix, Direction::Forward);
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let amount_bytes: [u8; 8] = value.as_ref().try_into()
+                .map_err(|_| StorageError::InvalidFormat("Invalid stake amount length".to_string()))?;
+            stakes.insert(key[1..].to_vec(), u64::from_be_bytes(amount_bytes));
+        }
+        Ok(stakes)
+    }
+
+    /// Records evidence of a rejected block, dropping the oldest records beyond `max_entries`.
+    pub fn save_invalid_block_evidence(&self, evidence: &InvalidBlockEvidence, max_entries: usize) -> Result<(), StorageError> {
+        let evidence_key = [&[PREFIX_INVALID_BLOCK], evidence.recorded_at.to_be_bytes().as_slice(), evidence.block_hash.as_slice()].concat();
+        let serialized_evidence = bincode::serialize(evidence).map_err(StorageError::Serialization)?;
+        self.store.put(CF_STATE, &evidence_key, &serialized_evidence)?;
+
+        // Keys sort by time, so the oldest records come first
+        let prefix = [PREFIX_INVALID_BLOCK];
+        let mut keys = Vec::new();
+        for item in self.store.iter_from(CF_STATE, &prefix, Direction::Forward) {
+            let (key, _) = item?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            keys.push(key);
+        }
+        if keys.len() > max_entries {
+            let mut batch = WriteBatch::default();
+            for key in &keys[..keys.len() - max_entries] {
+                batch.delete_cf(CF_STATE, key);
+            }
+            self.store.write(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Retrieves up to `limit` invalid-block records, newest first.
+    pub fn get_invalid_block_evidence(&self, limit: usize) -> Result<Vec<InvalidBlockEvidence>, StorageError> {
+        // Seek to the last key before the next prefix and walk backwards
+        let end = [PREFIX_INVALID_BLOCK + 1];
+        let mut records = Vec::new();
+        for item in self.store.iter_from(CF_STATE, &end, Direction::Reverse) {
+            let (key, value) = item?;
+            if key.first() != Some(&PREFIX_INVALID_BLOCK) || records.len() >= limit {
+                break;
+            }
+            records.push(bincode::deserialize(&value)?);
+        }
+        Ok(records)
+    }
+
+    /// Saves a tenant's usage counters for a month (`YYYY-MM`).
+    pub fn save_tenant_usage(&self, tenant_id: &str, month: &str, usage: &TenantUsage) -> Result<(), StorageError> {
+        let usage_key = [&[PREFIX_TENANT_USAGE], tenant_id.as_bytes(), &[0], month.as_bytes()].concat();
+        let serialized_usage = bincode::serialize(usage).map_err(StorageError::Serialization)?;
+        self.store.put(CF_STATE, &usage_key, &serialized_usage)?;
+        Ok(())
+    }
+
+    /// Retrieves a tenant's usage counters for a month, if any were recorded.
+    pub fn get_tenant_usage(&self, tenant_id: &str, month: &str) -> Result<Option<TenantUsage>, StorageError> {
+        let usage_key = [&[PREFIX_TENANT_USAGE], tenant_id.as_bytes(), &[0], month.as_bytes()].concat();
+        match self.store.get(CF_STATE, &usage_key)? {
+            Some(serialized_usage) => Ok(Some(bincode::deserialize(&serialized_usage)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Records a payment of `amount` for serving the payload `payload_hash` at `now`, adding it to
+    /// the payload's earnings. Returns `Ok(false)`, recording nothing, if the payment was already used.
+    pub fn record_retrieval_payment(&self, payload_hash: &Hash, payment: &Hash, amount: u64, now: u64) -> Result<bool, StorageError> {
+        let payment_key = [&[PREFIX_RETRIEVAL_PAYMENT], payment.as_slice()].concat();
+        if self.store.get(CF_STATE, &payment_key)?.is_some() {
+            return Ok(false);
+        }
+        let earnings_key = [&[PREFIX_RETRIEVAL_EARNINGS], payload_hash.as_slice()].concat();
+        let mut earnings: RetrievalEarnings = match self.store.get(CF_STATE, &earnings_key)? {
+            Some(serialized_earnings) => bincode::deserialize(&serialized_earnings)?,
+            None => RetrievalEarnings::default(),
+        };
+        earnings.retrievals += 1;
+        earnings.earned = earnings.earned.saturating_add(amount);
+        earnings.last_paid_at = now;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(CF_STATE, payment_key, payload_hash);
+        batch.put_cf(CF_STATE, earnings_key, bincode::serialize(&earnings).map_err(StorageError::Serialization)?);
+        self.store.write(batch)?;
+        Ok(true)
+    }
+
+    /// Retrieves the earnings of every payload served for a payment, by payload hash.
+    pub fn get_retrieval_earnings(&self) -> Result<Vec<(Hash, RetrievalEarnings)>, StorageError> {
+        let mut earnings = Vec::new();
+        for item in self.store.iter_from(CF_STATE, &[PREFIX_RETRIEVAL_EARNINGS], Direction::Forward) {
+            let (key, value) = item?;
+            if key.first() != Some(&PREFIX_RETRIEVAL_EARNINGS) {
+                break;
+            }
+            let payload_hash: Hash = key[1..].try_into()
+                .map_err(|_| StorageError::InvalidFormat("Retrieval earnings key is not a payload hash".to_string()))?;
+            earnings.push((payload_hash, bincode::deserialize(&value)?));
+        }
+        Ok(earnings)
+    }
+
+    /// Saves the redundancy policy asked for the payload `payload_hash`, replacing any earlier one.
+    pub fn put_redundancy_policy(&self, payload_hash: &Hash, policy: &RedundancyPolicy) -> Result<(), StorageError> {
+        let policy_key = [&[PREFIX_REDUNDANCY_POLICY], payload_hash.as_slice()].concat();
+        self.store.put(CF_STATE, &policy_key, &bincode::serialize(policy)?)?;
+        Ok(())
+    }
+
+    /// Retrieves the redundancy policy of the payload `payload_hash`, if one was asked for.
+    pub fn get_redundancy_policy(&self, payload_hash: &Hash) -> Result<Option<RedundancyPolicy>, StorageError> {
+        let policy_key = [&[PREFIX_REDUNDANCY_POLICY], payload_hash.as_slice()].concat();
+        match self.store.get(CF_STATE, &policy_key)? {
+            Some(serialized_policy) => Ok(Some(bincode::deserialize(&serialized_policy)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Retrieves every redundancy policy, by payload hash.
+    pub fn get_redundancy_policies(&self) -> Result<Vec<(Hash, RedundancyPolicy)>, StorageError> {
+        let mut policies = Vec::new();
+        for item in self.store.iter_from(CF_STATE, &[PREFIX_REDUNDANCY_POLICY], Direction::Forward) {
+            let (key, value) = item?;
+            if key.first() != Some(&PREFIX_REDUNDANCY_POLICY) {
+                break;
+            }
+            let payload_hash: Hash = key[1..].try_into()
+                .map_err(|_| StorageError::InvalidFormat("Redundancy policy key is not a payload hash".to_string()))?;
+            policies.push((payload_hash, bincode::deserialize(&value)?));
+        }
+        Ok(policies)
+    }
+
+    /// Saves the ACL of the payload it names, replacing any earlier one.
+    pub fn put_payload_acl(&self, acl: &PayloadAcl) -> Result<(), StorageError> {
+        let acl_key = [&[PREFIX_PAYLOAD_ACL], acl.payload_hash.as_slice()].concat();
+        self.store.put(CF_STATE, &acl_key, &bincode::serialize(acl)?)?;
+        Ok(())
+    }
+
+    /// Deletes the ACL of the payload `payload_hash`, lifting its restriction.
+    pub fn delete_payload_acl(&self, payload_hash: &Hash) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(CF_STATE, [&[PREFIX_PAYLOAD_ACL], payload_hash.as_slice()].concat());
+        self.store.write(batch)?;
+        Ok(())
+    }
+
+    /// Retrieves the ACL in force for the payload `payload_hash`, if its owner set one.
+    pub fn get_payload_acl(&self, payload_hash: &Hash) -> Result<Option<PayloadAcl>, StorageError> {
+        let acl_key = [&[PREFIX_PAYLOAD_ACL], payload_hash.as_slice()].concat();
+        match self.store.get(CF_STATE, &acl_key)? {
+            Some(serialized_acl) => Ok(Some(bincode::deserialize(&serialized_acl)?)),
+            None => Ok(None),
+        }
+    }
+
     /// Saves the metadata for a newly created token.
     pub fn save_token_metadata(&self, metadata: &TokenMetadata) -> Result<(), StorageError> {
         let token_key = [&[PREFIX_TOKEN_METADATA], metadata.metadata_hash.as_slice()].concat();
         let serialized_metadata = bincode::serialize(metadata)?;
-        self.db.put(&token_key, &serialized_metadata)?;
+        self.store.put(CF_STATE, &token_key, &serialized_metadata)?;
         Ok(())
     }
 
     /// Retrieves the metadata for a specific token by its hash.
     pub fn get_token_metadata(&self, token_hash: &Hash) -> Result<Option<TokenMetadata>, StorageError> {
         let token_key = [&[PREFIX_TOKEN_METADATA], token_hash.as_slice()].concat();
-        match self.db.get(&token_key)? {
+        match self.store.get(CF_STATE, &token_key)? {
             Some(serialized_metadata) => {
                 let metadata: TokenMetadata = bincode::deserialize(&serialized_metadata)?;
                 Ok(Some(metadata))
@@ -198,7 +1833,7 @@ impl StorageManager {
     pub fn list_all_token_metadata(&self) -> Result<Vec<TokenMetadata>, StorageError> {
         let mut tokens = Vec::new();
         let prefix = [PREFIX_TOKEN_METADATA];
-        let iter = self.db.iterator(IteratorMode::From(&prefix, rocksdb::Direction::Forward));
+        let iter = self.store.iter_from(CF_STATE, &prefix, Direction::Forward);
 
         for item in iter {
             match item {
@@ -219,7 +1854,7 @@ impl StorageManager {
                 }
                 Err(e) => {
                     error!("Error during token metadata iteration: {}", e);
-                    return Err(StorageError::Database(e));
+                    return Err(e);
                 }
             }
         }
@@ -241,6 +1876,16 @@ mod tests {
         Block::new(previous_hash, transactions, 10, height)
     }
 
+    // Serializes `block` the way databases before schema version 8 stored it
+    fn legacy_block_bytes(block: &Block) -> Vec<u8> {
+        let legacy = LegacyBlock {
+            header: block.header.clone(),
+            transactions: block.transactions.iter().cloned().map(Into::into).collect(),
+            seal: block.seal.clone(),
+        };
+        bincode::serialize(&legacy).unwrap()
+    }
+
     #[test]
     fn test_storage_new_open() {
         let dir = tempdir().unwrap();
@@ -310,5 +1955,490 @@ mod tests {
         assert_eq!(storage.get_hash_by_height(1).unwrap(), Some(hash1));
         assert!(storage.get_hash_by_height(2).unwrap().is_none());
     }
-}
 
+    #[test]
+    fn test_invalid_block_evidence_is_bounded() {
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+
+        for i in 0..5u64 {
+            let block = create_test_block(i, [0u8; 32], 0);
+            let evidence = InvalidBlockEvidence {
+                block_hash: block.hash(),
+                header: block.header,
+                reason: format!("bad block {}", i),
+                source: None,
+                recorded_at: 1_000 + i,
+            };
+            storage.save_invalid_block_evidence(&evidence, 3).unwrap();
+        }
+
+        let records = storage.get_invalid_block_evidence(10).unwrap();
+        let reasons: Vec<&str> = records.iter().map(|e| e.reason.as_str()).collect();
+        assert_eq!(reasons, vec!["bad block 4", "bad block 3", "bad block 2"]);
+        assert_eq!(storage.get_invalid_block_evidence(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "rocksdb")]
+    fn test_legacy_keyspace_is_migrated() {
+        let dir = tempdir().unwrap();
+        let block0 = create_test_block(0, [0u8; 32], 1);
+        let hash0 = block0.hash();
+        {
+            // Layout written before column families: everything in the default keyspace
+            let db = rocksdb::DB::open_default(dir.path()).unwrap();
+            db.put([&[LEGACY_PREFIX_BLOCK], hash0.as_slice()].concat(), legacy_block_bytes(&block0)).unwrap();
+            db.put([&[LEGACY_PREFIX_HEIGHT_TO_HASH], 0u64.to_be_bytes().as_slice()].concat(), hash0).unwrap();
+            db.put(KEY_LAST_HASH, hash0).unwrap();
+            db.put(KEY_CHAIN_HEIGHT, 0u64.to_be_bytes()).unwrap();
+            db.put([&[PREFIX_STAKE], b"validator".as_slice()].concat(), 5u64.to_be_bytes()).unwrap();
+        }
+
+        let storage = StorageManager::new(dir.path()).unwrap();
+        assert_eq!(storage.get_block_by_height(0).unwrap().unwrap().header, block0.header);
+        assert_eq!(storage.get_header_by_hash(&hash0).unwrap(), Some(block0.header.clone()));
+        assert_eq!(storage.get_last_block_hash().unwrap(), Some(hash0));
+        assert_eq!(storage.get_chain_height().unwrap(), Some(0));
+        assert_eq!(storage.get_stake(&b"validator".to_vec()).unwrap(), 5);
+        assert!(storage.store.iter_from(CF_DEFAULT, &[], Direction::Forward).next().is_none());
+        assert_eq!(storage.store.get(CF_BLOCKS, &hash0).unwrap().unwrap()[0], ENCODING_RAW);
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_legacy_chain_config_is_extended() {
+        let dir = tempdir().unwrap();
+        // HTLCs leave the features, the other ones keep their activation height
+        let mut config = ChainConfig::default().with_finality_depth(7);
+        config.feature_activations.insert(TxFeature::Pointers, 10);
+        {
+            let storage = StorageManager::new(dir.path()).unwrap();
+            let legacy = LegacyChainConfig {
+                consensus_mode: config.consensus_mode,
+                pow_algorithm: config.pow_algorithm,
+                difficulty_algorithm: config.difficulty_algorithm,
+                initial_stakes: config.initial_stakes.clone(),
+                transaction_version_activations: config.transaction_version_activations.clone(),
+                feature_activations: BTreeMap::from([
+                    (LegacyTxFeature::Tokens, 0), (LegacyTxFeature::Htlc, 5), (LegacyTxFeature::Pointers, 10),
+                ]),
+                finality_depth: config.finality_depth,
+                max_tx_data_size: config.max_tx_data_size,
+                max_block_data_size: config.max_block_data_size,
+            };
+            storage.store.put(CF_METADATA, KEY_CHAIN_CONFIG, &bincode::serialize(&legacy).unwrap()).unwrap();
+            storage.store.put(CF_METADATA, KEY_SCHEMA_VERSION, &4u32.to_be_bytes()).unwrap();
+        }
+        let storage = StorageManager::new(dir.path()).unwrap();
+        assert_eq!(storage.get_chain_config().unwrap(), Some(config));
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_transactions_gain_fees_and_nonces() {
+        let dir = tempdir().unwrap();
+        let block0 = create_test_block(0, [0u8; 32], 1);
+        let block1 = create_test_block(1, block0.hash(), 2);
+        let tx_hash = block1.transactions[1].calculate_hash();
+        {
+            let storage = StorageManager::new(dir.path()).unwrap();
+            storage.save_blocks(&[block0.clone(), block1.clone()]).unwrap();
+            storage.reindex_transactions().unwrap();
+            // Blocks written before fees and nonces
+            let mut batch = WriteBatch::default();
+            for block in [&block0, &block1] {
+                batch.put_cf(CF_BLOCKS, block.hash(), [&[ENCODING_RAW], legacy_block_bytes(block).as_slice()].concat());
+            }
+            storage.store.write(batch).unwrap();
+            storage.store.put(CF_METADATA, KEY_SCHEMA_VERSION, &7u32.to_be_bytes()).unwrap();
+        }
+        drop(StorageManager::new(dir.path()).unwrap());
+
+        let storage = StorageManager::new(dir.path()).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+        let migrated = storage.get_block_by_height(1).unwrap().unwrap();
+        assert_eq!(migrated.transactions, block1.transactions);
+        assert!(migrated.transactions.iter().all(|tx| tx.fee() == 0 && tx.nonce().is_none()));
+        assert_eq!(migrated.witnesses, vec![None, None]);
+        assert_eq!(storage.get_tx_location(&tx_hash).unwrap(), Some(TxLocation { block_hash: block1.hash(), position: 1 }));
+        assert!(storage.is_tx_index_complete().unwrap());
+    }
+
+    #[test]
+    fn test_newer_schema_is_refused() {
+        let dir = tempdir().unwrap();
+        {
+            let storage = StorageManager::new(dir.path()).unwrap();
+            assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+            storage.store.put(CF_METADATA, KEY_SCHEMA_VERSION, &(SCHEMA_VERSION + 1).to_be_bytes()).unwrap();
+        }
+        match StorageManager::new(dir.path()) {
+            Err(StorageError::UnsupportedSchema { found, supported }) => {
+                assert_eq!((found, supported), (SCHEMA_VERSION + 1, SCHEMA_VERSION));
+            }
+            other => panic!("expected UnsupportedSchema, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_tx_index_locates_transactions() {
+        let dir = tempdir().unwrap();
+        let mut storage = StorageManager::new(dir.path()).unwrap();
+        let block0 = create_test_block(0, [0u8; 32], 0);
+        storage.save_block(&block0).unwrap();
+        let block1 = create_test_block(1, block0.hash(), 3);
+        storage.save_block(&block1).unwrap();
+        // Saved without the index: nothing is indexed and the index is incomplete
+        let tx_hash = block1.transactions[2].calculate_hash();
+        assert!(!storage.is_tx_index_complete().unwrap());
+        assert!(storage.get_tx_location(&tx_hash).unwrap().is_none());
+
+        storage.set_tx_index(true);
+        storage.reindex_transactions().unwrap();
+        assert!(storage.is_tx_index_complete().unwrap());
+        assert_eq!(storage.get_tx_location(&tx_hash).unwrap(), Some(TxLocation { block_hash: block1.hash(), position: 2 }));
+
+        let block2 = create_test_block(2, block1.hash(), 1);
+        storage.save_block(&block2).unwrap();
+        let tx2_hash = block2.transactions[0].calculate_hash();
+        assert_eq!(storage.get_tx_location(&tx2_hash).unwrap().unwrap().block_hash, block2.hash());
+        storage.rewind_main_chain(1, &block1.hash(), 2).unwrap();
+        assert!(storage.get_tx_location(&tx2_hash).unwrap().is_none());
+        assert!(storage.is_tx_index_complete().unwrap());
+    }
+
+    #[test]
+    fn test_events_follow_main_chain() {
+        use crate::core::events::{TOPIC_TOKEN_TRANSFER, TOPIC_TRANSFER};
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+        let (alice, bob) = (vec![0xa1], vec![0xb0]);
+        let block0 = Block::new([0u8; 32], vec![Transaction::new_transfer_native(alice.clone(), bob.clone(), 5)], 10, 0);
+        storage.save_block(&block0).unwrap();
+        let block1 = Block::new(block0.hash(), vec![
+            Transaction::new_transfer_token(bob.clone(), alice.clone(), [7u8; 32], 2),
+            Transaction::new_transfer_native(bob.clone(), bob.clone(), 1),
+        ], 10, 1);
+        storage.save_block(&block1).unwrap();
+
+        let all = storage.get_events(&EventFilter::default(), None, 10).unwrap();
+        assert_eq!(all.iter().map(EventRecord::locator).collect::<Vec<_>>(), vec![(0, 0, 0), (1, 0, 0), (1, 1, 0)]);
+        assert_eq!(all[1].tx_hash, block1.transactions[0].calculate_hash());
+        let by_topic = EventFilter { topic: Some(TOPIC_TRANSFER.to_string()), ..Default::default() };
+        assert_eq!(storage.get_events(&by_topic, None, 10).unwrap().len(), 2);
+        let by_address = EventFilter { address: Some(alice.clone()), from_height: 1, ..Default::default() };
+        assert_eq!(storage.get_events(&by_address, None, 10).unwrap()[0].event.topic, TOPIC_TOKEN_TRANSFER);
+        let both = EventFilter { topic: Some(TOPIC_TRANSFER.to_string()), address: Some(alice.clone()), to_height: Some(0), ..Default::default() };
+        assert_eq!(storage.get_events(&both, None, 10).unwrap().len(), 1);
+
+        // Pages continue after the last event returned
+        let page = storage.get_events(&EventFilter::default(), Some((1, 0, 0)), 10).unwrap();
+        assert_eq!(page.iter().map(EventRecord::locator).collect::<Vec<_>>(), vec![(1, 1, 0)]);
+
+        // Rolling back a block removes its events
+        storage.rewind_main_chain(0, &block0.hash(), 1).unwrap();
+        assert_eq!(storage.get_events(&EventFilter::default(), None, 10).unwrap().len(), 1);
+        assert!(storage.get_events(&EventFilter { address: Some(bob.clone()), from_height: 1, ..Default::default() }, None, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_block_filters_are_stored() {
+        let dir = tempdir().unwrap();
+        let (alice, bob) = (vec![0xa1], vec![0xb0]);
+        let block0 = Block::new([0u8; 32], vec![Transaction::new_transfer_native(alice.clone(), bob.clone(), 5)], 10, 0);
+        let side = Block::new(block0.hash(), vec![Transaction::new_transfer_native(bob.clone(), bob.clone(), 1)], 10, 1);
+        {
+            let storage = StorageManager::new(dir.path()).unwrap();
+            storage.save_block(&block0).unwrap();
+            storage.save_side_block(&side).unwrap();
+            let filter = storage.get_block_filter(&block0.hash()).unwrap().unwrap();
+            assert!(filter.matches(&block0.hash(), &alice) && filter.matches(&block0.hash(), &bob));
+            assert!(storage.get_block_filter(&side.hash()).unwrap().unwrap().matches(&side.hash(), &bob));
+            assert!(storage.get_block_filter(&[0u8; 32]).unwrap().is_none());
+
+            // A database from before filters gets them for its main chain
+            let mut batch = WriteBatch::default();
+            batch.delete_cf(CF_BLOCK_FILTERS, block0.hash());
+            for block in [&block0, &side] {
+                batch.put_cf(CF_BLOCKS, block.hash(), [&[ENCODING_RAW], legacy_block_bytes(block).as_slice()].concat());
+            }
+            storage.store.write(batch).unwrap();
+            storage.store.put(CF_METADATA, KEY_SCHEMA_VERSION, &6u32.to_be_bytes()).unwrap();
+        }
+        let storage = StorageManager::new(dir.path()).unwrap();
+        assert_eq!(storage.get_block_filter(&block0.hash()).unwrap(), Some(BlockFilter::for_block(&block0)));
+    }
+
+    #[test]
+    fn test_address_index_follows_main_chain() {
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+        let alice = vec![0xa1];
+        let bob = vec![0xb0];
+        let block0 = Block::new([0u8; 32], vec![Transaction::new_transfer_native(alice.clone(), bob.clone(), 5)], 10, 0);
+        storage.save_block(&block0).unwrap();
+        let block1 = Block::new(block0.hash(), vec![
+            Transaction::new_transfer_native(bob.clone(), bob.clone(), 1),
+            Transaction::new_transfer_native(bob.clone(), alice.clone(), 2),
+        ], 10, 1);
+        storage.save_block(&block1).unwrap();
+
+        let bob_refs = storage.get_address_transactions(&bob).unwrap();
+        let positions: Vec<(u64, u32)> = bob_refs.iter().map(|r| (r.height, r.position)).collect();
+        assert_eq!(positions, vec![(0, 0), (1, 0), (1, 1)]);
+        assert_eq!(bob_refs[2].tx_hash, block1.transactions[1].calculate_hash());
+        assert_eq!(storage.get_address_transactions(&alice).unwrap().len(), 2);
+
+        // History pages run newest first, each continuing below the last entry of the previous one
+        let page = storage.get_address_history(&bob, None, 2).unwrap();
+        assert_eq!(page.iter().map(|r| (r.height, r.position)).collect::<Vec<_>>(), vec![(1, 1), (1, 0)]);
+        let page = storage.get_address_history(&bob, Some((1, 0)), 2).unwrap();
+        assert_eq!(page.iter().map(|r| (r.height, r.position)).collect::<Vec<_>>(), vec![(0, 0)]);
+        assert!(storage.get_address_history(&bob, Some((0, 0)), 2).unwrap().is_empty());
+
+        // Rolling back a block removes its entries
+        storage.rewind_main_chain(0, &block0.hash(), 1).unwrap();
+        assert_eq!(storage.get_address_transactions(&bob).unwrap().len(), 1);
+        assert_eq!(storage.get_address_transactions(&alice).unwrap()[0].height, 0);
+        assert!(storage.get_address_transactions(&vec![0xa1, 0x00]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_by_hash_prefix() {
+        let dir = tempdir().unwrap();
+        let mut storage = StorageManager::new(dir.path()).unwrap();
+        storage.set_tx_index(true);
+        let mut previous_hash = [0u8; 32];
+        let mut hashes = Vec::new();
+        for height in 0..20 {
+            let block = create_test_block(height, previous_hash, 1);
+            previous_hash = block.hash();
+            hashes.push(previous_hash);
+            storage.save_block(&block).unwrap();
+        }
+
+        let target = hex::encode(hashes[7]);
+        // Odd-length and upper-case prefixes match on nibbles
+        let found = storage.find_blocks_by_prefix(&target[..5].to_uppercase(), 10).unwrap();
+        assert!(found.contains(&hashes[7]));
+        assert!(found.iter().all(|hash| hex::encode(hash).starts_with(&target[..5])));
+        assert_eq!(storage.find_blocks_by_prefix(&target, 10).unwrap(), vec![hashes[7]]);
+        // A one-digit prefix matches about 1 in 16 blocks but the result is capped
+        assert!(storage.find_blocks_by_prefix(&target[..1], 1).unwrap().len() <= 1);
+
+        let block7 = storage.get_block_by_hash(&hashes[7]).unwrap().unwrap();
+        let tx_hash = block7.transactions[0].calculate_hash();
+        assert_eq!(storage.find_transactions_by_prefix(&hex::encode(tx_hash)[..12], 10).unwrap(), vec![tx_hash]);
+        assert!(storage.find_blocks_by_prefix("xyz", 10).is_err());
+        assert!(storage.find_blocks_by_prefix("", 10).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path().join("live")).unwrap();
+        let block0 = create_test_block(0, [0u8; 32], 2);
+        storage.save_block(&block0).unwrap();
+        storage.create_snapshot(dir.path().join("snapshot")).unwrap();
+        // Writes after the snapshot are not part of it
+        storage.save_block(&create_test_block(1, block0.hash(), 1)).unwrap();
+        assert!(storage.create_snapshot(dir.path().join("snapshot")).is_err());
+
+        StorageManager::restore_from_snapshot(dir.path().join("snapshot"), dir.path().join("replica")).unwrap();
+        let replica = StorageManager::new(dir.path().join("replica")).unwrap();
+        assert_eq!(replica.get_chain_height().unwrap(), Some(0));
+        assert_eq!(replica.get_block_by_hash(&block0.hash()).unwrap().unwrap().header, block0.header);
+        assert!(StorageManager::restore_from_snapshot(dir.path().join("snapshot"), dir.path().join("replica")).is_err());
+    }
+
+    #[test]
+    fn test_save_blocks_in_one_batch() {
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+        let mut blocks = Vec::new();
+        let mut previous_hash = [0u8; 32];
+        for height in 0..5 {
+            let block = create_test_block(height, previous_hash, 2);
+            previous_hash = block.hash();
+            blocks.push(block);
+        }
+        storage.save_blocks(&blocks).unwrap();
+        storage.save_chain_works(&blocks.iter().map(|b| (b.hash(), U256::from(b.header.height + 1))).collect::<Vec<_>>()).unwrap();
+
+        assert_eq!(storage.get_chain_height().unwrap(), Some(4));
+        assert_eq!(storage.get_last_block_hash().unwrap(), Some(previous_hash));
+        for block in &blocks {
+            assert_eq!(storage.get_hash_by_height(block.header.height).unwrap(), Some(block.hash()));
+        }
+        assert_eq!(storage.get_chain_work(&blocks[2].hash()).unwrap(), Some(U256::from(3)));
+        assert!(storage.save_blocks(&[]).is_ok());
+        assert_eq!(storage.get_chain_height().unwrap(), Some(4));
+    }
+
+    #[test]
+    fn test_iter_blocks_by_height_range() {
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+        let mut previous_hash = [0u8; 32];
+        for height in 0..6 {
+            let block = create_test_block(height, previous_hash, 1);
+            previous_hash = block.hash();
+            storage.save_block(&block).unwrap();
+        }
+        let heights = |range: std::ops::Range<u64>| storage.iter_blocks(range).map(|b| b.unwrap().header.height).collect::<Vec<_>>();
+        assert_eq!(heights(2..5), vec![2, 3, 4]);
+        assert_eq!(heights(4..100), vec![4, 5]);
+        assert!(heights(3..3).is_empty());
+        assert_eq!(storage.iter_blocks(..).count(), 6);
+        let headers: Vec<BlockHeader> = storage.iter_headers(5..=5).map(Result::unwrap).collect();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].calculate_hash(), previous_hash);
+    }
+
+    #[test]
+    fn test_get_block_by_time() {
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+        let mut previous_hash = [0u8; 32];
+        let mut blocks = Vec::new();
+        // Timestamps are not monotonic: block 2 claims an earlier time than block 1
+        for (height, timestamp) in [(0, 100), (1, 200), (2, 150), (3, 300)] {
+            let mut block = create_test_block(height, previous_hash, 1);
+            block.header.timestamp = timestamp;
+            previous_hash = block.hash();
+            storage.save_block(&block).unwrap();
+            blocks.push(block);
+        }
+        let height_at = |timestamp| storage.get_block_by_time(timestamp).unwrap().map(|block| block.header.height);
+        assert_eq!(height_at(99), None);
+        assert_eq!(height_at(100), Some(0));
+        assert_eq!(height_at(199), Some(2));
+        assert_eq!(height_at(250), Some(1));
+        assert_eq!(height_at(u64::MAX), Some(3));
+
+        storage.rewind_main_chain(2, &blocks[2].hash(), 3).unwrap();
+        assert_eq!(height_at(u64::MAX), Some(1));
+    }
+
+    #[test]
+    fn test_rollback_to_height() {
+        let dir = tempdir().unwrap();
+        let mut storage = StorageManager::new(dir.path()).unwrap();
+        storage.set_tx_index(true);
+        let mut blocks = Vec::new();
+        let mut previous_hash = [0u8; 32];
+        for height in 0..6 {
+            let mut block = create_test_block(height, previous_hash, 1);
+            match height {
+                1 => block.transactions.push(Transaction::new_stake(vec![9], 50)),
+                4 => block.transactions.push(Transaction::new_stake(vec![9], 25)),
+                5 => block.transactions.push(Transaction::new_create_token(vec![9], "Rolled".to_string(), "RLD".to_string(), 1)),
+                _ => {}
+            }
+            block.header.merkle_root = Block::calculate_merkle_root(&block.transactions);
+            previous_hash = block.hash();
+            storage.save_block(&block).unwrap();
+            storage.save_chain_work(&previous_hash, U256::from(height + 1)).unwrap();
+            blocks.push(block);
+        }
+        storage.save_stakes(&StakeTable::from([(vec![9], 75)])).unwrap();
+        let TransactionType::CreateToken { metadata } = blocks[5].transactions[1].transaction_type().clone() else { unreachable!() };
+        storage.save_token_metadata(&metadata).unwrap();
+        storage.save_finalized_height(4).unwrap();
+
+        assert_eq!(storage.rollback_to_height(2).unwrap(), 3);
+        assert_eq!(storage.get_chain_height().unwrap(), Some(2));
+        assert_eq!(storage.get_last_block_hash().unwrap(), Some(blocks[2].hash()));
+        assert_eq!(storage.get_finalized_height().unwrap(), Some(2));
+        assert_eq!(storage.get_stake(&vec![9]).unwrap(), 50);
+        assert!(storage.get_token_metadata(&metadata.metadata_hash).unwrap().is_none());
+        for block in &blocks[3..] {
+            let hash = block.hash();
+            assert!(storage.get_block_by_hash(&hash).unwrap().is_none());
+            assert!(storage.get_header_by_hash(&hash).unwrap().is_none());
+            assert!(storage.get_chain_work(&hash).unwrap().is_none());
+            assert!(storage.get_hash_by_height(block.header.height).unwrap().is_none());
+            assert!(storage.get_tx_location(&block.transactions[0].calculate_hash()).unwrap().is_none());
+        }
+        assert!(storage.get_block_by_hash(&blocks[2].hash()).unwrap().is_some());
+        assert_eq!(storage.rollback_to_height(5).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_storage_stats() {
+        let dir = tempdir().unwrap();
+        let offchain_dir = tempdir().unwrap();
+        std::fs::write(offchain_dir.path().join("payload"), [7u8; 100]).unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+        let mut previous_hash = [0u8; 32];
+        for height in 0..10 {
+            let block = create_test_block(height, previous_hash, 2);
+            previous_hash = block.hash();
+            storage.save_block(&block).unwrap();
+        }
+
+        let stats = storage.stats(Some(offchain_dir.path())).unwrap();
+        assert_eq!(stats.column_families.len(), COLUMN_FAMILIES.len());
+        let (_, blocks) = stats.column_families.iter().find(|(cf, _)| *cf == CF_BLOCKS).unwrap();
+        assert!(blocks.size_bytes > 0);
+        assert!(stats.database_size_bytes() >= blocks.size_bytes);
+        assert_eq!(stats.offchain, Some(DirectoryUsage { files: 1, size_bytes: 100 }));
+        assert_eq!(storage.stats(None).unwrap().offchain, None);
+    }
+
+    #[test]
+    fn test_mixed_block_encodings() {
+        let dir = tempdir().unwrap();
+        let mut previous_hash = [0u8; 32];
+        let mut hashes = Vec::new();
+        {
+            let storage = StorageManager::new(dir.path()).unwrap();
+            for height in 0..300 {
+                // Raw, then plain zstd, then zstd with a trained dictionary
+                if height == 100 {
+                    storage.set_block_compression(Some(3));
+                }
+                if height == 200 {
+                    assert!(storage.train_block_dictionary(200, 4096).unwrap());
+                    assert!(!storage.train_block_dictionary(200, 4096).unwrap());
+                }
+                let block = create_test_block(height, previous_hash, 8);
+                previous_hash = block.hash();
+                hashes.push(previous_hash);
+                storage.save_block(&block).unwrap();
+            }
+            let encodings: Vec<u8> = [0, 150, 250].iter().map(|&h| storage.store.get(CF_BLOCKS, &hashes[h]).unwrap().unwrap()[0]).collect();
+            assert_eq!(encodings, vec![ENCODING_RAW, compression::ENCODING_ZSTD, compression::ENCODING_ZSTD_DICTIONARY]);
+        }
+        // Reopened without compression, every block still reads back
+        let storage = StorageManager::new(dir.path()).unwrap();
+        assert!(storage.block_dictionary_trained());
+        for (height, hash) in hashes.iter().enumerate() {
+            assert_eq!(storage.get_block_by_hash(hash).unwrap().unwrap().header.height, height as u64);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sled")]
+    fn test_sled_backend_stores_chain() {
+        let dir = tempdir().unwrap();
+        let store = sled_store::SledStore::open(&dir.path().join("sled"), &COLUMN_FAMILIES).unwrap();
+        let storage = StorageManager::with_store(Box::new(store)).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+        let mut previous_hash = [0u8; 32];
+        for height in 0..3 {
+            let block = create_test_block(height, previous_hash, 2);
+            previous_hash = block.hash();
+            storage.save_block(&block).unwrap();
+        }
+        storage.rewind_main_chain(1, &storage.get_hash_by_height(1).unwrap().unwrap(), 2).unwrap();
+        assert_eq!(storage.get_chain_height().unwrap(), Some(1));
+        assert_eq!(storage.iter_blocks(..).count(), 2);
+        assert!(storage.get_block_by_hash(&previous_hash).unwrap().is_some());
+
+        storage.create_snapshot(dir.path().join("snapshot")).unwrap();
+        drop(storage);
+        StorageManager::restore_from_snapshot(dir.path().join("snapshot"), dir.path().join("replica")).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+//!
+//! # RocksDB Backend
+//! [`ChainStore`] on RocksDB, one RocksDB column family per store column family.
+
+use super::backend::{BatchOp, ChainStore, ColumnFamilyUsage, Direction, KvIter, WriteBatch, CF_DEFAULT};
+use super::StorageError;
+use log::info;
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, DB};
+use std::path::Path;
+
+impl From<rocksdb::Error> for StorageError {
+    fn from(err: rocksdb::Error) -> Self {
+        StorageError::Database(err.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct RocksDbStore {
+    db: DB,
+    column_families: Vec<&'static str>,
+}
+
+impl RocksDbStore {
+    /// Opens or creates the database at `path`, creating missing column families.
+    pub fn open(path: &Path, column_families: &[&'static str]) -> Result<Self, StorageError> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let descriptors = column_families.iter().map(|name| ColumnFamilyDescriptor::new(*name, Options::default()));
+        let db = DB::open_cf_descriptors(&opts, path, descriptors)?;
+        info!("RocksDB database opened successfully at {:?}", path);
+        Ok(RocksDbStore { db, column_families: column_families.to_vec() })
+    }
+
+    /// The handle of `name`; `None` stands for RocksDB's own default column family.
+    fn cf(&self, name: &str) -> Option<&ColumnFamily> {
+        if name == CF_DEFAULT {
+            return None;
+        }
+        Some(self.db.cf_handle(name).expect("column family is created in RocksDbStore::open"))
+    }
+
+    fn int_property(&self, cf: &str, name: &str) -> Result<u64, StorageError> {
+        let value = match self.cf(cf) {
+            Some(handle) => self.db.property_int_value_cf(handle, name)?,
+            None => self.db.property_int_value(name)?,
+        };
+        Ok(value.unwrap_or(0))
+    }
+}
+
+impl ChainStore for RocksDbStore {
+    fn backend_name(&self) -> &'static str {
+        "rocksdb"
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(match self.cf(cf) {
+            Some(handle) => self.db.get_cf(handle, key)?,
+            None => self.db.get(key)?,
+        })
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<(), StorageError> {
+        let mut rocks_batch = rocksdb::WriteBatch::default();
+        for op in batch.ops() {
+            match op {
+                BatchOp::Put { cf, key, value } => match self.cf(cf) {
+                    Some(handle) => rocks_batch.put_cf(handle, key, value),
+                    None => rocks_batch.put(key, value),
+                },
+                BatchOp::Delete { cf, key } => match self.cf(cf) {
+                    Some(handle) => rocks_batch.delete_cf(handle, key),
+                    None => rocks_batch.delete(key),
+                },
+            }
+        }
+        self.db.write(rocks_batch)?;
+        Ok(())
+    }
+
+    fn iter_from<'a>(&'a self, cf: &str, start: &[u8], direction: Direction) -> KvIter<'a> {
+        let direction = match direction {
+            Direction::Forward => rocksdb::Direction::Forward,
+            Direction::Reverse => rocksdb::Direction::Reverse,
+        };
+        let mode = IteratorMode::From(start, direction);
+        let iter = match self.cf(cf) {
+            Some(handle) => self.db.iterator_cf(handle, mode),
+            None => self.db.iterator(mode),
+        };
+        Box::new(iter.map(|item| item.map_err(StorageError::from)))
+    }
+
+    fn checkpoint(&self, path: &Path) -> Result<(), StorageError> {
+        Checkpoint::new(&self.db)?.create_checkpoint(path)?;
+        Ok(())
+    }
+
+    /// SST files plus memtables; the WAL is not counted.
+    fn usage(&self, cf: &str) -> Result<ColumnFamilyUsage, StorageError> {
+        Ok(ColumnFamilyUsage {
+            size_bytes: self.int_property(cf, "rocksdb.total-sst-files-size")? + self.int_property(cf, "rocksdb.cur-size-all-mem-tables")?,
+            estimated_keys: self.int_property(cf, "rocksdb.estimate-num-keys")?,
+        })
+    }
+
+    /// Syncs the WAL and writes every memtable out to SST files, so reopening replays nothing.
+    fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush_wal(true)?;
+        for name in &self.column_families {
+            match self.cf(name) {
+                Some(handle) => self.db.flush_cf(handle)?,
+                None => self.db.flush()?,
+            }
+        }
+        Ok(())
+    }
+}
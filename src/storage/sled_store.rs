@@ -0,0 +1,119 @@
+//!
+//! # sled Backend
+//! [`ChainStore`] on sled, a pure-Rust embedded database: one sled tree per column
+//! family, with the legacy default column family mapped to sled's default tree.
+//! Batches touching several column families run as one multi-tree transaction.
+
+use super::backend::{BatchOp, ChainStore, ColumnFamilyUsage, Direction, KvIter, WriteBatch, CF_DEFAULT};
+use super::StorageError;
+use log::info;
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use std::collections::HashMap;
+use std::path::Path;
+
+impl From<sled::Error> for StorageError {
+    fn from(err: sled::Error) -> Self {
+        StorageError::Database(err.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct SledStore {
+    db: sled::Db,
+    /// Trees in a fixed order, so batches can address them by index inside a transaction.
+    trees: Vec<sled::Tree>,
+    tree_index: HashMap<&'static str, usize>,
+}
+
+impl SledStore {
+    /// Opens or creates the database at `path`, opening a tree per column family.
+    pub fn open(path: &Path, column_families: &[&'static str]) -> Result<Self, StorageError> {
+        let db = sled::open(path)?;
+        let mut trees = vec![(*db).clone()];
+        let mut tree_index = HashMap::from([(CF_DEFAULT, 0)]);
+        for name in column_families {
+            tree_index.insert(*name, trees.len());
+            trees.push(db.open_tree(name)?);
+        }
+        info!("sled database opened successfully at {:?}", path);
+        Ok(SledStore { db, trees, tree_index })
+    }
+
+    fn tree(&self, name: &str) -> &sled::Tree {
+        &self.trees[*self.tree_index.get(name).expect("column family is opened in SledStore::open")]
+    }
+}
+
+impl ChainStore for SledStore {
+    fn backend_name(&self) -> &'static str {
+        "sled"
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.tree(cf).get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<(), StorageError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let result: Result<(), TransactionError<()>> = self.trees.as_slice().transaction(|trees| {
+            for op in batch.ops() {
+                match op {
+                    BatchOp::Put { cf, key, value } => {
+                        trees[self.tree_index[cf]].insert(key.as_slice(), value.as_slice())?;
+                    }
+                    BatchOp::Delete { cf, key } => {
+                        trees[self.tree_index[cf]].remove(key.as_slice())?;
+                    }
+                }
+            }
+            Ok::<(), ConflictableTransactionError<()>>(())
+        });
+        result.map_err(|e| StorageError::Database(format!("sled transaction failed: {:?}", e)))
+    }
+
+    fn iter_from<'a>(&'a self, cf: &str, start: &[u8], direction: Direction) -> KvIter<'a> {
+        let tree = self.tree(cf);
+        let iter: Box<dyn Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>>> = match direction {
+            Direction::Forward => Box::new(tree.range(start.to_vec()..)),
+            Direction::Reverse => Box::new(tree.range(..=start.to_vec()).rev()),
+        };
+        Box::new(iter.map(|item| {
+            let (key, value) = item?;
+            Ok((Box::from(key.as_ref()), Box::from(value.as_ref())))
+        }))
+    }
+
+    /// Copies every tree into a new database at `path`. Unlike a RocksDB checkpoint this is not
+    /// a single point in time, so callers should keep writers out while it runs.
+    fn checkpoint(&self, path: &Path) -> Result<(), StorageError> {
+        self.db.flush()?;
+        let target = sled::open(path)?;
+        for (name, &index) in &self.tree_index {
+            let target_tree = if *name == CF_DEFAULT { (*target).clone() } else { target.open_tree(name)? };
+            for item in self.trees[index].iter() {
+                let (key, value) = item?;
+                target_tree.insert(key, value)?;
+            }
+        }
+        target.flush()?;
+        Ok(())
+    }
+
+    /// sled has no per-tree size statistics, so this sums the key and value bytes of the tree.
+    fn usage(&self, cf: &str) -> Result<ColumnFamilyUsage, StorageError> {
+        let mut usage = ColumnFamilyUsage::default();
+        for item in self.tree(cf).iter() {
+            let (key, value) = item?;
+            usage.size_bytes += (key.len() + value.len()) as u64;
+            usage.estimated_keys += 1;
+        }
+        Ok(usage)
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+//!
+//! # Stratum Mining Server
+//! Optional TCP server that hands mining jobs to external workers (a simple Stratum dialect).
+//!
+//! The protocol is newline-delimited JSON. A worker sends
+//! `{"id": 1, "method": "mining.subscribe", "params": {"worker": "rig-1"}}` and then receives
+//! `mining.notify` messages carrying a job: a block header to grind the `nonce` of, the
+//! chain's PoW algorithm and a share target easier than the block target. Solutions are sent
+//! back with `{"id": 2, "method": "mining.submit", "params": {"job_id": 7, "nonce": 123}}`.
+//! Every hash under the share target counts as a share in the worker's statistics; one that
+//! also meets the block target completes the block, which is imported through
+//! `process_mined_block` and broadcast. A new job is pushed whenever the tip changes.
+
+use crate::consensus::{self, PowAlgorithm};
+use crate::core::{Block, Blockchain, BlockchainError};
+use crate::network::{NetworkMessage, OutboundSender};
+use crate::service::Shutdown;
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+/// Default share target: hashes with at least 16 leading zero bits.
+pub const DEFAULT_SHARE_BITS: u32 = 0x1f00_ffff;
+/// Interval between checks for a new tip.
+pub const TIP_POLL_INTERVAL_MS: u64 = 250;
+/// A fresh job (picking up new mempool transactions) is issued at least this often.
+pub const JOB_REFRESH_SECS: u64 = 30;
+/// Number of recent jobs that still accept submissions.
+const MAX_ACTIVE_JOBS: usize = 4;
+
+/// A unit of work pushed to workers.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub job_id: u64,
+    /// Unmined block; workers only change `header.nonce`.
+    pub block: Block,
+    /// Compact target a hash must meet to count as a share.
+    pub share_bits: u32,
+    pub pow_algorithm: PowAlgorithm,
+    /// Tip generation the template was built on; the job is stale once the tip moves.
+    tip_generation: u64,
+}
+
+impl Job {
+    fn notify_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "method": "mining.notify",
+            "params": {
+                "job_id": self.job_id,
+                "header": self.block.header,
+                "share_bits": self.share_bits,
+                "block_bits": self.block.header.bits,
+                "pow_algorithm": self.pow_algorithm.name(),
+                "clean_jobs": true,
+            }
+        })
+    }
+}
+
+/// Per-worker counters.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    pub accepted_shares: u64,
+    pub rejected_shares: u64,
+    pub blocks_found: u64,
+    /// UNIX time of the last accepted share.
+    pub last_share_at: Option<u64>,
+}
+
+/// Outcome of a `mining.submit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmitOutcome {
+    /// Valid share that does not meet the block target.
+    Share,
+    /// Valid share that completed a block, now part of the chain.
+    Block { height: u64 },
+}
+
+/// Reasons a submission is rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum StratumError {
+    #[error("Unknown or expired job {0}")]
+    UnknownJob(u64),
+    #[error("Job {0} is stale: the chain tip has moved")]
+    StaleJob(u64),
+    #[error("Duplicate share")]
+    DuplicateShare,
+    #[error("Hash does not meet the share target")]
+    LowDifficulty,
+    #[error("Block rejected: {0}")]
+    BlockRejected(#[from] BlockchainError),
+}
+
+#[derive(Deserialize, Debug)]
+struct StratumRequest {
+    id: Option<serde_json::Value>,
+    method: String,
+    #[serde(default)]
+    params: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct SubscribeParams {
+    worker: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SubmitParams {
+    job_id: u64,
+    nonce: u64,
+}
+
+#[derive(Debug, Default)]
+struct JobBook {
+    next_job_id: u64,
+    /// Recent jobs with the nonces already submitted for each.
+    active: Vec<(Job, HashSet<u64>)>,
+}
+
+/// Shared state of the mining server.
+pub struct StratumServer {
+    blockchain: Arc<Mutex<Blockchain>>,
+    outbound: Option<OutboundSender>,
+    share_bits: u32,
+    jobs: Mutex<JobBook>,
+    workers: Mutex<HashMap<String, WorkerStats>>,
+    notifier: broadcast::Sender<Job>,
+}
+
+impl StratumServer {
+    /// Creates a server issuing shares at `share_bits` (never harder than the block target).
+    /// Found blocks are broadcast through `outbound` when given.
+    pub fn new(blockchain: Arc<Mutex<Blockchain>>, outbound: Option<OutboundSender>, share_bits: u32) -> Arc<Self> {
+        let (notifier, _) = broadcast::channel(16);
+        Arc::new(StratumServer {
+            blockchain,
+            outbound,
+            share_bits,
+            jobs: Mutex::new(JobBook::default()),
+            workers: Mutex::new(HashMap::new()),
+            notifier,
+        })
+    }
+
+    /// Builds a job from a fresh block template and makes it the current one.
+    pub fn new_job(&self) -> Result<Job, BlockchainError> {
+        let (block, pow_algorithm, tip_generation) = {
+            let mut bc_guard = self.blockchain.lock().expect("Blockchain lock poisoned");
+            let tip_generation = bc_guard.tip_watch().generation();
+            (bc_guard.create_block_template()?, bc_guard.chain_config().pow_algorithm, tip_generation)
+        };
+        // Shares must be at least as easy as the block itself
+        let share_target = consensus::compact_to_target(self.share_bits);
+        let block_target = consensus::compact_to_target(block.header.bits);
+        let share_bits = if share_target > block_target { self.share_bits } else { block.header.bits };
+
+        let mut jobs = self.jobs.lock().expect("Job book poisoned");
+        jobs.next_job_id += 1;
+        let job = Job { job_id: jobs.next_job_id, block, share_bits, pow_algorithm, tip_generation };
+        jobs.active.push((job.clone(), HashSet::new()));
+        if jobs.active.len() > MAX_ACTIVE_JOBS {
+            jobs.active.remove(0);
+        }
+        drop(jobs);
+        debug!("Issued stratum job {} for block {}", job.job_id, job.block.header.height);
+        // No subscribers is fine: workers get the current job when they subscribe
+        let _ = self.notifier.send(job.clone());
+        Ok(job)
+    }
+
+    /// The most recently issued job.
+    pub fn current_job(&self) -> Option<Job> {
+        self.jobs.lock().expect("Job book poisoned").active.last().map(|(job, _)| job.clone())
+    }
+
+    /// Checks a worker's solution, updates its statistics and imports the block if one was found.
+    pub fn submit(&self, worker: &str, job_id: u64, nonce: u64) -> Result<SubmitOutcome, StratumError> {
+        let result = self.check_submission(job_id, nonce);
+        let mut workers = self.workers.lock().expect("Worker stats poisoned");
+        let stats = workers.entry(worker.to_string()).or_default();
+        match &result {
+            Ok(outcome) => {
+                stats.accepted_shares += 1;
+                stats.last_share_at = Some(crate::core::time::unix_now());
+                if let SubmitOutcome::Block { height } = outcome {
+                    stats.blocks_found += 1;
+                    info!("Worker {} found block {}", worker, height);
+                }
+            }
+            Err(e) => {
+                stats.rejected_shares += 1;
+                debug!("Rejected share from worker {}: {}", worker, e);
+            }
+        }
+        result
+    }
+
+    fn check_submission(&self, job_id: u64, nonce: u64) -> Result<SubmitOutcome, StratumError> {
+        let block = {
+            let mut jobs = self.jobs.lock().expect("Job book poisoned");
+            let (job, submitted) = jobs.active.iter_mut()
+                .find(|(job, _)| job.job_id == job_id)
+                .ok_or(StratumError::UnknownJob(job_id))?;
+            let tip_watch = self.blockchain.lock().expect("Blockchain lock poisoned").tip_watch();
+            if tip_watch.changed_since(job.tip_generation) {
+                return Err(StratumError::StaleJob(job_id));
+            }
+            if !submitted.insert(nonce) {
+                return Err(StratumError::DuplicateShare);
+            }
+            let mut block = job.block.clone();
+            block.header.nonce = nonce;
+            let pow_hash = job.pow_algorithm.hash_header(&block.header);
+            if !consensus::verify_pow(&pow_hash, job.share_bits) {
+                return Err(StratumError::LowDifficulty);
+            }
+            if !consensus::verify_pow(&pow_hash, block.header.bits) {
+                return Ok(SubmitOutcome::Share);
+            }
+            block
+        };
+
+        let height = block.header.height;
+        self.blockchain.lock().expect("Blockchain lock poisoned").process_mined_block(block.clone())?;
+        if let Some(outbound) = &self.outbound {
+            if outbound.send(NetworkMessage::NewBlock(block)).is_err() {
+                warn!("Network node is not running; stratum block {} was not broadcast", height);
+            }
+        }
+        // The tip moved: hand out work on top of the new block right away
+        if let Err(e) = self.new_job() {
+            warn!("Failed to issue stratum job after block {}: {}", height, e);
+        }
+        Ok(SubmitOutcome::Block { height })
+    }
+
+    /// Statistics of every worker that has submitted at least once.
+    pub fn worker_stats(&self) -> HashMap<String, WorkerStats> {
+        self.workers.lock().expect("Worker stats poisoned").clone()
+    }
+
+    /// Accepts workers on `bind_address` and keeps their jobs current. Runs until an I/O error,
+    /// or until `shutdown` is triggered: then workers are disconnected, so no more solutions
+    /// are taken, and `Ok(())` is returned.
+    pub async fn run(self: Arc<Self>, bind_address: String, shutdown: Shutdown) -> std::io::Result<()> {
+        let listener = TcpListener::bind(&bind_address).await?;
+        info!("Stratum mining server listening on {}", bind_address);
+        tokio::spawn(self.clone().refresh_jobs(shutdown.clone()));
+        loop {
+            let (stream, peer_addr) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = shutdown.wait() => {
+                    info!("Stratum mining server stopped.");
+                    return Ok(());
+                }
+            };
+            debug!("Stratum connection from {}", peer_addr);
+            let server = self.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream, shutdown).await {
+                    debug!("Stratum connection from {} closed: {}", peer_addr, e);
+                }
+            });
+        }
+    }
+
+    /// Issues a new job when the tip changes or the current job gets old.
+    async fn refresh_jobs(self: Arc<Self>, shutdown: Shutdown) {
+        let tip_watch = self.blockchain.lock().expect("Blockchain lock poisoned").tip_watch();
+        let mut last_issued = std::time::Instant::now();
+        let mut interval = tokio::time::interval(Duration::from_millis(TIP_POLL_INTERVAL_MS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.wait() => return,
+            }
+            let current = self.current_job();
+            let stale = current.as_ref().is_none_or(|job| tip_watch.changed_since(job.tip_generation));
+            if stale || last_issued.elapsed() >= Duration::from_secs(JOB_REFRESH_SECS) {
+                match self.new_job() {
+                    Ok(_) => last_issued = std::time::Instant::now(),
+                    Err(e) => warn!("Failed to issue stratum job: {}", e),
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(self: Arc<Self>, stream: TcpStream, shutdown: Shutdown) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut jobs = self.notifier.subscribe();
+        let mut worker: Option<String> = None;
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let line = match line? {
+                        Some(line) => line,
+                        None => return Ok(()),
+                    };
+                    let (reply, subscribed) = self.handle_request(&line, &mut worker);
+                    write_message(&mut writer, &reply).await?;
+                    if subscribed {
+                        if let Some(job) = self.current_job() {
+                            write_message(&mut writer, &job.notify_message()).await?;
+                        }
+                    }
+                }
+                job = jobs.recv() => match job {
+                    Ok(job) if worker.is_some() => write_message(&mut writer, &job.notify_message()).await?,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                _ = shutdown.wait() => return Ok(()),
+            }
+        }
+    }
+
+    /// Handles one request line; returns the reply and whether the worker just subscribed.
+    fn handle_request(&self, line: &str, worker: &mut Option<String>) -> (serde_json::Value, bool) {
+        let request: StratumRequest = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return (reply(None, Err(format!("Malformed request: {}", e))), false),
+        };
+        match request.method.as_str() {
+            "mining.subscribe" => match serde_json::from_value::<SubscribeParams>(request.params) {
+                Ok(params) => {
+                    info!("Stratum worker {} subscribed", params.worker);
+                    *worker = Some(params.worker);
+                    (reply(request.id, Ok(serde_json::json!(true))), true)
+                }
+                Err(e) => (reply(request.id, Err(format!("Invalid params: {}", e))), false),
+            },
+            "mining.submit" => {
+                let worker_name = match worker {
+                    Some(name) => name.clone(),
+                    None => return (reply(request.id, Err("Not subscribed".to_string())), false),
+                };
+                let outcome = serde_json::from_value::<SubmitParams>(request.params)
+                    .map_err(|e| format!("Invalid params: {}", e))
+                    .and_then(|params| self.submit(&worker_name, params.job_id, params.nonce).map_err(|e| e.to_string()));
+                let result = outcome.map(|outcome| match outcome {
+                    SubmitOutcome::Share => serde_json::json!({ "accepted": true, "block": false }),
+                    SubmitOutcome::Block { height } => serde_json::json!({ "accepted": true, "block": true, "height": height }),
+                });
+                (reply(request.id, result), false)
+            }
+            other => (reply(request.id, Err(format!("Unknown method '{}'", other))), false),
+        }
+    }
+}
+
+fn reply(id: Option<serde_json::Value>, result: Result<serde_json::Value, String>) -> serde_json::Value {
+    match result {
+        Ok(result) => serde_json::json!({ "id": id, "result": result, "error": null }),
+        Err(message) => serde_json::json!({ "id": id, "result": null, "error": message }),
+    }
+}
+
+async fn write_message(writer: &mut tokio::net::tcp::OwnedWriteHalf, message: &serde_json::Value) -> std::io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // Finds a nonce whose hash meets `share_bits` but, if `want_block` is false, not the block target
+    fn find_nonce(job: &Job, want_block: bool) -> u64 {
+        let mut header = job.block.header.clone();
+        loop {
+            let hash = job.pow_algorithm.hash_header(&header);
+            let is_block = consensus::verify_pow(&hash, header.bits);
+            if consensus::verify_pow(&hash, job.share_bits) && is_block == want_block {
+                return header.nonce;
+            }
+            header.nonce += 1;
+        }
+    }
+
+    #[test]
+    fn test_stratum_shares_and_blocks() {
+        let dir = tempdir().unwrap();
+        let mut blockchain = Blockchain::new(dir.path()).unwrap();
+        blockchain.initialize_genesis_if_needed().unwrap();
+        let blockchain = Arc::new(Mutex::new(blockchain));
+        // A share target easier than the genesis PoW limit (4 zero bits), so plain shares exist
+        let server = StratumServer::new(blockchain.clone(), None, consensus::bits_for_leading_zeros(2));
+
+        let job = server.new_job().unwrap();
+        let block_nonce = find_nonce(&job, true);
+        let share_nonce = find_nonce(&job, false);
+        assert_eq!(server.submit("rig", job.job_id, share_nonce).unwrap(), SubmitOutcome::Share);
+        assert!(matches!(server.submit("rig", job.job_id, share_nonce), Err(StratumError::DuplicateShare)));
+        assert!(matches!(server.submit("rig", job.job_id + 100, 0), Err(StratumError::UnknownJob(_))));
+
+        assert_eq!(server.submit("rig", job.job_id, block_nonce).unwrap(), SubmitOutcome::Block { height: 1 });
+        assert_eq!(blockchain.lock().unwrap().get_chain_height(), Some(1));
+        // The old job now builds on a replaced tip
+        assert!(matches!(server.submit("rig", job.job_id, block_nonce + 1), Err(StratumError::StaleJob(_))));
+        assert_eq!(server.current_job().unwrap().block.header.height, 2);
+
+        let stats = &server.worker_stats()["rig"];
+        assert_eq!((stats.accepted_shares, stats.rejected_shares, stats.blocks_found), (2, 3, 1));
+    }
+}
@@ -0,0 +1,190 @@
+//!
+//! # Service Supervision
+//! Runs the node's long-lived subsystems (RPC server, network node, Stratum server,
+//! background jobs) as tokio tasks watched by a [`Supervisor`].
+//!
+//! A service that panics, returns an error or stops on its own is logged and, depending
+//! on its [`RestartPolicy`], started again after a backoff or reported as a
+//! [`ServiceFailure`], on which the node shuts down cleanly instead of running on without
+//! it. A service that keeps failing soon after each restart is given up on as well. On
+//! shutdown the services are stopped one at a time, in the order they were spawned.
+
+use crate::service::{shutdown_channel, Shutdown, ShutdownTrigger};
+use log::{debug, error, warn};
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::{JoinError, JoinHandle};
+
+/// Restarts in a row after which a failing service is given up on.
+pub const MAX_QUICK_RESTARTS: u32 = 5;
+/// A service that ran this long before failing is restarted as if for the first time.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+/// Wait before the first restart; it doubles with each further restart in a row.
+const FIRST_BACKOFF: Duration = if cfg!(test) { Duration::from_millis(1) } else { Duration::from_secs(1) };
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What the supervisor does when a service fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Start it again after a backoff, up to [`MAX_QUICK_RESTARTS`] times in a row.
+    Restart,
+    /// Report it as a [`ServiceFailure`]: the node can't run without it.
+    Fatal,
+}
+
+/// A service that stopped for good.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceFailure {
+    pub service: &'static str,
+    pub reason: String,
+}
+
+impl fmt::Display for ServiceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} service failed: {}", self.service, self.reason)
+    }
+}
+
+struct Supervised {
+    name: &'static str,
+    trigger: ShutdownTrigger,
+    task: JoinHandle<()>,
+}
+
+/// Owns the node's services; see the module documentation.
+pub struct Supervisor {
+    services: Vec<Supervised>,
+    failures_tx: mpsc::UnboundedSender<ServiceFailure>,
+    failures: mpsc::UnboundedReceiver<ServiceFailure>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        let (failures_tx, failures) = mpsc::unbounded_channel();
+        Supervisor { services: Vec::new(), failures_tx, failures }
+    }
+
+    /// Runs the service `name`, calling `start` for each (re)start with the handle that tells
+    /// it to stop. The service should return `Ok(())` once that handle is triggered, and only then.
+    pub fn spawn<F, Fut>(&mut self, name: &'static str, policy: RestartPolicy, mut start: F)
+    where
+        F: FnMut(Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let (trigger, shutdown) = shutdown_channel();
+        let failures = self.failures_tx.clone();
+        let task = tokio::spawn(async move {
+            let mut quick_restarts = 0;
+            loop {
+                let started = Instant::now();
+                // Run in a task of its own so a panic surfaces here as a `JoinError`
+                let outcome = tokio::spawn(start(shutdown.clone())).await;
+                let reason = match outcome {
+                    Ok(Ok(())) if shutdown.is_triggered() => return,
+                    Ok(Ok(())) => "stopped unexpectedly".to_string(),
+                    Ok(Err(e)) => e,
+                    Err(e) => panic_reason(e),
+                };
+                if shutdown.is_triggered() {
+                    warn!("Service {} failed while stopping: {}", name, reason);
+                    return;
+                }
+                error!("Service {} failed: {}", name, reason);
+
+                if started.elapsed() >= STABLE_RUN {
+                    quick_restarts = 0;
+                }
+                if policy == RestartPolicy::Fatal || quick_restarts >= MAX_QUICK_RESTARTS {
+                    let _ = failures.send(ServiceFailure { service: name, reason });
+                    return;
+                }
+                let backoff = FIRST_BACKOFF.saturating_mul(1 << quick_restarts).min(MAX_BACKOFF);
+                quick_restarts += 1;
+                warn!("Restarting service {} in {:?} (restart {} of {})", name, backoff, quick_restarts, MAX_QUICK_RESTARTS);
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = shutdown.wait() => return,
+                }
+            }
+        });
+        self.services.push(Supervised { name, trigger, task });
+    }
+
+    /// Resolves when a service has failed for good; the node should then shut down.
+    pub async fn failure(&mut self) -> ServiceFailure {
+        self.failures.recv().await.expect("The supervisor keeps a sender")
+    }
+
+    /// Stops the services one at a time, in spawn order, each finishing before the next is told to.
+    pub async fn shutdown(self) {
+        for service in self.services {
+            debug!("Stopping service {}", service.name);
+            service.trigger.trigger();
+            if let Err(e) = service.task.await {
+                warn!("Supervisor of service {} ended abnormally: {}", service.name, e);
+            }
+        }
+    }
+}
+
+fn panic_reason(error: JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+    let payload = error.into_panic();
+    let message = payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string payload");
+    format!("panicked: {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    #[test]
+    fn test_supervisor_restarts_and_gives_up() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut supervisor = Supervisor::new();
+
+            // Panics twice, then runs until told to stop
+            let starts = Arc::new(AtomicU32::new(0));
+            let running = Arc::new(Notify::new());
+            let (counter, started) = (starts.clone(), running.clone());
+            supervisor.spawn("flaky", RestartPolicy::Restart, move |shutdown| {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                let started = started.clone();
+                async move {
+                    if attempt < 2 {
+                        panic!("attempt {}", attempt);
+                    }
+                    started.notify_one();
+                    shutdown.wait().await;
+                    Ok(())
+                }
+            });
+            supervisor.spawn("broken", RestartPolicy::Restart, |_| async { Err("cannot start".to_string()) });
+
+            let failure = supervisor.failure().await;
+            assert_eq!(failure, ServiceFailure { service: "broken", reason: "cannot start".to_string() });
+            running.notified().await;
+            assert_eq!(starts.load(Ordering::SeqCst), 3);
+
+            supervisor.spawn("essential", RestartPolicy::Fatal, |_| async { panic!("boom") });
+            assert_eq!(supervisor.failure().await.reason, "panicked: boom");
+            supervisor.shutdown().await;
+        });
+    }
+}
@@ -0,0 +1,268 @@
+//!
+//! # Tenants
+//! Multi-tenant access to the RPC API for gateway operators.
+//!
+//! Each tenant is identified by an API key and has a monthly budget of payload bytes
+//! it may store and a per-minute request budget. Monthly usage counters are persisted
+//! in the node's storage so they survive restarts and can be read by billing systems
+//! through `get_tenant_usage`. When no tenants are configured the API stays open.
+
+use crate::storage::{StorageError, StorageManager};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Length of the request-rate window, in seconds.
+pub const RATE_WINDOW_SECS: u64 = 60;
+
+/// A tenant as declared in the tenants file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TenantConfig {
+    /// Stable identifier used in usage records.
+    pub id: String,
+    /// Secret presented in the `X-API-Key` header.
+    pub api_key: String,
+    /// Payload bytes the tenant may store per calendar month (UTC).
+    pub payload_quota_bytes: u64,
+    /// Requests allowed per `RATE_WINDOW_SECS` window.
+    pub requests_per_minute: u32,
+    /// Admin tenants may read other tenants' usage (billing integrations).
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// Usage counters for one tenant and one calendar month, as persisted in storage.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantUsage {
+    /// Accepted requests.
+    pub requests: u64,
+    /// Requests refused because the rate budget was exhausted.
+    pub rate_limited_requests: u64,
+    /// Payload bytes stored (off-chain payloads and token metadata).
+    pub payload_bytes: u64,
+}
+
+/// Errors raised while authenticating or accounting a tenant request.
+#[derive(Debug, thiserror::Error)]
+pub enum TenantError {
+    #[error("Missing or unknown API key")]
+    Unauthorized,
+    #[error("Unknown tenant: {0}")]
+    UnknownTenant(String),
+    #[error("Rate limit of {limit} requests per minute exceeded")]
+    RateLimited { limit: u32 },
+    #[error("Monthly payload quota exceeded: {used} of {quota} bytes used, {requested} requested")]
+    QuotaExceeded { used: u64, requested: u64, quota: u64 },
+    #[error("Invalid tenants configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+/// Holds the configured tenants and their in-memory rate windows.
+#[derive(Debug)]
+pub struct TenantManager {
+    /// Tenants keyed by the SHA-256 of their API key, so lookups don't branch on the secret itself.
+    by_key_hash: HashMap<[u8; 32], TenantConfig>,
+    /// Current rate window per tenant id: (window start, requests in window).
+    windows: Mutex<HashMap<String, (u64, u32)>>,
+}
+
+fn hash_api_key(api_key: &str) -> [u8; 32] {
+    Sha256::digest(api_key.as_bytes()).into()
+}
+
+/// Calendar month (UTC) of a UNIX timestamp as `YYYY-MM`.
+pub fn month_of(unix_secs: u64) -> String {
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}", year, month)
+}
+
+impl TenantManager {
+    /// Builds a manager from tenant declarations, rejecting duplicate ids or keys.
+    pub fn new(tenants: Vec<TenantConfig>) -> Result<Self, TenantError> {
+        let mut by_key_hash = HashMap::new();
+        let mut ids = std::collections::HashSet::new();
+        for tenant in tenants {
+            if tenant.id.is_empty() || tenant.id.contains('\0') {
+                return Err(TenantError::InvalidConfig(format!("Invalid tenant id {:?}", tenant.id)));
+            }
+            if tenant.api_key.is_empty() {
+                return Err(TenantError::InvalidConfig(format!("Tenant {} has an empty API key", tenant.id)));
+            }
+            if !ids.insert(tenant.id.clone()) {
+                return Err(TenantError::InvalidConfig(format!("Duplicate tenant id {}", tenant.id)));
+            }
+            if by_key_hash.insert(hash_api_key(&tenant.api_key), tenant.clone()).is_some() {
+                return Err(TenantError::InvalidConfig(format!("Tenant {} reuses another tenant's API key", tenant.id)));
+            }
+        }
+        Ok(TenantManager { by_key_hash, windows: Mutex::new(HashMap::new()) })
+    }
+
+    /// Loads tenant declarations from a JSON file containing an array of `TenantConfig`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TenantError> {
+        let contents = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| TenantError::InvalidConfig(format!("Cannot read {:?}: {}", path.as_ref(), e)))?;
+        let tenants: Vec<TenantConfig> = serde_json::from_str(&contents)
+            .map_err(|e| TenantError::InvalidConfig(format!("Cannot parse {:?}: {}", path.as_ref(), e)))?;
+        Self::new(tenants)
+    }
+
+    /// Resolves the tenant presenting `api_key`.
+    pub fn authenticate(&self, api_key: Option<&str>) -> Result<&TenantConfig, TenantError> {
+        api_key
+            .and_then(|key| self.by_key_hash.get(&hash_api_key(key)))
+            .ok_or(TenantError::Unauthorized)
+    }
+
+    /// Looks up a tenant by id.
+    pub fn tenant(&self, tenant_id: &str) -> Option<&TenantConfig> {
+        self.by_key_hash.values().find(|t| t.id == tenant_id)
+    }
+
+    /// Counts a request against the tenant's rate budget and monthly usage.
+    ///
+    /// Refused requests are still recorded (as `rate_limited_requests`) before the error is returned.
+    pub fn record_request(&self, storage: &StorageManager, tenant: &TenantConfig, now: u64) -> Result<(), TenantError> {
+        let allowed = {
+            let mut windows = self.windows.lock().expect("Tenant windows lock poisoned");
+            let window_start = now - now % RATE_WINDOW_SECS;
+            let window = windows.entry(tenant.id.clone()).or_insert((window_start, 0));
+            if window.0 != window_start {
+                *window = (window_start, 0);
+            }
+            if window.1 < tenant.requests_per_minute {
+                window.1 += 1;
+                true
+            } else {
+                false
+            }
+        };
+
+        let month = month_of(now);
+        let mut usage = storage.get_tenant_usage(&tenant.id, &month)?.unwrap_or_default();
+        if allowed {
+            usage.requests += 1;
+        } else {
+            usage.rate_limited_requests += 1;
+        }
+        storage.save_tenant_usage(&tenant.id, &month, &usage)?;
+        if allowed {
+            Ok(())
+        } else {
+            Err(TenantError::RateLimited { limit: tenant.requests_per_minute })
+        }
+    }
+
+    /// Charges `bytes` of stored payload to the tenant, failing without charging if it would exceed the quota.
+    pub fn charge_payload(&self, storage: &StorageManager, tenant_id: &str, bytes: u64, now: u64) -> Result<(), TenantError> {
+        let tenant = self.tenant(tenant_id).ok_or_else(|| TenantError::UnknownTenant(tenant_id.to_string()))?;
+        let month = month_of(now);
+        let mut usage = storage.get_tenant_usage(tenant_id, &month)?.unwrap_or_default();
+        if usage.payload_bytes.saturating_add(bytes) > tenant.payload_quota_bytes {
+            return Err(TenantError::QuotaExceeded {
+                used: usage.payload_bytes,
+                requested: bytes,
+                quota: tenant.payload_quota_bytes,
+            });
+        }
+        usage.payload_bytes += bytes;
+        storage.save_tenant_usage(tenant_id, &month, &usage)?;
+        Ok(())
+    }
+
+    /// Gives back `bytes` charged at `charged_at` by [`TenantManager::charge_payload`], for a
+    /// payload that could not be stored after all.
+    pub fn refund_payload(&self, storage: &StorageManager, tenant_id: &str, bytes: u64, charged_at: u64) -> Result<(), TenantError> {
+        if self.tenant(tenant_id).is_none() {
+            return Err(TenantError::UnknownTenant(tenant_id.to_string()));
+        }
+        let month = month_of(charged_at);
+        let mut usage = storage.get_tenant_usage(tenant_id, &month)?.unwrap_or_default();
+        usage.payload_bytes = usage.payload_bytes.saturating_sub(bytes);
+        storage.save_tenant_usage(tenant_id, &month, &usage)?;
+        Ok(())
+    }
+
+    /// Usage of `tenant_id` in `month` (`YYYY-MM`); zero counters if nothing was recorded.
+    pub fn usage(&self, storage: &StorageManager, tenant_id: &str, month: &str) -> Result<TenantUsage, TenantError> {
+        if self.tenant(tenant_id).is_none() {
+            return Err(TenantError::UnknownTenant(tenant_id.to_string()));
+        }
+        Ok(storage.get_tenant_usage(tenant_id, month)?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn tenant(id: &str, key: &str) -> TenantConfig {
+        TenantConfig {
+            id: id.to_string(),
+            api_key: key.to_string(),
+            payload_quota_bytes: 100,
+            requests_per_minute: 2,
+            admin: false,
+        }
+    }
+
+    #[test]
+    fn test_month_of() {
+        assert_eq!(month_of(0), "1970-01");
+        assert_eq!(month_of(951_782_400), "2000-02"); // 2000-02-29
+        assert_eq!(month_of(1_798_761_599), "2026-12"); // 2026-12-31T23:59:59Z
+        assert_eq!(month_of(1_798_761_600), "2027-01");
+    }
+
+    #[test]
+    fn test_authenticate_and_reject_duplicates() {
+        let manager = TenantManager::new(vec![tenant("acme", "k1"), tenant("globex", "k2")]).unwrap();
+        assert_eq!(manager.authenticate(Some("k2")).unwrap().id, "globex");
+        assert!(matches!(manager.authenticate(Some("nope")), Err(TenantError::Unauthorized)));
+        assert!(matches!(manager.authenticate(None), Err(TenantError::Unauthorized)));
+
+        assert!(TenantManager::new(vec![tenant("a", "k"), tenant("b", "k")]).is_err());
+        assert!(TenantManager::new(vec![tenant("a", "k1"), tenant("a", "k2")]).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_and_quota_are_persisted() {
+        let dir = tempdir().unwrap();
+        let storage = StorageManager::new(dir.path()).unwrap();
+        let manager = TenantManager::new(vec![tenant("acme", "k1")]).unwrap();
+        let acme = manager.authenticate(Some("k1")).unwrap().clone();
+        let now = 1_760_400_000; // 2025-10
+
+        assert!(manager.record_request(&storage, &acme, now).is_ok());
+        assert!(manager.record_request(&storage, &acme, now + 1).is_ok());
+        assert!(matches!(manager.record_request(&storage, &acme, now + 2), Err(TenantError::RateLimited { limit: 2 })));
+        // A new window restores the budget
+        assert!(manager.record_request(&storage, &acme, now + RATE_WINDOW_SECS).is_ok());
+
+        assert!(manager.charge_payload(&storage, "acme", 60, now).is_ok());
+        assert!(matches!(manager.charge_payload(&storage, "acme", 41, now), Err(TenantError::QuotaExceeded { .. })));
+        assert!(manager.charge_payload(&storage, "acme", 40, now).is_ok());
+        // A refunded charge frees its bytes again
+        assert!(manager.refund_payload(&storage, "acme", 40, now).is_ok());
+        assert!(manager.charge_payload(&storage, "acme", 40, now).is_ok());
+
+        let month = month_of(now);
+        let usage = TenantManager::new(vec![tenant("acme", "k1")]).unwrap().usage(&storage, "acme", &month).unwrap();
+        assert_eq!(usage, TenantUsage { requests: 3, rate_limited_requests: 1, payload_bytes: 100 });
+        assert_eq!(manager.usage(&storage, "acme", "1999-01").unwrap(), TenantUsage::default());
+        assert!(manager.usage(&storage, "initech", &month).is_err());
+    }
+}
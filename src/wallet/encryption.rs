@@ -0,0 +1,170 @@
+//!
+//! # Keystore Encryption
+//! Password protection for the secret half of the keystore.
+//!
+//! The password is stretched with scrypt (RFC 7914) under a random salt into an AES-256-GCM
+//! key, which seals the secrets under a fresh random nonce each time they are saved. The
+//! GCM tag makes a wrong password, or a tampered file, fail to decrypt instead of yielding
+//! garbage keys.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// scrypt cost (log2 of N) for new keystores: 32 MiB of memory per derivation with r = 8.
+const SCRYPT_LOG_N: u8 = if cfg!(test) { 10 } else { 15 };
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// Most memory a keystore file may ask scrypt for, so a crafted one can't exhaust the node's.
+const MAX_SCRYPT_MEMORY: u64 = 256 * 1024 * 1024;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum EncryptionError {
+    #[error("Wrong password (or the encrypted keystore was modified)")]
+    WrongPassword,
+    #[error("Unsupported key derivation {0:?}")]
+    UnsupportedKdf(String),
+    #[error("Malformed encrypted data: {0}")]
+    Malformed(String),
+}
+
+/// Secrets sealed under a password, as stored in the keys file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedData {
+    kdf: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    /// Hex-encoded scrypt salt.
+    salt: String,
+    /// Hex-encoded AES-GCM nonce.
+    nonce: String,
+    /// Hex-encoded ciphertext, including the GCM tag.
+    ciphertext: String,
+}
+
+/// AES key derived from a password, kept while the keystore is unlocked to reseal it on save.
+#[derive(Clone)]
+pub struct SealingKey {
+    key: [u8; 32],
+    salt: [u8; SALT_LEN],
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl std::fmt::Debug for SealingKey {
+    // Never prints the key
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SealingKey(scrypt log_n={})", self.log_n)
+    }
+}
+
+impl SealingKey {
+    /// Derives a key from `password` under a new random salt.
+    pub fn new(password: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        Self::derive(password, salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+    }
+
+    fn derive(password: &str, salt: [u8; SALT_LEN], log_n: u8, r: u32, p: u32) -> Self {
+        let mut key = [0u8; 32];
+        scrypt(password.as_bytes(), &salt, log_n, r, p, &mut key);
+        SealingKey { key, salt, log_n, r, p }
+    }
+
+    /// Encrypts `plaintext` under a fresh nonce.
+    pub fn seal(&self, plaintext: &[u8]) -> EncryptedData {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let cipher = Aes256Gcm::new(&self.key.into());
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext).expect("AES-GCM encrypts any length we store");
+        EncryptedData {
+            kdf: "scrypt".to_string(),
+            log_n: self.log_n,
+            r: self.r,
+            p: self.p,
+            salt: hex::encode(self.salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        }
+    }
+}
+
+impl EncryptedData {
+    /// Decrypts with `password`, returning the plaintext and the key to reseal it with.
+    pub fn open(&self, password: &str) -> Result<(Vec<u8>, SealingKey), EncryptionError> {
+        if self.kdf != "scrypt" {
+            return Err(EncryptionError::UnsupportedKdf(self.kdf.clone()));
+        }
+        let valid = (1..32).contains(&self.log_n) && self.r > 0 && (1..=16).contains(&self.p)
+            && (128 * self.r as u128) << self.log_n <= MAX_SCRYPT_MEMORY as u128
+            && ::scrypt::Params::new(self.log_n, self.r, self.p, 32).is_ok();
+        if !valid {
+            return Err(EncryptionError::Malformed(format!("scrypt parameters log_n={} r={} p={}", self.log_n, self.r, self.p)));
+        }
+        let salt: [u8; SALT_LEN] = decode_hex(&self.salt, "salt")?.try_into()
+            .map_err(|_| EncryptionError::Malformed("salt length".to_string()))?;
+        let nonce = decode_hex(&self.nonce, "nonce")?;
+        if nonce.len() != NONCE_LEN {
+            return Err(EncryptionError::Malformed("nonce length".to_string()));
+        }
+        let ciphertext = decode_hex(&self.ciphertext, "ciphertext")?;
+
+        let key = SealingKey::derive(password, salt, self.log_n, self.r, self.p);
+        let plaintext = Aes256Gcm::new(&key.key.into())
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| EncryptionError::WrongPassword)?;
+        Ok((plaintext, key))
+    }
+}
+
+fn decode_hex(value: &str, field: &str) -> Result<Vec<u8>, EncryptionError> {
+    hex::decode(value).map_err(|_| EncryptionError::Malformed(format!("{} is not hex", field)))
+}
+
+/// scrypt with N = 2^`log_n`, filling `output`.
+fn scrypt(password: &[u8], salt: &[u8], log_n: u8, r: u32, p: u32, output: &mut [u8]) {
+    let params = ::scrypt::Params::new(log_n, r, p, output.len()).expect("scrypt parameters are checked before deriving");
+    ::scrypt::scrypt(password, salt, &params, output).expect("output length is accepted by scrypt");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrypt_vector() {
+        // RFC 7914, section 12
+        let mut output = [0u8; 64];
+        scrypt(b"password", b"NaCl", 10, 8, 16, &mut output);
+        assert_eq!(
+            hex::encode(output),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b3731622eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = SealingKey::new("correct horse");
+        let sealed = key.seal(b"secret keys");
+        assert_ne!(key.seal(b"secret keys").nonce, sealed.nonce);
+
+        let (plaintext, reopened) = sealed.open("correct horse").unwrap();
+        assert_eq!(plaintext, b"secret keys");
+        assert_eq!(reopened.key, key.key);
+        assert_eq!(sealed.open("wrong horse").unwrap_err(), EncryptionError::WrongPassword);
+
+        let mut tampered = sealed.clone();
+        tampered.ciphertext.replace_range(0..2, if &sealed.ciphertext[0..2] == "00" { "01" } else { "00" });
+        assert_eq!(tampered.open("correct horse").unwrap_err(), EncryptionError::WrongPassword);
+
+        // scrypt needs N < 2^(16r)
+        let weak = EncryptedData { r: 1, log_n: 16, ..sealed };
+        assert!(matches!(weak.open("correct horse").unwrap_err(), EncryptionError::Malformed(_)));
+    }
+}
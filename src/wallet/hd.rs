@@ -0,0 +1,178 @@
+//!
+//! # HD Wallet
+//! BIP39 seed phrases and the deterministic derivation of wallet keys from them.
+//!
+//! A mnemonic of 12 to 24 words from the BIP39 English list encodes random entropy plus a
+//! checksum; with an optional passphrase it stretches (PBKDF2-HMAC-SHA512) into a 64-byte
+//! seed. Keys are derived from the seed along `m/44'/1'/0'/0'/i'`, every level hardened:
+//! SLIP-10 for ed25519 and BIP32 for secp256k1. The two curves start from different master
+//! keys, so the same index yields unrelated keys for each algorithm.
+
+use super::keystore::WalletKey;
+use crate::core::address::KeyAlgorithm;
+use bip32::{ChildNumber, XPrv};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha512;
+
+/// Words in freshly generated mnemonics (256 bits of entropy).
+pub const DEFAULT_MNEMONIC_WORDS: usize = 24;
+/// BIP44 coin type in the derivation path (the SLIP-44 value shared by test networks).
+pub const COIN_TYPE: u32 = 1;
+const HARDENED: u32 = 0x8000_0000;
+
+type HmacSha512 = Hmac<Sha512>;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum MnemonicError {
+    #[error("A mnemonic has 12, 15, 18, 21 or 24 words, not {0}")]
+    InvalidWordCount(usize),
+    #[error("{0:?} is not in the BIP39 English wordlist")]
+    UnknownWord(String),
+    #[error("Mnemonic checksum mismatch (a word is wrong or out of order)")]
+    InvalidChecksum,
+}
+
+/// A BIP39 seed phrase.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    inner: bip39::Mnemonic,
+}
+
+impl std::fmt::Debug for Mnemonic {
+    // Never prints the phrase
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Mnemonic({} words)", self.inner.word_count())
+    }
+}
+
+impl Mnemonic {
+    /// Generates a mnemonic of `words` words from the operating system's random source.
+    pub fn generate(words: usize) -> Result<Self, MnemonicError> {
+        if !matches!(words, 12 | 15 | 18 | 21 | 24) {
+            return Err(MnemonicError::InvalidWordCount(words));
+        }
+        let mut entropy = vec![0u8; words * 4 / 3];
+        rand::rngs::OsRng.fill_bytes(&mut entropy);
+        Ok(Self::from_entropy(&entropy))
+    }
+
+    /// Encodes 16 to 32 bytes of entropy (a multiple of 4) as words.
+    pub fn from_entropy(entropy: &[u8]) -> Self {
+        assert!(matches!(entropy.len(), 16 | 20 | 24 | 28 | 32), "BIP39 entropy is 128 to 256 bits");
+        Mnemonic { inner: bip39::Mnemonic::from_entropy(entropy).expect("Entropy length was checked") }
+    }
+
+    /// Parses a phrase, checking its words and checksum; case and spacing are normalized.
+    pub fn parse(phrase: &str) -> Result<Self, MnemonicError> {
+        let words: Vec<String> = phrase.split_whitespace().map(str::to_lowercase).collect();
+        let inner = bip39::Mnemonic::parse_in_normalized(bip39::Language::English, &words.join(" ")).map_err(|e| match e {
+            bip39::Error::BadWordCount(count) => MnemonicError::InvalidWordCount(count),
+            bip39::Error::UnknownWord(index) => MnemonicError::UnknownWord(words[index].clone()),
+            bip39::Error::InvalidChecksum => MnemonicError::InvalidChecksum,
+            other => unreachable!("Parsing English words failed with {}", other),
+        })?;
+        Ok(Mnemonic { inner })
+    }
+
+    /// The words, separated by single spaces.
+    pub fn phrase(&self) -> String {
+        self.inner.to_string()
+    }
+
+    /// Stretches the phrase and `passphrase` into the 64-byte BIP39 seed.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        self.inner.to_seed(passphrase)
+    }
+}
+
+/// Path of the key at `index`, in BIP32 notation.
+pub fn derivation_path(index: u32) -> String {
+    format!("m/44'/{}'/0'/0'/{}'", COIN_TYPE, index)
+}
+
+/// Derives the `algorithm` key at `index` of the wallet path from a BIP39 seed.
+pub fn derive_key(seed: &[u8], algorithm: KeyAlgorithm, index: u32) -> WalletKey {
+    let path = [44, COIN_TYPE, 0, 0, index];
+    let (secret, _) = derive_path(seed, algorithm, &path);
+    WalletKey::from_secret_bytes(algorithm, &secret).expect("Derived keys are valid")
+}
+
+// Walks hardened `path` from the master key, returning the secret key and chain code
+fn derive_path(seed: &[u8], algorithm: KeyAlgorithm, path: &[u32]) -> ([u8; 32], [u8; 32]) {
+    match algorithm {
+        KeyAlgorithm::Ed25519 => {
+            // SLIP-10: every ed25519 child is hardened and takes the left half of the HMAC as its key
+            let (mut key, mut chain_code) = split(hmac_sha512(b"ed25519 seed", &[seed]));
+            for index in path {
+                (key, chain_code) = split(hmac_sha512(&chain_code, &[&[0], &key, &(index | HARDENED).to_be_bytes()]));
+            }
+            (key, chain_code)
+        }
+        KeyAlgorithm::Secp256k1 => {
+            let mut key = XPrv::new(seed).expect("BIP39 seeds are 64 bytes and the master key is in range");
+            for index in path {
+                key = key.derive_child(ChildNumber(index | HARDENED)).expect("Child keys are in range");
+            }
+            (key.to_bytes(), key.attrs().chain_code)
+        }
+    }
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts any key length");
+    parts.iter().for_each(|part| mac.update(part));
+    mac.finalize().into_bytes().into()
+}
+
+fn split(output: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let (left, right) = output.split_at(32);
+    (left.try_into().unwrap(), right.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bip39_vectors() {
+        // Vectors from the BIP39 reference implementation (passphrase "TREZOR")
+        let vectors = [
+            ("00000000000000000000000000000000", "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"),
+            ("7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f", "legal winner thank year wave sausage worth useful legal winner thank yellow"),
+            ("ffffffffffffffffffffffffffffffff", "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong"),
+            (
+                "68a79eaca2324873eacc50cb9c6eca8cc68ea5d936f98787c60c7ebc74e6ce7c",
+                "hamster diagram private dutch cause delay private meat slide toddler razor book happy fancy gospel tennis maple dilemma loan word shrug inflict delay length",
+            ),
+        ];
+        for (entropy, phrase) in vectors {
+            let mnemonic = Mnemonic::from_entropy(&hex::decode(entropy).unwrap());
+            assert_eq!(mnemonic.phrase(), phrase);
+            assert_eq!(Mnemonic::parse(&phrase.to_uppercase()).unwrap(), mnemonic);
+        }
+        assert_eq!(
+            hex::encode(Mnemonic::parse(vectors[0].1).unwrap().to_seed("TREZOR")),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+
+        assert_eq!(Mnemonic::parse("abandon abandon about"), Err(MnemonicError::InvalidWordCount(3)));
+        assert_eq!(Mnemonic::parse(&vectors[0].1.replace("about", "abound")), Err(MnemonicError::UnknownWord("abound".to_string())));
+        assert_eq!(Mnemonic::parse(&vectors[0].1.replace("about", "above")), Err(MnemonicError::InvalidChecksum));
+        assert_eq!(Mnemonic::generate(24).unwrap().phrase().split(' ').count(), 24);
+    }
+
+    #[test]
+    fn test_derivation_vectors() {
+        // SLIP-10 test vector 1 (seed 000102..0f), chain m/0'
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        assert_eq!(hex::encode(derive_path(&seed, KeyAlgorithm::Ed25519, &[]).0), "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7");
+        assert_eq!(hex::encode(derive_path(&seed, KeyAlgorithm::Ed25519, &[0]).0), "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3");
+        assert_eq!(hex::encode(derive_path(&seed, KeyAlgorithm::Secp256k1, &[]).0), "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35");
+        assert_eq!(hex::encode(derive_path(&seed, KeyAlgorithm::Secp256k1, &[0]).0), "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea");
+
+        let first = derive_key(&seed, KeyAlgorithm::Ed25519, 0);
+        assert_eq!(first.address(), derive_key(&seed, KeyAlgorithm::Ed25519, 0).address());
+        assert_ne!(first.address(), derive_key(&seed, KeyAlgorithm::Ed25519, 1).address());
+    }
+}
@@ -0,0 +1,623 @@
+//!
+//! # Keystore
+//! Keys the node's wallet controls, kept in the data directory.
+//!
+//! Each key is listed in `keys.json` under the wallet directory, together with the address
+//! derived from its public key (see [`crate::core::address`]). Keys are derived from the
+//! wallet's BIP39 seed (see [`super::hd`]), created with the keystore, so writing down its
+//! mnemonic is enough to restore every address with [`Keystore::restore`]. Keys from
+//! wallets that predate the seed were generated at random and are only in the file.
+//! The file is rewritten through a temporary file and renamed into place, so a crash
+//! never leaves it half written, and on Unix it is readable by the owner only.
+//!
+//! After [`Keystore::encrypt`] the seed and secret keys are stored sealed under a password
+//! (see [`super::encryption`]); only the addresses and public keys stay readable. Such a
+//! keystore opens locked: it lists addresses, but deriving keys, signing and showing the
+//! mnemonic wait for [`Keystore::unlock`], optionally only for a while.
+//!
+//! Transactions carry no signature field; [`Keystore::sign_transaction`] produces a
+//! detached signature over the transaction hash that anyone can check against the
+//! sender address with [`crate::core::address::verify_signature`].
+//! [`Keystore::sign_raw_transaction`] bundles the two into a [`SignedTransaction`].
+
+use super::encryption::{EncryptedData, EncryptionError, SealingKey};
+use super::hd::{self, Mnemonic, MnemonicError};
+use crate::core::address::{address_from_public_key, KeyAlgorithm};
+use crate::core::time::unix_now;
+use crate::core::{Address, SignedTransaction, Transaction};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Subdirectory of the data directory holding the wallet.
+pub const WALLET_DIR: &str = "wallet";
+/// File listing the wallet's keys.
+const KEYS_FILE: &str = "keys.json";
+/// Consecutive unused addresses [`Keystore::restore`] looks past before it stops.
+pub const RESTORE_GAP_LIMIT: u32 = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeystoreError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Corrupt keystore {path:?}: {reason}")]
+    Corrupt { path: PathBuf, reason: String },
+    #[error("No key for address {0}")]
+    UnknownAddress(String),
+    #[error("Invalid mnemonic: {0}")]
+    Mnemonic(#[from] MnemonicError),
+    #[error("A keystore already exists at {0:?}; move it away before restoring")]
+    AlreadyExists(PathBuf),
+    #[error("The wallet is locked; unlock it with its password first")]
+    Locked,
+    #[error("The wallet is not encrypted")]
+    NotEncrypted,
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+}
+
+/// Secret half of a wallet key.
+#[derive(Clone)]
+pub enum WalletKey {
+    Ed25519(ed25519_dalek::SigningKey),
+    Secp256k1(k256::ecdsa::SigningKey),
+}
+
+impl std::fmt::Debug for WalletKey {
+    // Never prints the secret
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WalletKey({}, {})", self.algorithm(), hex::encode(self.address()))
+    }
+}
+
+impl WalletKey {
+    /// Generates a fresh key from the operating system's random source.
+    pub fn generate(algorithm: KeyAlgorithm) -> Self {
+        match algorithm {
+            KeyAlgorithm::Ed25519 => WalletKey::Ed25519(ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng)),
+            KeyAlgorithm::Secp256k1 => WalletKey::Secp256k1(k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng)),
+        }
+    }
+
+    /// Rebuilds a key from its 32 secret bytes.
+    pub fn from_secret_bytes(algorithm: KeyAlgorithm, secret: &[u8]) -> Option<Self> {
+        match algorithm {
+            KeyAlgorithm::Ed25519 => <[u8; 32]>::try_from(secret).ok()
+                .map(|secret| WalletKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&secret))),
+            KeyAlgorithm::Secp256k1 => k256::ecdsa::SigningKey::from_slice(secret).ok().map(WalletKey::Secp256k1),
+        }
+    }
+
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        match self {
+            WalletKey::Ed25519(_) => KeyAlgorithm::Ed25519,
+            WalletKey::Secp256k1(_) => KeyAlgorithm::Secp256k1,
+        }
+    }
+
+    pub fn secret_bytes(&self) -> Vec<u8> {
+        match self {
+            WalletKey::Ed25519(key) => key.to_bytes().to_vec(),
+            WalletKey::Secp256k1(key) => key.to_bytes().to_vec(),
+        }
+    }
+
+    /// Public key in the encoding addresses are derived from (compressed SEC1 for secp256k1).
+    pub fn public_key(&self) -> Vec<u8> {
+        match self {
+            WalletKey::Ed25519(key) => key.verifying_key().to_bytes().to_vec(),
+            WalletKey::Secp256k1(key) => key.verifying_key().to_sec1_bytes().to_vec(),
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        address_from_public_key(self.algorithm(), &self.public_key()).expect("Generated keys are valid")
+    }
+
+    /// Signs `message` with the scheme the address version names.
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            WalletKey::Ed25519(key) => ed25519_dalek::Signer::sign(key, message).to_bytes().to_vec(),
+            WalletKey::Secp256k1(key) => {
+                let signature: k256::ecdsa::Signature = k256::ecdsa::signature::Signer::sign(key, message);
+                signature.to_bytes().to_vec()
+            }
+        }
+    }
+}
+
+/// A signature over a transaction hash, with the public key needed to check it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionSignature {
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// A wallet address, as listed over RPC.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletAddress {
+    /// Hex-encoded address.
+    pub address: String,
+    pub algorithm: String,
+    /// Hex-encoded public key.
+    pub public_key: String,
+    pub label: Option<String>,
+    /// When the key was generated (UNIX seconds).
+    pub created_at: u64,
+    /// Where the key sits under the wallet seed; `None` for keys generated at random.
+    pub derivation_path: Option<String>,
+}
+
+// One entry of a plaintext keys file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKey {
+    algorithm: String,
+    address: String,
+    secret_key: String,
+    label: Option<String>,
+    created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hd_index: Option<u32>,
+}
+
+// One entry of an encrypted keys file, without its secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredPublicKey {
+    algorithm: String,
+    address: String,
+    public_key: String,
+    label: Option<String>,
+    created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hd_index: Option<u32>,
+}
+
+// What an encrypted keys file seals: the seed and the secret keys in entry order
+#[derive(Serialize, Deserialize)]
+struct SealedSecrets {
+    mnemonic: String,
+    seed: String,
+    secret_keys: Vec<String>,
+}
+
+// The keys file; wallets written before the seed existed hold a bare list of keys
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredKeystore {
+    Encrypted { encrypted: EncryptedData, keys: Vec<StoredPublicKey> },
+    Seeded { mnemonic: String, seed: String, keys: Vec<StoredKey> },
+    Legacy(Vec<StoredKey>),
+}
+
+// What is listed about a key of the wallet
+#[derive(Debug)]
+struct Entry {
+    address: WalletAddress,
+    hd_index: Option<u32>,
+}
+
+// The wallet's secret material; absent while an encrypted keystore is locked
+struct Secrets {
+    mnemonic: Mnemonic,
+    // BIP39 seed of the mnemonic, kept since the passphrase it was stretched with is not
+    seed: [u8; 64],
+    // Parallel to the entries
+    keys: Vec<WalletKey>,
+}
+
+impl std::fmt::Debug for Secrets {
+    // Never prints the secrets
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secrets({} keys)", self.keys.len())
+    }
+}
+
+// Password protection of the keys file
+#[derive(Debug)]
+struct Encryption {
+    sealed: EncryptedData,
+    // Present while unlocked, to reseal the secrets on save
+    key: Option<SealingKey>,
+    relock_at: Option<Instant>,
+}
+
+/// The wallet's keys, loaded from and saved to the wallet directory.
+#[derive(Debug)]
+pub struct Keystore {
+    path: PathBuf,
+    entries: Vec<Entry>,
+    secrets: Option<Secrets>,
+    encryption: Option<Encryption>,
+}
+
+impl Keystore {
+    /// Opens the keystore in `wallet_dir`, creating the directory and a new seed if needed.
+    /// An encrypted keystore opens locked.
+    pub fn open(wallet_dir: &Path) -> Result<Self, KeystoreError> {
+        fs::create_dir_all(wallet_dir)?;
+        let path = wallet_dir.join(KEYS_FILE);
+        let stored = match fs::read(&path) {
+            Ok(bytes) => Some(serde_json::from_slice(&bytes)
+                .map_err(|e| KeystoreError::Corrupt { path: path.clone(), reason: e.to_string() })?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let corrupt = |reason: String| KeystoreError::Corrupt { path: path.clone(), reason };
+        let (seed, stored) = match stored {
+            Some(StoredKeystore::Encrypted { encrypted, keys }) => {
+                let entries = keys.into_iter().map(|entry| {
+                    let algorithm: KeyAlgorithm = entry.algorithm.parse().map_err(|_| corrupt(format!("{}: unknown algorithm", entry.address)))?;
+                    let matches = hex::decode(&entry.public_key).ok()
+                        .and_then(|public_key| address_from_public_key(algorithm, &public_key).ok())
+                        .is_some_and(|address| hex::encode(address) == entry.address);
+                    if !matches {
+                        return Err(corrupt(format!("{}: public key does not match the address", entry.address)));
+                    }
+                    let address = WalletAddress {
+                        address: entry.address,
+                        algorithm: entry.algorithm,
+                        public_key: entry.public_key,
+                        label: entry.label,
+                        created_at: entry.created_at,
+                        derivation_path: entry.hd_index.map(hd::derivation_path),
+                    };
+                    Ok(Entry { address, hd_index: entry.hd_index })
+                }).collect::<Result<_, KeystoreError>>()?;
+                let encryption = Encryption { sealed: encrypted, key: None, relock_at: None };
+                return Ok(Keystore { path, entries, secrets: None, encryption: Some(encryption) });
+            }
+            Some(StoredKeystore::Seeded { mnemonic, seed, keys }) => (Some(parse_seed(&mnemonic, &seed).map_err(corrupt)?), keys),
+            Some(StoredKeystore::Legacy(keys)) => (None, keys),
+            None => (None, Vec::new()),
+        };
+        let (entries, keys) = stored.into_iter().map(|entry| {
+            let key = parse_secret_key(&entry.algorithm, &entry.secret_key, &entry.address).map_err(corrupt)?;
+            let address = WalletAddress {
+                address: entry.address,
+                algorithm: entry.algorithm,
+                public_key: hex::encode(key.public_key()),
+                label: entry.label,
+                created_at: entry.created_at,
+                derivation_path: entry.hd_index.map(hd::derivation_path),
+            };
+            Ok((Entry { address, hd_index: entry.hd_index }, key))
+        }).collect::<Result<(Vec<_>, Vec<_>), KeystoreError>>()?;
+
+        match seed {
+            Some((mnemonic, seed)) => Ok(Keystore { path, entries, secrets: Some(Secrets { mnemonic, seed, keys }), encryption: None }),
+            None => {
+                // New wallets, and wallets from before seeds, get one now
+                let mnemonic = Mnemonic::generate(hd::DEFAULT_MNEMONIC_WORDS)?;
+                let seed = mnemonic.to_seed("");
+                let keystore = Keystore { path, entries, secrets: Some(Secrets { mnemonic, seed, keys }), encryption: None };
+                keystore.save()?;
+                Ok(keystore)
+            }
+        }
+    }
+
+    /// Creates a keystore in `wallet_dir` from a backed-up mnemonic and rederives its addresses,
+    /// encrypting it under `password` if one is given (it is then left locked).
+    ///
+    /// For each algorithm, addresses are derived in order and kept up to the last one
+    /// `is_used` reports, stopping after [`RESTORE_GAP_LIMIT`] unused addresses in a row.
+    /// Labels are not part of the seed and are lost. Fails if `wallet_dir` already has a keystore.
+    pub fn restore(
+        wallet_dir: &Path,
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+        password: Option<&str>,
+        mut is_used: impl FnMut(&Address) -> bool,
+    ) -> Result<Self, KeystoreError> {
+        fs::create_dir_all(wallet_dir)?;
+        let path = wallet_dir.join(KEYS_FILE);
+        if path.exists() {
+            return Err(KeystoreError::AlreadyExists(path));
+        }
+        let secrets = Secrets { mnemonic: mnemonic.clone(), seed: mnemonic.to_seed(passphrase), keys: Vec::new() };
+        let mut keystore = Keystore { path, entries: Vec::new(), secrets: Some(secrets), encryption: None };
+        for algorithm in [KeyAlgorithm::Ed25519, KeyAlgorithm::Secp256k1] {
+            let seed = keystore.secrets()?.seed;
+            let mut used = 0;
+            let mut index = 0;
+            while index < used + RESTORE_GAP_LIMIT {
+                if is_used(&hd::derive_key(&seed, algorithm, index).address()) {
+                    used = index + 1;
+                }
+                index += 1;
+            }
+            for index in 0..used {
+                keystore.push_derived(algorithm, index, None)?;
+            }
+        }
+        match password {
+            Some(password) => keystore.encrypt(password)?,
+            None => keystore.save()?,
+        }
+        Ok(keystore)
+    }
+
+    /// Whether the keystore is stored encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Whether the secrets are out of reach until [`Keystore::unlock`].
+    pub fn is_locked(&self) -> bool {
+        self.secrets().is_err()
+    }
+
+    /// Encrypts the keystore under `password`, or changes its password, and locks it.
+    /// The keystore must be unlocked.
+    pub fn encrypt(&mut self, password: &str) -> Result<(), KeystoreError> {
+        self.secrets()?;
+        let key = SealingKey::new(password);
+        let sealed = key.seal(&self.sealed_secrets()?);
+        let previous = self.encryption.replace(Encryption { sealed, key: Some(key), relock_at: None });
+        if let Err(e) = self.save() {
+            self.encryption = previous;
+            return Err(e);
+        }
+        self.lock()
+    }
+
+    /// Decrypts the secrets with `password`, for `duration` or until [`Keystore::lock`].
+    pub fn unlock(&mut self, password: &str, duration: Option<Duration>) -> Result<(), KeystoreError> {
+        let encryption = self.encryption.as_ref().ok_or(KeystoreError::NotEncrypted)?;
+        let (plaintext, key) = encryption.sealed.open(password)?;
+        let corrupt = |reason: String| KeystoreError::Corrupt { path: self.path.clone(), reason };
+        let sealed: SealedSecrets = serde_json::from_slice(&plaintext).map_err(|e| corrupt(e.to_string()))?;
+        let (mnemonic, seed) = parse_seed(&sealed.mnemonic, &sealed.seed).map_err(corrupt)?;
+        if sealed.secret_keys.len() != self.entries.len() {
+            return Err(corrupt("sealed keys do not match the listed addresses".to_string()));
+        }
+        let keys = self.entries.iter().zip(&sealed.secret_keys)
+            .map(|(entry, secret)| parse_secret_key(&entry.address.algorithm, secret, &entry.address.address).map_err(corrupt))
+            .collect::<Result<_, _>>()?;
+        self.secrets = Some(Secrets { mnemonic, seed, keys });
+        let encryption = self.encryption.as_mut().expect("Checked above");
+        encryption.key = Some(key);
+        encryption.relock_at = duration.map(|duration| Instant::now() + duration);
+        Ok(())
+    }
+
+    /// Drops the decrypted secrets of an encrypted keystore.
+    pub fn lock(&mut self) -> Result<(), KeystoreError> {
+        let encryption = self.encryption.as_mut().ok_or(KeystoreError::NotEncrypted)?;
+        encryption.key = None;
+        encryption.relock_at = None;
+        self.secrets = None;
+        Ok(())
+    }
+
+    // The secrets, unless the keystore is locked or its unlock period has run out
+    fn secrets(&self) -> Result<&Secrets, KeystoreError> {
+        let expired = self.encryption.as_ref()
+            .and_then(|encryption| encryption.relock_at)
+            .is_some_and(|relock_at| Instant::now() >= relock_at);
+        match &self.secrets {
+            Some(secrets) if !expired => Ok(secrets),
+            _ => Err(KeystoreError::Locked),
+        }
+    }
+
+    /// The seed phrase to write down as the wallet's backup.
+    pub fn mnemonic(&self) -> Result<&Mnemonic, KeystoreError> {
+        Ok(&self.secrets()?.mnemonic)
+    }
+
+    /// Derives the next key of `algorithm` from the seed, saves it and returns its address.
+    pub fn new_address(&mut self, algorithm: KeyAlgorithm, label: Option<String>) -> Result<WalletAddress, KeystoreError> {
+        let index = self.entries.iter()
+            .filter(|entry| entry.address.algorithm == algorithm.to_string())
+            .filter_map(|entry| entry.hd_index)
+            .max()
+            .map_or(0, |index| index + 1);
+        let address = self.push_derived(algorithm, index, label)?;
+        if let Err(e) = self.save() {
+            self.entries.pop();
+            self.secrets.as_mut().expect("Unlocked to derive").keys.pop();
+            return Err(e);
+        }
+        Ok(address)
+    }
+
+    fn push_derived(&mut self, algorithm: KeyAlgorithm, index: u32, label: Option<String>) -> Result<WalletAddress, KeystoreError> {
+        let key = hd::derive_key(&self.secrets()?.seed, algorithm, index);
+        let address = WalletAddress {
+            address: hex::encode(key.address()),
+            algorithm: algorithm.to_string(),
+            public_key: hex::encode(key.public_key()),
+            label,
+            created_at: unix_now(),
+            derivation_path: Some(hd::derivation_path(index)),
+        };
+        self.entries.push(Entry { address: address.clone(), hd_index: Some(index) });
+        self.secrets.as_mut().expect("Unlocked to derive").keys.push(key);
+        Ok(address)
+    }
+
+    /// Addresses in the order they were created; listed even while locked.
+    pub fn addresses(&self) -> Vec<WalletAddress> {
+        self.entries.iter().map(|entry| entry.address.clone()).collect()
+    }
+
+    /// The key behind `address`, if the wallet holds it and is unlocked.
+    pub fn key(&self, address: &[u8]) -> Result<&WalletKey, KeystoreError> {
+        let hex_address = hex::encode(address);
+        let position = self.entries.iter().position(|entry| entry.address.address == hex_address)
+            .ok_or(KeystoreError::UnknownAddress(hex_address))?;
+        Ok(&self.secrets()?.keys[position])
+    }
+
+    /// Signs the hash of `tx` with the key of its sender.
+    pub fn sign_transaction(&self, tx: &Transaction) -> Result<TransactionSignature, KeystoreError> {
+        let key = self.key(tx.sender())?;
+        Ok(TransactionSignature { public_key: key.public_key(), signature: key.sign(&tx.calculate_hash()) })
+    }
+
+    /// Signs `tx` into the self-contained form `send_raw_transaction` accepts.
+    pub fn sign_raw_transaction(&self, tx: Transaction) -> Result<SignedTransaction, KeystoreError> {
+        let TransactionSignature { public_key, signature } = self.sign_transaction(&tx)?;
+        Ok(SignedTransaction { transaction: tx, public_key, signature })
+    }
+
+    // The secrets as sealed in an encrypted keys file
+    fn sealed_secrets(&self) -> Result<Vec<u8>, KeystoreError> {
+        let secrets = self.secrets()?;
+        let sealed = SealedSecrets {
+            mnemonic: secrets.mnemonic.phrase(),
+            seed: hex::encode(secrets.seed),
+            secret_keys: secrets.keys.iter().map(|key| hex::encode(key.secret_bytes())).collect(),
+        };
+        Ok(serde_json::to_vec(&sealed).expect("Secrets serialize"))
+    }
+
+    // Writes every key through a temporary file renamed into place
+    fn save(&self) -> Result<(), KeystoreError> {
+        let secrets = self.secrets()?;
+        let stored = match &self.encryption {
+            Some(encryption) => StoredKeystore::Encrypted {
+                encrypted: encryption.key.as_ref().ok_or(KeystoreError::Locked)?.seal(&self.sealed_secrets()?),
+                keys: self.entries.iter().map(|entry| StoredPublicKey {
+                    algorithm: entry.address.algorithm.clone(),
+                    address: entry.address.address.clone(),
+                    public_key: entry.address.public_key.clone(),
+                    label: entry.address.label.clone(),
+                    created_at: entry.address.created_at,
+                    hd_index: entry.hd_index,
+                }).collect(),
+            },
+            None => StoredKeystore::Seeded {
+                mnemonic: secrets.mnemonic.phrase(),
+                seed: hex::encode(secrets.seed),
+                keys: self.entries.iter().zip(&secrets.keys).map(|(entry, key)| StoredKey {
+                    algorithm: entry.address.algorithm.clone(),
+                    address: entry.address.address.clone(),
+                    secret_key: hex::encode(key.secret_bytes()),
+                    label: entry.address.label.clone(),
+                    created_at: entry.address.created_at,
+                    hd_index: entry.hd_index,
+                }).collect(),
+            },
+        };
+        let bytes = serde_json::to_vec_pretty(&stored).expect("Keystore serializes");
+        let partial = self.path.with_extension("json.partial");
+        fs::write(&partial, bytes)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&partial, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+}
+
+fn parse_seed(mnemonic: &str, seed: &str) -> Result<(Mnemonic, [u8; 64]), String> {
+    let mnemonic = Mnemonic::parse(mnemonic).map_err(|e| e.to_string())?;
+    let seed = hex::decode(seed).ok().and_then(|seed| <[u8; 64]>::try_from(seed).ok())
+        .ok_or_else(|| "invalid seed".to_string())?;
+    Ok((mnemonic, seed))
+}
+
+// Rebuilds a stored secret key, checking it still yields its address
+fn parse_secret_key(algorithm: &str, secret_key: &str, address: &str) -> Result<WalletKey, String> {
+    let algorithm: KeyAlgorithm = algorithm.parse().map_err(|_| format!("{}: unknown algorithm", address))?;
+    let key = hex::decode(secret_key).ok()
+        .and_then(|secret| WalletKey::from_secret_bytes(algorithm, &secret))
+        .ok_or_else(|| format!("{}: invalid secret key", address))?;
+    if hex::encode(key.address()) != address {
+        return Err(format!("{}: secret key does not match the address", address));
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::address::verify_signature;
+
+    #[test]
+    fn test_keys_persist_and_sign() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keystore = Keystore::open(dir.path()).unwrap();
+        let ed = keystore.new_address(KeyAlgorithm::Ed25519, Some("savings".to_string())).unwrap();
+        let k = keystore.new_address(KeyAlgorithm::Secp256k1, None).unwrap();
+        assert_ne!(ed.address, k.address);
+
+        // Reopening loads the same keys
+        let keystore = Keystore::open(dir.path()).unwrap();
+        assert_eq!(keystore.addresses(), vec![ed.clone(), k.clone()]);
+
+        for address in [&ed.address, &k.address] {
+            let sender = hex::decode(address).unwrap();
+            let tx = Transaction::new_transfer_native(sender.clone(), vec![9; 21], 5);
+            let signed = keystore.sign_transaction(&tx).unwrap();
+            assert!(verify_signature(&sender, &signed.public_key, &tx.calculate_hash(), &signed.signature).is_ok());
+            let raw = keystore.sign_raw_transaction(tx).unwrap();
+            assert!(SignedTransaction::from_bytes(&raw.to_bytes()).unwrap().verify().is_ok());
+        }
+        let foreign = Transaction::new_transfer_native(vec![3; 21], vec![9; 21], 5);
+        assert!(matches!(keystore.sign_transaction(&foreign), Err(KeystoreError::UnknownAddress(_))));
+    }
+
+    #[test]
+    fn test_restore_from_mnemonic() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keystore = Keystore::open(dir.path()).unwrap();
+        let mut created: Vec<WalletAddress> = (0..3).map(|_| keystore.new_address(KeyAlgorithm::Ed25519, None).unwrap()).collect();
+        created.push(keystore.new_address(KeyAlgorithm::Secp256k1, None).unwrap());
+        assert_eq!(created[2].derivation_path.as_deref(), Some("m/44'/1'/0'/0'/2'"));
+        let mnemonic = keystore.mnemonic().unwrap().clone();
+        assert!(matches!(Keystore::restore(dir.path(), &mnemonic, "", None, |_| true), Err(KeystoreError::AlreadyExists(_))));
+
+        // Only the second ed25519 address and the secp256k1 one were used on chain
+        let used: Vec<String> = vec![created[1].address.clone(), created[3].address.clone()];
+        let restored_dir = tempfile::tempdir().unwrap();
+        let restored = Keystore::restore(restored_dir.path(), &mnemonic, "", None, |address| used.contains(&hex::encode(address))).unwrap();
+        let addresses: Vec<String> = restored.addresses().into_iter().map(|address| address.address).collect();
+        assert_eq!(addresses, vec![created[0].address.clone(), created[1].address.clone(), created[3].address.clone()]);
+
+        // New addresses continue after the restored ones, and the keystore reopens intact
+        let mut restored = Keystore::open(restored_dir.path()).unwrap();
+        assert_eq!(restored.new_address(KeyAlgorithm::Ed25519, None).unwrap().address, hex::encode(hd::derive_key(&mnemonic.to_seed(""), KeyAlgorithm::Ed25519, 2).address()));
+        assert_eq!(restored.mnemonic().unwrap(), &mnemonic);
+    }
+
+    #[test]
+    fn test_encrypted_keystore_needs_unlocking() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keystore = Keystore::open(dir.path()).unwrap();
+        let first = keystore.new_address(KeyAlgorithm::Ed25519, Some("savings".to_string())).unwrap();
+        let mnemonic = keystore.mnemonic().unwrap().clone();
+        let secret = hex::encode(keystore.key(&hex::decode(&first.address).unwrap()).unwrap().secret_bytes());
+        assert!(matches!(keystore.lock(), Err(KeystoreError::NotEncrypted)));
+        keystore.encrypt("hunter2").unwrap();
+        assert!(keystore.is_locked());
+
+        // Nothing secret is left in the file, and it opens locked
+        let file = fs::read_to_string(dir.path().join(KEYS_FILE)).unwrap();
+        assert!(!file.contains(&mnemonic.phrase()) && !file.contains(&secret));
+        let mut keystore = Keystore::open(dir.path()).unwrap();
+        assert!(keystore.is_encrypted() && keystore.is_locked());
+        assert_eq!(keystore.addresses(), vec![first.clone()]);
+        let tx = Transaction::new_transfer_native(hex::decode(&first.address).unwrap(), vec![9; 21], 5);
+        assert!(matches!(keystore.sign_transaction(&tx), Err(KeystoreError::Locked)));
+        assert!(matches!(keystore.new_address(KeyAlgorithm::Ed25519, None), Err(KeystoreError::Locked)));
+        assert!(matches!(keystore.unlock("hunter3", None), Err(KeystoreError::Encryption(EncryptionError::WrongPassword))));
+
+        keystore.unlock("hunter2", None).unwrap();
+        assert!(keystore.sign_transaction(&tx).is_ok());
+        let second = keystore.new_address(KeyAlgorithm::Secp256k1, None).unwrap();
+        assert_eq!(keystore.mnemonic().unwrap(), &mnemonic);
+
+        // Keys added while unlocked are sealed too; an expired unlock locks again
+        let mut keystore = Keystore::open(dir.path()).unwrap();
+        assert_eq!(keystore.addresses(), vec![first, second]);
+        keystore.unlock("hunter2", Some(Duration::ZERO)).unwrap();
+        assert!(keystore.is_locked());
+    }
+}
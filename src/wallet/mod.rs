@@ -0,0 +1,448 @@
+//!
+//! # Wallet
+//! Client-side flows that drive a running node over JSON-RPC.
+//!
+//! [`create_address`], [`balance`] and [`send`] wrap the wallet, balance and transfer RPCs.
+//! `store_file` is the end-to-end upload: it splits the file into chunks that fit in
+//! one RPC request, checks the sender can pay the estimated fees, submits one
+//! `StoreData` transaction per chunk through `send_transaction` and then follows the
+//! transactions until they are final. A file of several chunks also gets a small
+//! manifest payload listing the chunk hashes in order; the manifest's hash is the
+//! handle for the whole file.
+//!
+//! The node builds the transactions from the `send_transaction` parameters, so there
+//! is no local signing step. Keys the node's own wallet controls live in [`keystore`],
+//! derived from a BIP39 seed phrase by [`hd`] and optionally sealed under a password by
+//! [`encryption`].
+
+pub mod encryption;
+pub mod hd;
+pub mod keystore;
+
+pub use hd::{Mnemonic, MnemonicError};
+pub use keystore::{Keystore, KeystoreError, TransactionSignature, WalletAddress, WalletKey};
+
+use crate::core::address::parse_address_str;
+use crate::core::{Address, Hash, Transaction};
+use crate::rpc::API_KEY_HEADER;
+use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Largest chunk sent in one `send_transaction`; base64 keeps the request under the RPC body limit.
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How often `store_file` polls the node while waiting for its transactions.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum WalletError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid response from node: {0}")]
+    InvalidResponse(String),
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("Insufficient balance: {needed} needed, {available} available")]
+    InsufficientBalance { needed: u64, available: u64 },
+    #[error("File is empty")]
+    EmptyFile,
+    #[error("Timed out after {0:?} waiting for the storage transactions to be final")]
+    Timeout(Duration),
+    #[error("The node's wallet has no addresses; create one with `wallet create`")]
+    NoAddress,
+}
+
+/// Blocking JSON-RPC client for a node's RPC server.
+#[derive(Debug, Clone)]
+pub struct RpcClient {
+    addr: String,
+    api_key: Option<String>,
+}
+
+impl RpcClient {
+    /// Client for the RPC server listening on `addr` (`host:port`).
+    pub fn new(addr: impl Into<String>) -> Self {
+        RpcClient { addr: addr.into(), api_key: None }
+    }
+
+    /// Sends `api_key` with every request, for nodes running in multi-tenant mode.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Calls `method` and returns its result, or the node's error as [`WalletError::Rpc`].
+    pub fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value, WalletError> {
+        let body = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }).to_string();
+        let mut request = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.addr,
+            body.len()
+        );
+        if let Some(api_key) = &self.api_key {
+            request.push_str(&format!("{}: {}\r\n", API_KEY_HEADER, api_key));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.write_all(request.as_bytes())?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        parse_rpc_response(&response)
+    }
+}
+
+// Extracts the JSON-RPC result from a raw HTTP response
+fn parse_rpc_response(response: &[u8]) -> Result<serde_json::Value, WalletError> {
+    let response = String::from_utf8_lossy(response);
+    let (head, body) = response.split_once("\r\n\r\n")
+        .ok_or_else(|| WalletError::InvalidResponse("truncated HTTP response".to_string()))?;
+    let status_line = head.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(WalletError::InvalidResponse(format!("{} {}", status_line, body.trim())));
+    }
+    let mut envelope: serde_json::Value = serde_json::from_str(body)
+        .map_err(|e| WalletError::InvalidResponse(format!("body is not JSON: {}", e)))?;
+    if let Some(error) = envelope.get("error").filter(|error| !error.is_null()) {
+        return Err(WalletError::Rpc {
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+    Ok(envelope["result"].take())
+}
+
+/// Has the node's wallet derive a new `algorithm` key (the node's default if `None`) and returns its address.
+pub fn create_address(client: &RpcClient, algorithm: Option<&str>, label: Option<&str>) -> Result<WalletAddress, WalletError> {
+    let result = client.call("wallet_new_address", serde_json::json!({ "algorithm": algorithm, "label": label }))?;
+    serde_json::from_value(result.clone()).map_err(|_| WalletError::InvalidResponse(format!("wallet_new_address returned {}", result)))
+}
+
+/// Human-readable part of the node's addresses, to check addresses typed for it against.
+pub fn address_hrp(client: &RpcClient) -> Result<String, WalletError> {
+    let result = client.call("get_node_info", serde_json::json!({}))?;
+    result["address_hrp"].as_str().map(str::to_string)
+        .ok_or_else(|| WalletError::InvalidResponse(format!("get_node_info returned {}", result)))
+}
+
+/// Native balance of `address`, as indexed by the node; nodes running without `--indexer`
+/// answer with an RPC error.
+pub fn balance(client: &RpcClient, address: &Address) -> Result<u64, WalletError> {
+    let result = client.call("get_balance", serde_json::json!({ "address": hex::encode(address) }))?;
+    result["balance"].as_u64().ok_or_else(|| WalletError::InvalidResponse(format!("get_balance returned {}", result)))
+}
+
+/// Sends `amount` native coins to `recipient`, from `sender` or else the first address of the
+/// node's wallet, and returns the transaction hash once it is in the mempool.
+pub fn send(client: &RpcClient, sender: Option<&Address>, recipient: &Address, amount: u64, fee: u64) -> Result<Hash, WalletError> {
+    let sender = match sender {
+        Some(sender) => sender.clone(),
+        None => {
+            let result = client.call("wallet_list_addresses", serde_json::json!({}))?;
+            let addresses: Vec<WalletAddress> = serde_json::from_value(result.clone())
+                .map_err(|_| WalletError::InvalidResponse(format!("wallet_list_addresses returned {}", result)))?;
+            let first = addresses.first().ok_or(WalletError::NoAddress)?;
+            parse_address_str(&address_hrp(client)?, &first.address)
+                .map_err(|e| WalletError::InvalidResponse(format!("invalid wallet address {}: {}", first.address, e)))?
+        }
+    };
+    let available = balance(client, &sender)?;
+    let needed = amount.saturating_add(fee);
+    if available < needed {
+        return Err(WalletError::InsufficientBalance { needed, available });
+    }
+    let result = client.call("send_transaction", serde_json::json!({
+        "sender": sender,
+        "recipient": recipient,
+        "amount": amount,
+        "fee": fee,
+    }))?;
+    parse_hash(&result)
+}
+
+/// Lists the chunks of a file stored in several payloads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileManifest {
+    pub size: u64,
+    /// Hex-encoded payload hashes, in file order.
+    pub chunks: Vec<String>,
+}
+
+/// Progress reported by [`store_file`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreFileStatus {
+    /// A payload was uploaded and its transaction entered the mempool.
+    Submitted { payload_hash: Hash, tx_hash: Hash, size: u64 },
+    /// Some transactions are in blocks, the rest still pending.
+    Confirming { confirmed: usize, total: usize, finalized_height: Option<u64> },
+    /// Every transaction is in a finalized block.
+    Final { height: u64 },
+}
+
+/// What [`store_file`] committed on chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFile {
+    /// Hash to retrieve the file by: its only payload, or the manifest of a chunked file.
+    pub root_hash: Hash,
+    pub size: u64,
+    pub chunk_hashes: Vec<Hash>,
+    pub tx_hashes: Vec<Hash>,
+    pub fee: u64,
+    /// Height at which the last transaction was finalized; `None` if not waited for.
+    pub final_height: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoreFileOptions {
+    /// Fee offered per serialized byte of each storage transaction.
+    pub fee_rate: u64,
+    /// Stop after submitting, without waiting for finality.
+    pub wait: bool,
+    pub poll_interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for StoreFileOptions {
+    fn default() -> Self {
+        StoreFileOptions { fee_rate: 0, wait: true, poll_interval: DEFAULT_POLL_INTERVAL, timeout: Duration::from_secs(60 * 60) }
+    }
+}
+
+/// Splits `data` into the payloads to upload: the chunks, followed by their manifest if there are several.
+pub fn plan_payloads(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut payloads: Vec<Vec<u8>> = data.chunks(MAX_CHUNK_SIZE).map(<[u8]>::to_vec).collect();
+    if payloads.len() > 1 {
+        let manifest = FileManifest {
+            size: data.len() as u64,
+            chunks: payloads.iter().map(|chunk| hex::encode(Sha256::digest(chunk))).collect(),
+        };
+        payloads.push(serde_json::to_vec(&manifest).expect("manifest serializes"));
+    }
+    payloads
+}
+
+/// Uploads the file at `path` as `sender` and follows it to finality, reporting progress to `on_status`.
+pub fn store_file(
+    client: &RpcClient,
+    sender: &Address,
+    path: &Path,
+    options: &StoreFileOptions,
+    mut on_status: impl FnMut(&StoreFileStatus),
+) -> Result<StoredFile, WalletError> {
+    let data = std::fs::read(path)?;
+    if data.is_empty() {
+        return Err(WalletError::EmptyFile);
+    }
+    let payloads = plan_payloads(&data);
+
+    // Check the fees are covered before uploading anything
+    let fees: Vec<u64> = payloads.iter()
+        .map(|payload| {
+            let tx = Transaction::new_store_data(sender.clone(), Sha256::digest(payload).into(), payload.len() as u64);
+            options.fee_rate.saturating_mul(tx.size() as u64)
+        })
+        .collect();
+    let fee: u64 = fees.iter().sum();
+    let available = balance(client, sender)?;
+    if available < fee {
+        return Err(WalletError::InsufficientBalance { needed: fee, available });
+    }
+
+    let mut chunk_hashes = Vec::new();
+    let mut tx_hashes = Vec::new();
+    for (payload, fee) in payloads.iter().zip(&fees) {
+        let result = client.call("send_transaction", serde_json::json!({
+            "sender": sender,
+            "payload_base64": base64_engine.encode(payload),
+            "fee": fee,
+        }))?;
+        let tx_hash = parse_hash(&result)?;
+        let payload_hash: Hash = Sha256::digest(payload).into();
+        on_status(&StoreFileStatus::Submitted { payload_hash, tx_hash, size: payload.len() as u64 });
+        chunk_hashes.push(payload_hash);
+        tx_hashes.push(tx_hash);
+    }
+    let root_hash = *chunk_hashes.last().expect("at least one payload");
+    if payloads.len() > 1 {
+        chunk_hashes.pop();
+    }
+
+    let final_height = if options.wait { Some(wait_for_finality(client, &tx_hashes, options, &mut on_status)?) } else { None };
+    Ok(StoredFile { root_hash, size: data.len() as u64, chunk_hashes, tx_hashes, fee, final_height })
+}
+
+// Polls until every transaction is in a block at or below the finalized height
+fn wait_for_finality(
+    client: &RpcClient,
+    tx_hashes: &[Hash],
+    options: &StoreFileOptions,
+    on_status: &mut impl FnMut(&StoreFileStatus),
+) -> Result<u64, WalletError> {
+    let started = Instant::now();
+    let mut last_status = None;
+    loop {
+        let mut heights = Vec::new();
+        for tx_hash in tx_hashes {
+            let confirmed = client.call("get_transaction", serde_json::json!({ "tx_hash": hex::encode(tx_hash) }))?;
+            if let Some(height) = confirmed["height"].as_u64() {
+                heights.push(height);
+            }
+        }
+        let sync_status = client.call("get_sync_status", serde_json::json!({}))?;
+        let finalized_height = sync_status["finalized_height"].as_u64();
+        let highest = heights.iter().copied().max();
+        if heights.len() == tx_hashes.len() {
+            if let (Some(highest), Some(finalized)) = (highest, finalized_height) {
+                if highest <= finalized {
+                    on_status(&StoreFileStatus::Final { height: highest });
+                    return Ok(highest);
+                }
+            }
+        }
+
+        let status = StoreFileStatus::Confirming { confirmed: heights.len(), total: tx_hashes.len(), finalized_height };
+        if last_status.as_ref() != Some(&status) {
+            on_status(&status);
+            last_status = Some(status);
+        }
+        if started.elapsed() >= options.timeout {
+            return Err(WalletError::Timeout(options.timeout));
+        }
+        std::thread::sleep(options.poll_interval);
+    }
+}
+
+fn parse_hash(value: &serde_json::Value) -> Result<Hash, WalletError> {
+    value.as_str()
+        .and_then(|hex_hash| hex::decode(hex_hash).ok())
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| WalletError::InvalidResponse(format!("expected a hex hash, got {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    // Answers JSON-RPC calls like a node that mines and finalizes each transaction on the second poll
+    fn spawn_fake_node(requests: Arc<Mutex<Vec<serde_json::Value>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let mut polls = 0;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut raw = Vec::new();
+                let mut buf = [0u8; 64 * 1024];
+                let request = loop {
+                    let n = stream.read(&mut buf).unwrap();
+                    raw.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&raw).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length: usize = head.lines()
+                            .find_map(|line| line.strip_prefix("Content-Length: "))
+                            .unwrap().parse().unwrap();
+                        if body.len() >= length {
+                            break serde_json::from_str::<serde_json::Value>(body).unwrap();
+                        }
+                    }
+                };
+                let mut error = serde_json::Value::Null;
+                let result = match request["method"].as_str().unwrap() {
+                    // Balances of [1; 21] and [2; 21]; other addresses stand for a node without the indexer
+                    "get_balance" => match request["params"]["address"].as_str().unwrap() {
+                        address if address == hex::encode([1u8; 21]) => serde_json::json!({ "balance": 1000, "indexed_height": 7 }),
+                        address if address == hex::encode([2u8; 21]) => serde_json::json!({ "balance": 600, "indexed_height": 7 }),
+                        _ => {
+                            error = serde_json::json!({ "code": -32040, "message": "Chain indexer is disabled" });
+                            serde_json::Value::Null
+                        }
+                    },
+                    "send_transaction" => {
+                        let payload = request["params"]["payload_base64"].as_str()
+                            .map(|payload| base64_engine.decode(payload).unwrap())
+                            .unwrap_or_else(|| request["params"].to_string().into_bytes());
+                        serde_json::json!(hex::encode(Sha256::digest([b"tx".as_slice(), &payload].concat())))
+                    }
+                    "get_transaction" => {
+                        polls += 1;
+                        if polls > 3 { serde_json::json!({ "height": 7 }) } else { serde_json::Value::Null }
+                    }
+                    "get_sync_status" => serde_json::json!({ "finalized_height": if polls > 3 { 7 } else { 0 } }),
+                    "get_node_info" => serde_json::json!({ "address_hrp": "bds" }),
+                    "wallet_list_addresses" => serde_json::json!([{
+                        "address": crate::core::address::encode_address("bds", &[1u8; 21]), "algorithm": "ed25519", "public_key": "02".repeat(32),
+                        "label": null, "created_at": 0, "derivation_path": "m/44'/1'/0'/0'/0'",
+                    }]),
+                    method => panic!("unexpected method {}", method),
+                };
+                requests.lock().unwrap().push(request);
+                let body = serde_json::json!({ "jsonrpc": "2.0", "result": result, "error": error, "id": 1 }).to_string();
+                let response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}", body.len(), body);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn store_file_uploads_chunks_and_waits_for_finality() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..MAX_CHUNK_SIZE + 10).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let client = RpcClient::new(spawn_fake_node(requests.clone()));
+        let options = StoreFileOptions { fee_rate: 2, poll_interval: Duration::from_millis(1), ..StoreFileOptions::default() };
+        let mut statuses = Vec::new();
+        let stored = store_file(&client, &vec![1u8; 21], &path, &options, |status| statuses.push(status.clone())).unwrap();
+
+        // Two chunks plus the manifest, each its own transaction
+        assert_eq!(stored.chunk_hashes.len(), 2);
+        assert_eq!(stored.tx_hashes.len(), 3);
+        assert_eq!(stored.final_height, Some(7));
+        let manifest = FileManifest { size: data.len() as u64, chunks: stored.chunk_hashes.iter().map(hex::encode).collect() };
+        assert_eq!(stored.root_hash, <Hash>::from(Sha256::digest(serde_json::to_vec(&manifest).unwrap())));
+        assert_eq!(statuses.last(), Some(&StoreFileStatus::Final { height: 7 }));
+        assert!(statuses.contains(&StoreFileStatus::Confirming { confirmed: 0, total: 3, finalized_height: Some(0) }));
+
+        let requests = requests.lock().unwrap();
+        let uploaded: Vec<u8> = requests.iter()
+            .filter(|request| request["method"] == "send_transaction")
+            .take(2)
+            .flat_map(|request| base64_engine.decode(request["params"]["payload_base64"].as_str().unwrap()).unwrap())
+            .collect();
+        assert_eq!(uploaded, data);
+        let fees: u64 = requests.iter().filter_map(|request| request["params"]["fee"].as_u64()).sum();
+        assert_eq!(fees, stored.fee);
+        assert!(stored.fee > 0);
+
+        assert!(matches!(store_file(&client, &vec![1u8; 21], &dir.path().join("missing"), &options, |_| {}), Err(WalletError::Io(_))));
+    }
+
+    #[test]
+    fn send_defaults_to_the_first_wallet_address() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let client = RpcClient::new(spawn_fake_node(requests.clone()));
+        send(&client, None, &vec![9u8; 21], 400, 10).unwrap();
+        assert!(matches!(
+            send(&client, Some(&vec![2u8; 21]), &vec![9u8; 21], 595, 10),
+            Err(WalletError::InsufficientBalance { needed: 605, available: 600 })
+        ));
+        assert!(matches!(send(&client, Some(&vec![3u8; 21]), &vec![9u8; 21], 1, 10), Err(WalletError::Rpc { code: -32040, .. })));
+
+        let requests = requests.lock().unwrap();
+        let sent: Vec<&serde_json::Value> = requests.iter().filter(|request| request["method"] == "send_transaction").collect();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["params"]["sender"], serde_json::json!(vec![1u8; 21]));
+        assert_eq!(sent[0]["params"]["amount"], 400);
+    }
+}
@@ -0,0 +1,229 @@
+// tests/data_lifecycle_test.rs
+//
+// End-to-end lifecycle of an off-chain payload across two nodes: upload over RPC,
+// storage transaction in the mempool, mined into a block, confirmed, replicated to a
+// second node through the P2P payload protocol, then expired and garbage collected.
+// Payloads their owner restricts with an ACL are only served to the keys it lists.
+
+use actix_web::{test, web, App};
+use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
+use blockchain_data_storage::core::address::{address_from_public_key, KeyAlgorithm};
+use blockchain_data_storage::core::time::unix_now;
+use blockchain_data_storage::core::{Blockchain, Transaction};
+use blockchain_data_storage::network::{self, payloads, PayloadAcl, PayloadRequest, PeerObservations};
+use blockchain_data_storage::offchain_storage::{OffChainStorageError, OffChainStorageManager, RetentionPolicy};
+use blockchain_data_storage::rpc::{rest_routes, rpc_handler, AppState};
+use blockchain_data_storage::storage::StorageManager;
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+fn rpc_request(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 })
+}
+
+// A second node sharing the first node's genesis block
+fn join_chain(data_dir: &Path, genesis: &blockchain_data_storage::core::Block) -> Blockchain {
+    {
+        let storage = StorageManager::new(data_dir).unwrap();
+        storage.save_block(genesis).unwrap();
+    }
+    Blockchain::new(data_dir).unwrap()
+}
+
+#[actix_web::test]
+async fn test_payload_lifecycle_across_nodes() {
+    let dir_a = tempdir().unwrap();
+    let dir_b = tempdir().unwrap();
+
+    // Node A serves RPC
+    let mut chain_a = Blockchain::new(&dir_a.path().join("chain")).unwrap();
+    chain_a.initialize_genesis_if_needed().unwrap();
+    let genesis = chain_a.get_block_by_height(0).unwrap().unwrap();
+    let blockchain_a = Arc::new(Mutex::new(chain_a));
+    let offchain_a = Arc::new(OffChainStorageManager::new(dir_a.path()).unwrap());
+    let (outbound, _outbound_rx) = network::outbound_channel();
+    let state = AppState::new(
+        blockchain_a.clone(),
+        offchain_a.clone(),
+        Arc::new(Mutex::new(PeerObservations::new())),
+        None,
+        outbound,
+        Arc::new(SigningKey::from_bytes(&[1u8; 32])),
+    );
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).service(rpc_handler).configure(rest_routes)).await;
+
+    // 1. Upload a payload; its storage transaction waits in the mempool
+    let payload = b"lifecycle payload".to_vec();
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("send_transaction", json!({
+        "sender": [7],
+        "payload_base64": base64_engine.encode(&payload),
+    }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    let tx_hash = response["result"].as_str().expect("send_transaction returns the tx hash").to_string();
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_mempool_entry", json!({ "tx_hash": tx_hash }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"]["size"].as_u64().map(|size| size > 0), Some(true));
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_transaction", json!({ "tx_hash": tx_hash }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"]["status"], json!("pending"));
+    assert_eq!(response["result"]["confirmations"], json!(0));
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_mempool_info", Value::Null)).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"]["size"], json!(1));
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_node_info", Value::Null)).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"]["genesis_hash"], json!(hex::encode(genesis.header.calculate_hash())));
+    assert_eq!((response["result"]["height"].clone(), response["result"]["mempool_size"].clone()), (json!(0), json!(1)));
+    assert_eq!(response["result"]["sync"]["state"], json!("unknown"));
+
+    // 2. Mine it and check it is confirmed
+    let block = blockchain_a.lock().unwrap().mine_new_block().unwrap();
+    blockchain_a.lock().unwrap().process_mined_block(block.clone()).unwrap();
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_transaction", json!({ "tx_hash": tx_hash }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"]["status"], json!("confirmed"));
+    assert_eq!(response["result"]["height"], json!(1));
+    assert_eq!(response["result"]["confirmations"], json!(1));
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_raw_block", json!({ "height": 1 }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"], json!(hex::encode(block.to_bytes())));
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_raw_transaction", json!({ "tx_hash": tx_hash }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    let raw_tx = hex::decode(response["result"].as_str().expect("get_raw_transaction returns hex")).unwrap();
+    assert_eq!(hex::encode(Transaction::from_bytes(&raw_tx).unwrap().calculate_hash()), tx_hash);
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_blocks", json!({ "start_height": 0, "count": 5, "headers_only": true }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"]["blocks"].as_array().map(Vec::len), Some(2));
+    assert_eq!(response["result"]["blocks"][1]["hash"], json!(hex::encode(block.header.calculate_hash())));
+    assert!(response["result"]["blocks"][1].get("transactions").is_none());
+    assert!(response["result"]["next_height"].is_null());
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_mempool_entry", json!({ "tx_hash": tx_hash }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert!(response["result"].is_null());
+
+    // 3. Node B accepts the block and fetches the payload it anchors from node A
+    let mut blockchain_b = join_chain(&dir_b.path().join("chain"), &genesis);
+    let offchain_b = OffChainStorageManager::new(dir_b.path()).unwrap();
+    blockchain_b.process_mined_block(block.clone()).unwrap();
+    let missing = payloads::missing_payloads(&block, &offchain_b);
+    assert_eq!(missing.len(), 1);
+    let node_key_b = SigningKey::from_bytes(&[2u8; 32]);
+    let request = PayloadRequest::signed(missing[0], None, &node_key_b, unix_now());
+    let payload_response = payloads::serve_authorized_request(&blockchain_a.lock().unwrap(), &offchain_a, None, &request, unix_now()).unwrap();
+    assert!(payloads::accept_payload_response(&offchain_b, payload_response).unwrap());
+    assert_eq!(offchain_b.retrieve_payload(&missing[0]).unwrap(), payload);
+    assert!(payloads::missing_payloads(&block, &offchain_b).is_empty());
+
+    // The REST routes serve the same data without JSON-RPC envelopes
+    let request = test::TestRequest::get().uri(&format!("/tx/{}", tx_hash)).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["status"], json!("confirmed"));
+    let request = test::TestRequest::get().uri(&format!("/data/{}", hex::encode(missing[0]))).to_request();
+    assert_eq!(test::call_and_read_body(&app, request).await.to_vec(), payload);
+    let request = test::TestRequest::get().uri(&format!("/data/{}", hex::encode(missing[0]))).insert_header(("Range", "bytes=4-10")).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.headers().get("Content-Range").unwrap(), &format!("bytes 4-10/{}", payload.len()));
+    assert_eq!(test::read_body(response).await.to_vec(), payload[4..=10]);
+    let request = test::TestRequest::get().uri(&format!("/data/{}", hex::encode(missing[0]))).insert_header(("Range", "bytes=500-")).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 416);
+    let request = test::TestRequest::get().uri(&format!("/tx/{}", hex::encode([0u8; 32]))).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+    // rpc.discover describes exactly the methods the node serves
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("rpc.discover", Value::Null)).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    let methods = response["result"]["methods"].as_array().expect("rpc.discover lists the methods").clone();
+    assert!(methods.iter().any(|method| method["name"] == "get_blocks"));
+    for method in methods {
+        let request = test::TestRequest::post().uri("/").set_json(rpc_request(method["name"].as_str().unwrap(), Value::Null)).to_request();
+        let response: Value = test::call_and_read_body_json(&app, request).await;
+        assert_ne!(response["error"]["code"], json!(-32601), "{} is listed but not served", method["name"]);
+    }
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_everything", Value::Null)).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["error"]["code"], json!(-32601));
+
+    // Envelopes: ids are echoed, notifications get no response, bad envelopes are refused
+    let request = test::TestRequest::post().uri("/").set_json(json!({ "jsonrpc": "2.0", "method": "get_chain_height", "id": "tip" })).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!((response["id"].clone(), response["result"].clone()), (json!("tip"), json!(1)));
+    assert!(response.get("error").is_none());
+    let request = test::TestRequest::post().uri("/").set_json(json!({ "jsonrpc": "2.0", "method": "get_chain_height" })).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 204);
+    let request = test::TestRequest::post().uri("/").set_json(json!({ "jsonrpc": "1.0", "method": "get_chain_height", "id": 7 })).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!((response["id"].clone(), response["error"]["code"].clone()), (json!(7), json!(-32600)));
+    let request = test::TestRequest::post().uri("/").set_payload("{\"jsonrpc\":").to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["error"]["code"], json!(-32700));
+
+    // 4. Once the retention period has passed, GC on node B removes it
+    let policy = RetentionPolicy { retention_blocks: Some(2), orphan_grace_secs: 3600 };
+    let next = blockchain_b.mine_new_block().unwrap();
+    blockchain_b.process_mined_block(next).unwrap();
+    let report = offchain_b.collect_garbage(&blockchain_b, &policy, 0).unwrap();
+    assert!(report.expired.is_empty(), "payload expired before its retention period");
+    let next = blockchain_b.mine_new_block().unwrap();
+    blockchain_b.process_mined_block(next).unwrap();
+    let report = offchain_b.collect_garbage(&blockchain_b, &policy, 0).unwrap();
+    assert_eq!(report.expired, missing);
+    assert!(matches!(offchain_b.retrieve_payload(&missing[0]), Err(OffChainStorageError::NotFound(_))));
+    // Node A keeps its copy under its own (default) policy
+    let report = offchain_a.collect_garbage(&blockchain_a.lock().unwrap(), &RetentionPolicy::default(), 0).unwrap();
+    assert!(report.expired.is_empty() && report.orphaned.is_empty());
+
+    // 5. Blocks replayed as raw bytes are connected once and reported as duplicates after
+    let next = blockchain_a.lock().unwrap().mine_new_block().unwrap();
+    for duplicate in [false, true] {
+        let request = test::TestRequest::post().uri("/").set_json(rpc_request("submit_block", json!({ "hex": hex::encode(next.to_bytes()) }))).to_request();
+        let response: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(response["result"]["accepted"], json!(!duplicate));
+        assert_eq!(response["result"]["duplicate"], json!(duplicate));
+    }
+    assert_eq!(blockchain_a.lock().unwrap().get_chain_height(), Some(2));
+
+    // 6. A payload its owner restricts to one reader is refused to node B, over RPC and P2P
+    let owner_key = SigningKey::from_bytes(&[3u8; 32]);
+    let owner_public_key = owner_key.verifying_key().to_bytes().to_vec();
+    let owner = address_from_public_key(KeyAlgorithm::Ed25519, &owner_public_key).unwrap();
+    let private = b"for one reader only".to_vec();
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("send_transaction", json!({
+        "sender": owner,
+        "payload_base64": base64_engine.encode(&private),
+    }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert!(response["result"].is_string(), "{}", response);
+    let block = blockchain_a.lock().unwrap().mine_new_block().unwrap();
+    blockchain_a.lock().unwrap().process_mined_block(block.clone()).unwrap();
+    let private_hash = payloads::missing_payloads(&block, &offchain_b)[0];
+    let reader_key = SigningKey::from_bytes(&[4u8; 32]);
+    let acl = PayloadAcl::signed(private_hash, vec![reader_key.verifying_key().to_bytes()], unix_now(), owner_public_key.clone(), |digest| {
+        owner_key.sign(digest).to_bytes().to_vec()
+    });
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("set_payload_acl", json!({
+        "payload_hash": hex::encode(private_hash),
+        "readers": [hex::encode(reader_key.verifying_key().to_bytes())],
+        "issued_at": acl.issued_at,
+        "owner_public_key": hex::encode(&owner_public_key),
+        "signature": hex::encode(&acl.signature),
+    }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["result"]["readers"].as_array().map(Vec::len), Some(1), "{}", response);
+    let request = test::TestRequest::post().uri("/").set_json(rpc_request("get_offchain_data", json!({ "hash": hex::encode(private_hash) }))).to_request();
+    let response: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response["error"]["code"], json!(-32050));
+    let request = test::TestRequest::get().uri(&format!("/data/{}", hex::encode(private_hash))).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 403);
+
+    let refused = PayloadRequest::signed(private_hash, None, &node_key_b, unix_now());
+    let response = payloads::serve_authorized_request(&blockchain_a.lock().unwrap(), &offchain_a, None, &refused, unix_now()).unwrap();
+    assert!(response.access_denied && response.payload.is_none());
+    assert!(!payloads::accept_payload_response(&offchain_b, response).unwrap());
+    let allowed = PayloadRequest::signed(private_hash, None, &reader_key, unix_now());
+    let response = payloads::serve_authorized_request(&blockchain_a.lock().unwrap(), &offchain_a, None, &allowed, unix_now()).unwrap();
+    assert_eq!((response.payload.as_deref(), response.acl.as_ref()), (Some(&private[..]), Some(&acl)));
+}